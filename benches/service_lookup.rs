@@ -0,0 +1,125 @@
+// ===================================================================
+// Service registry lookups on the proxy hot path
+// ===================================================================
+// 1000 concurrent fake proxy requests each look up their service (repeatedly,
+// so the lookup rather than spawning the task dominates), through:
+// - the shared `Arc<ServiceRegistry>` the proxy uses
+// - an `ArcSwap` holding it, loaded per request as after a hot-reload
+// - an `RwLock` around it, as a hot-reload that locks the registry would need
+// - a DashMap cache in front of it, cloning the config out of a shard
+//
+// Run with `cargo bench --bench service_lookup`.
+
+use std::sync::{Arc, RwLock};
+
+use arc_swap::ArcSwap;
+use criterion::{criterion_group, criterion_main, Criterion};
+use dashmap::DashMap;
+use serde_json::json;
+use tokio::runtime::Runtime;
+use tokio::task::JoinSet;
+
+use sec_ai_agent_gw::config::{ServiceConfig, ServiceRegistry};
+
+const CONCURRENT_REQUESTS: usize = 1000;
+const SERVICES: usize = 20;
+const LOOKUPS_PER_REQUEST: usize = 100;
+
+fn service(id: usize) -> ServiceConfig {
+    serde_json::from_value(json!({
+        "id": format!("service-{}", id),
+        "name": format!("Service {}", id),
+        "description": "Benchmark service",
+        "base_url": format!("https://api.service-{}.example.com/v1", id),
+        "auth_type": "bearer_token",
+        "endpoints": [{ "path": "/items", "methods": ["GET", "POST"], "required_scopes": [] }],
+        "rate_limit": { "requests": 100, "window_secs": 60 }
+    }))
+    .expect("valid service config")
+}
+
+fn registry() -> ServiceRegistry {
+    ServiceRegistry::from_services((0..SERVICES).map(service).collect())
+}
+
+fn service_ids() -> Arc<Vec<String>> {
+    Arc::new((0..CONCURRENT_REQUESTS).map(|i| format!("service-{}", i % SERVICES)).collect())
+}
+
+/// Spawn one task per request, each running `lookup` on its service id
+fn run_requests<F>(rt: &Runtime, ids: &Arc<Vec<String>>, lookup: F)
+where
+    F: Fn(&str) -> bool + Clone + Send + 'static,
+{
+    rt.block_on(async {
+        let mut requests = JoinSet::new();
+        for i in 0..CONCURRENT_REQUESTS {
+            let ids = ids.clone();
+            let lookup = lookup.clone();
+            requests.spawn(async move {
+                for _ in 0..LOOKUPS_PER_REQUEST {
+                    assert!(lookup(&ids[i]));
+                }
+            });
+        }
+        while let Some(result) = requests.join_next().await {
+            result.expect("lookup task panicked");
+        }
+    });
+}
+
+fn bench_service_lookup(c: &mut Criterion) {
+    let rt = tokio::runtime::Builder::new_multi_thread().enable_all().build().unwrap();
+    let ids = service_ids();
+    let mut group = c.benchmark_group("service_lookup_1000_concurrent");
+
+    let shared = Arc::new(registry());
+    group.bench_function("arc_registry", |b| {
+        b.iter(|| {
+            let shared = shared.clone();
+            run_requests(&rt, &ids, move |id| shared.get(id).is_some())
+        })
+    });
+
+    let swapped = Arc::new(ArcSwap::from_pointee(registry()));
+    group.bench_function("arc_swap_registry", |b| {
+        b.iter(|| {
+            let swapped = swapped.clone();
+            run_requests(&rt, &ids, move |id| swapped.load_full().get(id).is_some())
+        })
+    });
+
+    let locked = Arc::new(RwLock::new(registry()));
+    group.bench_function("rwlock_registry", |b| {
+        b.iter(|| {
+            let locked = locked.clone();
+            run_requests(&rt, &ids, move |id| locked.read().unwrap().get(id).is_some())
+        })
+    });
+
+    let cached = Arc::new((registry(), DashMap::<String, ServiceConfig>::new()));
+    group.bench_function("dashmap_cache", |b| {
+        b.iter(|| {
+            let cached = cached.clone();
+            run_requests(&rt, &ids, move |id| {
+                let (registry, cache) = &*cached;
+                // The cache can't lend a reference out of its shard, so a hit clones
+                if let Some(config) = cache.get(id).map(|entry| entry.clone()) {
+                    return !config.id.is_empty();
+                }
+                match registry.get(id) {
+                    Some(config) => {
+                        cache.insert(id.to_string(), config.clone());
+                        true
+                    }
+                    None => false,
+                }
+            })
+        })
+    });
+
+    group.finish();
+}
+
+criterion_group!(benches, bench_service_lookup);
+criterion_main!(benches);
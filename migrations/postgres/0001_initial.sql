@@ -0,0 +1,29 @@
+-- Lookup keys live in real columns; the full model is kept as JSONB in `data`
+-- so new optional fields on Agent/User don't need a migration.
+
+CREATE TABLE users (
+    id UUID PRIMARY KEY,
+    email TEXT NOT NULL UNIQUE,
+    data JSONB NOT NULL
+);
+
+CREATE TABLE agents (
+    id UUID PRIMARY KEY,
+    data JSONB NOT NULL
+);
+
+CREATE TABLE sessions (
+    session_id TEXT PRIMARY KEY,
+    agent_id UUID NOT NULL,
+    expires_at TIMESTAMPTZ NOT NULL,
+    data JSONB NOT NULL
+);
+
+CREATE INDEX idx_sessions_agent_id ON sessions (agent_id);
+
+CREATE TABLE agent_credentials (
+    agent_id UUID NOT NULL,
+    service_id TEXT NOT NULL,
+    data JSONB NOT NULL,
+    PRIMARY KEY (agent_id, service_id)
+);
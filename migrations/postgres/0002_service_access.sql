@@ -0,0 +1,17 @@
+-- allowed_services ids become grants with per-service scopes:
+-- "payment" -> {"service_id": "payment", "granted_scopes": []} (every scope, as before)
+
+UPDATE agents SET data = jsonb_set(data, '{allowed_services}', (
+    SELECT COALESCE(
+        jsonb_agg(
+            CASE WHEN jsonb_typeof(service) = 'string'
+                THEN jsonb_build_object('service_id', service, 'granted_scopes', '[]'::JSONB)
+                ELSE service
+            END
+            ORDER BY position
+        ),
+        '[]'::JSONB
+    )
+    FROM jsonb_array_elements(data->'allowed_services') WITH ORDINALITY AS grants(service, position)
+))
+WHERE jsonb_typeof(data->'allowed_services') = 'array';
@@ -0,0 +1,8 @@
+-- Registrations awaiting email verification (REQUIRE_EMAIL_VERIFICATION);
+-- promoted to `users` once their code is confirmed
+
+CREATE TABLE pending_users (
+    id UUID PRIMARY KEY,
+    expires_at TIMESTAMPTZ NOT NULL,
+    data JSONB NOT NULL
+);
@@ -0,0 +1,269 @@
+//! Trail of admin operations (ADMIN_AUDIT_LOG_PATH), kept apart from the proxy
+//! audit log so compliance reviews see who changed what without wading through
+//! traffic. One JSON object per line in a single append-only file; admin
+//! traffic is light, so queries read the whole file. On the memory backend the
+//! events are kept in memory instead.
+
+use chrono::{DateTime, Utc};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use std::collections::VecDeque;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::AsyncWriteExt;
+use tokio::sync::Mutex;
+
+use crate::error::GatewayError;
+use crate::models::{AdminAction, AdminEvent};
+use crate::storage::{with_io_timeout, Page, DEFAULT_FILE_IO_TIMEOUT};
+
+/// Events kept without a file; the oldest are dropped first
+const MEMORY_CAPACITY: usize = 10_000;
+
+/// Hex characters of the key's SHA-256 that identify an admin
+const KEY_HASH_LEN: usize = 8;
+
+/// Which admin key acted, without storing the key
+pub fn admin_key_hash(key: &str) -> String {
+    let mut hash = hex::encode(Sha256::digest(key.as_bytes()));
+    hash.truncate(KEY_HASH_LEN);
+    hash
+}
+
+/// Events a query returns; unset fields match everything. Bounds are inclusive.
+#[derive(Debug, Clone, Default)]
+pub struct AdminEventFilter {
+    pub action: Option<AdminAction>,
+    pub resource_id: Option<String>,
+    pub admin_key_hash: Option<String>,
+    pub from: Option<DateTime<Utc>>,
+    pub to: Option<DateTime<Utc>>,
+}
+
+impl AdminEventFilter {
+    pub fn matches(&self, event: &AdminEvent) -> bool {
+        self.action.is_none_or(|action| event.action == action)
+            && self.resource_id.as_deref().is_none_or(|id| event.resource_id.as_deref() == Some(id))
+            && self.admin_key_hash.as_deref().is_none_or(|hash| event.admin_key_hash == hash)
+            && self.from.is_none_or(|from| event.timestamp >= from)
+            && self.to.is_none_or(|to| event.timestamp <= to)
+    }
+}
+
+enum Backing {
+    File(PathBuf),
+    Memory(VecDeque<AdminEvent>),
+    Disabled,
+}
+
+/// Records admin events; every one is also traced. Cheap to clone.
+#[derive(Clone)]
+pub struct AdminAuditLogger {
+    // The lock keeps concurrent appends from interleaving
+    backing: Arc<Mutex<Backing>>,
+    io_timeout: Duration,
+}
+
+impl AdminAuditLogger {
+    /// Append to the JSONL file at `path`
+    pub fn file(path: impl Into<PathBuf>) -> Self {
+        Self::with_backing(Backing::File(path.into()))
+    }
+
+    /// Keep the most recent events in memory (memory backend)
+    pub fn in_memory() -> Self {
+        Self::with_backing(Backing::Memory(VecDeque::new()))
+    }
+
+    /// Trace events only (ADMIN_AUDIT_LOG_PATH empty)
+    pub fn disabled() -> Self {
+        Self::with_backing(Backing::Disabled)
+    }
+
+    fn with_backing(backing: Backing) -> Self {
+        Self {
+            backing: Arc::new(Mutex::new(backing)),
+            io_timeout: DEFAULT_FILE_IO_TIMEOUT,
+        }
+    }
+
+    /// Deadline for each append and read (FILE_IO_TIMEOUT_SECS)
+    pub fn with_io_timeout(mut self, timeout: Duration) -> Self {
+        self.io_timeout = timeout;
+        self
+    }
+
+    /// Record that the admin holding `admin_key_hash` did `action` on `resource_id`.
+    /// The operation has already happened, so a failed write is logged, not returned.
+    pub async fn record(
+        &self,
+        action: AdminAction,
+        admin_key_hash: &str,
+        resource_id: Option<String>,
+        details: Value,
+    ) {
+        let event = AdminEvent {
+            timestamp: Utc::now(),
+            action,
+            admin_key_hash: admin_key_hash.to_string(),
+            resource_id,
+            details,
+        };
+        tracing::info!(
+            action = ?event.action,
+            admin = %event.admin_key_hash,
+            resource_id = ?event.resource_id,
+            details = %event.details,
+            "Admin event"
+        );
+        if let Err(e) = self.append(event).await {
+            tracing::error!(error = ?e, action = ?action, "Failed to write admin audit event");
+        }
+    }
+
+    async fn append(&self, event: AdminEvent) -> Result<(), GatewayError> {
+        let mut backing = self.backing.lock().await;
+        match &mut *backing {
+            Backing::File(path) => {
+                let mut line = serde_json::to_string(&event)
+                    .map_err(|e| GatewayError::Internal(format!("Failed to serialize admin event: {}", e)))?;
+                line.push('\n');
+                with_io_timeout(self.io_timeout, path, append_line(path, line)).await
+            }
+            Backing::Memory(events) => {
+                if events.len() == MEMORY_CAPACITY {
+                    events.pop_front();
+                }
+                events.push_back(event);
+                Ok(())
+            }
+            Backing::Disabled => Ok(()),
+        }
+    }
+
+    /// Matching events newest first, `limit` of them from `offset`, with the
+    /// total number of matches
+    pub async fn query(
+        &self,
+        filter: &AdminEventFilter,
+        offset: usize,
+        limit: usize,
+    ) -> Result<Page<AdminEvent>, GatewayError> {
+        let mut events: Vec<AdminEvent> = match &*self.backing.lock().await {
+            Backing::File(path) => with_io_timeout(self.io_timeout, path, read_events(path)).await?,
+            Backing::Memory(events) => events.iter().cloned().collect(),
+            Backing::Disabled => {
+                return Err(GatewayError::BadRequest(
+                    "Admin event log is disabled (ADMIN_AUDIT_LOG_PATH is empty)".to_string(),
+                ))
+            }
+        };
+        events.retain(|event| filter.matches(event));
+        // Stable, so events with the same timestamp stay in the order they were appended
+        events.sort_by_key(|event| event.timestamp);
+        Ok(Page {
+            total: events.len(),
+            items: events.into_iter().rev().skip(offset).take(limit).collect(),
+        })
+    }
+}
+
+async fn append_line(path: &Path, line: String) -> Result<(), GatewayError> {
+    let write_error =
+        |e: std::io::Error| GatewayError::Internal(format!("Failed to write admin audit log {}: {}", path.display(), e));
+    if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+        tokio::fs::create_dir_all(dir).await.map_err(write_error)?;
+    }
+    let mut file = tokio::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .await
+        .map_err(write_error)?;
+    file.write_all(line.as_bytes()).await.map_err(write_error)?;
+    file.flush().await.map_err(write_error)
+}
+
+/// Every event in the file, oldest first; a missing file means none.
+/// A line that doesn't parse (e.g. cut short by a crash) is skipped.
+async fn read_events(path: &Path) -> Result<Vec<AdminEvent>, GatewayError> {
+    let content = match tokio::fs::read_to_string(path).await {
+        Ok(content) => content,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => {
+            return Err(GatewayError::Internal(format!(
+                "Failed to read admin audit log {}: {}",
+                path.display(),
+                e
+            )))
+        }
+    };
+    Ok(content
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .filter_map(|line| match serde_json::from_str(line) {
+            Ok(event) => Some(event),
+            Err(e) => {
+                tracing::warn!(path = %path.display(), error = %e, "Skipping unreadable admin audit line");
+                None
+            }
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_key_hash_is_a_short_sha256_prefix() {
+        let hash = admin_key_hash("test-admin-key");
+        assert_eq!(hash.len(), KEY_HASH_LEN);
+        assert!(hex::encode(Sha256::digest(b"test-admin-key")).starts_with(&hash));
+        assert_ne!(hash, admin_key_hash("other-key"));
+    }
+
+    #[tokio::test]
+    async fn test_file_log_is_queried_newest_first() {
+        let dir = tempfile::tempdir().unwrap();
+        let logger = AdminAuditLogger::file(dir.path().join("nested/admin.jsonl"));
+
+        logger.record(AdminAction::CacheCleared, "aaaa1111", None, json!({ "cleared": 3 })).await;
+        logger
+            .record(AdminAction::CredentialRotated, "bbbb2222", Some("payment".to_string()), json!({}))
+            .await;
+        logger
+            .record(AdminAction::CredentialRotated, "aaaa1111", Some("bank".to_string()), json!({}))
+            .await;
+
+        let page = logger.query(&AdminEventFilter::default(), 0, 2).await.unwrap();
+        assert_eq!(page.total, 3);
+        assert_eq!(page.items[0].resource_id.as_deref(), Some("bank"));
+        assert_eq!(page.items[1].resource_id.as_deref(), Some("payment"));
+
+        let filter = AdminEventFilter {
+            action: Some(AdminAction::CredentialRotated),
+            admin_key_hash: Some("aaaa1111".to_string()),
+            ..Default::default()
+        };
+        let page = logger.query(&filter, 0, 10).await.unwrap();
+        assert_eq!(page.total, 1);
+        assert_eq!(page.items[0].resource_id.as_deref(), Some("bank"));
+
+        // A torn last line doesn't hide the others
+        let path = dir.path().join("nested/admin.jsonl");
+        let mut content = std::fs::read_to_string(&path).unwrap();
+        content.push_str("{\"timestamp\":");
+        std::fs::write(&path, content).unwrap();
+        assert_eq!(logger.query(&AdminEventFilter::default(), 0, 10).await.unwrap().total, 3);
+    }
+
+    #[tokio::test]
+    async fn test_disabled_log_refuses_queries() {
+        let logger = AdminAuditLogger::disabled();
+        logger.record(AdminAction::BackupWritten, "aaaa1111", None, json!({})).await;
+        assert!(logger.query(&AdminEventFilter::default(), 0, 10).await.is_err());
+    }
+}
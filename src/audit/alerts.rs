@@ -0,0 +1,432 @@
+//! Security alerting over the audit stream: rules from ALERT_RULES_PATH are
+//! evaluated as entries leave the request path (the engine is one more
+//! `AuditSink` behind the writer's queues), and a rule whose threshold is
+//! crossed within its window POSTs to its webhook. Windows and cooldowns run on
+//! the entries' own timestamps; a rule that fired stays quiet for
+//! `cooldown_secs`, whoever trips it. Every alert fired is logged as an audit
+//! record.
+//!
+//! ```json
+//! { "rules": [{
+//!     "name": "service-probing",
+//!     "when": { "deny_reason": "service_not_allowed" },
+//!     "threshold": 20, "window_secs": 300, "group_by": "agent",
+//!     "webhook_url": "https://hooks.example.com/security"
+//! }] }
+//! ```
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::net::IpAddr;
+use std::path::Path;
+use std::time::Duration;
+use tokio::sync::Mutex;
+use uuid::Uuid;
+
+use super::{log_alert, AuditSink};
+use crate::error::GatewayError;
+use crate::models::{AlertAudit, AuditLog, Decision};
+
+const WEBHOOK_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// IPs remembered per agent for `new_ip`; the oldest is forgotten past this
+const MAX_IPS_PER_AGENT: usize = 64;
+
+fn default_threshold() -> u32 {
+    1
+}
+
+fn default_cooldown_secs() -> u64 {
+    300
+}
+
+/// Which entries a rule counts; unset fields match everything
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct EventPredicate {
+    pub decision: Option<Decision>,
+    /// `service_not_allowed`, `rate_limit_exceeded`, ...
+    pub deny_reason: Option<String>,
+    pub service_id: Option<String>,
+    pub status_min: Option<u16>,
+    pub status_max: Option<u16>,
+    /// Only entries from an IP the agent hasn't used before (since startup)
+    #[serde(default)]
+    pub new_ip: bool,
+}
+
+impl EventPredicate {
+    fn matches(&self, entry: &AuditLog, new_ip: bool) -> bool {
+        self.decision.is_none_or(|decision| entry.decision == decision)
+            && self.deny_reason.as_ref().is_none_or(|reason| entry.deny_reason.as_ref() == Some(reason))
+            && self.service_id.as_ref().is_none_or(|id| &entry.service_id == id)
+            && self.status_min.is_none_or(|min| entry.status_code >= min)
+            && self.status_max.is_none_or(|max| entry.status_code <= max)
+            && (!self.new_ip || new_ip)
+    }
+}
+
+/// What a rule counts per: matches from different agents never add up
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AlertGroupBy {
+    #[default]
+    Agent,
+    Service,
+    Ip,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AlertRule {
+    pub name: String,
+    pub when: EventPredicate,
+    /// Matches within `window_secs` that fire the alert
+    #[serde(default = "default_threshold")]
+    pub threshold: u32,
+    pub window_secs: u64,
+    #[serde(default)]
+    pub group_by: AlertGroupBy,
+    #[serde(default = "default_cooldown_secs")]
+    pub cooldown_secs: u64,
+    pub webhook_url: String,
+}
+
+impl AlertRule {
+    /// The group an entry counts toward; entries without one (no agent, no IP) don't count
+    fn key(&self, entry: &AuditLog) -> Option<String> {
+        match self.group_by {
+            AlertGroupBy::Agent => entry.agent_id.map(|id| id.to_string()),
+            AlertGroupBy::Service => Some(entry.service_id.clone()),
+            AlertGroupBy::Ip => entry.ip_address.map(|ip| ip.to_string()),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct AlertRulesFile {
+    rules: Vec<AlertRule>,
+}
+
+/// Rules from ALERT_RULES_PATH; a missing, malformed or invalid file fails startup
+pub fn load_alert_rules<P: AsRef<Path>>(path: P) -> Result<Vec<AlertRule>, GatewayError> {
+    let path = path.as_ref();
+    let content = std::fs::read_to_string(path).map_err(|e| {
+        GatewayError::Internal(format!("Failed to read alert rules {}: {}", path.display(), e))
+    })?;
+    let file: AlertRulesFile = serde_json::from_str(&content).map_err(|e| {
+        GatewayError::Internal(format!("Failed to parse alert rules {}: {}", path.display(), e))
+    })?;
+
+    let mut names = HashSet::new();
+    for rule in &file.rules {
+        let invalid = |reason: &str| GatewayError::Internal(format!("Alert rule '{}' {}", rule.name, reason));
+        if !names.insert(rule.name.as_str()) {
+            return Err(invalid("is defined twice"));
+        }
+        if rule.threshold == 0 {
+            return Err(invalid("needs a threshold of at least 1"));
+        }
+        if rule.window_secs == 0 {
+            return Err(invalid("needs a window_secs of at least 1"));
+        }
+        reqwest::Url::parse(&rule.webhook_url).map_err(|e| invalid(&format!("has an invalid webhook_url: {}", e)))?;
+    }
+    Ok(file.rules)
+}
+
+/// Webhook body
+#[derive(Debug, Clone, Serialize)]
+pub struct Alert {
+    pub rule: String,
+    pub group_by: AlertGroupBy,
+    /// Agent id, service id or IP, per `group_by`
+    pub key: String,
+    /// Matches in the window, the one that fired included
+    pub count: u32,
+    pub window_secs: u64,
+    pub first_seen: DateTime<Utc>,
+    pub last_seen: DateTime<Utc>,
+    /// `request_id` of the entry that fired the alert
+    pub request_id: Option<String>,
+}
+
+#[derive(Default)]
+struct RuleState {
+    /// Timestamps of the matches still in the window, per group
+    windows: HashMap<String, VecDeque<DateTime<Utc>>>,
+    quiet_until: Option<DateTime<Utc>>,
+}
+
+#[derive(Default)]
+struct EngineState {
+    rules: Vec<RuleState>,
+    /// IPs each agent has been seen from, oldest first
+    agent_ips: HashMap<Uuid, VecDeque<IpAddr>>,
+}
+
+impl EngineState {
+    /// Remember the entry's IP for its agent; whether it is one the agent hasn't used before
+    fn observe_ip(&mut self, entry: &AuditLog) -> bool {
+        let (Some(agent_id), Some(ip)) = (entry.agent_id, entry.ip_address) else {
+            return false;
+        };
+        let ips = self.agent_ips.entry(agent_id).or_default();
+        if ips.contains(&ip) {
+            return false;
+        }
+        // The first IP is the baseline, not a new one
+        let first = ips.is_empty();
+        if ips.len() == MAX_IPS_PER_AGENT {
+            ips.pop_front();
+        }
+        ips.push_back(ip);
+        !first
+    }
+}
+
+pub struct AlertEngine {
+    rules: Vec<AlertRule>,
+    state: Mutex<EngineState>,
+    client: Client,
+}
+
+impl AlertEngine {
+    pub fn new(rules: Vec<AlertRule>) -> Self {
+        let state = EngineState {
+            rules: rules.iter().map(|_| RuleState::default()).collect(),
+            ..Default::default()
+        };
+        Self {
+            rules,
+            state: Mutex::new(state),
+            client: Client::builder().timeout(WEBHOOK_TIMEOUT).build().unwrap_or_default(),
+        }
+    }
+
+    /// Feed entries through every rule, returning the alerts they fire (with their rule)
+    async fn evaluate(&self, entries: &[AuditLog]) -> Vec<(&AlertRule, Alert)> {
+        let mut state = self.state.lock().await;
+        let mut fired = Vec::new();
+        for entry in entries {
+            let new_ip = state.observe_ip(entry);
+            for (rule, rule_state) in self.rules.iter().zip(state.rules.iter_mut()) {
+                if !rule.when.matches(entry, new_ip) {
+                    continue;
+                }
+                let Some(key) = rule.key(entry) else {
+                    continue;
+                };
+                let window = chrono::Duration::seconds(rule.window_secs as i64);
+                let matches = rule_state.windows.entry(key.clone()).or_default();
+                matches.push_back(entry.timestamp);
+                while matches.front().is_some_and(|&t| t <= entry.timestamp - window) {
+                    matches.pop_front();
+                }
+                let quiet = rule_state.quiet_until.is_some_and(|until| entry.timestamp < until);
+                if matches.len() < rule.threshold as usize || quiet {
+                    continue;
+                }
+                rule_state.quiet_until = Some(entry.timestamp + chrono::Duration::seconds(rule.cooldown_secs as i64));
+                fired.push((
+                    rule,
+                    Alert {
+                        rule: rule.name.clone(),
+                        group_by: rule.group_by,
+                        key,
+                        count: matches.len() as u32,
+                        window_secs: rule.window_secs,
+                        first_seen: matches.front().copied().unwrap_or(entry.timestamp),
+                        last_seen: entry.timestamp,
+                        request_id: entry.request_id.clone(),
+                    },
+                ));
+            }
+        }
+
+        // Groups with nothing left in their window
+        if let Some(newest) = entries.iter().map(|entry| entry.timestamp).max() {
+            for (rule, rule_state) in self.rules.iter().zip(state.rules.iter_mut()) {
+                let window = chrono::Duration::seconds(rule.window_secs as i64);
+                rule_state
+                    .windows
+                    .retain(|_, matches| matches.back().is_some_and(|&t| t > newest - window));
+            }
+        }
+        fired
+    }
+
+    async fn notify(&self, rule: &AlertRule, alert: &Alert) -> Result<(), GatewayError> {
+        let response = self
+            .client
+            .post(&rule.webhook_url)
+            .json(alert)
+            .send()
+            .await
+            .map_err(|e| GatewayError::UpstreamError(format!("Alert webhook failed: {}", e)))?;
+        if !response.status().is_success() {
+            return Err(GatewayError::UpstreamError(format!(
+                "Alert webhook returned {}",
+                response.status()
+            )));
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl AuditSink for AlertEngine {
+    fn name(&self) -> &'static str {
+        "alerts"
+    }
+
+    /// Never fails the batch: a dead webhook loses that alert, not the entries
+    async fn write_batch(&self, entries: &[AuditLog]) -> Result<(), GatewayError> {
+        for (rule, alert) in self.evaluate(entries).await {
+            let delivered = match self.notify(rule, &alert).await {
+                Ok(()) => true,
+                Err(e) => {
+                    tracing::error!(rule = %rule.name, error = ?e, "Failed to deliver security alert");
+                    false
+                }
+            };
+            log_alert(&AlertAudit {
+                rule: alert.rule,
+                key: alert.key,
+                count: alert.count,
+                window_secs: alert.window_secs,
+                delivered,
+                timestamp: Utc::now(),
+            });
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+    use serde_json::json;
+    use wiremock::matchers::method;
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    fn rule(webhook_url: &str, when: serde_json::Value) -> AlertRule {
+        serde_json::from_value(json!({
+            "name": "probing",
+            "when": when,
+            "threshold": 3,
+            "window_secs": 300,
+            "cooldown_secs": 600,
+            "webhook_url": webhook_url
+        }))
+        .unwrap()
+    }
+
+    fn denied(agent_id: Uuid, minute: i64) -> AuditLog {
+        let mut entry = AuditLog::new("bank".to_string(), "accounts".to_string(), "GET".to_string());
+        entry.agent_id = Some(agent_id);
+        entry.decision = Decision::Denied;
+        entry.deny_reason = Some("service_not_allowed".to_string());
+        entry.status_code = 403;
+        entry.timestamp = Utc.with_ymd_and_hms(2025, 12, 1, 10, 0, 0).unwrap() + chrono::Duration::minutes(minute);
+        entry
+    }
+
+    #[tokio::test]
+    async fn test_threshold_fires_once_then_cools_down() {
+        let webhook = MockServer::start().await;
+        Mock::given(method("POST"))
+            .respond_with(ResponseTemplate::new(200))
+            .mount(&webhook)
+            .await;
+        let engine = AlertEngine::new(vec![rule(&webhook.uri(), json!({ "deny_reason": "service_not_allowed" }))]);
+        let (agent, other) = (Uuid::new_v4(), Uuid::new_v4());
+
+        // Three matches, but the first has aged out by the third
+        let mut allowed = denied(agent, 1);
+        allowed.decision = Decision::Allowed;
+        allowed.deny_reason = None;
+        engine
+            .write_batch(&[denied(agent, 0), denied(agent, 2), allowed, denied(other, 2), denied(agent, 5)])
+            .await
+            .unwrap();
+        assert!(webhook.received_requests().await.unwrap().is_empty());
+
+        engine.write_batch(&[denied(agent, 6)]).await.unwrap();
+        let received = webhook.received_requests().await.unwrap();
+        assert_eq!(received.len(), 1);
+        let alert: serde_json::Value = received[0].body_json().unwrap();
+        assert_eq!(alert["rule"], "probing");
+        assert_eq!(alert["key"], agent.to_string());
+        assert_eq!(alert["count"], 3);
+
+        // Still crossing, but within the 10 minute cooldown
+        engine
+            .write_batch(&[denied(agent, 7), denied(agent, 8), denied(other, 9), denied(other, 10), denied(other, 11)])
+            .await
+            .unwrap();
+        assert_eq!(webhook.received_requests().await.unwrap().len(), 1);
+
+        // Cooldown over
+        engine
+            .write_batch(&[denied(agent, 16), denied(agent, 17), denied(agent, 18)])
+            .await
+            .unwrap();
+        assert_eq!(webhook.received_requests().await.unwrap().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_new_ip_for_an_agent() {
+        let webhook = MockServer::start().await;
+        Mock::given(method("POST"))
+            .respond_with(ResponseTemplate::new(200))
+            .mount(&webhook)
+            .await;
+        let mut new_ip = rule(&webhook.uri(), json!({ "new_ip": true }));
+        new_ip.threshold = 1;
+        let engine = AlertEngine::new(vec![new_ip]);
+        let agent = Uuid::new_v4();
+        let from = |ip: &str, minute| {
+            let mut entry = denied(agent, minute);
+            entry.ip_address = Some(ip.parse().unwrap());
+            entry
+        };
+
+        engine
+            .write_batch(&[from("10.0.0.1", 0), from("10.0.0.1", 1)])
+            .await
+            .unwrap();
+        assert!(webhook.received_requests().await.unwrap().is_empty());
+        engine.write_batch(&[from("203.0.113.9", 2)]).await.unwrap();
+        assert_eq!(webhook.received_requests().await.unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_example_rules_load() {
+        let rules = load_alert_rules(concat!(env!("CARGO_MANIFEST_DIR"), "/config/alert_rules.json")).unwrap();
+        assert_eq!(rules.len(), 2);
+        assert!(rules[1].when.new_ip);
+    }
+
+    #[test]
+    fn test_invalid_rules_are_rejected() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("alerts.json");
+        let write = |rules: serde_json::Value| std::fs::write(&path, json!({ "rules": [rules] }).to_string()).unwrap();
+
+        write(json!({ "name": "a", "when": {}, "window_secs": 60, "webhook_url": "http://hooks.local/a" }));
+        let rules = load_alert_rules(&path).unwrap();
+        assert_eq!((rules[0].threshold, rules[0].cooldown_secs, rules[0].group_by), (1, 300, AlertGroupBy::Agent));
+
+        write(json!({ "name": "a", "when": {}, "window_secs": 0, "webhook_url": "http://hooks.local/a" }));
+        assert!(load_alert_rules(&path).is_err());
+        write(json!({ "name": "a", "when": {}, "window_secs": 60, "webhook_url": "not a url" }));
+        assert!(load_alert_rules(&path).is_err());
+        write(json!({ "name": "a", "when": { "deny": "x" }, "window_secs": 60, "webhook_url": "http://hooks.local/a" }));
+        assert!(load_alert_rules(&path).is_err());
+    }
+}
@@ -0,0 +1,176 @@
+//! Payload capture for services with `audit_capture`, for settling disputes
+//! about what an agent sent or got back. Everything is redacted before it
+//! reaches the entry: credentials headers always, plus the service's
+//! `redact_paths` and `redact_headers`. Bodies past `max_body_bytes`, and bodies
+//! that aren't JSON (so can't be redacted), are kept as a SHA-256 digest and
+//! length only. Captures are copies taken after the upstream answered; what is
+//! forwarded never changes.
+
+use axum::http::HeaderMap;
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+
+use crate::config::AuditCaptureConfig;
+use crate::gateway::REDACTED;
+use crate::models::{AuditLog, CapturedBody};
+
+/// Never stored, whatever `redact_headers` says
+const ALWAYS_REDACTED_HEADERS: [&str; 5] =
+    ["x-session-id", "authorization", "proxy-authorization", "cookie", "x-admin-key"];
+
+/// Fill in the entry's payloads for what `config` asks to capture
+pub fn capture_exchange(
+    audit: &mut AuditLog,
+    config: &AuditCaptureConfig,
+    request_headers: &HeaderMap,
+    request_body: Option<&[u8]>,
+    response_body: &Value,
+) {
+    if config.capture_request_body {
+        audit.request_headers = Some(capture_headers(request_headers, config));
+        audit.request_body = request_body.map(|body| capture_body(body, config));
+    }
+    if config.capture_response_body {
+        let raw = serde_json::to_vec(response_body).unwrap_or_default();
+        audit.response_body = Some(capture_body(&raw, config));
+    }
+}
+
+pub fn capture_body(raw: &[u8], config: &AuditCaptureConfig) -> CapturedBody {
+    let parsed = (raw.len() <= config.max_body_bytes)
+        .then(|| serde_json::from_slice::<Value>(raw).ok())
+        .flatten();
+    match parsed {
+        Some(mut body) => {
+            for path in &config.redact_paths {
+                redact_pointer(&mut body, &pointer_segments(path));
+            }
+            CapturedBody { length: raw.len(), body: Some(body), sha256: None }
+        }
+        None => CapturedBody {
+            length: raw.len(),
+            body: None,
+            sha256: Some(hex::encode(Sha256::digest(raw))),
+        },
+    }
+}
+
+pub fn capture_headers(headers: &HeaderMap, config: &AuditCaptureConfig) -> BTreeMap<String, String> {
+    headers
+        .iter()
+        .map(|(name, value)| {
+            let name = name.as_str();
+            let redacted = ALWAYS_REDACTED_HEADERS.contains(&name)
+                || config.redact_headers.iter().any(|h| h.eq_ignore_ascii_case(name));
+            let value = if redacted {
+                REDACTED.to_string()
+            } else {
+                String::from_utf8_lossy(value.as_bytes()).into_owned()
+            };
+            (name.to_string(), value)
+        })
+        .collect()
+}
+
+/// `/a/b~1c` -> ["a", "b/c"]; "" (the whole body) -> []
+fn pointer_segments(pointer: &str) -> Vec<String> {
+    pointer
+        .split('/')
+        .skip(1)
+        .map(|segment| segment.replace("~1", "/").replace("~0", "~"))
+        .collect()
+}
+
+fn redact_pointer(value: &mut Value, segments: &[String]) {
+    let Some((first, rest)) = segments.split_first() else {
+        *value = Value::String(REDACTED.to_string());
+        return;
+    };
+    match value {
+        Value::Object(fields) if first == "*" => {
+            fields.values_mut().for_each(|field| redact_pointer(field, rest));
+        }
+        Value::Object(fields) => {
+            if let Some(field) = fields.get_mut(first) {
+                redact_pointer(field, rest);
+            }
+        }
+        Value::Array(items) if first == "*" => {
+            items.iter_mut().for_each(|item| redact_pointer(item, rest));
+        }
+        Value::Array(items) => {
+            if let Some(item) = first.parse::<usize>().ok().and_then(|i| items.get_mut(i)) {
+                redact_pointer(item, rest);
+            }
+        }
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn config(max_body_bytes: usize, redact_paths: &[&str]) -> AuditCaptureConfig {
+        AuditCaptureConfig {
+            capture_request_body: true,
+            capture_response_body: true,
+            max_body_bytes,
+            redact_paths: redact_paths.iter().map(|p| p.to_string()).collect(),
+            redact_headers: vec!["X-Api-Key".to_string()],
+        }
+    }
+
+    #[test]
+    fn test_nested_fields_are_redacted() {
+        let body = json!({
+            "user": { "name": "ana", "password": "hunter2", "a/b": 1 },
+            "cards": [{ "number": "4111", "brand": "visa" }, { "number": "5500", "brand": "mc" }],
+            "items": ["x", "y"]
+        });
+        let raw = serde_json::to_vec(&body).unwrap();
+        let captured = capture_body(
+            &raw,
+            &config(4096, &["/user/password", "/user/a~1b", "/cards/*/number", "/items/1", "/missing/path"]),
+        );
+
+        assert_eq!(captured.length, raw.len());
+        assert_eq!(captured.sha256, None);
+        assert_eq!(
+            captured.body.unwrap(),
+            json!({
+                "user": { "name": "ana", "password": REDACTED, "a/b": REDACTED },
+                "cards": [{ "number": REDACTED, "brand": "visa" }, { "number": REDACTED, "brand": "mc" }],
+                "items": ["x", REDACTED]
+            })
+        );
+    }
+
+    #[test]
+    fn test_large_and_non_json_bodies_keep_a_digest() {
+        let raw = serde_json::to_vec(&json!({ "blob": "a".repeat(100) })).unwrap();
+        let captured = capture_body(&raw, &config(64, &[]));
+        assert_eq!(captured.body, None);
+        assert_eq!(captured.length, raw.len());
+        assert_eq!(captured.sha256.unwrap(), hex::encode(Sha256::digest(&raw)));
+
+        let captured = capture_body(b"password=hunter2", &config(4096, &[]));
+        assert_eq!((captured.body, captured.length), (None, 16));
+        assert!(captured.sha256.is_some());
+    }
+
+    #[test]
+    fn test_credential_headers_are_redacted() {
+        let mut headers = HeaderMap::new();
+        headers.insert("x-session-id", "secret-session".parse().unwrap());
+        headers.insert("x-api-key", "secret-key".parse().unwrap());
+        headers.insert("content-type", "application/json".parse().unwrap());
+        let captured = capture_headers(&headers, &config(4096, &[]));
+
+        assert_eq!(captured["x-session-id"], REDACTED);
+        assert_eq!(captured["x-api-key"], REDACTED);
+        assert_eq!(captured["content-type"], "application/json");
+    }
+}
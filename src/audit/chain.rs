@@ -0,0 +1,433 @@
+//! Tamper evidence for the audit trail. With AUDIT_HMAC_KEY set, every entry
+//! gets the next `chain_seq`, the previous entry's hash as `prev_hash`, and a
+//! `chain_hash` of HMAC-SHA256(prev_hash || entry JSON), the first one chained
+//! to a random seed kept next to the log. Editing, removing or reordering an
+//! entry breaks every hash from there on, and without the key the chain can't
+//! be recomputed.
+//!
+//! `chain_hash` is always the last field of a line, so the signed JSON is the
+//! line with it cut off: verification works on the bytes as written, whatever
+//! fields later versions add to `AuditLog`.
+//!
+//! Next to the log, `chain.head` holds the newest link (so removing the newest
+//! entries shows) and `chain.boundaries.jsonl` the link each file started
+//! from (so a range of days can be verified without replaying all before it).
+
+use hmac::{Hmac, Mac};
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+use tokio::io::AsyncWriteExt;
+
+use crate::error::GatewayError;
+use crate::models::AuditLog;
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// Files in the audit directory next to the daily logs
+const SEED_FILE: &str = "chain.seed";
+const HEAD_FILE: &str = "chain.head";
+const BOUNDARIES_FILE: &str = "chain.boundaries.jsonl";
+
+const HASH_FIELD: &str = ",\"chain_hash\":\"";
+
+/// One link of the chain: an entry's `chain_seq` and `chain_hash`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ChainHead {
+    pub seq: u64,
+    pub hash: String,
+}
+
+/// The link a log file started from, recorded when the file was created
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Boundary {
+    file: String,
+    #[serde(flatten)]
+    head: ChainHead,
+}
+
+pub struct AuditChain {
+    key: Vec<u8>,
+}
+
+impl AuditChain {
+    pub fn new(key: &str) -> Self {
+        Self { key: key.as_bytes().to_vec() }
+    }
+
+    fn sign(&self, prev: &str, body: &str) -> String {
+        let mut mac = HmacSha256::new_from_slice(&self.key).expect("HMAC takes keys of any length");
+        mac.update(prev.as_bytes());
+        mac.update(body.as_bytes());
+        hex::encode(mac.finalize().into_bytes())
+    }
+
+    /// The line (newline excluded) for `entry` as the successor of `prev`
+    pub fn seal(&self, entry: &AuditLog, prev: &ChainHead) -> Result<(String, ChainHead), GatewayError> {
+        let mut entry = entry.clone();
+        entry.chain_seq = Some(prev.seq + 1);
+        entry.prev_hash = Some(prev.hash.clone());
+        entry.chain_hash = None;
+        let body = serde_json::to_string(&entry)
+            .map_err(|e| GatewayError::Internal(format!("Failed to serialize audit entry: {}", e)))?;
+        let hash = self.sign(&prev.hash, &body);
+        let line = format!("{}{}{}\"}}", &body[..body.len() - 1], HASH_FIELD, hash);
+        Ok((line, ChainHead { seq: prev.seq + 1, hash }))
+    }
+}
+
+/// Head of the chain before any entry: seq 0 and the seed
+pub fn genesis(seed: String) -> ChainHead {
+    ChainHead { seq: 0, hash: seed }
+}
+
+// === Files kept next to the log ===
+
+fn io_error(what: &'static str) -> impl Fn(std::io::Error) -> GatewayError {
+    move |e| GatewayError::Internal(format!("Failed to {} audit chain file: {}", what, e))
+}
+
+async fn read_optional(path: PathBuf) -> Result<Option<String>, GatewayError> {
+    match tokio::fs::read_to_string(path).await {
+        Ok(content) => Ok(Some(content)),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(io_error("read")(e)),
+    }
+}
+
+/// The stored seed, if the chain was ever started
+pub async fn read_seed(dir: &Path) -> Result<Option<String>, GatewayError> {
+    Ok(read_optional(dir.join(SEED_FILE)).await?.map(|seed| seed.trim().to_string()))
+}
+
+/// Store a new random seed; only done when writing the first chained entry
+pub async fn create_seed(dir: &Path) -> Result<String, GatewayError> {
+    let mut bytes = [0u8; 32];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    let seed = hex::encode(bytes);
+    tokio::fs::create_dir_all(dir).await.map_err(io_error("write"))?;
+    tokio::fs::write(dir.join(SEED_FILE), &seed).await.map_err(io_error("write"))?;
+    Ok(seed)
+}
+
+/// The newest link as of the last append
+pub async fn read_head(dir: &Path) -> Result<Option<ChainHead>, GatewayError> {
+    Ok(read_optional(dir.join(HEAD_FILE))
+        .await?
+        .and_then(|content| serde_json::from_str(&content).ok()))
+}
+
+/// Replace `chain.head`; written to a temporary file first so it is never half there
+pub async fn write_head(dir: &Path, head: &ChainHead) -> Result<(), GatewayError> {
+    let path = dir.join(HEAD_FILE);
+    let partial = dir.join(format!("{}.tmp", HEAD_FILE));
+    let json = serde_json::to_vec(head).map_err(|e| GatewayError::Internal(e.to_string()))?;
+    tokio::fs::write(&partial, json).await.map_err(io_error("write"))?;
+    tokio::fs::rename(&partial, &path).await.map_err(io_error("write"))
+}
+
+/// Record that `file` starts after `head`
+pub async fn record_boundary(dir: &Path, file: &str, head: &ChainHead) -> Result<(), GatewayError> {
+    let boundary = Boundary { file: file.to_string(), head: head.clone() };
+    let mut line = serde_json::to_vec(&boundary).map_err(|e| GatewayError::Internal(e.to_string()))?;
+    line.push(b'\n');
+    let mut out = tokio::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(dir.join(BOUNDARIES_FILE))
+        .await
+        .map_err(io_error("write"))?;
+    out.write_all(&line).await.map_err(io_error("write"))?;
+    out.flush().await.map_err(io_error("write"))
+}
+
+/// File name (without `.gz`) -> the link it started from
+pub async fn read_boundaries(dir: &Path) -> Result<HashMap<String, ChainHead>, GatewayError> {
+    let content = read_optional(dir.join(BOUNDARIES_FILE)).await?.unwrap_or_default();
+    Ok(content
+        .lines()
+        .filter_map(|line| serde_json::from_str::<Boundary>(line).ok())
+        .map(|boundary| (boundary.file, boundary.head))
+        .collect())
+}
+
+// === Reading links back ===
+
+/// `chain_seq` and `chain_hash` of a line, and the JSON that was signed
+fn split_line(line: &str) -> Option<(u64, &str, String)> {
+    let start = line.rfind(HASH_FIELD)?;
+    let hash = line[start + HASH_FIELD.len()..].strip_suffix("\"}")?;
+    let body = format!("{}}}", &line[..start]);
+    let seq = serde_json::from_str::<AuditLog>(&body).ok()?.chain_seq?;
+    Some((seq, hash, body))
+}
+
+/// The highest `chain_seq` in `content`, with its hash
+pub fn last_link(content: &str) -> Option<ChainHead> {
+    content
+        .lines()
+        .filter_map(split_line)
+        .max_by_key(|(seq, _, _)| *seq)
+        .map(|(seq, hash, _)| ChainHead { seq, hash: hash.to_string() })
+}
+
+/// First entry whose hash didn't match: the first break
+#[derive(Debug, Clone, Serialize, PartialEq, Eq)]
+pub struct BrokenLink {
+    pub seq: u64,
+    pub file: String,
+}
+
+/// Outcome of `POST /admin/audit/verify`
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ChainReport {
+    /// Every chained entry matches and none is missing
+    pub valid: bool,
+    /// Chained entries checked
+    pub checked: u64,
+    /// Entries without a chain (written before AUDIT_HMAC_KEY was set)
+    pub unchained: u64,
+    /// The check started from a known link: the seed, or the link recorded
+    /// when the first file was created. Otherwise (files from before
+    /// boundaries were recorded, with the start deleted by retention) the
+    /// oldest entry is taken as it is.
+    pub anchored: bool,
+    pub first_seq: Option<u64>,
+    pub last_seq: Option<u64>,
+    /// Entries whose hash doesn't match the recomputed chain: the first edited
+    /// one and every one after it
+    pub invalid: u64,
+    pub first_invalid: Option<BrokenLink>,
+    /// Lowest `chain_seq` absent from the log while later ones (or a later
+    /// `chain.head`) exist
+    pub first_missing: Option<u64>,
+}
+
+struct Link {
+    hash: String,
+    body: String,
+    file: String,
+    // Outside the requested range: replayed to keep the chain going, not reported
+    context: bool,
+}
+
+/// Replays the chain over files fed oldest first. Entries reach a file in the
+/// order they are written except around midnight (a request that started the
+/// day before lands in that day's file), so links are buffered until their
+/// predecessor has been seen.
+pub struct ChainVerifier<'a> {
+    chain: &'a AuditChain,
+    seed: Option<String>,
+    // Recomputed hash of the last entry checked
+    prev: Option<ChainHead>,
+    pending: BTreeMap<u64, Link>,
+    report: ChainReport,
+}
+
+impl<'a> ChainVerifier<'a> {
+    pub fn new(chain: &'a AuditChain, seed: Option<String>) -> Self {
+        Self {
+            chain,
+            seed,
+            prev: None,
+            pending: BTreeMap::new(),
+            report: ChainReport::default(),
+        }
+    }
+
+    /// Start after `anchor` (a recorded boundary); earlier entries are skipped
+    pub fn starting_after(mut self, anchor: ChainHead) -> Self {
+        self.report.anchored = true;
+        self.prev = Some(anchor);
+        self
+    }
+
+    /// Feed one file's content; `context` files (just outside a requested
+    /// range) only bridge entries written across midnight
+    pub fn feed(&mut self, file: &Path, content: &str, context: bool) {
+        let name = file.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default();
+        for line in content.lines().filter(|line| !line.trim().is_empty()) {
+            match split_line(line) {
+                Some((seq, _, _)) if self.prev.as_ref().is_some_and(|prev| seq <= prev.seq) => {}
+                Some((seq, hash, body)) => {
+                    let link = Link { hash: hash.to_string(), body, file: name.clone(), context };
+                    self.pending.insert(seq, link);
+                }
+                None if !context => self.report.unchained += 1,
+                None => {}
+            }
+        }
+        if self.prev.is_none() {
+            let Some(&first) = self.pending.keys().next() else { return };
+            self.prev = Some(match &self.seed {
+                Some(seed) if first == 1 => {
+                    self.report.anchored = true;
+                    genesis(seed.clone())
+                }
+                // The start is gone: trust the oldest entry left and check from its successor
+                _ => {
+                    let link = self.pending.remove(&first).expect("key just read");
+                    self.count(first, &link, true);
+                    ChainHead { seq: first, hash: link.hash }
+                }
+            });
+        }
+        self.advance();
+    }
+
+    fn count(&mut self, seq: u64, link: &Link, valid: bool) {
+        if link.context {
+            return;
+        }
+        self.report.first_seq.get_or_insert(seq);
+        self.report.last_seq = Some(seq);
+        self.report.checked += 1;
+        if !valid {
+            self.report.invalid += 1;
+            self.report.first_invalid.get_or_insert(BrokenLink { seq, file: link.file.clone() });
+        }
+    }
+
+    fn advance(&mut self) {
+        while let Some(prev) = self.prev.take() {
+            let seq = prev.seq + 1;
+            let Some(link) = self.pending.remove(&seq) else {
+                self.prev = Some(prev);
+                return;
+            };
+            let expected = self.chain.sign(&prev.hash, &link.body);
+            self.count(seq, &link, expected == link.hash);
+            // Out of range entries aren't judged, so the chain goes on from what they say
+            let hash = if link.context { link.hash } else { expected };
+            self.prev = Some(ChainHead { seq, hash });
+        }
+    }
+
+    /// Close the report. `head` is the newest link written (`chain.head`) when
+    /// the check runs to the end of the log.
+    pub fn finish(mut self, head: Option<&ChainHead>) -> ChainReport {
+        let reported: Vec<u64> = self
+            .pending
+            .iter()
+            .filter(|(_, link)| !link.context)
+            .map(|(seq, _)| *seq)
+            .collect();
+        if let Some(prev) = &self.prev {
+            if !reported.is_empty() {
+                self.report.first_missing = Some(prev.seq + 1);
+                // Whatever follows the gap can't be checked; count it all as suspect
+                self.report.invalid += reported.len() as u64;
+                self.report.last_seq = reported.last().copied();
+            } else if head.is_some_and(|head| head.seq > prev.seq) {
+                // The newest entries were removed
+                self.report.first_missing = Some(prev.seq + 1);
+            }
+        }
+        self.report.valid = self.report.invalid == 0 && self.report.first_missing.is_none();
+        self.report
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chain_of(chain: &AuditChain, seed: &str, endpoints: &[&str]) -> Vec<String> {
+        let mut head = genesis(seed.to_string());
+        endpoints
+            .iter()
+            .map(|endpoint| {
+                let entry = AuditLog::new("svc".to_string(), endpoint.to_string(), "GET".to_string());
+                let (line, next) = chain.seal(&entry, &head).unwrap();
+                head = next;
+                line
+            })
+            .collect()
+    }
+
+    fn verify(chain: &AuditChain, seed: &str, files: &[Vec<String>]) -> ChainReport {
+        let mut verifier = ChainVerifier::new(chain, Some(seed.to_string()));
+        for (i, lines) in files.iter().enumerate() {
+            verifier.feed(Path::new(&format!("audit-{}.jsonl", i)), &lines.join("\n"), false);
+        }
+        verifier.finish(None)
+    }
+
+    #[test]
+    fn test_sealed_lines_parse_and_verify() {
+        let chain = AuditChain::new("audit-key");
+        let lines = chain_of(&chain, "seed", &["/a", "/b", "/c"]);
+        let entry: AuditLog = serde_json::from_str(&lines[2]).unwrap();
+        let previous: AuditLog = serde_json::from_str(&lines[1]).unwrap();
+        assert_eq!(entry.chain_seq, Some(3));
+        assert_eq!(entry.prev_hash, previous.chain_hash);
+        assert_eq!(entry.chain_hash.as_deref().map(str::len), Some(64));
+        assert_eq!(last_link(&lines.join("\n")).unwrap().seq, 3);
+
+        let report = verify(&chain, "seed", &[lines]);
+        assert!(report.valid && report.anchored);
+        assert_eq!((report.checked, report.first_seq, report.last_seq), (3, Some(1), Some(3)));
+    }
+
+    #[test]
+    fn test_editing_an_entry_invalidates_it_and_everything_after() {
+        let chain = AuditChain::new("audit-key");
+        let mut lines = chain_of(&chain, "seed", &["/a", "/b", "/c", "/d"]);
+        lines[1] = lines[1].replace("\"/b\"", "\"/innocent\"");
+
+        let report = verify(&chain, "seed", &[lines]);
+        assert!(!report.valid);
+        assert_eq!(report.invalid, 3);
+        assert_eq!(report.first_invalid.unwrap().seq, 2);
+        // Without the key the hashes can't be redone
+        let forged = chain_of(&AuditChain::new("guess"), "seed", &["/a"]);
+        assert!(!verify(&chain, "seed", &[forged]).valid);
+    }
+
+    #[test]
+    fn test_removed_entries_and_midnight_reordering() {
+        let chain = AuditChain::new("audit-key");
+        let lines = chain_of(&chain, "seed", &["/a", "/b", "/c", "/d"]);
+
+        // Entry 3 started before midnight and was written to the earlier day's file
+        let reordered = vec![
+            vec![lines[0].clone(), lines[1].clone(), lines[2].clone()],
+            vec![lines[3].clone()],
+        ];
+        let swapped = vec![vec![lines[0].clone(), lines[2].clone()], vec![lines[1].clone(), lines[3].clone()]];
+        assert!(verify(&chain, "seed", &reordered).valid);
+        assert!(verify(&chain, "seed", &swapped).valid);
+
+        let gap = vec![vec![lines[0].clone(), lines[1].clone(), lines[3].clone()]];
+        let report = verify(&chain, "seed", &gap);
+        assert!(!report.valid);
+        assert_eq!(report.first_missing, Some(3));
+
+        // Retention removed the start: checked from the oldest entry left
+        let report = verify(&chain, "seed", &[lines[2..].to_vec()]);
+        assert!(report.valid && !report.anchored);
+        assert_eq!(report.first_seq, Some(3));
+
+        // Removing the newest entries only shows against the stored head
+        let mut verifier = ChainVerifier::new(&chain, Some("seed".to_string()));
+        verifier.feed(Path::new("audit-0.jsonl"), &lines[..3].join("\n"), false);
+        let head = last_link(&lines.join("\n")).unwrap();
+        assert_eq!(verifier.finish(Some(&head)).first_missing, Some(4));
+    }
+
+    #[test]
+    fn test_range_starts_from_a_boundary() {
+        let chain = AuditChain::new("audit-key");
+        let lines = chain_of(&chain, "seed", &["/a", "/b", "/c", "/d"]);
+        let boundary = last_link(&lines[..2].join("\n")).unwrap();
+
+        // Entries 3 and 4 alone, anchored at the link their file started from
+        let mut verifier = ChainVerifier::new(&chain, None).starting_after(boundary);
+        verifier.feed(Path::new("audit-1.jsonl"), &lines[2..].join("\n"), false);
+        let report = verifier.finish(None);
+        assert!(report.valid && report.anchored);
+        assert_eq!((report.first_seq, report.last_seq, report.checked), (Some(3), Some(4), 2));
+    }
+}
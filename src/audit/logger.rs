@@ -1,10 +1,12 @@
-use crate::models::AuditLog;
+use crate::models::{
+    AgentConfigAudit, AgentDeletionAudit, AlertAudit, AuditExportAudit, AuditLog, DataTransferAudit, TokenRefreshAudit,
+};
 
 /// Log an API request to the audit trail (for future audit integration)
 #[allow(dead_code)]
 pub fn log_request(audit: &AuditLog) {
     tracing::info!(
-        agent_id = %audit.agent_id,
+        agent_id = ?audit.agent_id,
         service = %audit.service_id,
         endpoint = %audit.endpoint,
         method = %audit.method,
@@ -13,3 +15,75 @@ pub fn log_request(audit: &AuditLog) {
         "API request"
     );
 }
+
+/// Log a credential refresh attempt to the audit trail
+pub fn log_token_refresh(audit: &TokenRefreshAudit) {
+    tracing::info!(
+        service = %audit.service_id,
+        trigger = ?audit.trigger,
+        outcome = ?audit.outcome,
+        new_expires_at = ?audit.new_expires_at,
+        duration_ms = audit.duration_ms,
+        "Token refresh"
+    );
+}
+
+/// Log an admin data export/import to the audit trail
+pub fn log_data_transfer(audit: &DataTransferAudit) {
+    tracing::info!(
+        action = ?audit.action,
+        mode = ?audit.mode,
+        users = audit.users,
+        agents = audit.agents,
+        sessions = audit.sessions,
+        credentials = audit.credentials,
+        "Data transfer"
+    );
+}
+
+/// Log an agent deletion and the sessions it revoked
+pub fn log_agent_deletion(audit: &AgentDeletionAudit) {
+    tracing::info!(
+        agent_id = %audit.agent_id,
+        owner_id = ?audit.owner_id,
+        by_admin = audit.by_admin,
+        sessions_revoked = audit.sessions_revoked,
+        "Agent deleted"
+    );
+}
+
+/// Log an admin change to an agent's configuration, one line per setting
+pub fn log_agent_config_change(audit: &AgentConfigAudit) {
+    for change in &audit.changes {
+        tracing::info!(
+            agent_id = %audit.agent_id,
+            field = %change.field,
+            before = %change.before,
+            after = %change.after,
+            "Agent configuration changed"
+        );
+    }
+}
+
+/// Log an admin export of the proxy audit trail
+pub fn log_audit_export(audit: &AuditExportAudit) {
+    tracing::info!(
+        format = %audit.format,
+        service = ?audit.service_id,
+        from = ?audit.from,
+        to = ?audit.to,
+        "Audit export"
+    );
+}
+
+/// Log a security alert fired by an alert rule
+pub fn log_alert(audit: &AlertAudit) {
+    tracing::warn!(
+        rule = %audit.rule,
+        key = %audit.key,
+        count = audit.count,
+        window_secs = audit.window_secs,
+        delivered = audit.delivered,
+        "Security alert"
+    );
+}
@@ -1,5 +1,42 @@
+mod admin_log;
+mod alerts;
+mod capture;
+mod chain;
+mod export;
 mod logger;
+mod request_id;
+mod sampling;
+mod sink;
+mod sqlite_store;
+mod stats;
+mod store;
+mod traits;
+mod writer;
 
 // Audit logging prepared for integration
 #[allow(unused_imports)]
 pub use logger::*;
+pub use admin_log::{admin_key_hash, AdminAuditLogger, AdminEventFilter};
+pub use alerts::{load_alert_rules, AlertEngine, AlertRule};
+#[allow(unused_imports)]
+pub use alerts::{Alert, AlertGroupBy, EventPredicate};
+pub use capture::capture_exchange;
+pub use chain::ChainReport;
+pub use export::ExportFormat;
+pub use request_id::*;
+pub use sampling::*;
+pub use store::{
+    spawn_audit_retention, AuditFilter, AuditStore, RetentionPolicy, DEFAULT_AUDIT_MAX_FILE_BYTES,
+};
+#[allow(unused_imports)]
+pub use store::RetentionSummary;
+pub use stats::{
+    compute_stats, parse_window, AuditStats, StatsCache, StatsGroupBy, DEFAULT_STATS_WINDOW, DEFAULT_TOP_PATHS,
+    MAX_TOP_PATHS,
+};
+pub use sqlite_store::SqliteAuditStore;
+pub use traits::{AuditStoreTrait, ExportChunks};
+pub use sink::{AuditSink, HttpAuditSink, HttpAuditSinkConfig, SyslogAuditSink};
+pub use writer::{AuditOverflowPolicy, AuditWriter, AuditWriterConfig, AuditWriterStats};
+#[allow(unused_imports)]
+pub use writer::AuditSinkStats;
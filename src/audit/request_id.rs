@@ -0,0 +1,78 @@
+// === Correlation ID for every request ===
+// Taken from the client's X-Request-ID when it looks sane, generated otherwise.
+// It tags the request's tracing events, goes upstream and into the audit entry,
+// and comes back in the response header and in error bodies.
+
+use axum::{
+    extract::Request,
+    http::{HeaderName, HeaderValue},
+    middleware::Next,
+    response::Response,
+};
+use tracing::Instrument;
+use uuid::Uuid;
+
+pub const REQUEST_ID_HEADER: HeaderName = HeaderName::from_static("x-request-id");
+
+/// Longest client-supplied ID kept; anything longer gets a fresh one
+const MAX_REQUEST_ID_LEN: usize = 128;
+
+tokio::task_local! {
+    // Set for the duration of each request, so error responses (which have no
+    // access to the request) can carry it
+    static REQUEST_ID: String;
+}
+
+/// ID of the request being handled on this task, if any
+pub fn current_request_id() -> Option<String> {
+    REQUEST_ID.try_with(Clone::clone).ok()
+}
+
+/// Client IDs are echoed into logs and upstream headers, so only plain tokens are kept
+fn is_acceptable(id: &str) -> bool {
+    !id.is_empty()
+        && id.len() <= MAX_REQUEST_ID_LEN
+        && id.bytes().all(|b| b.is_ascii_alphanumeric() || b"-_.:".contains(&b))
+}
+
+/// Middleware: assign the request ID and echo it in `X-Request-ID`
+pub async fn assign_request_id(mut request: Request, next: Next) -> Response {
+    let id = request
+        .headers()
+        .get(&REQUEST_ID_HEADER)
+        .and_then(|v| v.to_str().ok())
+        .filter(|id| is_acceptable(id))
+        .map(str::to_string)
+        .unwrap_or_else(|| Uuid::new_v4().to_string());
+    // Only acceptable IDs and UUIDs get here, both valid header values
+    let header = HeaderValue::from_str(&id).expect("request ID is a valid header value");
+    // Handlers (and the proxy's upstream call) see the ID the gateway settled on
+    request.headers_mut().insert(REQUEST_ID_HEADER, header.clone());
+
+    let span = tracing::info_span!("request", request_id = %id);
+    let mut response = REQUEST_ID.scope(id, next.run(request).instrument(span)).await;
+    response.headers_mut().insert(REQUEST_ID_HEADER, header);
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_only_plain_tokens_are_accepted() {
+        assert!(is_acceptable("0b6e2f4c-8d1a-4c1e-9f3b-2a7d5e6c8b90"));
+        assert!(is_acceptable("trace:abc.123_x"));
+        assert!(!is_acceptable(""));
+        assert!(!is_acceptable("has space"));
+        assert!(!is_acceptable("line\nbreak"));
+        assert!(!is_acceptable(&"a".repeat(MAX_REQUEST_ID_LEN + 1)));
+    }
+
+    #[tokio::test]
+    async fn test_current_request_id_is_scoped() {
+        assert_eq!(current_request_id(), None);
+        let inside = REQUEST_ID.scope("abc".to_string(), async { current_request_id() }).await;
+        assert_eq!(inside.as_deref(), Some("abc"));
+    }
+}
@@ -0,0 +1,67 @@
+// === Probabilistic sampling for high-volume info logs ===
+// Only routine per-request lines are sampled. Error-level logs and audit
+// records must never go through `should_log`.
+
+use axum::http::Request;
+use tower_http::trace::{DefaultMakeSpan, MakeSpan};
+use tracing::Span;
+
+/// Decide whether to emit a sampled log line; `rate` is clamped to 0.0..=1.0
+pub fn should_log(rate: f64) -> bool {
+    if rate >= 1.0 {
+        return true;
+    }
+    if rate <= 0.0 {
+        return false;
+    }
+    rand::random::<f64>() < rate
+}
+
+/// `TraceLayer` span factory that only creates request spans for sampled requests
+#[derive(Debug, Clone)]
+pub struct SampledMakeSpan {
+    rate: f64,
+    inner: DefaultMakeSpan,
+}
+
+impl SampledMakeSpan {
+    pub fn new(rate: f64) -> Self {
+        Self {
+            rate,
+            inner: DefaultMakeSpan::new(),
+        }
+    }
+}
+
+impl<B> MakeSpan<B> for SampledMakeSpan {
+    fn make_span(&mut self, request: &Request<B>) -> Span {
+        if should_log(self.rate) {
+            self.inner.make_span(request)
+        } else {
+            Span::none()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rate_bounds() {
+        assert!((0..1000).all(|_| should_log(1.0)));
+        assert!((0..1000).all(|_| !should_log(0.0)));
+    }
+
+    #[test]
+    fn test_partial_rate_samples_some() {
+        let logged = (0..10_000).filter(|_| should_log(0.5)).count();
+        assert!((4_000..6_000).contains(&logged));
+    }
+
+    #[test]
+    fn test_unsampled_request_gets_no_span() {
+        let request = Request::builder().uri("/api/x").body(()).unwrap();
+        assert!(SampledMakeSpan::new(0.0).make_span(&request).is_none());
+    }
+}
@@ -0,0 +1,356 @@
+//! Where queued audit entries end up. The local files (`AuditStore`) are what
+//! the admin endpoints query; external sinks ship the same entries to a SIEM:
+//! an HTTP endpoint taking JSON arrays (AUDIT_HTTP_ENDPOINT) and/or a syslog
+//! collector over UDP (AUDIT_SYSLOG_ADDR). Each sink has its own queue (see
+//! `AuditWriter`), so one that is down delays nothing but itself.
+
+use async_trait::async_trait;
+use reqwest::Client;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+use tokio::io::AsyncWriteExt;
+use tokio::net::UdpSocket;
+use tokio::sync::OnceCell;
+
+use super::AuditStore;
+use crate::error::GatewayError;
+use crate::models::AuditLog;
+
+const HTTP_SINK_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// facility log audit (13), severity informational (6)
+const SYSLOG_PRIORITY: u8 = 13 * 8 + 6;
+const SYSLOG_APP_NAME: &str = "sec_ai_agent_gw";
+
+#[async_trait]
+pub trait AuditSink: Send + Sync {
+    /// Shown in `GET /admin/proxy/stats`
+    fn name(&self) -> &'static str;
+
+    /// Deliver `entries`, in order. An error counts them as failed; it never
+    /// reaches the request that produced them.
+    async fn write_batch(&self, entries: &[AuditLog]) -> Result<(), GatewayError>;
+
+    /// Failed entries kept aside for a later replay
+    fn spooled(&self) -> u64 {
+        0
+    }
+}
+
+#[async_trait]
+impl AuditSink for AuditStore {
+    fn name(&self) -> &'static str {
+        "file"
+    }
+
+    async fn write_batch(&self, entries: &[AuditLog]) -> Result<(), GatewayError> {
+        self.append_batch(entries).await
+    }
+}
+
+/// AUDIT_HTTP_*: batches POSTed to a collector
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HttpAuditSinkConfig {
+    pub endpoint: String,
+    /// Sent with every batch, e.g. `("Authorization", "Bearer ...")`
+    pub auth_header: Option<(String, String)>,
+    pub batch_size: usize,
+    /// Attempts after the first, waiting `retry_backoff`, then twice as long, ...
+    pub max_retries: u32,
+    pub retry_backoff: Duration,
+    /// Batches still failing after the retries are appended here as JSON lines;
+    /// `None` drops them
+    pub spool_path: Option<PathBuf>,
+}
+
+/// POSTs each batch as a JSON array; any non-2xx answer is a failure
+pub struct HttpAuditSink {
+    client: Client,
+    config: HttpAuditSinkConfig,
+    spooled: AtomicU64,
+}
+
+impl HttpAuditSink {
+    pub fn new(config: HttpAuditSinkConfig) -> Self {
+        Self {
+            client: Client::builder()
+                .timeout(HTTP_SINK_TIMEOUT)
+                .build()
+                .unwrap_or_default(),
+            config,
+            spooled: AtomicU64::new(0),
+        }
+    }
+
+    async fn post(&self, entries: &[AuditLog]) -> Result<(), GatewayError> {
+        let mut request = self.client.post(&self.config.endpoint).json(entries);
+        if let Some((name, value)) = &self.config.auth_header {
+            request = request.header(name, value);
+        }
+        let response = request
+            .send()
+            .await
+            .map_err(|e| GatewayError::UpstreamUnavailable(format!("Audit sink unreachable: {}", e)))?;
+        if !response.status().is_success() {
+            return Err(GatewayError::UpstreamError(format!(
+                "Audit sink answered {}",
+                response.status()
+            )));
+        }
+        Ok(())
+    }
+
+    /// Append `entries` to the spool file, one JSON object per line
+    async fn spool(&self, entries: &[AuditLog]) -> Result<(), GatewayError> {
+        let Some(path) = &self.config.spool_path else {
+            return Err(GatewayError::Internal("No AUDIT_HTTP_SPOOL_PATH to keep them in".to_string()));
+        };
+        let mut lines = String::new();
+        for entry in entries {
+            lines.push_str(&serde_json::to_string(entry).map_err(|e| GatewayError::Internal(e.to_string()))?);
+            lines.push('\n');
+        }
+        if let Some(parent) = path.parent() {
+            tokio::fs::create_dir_all(parent)
+                .await
+                .map_err(|e| GatewayError::Internal(format!("Failed to create audit spool directory: {}", e)))?;
+        }
+        let mut file = tokio::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .await
+            .map_err(|e| GatewayError::Internal(format!("Failed to open audit spool: {}", e)))?;
+        let write_error = |e: std::io::Error| GatewayError::Internal(format!("Failed to write audit spool: {}", e));
+        file.write_all(lines.as_bytes()).await.map_err(write_error)?;
+        file.flush().await.map_err(write_error)?;
+        self.spooled.fetch_add(entries.len() as u64, Ordering::Relaxed);
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl AuditSink for HttpAuditSink {
+    fn name(&self) -> &'static str {
+        "http"
+    }
+
+    async fn write_batch(&self, entries: &[AuditLog]) -> Result<(), GatewayError> {
+        let mut backoff = self.config.retry_backoff;
+        let mut attempt = 0;
+        let error = loop {
+            match self.post(entries).await {
+                Ok(()) => return Ok(()),
+                Err(e) if attempt < self.config.max_retries => {
+                    attempt += 1;
+                    tracing::warn!(error = ?e, attempt, entries = entries.len(), "Audit sink batch failed, retrying");
+                    tokio::time::sleep(backoff).await;
+                    backoff *= 2;
+                }
+                Err(e) => break e,
+            }
+        };
+        // Still counted as failed: spooled entries weren't delivered
+        match self.spool(entries).await {
+            Ok(()) => Err(GatewayError::UpstreamError(format!("{:?}; batch spooled", error))),
+            Err(spool_error) => Err(GatewayError::Internal(format!("{:?}; not spooled: {:?}", error, spool_error))),
+        }
+    }
+
+    fn spooled(&self) -> u64 {
+        self.spooled.load(Ordering::Relaxed)
+    }
+}
+
+/// One RFC 5424 datagram per entry, the entry's JSON as the message
+pub struct SyslogAuditSink {
+    addr: String,
+    // Bound on first use: AppState is built outside the runtime
+    socket: OnceCell<UdpSocket>,
+}
+
+impl SyslogAuditSink {
+    /// `addr`: `host:port` of the collector
+    pub fn new(addr: String) -> Self {
+        Self { addr, socket: OnceCell::new() }
+    }
+
+    fn message(entry: &AuditLog) -> Result<String, GatewayError> {
+        let json = serde_json::to_string(entry).map_err(|e| GatewayError::Internal(e.to_string()))?;
+        Ok(format!(
+            "<{}>1 {} - {} - audit - {}",
+            SYSLOG_PRIORITY,
+            entry.timestamp.to_rfc3339_opts(chrono::SecondsFormat::Millis, true),
+            SYSLOG_APP_NAME,
+            json
+        ))
+    }
+}
+
+#[async_trait]
+impl AuditSink for SyslogAuditSink {
+    fn name(&self) -> &'static str {
+        "syslog"
+    }
+
+    async fn write_batch(&self, entries: &[AuditLog]) -> Result<(), GatewayError> {
+        let socket = self
+            .socket
+            .get_or_try_init(|| async {
+                let socket = UdpSocket::bind("0.0.0.0:0").await?;
+                socket.connect(&self.addr).await?;
+                Ok::<_, std::io::Error>(socket)
+            })
+            .await
+            .map_err(|e| GatewayError::UpstreamUnavailable(format!("Syslog sink {}: {}", self.addr, e)))?;
+        for entry in entries {
+            socket
+                .send(Self::message(entry)?.as_bytes())
+                .await
+                .map_err(|e| GatewayError::UpstreamUnavailable(format!("Syslog sink {}: {}", self.addr, e)))?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::audit::{AuditWriter, AuditWriterConfig};
+    use std::sync::Arc;
+    use tempfile::TempDir;
+    use wiremock::matchers::{header, method};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    fn entry(i: usize) -> AuditLog {
+        AuditLog::new("svc".to_string(), format!("/{}", i), "GET".to_string())
+    }
+
+    fn http_config(endpoint: String, spool_path: Option<PathBuf>) -> HttpAuditSinkConfig {
+        HttpAuditSinkConfig {
+            endpoint,
+            auth_header: Some(("Authorization".to_string(), "Bearer siem".to_string())),
+            batch_size: 2,
+            max_retries: 2,
+            retry_backoff: Duration::from_millis(10),
+            spool_path,
+        }
+    }
+
+    fn writer_with(sink: Arc<dyn AuditSink>, batch_size: usize) -> AuditWriter {
+        let config = AuditWriterConfig { batch_size, ..Default::default() };
+        AuditWriter::default().with_sink(sink, config)
+    }
+
+    #[tokio::test]
+    async fn test_entries_are_posted_in_batches() {
+        let receiver = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(header("Authorization", "Bearer siem"))
+            .respond_with(ResponseTemplate::new(200))
+            .mount(&receiver)
+            .await;
+        let sink = Arc::new(HttpAuditSink::new(http_config(receiver.uri(), None)));
+        let writer = writer_with(sink, 2);
+
+        for i in 0..5 {
+            writer.record(entry(i)).await;
+        }
+        writer.drain().await;
+
+        let batches: Vec<Vec<AuditLog>> = receiver
+            .received_requests()
+            .await
+            .unwrap()
+            .iter()
+            .map(|request| serde_json::from_slice(&request.body).unwrap())
+            .collect();
+        assert_eq!(batches.iter().map(Vec::len).collect::<Vec<_>>(), [2, 2, 1]);
+        assert_eq!(batches[2][0].endpoint, "/4");
+        assert_eq!(writer.stats().sinks[0].written, 5);
+    }
+
+    #[tokio::test]
+    async fn test_failed_batches_are_retried() {
+        let receiver = MockServer::start().await;
+        Mock::given(method("POST"))
+            .respond_with(ResponseTemplate::new(503))
+            .up_to_n_times(2)
+            .mount(&receiver)
+            .await;
+        Mock::given(method("POST"))
+            .respond_with(ResponseTemplate::new(202))
+            .mount(&receiver)
+            .await;
+        let sink = Arc::new(HttpAuditSink::new(http_config(receiver.uri(), None)));
+        let writer = writer_with(sink, 2);
+
+        writer.record(entry(0)).await;
+        writer.drain().await;
+
+        assert_eq!(receiver.received_requests().await.unwrap().len(), 3);
+        let stats = &writer.stats().sinks[0];
+        assert_eq!((stats.written, stats.failed, stats.spooled), (1, 0, 0));
+    }
+
+    #[tokio::test]
+    async fn test_persistent_failure_is_spooled() {
+        let receiver = MockServer::start().await;
+        Mock::given(method("POST"))
+            .respond_with(ResponseTemplate::new(500))
+            .mount(&receiver)
+            .await;
+        let dir = TempDir::new().unwrap();
+        let spool = dir.path().join("spool/audit_http.jsonl");
+        let sink = Arc::new(HttpAuditSink::new(http_config(receiver.uri(), Some(spool.clone()))));
+        let writer = writer_with(sink, 2);
+
+        for i in 0..3 {
+            writer.record(entry(i)).await;
+        }
+        writer.drain().await;
+
+        // Two batches, each tried three times
+        assert_eq!(receiver.received_requests().await.unwrap().len(), 6);
+        let spooled: Vec<AuditLog> = std::fs::read_to_string(&spool)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(spooled.iter().map(|e| e.endpoint.as_str()).collect::<Vec<_>>(), ["/0", "/1", "/2"]);
+        let stats = &writer.stats().sinks[0];
+        assert_eq!((stats.written, stats.failed, stats.spooled), (0, 3, 3));
+    }
+
+    #[tokio::test]
+    async fn test_sinks_run_side_by_side() {
+        let collector = std::net::UdpSocket::bind("127.0.0.1:0").unwrap();
+        collector.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+        let dir = TempDir::new().unwrap();
+        let store = Arc::new(AuditStore::new(dir.path()));
+        let unreachable = http_config("http://127.0.0.1:9/audit".to_string(), None);
+        let writer = AuditWriter::new(store, AuditWriterConfig::default())
+            .with_sink(Arc::new(HttpAuditSink::new(unreachable)), AuditWriterConfig::default())
+            .with_sink(
+                Arc::new(SyslogAuditSink::new(collector.local_addr().unwrap().to_string())),
+                AuditWriterConfig::default(),
+            );
+
+        writer.record(entry(7)).await;
+        writer.drain().await;
+
+        let mut datagram = [0u8; 4096];
+        let len = collector.recv(&mut datagram).unwrap();
+        let message = std::str::from_utf8(&datagram[..len]).unwrap();
+        assert!(message.starts_with("<110>1 "));
+        assert!(message.contains(r#""endpoint":"/7""#));
+
+        // The collector being down cost the other sinks nothing
+        let stats = writer.stats();
+        assert_eq!(stats.written, 1);
+        let by_name = |name: &str| stats.sinks.iter().find(|s| s.name == name).unwrap().clone();
+        assert_eq!(by_name("syslog").written, 1);
+        assert_eq!(by_name("http").failed, 1);
+    }
+}
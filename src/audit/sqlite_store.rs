@@ -0,0 +1,493 @@
+//! SQLite audit backend (AUDIT_BACKEND=sqlite): one `audit_log` row per entry
+//! in AUDIT_DATABASE_PATH, with the full entry as JSON in `data` and the
+//! filtered fields in indexed columns, so a query over a long history reads
+//! the matching rows instead of whole days of files. Each batch from the
+//! `AuditWriter` goes in as one transaction. Entries are not HMAC-chained.
+
+use async_trait::async_trait;
+use chrono::{DateTime, NaiveDate, Utc};
+use futures_util::stream::{self, StreamExt};
+use rusqlite::types::Value;
+use rusqlite::{params, Connection};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+use super::store::read_days_blocking;
+use super::{
+    AuditFilter, AuditSink, AuditStoreTrait, ExportChunks, ExportFormat, RetentionPolicy, RetentionSummary,
+};
+use crate::error::GatewayError;
+use crate::models::{AuditLog, Decision};
+use crate::storage::{run_migrations, Page};
+
+/// Rows fetched per round trip by `scan` and `export`
+const PAGE_ROWS: usize = 1000;
+
+// === Schema migrations, applied in order; never edit a released entry ===
+const MIGRATIONS: &[&str] = &[
+    // 1: initial schema
+    "CREATE TABLE audit_log (
+        id TEXT PRIMARY KEY,
+        timestamp TEXT NOT NULL,
+        agent_id TEXT,
+        service_id TEXT NOT NULL,
+        status_code INTEGER NOT NULL,
+        decision TEXT NOT NULL,
+        data TEXT NOT NULL
+    );
+    CREATE INDEX idx_audit_log_timestamp ON audit_log (timestamp);
+    CREATE INDEX idx_audit_log_agent_id ON audit_log (agent_id, timestamp);
+    CREATE INDEX idx_audit_log_service_id ON audit_log (service_id, timestamp);",
+];
+
+const INSERT: &str = "INSERT OR IGNORE INTO audit_log
+     (id, timestamp, agent_id, service_id, status_code, decision, data)
+     VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)";
+
+/// Where `scan` and `export` continue from: the last row's (timestamp, rowid)
+type Cursor = (String, i64);
+
+#[derive(Clone)]
+pub struct SqliteAuditStore {
+    conn: Arc<Mutex<Connection>>,
+}
+
+impl SqliteAuditStore {
+    /// Open (or create) the database and bring its schema up to date
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, GatewayError> {
+        let path = path.as_ref();
+        if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+            std::fs::create_dir_all(parent).map_err(|e| {
+                GatewayError::Internal(format!("Failed to create {}: {}", parent.display(), e))
+            })?;
+        }
+
+        let mut conn = Connection::open(path).map_err(|e| {
+            GatewayError::Internal(format!("Failed to open audit database {}: {}", path.display(), e))
+        })?;
+        run_migrations(&mut conn, MIGRATIONS)?;
+
+        Ok(Self {
+            conn: Arc::new(Mutex::new(conn)),
+        })
+    }
+
+    /// Load the JSONL files of `dir` (AUDIT_LOG_PATH) into an empty table, one
+    /// transaction per day: the first boot after switching backends. Returns
+    /// the number of entries imported; the files are left in place.
+    pub fn import_jsonl_if_empty(&self, dir: &Path) -> Result<usize, GatewayError> {
+        let mut conn = self
+            .conn
+            .lock()
+            .map_err(|_| GatewayError::Internal("SQLite connection poisoned".to_string()))?;
+        let is_empty: bool = conn
+            .query_row("SELECT NOT EXISTS (SELECT 1 FROM audit_log)", [], |row| row.get(0))
+            .map_err(db_error)?;
+        if !is_empty {
+            return Ok(0);
+        }
+
+        let mut imported = 0;
+        read_days_blocking(dir, |date, entries| {
+            let rows = entries.iter().map(Row::new).collect::<Result<Vec<_>, _>>()?;
+            let tx = conn.transaction().map_err(db_error)?;
+            insert_rows(&tx, &rows).map_err(db_error)?;
+            tx.commit().map_err(db_error)?;
+            tracing::info!(day = %date, entries = rows.len(), "Imported audit log day");
+            imported += rows.len();
+            Ok(())
+        })?;
+        Ok(imported)
+    }
+
+    // === Run a blocking closure against the connection off the async runtime ===
+    async fn with_conn<T, F>(&self, f: F) -> Result<T, GatewayError>
+    where
+        T: Send + 'static,
+        F: FnOnce(&Connection) -> rusqlite::Result<T> + Send + 'static,
+    {
+        let conn = self.conn.clone();
+        tokio::task::spawn_blocking(move || {
+            let conn = conn
+                .lock()
+                .map_err(|_| GatewayError::Internal("SQLite connection poisoned".to_string()))?;
+            f(&conn).map_err(db_error)
+        })
+        .await
+        .map_err(|e| GatewayError::Internal(format!("SQLite task failed: {}", e)))?
+    }
+
+    /// Up to `PAGE_ROWS` matching entries after `cursor`, oldest first
+    async fn page_after(
+        &self,
+        filter: &AuditFilter,
+        cursor: Option<Cursor>,
+    ) -> Result<Vec<(Cursor, AuditLog)>, GatewayError> {
+        let (mut clauses, mut values) = filter_sql(filter);
+        if let Some((timestamp, rowid)) = cursor {
+            clauses.push("(timestamp, rowid) > (?, ?)");
+            values.push(Value::Text(timestamp));
+            values.push(Value::Integer(rowid));
+        }
+        let sql = format!(
+            "SELECT timestamp, rowid, data FROM audit_log {} ORDER BY timestamp, rowid LIMIT {}",
+            where_sql(&clauses),
+            PAGE_ROWS
+        );
+        let rows: Vec<(String, i64, String)> = self
+            .with_conn(move |conn| {
+                let mut stmt = conn.prepare(&sql)?;
+                let rows = stmt.query_map(rusqlite::params_from_iter(values), |row| {
+                    Ok((row.get(0)?, row.get(1)?, row.get(2)?))
+                })?;
+                rows.collect()
+            })
+            .await?;
+        rows.into_iter()
+            .map(|(timestamp, rowid, data)| Ok(((timestamp, rowid), from_json(&data)?)))
+            .collect()
+    }
+}
+
+#[async_trait]
+impl AuditSink for SqliteAuditStore {
+    fn name(&self) -> &'static str {
+        "sqlite"
+    }
+
+    async fn write_batch(&self, entries: &[AuditLog]) -> Result<(), GatewayError> {
+        let rows = entries.iter().map(Row::new).collect::<Result<Vec<_>, _>>()?;
+        self.with_conn(move |conn| {
+            let tx = conn.unchecked_transaction()?;
+            insert_rows(&tx, &rows)?;
+            tx.commit()
+        })
+        .await
+    }
+}
+
+#[async_trait]
+impl AuditStoreTrait for SqliteAuditStore {
+    async fn query(
+        &self,
+        filter: &AuditFilter,
+        offset: usize,
+        limit: usize,
+    ) -> Result<Page<AuditLog>, GatewayError> {
+        let (clauses, values) = filter_sql(filter);
+        let filter_sql = where_sql(&clauses);
+        let count_sql = format!("SELECT COUNT(*) FROM audit_log {}", filter_sql);
+        let page_sql = format!(
+            "SELECT data FROM audit_log {} ORDER BY timestamp DESC, id DESC LIMIT {} OFFSET {}",
+            filter_sql,
+            i64::try_from(limit).unwrap_or(i64::MAX),
+            i64::try_from(offset).unwrap_or(i64::MAX)
+        );
+        let (total, rows): (i64, Vec<String>) = self
+            .with_conn(move |conn| {
+                let total = conn.query_row(&count_sql, rusqlite::params_from_iter(values.iter()), |row| row.get(0))?;
+                let mut stmt = conn.prepare(&page_sql)?;
+                let rows = stmt
+                    .query_map(rusqlite::params_from_iter(values.iter()), |row| row.get(0))?
+                    .collect::<rusqlite::Result<_>>()?;
+                Ok((total, rows))
+            })
+            .await?;
+        Ok(Page {
+            total: total as usize,
+            items: rows.iter().map(|data| from_json(data)).collect::<Result<_, _>>()?,
+        })
+    }
+
+    async fn scan(
+        &self,
+        filter: &AuditFilter,
+        visit: &mut (dyn FnMut(AuditLog) + Send),
+    ) -> Result<(), GatewayError> {
+        let mut cursor = None;
+        loop {
+            let page = self.page_after(filter, cursor.take()).await?;
+            let full = page.len() == PAGE_ROWS;
+            for (next, entry) in page {
+                cursor = Some(next);
+                visit(entry);
+            }
+            if !full {
+                return Ok(());
+            }
+        }
+    }
+
+    async fn export(self: Arc<Self>, filter: AuditFilter, format: ExportFormat) -> Result<ExportChunks, GatewayError> {
+        let header = Some(format.header()).filter(|header| !header.is_empty());
+        // (cursor, done): done once a page comes back short
+        let rows = stream::unfold((self, None, false), move |(store, cursor, done): (Arc<Self>, Option<Cursor>, bool)| {
+            let filter = filter.clone();
+            async move {
+                if done {
+                    return None;
+                }
+                match store.page_after(&filter, cursor).await {
+                    Ok(page) if page.is_empty() => None,
+                    Ok(page) => {
+                        let done = page.len() < PAGE_ROWS;
+                        let cursor = page.last().map(|(cursor, _)| cursor.clone());
+                        let chunk: String = page.iter().map(|(_, entry)| format.row(entry)).collect();
+                        Some((Ok(chunk), (store, cursor, done)))
+                    }
+                    Err(e) => {
+                        tracing::error!(error = ?e, "Audit export failed");
+                        let error = std::io::Error::other(format!("{:?}", e));
+                        Some((Err(error), (store, None, true)))
+                    }
+                }
+            }
+        });
+        Ok(stream::iter(header.map(Ok)).chain(rows).boxed())
+    }
+
+    /// Deletes the rows of the days past `retention_days`; there are no files
+    /// to compress, so `compress_after_days` has nothing to do here
+    async fn apply_retention(
+        &self,
+        policy: &RetentionPolicy,
+        today: NaiveDate,
+    ) -> Result<RetentionSummary, GatewayError> {
+        let Some(days) = policy.retention_days else {
+            return Ok(RetentionSummary::default());
+        };
+        let cutoff = (today - chrono::Duration::days(i64::from(days))).and_time(chrono::NaiveTime::MIN).and_utc();
+        let cutoff = timestamp_key(&cutoff);
+        let deleted_entries = self
+            .with_conn(move |conn| conn.execute("DELETE FROM audit_log WHERE timestamp < ?1", params![cutoff]))
+            .await?;
+        Ok(RetentionSummary {
+            deleted_entries,
+            ..Default::default()
+        })
+    }
+}
+
+/// An entry's columns, serialized before the blocking insert
+struct Row {
+    id: String,
+    timestamp: String,
+    agent_id: Option<String>,
+    service_id: String,
+    status_code: u16,
+    decision: &'static str,
+    data: String,
+}
+
+impl Row {
+    fn new(entry: &AuditLog) -> Result<Self, GatewayError> {
+        Ok(Self {
+            id: entry.id.to_string(),
+            timestamp: timestamp_key(&entry.timestamp),
+            agent_id: entry.agent_id.map(|id| id.to_string()),
+            service_id: entry.service_id.clone(),
+            status_code: entry.status_code,
+            decision: decision_key(entry.decision),
+            data: serde_json::to_string(entry)
+                .map_err(|e| GatewayError::Internal(format!("Failed to serialize audit entry: {}", e)))?,
+        })
+    }
+}
+
+fn insert_rows(conn: &Connection, rows: &[Row]) -> rusqlite::Result<()> {
+    let mut stmt = conn.prepare_cached(INSERT)?;
+    for row in rows {
+        stmt.execute(params![
+            row.id,
+            row.timestamp,
+            row.agent_id,
+            row.service_id,
+            row.status_code,
+            row.decision,
+            row.data
+        ])?;
+    }
+    Ok(())
+}
+
+/// Fixed-width UTC with nanoseconds, so text order is time order
+fn timestamp_key(timestamp: &DateTime<Utc>) -> String {
+    timestamp.format("%Y-%m-%dT%H:%M:%S%.9fZ").to_string()
+}
+
+fn decision_key(decision: Decision) -> &'static str {
+    match decision {
+        Decision::Allowed => "allowed",
+        Decision::Denied => "denied",
+    }
+}
+
+/// `filter` as SQL conditions on the indexed columns, with their parameters
+fn filter_sql(filter: &AuditFilter) -> (Vec<&'static str>, Vec<Value>) {
+    let mut clauses = Vec::new();
+    let mut values = Vec::new();
+    if let Some(agent_id) = filter.agent_id {
+        clauses.push("agent_id = ?");
+        values.push(Value::Text(agent_id.to_string()));
+    }
+    if let Some(service_id) = &filter.service_id {
+        clauses.push("service_id = ?");
+        values.push(Value::Text(service_id.clone()));
+    }
+    if let Some(from) = &filter.from {
+        clauses.push("timestamp >= ?");
+        values.push(Value::Text(timestamp_key(from)));
+    }
+    if let Some(to) = &filter.to {
+        clauses.push("timestamp <= ?");
+        values.push(Value::Text(timestamp_key(to)));
+    }
+    if let Some(min) = filter.status_min {
+        clauses.push("status_code >= ?");
+        values.push(Value::Integer(i64::from(min)));
+    }
+    if let Some(max) = filter.status_max {
+        clauses.push("status_code <= ?");
+        values.push(Value::Integer(i64::from(max)));
+    }
+    if let Some(decision) = filter.decision {
+        clauses.push("decision = ?");
+        values.push(Value::Text(decision_key(decision).to_string()));
+    }
+    (clauses, values)
+}
+
+fn where_sql(clauses: &[&str]) -> String {
+    if clauses.is_empty() {
+        String::new()
+    } else {
+        format!("WHERE {}", clauses.join(" AND "))
+    }
+}
+
+fn db_error(e: rusqlite::Error) -> GatewayError {
+    GatewayError::Internal(format!("Audit database error: {}", e))
+}
+
+fn from_json(data: &str) -> Result<AuditLog, GatewayError> {
+    serde_json::from_str(data)
+        .map_err(|e| GatewayError::Internal(format!("Failed to parse audit row: {}", e)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::audit::AuditStore;
+    use chrono::TimeZone;
+    use tempfile::TempDir;
+    use uuid::Uuid;
+
+    /// The same entries, over three days, in both backends
+    async fn seeded(dir: &TempDir) -> (AuditStore, SqliteAuditStore, [Uuid; 2]) {
+        let files = AuditStore::new(dir.path().join("files"));
+        let sqlite = SqliteAuditStore::open(dir.path().join("audit.db")).unwrap();
+        let agents = [Uuid::new_v4(), Uuid::new_v4()];
+        let mut entries = Vec::new();
+        for i in 0..30u32 {
+            let service = if i % 3 == 0 { "bank" } else { "payment" };
+            let mut entry = AuditLog::new(service.to_string(), format!("/items/{}", i), "GET".to_string());
+            entry.agent_id = (i % 5 != 0).then(|| agents[(i % 2) as usize]);
+            entry.timestamp = Utc.with_ymd_and_hms(2025, 11, 1 + i / 10, i % 24, i, 0).unwrap();
+            entry.status_code = [200, 404, 429, 502][(i % 4) as usize];
+            entry.decision = if entry.status_code == 429 { Decision::Denied } else { Decision::Allowed };
+            entries.push(entry);
+        }
+        // Two entries in the same instant, ordered by id in both backends
+        let mut twin = entries[3].clone();
+        twin.id = Uuid::new_v4();
+        entries.push(twin);
+        for batch in entries.chunks(7) {
+            files.write_batch(batch).await.unwrap();
+            sqlite.write_batch(batch).await.unwrap();
+        }
+        (files, sqlite, agents)
+    }
+
+    fn ids(page: &Page<AuditLog>) -> (usize, Vec<Uuid>) {
+        (page.total, page.items.iter().map(|entry| entry.id).collect())
+    }
+
+    async fn scanned(store: &dyn AuditStoreTrait, filter: &AuditFilter) -> Vec<Uuid> {
+        let mut seen = Vec::new();
+        store.scan(filter, &mut |entry| seen.push(entry.id)).await.unwrap();
+        seen.sort();
+        seen
+    }
+
+    #[tokio::test]
+    async fn test_queries_match_the_file_backend() {
+        let dir = TempDir::new().unwrap();
+        let (files, sqlite, agents) = seeded(&dir).await;
+        let day = |d: u32, h: u32| Utc.with_ymd_and_hms(2025, 11, d, h, 0, 0).unwrap();
+        let filters = [
+            AuditFilter::default(),
+            AuditFilter { agent_id: Some(agents[0]), ..Default::default() },
+            AuditFilter { service_id: Some("bank".to_string()), ..Default::default() },
+            AuditFilter { from: Some(day(1, 5)), to: Some(day(2, 13)), ..Default::default() },
+            AuditFilter { status_min: Some(400), status_max: Some(499), ..Default::default() },
+            AuditFilter { decision: Some(Decision::Denied), agent_id: Some(agents[1]), ..Default::default() },
+            AuditFilter { from: Some(day(4, 0)), ..Default::default() },
+        ];
+        for filter in &filters {
+            for (offset, limit) in [(0, 100), (0, 3), (4, 5), (50, 10)] {
+                assert_eq!(
+                    ids(&files.query(filter, offset, limit).await.unwrap()),
+                    ids(&sqlite.query(filter, offset, limit).await.unwrap()),
+                    "{:?} from {} limit {}",
+                    filter,
+                    offset,
+                    limit
+                );
+            }
+            assert_eq!(scanned(&files, filter).await, scanned(&sqlite, filter).await, "{:?}", filter);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_export_and_retention() {
+        let dir = TempDir::new().unwrap();
+        let (files, sqlite, _) = seeded(&dir).await;
+        let filter = AuditFilter { service_id: Some("payment".to_string()), ..Default::default() };
+
+        let export = |store: Arc<dyn AuditStoreTrait>| {
+            let filter = filter.clone();
+            async move {
+                let chunks: Vec<_> = store.export(filter, ExportFormat::Jsonl).await.unwrap().collect().await;
+                let text: String = chunks.into_iter().map(Result::unwrap).collect();
+                let mut lines: Vec<String> = text.lines().map(str::to_string).collect();
+                lines.sort();
+                lines
+            }
+        };
+        let exported = export(Arc::new(sqlite.clone())).await;
+        assert_eq!(exported.len(), 20);
+        assert_eq!(exported, export(Arc::new(files)).await);
+
+        let policy = RetentionPolicy { retention_days: Some(1), compress_after_days: Some(1) };
+        let summary = sqlite
+            .apply_retention(&policy, NaiveDate::from_ymd_opt(2025, 11, 4).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(summary.deleted_entries, 21);
+        let left = sqlite.query(&AuditFilter::default(), 0, 100).await.unwrap();
+        assert_eq!(left.total, 10);
+        assert!(left.items.iter().all(|entry| entry.timestamp.date_naive() == NaiveDate::from_ymd_opt(2025, 11, 3).unwrap()));
+    }
+
+    #[tokio::test]
+    async fn test_imports_jsonl_files_once() {
+        let dir = TempDir::new().unwrap();
+        let (files, _, _) = seeded(&dir).await;
+        let imported = SqliteAuditStore::open(dir.path().join("imported.db")).unwrap();
+
+        assert_eq!(imported.import_jsonl_if_empty(&dir.path().join("files")).unwrap(), 31);
+        assert_eq!(imported.import_jsonl_if_empty(&dir.path().join("files")).unwrap(), 0);
+        let all = AuditFilter::default();
+        assert_eq!(ids(&files.query(&all, 0, 100).await.unwrap()), ids(&imported.query(&all, 0, 100).await.unwrap()));
+    }
+}
@@ -0,0 +1,345 @@
+//! Aggregated audit statistics for dashboards (`GET /admin/stats`): request and
+//! error counts, latency and the busiest paths per service or agent over a
+//! recent window. Computed in one pass over the audit files with
+//! `AuditStoreTrait::scan`, keeping only per-group counters, and cached for a few
+//! seconds since dashboards poll.
+
+use chrono::{DateTime, Utc};
+use dashmap::DashMap;
+use serde::{Deserialize, Serialize};
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use super::{AuditFilter, AuditStoreTrait};
+use crate::error::GatewayError;
+use crate::models::AuditLog;
+
+pub const DEFAULT_STATS_WINDOW: &str = "24h";
+pub const DEFAULT_TOP_PATHS: usize = 5;
+pub const MAX_TOP_PATHS: usize = 50;
+
+/// Longest window accepted; every file in it is read
+const MAX_STATS_WINDOW_DAYS: i64 = 90;
+
+/// How long a computed result is served again
+const STATS_CACHE_TTL: Duration = Duration::from_secs(15);
+
+/// Group key of entries whose session didn't validate, when grouping by agent
+pub const UNAUTHENTICATED_GROUP: &str = "unauthenticated";
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StatsGroupBy {
+    #[default]
+    Service,
+    Agent,
+}
+
+/// `30m`, `24h`, `7d`, ... up to 90 days
+pub fn parse_window(window: &str) -> Result<chrono::Duration, GatewayError> {
+    let invalid = || {
+        GatewayError::BadRequest(format!(
+            "Invalid window '{}': expected a number of minutes, hours or days (30m, 24h, 7d) up to {}d",
+            window, MAX_STATS_WINDOW_DAYS
+        ))
+    };
+    let (split, _) = window.char_indices().last().ok_or_else(invalid)?;
+    let (amount, unit) = window.split_at(split);
+    let amount: i64 = amount.parse().map_err(|_| invalid())?;
+    let duration = match unit {
+        "m" => chrono::Duration::try_minutes(amount),
+        "h" => chrono::Duration::try_hours(amount),
+        "d" => chrono::Duration::try_days(amount),
+        _ => None,
+    }
+    .ok_or_else(invalid)?;
+    if amount <= 0 || duration > chrono::Duration::days(MAX_STATS_WINDOW_DAYS) {
+        return Err(invalid());
+    }
+    Ok(duration)
+}
+
+/// Failed requests by class; a 429 counts only as `429`, not as `4xx`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct ErrorCounts {
+    #[serde(rename = "4xx")]
+    pub client: u64,
+    #[serde(rename = "5xx")]
+    pub server: u64,
+    #[serde(rename = "429")]
+    pub rate_limited: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct PathCount {
+    pub path: String,
+    pub requests: u64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct GroupStats {
+    /// Service id, or agent id (`unauthenticated` for entries without one)
+    pub key: String,
+    /// Agent name, when grouping by agent and the agent still exists
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    pub requests: u64,
+    pub errors: ErrorCounts,
+    pub avg_latency_ms: Option<f64>,
+    pub p95_latency_ms: Option<u64>,
+    /// Busiest paths, most requests first
+    pub top_paths: Vec<PathCount>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct AuditStats {
+    pub from: DateTime<Utc>,
+    pub to: DateTime<Utc>,
+    pub group_by: StatsGroupBy,
+    pub requests: u64,
+    /// Most requests first
+    pub groups: Vec<GroupStats>,
+}
+
+/// Running totals of one group; latencies are kept as a count per millisecond
+/// value, so memory grows with distinct latencies rather than entries
+#[derive(Default)]
+struct Accumulator {
+    requests: u64,
+    errors: ErrorCounts,
+    latency_sum_ms: u64,
+    latencies: BTreeMap<u64, u64>,
+    paths: HashMap<String, u64>,
+}
+
+impl Accumulator {
+    fn observe(&mut self, entry: AuditLog) {
+        self.requests += 1;
+        match entry.status_code {
+            429 => self.errors.rate_limited += 1,
+            400..=499 => self.errors.client += 1,
+            500..=599 => self.errors.server += 1,
+            _ => {}
+        }
+        self.latency_sum_ms += entry.response_time_ms;
+        *self.latencies.entry(entry.response_time_ms).or_default() += 1;
+        *self.paths.entry(entry.endpoint).or_default() += 1;
+    }
+
+    /// Nearest-rank p95, like the live latency stats
+    fn p95(&self) -> Option<u64> {
+        let rank = (self.requests * 95).div_ceil(100).max(1);
+        let mut seen = 0;
+        self.latencies.iter().find_map(|(&latency, &count)| {
+            seen += count;
+            (seen >= rank).then_some(latency)
+        })
+    }
+
+    fn finish(self, key: String, top: usize) -> GroupStats {
+        let p95_latency_ms = self.p95();
+        let avg_latency_ms = (self.requests > 0).then(|| self.latency_sum_ms as f64 / self.requests as f64);
+        let mut top_paths: Vec<_> = self
+            .paths
+            .into_iter()
+            .map(|(path, requests)| PathCount { path, requests })
+            .collect();
+        top_paths.sort_by(|a, b| b.requests.cmp(&a.requests).then_with(|| a.path.cmp(&b.path)));
+        top_paths.truncate(top);
+        GroupStats {
+            key,
+            name: None,
+            requests: self.requests,
+            errors: self.errors,
+            avg_latency_ms,
+            p95_latency_ms,
+            top_paths,
+        }
+    }
+}
+
+/// Statistics over the entries between `from` and `to` (inclusive), with the
+/// `top` busiest paths of each group
+pub async fn compute_stats(
+    store: &dyn AuditStoreTrait,
+    from: DateTime<Utc>,
+    to: DateTime<Utc>,
+    group_by: StatsGroupBy,
+    top: usize,
+) -> Result<AuditStats, GatewayError> {
+    let filter = AuditFilter { from: Some(from), to: Some(to), ..Default::default() };
+    let mut groups: HashMap<String, Accumulator> = HashMap::new();
+    store
+        .scan(&filter, &mut |entry| {
+            let key = match group_by {
+                StatsGroupBy::Service => entry.service_id.clone(),
+                StatsGroupBy::Agent => entry
+                    .agent_id
+                    .map(|id| id.to_string())
+                    .unwrap_or_else(|| UNAUTHENTICATED_GROUP.to_string()),
+            };
+            groups.entry(key).or_default().observe(entry);
+        })
+        .await?;
+
+    let mut groups: Vec<_> = groups.into_iter().map(|(key, acc)| acc.finish(key, top)).collect();
+    groups.sort_by(|a, b| (Reverse(a.requests), &a.key).cmp(&(Reverse(b.requests), &b.key)));
+    Ok(AuditStats {
+        from,
+        to,
+        group_by,
+        requests: groups.iter().map(|group| group.requests).sum(),
+        groups,
+    })
+}
+
+/// Window length in seconds, grouping and top N
+type StatsKey = (i64, StatsGroupBy, usize);
+
+/// Recently computed statistics, served again for `STATS_CACHE_TTL`
+#[derive(Clone, Default)]
+pub struct StatsCache {
+    entries: Arc<DashMap<StatsKey, (AuditStats, Instant)>>,
+}
+
+impl StatsCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn get(&self, window: chrono::Duration, group_by: StatsGroupBy, top: usize) -> Option<AuditStats> {
+        let key = (window.num_seconds(), group_by, top);
+        let entry = self.entries.get(&key)?;
+        let (stats, computed_at) = entry.value();
+        (computed_at.elapsed() < STATS_CACHE_TTL).then(|| stats.clone())
+    }
+
+    pub fn insert(&self, window: chrono::Duration, group_by: StatsGroupBy, top: usize, stats: AuditStats) {
+        self.entries.insert((window.num_seconds(), group_by, top), (stats, Instant::now()));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::audit::AuditStore;
+    use chrono::TimeZone;
+    use tempfile::TempDir;
+    use uuid::Uuid;
+
+    #[test]
+    fn test_parse_window() {
+        assert_eq!(parse_window("30m").unwrap(), chrono::Duration::minutes(30));
+        assert_eq!(parse_window("24h").unwrap(), chrono::Duration::hours(24));
+        assert_eq!(parse_window("7d").unwrap(), chrono::Duration::days(7));
+        for invalid in ["", "h", "0h", "-1h", "24", "24x", "91d", "1.5h", "2é"] {
+            assert!(parse_window(invalid).is_err(), "{} should be rejected", invalid);
+        }
+    }
+
+    /// 20 entries on 2025-12-01 from 10:00, one a minute: `bank` gets
+    /// 200, 404, 429, 500 in turn with 10..=100 ms; `payment` five 200s
+    /// from agent 1; plus one entry the day before, outside the window
+    async fn seeded(dir: &TempDir) -> (AuditStore, Uuid, Uuid) {
+        let store = AuditStore::new(dir.path());
+        let (agent_a, agent_b) = (Uuid::new_v4(), Uuid::new_v4());
+        let start = Utc.with_ymd_and_hms(2025, 12, 1, 10, 0, 0).unwrap();
+        for i in 0..20u64 {
+            let (service, path, agent) = if i < 15 {
+                ("bank", ["accounts", "accounts", "transfers"][i as usize % 3], Some(agent_a))
+            } else {
+                ("payment", "charges", Some(agent_b))
+            };
+            let mut entry = AuditLog::new(service.to_string(), path.to_string(), "GET".to_string());
+            entry.agent_id = if i == 14 { None } else { agent };
+            entry.status_code = if i < 15 { [200, 404, 429, 500][i as usize % 4] } else { 200 };
+            entry.response_time_ms = (i + 1) * 10;
+            entry.timestamp = start + chrono::Duration::minutes(i as i64);
+            store.append(&entry).await.unwrap();
+        }
+        let mut old = AuditLog::new("bank".to_string(), "accounts".to_string(), "GET".to_string());
+        old.status_code = 500;
+        old.timestamp = start - chrono::Duration::days(1);
+        store.append(&old).await.unwrap();
+        (store, agent_a, agent_b)
+    }
+
+    #[tokio::test]
+    async fn test_stats_by_service() {
+        let dir = TempDir::new().unwrap();
+        let (store, _, _) = seeded(&dir).await;
+        let from = Utc.with_ymd_and_hms(2025, 12, 1, 0, 0, 0).unwrap();
+        let to = Utc.with_ymd_and_hms(2025, 12, 1, 23, 59, 59).unwrap();
+
+        let stats = compute_stats(&store, from, to, StatsGroupBy::Service, 2).await.unwrap();
+        assert_eq!(stats.requests, 20);
+        let keys: Vec<_> = stats.groups.iter().map(|g| g.key.as_str()).collect();
+        assert_eq!(keys, vec!["bank", "payment"]);
+
+        let bank = &stats.groups[0];
+        assert_eq!(bank.requests, 15);
+        // Statuses 200, 404, 429, 500 repeating over 15 entries
+        assert_eq!(bank.errors, ErrorCounts { client: 4, server: 3, rate_limited: 4 });
+        assert_eq!(bank.avg_latency_ms, Some(80.0));
+        assert_eq!(bank.p95_latency_ms, Some(150));
+        assert_eq!(
+            bank.top_paths,
+            vec![
+                PathCount { path: "accounts".to_string(), requests: 10 },
+                PathCount { path: "transfers".to_string(), requests: 5 },
+            ]
+        );
+
+        let payment = &stats.groups[1];
+        assert_eq!(payment.requests, 5);
+        assert_eq!(payment.errors, ErrorCounts::default());
+        assert_eq!(payment.avg_latency_ms, Some(180.0));
+        assert_eq!(payment.p95_latency_ms, Some(200));
+
+        // The first three minutes only
+        let narrow = compute_stats(&store, from, from + chrono::Duration::minutes(602), StatsGroupBy::Service, 5)
+            .await
+            .unwrap();
+        assert_eq!(narrow.requests, 3);
+        assert_eq!(narrow.groups[0].errors, ErrorCounts { client: 1, server: 0, rate_limited: 1 });
+    }
+
+    #[tokio::test]
+    async fn test_stats_by_agent() {
+        let dir = TempDir::new().unwrap();
+        let (store, agent_a, agent_b) = seeded(&dir).await;
+        let from = Utc.with_ymd_and_hms(2025, 12, 1, 0, 0, 0).unwrap();
+        let to = Utc.with_ymd_and_hms(2025, 12, 2, 0, 0, 0).unwrap();
+
+        let stats = compute_stats(&store, from, to, StatsGroupBy::Agent, 5).await.unwrap();
+        let groups: Vec<_> = stats.groups.iter().map(|g| (g.key.clone(), g.requests)).collect();
+        assert_eq!(
+            groups,
+            vec![
+                (agent_a.to_string(), 14),
+                (agent_b.to_string(), 5),
+                (UNAUTHENTICATED_GROUP.to_string(), 1),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_cache_is_keyed_by_query() {
+        let cache = StatsCache::new();
+        let stats = AuditStats {
+            from: Utc::now(),
+            to: Utc::now(),
+            group_by: StatsGroupBy::Service,
+            requests: 3,
+            groups: Vec::new(),
+        };
+        let day = chrono::Duration::hours(24);
+        cache.insert(day, StatsGroupBy::Service, 5, stats);
+        assert_eq!(cache.get(day, StatsGroupBy::Service, 5).unwrap().requests, 3);
+        assert!(cache.get(day, StatsGroupBy::Agent, 5).is_none());
+        assert!(cache.get(day, StatsGroupBy::Service, 10).is_none());
+        assert!(cache.get(chrono::Duration::hours(1), StatsGroupBy::Service, 5).is_none());
+    }
+}
@@ -0,0 +1,994 @@
+//! Append-only audit trail of proxied requests: one JSON object per line, one
+//! set of files per UTC day. A day starts in `<AUDIT_LOG_PATH>/audit-YYYY-MM-DD.0.jsonl`
+//! and rolls over to `.1.jsonl`, `.2.jsonl`, ... once a file reaches
+//! AUDIT_MAX_FILE_BYTES. Entries are never rewritten; the retention task gzips
+//! (`.jsonl.gz`) and later deletes whole files by their day. Queries
+//! (`GET /admin/audit`) only open the files of the days in their time range,
+//! compressed or not. The default backend (AUDIT_BACKEND=file); see
+//! `SqliteAuditStore` for the indexed one.
+
+use async_trait::async_trait;
+use chrono::{DateTime, NaiveDate, Utc};
+use futures_util::stream::{self, StreamExt};
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use std::cmp::Reverse;
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::AsyncWriteExt;
+use tokio::sync::{Mutex, RwLock};
+use uuid::Uuid;
+
+use super::chain::{
+    create_seed, genesis, last_link, read_boundaries, read_head, read_seed, record_boundary, write_head, AuditChain,
+    ChainHead, ChainVerifier,
+};
+use super::{AuditStoreTrait, ChainReport, ExportChunks, ExportFormat};
+use crate::error::GatewayError;
+use crate::models::{AuditLog, Decision};
+use crate::storage::{with_io_timeout, Page, DEFAULT_FILE_IO_TIMEOUT};
+
+const FILE_PREFIX: &str = "audit-";
+const FILE_SUFFIX: &str = ".jsonl";
+const GZIP_SUFFIX: &str = ".gz";
+
+/// Default AUDIT_MAX_FILE_BYTES: 100 MiB
+pub const DEFAULT_AUDIT_MAX_FILE_BYTES: u64 = 100 * 1024 * 1024;
+
+/// Entries a query returns; unset fields match everything. Bounds are inclusive.
+#[derive(Debug, Clone, Default)]
+pub struct AuditFilter {
+    pub agent_id: Option<Uuid>,
+    pub service_id: Option<String>,
+    pub from: Option<DateTime<Utc>>,
+    pub to: Option<DateTime<Utc>>,
+    pub status_min: Option<u16>,
+    pub status_max: Option<u16>,
+    pub decision: Option<Decision>,
+}
+
+impl AuditFilter {
+    pub fn matches(&self, entry: &AuditLog) -> bool {
+        self.agent_id.is_none_or(|id| entry.agent_id == Some(id))
+            && self.service_id.as_deref().is_none_or(|id| entry.service_id == id)
+            && self.from.is_none_or(|from| entry.timestamp >= from)
+            && self.to.is_none_or(|to| entry.timestamp <= to)
+            && self.status_min.is_none_or(|min| entry.status_code >= min)
+            && self.status_max.is_none_or(|max| entry.status_code <= max)
+            && self.decision.is_none_or(|decision| entry.decision == decision)
+    }
+
+    /// Whether `date`'s file can hold matching entries
+    fn covers(&self, date: NaiveDate) -> bool {
+        self.from.is_none_or(|from| date >= from.date_naive())
+            && self.to.is_none_or(|to| date <= to.date_naive())
+    }
+}
+
+/// AUDIT_RETENTION_DAYS / AUDIT_COMPRESS_AFTER_DAYS; `None` keeps files as they are
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RetentionPolicy {
+    /// Delete files whose day is more than this many days ago
+    pub retention_days: Option<u32>,
+    /// Gzip files whose day is more than this many days ago (at least one, so
+    /// yesterday's file can still take entries that straddled midnight)
+    pub compress_after_days: Option<u32>,
+}
+
+impl RetentionPolicy {
+    pub fn is_enabled(&self) -> bool {
+        self.retention_days.is_some() || self.compress_after_days.is_some()
+    }
+}
+
+/// What one retention pass did
+#[derive(Debug, Default)]
+pub struct RetentionSummary {
+    pub deleted: Vec<PathBuf>,
+    pub compressed: Vec<PathBuf>,
+    /// Rows removed by a database backend, which has no files to list
+    pub deleted_entries: usize,
+}
+
+/// One audit file, parsed from its name
+#[derive(Debug)]
+struct AuditFile {
+    path: PathBuf,
+    date: NaiveDate,
+    /// Intra-day segment; `None` for files written before rotation (`audit-YYYY-MM-DD.jsonl`)
+    index: Option<u32>,
+    compressed: bool,
+}
+
+impl AuditFile {
+    fn parse(path: PathBuf) -> Option<Self> {
+        let name = path.file_name()?.to_str()?;
+        let rest = name.strip_prefix(FILE_PREFIX)?;
+        let (rest, compressed) = match rest.strip_suffix(GZIP_SUFFIX) {
+            Some(rest) => (rest, true),
+            None => (rest, false),
+        };
+        let rest = rest.strip_suffix(FILE_SUFFIX)?;
+        let (date, index) = match rest.split_once('.') {
+            Some((date, index)) => (date, Some(index.parse().ok()?)),
+            None => (rest, None),
+        };
+        let date = NaiveDate::parse_from_str(date, "%Y-%m-%d").ok()?;
+        Some(Self { path, date, index, compressed })
+    }
+}
+
+/// The file appends currently go to
+struct Segment {
+    date: NaiveDate,
+    index: u32,
+    size: u64,
+}
+
+#[derive(Default)]
+struct Writer {
+    segment: Option<Segment>,
+    // Last chained entry; found on disk by the first append after a restart
+    head: Option<ChainHead>,
+}
+
+pub struct AuditStore {
+    dir: PathBuf,
+    io_timeout: Duration,
+    max_file_bytes: u64,
+    chain: Option<AuditChain>,
+    // One line per write; the lock keeps concurrent appends from interleaving
+    // and chain links in write order
+    writer: Mutex<Writer>,
+    // Queries read while no retention pass is swapping a file for its archive
+    archive_lock: Arc<RwLock<()>>,
+}
+
+impl AuditStore {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self {
+            dir: dir.into(),
+            io_timeout: DEFAULT_FILE_IO_TIMEOUT,
+            max_file_bytes: DEFAULT_AUDIT_MAX_FILE_BYTES,
+            chain: None,
+            writer: Mutex::new(Writer::default()),
+            archive_lock: Arc::new(RwLock::new(())),
+        }
+    }
+
+    /// Deadline for each append and file read (FILE_IO_TIMEOUT_SECS)
+    pub fn with_io_timeout(mut self, timeout: Duration) -> Self {
+        self.io_timeout = timeout;
+        self
+    }
+
+    /// Start a new file for the day once the current one would grow past this (0: never)
+    pub fn with_max_file_bytes(mut self, max_file_bytes: u64) -> Self {
+        self.max_file_bytes = max_file_bytes;
+        self
+    }
+
+    /// Chain every entry with HMAC-SHA256 under `key` (AUDIT_HMAC_KEY)
+    pub fn with_hmac_key(mut self, key: &str) -> Self {
+        self.chain = Some(AuditChain::new(key));
+        self
+    }
+
+    /// Segment `index` of `date`'s entries
+    pub fn segment_path(&self, date: NaiveDate, index: u32) -> PathBuf {
+        self.dir.join(format!(
+            "{}{}.{}{}",
+            FILE_PREFIX,
+            date.format("%Y-%m-%d"),
+            index,
+            FILE_SUFFIX
+        ))
+    }
+
+    async fn read(&self, file: &AuditFile) -> Result<String, GatewayError> {
+        let path = &file.path;
+        let read_error = |e: std::io::Error| {
+            GatewayError::Internal(format!("Failed to read audit log {}: {}", path.display(), e))
+        };
+        let bytes = with_io_timeout(self.io_timeout, path, async {
+            tokio::fs::read(path).await.map_err(read_error)
+        })
+        .await?;
+        if !file.compressed {
+            return String::from_utf8(bytes).map_err(|e| {
+                GatewayError::Internal(format!("Failed to read audit log {}: {}", path.display(), e))
+            });
+        }
+        let content = tokio::task::spawn_blocking(move || {
+            let mut content = String::new();
+            GzDecoder::new(bytes.as_slice()).read_to_string(&mut content).map(|_| content)
+        })
+        .await
+        .map_err(|e| GatewayError::Internal(format!("Audit log decompression task failed: {}", e)))?;
+        content.map_err(read_error)
+    }
+
+    /// Audit files on disk; none before the first append
+    async fn files(&self) -> Result<Vec<AuditFile>, GatewayError> {
+        let mut entries = match tokio::fs::read_dir(&self.dir).await {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => {
+                return Err(GatewayError::Internal(format!(
+                    "Failed to list audit logs in {}: {}",
+                    self.dir.display(),
+                    e
+                )))
+            }
+        };
+        let mut files = Vec::new();
+        while let Ok(Some(entry)) = entries.next_entry().await {
+            files.extend(AuditFile::parse(entry.path()));
+        }
+        Ok(files)
+    }
+
+    /// Append `entries`, in order, each to the file of the day it happened and
+    /// chained to the previous entry when an HMAC key is set; the lines bound
+    /// for one file go out in one write
+    pub async fn append_batch(&self, entries: &[AuditLog]) -> Result<(), GatewayError> {
+        let mut writer = self.writer.lock().await;
+        // Taken, so a failure part way makes the next append look at the directory again
+        let mut head = match (&self.chain, writer.head.take()) {
+            (Some(_), None) => Some(self.chain_head().await?),
+            (_, head) => head,
+        };
+        let mut segments: HashMap<NaiveDate, Segment> = writer
+            .segment
+            .take()
+            .map(|segment| (segment.date, segment))
+            .into_iter()
+            .collect();
+        let mut writes: Vec<(PathBuf, Vec<u8>)> = Vec::new();
+        let mut last_date = None;
+        for entry in entries {
+            let (mut line, next) = match (&self.chain, &head) {
+                (Some(chain), Some(prev)) => {
+                    let (line, next) = chain.seal(entry, prev)?;
+                    (line.into_bytes(), Some(next))
+                }
+                _ => {
+                    let line = serde_json::to_vec(entry)
+                        .map_err(|e| GatewayError::Internal(format!("Failed to serialize audit entry: {}", e)))?;
+                    (line, None)
+                }
+            };
+            line.push(b'\n');
+            let date = entry.timestamp.date_naive();
+            let segment = match segments.entry(date) {
+                Entry::Occupied(entry) => entry.into_mut(),
+                Entry::Vacant(entry) => entry.insert(self.last_segment(date).await?),
+            };
+            let len = line.len() as u64;
+            if self.max_file_bytes > 0 && segment.size > 0 && segment.size + len > self.max_file_bytes {
+                segment.index += 1;
+                segment.size = 0;
+            }
+            let path = self.segment_path(date, segment.index);
+            if let (Some(prev), 0) = (&head, segment.size) {
+                // A new file: note where in the chain it starts, for ranged verification
+                let name = path.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default();
+                with_io_timeout(self.io_timeout, &path, record_boundary(&self.dir, &name, prev)).await?;
+            }
+            segment.size += len;
+            match writes.last_mut() {
+                Some((last, lines)) if *last == path => lines.extend_from_slice(&line),
+                _ => writes.push((path, line)),
+            }
+            if next.is_some() {
+                head = next;
+            }
+            last_date = Some(date);
+        }
+
+        for (path, lines) in &writes {
+            with_io_timeout(self.io_timeout, path, append_line(&self.dir, path, lines)).await?;
+        }
+        if let (Some(head), Some((path, _))) = (&head, writes.last()) {
+            with_io_timeout(self.io_timeout, path, write_head(&self.dir, head)).await?;
+        }
+        writer.segment = last_date.and_then(|date| segments.remove(&date));
+        writer.head = head;
+        Ok(())
+    }
+
+    /// The newest chained entry, or the seed when there is none yet: the later of
+    /// `chain.head` and the newest entry on disk (the gateway may have stopped
+    /// between the two writes). Besides the newest day holding an entry, the day
+    /// before is read too: a request that started before midnight is written to
+    /// that day's file.
+    async fn chain_head(&self) -> Result<ChainHead, GatewayError> {
+        let mut files = self.files().await?;
+        files.sort_by_key(|file| Reverse((file.date, file.index)));
+        let mut head = read_head(&self.dir).await?;
+        let mut oldest_day = None;
+        for file in files {
+            if oldest_day.is_some_and(|day| file.date < day) {
+                break;
+            }
+            if let Some(link) = last_link(&self.read(&file).await?) {
+                if head.as_ref().is_none_or(|head| link.seq > head.seq) {
+                    head = Some(link);
+                }
+                oldest_day.get_or_insert(file.date - chrono::Duration::days(1));
+            }
+        }
+        if let Some(head) = head {
+            return Ok(head);
+        }
+        let seed = match read_seed(&self.dir).await? {
+            Some(seed) => seed,
+            None => create_seed(&self.dir).await?,
+        };
+        Ok(genesis(seed))
+    }
+
+    /// Where `date`'s entries continue after a restart: its highest segment, or
+    /// the one after it if that was already archived
+    async fn last_segment(&self, date: NaiveDate) -> Result<Segment, GatewayError> {
+        let last = self
+            .files()
+            .await?
+            .into_iter()
+            .filter(|file| file.date == date)
+            .filter_map(|file| Some((file.index?, file.compressed, file.path)))
+            .max_by_key(|(index, _, _)| *index);
+        Ok(match last {
+            None => Segment { date, index: 0, size: 0 },
+            Some((index, true, _)) => Segment { date, index: index + 1, size: 0 },
+            Some((index, false, path)) => Segment {
+                date,
+                index,
+                size: tokio::fs::metadata(&path).await.map(|m| m.len()).unwrap_or(0),
+            },
+        })
+    }
+
+}
+
+#[async_trait]
+impl AuditStoreTrait for AuditStore {
+    /// Matching entries newest first, `limit` of them from `offset`, with the total
+    /// number of matches. A line that doesn't parse (one cut short by a crash) is skipped.
+    async fn query(
+        &self,
+        filter: &AuditFilter,
+        offset: usize,
+        limit: usize,
+    ) -> Result<Page<AuditLog>, GatewayError> {
+        let _guard = self.archive_lock.read().await;
+        let mut matching = Vec::new();
+        for file in self.files().await? {
+            if !filter.covers(file.date) {
+                continue;
+            }
+            let content = self.read(&file).await?;
+            matching.extend(
+                content
+                    .lines()
+                    .filter_map(|line| serde_json::from_str::<AuditLog>(line).ok())
+                    .filter(|entry| filter.matches(entry)),
+            );
+        }
+
+        matching.sort_by_key(|entry| Reverse((entry.timestamp, entry.id)));
+        Ok(Page {
+            total: matching.len(),
+            items: matching.into_iter().skip(offset).take(limit).collect(),
+        })
+    }
+
+    /// Hand every matching entry to `visit`, oldest day first, without collecting
+    /// them: only one file is held in memory at a time. For aggregations over
+    /// ranges too large to `query`.
+    async fn scan(
+        &self,
+        filter: &AuditFilter,
+        visit: &mut (dyn FnMut(AuditLog) + Send),
+    ) -> Result<(), GatewayError> {
+        let _guard = self.archive_lock.read().await;
+        let mut files: Vec<_> = self
+            .files()
+            .await?
+            .into_iter()
+            .filter(|file| filter.covers(file.date))
+            .collect();
+        files.sort_by_key(|file| (file.date, file.index));
+        for file in files {
+            let content = self.read(&file).await?;
+            content
+                .lines()
+                .filter_map(|line| serde_json::from_str::<AuditLog>(line).ok())
+                .filter(|entry| filter.matches(entry))
+                .for_each(&mut *visit);
+        }
+        Ok(())
+    }
+
+    /// Matching entries rendered as `format`, oldest day first and in the order
+    /// they were written, as a stream of chunks: the header, then one chunk per
+    /// file with matches. Only one file is held in memory at a time, and
+    /// retention waits until the stream is done or dropped.
+    async fn export(self: Arc<Self>, filter: AuditFilter, format: ExportFormat) -> Result<ExportChunks, GatewayError> {
+        let guard = self.archive_lock.clone().read_owned().await;
+        let mut files: Vec<_> = self
+            .files()
+            .await?
+            .into_iter()
+            .filter(|file| filter.covers(file.date))
+            .collect();
+        files.sort_by_key(|file| (file.date, file.index));
+
+        let header = Some(format.header()).filter(|header| !header.is_empty());
+        let chunks = stream::unfold(
+            (self, files.into_iter(), header, guard),
+            move |(store, mut files, header, guard)| {
+                let filter = filter.clone();
+                async move {
+                    if let Some(header) = header {
+                        return Some((Ok(header), (store, files, None, guard)));
+                    }
+                    for file in files.by_ref() {
+                        let content = match store.read(&file).await {
+                            Ok(content) => content,
+                            Err(e) => {
+                                tracing::error!(file = %file.path.display(), error = ?e, "Audit export failed");
+                                let error = std::io::Error::other(format!("{:?}", e));
+                                return Some((Err(error), (store, files, None, guard)));
+                            }
+                        };
+                        let chunk: String = content
+                            .lines()
+                            .filter_map(|line| serde_json::from_str::<AuditLog>(line).ok())
+                            .filter(|entry| filter.matches(entry))
+                            .map(|entry| format.row(&entry))
+                            .collect();
+                        if !chunk.is_empty() {
+                            return Some((Ok(chunk), (store, files, None, guard)));
+                        }
+                    }
+                    None
+                }
+            },
+        );
+        Ok(chunks.boxed())
+    }
+
+    /// Replay the HMAC chain over the files of the days between `from` and `to`
+    /// (every file when both are unset). The range starts from the link its
+    /// first file was created after; the files just outside it are read too,
+    /// for entries written across midnight.
+    async fn verify_chain(
+        &self,
+        from: Option<DateTime<Utc>>,
+        to: Option<DateTime<Utc>>,
+    ) -> Result<ChainReport, GatewayError> {
+        let chain = self.chain.as_ref().ok_or_else(|| {
+            GatewayError::BadRequest("Audit chaining is disabled (AUDIT_HMAC_KEY is not set)".to_string())
+        })?;
+        let _guard = self.archive_lock.read().await;
+        let range = AuditFilter { from, to, ..Default::default() };
+        let mut files = self.files().await?;
+        files.sort_by_key(|file| (file.date, file.index));
+        let first = files.iter().position(|file| range.covers(file.date));
+        let last = files.iter().rposition(|file| range.covers(file.date));
+        let (Some(first), Some(last)) = (first, last) else {
+            return Ok(ChainVerifier::new(chain, None).finish(None));
+        };
+
+        let name = files[first].path.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default();
+        let anchor = read_boundaries(&self.dir).await?.remove(name.trim_end_matches(GZIP_SUFFIX));
+        let (mut verifier, start) = match anchor {
+            Some(anchor) => (ChainVerifier::new(chain, None).starting_after(anchor), first.saturating_sub(1)),
+            // Files from before boundaries were recorded: replay from the oldest
+            None => (ChainVerifier::new(chain, read_seed(&self.dir).await?), 0),
+        };
+        let end = (last + 1).min(files.len() - 1);
+        for (i, file) in files.iter().enumerate().take(end + 1).skip(start) {
+            let in_range = (first..=last).contains(&i);
+            verifier.feed(&file.path, &self.read(file).await?, !in_range);
+        }
+        // Entries removed from the end only show against the stored head
+        let head = match to {
+            None => read_head(&self.dir).await?,
+            Some(_) => None,
+        };
+        Ok(verifier.finish(head.as_ref()))
+    }
+
+    /// Delete and gzip files older than `policy` allows, counting days back from `today`
+    async fn apply_retention(
+        &self,
+        policy: &RetentionPolicy,
+        today: NaiveDate,
+    ) -> Result<RetentionSummary, GatewayError> {
+        let cutoff = |days: u32| today - chrono::Duration::days(i64::from(days));
+        let delete_before = policy.retention_days.map(cutoff);
+        let compress_before = policy.compress_after_days.map(|days| cutoff(days.max(1)));
+
+        let _guard = self.archive_lock.write().await;
+        let mut summary = RetentionSummary::default();
+        for file in self.files().await? {
+            if delete_before.is_some_and(|before| file.date < before) {
+                tokio::fs::remove_file(&file.path).await.map_err(|e| {
+                    GatewayError::Internal(format!("Failed to delete audit log {}: {}", file.path.display(), e))
+                })?;
+                summary.deleted.push(file.path);
+            } else if !file.compressed && compress_before.is_some_and(|before| file.date < before) {
+                let path = file.path.clone();
+                tokio::task::spawn_blocking(move || compress(&path))
+                    .await
+                    .map_err(|e| GatewayError::Internal(format!("Audit log compression task failed: {}", e)))?
+                    .map_err(|e| {
+                        GatewayError::Internal(format!("Failed to compress audit log {}: {}", file.path.display(), e))
+                    })?;
+                summary.compressed.push(file.path);
+            }
+        }
+        Ok(summary)
+    }
+}
+
+/// Replace `path` with `path.gz`. The archive is written under a temporary name
+/// and renamed into place before the original goes, so a crash leaves one or the other.
+fn compress(path: &Path) -> std::io::Result<()> {
+    let archive = PathBuf::from(format!("{}{}", path.display(), GZIP_SUFFIX));
+    let partial = PathBuf::from(format!("{}.tmp", archive.display()));
+    let mut encoder = GzEncoder::new(std::fs::File::create(&partial)?, Compression::default());
+    std::io::copy(&mut std::fs::File::open(path)?, &mut encoder)?;
+    encoder.finish()?.sync_all()?;
+    std::fs::rename(&partial, &archive)?;
+    std::fs::remove_file(path)
+}
+
+/// Every entry in `dir`'s audit files, a day at a time and oldest first, read
+/// without the async runtime: the one-shot import into AUDIT_BACKEND=sqlite.
+/// Lines that don't parse are skipped, as by queries.
+pub(super) fn read_days_blocking(
+    dir: &Path,
+    mut visit: impl FnMut(NaiveDate, Vec<AuditLog>) -> Result<(), GatewayError>,
+) -> Result<(), GatewayError> {
+    let list_error =
+        |e: std::io::Error| GatewayError::Internal(format!("Failed to list audit logs in {}: {}", dir.display(), e));
+    let entries = match std::fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err(list_error(e)),
+    };
+    let mut files = Vec::new();
+    for entry in entries {
+        files.extend(AuditFile::parse(entry.map_err(list_error)?.path()));
+    }
+    files.sort_by_key(|file| (file.date, file.index));
+
+    for day in files.chunk_by(|a, b| a.date == b.date) {
+        let mut entries = Vec::new();
+        for file in day {
+            let content = read_blocking(file).map_err(|e| {
+                GatewayError::Internal(format!("Failed to read audit log {}: {}", file.path.display(), e))
+            })?;
+            entries.extend(content.lines().filter_map(|line| serde_json::from_str::<AuditLog>(line).ok()));
+        }
+        visit(day[0].date, entries)?;
+    }
+    Ok(())
+}
+
+fn read_blocking(file: &AuditFile) -> std::io::Result<String> {
+    let mut content = String::new();
+    if file.compressed {
+        GzDecoder::new(std::fs::File::open(&file.path)?).read_to_string(&mut content)?;
+    } else {
+        std::fs::File::open(&file.path)?.read_to_string(&mut content)?;
+    }
+    Ok(content)
+}
+
+async fn append_line(dir: &Path, path: &Path, line: &[u8]) -> Result<(), GatewayError> {
+    let io_error = |e: std::io::Error| {
+        GatewayError::Internal(format!("Failed to write audit log {}: {}", path.display(), e))
+    };
+    tokio::fs::create_dir_all(dir).await.map_err(io_error)?;
+    let mut file = tokio::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .await
+        .map_err(io_error)?;
+    file.write_all(line).await.map_err(io_error)?;
+    file.flush().await.map_err(io_error)
+}
+
+/// Apply `policy` once at startup and then daily, logging every file removed or archived
+pub fn spawn_audit_retention(store: Arc<dyn AuditStoreTrait>, policy: RetentionPolicy) {
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(Duration::from_secs(24 * 60 * 60));
+        loop {
+            ticker.tick().await;
+            match store.apply_retention(&policy, Utc::now().date_naive()).await {
+                Ok(summary) => {
+                    for path in &summary.deleted {
+                        tracing::info!(file = %path.display(), "Deleted expired audit log");
+                    }
+                    for path in &summary.compressed {
+                        tracing::info!(file = %path.display(), "Compressed audit log");
+                    }
+                    if !summary.deleted.is_empty() || !summary.compressed.is_empty() || summary.deleted_entries > 0 {
+                        tracing::info!(
+                            deleted = summary.deleted.len(),
+                            compressed = summary.compressed.len(),
+                            deleted_entries = summary.deleted_entries,
+                            "Audit log retention applied"
+                        );
+                    }
+                }
+                Err(e) => tracing::error!(error = ?e, "Audit log retention failed"),
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{TimeZone, Utc};
+    use std::sync::Arc;
+    use tempfile::TempDir;
+
+    fn entry(endpoint: &str) -> AuditLog {
+        AuditLog::new("svc".to_string(), endpoint.to_string(), "GET".to_string())
+    }
+
+    fn read_lines(path: &Path) -> Vec<AuditLog> {
+        std::fs::read_to_string(path)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_entries_go_to_the_file_of_their_day() {
+        let dir = TempDir::new().unwrap();
+        let store = AuditStore::new(dir.path().join("audit"));
+
+        let mut yesterday = entry("old");
+        yesterday.timestamp = Utc.with_ymd_and_hms(2025, 11, 30, 23, 59, 59).unwrap();
+        let mut today = entry("new");
+        today.timestamp = Utc.with_ymd_and_hms(2025, 12, 1, 0, 0, 0).unwrap();
+        store.append(&yesterday).await.unwrap();
+        store.append(&today).await.unwrap();
+        store.append(&today).await.unwrap();
+
+        let first = read_lines(&dir.path().join("audit/audit-2025-11-30.0.jsonl"));
+        assert_eq!(first.len(), 1);
+        assert_eq!(first[0].endpoint, "old");
+        assert_eq!(read_lines(&store.segment_path(today.timestamp.date_naive(), 0)).len(), 2);
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_concurrent_appends_keep_whole_lines() {
+        let dir = TempDir::new().unwrap();
+        let store = Arc::new(AuditStore::new(dir.path()));
+        let writers: Vec<_> = (0..50)
+            .map(|i| {
+                let store = store.clone();
+                tokio::spawn(async move { store.append(&entry(&format!("/{}", i))).await.unwrap() })
+            })
+            .collect();
+        for writer in writers {
+            writer.await.unwrap();
+        }
+
+        let entries = read_lines(&store.segment_path(Utc::now().date_naive(), 0));
+        assert_eq!(entries.len(), 50);
+    }
+
+    /// Two days of entries alternating between two agents and services, one
+    /// every hour, with statuses 200, 404, 429, 500 in turn
+    async fn seeded(dir: &TempDir) -> (AuditStore, [Uuid; 2]) {
+        let store = AuditStore::new(dir.path());
+        let agents = [Uuid::new_v4(), Uuid::new_v4()];
+        let start = Utc.with_ymd_and_hms(2025, 12, 1, 0, 0, 0).unwrap();
+        for hour in 0..48 {
+            let mut entry = entry(&format!("/{}", hour));
+            entry.agent_id = Some(agents[hour % 2]);
+            entry.service_id = ["bank", "payment"][hour % 2].to_string();
+            entry.status_code = [200, 404, 429, 500][hour % 4];
+            entry.timestamp = start + chrono::Duration::hours(hour as i64);
+            store.append(&entry).await.unwrap();
+        }
+        (store, agents)
+    }
+
+    #[tokio::test]
+    async fn test_query_filters() {
+        let dir = TempDir::new().unwrap();
+        let (store, agents) = seeded(&dir).await;
+        let query = |filter: AuditFilter| {
+            let store = &store;
+            async move { store.query(&filter, 0, 100).await.unwrap() }
+        };
+
+        let all = query(AuditFilter::default()).await;
+        assert_eq!(all.total, 48);
+        assert_eq!(all.items[0].endpoint, "/47");
+        assert!(all.items.windows(2).all(|w| w[0].timestamp >= w[1].timestamp));
+
+        let by_agent = query(AuditFilter { agent_id: Some(agents[1]), ..Default::default() }).await;
+        assert_eq!(by_agent.total, 24);
+        assert!(by_agent.items.iter().all(|e| e.agent_id == Some(agents[1])));
+
+        let by_service = query(AuditFilter { service_id: Some("bank".to_string()), ..Default::default() }).await;
+        assert_eq!(by_service.total, 24);
+        assert!(by_service.items.iter().all(|e| e.service_id == "bank"));
+
+        // 10:00 through 13:00 on the first day, both ends included
+        let range = query(AuditFilter {
+            from: Some(Utc.with_ymd_and_hms(2025, 12, 1, 10, 0, 0).unwrap()),
+            to: Some(Utc.with_ymd_and_hms(2025, 12, 1, 13, 0, 0).unwrap()),
+            ..Default::default()
+        })
+        .await;
+        let endpoints: Vec<_> = range.items.iter().map(|e| e.endpoint.as_str()).collect();
+        assert_eq!(endpoints, vec!["/13", "/12", "/11", "/10"]);
+
+        let errors = query(AuditFilter { status_min: Some(400), status_max: Some(499), ..Default::default() }).await;
+        assert_eq!(errors.total, 24);
+        assert!(errors.items.iter().all(|e| e.status_code == 404 || e.status_code == 429));
+
+        let combined = query(AuditFilter {
+            agent_id: Some(agents[0]),
+            status_min: Some(500),
+            ..Default::default()
+        })
+        .await;
+        assert_eq!(combined.total, 0); // agent 0 only ever got 200 and 429
+    }
+
+    #[tokio::test]
+    async fn test_query_pages_and_reads_only_days_in_range() {
+        let dir = TempDir::new().unwrap();
+        let (store, _) = seeded(&dir).await;
+
+        let page = store.query(&AuditFilter::default(), 45, 10).await.unwrap();
+        assert_eq!(page.total, 48);
+        let endpoints: Vec<_> = page.items.iter().map(|e| e.endpoint.as_str()).collect();
+        assert_eq!(endpoints, vec!["/2", "/1", "/0"]);
+
+        // An unreadable file outside the range is never opened
+        std::fs::write(dir.path().join("audit-2025-12-01.0.jsonl"), [0xff, 0xfe]).unwrap();
+        let second_day = AuditFilter {
+            from: Some(Utc.with_ymd_and_hms(2025, 12, 2, 0, 0, 0).unwrap()),
+            ..Default::default()
+        };
+        assert_eq!(store.query(&second_day, 0, 100).await.unwrap().total, 24);
+        assert!(store.query(&AuditFilter::default(), 0, 100).await.is_err());
+
+        let empty = AuditStore::new(dir.path().join("missing"));
+        assert_eq!(empty.query(&AuditFilter::default(), 0, 10).await.unwrap().total, 0);
+    }
+
+    #[tokio::test]
+    async fn test_files_roll_over_at_the_size_cap() {
+        let dir = TempDir::new().unwrap();
+        let mut fixed = entry("/rotated");
+        fixed.timestamp = Utc.with_ymd_and_hms(2025, 12, 1, 12, 0, 0).unwrap();
+        let line_len = serde_json::to_vec(&fixed).unwrap().len() as u64 + 1;
+        // Two lines per file
+        let store = AuditStore::new(dir.path()).with_max_file_bytes(2 * line_len);
+        let date = fixed.timestamp.date_naive();
+        async fn append_copy(store: &AuditStore, fixed: &AuditLog) {
+            let mut next = fixed.clone();
+            next.id = Uuid::new_v4();
+            store.append(&next).await.unwrap();
+        }
+
+        for _ in 0..5 {
+            append_copy(&store, &fixed).await;
+        }
+        let counts = |n: u32| (0..n).map(|i| read_lines(&store.segment_path(date, i)).len()).collect::<Vec<_>>();
+        assert_eq!(counts(3), vec![2, 2, 1]);
+        assert!(!store.segment_path(date, 3).exists());
+
+        // A restarted store fills up the last file before starting another
+        let store = AuditStore::new(dir.path()).with_max_file_bytes(2 * line_len);
+        append_copy(&store, &fixed).await;
+        append_copy(&store, &fixed).await;
+        assert_eq!(counts(4), vec![2, 2, 2, 1]);
+        assert_eq!(store.query(&AuditFilter::default(), 0, 100).await.unwrap().total, 7);
+
+        // An entry bigger than the cap still gets written, alone in its file
+        let tiny = AuditStore::new(dir.path().join("tiny")).with_max_file_bytes(10);
+        append_copy(&tiny, &fixed).await;
+        append_copy(&tiny, &fixed).await;
+        assert_eq!(read_lines(&tiny.segment_path(date, 0)).len(), 1);
+        assert_eq!(read_lines(&tiny.segment_path(date, 1)).len(), 1);
+    }
+
+    fn write_day(dir: &Path, name: &str, endpoint: &str) {
+        let line = serde_json::to_string(&entry(endpoint)).unwrap();
+        std::fs::write(dir.join(name), format!("{}\n", line)).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_retention_deletes_and_compresses_old_files() {
+        let dir = TempDir::new().unwrap();
+        write_day(dir.path(), "audit-2025-10-01.jsonl", "/legacy");
+        write_day(dir.path(), "audit-2025-10-02.0.jsonl", "/expired");
+        write_day(dir.path(), "audit-2025-11-20.0.jsonl", "/old");
+        write_day(dir.path(), "audit-2025-11-20.1.jsonl", "/old");
+        write_day(dir.path(), "audit-2025-11-30.0.jsonl", "/recent");
+        write_day(dir.path(), "audit-2025-12-03.0.jsonl", "/today");
+        std::fs::write(dir.path().join("notes.txt"), "not an audit file").unwrap();
+        let store = AuditStore::new(dir.path());
+        let policy = RetentionPolicy { retention_days: Some(30), compress_after_days: Some(7) };
+        let today = NaiveDate::from_ymd_opt(2025, 12, 3).unwrap();
+
+        let summary = store.apply_retention(&policy, today).await.unwrap();
+        assert_eq!(summary.deleted.len(), 2);
+        assert_eq!(summary.compressed.len(), 2);
+        let mut names: Vec<_> = std::fs::read_dir(dir.path())
+            .unwrap()
+            .map(|e| e.unwrap().file_name().into_string().unwrap())
+            .collect();
+        names.sort();
+        assert_eq!(
+            names,
+            vec![
+                "audit-2025-11-20.0.jsonl.gz",
+                "audit-2025-11-20.1.jsonl.gz",
+                "audit-2025-11-30.0.jsonl",
+                "audit-2025-12-03.0.jsonl",
+                "notes.txt",
+            ]
+        );
+
+        let mut archived = String::new();
+        GzDecoder::new(std::fs::File::open(dir.path().join("audit-2025-11-20.0.jsonl.gz")).unwrap())
+            .read_to_string(&mut archived)
+            .unwrap();
+        assert_eq!(serde_json::from_str::<AuditLog>(archived.trim()).unwrap().endpoint, "/old");
+
+        // Already archived files are left alone on the next pass
+        let again = store.apply_retention(&policy, today).await.unwrap();
+        assert!(again.deleted.is_empty() && again.compressed.is_empty());
+
+        // A day whose last file is archived continues in a new one
+        let mut late = entry("/late");
+        late.timestamp = Utc.with_ymd_and_hms(2025, 11, 20, 23, 0, 0).unwrap();
+        store.append(&late).await.unwrap();
+        assert_eq!(read_lines(&dir.path().join("audit-2025-11-20.2.jsonl"))[0].endpoint, "/late");
+    }
+
+    #[tokio::test]
+    async fn test_query_reads_across_the_compressed_boundary() {
+        let dir = TempDir::new().unwrap();
+        let (store, _) = seeded(&dir).await;
+        let policy = RetentionPolicy { retention_days: None, compress_after_days: Some(1) };
+        let summary = store
+            .apply_retention(&policy, NaiveDate::from_ymd_opt(2025, 12, 3).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(summary.compressed, vec![dir.path().join("audit-2025-12-01.0.jsonl")]);
+
+        let all = store.query(&AuditFilter::default(), 0, 100).await.unwrap();
+        assert_eq!(all.total, 48);
+
+        // 20:00 on the compressed day through 03:00 on the plain one
+        let range = store
+            .query(
+                &AuditFilter {
+                    from: Some(Utc.with_ymd_and_hms(2025, 12, 1, 20, 0, 0).unwrap()),
+                    to: Some(Utc.with_ymd_and_hms(2025, 12, 2, 3, 0, 0).unwrap()),
+                    ..Default::default()
+                },
+                0,
+                100,
+            )
+            .await
+            .unwrap();
+        let endpoints: Vec<_> = range.items.iter().map(|e| e.endpoint.as_str()).collect();
+        assert_eq!(endpoints, vec!["/27", "/26", "/25", "/24", "/23", "/22", "/21", "/20"]);
+    }
+
+    #[tokio::test]
+    async fn test_chain_survives_restarts_and_exposes_edits() {
+        let dir = TempDir::new().unwrap();
+        let day = |d: u32, h: u32| Utc.with_ymd_and_hms(2025, 12, d, h, 0, 0).unwrap();
+        let store = AuditStore::new(dir.path()).with_hmac_key("audit-key");
+        for (i, timestamp) in [day(1, 10), day(2, 9), day(2, 10)].into_iter().enumerate() {
+            let mut entry = entry(&format!("/{}", i));
+            entry.timestamp = timestamp;
+            store.append(&entry).await.unwrap();
+        }
+        // A restarted gateway picks the chain up where it stopped, including
+        // a request from before midnight written after the new day began
+        let store = AuditStore::new(dir.path()).with_hmac_key("audit-key");
+        for (i, timestamp) in [day(1, 23), day(2, 11)].into_iter().enumerate() {
+            let mut entry = entry(&format!("/late-{}", i));
+            entry.timestamp = timestamp;
+            store.append(&entry).await.unwrap();
+        }
+        let seqs: Vec<_> = read_lines(&dir.path().join("audit-2025-12-01.0.jsonl"))
+            .into_iter()
+            .chain(read_lines(&dir.path().join("audit-2025-12-02.0.jsonl")))
+            .map(|entry| entry.chain_seq.unwrap())
+            .collect();
+        assert_eq!(seqs, vec![1, 4, 2, 3, 5]);
+        let report = store.verify_chain(None, None).await.unwrap();
+        assert!(report.valid && report.anchored, "{:?}", report);
+        assert_eq!(report.checked, 5);
+
+        // Rewrite the second entry (seq 2) as an attacker without the key would
+        let path = dir.path().join("audit-2025-12-02.0.jsonl");
+        let content = std::fs::read_to_string(&path).unwrap().replacen("\"/1\"", "\"/harmless\"", 1);
+        std::fs::write(&path, content).unwrap();
+        let report = store.verify_chain(None, None).await.unwrap();
+        assert!(!report.valid);
+        assert_eq!(report.first_invalid.as_ref().map(|link| link.seq), Some(2));
+        // It and every entry written after it
+        assert_eq!(report.invalid, 4);
+
+        let unkeyed = AuditStore::new(dir.path());
+        assert!(matches!(unkeyed.verify_chain(None, None).await, Err(GatewayError::BadRequest(_))));
+    }
+
+    #[tokio::test]
+    async fn test_verification_by_range_pinpoints_the_edited_line() {
+        let dir = TempDir::new().unwrap();
+        let day = |d: u32| Utc.with_ymd_and_hms(2025, 12, d, 12, 0, 0).unwrap();
+        let store = AuditStore::new(dir.path()).with_hmac_key("audit-key");
+        // One batch across four days: each file still gets its boundary
+        let entries: Vec<_> = (1..=4)
+            .flat_map(|d| (0..2).map(move |i| (d, i)))
+            .map(|(d, i)| {
+                let mut entry = entry(&format!("/{}-{}", d, i));
+                entry.timestamp = day(d);
+                entry
+            })
+            .collect();
+        store.append_batch(&entries).await.unwrap();
+        let report = store.verify_chain(None, None).await.unwrap();
+        assert!(report.valid, "{:?}", report);
+        assert_eq!(report.checked, 8);
+
+        // Days 3 and 4 alone start from the link day 3's file began after
+        let report = store.verify_chain(Some(day(3)), None).await.unwrap();
+        assert!(report.valid && report.anchored, "{:?}", report);
+        assert_eq!((report.first_seq, report.last_seq), (Some(5), Some(8)));
+
+        // Still anchored once retention has deleted the first days
+        std::fs::remove_file(dir.path().join("audit-2025-12-01.0.jsonl")).unwrap();
+        std::fs::remove_file(dir.path().join("audit-2025-12-02.0.jsonl")).unwrap();
+        let report = store.verify_chain(None, None).await.unwrap();
+        assert!(report.valid && report.anchored, "{:?}", report);
+
+        let path = dir.path().join("audit-2025-12-03.0.jsonl");
+        let content = std::fs::read_to_string(&path).unwrap().replacen("\"/3-1\"", "\"/other\"", 1);
+        std::fs::write(&path, content).unwrap();
+        let report = store.verify_chain(Some(day(3)), Some(day(4))).await.unwrap();
+        assert!(!report.valid);
+        let broken = report.first_invalid.unwrap();
+        assert_eq!((broken.seq, broken.file.as_str()), (6, "audit-2025-12-03.0.jsonl"));
+        // Day 4 alone starts from the link stored when its file was created
+        assert!(store.verify_chain(Some(day(4)), Some(day(4))).await.unwrap().valid);
+
+        // Dropping the newest entry shows against chain.head
+        let path = dir.path().join("audit-2025-12-04.0.jsonl");
+        let content = std::fs::read_to_string(&path).unwrap();
+        std::fs::write(&path, format!("{}\n", content.lines().next().unwrap())).unwrap();
+        let report = store.verify_chain(Some(day(4)), None).await.unwrap();
+        assert_eq!(report.first_missing, Some(8));
+    }
+}
@@ -0,0 +1,65 @@
+//! What the admin endpoints, statistics and retention need from an audit
+//! backend (AUDIT_BACKEND): the daily JSONL files (`AuditStore`) or a SQLite
+//! table (`SqliteAuditStore`). Both take their writes as an `AuditSink`.
+
+use async_trait::async_trait;
+use chrono::{DateTime, NaiveDate, Utc};
+use futures_util::stream::BoxStream;
+use std::sync::Arc;
+
+use super::{AuditFilter, AuditSink, ChainReport, ExportFormat, RetentionPolicy, RetentionSummary};
+use crate::error::GatewayError;
+use crate::models::AuditLog;
+use crate::storage::Page;
+
+/// `GET /admin/audit/export` output: the header, then the rows, in chunks
+pub type ExportChunks = BoxStream<'static, std::io::Result<String>>;
+
+#[async_trait]
+pub trait AuditStoreTrait: AuditSink {
+    /// Matching entries newest first, `limit` of them from `offset`, with the
+    /// total number of matches
+    async fn query(
+        &self,
+        filter: &AuditFilter,
+        offset: usize,
+        limit: usize,
+    ) -> Result<Page<AuditLog>, GatewayError>;
+
+    /// Hand every matching entry to `visit`, oldest first, without collecting
+    /// them. For aggregations over ranges too large to `query`.
+    async fn scan(
+        &self,
+        filter: &AuditFilter,
+        visit: &mut (dyn FnMut(AuditLog) + Send),
+    ) -> Result<(), GatewayError>;
+
+    /// Matching entries rendered as `format`, oldest first, as a stream of
+    /// chunks that holds only one of them in memory at a time
+    async fn export(self: Arc<Self>, filter: AuditFilter, format: ExportFormat) -> Result<ExportChunks, GatewayError>;
+
+    /// Remove entries older than `policy` allows, counting days back from `today`
+    async fn apply_retention(
+        &self,
+        policy: &RetentionPolicy,
+        today: NaiveDate,
+    ) -> Result<RetentionSummary, GatewayError>;
+
+    /// Replay the HMAC chain over the entries between `from` and `to`
+    async fn verify_chain(
+        &self,
+        _from: Option<DateTime<Utc>>,
+        _to: Option<DateTime<Utc>>,
+    ) -> Result<ChainReport, GatewayError> {
+        Err(GatewayError::BadRequest(
+            "Audit chaining is disabled (AUDIT_HMAC_KEY is not set)".to_string(),
+        ))
+    }
+
+    /// One entry, straight to the backend (tests and tools; the proxy goes
+    /// through `AuditWriter`)
+    #[allow(dead_code)]
+    async fn append(&self, entry: &AuditLog) -> Result<(), GatewayError> {
+        self.write_batch(std::slice::from_ref(entry)).await
+    }
+}
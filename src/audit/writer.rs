@@ -0,0 +1,369 @@
+//! Audit entries leave the request path through bounded queues, one per sink
+//! (the local files, and any of `super::sink`'s external ones): a background
+//! task per queue writes them in batches, once AUDIT_BATCH_SIZE entries are
+//! waiting or AUDIT_FLUSH_INTERVAL_MS after the first of a batch, so a slow
+//! disk or collector delays the audit trail rather than the proxied requests.
+//! When the local queue is full, AUDIT_OVERFLOW_POLICY decides between dropping
+//! the entry and waiting briefly for room; dropped entries are counted.
+
+use serde::Serialize;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::{mpsc, oneshot};
+use tokio::time::Instant;
+
+use super::AuditSink;
+use crate::models::AuditLog;
+
+/// How long `block` waits for room before dropping the entry after all
+const BACKPRESSURE_TIMEOUT: Duration = Duration::from_millis(100);
+
+/// AUDIT_OVERFLOW_POLICY: what a request does when the audit queue is full
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AuditOverflowPolicy {
+    /// Drop the entry and count it; requests never wait
+    Drop,
+    /// Wait up to 100ms for room, then drop
+    Block,
+}
+
+impl AuditOverflowPolicy {
+    pub fn from_env_value(value: &str) -> Self {
+        match value.to_lowercase().as_str() {
+            "drop" => Self::Drop,
+            "block" => Self::Block,
+            other => panic!(
+                "AUDIT_OVERFLOW_POLICY must be 'drop' or 'block', got '{}'",
+                other
+            ),
+        }
+    }
+}
+
+/// AUDIT_QUEUE_CAPACITY / AUDIT_BATCH_SIZE / AUDIT_FLUSH_INTERVAL_MS / AUDIT_OVERFLOW_POLICY
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AuditWriterConfig {
+    pub capacity: usize,
+    pub batch_size: usize,
+    pub flush_interval: Duration,
+    pub overflow: AuditOverflowPolicy,
+}
+
+impl Default for AuditWriterConfig {
+    fn default() -> Self {
+        Self {
+            capacity: 10_000,
+            batch_size: 256,
+            flush_interval: Duration::from_millis(200),
+            overflow: AuditOverflowPolicy::Block,
+        }
+    }
+}
+
+/// Counters for `GET /admin/proxy/stats`: the local files at the top level
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct AuditWriterStats {
+    /// Entries waiting in the queue
+    pub queued: usize,
+    pub written: u64,
+    /// Turned away by a full queue
+    pub dropped: u64,
+    /// Lost to a failed write (full disk, ...)
+    pub failed: u64,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub sinks: Vec<AuditSinkStats>,
+}
+
+/// Counters of one external sink
+#[derive(Debug, Clone, Serialize)]
+pub struct AuditSinkStats {
+    pub name: &'static str,
+    pub queued: usize,
+    pub written: u64,
+    pub dropped: u64,
+    /// Not delivered, spooled ones included
+    pub failed: u64,
+    pub spooled: u64,
+}
+
+enum Message {
+    Entry(Box<AuditLog>),
+    Flush(oneshot::Sender<()>),
+}
+
+#[derive(Default)]
+struct Counters {
+    written: AtomicU64,
+    dropped: AtomicU64,
+    failed: AtomicU64,
+}
+
+/// One sink's queue and the task draining it
+#[derive(Clone)]
+struct SinkQueue {
+    sink: Arc<dyn AuditSink>,
+    sender: mpsc::Sender<Message>,
+    config: AuditWriterConfig,
+    counters: Arc<Counters>,
+    // The writer task starts with the first entry: AppState is built outside the runtime
+    idle: Arc<Mutex<Option<mpsc::Receiver<Message>>>>,
+}
+
+impl SinkQueue {
+    fn new(sink: Arc<dyn AuditSink>, config: AuditWriterConfig) -> Self {
+        let (sender, receiver) = mpsc::channel(config.capacity.max(1));
+        Self {
+            sink,
+            sender,
+            config,
+            counters: Arc::default(),
+            idle: Arc::new(Mutex::new(Some(receiver))),
+        }
+    }
+
+    async fn record(&self, entry: AuditLog) {
+        self.start();
+        let message = Message::Entry(Box::new(entry));
+        let sent = match self.config.overflow {
+            AuditOverflowPolicy::Drop => self.sender.try_send(message).is_ok(),
+            AuditOverflowPolicy::Block => self.sender.send_timeout(message, BACKPRESSURE_TIMEOUT).await.is_ok(),
+        };
+        if !sent {
+            let dropped = self.counters.dropped.fetch_add(1, Ordering::Relaxed) + 1;
+            // Once per thousand, so a full disk doesn't also flood the log
+            if dropped % 1000 == 1 {
+                tracing::warn!(sink = self.sink.name(), dropped, "Audit queue full; entries dropped");
+            }
+        }
+    }
+
+    async fn flush(&self) {
+        self.start();
+        let (done, wait) = oneshot::channel();
+        if self.sender.send(Message::Flush(done)).await.is_ok() {
+            let _ = wait.await;
+        }
+    }
+
+    fn stats(&self) -> AuditSinkStats {
+        AuditSinkStats {
+            name: self.sink.name(),
+            queued: self.sender.max_capacity() - self.sender.capacity(),
+            written: self.counters.written.load(Ordering::Relaxed),
+            dropped: self.counters.dropped.load(Ordering::Relaxed),
+            failed: self.counters.failed.load(Ordering::Relaxed),
+            spooled: self.sink.spooled(),
+        }
+    }
+
+    fn start(&self) {
+        let idle = self.idle.lock().unwrap_or_else(|e| e.into_inner()).take();
+        if let Some(receiver) = idle {
+            tokio::spawn(run(receiver, self.sink.clone(), self.config, self.counters.clone()));
+        }
+    }
+}
+
+#[derive(Clone, Default)]
+pub struct AuditWriter {
+    /// The audit store the admin endpoints read; `None` when only external sinks are set
+    local: Option<SinkQueue>,
+    external: Vec<SinkQueue>,
+}
+
+impl AuditWriter {
+    pub fn new(store: Arc<dyn AuditSink>, config: AuditWriterConfig) -> Self {
+        Self {
+            local: Some(SinkQueue::new(store, config)),
+            external: Vec::new(),
+        }
+    }
+
+    /// Also ship every entry to `sink`, through its own queue
+    pub fn with_sink(mut self, sink: Arc<dyn AuditSink>, config: AuditWriterConfig) -> Self {
+        self.external.push(SinkQueue::new(sink, config));
+        self
+    }
+
+    /// Queue `entry` for every sink; never fails the request, whatever happens to the entry
+    pub async fn record(&self, entry: AuditLog) {
+        for queue in &self.external {
+            queue.record(entry.clone()).await;
+        }
+        if let Some(local) = &self.local {
+            local.record(entry).await;
+        }
+    }
+
+    /// Wait until every entry queued so far is in the audit files (or failed).
+    /// Queries call this first so they see what was just audited.
+    pub async fn flush(&self) {
+        if let Some(local) = &self.local {
+            local.flush().await;
+        }
+    }
+
+    /// `flush` for every sink, external ones included; shutdown calls it to drain
+    pub async fn drain(&self) {
+        futures_util::future::join_all(self.local.iter().chain(&self.external).map(SinkQueue::flush)).await;
+    }
+
+    pub fn stats(&self) -> AuditWriterStats {
+        let local = self.local.as_ref().map(SinkQueue::stats);
+        AuditWriterStats {
+            queued: local.as_ref().map_or(0, |s| s.queued),
+            written: local.as_ref().map_or(0, |s| s.written),
+            dropped: local.as_ref().map_or(0, |s| s.dropped),
+            failed: local.as_ref().map_or(0, |s| s.failed),
+            sinks: self.external.iter().map(SinkQueue::stats).collect(),
+        }
+    }
+}
+
+async fn run(
+    mut receiver: mpsc::Receiver<Message>,
+    sink: Arc<dyn AuditSink>,
+    config: AuditWriterConfig,
+    counters: Arc<Counters>,
+) {
+    let mut batch: Vec<AuditLog> = Vec::new();
+    let mut deadline = Instant::now();
+    loop {
+        let message = if batch.is_empty() {
+            receiver.recv().await
+        } else {
+            match tokio::time::timeout_at(deadline, receiver.recv()).await {
+                Ok(message) => message,
+                Err(_) => {
+                    write(sink.as_ref(), &mut batch, &counters).await;
+                    continue;
+                }
+            }
+        };
+        match message {
+            Some(Message::Entry(entry)) => {
+                if batch.is_empty() {
+                    deadline = Instant::now() + config.flush_interval;
+                }
+                batch.push(*entry);
+                if batch.len() >= config.batch_size {
+                    write(sink.as_ref(), &mut batch, &counters).await;
+                }
+            }
+            Some(Message::Flush(done)) => {
+                write(sink.as_ref(), &mut batch, &counters).await;
+                let _ = done.send(());
+            }
+            None => {
+                write(sink.as_ref(), &mut batch, &counters).await;
+                return;
+            }
+        }
+    }
+}
+
+async fn write(sink: &dyn AuditSink, batch: &mut Vec<AuditLog>, counters: &Counters) {
+    if batch.is_empty() {
+        return;
+    }
+    let count = batch.len() as u64;
+    match sink.write_batch(batch).await {
+        Ok(()) => counters.written.fetch_add(count, Ordering::Relaxed),
+        Err(e) => {
+            // Best effort: a full disk or a dead collector must not take the proxy down with it
+            tracing::error!(sink = sink.name(), error = ?e, entries = count, "Failed to write audit entries");
+            counters.failed.fetch_add(count, Ordering::Relaxed)
+        }
+    };
+    batch.clear();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::audit::AuditStore;
+    use tempfile::TempDir;
+
+    fn entry(i: usize) -> AuditLog {
+        AuditLog::new("svc".to_string(), format!("/{}", i), "GET".to_string())
+    }
+
+    #[tokio::test]
+    async fn test_burst_lands_on_disk_after_a_flush() {
+        let dir = TempDir::new().unwrap();
+        let store = Arc::new(AuditStore::new(dir.path()));
+        let writer = AuditWriter::new(store.clone(), AuditWriterConfig::default());
+
+        for i in 0..10_000 {
+            writer.record(entry(i)).await;
+        }
+        writer.flush().await;
+        let stats = writer.stats();
+        assert_eq!((stats.written, stats.dropped, stats.queued), (10_000, 0, 0));
+
+        let lines: usize = std::fs::read_dir(dir.path())
+            .unwrap()
+            .map(|file| std::fs::read_to_string(file.unwrap().path()).unwrap().lines().count())
+            .sum();
+        assert_eq!(lines, 10_000);
+    }
+
+    #[tokio::test]
+    async fn test_requests_never_wait_for_the_disk() {
+        let dir = TempDir::new().unwrap();
+        let store = Arc::new(AuditStore::new(dir.path()));
+        let config = AuditWriterConfig {
+            batch_size: usize::MAX,
+            flush_interval: Duration::from_secs(3600),
+            ..Default::default()
+        };
+        let writer = AuditWriter::new(store, config);
+
+        // Queued without a single write, however slow the disk would be
+        for i in 0..5_000 {
+            writer.record(entry(i)).await;
+        }
+        assert_eq!(writer.stats().written, 0);
+        assert!(std::fs::read_dir(dir.path()).unwrap().next().is_none());
+        writer.flush().await;
+        assert_eq!(writer.stats().written, 5_000);
+    }
+
+    #[tokio::test]
+    async fn test_full_queue_drops_and_counts() {
+        let dir = TempDir::new().unwrap();
+        let store = Arc::new(AuditStore::new(dir.path()));
+        let config = AuditWriterConfig {
+            capacity: 4,
+            overflow: AuditOverflowPolicy::Drop,
+            ..Default::default()
+        };
+        let writer = AuditWriter::new(store, config);
+
+        // `drop` never yields, so the writer task can't make room in between
+        for i in 0..1_000 {
+            writer.record(entry(i)).await;
+        }
+        let stats = writer.stats();
+        assert_eq!((stats.queued, stats.dropped), (4, 996));
+        writer.flush().await;
+        assert_eq!(writer.stats().written, 4);
+    }
+
+    #[tokio::test]
+    async fn test_entries_are_written_after_the_flush_interval() {
+        let dir = TempDir::new().unwrap();
+        let store = Arc::new(AuditStore::new(dir.path()));
+        let config = AuditWriterConfig {
+            flush_interval: Duration::from_millis(50),
+            ..Default::default()
+        };
+        let writer = AuditWriter::new(store, config);
+
+        writer.record(entry(0)).await;
+        assert_eq!(writer.stats().written, 0);
+        tokio::time::sleep(Duration::from_millis(300)).await;
+        assert_eq!(writer.stats().written, 1);
+    }
+}
@@ -1,8 +1,19 @@
-use axum::{extract::Request, middleware::Next, response::Response};
+use async_trait::async_trait;
+use axum::{
+    extract::{FromRequestParts, Path, Request},
+    http::{request::Parts, HeaderMap},
+    middleware::Next,
+    response::Response,
+};
+use std::collections::HashMap;
+use uuid::Uuid;
 
 use crate::error::GatewayError;
+use crate::models::Agent;
+use crate::state::AppState;
 
 const SESSION_HEADER: &str = "X-Session-ID";
+pub const ADMIN_KEY_HEADER: &str = "X-Admin-Key";
 
 /// Session authentication middleware (for future use with Tower middleware)
 #[allow(dead_code)]
@@ -23,3 +34,97 @@ pub async fn session_auth(
 
     Ok(next.run(request).await)
 }
+
+/// How the caller proved access to an agent
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AgentAccess {
+    Admin,
+    OwnSession,
+}
+
+/// Agent named by the `{agent_id}` path param, verified to be accessible by the caller:
+/// either a valid `X-Admin-Key`, or an `X-Session-ID` belonging to that same agent.
+/// The agent is also inserted into request extensions for downstream extractors.
+#[derive(Debug, Clone)]
+pub struct VerifiedAgent {
+    pub agent: Agent,
+    pub access: AgentAccess,
+}
+
+#[async_trait]
+impl FromRequestParts<AppState> for VerifiedAgent {
+    type Rejection = GatewayError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &AppState) -> Result<Self, Self::Rejection> {
+        let Path(params) = Path::<HashMap<String, String>>::from_request_parts(parts, state)
+            .await
+            .map_err(|_| GatewayError::BadRequest("Missing agent_id".to_string()))?;
+
+        let agent_id = params
+            .get("agent_id")
+            .and_then(|id| Uuid::parse_str(id).ok())
+            .ok_or_else(|| GatewayError::BadRequest("Invalid agent_id".to_string()))?;
+
+        let verified = if is_admin(&parts.headers, state) {
+            let agent = state
+                .agents
+                .get_agent(agent_id)
+                .await?
+                .ok_or_else(|| GatewayError::NotFound("Agent not found".to_string()))?;
+            VerifiedAgent {
+                agent,
+                access: AgentAccess::Admin,
+            }
+        } else {
+            let session_id = parts
+                .headers
+                .get(SESSION_HEADER)
+                .and_then(|v| v.to_str().ok())
+                .ok_or_else(|| {
+                    GatewayError::Unauthorized("Missing X-Session-ID or X-Admin-Key header".to_string())
+                })?;
+
+            let (_, agent) = state.validate_session(session_id).await?;
+
+            // Only the agent's own session may view or modify it
+            if agent.id != agent_id {
+                tracing::warn!(
+                    session_agent = %agent.id,
+                    requested_agent = %agent_id,
+                    "Cross-agent access denied"
+                );
+                return Err(GatewayError::Forbidden(
+                    "Session does not belong to this agent".to_string(),
+                ));
+            }
+
+            VerifiedAgent {
+                agent,
+                access: AgentAccess::OwnSession,
+            }
+        };
+
+        parts.extensions.insert(verified.agent.clone());
+        Ok(verified)
+    }
+}
+
+/// Check the `X-Admin-Key` header against the configured admin key
+pub fn is_admin(headers: &HeaderMap, state: &AppState) -> bool {
+    let Some(expected) = state.settings.admin_api_key.as_deref() else {
+        return false;
+    };
+
+    headers
+        .get(ADMIN_KEY_HEADER)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|provided| constant_time_eq(provided.as_bytes(), expected.as_bytes()))
+}
+
+/// Compare without short-circuiting on the first differing byte
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+    a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
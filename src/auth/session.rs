@@ -1,11 +1,10 @@
-//! Session creation utilities (prepared for session management)
+//! Session creation utilities
 
 use chrono::{Duration, Utc};
 use uuid::Uuid;
 
 use crate::models::AgentSession;
 
-#[allow(dead_code)]
 pub fn create_session(agent_id: Uuid, ttl_secs: u64) -> AgentSession {
     let now = Utc::now();
     AgentSession {
@@ -1,17 +1,22 @@
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
+use serde_json::Value;
 use std::collections::HashMap;
 use std::fs;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
+use std::time::Duration;
 use tokio::sync::RwLock;
 
 use crate::error::GatewayError;
-use crate::gateway::{decrypt, encrypt};
+use crate::gateway::{decrypt, encrypt, is_expired_with_skew, needs_refresh_with_skew};
+use crate::storage::{
+    backup_file, check_store_file, with_io_timeout, CREDENTIALS_SCHEMA, DEFAULT_BACKUP_COUNT, DEFAULT_FILE_IO_TIMEOUT,
+};
 
-/// Credential as stored in JSON file (tokens are encrypted)
+/// Credential as stored in JSON file (tokens are encrypted); also the data export format
 #[derive(Debug, Clone, Serialize, Deserialize)]
-struct EncryptedCredential {
+pub struct EncryptedCredential {
     pub service_id: String,
     pub access_token: String,           // Encrypted, base64
     pub refresh_token: Option<String>,  // Encrypted, base64
@@ -31,16 +36,63 @@ pub struct StoredCredential {
     pub scopes: Vec<String>,
 }
 
+/// Characters of the access token `GET /admin/credentials` shows, from the end
+const TOKEN_SUFFIX_LEN: usize = 4;
+
+/// Tokens shorter than this are masked entirely
+const MIN_TOKEN_LEN_FOR_SUFFIX: usize = 12;
+
+/// What an admin may see of a stored credential: never the tokens themselves
+#[derive(Debug, Clone, Serialize)]
+pub struct CredentialSummary {
+    pub service_id: String,
+    pub token_suffix: String,              // "****abcd"
+    pub scopes: Vec<String>,
+    pub expires_at: Option<DateTime<Utc>>,
+    pub needs_refresh: bool,               // Within the refresh buffer, or expired
+    pub expired: bool,                     // Past expiry by more than the clock skew allowance
+    pub has_refresh_token: bool,
+    pub last_used_at: Option<DateTime<Utc>>,  // Last proxy call the upstream accepted; since startup
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 struct CredentialsFile {
+    #[serde(default)]
+    schema_version: u32,
     credentials: Vec<EncryptedCredential>,
 }
 
+impl CredentialsFile {
+    fn new(credentials: Vec<EncryptedCredential>) -> Self {
+        Self {
+            schema_version: CREDENTIALS_SCHEMA.current_version(),
+            credentials,
+        }
+    }
+
+    /// Parse, upgrading older schema versions; `write_back` persists the upgrade
+    fn parse(content: &str, path: &Path, write_back: bool) -> Result<Self, GatewayError> {
+        let mut doc: Value = serde_json::from_str(content)
+            .map_err(|e| GatewayError::Internal(format!("Failed to parse credentials: {}", e)))?;
+        if write_back {
+            CREDENTIALS_SCHEMA.upgrade_file(&mut doc, path)?;
+        } else {
+            CREDENTIALS_SCHEMA.upgrade(&mut doc, path)?;
+        }
+        serde_json::from_value(doc)
+            .map_err(|e| GatewayError::Internal(format!("Failed to parse credentials: {}", e)))
+    }
+}
+
 #[derive(Clone)]
 pub struct CredentialManager {
     credentials: Arc<RwLock<HashMap<String, StoredCredential>>>,
-    file_path: String,
+    file_path: Option<String>,  // None: in-memory only, changes are never written
     encryption_key: String,
+    io_timeout: Duration,
+    backup_count: usize,
+    // Key: service_id; in memory only
+    last_used: Arc<std::sync::Mutex<HashMap<String, DateTime<Utc>>>>,
 }
 
 impl CredentialManager {
@@ -51,8 +103,7 @@ impl CredentialManager {
         let content = fs::read_to_string(&path)
             .map_err(|e| GatewayError::Internal(format!("Failed to read credentials: {}", e)))?;
 
-        let file: CredentialsFile = serde_json::from_str(&content)
-            .map_err(|e| GatewayError::Internal(format!("Failed to parse credentials: {}", e)))?;
+        let file = CredentialsFile::parse(&content, path.as_ref(), true)?;
 
         let mut credentials = HashMap::new();
         let mut needs_migration = false;
@@ -127,9 +178,10 @@ impl CredentialManager {
             if let Some(e) = migration_error {
                 tracing::error!("Failed to migrate credentials: {:?}", e);
             } else {
-                let file = CredentialsFile { credentials: encrypted_creds };
+                let file = CredentialsFile::new(encrypted_creds);
                 match serde_json::to_string_pretty(&file) {
                     Ok(content) => {
+                        // Not backed up: the old file holds the tokens in plaintext
                         if let Err(e) = fs::write(&path_str, content) {
                             tracing::error!("Failed to write migrated credentials: {}", e);
                         } else {
@@ -145,21 +197,156 @@ impl CredentialManager {
 
         Ok(Self {
             credentials: Arc::new(RwLock::new(credentials)),
-            file_path: path_str,
+            file_path: Some(path_str),
             encryption_key: encryption_key.to_string(),
+            io_timeout: DEFAULT_FILE_IO_TIMEOUT,
+            backup_count: DEFAULT_BACKUP_COUNT,
+            last_used: Arc::default(),
         })
     }
 
+    /// Credentials held only in memory (STORAGE_BACKEND=memory, tests); updates are never persisted
+    pub fn in_memory(seed: Vec<StoredCredential>, encryption_key: &str) -> Self {
+        Self {
+            credentials: Arc::new(RwLock::new(
+                seed.into_iter().map(|c| (c.service_id.clone(), c)).collect(),
+            )),
+            file_path: None,
+            encryption_key: encryption_key.to_string(),
+            io_timeout: DEFAULT_FILE_IO_TIMEOUT,
+            backup_count: DEFAULT_BACKUP_COUNT,
+            last_used: Arc::default(),
+        }
+    }
+
+    /// Deadline for each credentials.json write (FILE_IO_TIMEOUT_SECS)
+    pub fn with_io_timeout(mut self, timeout: Duration) -> Self {
+        self.io_timeout = timeout;
+        self
+    }
+
+    /// Timestamped backups of credentials.json to keep (CREDENTIALS_BACKUP_COUNT, 0 = none)
+    pub fn with_backup_count(mut self, backup_count: usize) -> Self {
+        self.backup_count = backup_count;
+        self
+    }
+
+    /// Back up credentials.json now (POST /admin/backup); `None` when in memory
+    pub async fn backup(&self) -> Result<Option<PathBuf>, GatewayError> {
+        let Some(file_path) = &self.file_path else {
+            return Ok(None);
+        };
+        // Holding the lock keeps a save from rewriting the file mid-copy
+        let _credentials = self.credentials.read().await;
+        let path = Path::new(file_path);
+        with_io_timeout(self.io_timeout, path, backup_file(path, self.backup_count)).await
+    }
+
+    /// Whether credentials.json can still be written (`GET /health/detailed`);
+    /// always when in memory
+    pub async fn health_check(&self) -> Result<(), GatewayError> {
+        match &self.file_path {
+            Some(file_path) => check_store_file(self.io_timeout, Path::new(file_path)).await,
+            None => Ok(()),
+        }
+    }
+
+    /// Decrypted credentials from a file, without migrating or rewriting it
+    /// (older schema versions are upgraded in memory only).
+    /// A missing file means no credentials.
+    pub fn read_seed<P: AsRef<Path>>(path: P, encryption_key: &str) -> Result<Vec<StoredCredential>, GatewayError> {
+        let Ok(content) = fs::read_to_string(&path) else {
+            return Ok(Vec::new());
+        };
+        let file = CredentialsFile::parse(&content, path.as_ref(), false)?;
+
+        file.credentials
+            .into_iter()
+            .map(|c| decrypt_credential(c, encryption_key))
+            .collect()
+    }
+
     pub async fn get(&self, service_id: &str) -> Option<StoredCredential> {
         self.credentials.read().await.get(service_id).cloned()
     }
 
+    /// Every stored credential, ordered by service id
+    pub async fn list(&self) -> Vec<StoredCredential> {
+        let mut credentials: Vec<_> = self.credentials.read().await.values().cloned().collect();
+        credentials.sort_by(|a, b| a.service_id.cmp(&b.service_id));
+        credentials
+    }
+
+    /// Every stored credential with the tokens masked, ordered by service id;
+    /// `skew_secs` as for `needs_refresh_with_skew` and `is_expired_with_skew`
+    pub async fn summaries(&self, skew_secs: u64) -> Vec<CredentialSummary> {
+        let last_used = self.last_used.lock().unwrap_or_else(|e| e.into_inner()).clone();
+        self.list()
+            .await
+            .into_iter()
+            .map(|c| CredentialSummary {
+                token_suffix: mask_token(&c.access_token),
+                needs_refresh: needs_refresh_with_skew(&c, skew_secs),
+                expired: is_expired_with_skew(&c, skew_secs),
+                has_refresh_token: c.refresh_token.is_some(),
+                last_used_at: last_used.get(&c.service_id).copied(),
+                service_id: c.service_id,
+                scopes: c.scopes,
+                expires_at: c.expires_at,
+            })
+            .collect()
+    }
+
+    /// Note that the upstream accepted a proxied call made with this service's credential
+    pub fn mark_used(&self, service_id: &str) {
+        self.last_used
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .insert(service_id.to_string(), Utc::now());
+    }
+
     pub async fn update(&self, credential: StoredCredential) -> Result<(), GatewayError> {
         let mut creds = self.credentials.write().await;
         creds.insert(credential.service_id.clone(), credential);
         self.save_to_file(&creds).await
     }
 
+    /// All credentials as they are persisted, i.e. encrypted with this gateway's key
+    pub async fn export_encrypted(&self) -> Result<Vec<EncryptedCredential>, GatewayError> {
+        self.credentials
+            .read()
+            .await
+            .values()
+            .map(|c| self.encrypt_credential(c))
+            .collect()
+    }
+
+    /// Load exported credentials. `source_key` is the exporting gateway's encryption
+    /// key; everything is re-encrypted with this gateway's key when saved. Nothing is
+    /// applied unless every credential decrypts. Returns how many were imported.
+    pub async fn import(
+        &self,
+        exported: Vec<EncryptedCredential>,
+        source_key: &str,
+        replace: bool,
+    ) -> Result<usize, GatewayError> {
+        let decrypted = exported
+            .into_iter()
+            .map(|c| decrypt_credential(c, source_key))
+            .collect::<Result<Vec<_>, _>>()?;
+        let imported = decrypted.len();
+
+        let mut creds = self.credentials.write().await;
+        if replace {
+            creds.clear();
+        }
+        for credential in decrypted {
+            creds.insert(credential.service_id.clone(), credential);
+        }
+        self.save_to_file(&creds).await?;
+        Ok(imported)
+    }
+
     /// Check if credential needs refresh
     #[allow(dead_code)]
     pub async fn needs_refresh(&self, service_id: &str, buffer_secs: i64) -> bool {
@@ -174,22 +361,26 @@ impl CredentialManager {
 
     /// Save credentials to file with encryption
     async fn save_to_file(&self, creds: &HashMap<String, StoredCredential>) -> Result<(), GatewayError> {
+        let Some(file_path) = &self.file_path else {
+            return Ok(());
+        };
         let encrypted_creds: Result<Vec<_>, _> = creds
             .values()
             .map(|c| self.encrypt_credential(c))
             .collect();
 
-        let file = CredentialsFile {
-            credentials: encrypted_creds?,
-        };
+        let file = CredentialsFile::new(encrypted_creds?);
 
         let content = serde_json::to_string_pretty(&file)
             .map_err(|e| GatewayError::Internal(format!("Failed to serialize credentials: {}", e)))?;
 
-        fs::write(&self.file_path, content)
-            .map_err(|e| GatewayError::Internal(format!("Failed to write credentials: {}", e)))?;
-
-        Ok(())
+        let write = async {
+            backup_file(Path::new(file_path), self.backup_count).await?;
+            tokio::fs::write(file_path, content)
+                .await
+                .map_err(|e| GatewayError::Internal(format!("Failed to write credentials: {}", e)))
+        };
+        with_io_timeout(self.io_timeout, Path::new(file_path), write).await
     }
 
     /// Encrypt a credential for storage
@@ -211,6 +402,41 @@ impl CredentialManager {
     }
 }
 
+/// The last few characters of a token, enough to tell which one is stored
+fn mask_token(token: &str) -> String {
+    let chars: Vec<char> = token.chars().collect();
+    if chars.len() < MIN_TOKEN_LEN_FOR_SUFFIX {
+        return "****".to_string();
+    }
+    let suffix: String = chars[chars.len() - TOKEN_SUFFIX_LEN..].iter().collect();
+    format!("****{}", suffix)
+}
+
+fn decrypt_credential(
+    credential: EncryptedCredential,
+    key: &str,
+) -> Result<StoredCredential, GatewayError> {
+    let open = |value: &str| {
+        if !credential.encrypted {
+            return Ok(value.to_string());
+        }
+        decrypt(value, key).map_err(|_| {
+            GatewayError::BadRequest(format!(
+                "Cannot decrypt credential for '{}': wrong encryption key?",
+                credential.service_id
+            ))
+        })
+    };
+
+    Ok(StoredCredential {
+        access_token: open(&credential.access_token)?,
+        refresh_token: credential.refresh_token.as_deref().map(open).transpose()?,
+        service_id: credential.service_id.clone(),
+        expires_at: credential.expires_at,
+        scopes: credential.scopes.clone(),
+    })
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -288,4 +514,59 @@ mod tests {
         assert_eq!(stored.access_token, "my_secret_token");
         assert_eq!(stored.refresh_token, Some("my_refresh_token".to_string()));
     }
+
+    #[tokio::test]
+    async fn test_summaries_mask_tokens_and_track_use() {
+        let credential = |service_id: &str, token: &str| StoredCredential {
+            service_id: service_id.to_string(),
+            access_token: token.to_string(),
+            refresh_token: None,
+            expires_at: None,
+            scopes: vec!["read".to_string()],
+        };
+        let manager = CredentialManager::in_memory(
+            vec![credential("long", "sk-live-0123456789abcd"), credential("short", "abc123")],
+            "test-encryption-key-32-chars!!!",
+        );
+        manager.mark_used("long");
+
+        let summaries = manager.summaries(0).await;
+        assert_eq!(summaries[0].service_id, "long");
+        assert_eq!(summaries[0].token_suffix, "****abcd");
+        assert!(summaries[0].last_used_at.is_some());
+        assert_eq!(summaries[1].token_suffix, "****");
+        assert!(summaries[1].last_used_at.is_none());
+        assert!(!serde_json::to_string(&summaries).unwrap().contains("abc123"));
+    }
+
+    #[tokio::test]
+    async fn test_save_backs_up_the_previous_file() {
+        let key = "test-encryption-key-32-chars!!!";
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("credentials.json");
+        fs::write(&path, r#"{"credentials": []}"#).unwrap();
+        let manager = CredentialManager::load_from_file(&path, key)
+            .unwrap()
+            .with_backup_count(2);
+        let credential = |token: &str| StoredCredential {
+            service_id: "svc".to_string(),
+            access_token: token.to_string(),
+            refresh_token: None,
+            expires_at: None,
+            scopes: vec![],
+        };
+
+        manager.update(credential("first")).await.unwrap();
+        assert_eq!(crate::storage::list_backups(&path).len(), 1);
+
+        let before = fs::read_to_string(&path).unwrap();
+        manager.update(credential("second")).await.unwrap();
+        manager.update(credential("third")).await.unwrap();
+        let backups = crate::storage::list_backups(&path);
+        assert_eq!(backups.len(), 2);
+        assert_eq!(fs::read_to_string(&backups[1]).unwrap(), before);
+
+        let reloaded = CredentialManager::load_from_file(&backups[0], key).unwrap();
+        assert_eq!(reloaded.get("svc").await.unwrap().access_token, "second");
+    }
 }
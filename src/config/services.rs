@@ -1,9 +1,16 @@
-use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use serde::{Deserialize, Serialize, Serializer};
+use serde_json::{Map, Value};
+use std::collections::{HashMap, HashSet};
 use std::fs;
 use std::path::Path;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+use tokio::net::TcpStream;
+use tokio::task::JoinSet;
 
 use crate::error::GatewayError;
+use crate::gateway::SsrfPolicy;
+use crate::models::ServiceAuthType;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ServiceConfig {
@@ -14,6 +21,118 @@ pub struct ServiceConfig {
     pub auth_type: String,
     pub endpoints: Vec<EndpointConfig>,
     pub rate_limit: RateLimitConfig,
+    // Other names the service answers to (`/api/{alias}/...`, grants); requests,
+    // credentials and rate limits all resolve to `id`
+    #[serde(default)]
+    pub aliases: Vec<String>,
+    #[serde(default)]
+    pub rotation: Option<RotationConfig>,
+    // Header the credential is sent in (default Authorization); bearer auth still
+    // sends `Bearer {token}`, `api_key` the bare token
+    #[serde(default)]
+    pub auth_header_name: Option<String>,
+    // Structured auth settings for auth types that need more than a stored token
+    #[serde(default)]
+    pub auth: Option<ServiceAuthType>,
+    // Assumed token lifetime when the provider returns no expiry
+    #[serde(default)]
+    pub default_token_ttl_secs: Option<u64>,
+    // Talk HTTP/2 (prior knowledge) to the upstream for connection multiplexing
+    #[serde(default)]
+    pub use_http2: bool,
+    // Forward the path exactly as received (no dot-segment/percent-encoding checks)
+    #[serde(default)]
+    pub skip_path_normalization: bool,
+    // Upstream timeouts: connect (TCP + TLS, default 10s) and read (whole response, default 60s)
+    #[serde(default)]
+    pub connect_timeout_secs: Option<u64>,
+    #[serde(default)]
+    pub read_timeout_secs: Option<u64>,
+    // Re-send the request when the upstream stalls mid-response (not on connect failures)
+    #[serde(default)]
+    pub retry_on_read_timeout: bool,
+    // On a read timeout mid-body, answer 206 with what arrived instead of 504 (streaming inference)
+    #[serde(default)]
+    pub partial_response_on_timeout: bool,
+    // Most of a timed-out body returned as the partial response; unset means 1 MiB
+    #[serde(default)]
+    pub max_partial_response_bytes: Option<usize>,
+    // Serve repeated 200 GET responses from the gateway cache
+    #[serde(default)]
+    pub cache_get_responses: bool,
+    #[serde(default = "default_cache_ttl_secs")]
+    pub cache_ttl_secs: u64,
+    // `Host` header sent upstream (virtual-hosted / SNI-routed APIs); default derives it from base_url
+    #[serde(default)]
+    pub override_host: Option<String>,
+    // Scan request bodies for prompt injection (INJECTION_GUARD_MODE decides block vs log)
+    #[serde(default)]
+    pub enable_injection_guard: bool,
+    // Case-insensitive regexes; empty means the built-in defaults
+    #[serde(default)]
+    pub injection_patterns: Vec<String>,
+    // Client headers forwarded upstream on top of the safe defaults (e.g. X-Conversation-Id)
+    #[serde(default)]
+    pub context_headers_passthrough: Vec<String>,
+    // Upstream response body cap; unset means MAX_RESPONSE_BODY_BYTES
+    #[serde(default)]
+    pub max_response_body_bytes: Option<usize>,
+    // Ask for gzip/deflate responses; they are decoded in the gateway, and the decoded
+    // size counts against max_response_body_bytes
+    #[serde(default)]
+    pub accept_compressed_response: bool,
+    // Wrap responses in `{ success, data | error, meta }` for agent SDKs
+    #[serde(default)]
+    pub wrap_response: bool,
+    // JSON shape used instead, with "{{data}}", "{{meta}}", ... placeholders
+    #[serde(default)]
+    pub response_envelope_template: Option<String>,
+    // Redact card numbers, Stripe live keys and SSNs in JSON responses
+    #[serde(default)]
+    pub sanitize_responses: bool,
+    // Extra regexes redacted along with the built-in ones
+    #[serde(default)]
+    pub response_sensitive_patterns: Vec<String>,
+    // Request/response payloads kept in audit entries, redacted
+    #[serde(default)]
+    pub audit_capture: Option<AuditCaptureConfig>,
+    // HMAC key for X-Gateway-Signature on outbound requests; SERVICE_<ID>_SIGNING_KEY
+    // overrides it, so the key needn't sit in the JSON. Never serialized back out.
+    #[serde(default, skip_serializing)]
+    pub signing_key: Option<String>,
+}
+
+fn default_cache_ttl_secs() -> u64 {
+    60
+}
+
+impl ServiceConfig {
+    /// The id, then the aliases
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        std::iter::once(self.id.as_str()).chain(self.aliases.iter().map(String::as_str))
+    }
+
+    /// SERVICE_<ID>_SIGNING_KEY (id uppercased, `-` as `_`), looked up with `lookup`
+    fn apply_signing_key_override(&mut self, lookup: impl Fn(&str) -> Option<String>) {
+        let var = format!("SERVICE_{}_SIGNING_KEY", self.id.to_uppercase().replace('-', "_"));
+        if let Some(key) = lookup(&var).filter(|key| !key.is_empty()) {
+            self.signing_key = Some(key);
+        }
+    }
+
+    /// Defaults for a service found by `ServiceRegistry::from_env`
+    fn discovered(id: String) -> Self {
+        serde_json::from_value(serde_json::json!({
+            "id": id,
+            "name": id,
+            "description": "",
+            "base_url": "",
+            "auth_type": "bearer_token",
+            "endpoints": [],
+            "rate_limit": DISCOVERED_RATE_LIMIT,
+        }))
+        .expect("discovered service defaults deserialize")
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -21,6 +140,32 @@ pub struct EndpointConfig {
     pub path: String,
     pub methods: Vec<String>,
     pub required_scopes: Vec<String>,
+    // JSON Schema request bodies must match: the schema itself, or a path to a file holding it
+    #[serde(default)]
+    pub request_schema: Option<serde_json::Value>,
+}
+
+/// Which payloads go in a service's audit entries, and what is blanked out first
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AuditCaptureConfig {
+    // The client's request body, and its headers
+    #[serde(default)]
+    pub capture_request_body: bool,
+    #[serde(default)]
+    pub capture_response_body: bool,
+    // Larger bodies are kept as a SHA-256 digest and length only
+    #[serde(default = "default_capture_max_body_bytes")]
+    pub max_body_bytes: usize,
+    // JSON pointers (`/user/password`); a `*` segment matches every key or index
+    #[serde(default)]
+    pub redact_paths: Vec<String>,
+    // On top of X-Session-ID, Authorization, Proxy-Authorization, Cookie and X-Admin-Key
+    #[serde(default)]
+    pub redact_headers: Vec<String>,
+}
+
+fn default_capture_max_body_bytes() -> usize {
+    4096
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -29,42 +174,574 @@ pub struct RateLimitConfig {
     pub window_secs: u64,
 }
 
+/// Provider-side rotation of a static API key
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RotationConfig {
+    pub endpoint: String,
+    #[serde(default = "default_rotation_method")]
+    pub method: String,
+    #[serde(default)]
+    pub auth: RotationAuth,
+    /// JSON pointer to the new key in the response, e.g. `/data/api_key`
+    pub key_pointer: String,
+    pub interval_secs: u64,
+}
+
+fn default_rotation_method() -> String {
+    "POST".to_string()
+}
+
+/// How the gateway authenticates against the rotation endpoint
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum RotationAuth {
+    None,
+    /// Send the current key as `Authorization: Bearer <key>`
+    #[default]
+    CurrentKey,
+    Header { name: String, value: String },
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 struct ServicesFile {
     services: Vec<ServiceConfig>,
 }
 
+/// Result of the startup TCP reachability check for a service
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ServiceStatus {
+    Reachable,
+    Unreachable,
+}
+
+/// Timeout for a single TCP connect during URL validation
+const URL_CHECK_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// Suffix of the env vars `from_env` discovers services from
+const DISCOVERY_SUFFIX: &str = "_SERVICE_URL";
+
+/// Rate limit of a discovered service
+const DISCOVERED_RATE_LIMIT: RateLimitConfig = RateLimitConfig { requests: 100, window_secs: 60 };
+
+/// Services known to the gateway. Never written once built, so `get` is a
+/// plain lookup that takes no lock; `POST /admin/services/reload` builds a new
+/// registry and swaps the `Arc` holding it (an `ArcSwap` in `AppState`), so
+/// loading the current one takes no lock either (see `benches/service_lookup.rs`
+/// against a locked registry and a DashMap cache).
 #[derive(Debug, Clone)]
 pub struct ServiceRegistry {
     services: HashMap<String, ServiceConfig>,
+    // Alias -> id of the service it names
+    aliases: HashMap<String, String>,
+    // Populated by `validate_urls`; empty when validation is disabled
+    statuses: Arc<RwLock<HashMap<String, ServiceStatus>>>,
+}
+
+impl AsRef<ServiceRegistry> for ServiceRegistry {
+    fn as_ref(&self) -> &ServiceRegistry {
+        self
+    }
 }
 
 impl ServiceRegistry {
-    pub fn load_from_file<P: AsRef<Path>>(path: P) -> Result<Self, GatewayError> {
+    /// Load services, rejecting any base_url that targets a private address
+    /// not allowed by `ssrf`
+    pub fn load_from_file<P: AsRef<Path>>(path: P, ssrf: &SsrfPolicy) -> Result<Self, GatewayError> {
         let content = fs::read_to_string(path)
             .map_err(|e| GatewayError::Internal(format!("Failed to read services config: {}", e)))?;
 
         let file: ServicesFile = serde_json::from_str(&content)
             .map_err(|e| GatewayError::Internal(format!("Failed to parse services config: {}", e)))?;
 
-        let services = file
-            .services
+        let problems = Self::check(&file.services, ssrf);
+        if !problems.is_empty() {
+            return Err(GatewayError::Internal(problems.join("; ")));
+        }
+
+        Ok(Self::from_services(file.services))
+    }
+
+    /// Every problem with `services` at once: empty or duplicate ids, aliases that
+    /// are blank or name more than one service, and base URLs that don't parse,
+    /// aren't http(s) or that `ssrf` refuses
+    pub fn check(services: &[ServiceConfig], ssrf: &SsrfPolicy) -> Vec<String> {
+        let mut problems = Vec::new();
+        let mut seen = HashSet::new();
+        for service in services {
+            if service.id.trim().is_empty() {
+                problems.push(format!("Service '{}' has an empty id", service.name));
+            } else if !seen.insert(service.id.as_str()) {
+                problems.push(format!("Service id '{}' is defined more than once", service.id));
+            }
+        }
+        let mut aliases = HashSet::new();
+        for service in services {
+            for alias in &service.aliases {
+                if alias.trim().is_empty() || alias.contains('/') {
+                    problems.push(format!("Service '{}' has an invalid alias '{}'", service.id, alias));
+                } else if seen.contains(alias.as_str()) || !aliases.insert(alias.as_str()) {
+                    problems.push(format!("Alias '{}' of service '{}' is already in use", alias, service.id));
+                }
+            }
+        }
+        for service in services {
+            let scheme = reqwest::Url::parse(&service.base_url).ok().map(|url| url.scheme().to_string());
+            if let Some(scheme) = scheme.filter(|s| !matches!(s.as_str(), "http" | "https")) {
+                problems.push(format!(
+                    "Service '{}' base_url must be http or https, not '{}'",
+                    service.id, scheme
+                ));
+            }
+            if let Err(e) = ssrf.check_base_url(&service.id, &service.base_url) {
+                problems.push(e.parts().2);
+            }
+        }
+        problems
+    }
+
+    /// `path`, plus the services `from_env(discovery_prefix)` finds that the file
+    /// doesn't define. Without the file, the discovered services alone (if any).
+    pub fn load<P: AsRef<Path>>(path: P, discovery_prefix: &str, ssrf: &SsrfPolicy) -> Result<Self, GatewayError> {
+        let discovered = Self::from_env(discovery_prefix);
+        for service in discovered.services.values() {
+            ssrf.check_base_url(&service.id, &service.base_url)?;
+        }
+        let mut registry = if !path.as_ref().exists() && !discovered.services.is_empty() {
+            discovered
+        } else {
+            Self::load_from_file(path, ssrf)?.merge(discovered)
+        };
+        for service in registry.services.values_mut() {
+            service.apply_signing_key_override(|var| std::env::var(var).ok());
+        }
+        Ok(registry)
+    }
+
+    /// Services named by `{prefix}_{ID}_SERVICE_URL` env vars (Kubernetes-style
+    /// injection): `GATEWAY_PAYMENT_SERVICE_URL` is service `payment`, with a
+    /// bearer token, no endpoint list and 100 requests a minute
+    pub fn from_env(prefix: &str) -> Self {
+        Self::from_vars(prefix, std::env::vars())
+    }
+
+    fn from_vars(prefix: &str, vars: impl IntoIterator<Item = (String, String)>) -> Self {
+        let prefix = format!("{}_", prefix.trim_end_matches('_'));
+        let services = vars
             .into_iter()
-            .map(|s| (s.id.clone(), s))
+            .filter(|(_, url)| !url.trim().is_empty())
+            .filter_map(|(name, url)| {
+                let id = name.strip_prefix(&prefix)?.strip_suffix(DISCOVERY_SUFFIX)?;
+                if id.is_empty() {
+                    return None;
+                }
+                let id = id.to_lowercase().replace('_', "-");
+                Some(ServiceConfig {
+                    name: id.clone(),
+                    description: format!("Discovered from {}", name),
+                    base_url: url.trim().to_string(),
+                    ..ServiceConfig::discovered(id)
+                })
+            })
             .collect();
+        Self::from_services(services)
+    }
+
+    /// `other`'s services that `self` doesn't have; `self` wins on overlapping ids
+    /// (and on ids that are one of its aliases)
+    pub fn merge(mut self, other: Self) -> Self {
+        for (id, service) in other.services {
+            if self.services.contains_key(&id) || self.aliases.contains_key(&id) {
+                tracing::info!(service_id = %id, "Discovered service overridden by the services file");
+                continue;
+            }
+            tracing::info!(service_id = %id, base_url = %service.base_url, "Service discovered from the environment");
+            self.services.insert(id, service);
+        }
+        self
+    }
+
+    /// Services as given; an alias that `check` would refuse is ignored
+    pub fn from_services(services: Vec<ServiceConfig>) -> Self {
+        let services: HashMap<String, ServiceConfig> = services.into_iter().map(|s| (s.id.clone(), s)).collect();
+        let mut aliases = HashMap::new();
+        for service in services.values() {
+            for alias in &service.aliases {
+                if !services.contains_key(alias) && !aliases.contains_key(alias) {
+                    aliases.insert(alias.clone(), service.id.clone());
+                }
+            }
+        }
 
-        Ok(Self { services })
+        Self {
+            services,
+            aliases,
+            statuses: Arc::new(RwLock::new(HashMap::new())),
+        }
     }
 
+    /// The service with this id or alias. Lock-free: a plain map lookup (see the type's docs)
     pub fn get(&self, service_id: &str) -> Option<&ServiceConfig> {
-        self.services.get(service_id)
+        self.services.get(self.resolve(service_id).unwrap_or(service_id))
     }
 
+    /// The id of the service named `name` (itself for an id, the target for an alias)
+    pub fn resolve<'a>(&'a self, name: &'a str) -> Option<&'a str> {
+        if self.services.contains_key(name) {
+            return Some(name);
+        }
+        self.aliases.get(name).map(String::as_str)
+    }
+
+    /// Every service once, under its id
     pub fn list(&self) -> Vec<&ServiceConfig> {
         self.services.values().collect()
     }
 
-    pub fn exists(&self, service_id: &str) -> bool {
-        self.services.contains_key(service_id)
+    /// Each alias with the service it names
+    pub fn aliases(&self) -> Vec<(&str, &ServiceConfig)> {
+        self.aliases
+            .iter()
+            .filter_map(|(alias, id)| Some((alias.as_str(), self.services.get(id)?)))
+            .collect()
+    }
+
+    /// Cached reachability of a service (None if never validated)
+    pub fn status(&self, service_id: &str) -> Option<ServiceStatus> {
+        self.statuses
+            .read()
+            .ok()
+            .and_then(|s| s.get(service_id).copied())
+    }
+
+    /// TCP-connect to every service's base_url host and cache the result.
+    /// Unreachable services only produce a warning; they never block startup.
+    pub async fn validate_urls(&self) {
+        for (id, status) in self.probe().await {
+            let base_url = &self.services[&id].base_url;
+            match status {
+                ServiceStatus::Reachable => {
+                    tracing::info!(service_id = %id, base_url = %base_url, "Service reachable");
+                }
+                ServiceStatus::Unreachable => {
+                    tracing::warn!(service_id = %id, base_url = %base_url, "Service unreachable");
+                }
+            }
+            if let Ok(mut statuses) = self.statuses.write() {
+                statuses.insert(id, status);
+            }
+        }
+    }
+
+    /// TCP-connect to every service's base_url host concurrently, without
+    /// caching (`GET /health/detailed`)
+    pub async fn probe(&self) -> HashMap<String, ServiceStatus> {
+        let mut checks = JoinSet::new();
+        for service in self.services.values() {
+            let id = service.id.clone();
+            let base_url = service.base_url.clone();
+            checks.spawn(async move { (id, check_reachable(&base_url).await) });
+        }
+
+        let mut statuses = HashMap::new();
+        while let Some(Ok((id, status))) = checks.join_next().await {
+            statuses.insert(id, status);
+        }
+        statuses
+    }
+}
+
+/// Fields written first, in this order, when the admin API rewrites the
+/// services file; the others follow alphabetically
+const LEADING_FIELDS: [&str; 8] =
+    ["id", "name", "description", "base_url", "auth_type", "aliases", "endpoints", "rate_limit"];
+
+/// The services file as JSON, for `POST`/`PUT`/`DELETE /admin/services`.
+/// Entries are edited as written, so an edit neither drops fields `ServiceConfig`
+/// never serializes (`signing_key`) nor fills in every default.
+#[derive(Debug, Clone, Default)]
+pub struct ServicesDocument {
+    // Top-level fields other than `services`, kept as they are
+    rest: Map<String, Value>,
+    services: Vec<Map<String, Value>>,
+}
+
+impl ServicesDocument {
+    /// The file's content; `None` (no file yet) is a document without services
+    pub fn parse(content: Option<&str>) -> Result<Self, GatewayError> {
+        let Some(content) = content else {
+            return Ok(Self::default());
+        };
+        let invalid = |e: String| GatewayError::Internal(format!("Failed to parse services config: {}", e));
+        let mut rest: Map<String, Value> = serde_json::from_str(content).map_err(|e| invalid(e.to_string()))?;
+        let services = match rest.remove("services") {
+            Some(services) => serde_json::from_value(services).map_err(|e| invalid(e.to_string()))?,
+            None => Vec::new(),
+        };
+        Ok(Self { rest, services })
+    }
+
+    pub fn get(&self, id: &str) -> Option<&Map<String, Value>> {
+        self.services.iter().find(|entry| entry_id(entry) == Some(id))
+    }
+
+    /// Replace the entry with the same id in place, or append it; returns the one replaced
+    pub fn upsert(&mut self, entry: Map<String, Value>) -> Option<Map<String, Value>> {
+        let id = entry_id(&entry).map(str::to_string);
+        match self.services.iter_mut().find(|existing| entry_id(existing) == id.as_deref()) {
+            Some(existing) => Some(std::mem::replace(existing, entry)),
+            None => {
+                self.services.push(entry);
+                None
+            }
+        }
+    }
+
+    pub fn remove(&mut self, id: &str) -> Option<Map<String, Value>> {
+        let index = self.services.iter().position(|entry| entry_id(entry) == Some(id))?;
+        Some(self.services.remove(index))
+    }
+
+    /// Every entry as a `ServiceConfig`; the first that doesn't parse is named
+    pub fn services(&self) -> Result<Vec<ServiceConfig>, GatewayError> {
+        self.services
+            .iter()
+            .map(|entry| {
+                serde_json::from_value(Value::Object(entry.clone())).map_err(|e| {
+                    GatewayError::BadRequest(format!(
+                        "Service '{}' is invalid: {}",
+                        entry_id(entry).unwrap_or_default(),
+                        e
+                    ))
+                })
+            })
+            .collect()
+    }
+
+    /// Pretty-printed, with each entry's fields in `LEADING_FIELDS` order
+    pub fn render(&self) -> String {
+        let file = RenderedFile {
+            services: self.services.iter().map(OrderedEntry).collect(),
+            rest: &self.rest,
+        };
+        let mut content = serde_json::to_string_pretty(&file).unwrap_or_default();
+        content.push('\n');
+        content
+    }
+}
+
+fn entry_id(entry: &Map<String, Value>) -> Option<&str> {
+    entry.get("id").and_then(Value::as_str)
+}
+
+#[derive(Serialize)]
+struct RenderedFile<'a> {
+    services: Vec<OrderedEntry<'a>>,
+    #[serde(flatten)]
+    rest: &'a Map<String, Value>,
+}
+
+struct OrderedEntry<'a>(&'a Map<String, Value>);
+
+impl Serialize for OrderedEntry<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let leading = LEADING_FIELDS.iter().filter_map(|field| self.0.get_key_value(*field));
+        let others = self.0.iter().filter(|(field, _)| !LEADING_FIELDS.contains(&field.as_str()));
+        serializer.collect_map(leading.chain(others))
+    }
+}
+
+/// Attempt a bare TCP connection to the host and port of `base_url`
+async fn check_reachable(base_url: &str) -> ServiceStatus {
+    let Ok(url) = reqwest::Url::parse(base_url) else {
+        return ServiceStatus::Unreachable;
+    };
+    let (Some(host), Some(port)) = (url.host_str(), url.port_or_known_default()) else {
+        return ServiceStatus::Unreachable;
+    };
+
+    match tokio::time::timeout(URL_CHECK_TIMEOUT, TcpStream::connect((host, port))).await {
+        Ok(Ok(_)) => ServiceStatus::Reachable,
+        _ => ServiceStatus::Unreachable,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::net::TcpListener;
+
+    fn service(id: &str, base_url: &str) -> ServiceConfig {
+        serde_json::from_value(serde_json::json!({
+            "id": id,
+            "name": id,
+            "description": "",
+            "base_url": base_url,
+            "auth_type": "bearer_token",
+            "endpoints": [],
+            "rate_limit": { "requests": 10, "window_secs": 60 }
+        }))
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_validate_urls_marks_reachable_and_unreachable() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let open_port = listener.local_addr().unwrap().port();
+
+        // Grab a free port, then release it so nothing is listening there
+        let closed_port = {
+            let l = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            l.local_addr().unwrap().port()
+        };
+
+        let registry = ServiceRegistry::from_services(vec![
+            service("up", &format!("http://127.0.0.1:{}/v1", open_port)),
+            service("down", &format!("http://127.0.0.1:{}", closed_port)),
+            service("garbage", "not a url"),
+        ]);
+
+        assert_eq!(registry.status("up"), None);
+
+        registry.validate_urls().await;
+
+        assert_eq!(registry.status("up"), Some(ServiceStatus::Reachable));
+        assert_eq!(registry.status("down"), Some(ServiceStatus::Unreachable));
+        assert_eq!(registry.status("garbage"), Some(ServiceStatus::Unreachable));
+    }
+
+    #[test]
+    fn test_services_are_discovered_from_env_vars() {
+        let vars = [
+            ("GATEWAY_PAYMENT_SERVICE_URL", "http://payment.default.svc:8080"),
+            ("GATEWAY_RISK_SCORING_SERVICE_URL", "http://risk:9000/v1"),
+            ("GATEWAY_EMPTY_SERVICE_URL", ""),
+            ("OTHER_BANK_SERVICE_URL", "http://bank"),
+            ("GATEWAY_PAYMENT_URL", "http://nope"),
+        ]
+        .map(|(name, value)| (name.to_string(), value.to_string()));
+        let registry = ServiceRegistry::from_vars("GATEWAY", vars);
+
+        let mut ids: Vec<_> = registry.list().iter().map(|s| s.id.clone()).collect();
+        ids.sort();
+        assert_eq!(ids, ["payment", "risk-scoring"]);
+        let payment = registry.get("payment").unwrap();
+        assert_eq!(payment.base_url, "http://payment.default.svc:8080");
+        assert_eq!(payment.auth_type, "bearer_token");
+        assert_eq!(payment.rate_limit.requests, 100);
+        assert!(payment.endpoints.is_empty());
+    }
+
+    #[test]
+    fn test_file_services_win_over_discovered_ones() {
+        let file = ServiceRegistry::from_services(vec![service("payment", "https://api.payment.com")]);
+        let discovered = ServiceRegistry::from_vars(
+            "GATEWAY",
+            [
+                ("GATEWAY_PAYMENT_SERVICE_URL".to_string(), "http://payment:8080".to_string()),
+                ("GATEWAY_BANK_SERVICE_URL".to_string(), "http://bank:8080".to_string()),
+            ],
+        );
+        let merged = file.merge(discovered);
+
+        assert_eq!(merged.get("payment").unwrap().base_url, "https://api.payment.com");
+        assert_eq!(merged.get("bank").unwrap().base_url, "http://bank:8080");
+    }
+
+    #[test]
+    fn test_aliases_resolve_to_their_service() {
+        let mut openai = service("openai", "https://api.openai.com");
+        openai.aliases = vec!["gpt4".to_string(), "llm".to_string()];
+        let registry = ServiceRegistry::from_services(vec![openai, service("bank", "https://api.bank.com")]);
+
+        assert_eq!(registry.get("gpt4").unwrap().id, "openai");
+        assert_eq!(registry.resolve("llm"), Some("openai"));
+        assert_eq!(registry.resolve("bank"), Some("bank"));
+        assert_eq!(registry.resolve("nope"), None);
+        assert_eq!(registry.list().len(), 2);
+        assert_eq!(registry.aliases().len(), 2);
+
+        let ssrf = SsrfPolicy::from_allowlist(&[]).unwrap();
+        let mut taken = service("bank", "https://203.0.113.10");
+        taken.aliases = vec!["payment".to_string(), "gpt4".to_string(), " ".to_string()];
+        let mut payment = service("payment", "https://203.0.113.11");
+        payment.aliases = vec!["gpt4".to_string()];
+        let problems = ServiceRegistry::check(&[taken, payment], &ssrf);
+        assert_eq!(problems.len(), 3, "{:?}", problems);
+        assert!(problems[0].contains("'payment' of service 'bank' is already in use"));
+        assert!(problems[1].contains("invalid alias"));
+        assert!(problems[2].contains("'gpt4' of service 'payment' is already in use"));
+    }
+
+    #[test]
+    fn test_signing_key_env_override() {
+        let mut payment = service("risk-scoring", "https://api.risk.com");
+        payment.signing_key = Some("from-json".to_string());
+        payment.apply_signing_key_override(|var| {
+            (var == "SERVICE_RISK_SCORING_SIGNING_KEY").then(|| "from-env".to_string())
+        });
+        assert_eq!(payment.signing_key.as_deref(), Some("from-env"));
+        assert!(serde_json::to_value(&payment).unwrap().get("signing_key").is_none());
+
+        let mut bank = service("bank", "https://api.bank.com");
+        bank.apply_signing_key_override(|_| None);
+        assert_eq!(bank.signing_key, None);
+    }
+
+    #[test]
+    fn test_services_document_edits_entries_as_written() {
+        let content = r#"{
+            "services": [
+                { "id": "payment", "name": "Payment", "description": "", "base_url": "https://api.payment.com",
+                  "auth_type": "bearer_token", "endpoints": [], "rate_limit": { "requests": 10, "window_secs": 60 },
+                  "signing_key": "secret" },
+                { "id": "bank", "name": "Bank", "description": "", "base_url": "https://api.bank.com",
+                  "auth_type": "bearer_token", "endpoints": [], "rate_limit": { "requests": 10, "window_secs": 60 } }
+            ]
+        }"#;
+        let mut document = ServicesDocument::parse(Some(content)).unwrap();
+        let mut bank = document.get("bank").unwrap().clone();
+        bank.insert("cache_get_responses".to_string(), Value::Bool(true));
+        assert!(document.upsert(bank).is_some());
+        let Value::Object(files) = serde_json::to_value(service("files", "https://files.example.com")).unwrap() else {
+            unreachable!()
+        };
+        assert!(document.upsert(files).is_none());
+        assert!(document.remove("nope").is_none());
+
+        let services = document.services().unwrap();
+        let ids: Vec<_> = services.iter().map(|s| s.id.as_str()).collect();
+        assert_eq!(ids, ["payment", "bank", "files"]);
+        assert!(services[1].cache_get_responses);
+
+        // Left out of `ServiceConfig`'s serialization, but kept in the file
+        let rendered = document.render();
+        assert!(rendered.contains("\"signing_key\": \"secret\""));
+        let id_at = rendered.find("\"id\": \"payment\"").unwrap();
+        assert!(id_at < rendered.find("\"base_url\"").unwrap());
+        assert!(rendered.find("\"base_url\"").unwrap() < rendered.find("\"signing_key\"").unwrap());
+        assert_eq!(ServicesDocument::parse(Some(&rendered)).unwrap().services().unwrap().len(), 3);
+
+        assert!(ServicesDocument::parse(None).unwrap().services().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_check_reports_every_problem() {
+        let ssrf = SsrfPolicy::from_allowlist(&[]).unwrap();
+        let services = [
+            service("payment", "https://203.0.113.10"),
+            service("payment", "https://203.0.113.11"),
+            service(" ", "https://203.0.113.12"),
+            service("files", "ftp://203.0.113.13"),
+            service("broken", "not a url"),
+        ];
+
+        let problems = ServiceRegistry::check(&services, &ssrf);
+        assert_eq!(problems.len(), 4, "{:?}", problems);
+        assert!(problems[0].contains("'payment' is defined more than once"));
+        assert!(problems[1].contains("empty id"));
+        assert!(problems[2].contains("must be http or https"));
+        assert!(problems[3].contains("'broken' has an invalid base_url"));
+        assert!(ServiceRegistry::check(&services[..1], &ssrf).is_empty());
     }
 }
@@ -1,4 +1,84 @@
+use std::collections::HashMap;
 use std::env;
+use std::fs;
+
+use serde::Deserialize;
+
+use super::RateLimitConfig;
+use crate::audit::{
+    AuditOverflowPolicy, AuditWriterConfig, HttpAuditSinkConfig, RetentionPolicy, DEFAULT_AUDIT_MAX_FILE_BYTES,
+};
+use crate::error::ErrorFormat;
+use crate::gateway::{ExpiryWebhookFormat, InjectionGuardMode};
+
+#[derive(Debug, Default, Deserialize)]
+struct RateLimitsFile {
+    #[serde(default)]
+    groups: HashMap<String, RateLimitConfig>,
+}
+
+/// Where users, agents and sessions are persisted
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StorageBackend {
+    Memory,    // nothing written to disk; for tests and demos
+    File,
+    Sqlite,
+    Postgres,  // requires the `postgres` cargo feature
+}
+
+impl StorageBackend {
+    fn from_env_value(value: &str) -> Self {
+        match value.to_lowercase().as_str() {
+            "memory" => Self::Memory,
+            "file" => Self::File,
+            "sqlite" => Self::Sqlite,
+            "postgres" => Self::Postgres,
+            other => panic!(
+                "STORAGE_BACKEND must be 'memory', 'file', 'sqlite' or 'postgres', got '{}'",
+                other
+            ),
+        }
+    }
+}
+
+/// AUDIT_BACKEND: where the proxy audit trail is kept
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AuditBackend {
+    File,    // daily JSONL files under AUDIT_LOG_PATH
+    Sqlite,  // indexed table in AUDIT_DATABASE_PATH; no HMAC chaining
+}
+
+impl AuditBackend {
+    fn from_env_value(value: &str) -> Self {
+        match value.to_lowercase().as_str() {
+            "file" => Self::File,
+            "sqlite" => Self::Sqlite,
+            other => panic!("AUDIT_BACKEND must be 'file' or 'sqlite', got '{}'", other),
+        }
+    }
+}
+
+/// SESSION_STORE: where sessions live when not in the storage backend itself
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SessionStoreKind {
+    Memory,  // per-process; sessions vanish on restart and aren't shared
+    File,    // sessions.json (STORAGE_BACKEND=file only)
+    Redis,   // shared across replicas; requires the `redis` cargo feature
+}
+
+impl SessionStoreKind {
+    fn from_env_value(value: &str) -> Self {
+        match value.to_lowercase().as_str() {
+            "memory" => Self::Memory,
+            "file" => Self::File,
+            "redis" => Self::Redis,
+            other => panic!(
+                "SESSION_STORE must be 'memory', 'file' or 'redis', got '{}'",
+                other
+            ),
+        }
+    }
+}
 
 #[derive(Debug, Clone)]
 pub struct Settings {
@@ -10,15 +90,96 @@ pub struct Settings {
     pub encryption_key: String,
     #[allow(dead_code)]
     pub session_secret: String,  // For future JWT sessions
+    pub admin_api_key: Option<String>,  // X-Admin-Key; admin access disabled when unset
+
+    // Registration
+    pub require_email_verification: bool,  // POST /auth/register holds users until POST /auth/verify-email
+    pub email_verification_ttl_secs: u64,  // How long a verification code is valid
+    pub email_verification_return_code: bool,  // Stub: code in the register response (no email is sent yet)
 
     // Session management
     pub session_ttl_secs: u64,
+    pub max_sessions_per_agent: u32,  // Oldest live session revoked past this (0: no limit)
+    pub session_cleanup_interval_secs: u64,  // Background removal of expired sessions
     #[allow(dead_code)]
     pub token_refresh_buffer_secs: u64,
+    pub clock_skew_secs: u64,
+    pub token_refresh_cooldown_secs: u64,  // No new refresh of a service this soon after the last (0: none)
+
+    // Agents
+    pub allow_duplicate_names: bool,  // false: POST /auth/agent is idempotent per (user, agent name)
+    pub max_agent_lifespan_days: u32,  // Longest key lifespan accepted on creation and rotation
+    pub expiry_notification_days: i64,  // Notify when an agent key expires within this many days
+    pub expiry_webhook_url: Option<String>,  // Expiry notifications disabled when unset
+    pub expiry_webhook_format: ExpiryWebhookFormat,
+    pub expiry_check_interval_secs: u64,
+    pub startup_prune: bool,  // Remove long-expired agents and dangling references at startup
+    pub prune_grace_days: i64,  // How long past expiry an agent is kept before pruning
+
+    // Request limits
+    pub max_request_body_bytes: usize,  // Cap on (decompressed) proxied request bodies
+    pub max_response_body_bytes: usize,  // Default cap on upstream response bodies
+    pub injection_guard_mode: InjectionGuardMode,  // For services with enable_injection_guard
+    pub idempotency_cache_ttl_secs: u64,  // Replay window for X-Idempotency-Key (0 disables)
+
+    // Rate limiting
+    pub rate_limit_groups: HashMap<String, RateLimitConfig>,
+    pub user_rate_limit: RateLimitConfig,  // Aggregate over all agents of one user
+    pub ip_rate_limit: RateLimitConfig,  // Per client IP on /auth/register and POST /auth/agent
+    pub trusted_client_ip_header: Option<String>,  // Set by the reverse proxy (X-Forwarded-For); unset = peer address
+
+    // Logging
+    pub log_sample_rate: f64,  // Fraction of per-request info logs/spans kept
+    pub audit_log_path: Option<String>,  // Directory of daily proxy audit files (imported by the sqlite backend); unset/empty disables auditing
+    pub audit_backend: AuditBackend,
+    pub audit_database_path: String,  // SQLite file, used when audit_backend = sqlite
+    pub audit_retention: RetentionPolicy,  // Delete/gzip audit files older than this
+    pub audit_max_file_bytes: u64,  // Roll over to the day's next audit file past this size (0 disables)
+    pub audit_hmac_key: Option<String>,  // HMAC-chains audit entries; separate from ENCRYPTION_KEY
+    pub audit_writer: AuditWriterConfig,  // Queue and batching between requests and the audit files
+    pub audit_http_sink: Option<HttpAuditSinkConfig>,  // AUDIT_HTTP_ENDPOINT; unset disables
+    pub audit_syslog_addr: Option<String>,  // host:port of a UDP syslog collector; unset disables
+    pub admin_audit_log_path: Option<String>,  // JSONL trail of admin operations; unset/empty disables
+    pub alert_rules_path: Option<String>,  // Security alert rules (JSON); unset disables alerting
+
+    // Metrics
+    pub statsd_host: Option<String>,  // StatsD collector for per-request metrics; unset disables
+    pub statsd_port: u16,
+
+    // Error responses
+    pub error_format: ErrorFormat,
+
+    // Startup checks
+    pub validate_service_urls_on_startup: bool,
+
+    // Private IPs / CIDRs / hostnames services may point at (on-premise integrations)
+    pub ssrf_allowlist: Vec<String>,
+
+    // Storage
+    pub storage_backend: StorageBackend,
+    pub agents_flush_interval_secs: u64,  // File backend: batch agents.json writes
+    pub agents_flush_max_pending: u64,
+    pub file_io_timeout_secs: u64,  // File backend: deadline for each store file load/write
+    pub agents_file_encryption: bool,  // File backend: encrypt agents.json/sessions.json with encryption_key
+    pub session_cache_ttl_ms: u64,  // Reuse validate_session results this long (0 disables)
+    pub credentials_backup_count: usize,  // Timestamped credentials.json backups to keep (0 disables)
+    pub store_backup_count: usize,  // File backend: timestamped backups of each store file to keep
+    pub database_path: String,  // SQLite file, used when storage_backend = sqlite
+    #[allow(dead_code)]
+    pub database_url: Option<String>,  // Postgres URL, used when storage_backend = postgres
+    #[allow(dead_code)]
+    pub database_max_connections: u32,
+    pub session_store: Option<SessionStoreKind>,  // None: sessions live in storage_backend
+    #[allow(dead_code)]
+    pub redis_url: Option<String>,  // Used when session_store = redis
 
     // Paths
     pub services_config_path: String,
+    pub discovery_prefix: String,  // {prefix}_{ID}_SERVICE_URL env vars add services the file lacks
     pub credentials_path: String,
+    pub users_path: String,  // File backend (also imported by sqlite on first boot)
+    pub agents_path: String,
+    pub sessions_path: String,
 }
 
 impl Settings {
@@ -35,18 +196,277 @@ impl Settings {
                 .expect("ENCRYPTION_KEY must be set"),
             session_secret: env::var("SESSION_SECRET")
                 .expect("SESSION_SECRET must be set"),
+            admin_api_key: env::var("ADMIN_API_KEY").ok().filter(|k| !k.is_empty()),
+            require_email_verification: env::var("REQUIRE_EMAIL_VERIFICATION")
+                .map(|v| v == "true")
+                .unwrap_or(false),
+            email_verification_ttl_secs: env::var("EMAIL_VERIFICATION_TTL_SECS")
+                .unwrap_or_else(|_| "900".to_string())
+                .parse()
+                .expect("EMAIL_VERIFICATION_TTL_SECS must be a number"),
+            email_verification_return_code: env::var("EMAIL_VERIFICATION_RETURN_CODE")
+                .map(|v| v != "false")
+                .unwrap_or(true),
             session_ttl_secs: env::var("SESSION_TTL_SECS")
                 .unwrap_or_else(|_| "3600".to_string())
                 .parse()
                 .expect("SESSION_TTL_SECS must be a number"),
+            max_sessions_per_agent: env::var("MAX_SESSIONS_PER_AGENT")
+                .unwrap_or_else(|_| "10".to_string())
+                .parse()
+                .expect("MAX_SESSIONS_PER_AGENT must be a number"),
+            session_cleanup_interval_secs: env::var("SESSION_CLEANUP_INTERVAL_SECS")
+                .unwrap_or_else(|_| "3600".to_string())
+                .parse::<u64>()
+                .expect("SESSION_CLEANUP_INTERVAL_SECS must be a number")
+                .max(1),
             token_refresh_buffer_secs: env::var("TOKEN_REFRESH_BUFFER_SECS")
                 .unwrap_or_else(|_| "300".to_string())
                 .parse()
                 .expect("TOKEN_REFRESH_BUFFER_SECS must be a number"),
+            clock_skew_secs: env::var("CLOCK_SKEW_SECS")
+                .unwrap_or_else(|_| "30".to_string())
+                .parse()
+                .expect("CLOCK_SKEW_SECS must be a number"),
+            token_refresh_cooldown_secs: env::var("TOKEN_REFRESH_COOLDOWN_SECS")
+                .unwrap_or_else(|_| "0".to_string())
+                .parse()
+                .expect("TOKEN_REFRESH_COOLDOWN_SECS must be a number"),
+            allow_duplicate_names: env::var("ALLOW_DUPLICATE_AGENT_NAMES")
+                .map(|v| v == "true")
+                .unwrap_or(false),
+            max_agent_lifespan_days: env::var("MAX_AGENT_LIFESPAN_DAYS")
+                .unwrap_or_else(|_| "365".to_string())
+                .parse::<u32>()
+                .expect("MAX_AGENT_LIFESPAN_DAYS must be a number")
+                .max(1),
+            expiry_notification_days: env::var("EXPIRY_NOTIFICATION_DAYS")
+                .unwrap_or_else(|_| "7".to_string())
+                .parse()
+                .expect("EXPIRY_NOTIFICATION_DAYS must be a number"),
+            expiry_webhook_url: env::var("EXPIRY_WEBHOOK_URL").ok().filter(|u| !u.is_empty()),
+            expiry_webhook_format: ExpiryWebhookFormat::from_env_value(
+                &env::var("EXPIRY_WEBHOOK_FORMAT").unwrap_or_else(|_| "default".to_string()),
+            ),
+            expiry_check_interval_secs: env::var("EXPIRY_CHECK_INTERVAL_SECS")
+                .unwrap_or_else(|_| "3600".to_string())
+                .parse::<u64>()
+                .expect("EXPIRY_CHECK_INTERVAL_SECS must be a number")
+                .max(1),
+            startup_prune: env::var("STARTUP_PRUNE")
+                .map(|v| v == "true")
+                .unwrap_or(false),
+            prune_grace_days: env::var("PRUNE_GRACE_DAYS")
+                .unwrap_or_else(|_| "30".to_string())
+                .parse()
+                .expect("PRUNE_GRACE_DAYS must be a number"),
+            max_request_body_bytes: env::var("MAX_REQUEST_BODY_BYTES")
+                .unwrap_or_else(|_| "10485760".to_string())
+                .parse()
+                .expect("MAX_REQUEST_BODY_BYTES must be a number"),
+            max_response_body_bytes: env::var("MAX_RESPONSE_BODY_BYTES")
+                .unwrap_or_else(|_| "10485760".to_string())
+                .parse()
+                .expect("MAX_RESPONSE_BODY_BYTES must be a number"),
+            injection_guard_mode: InjectionGuardMode::from_env_value(
+                &env::var("INJECTION_GUARD_MODE").unwrap_or_else(|_| "block".to_string()),
+            ),
+            idempotency_cache_ttl_secs: env::var("IDEMPOTENCY_CACHE_TTL_SECS")
+                .unwrap_or_else(|_| "300".to_string())
+                .parse()
+                .expect("IDEMPOTENCY_CACHE_TTL_SECS must be a number"),
+            rate_limit_groups: load_rate_limit_groups(
+                &env::var("RATE_LIMITS_PATH")
+                    .unwrap_or_else(|_| "config/rate_limits.json".to_string()),
+            ),
+            user_rate_limit: RateLimitConfig {
+                requests: env::var("USER_RATE_LIMIT_REQUESTS")
+                    .unwrap_or_else(|_| "1000".to_string())
+                    .parse()
+                    .expect("USER_RATE_LIMIT_REQUESTS must be a number"),
+                window_secs: env::var("USER_RATE_LIMIT_WINDOW_SECS")
+                    .unwrap_or_else(|_| "60".to_string())
+                    .parse::<u64>()
+                    .expect("USER_RATE_LIMIT_WINDOW_SECS must be a number")
+                    .max(1),
+            },
+            ip_rate_limit: RateLimitConfig {
+                requests: env::var("IP_RATE_LIMIT_REQUESTS")
+                    .unwrap_or_else(|_| "20".to_string())
+                    .parse()
+                    .expect("IP_RATE_LIMIT_REQUESTS must be a number"),
+                window_secs: env::var("IP_RATE_LIMIT_WINDOW_SECS")
+                    .unwrap_or_else(|_| "60".to_string())
+                    .parse::<u64>()
+                    .expect("IP_RATE_LIMIT_WINDOW_SECS must be a number")
+                    .max(1),
+            },
+            trusted_client_ip_header: env::var("TRUSTED_CLIENT_IP_HEADER")
+                .ok()
+                .map(|h| h.trim().to_string())
+                .filter(|h| !h.is_empty()),
+            log_sample_rate: env::var("LOG_SAMPLE_RATE")
+                .unwrap_or_else(|_| "1.0".to_string())
+                .parse::<f64>()
+                .ok()
+                // NaN would survive the clamp and turn every request log off
+                .filter(|rate| rate.is_finite())
+                .expect("LOG_SAMPLE_RATE must be a finite number")
+                .clamp(0.0, 1.0),
+            audit_log_path: Some(env::var("AUDIT_LOG_PATH").unwrap_or_else(|_| "data/audit".to_string()))
+                .filter(|p| !p.trim().is_empty()),
+            audit_backend: AuditBackend::from_env_value(
+                &env::var("AUDIT_BACKEND").unwrap_or_else(|_| "file".to_string()),
+            ),
+            audit_database_path: env::var("AUDIT_DATABASE_PATH")
+                .unwrap_or_else(|_| "data/audit.db".to_string()),
+            audit_retention: RetentionPolicy {
+                // Unset or 0 keeps audit files forever
+                retention_days: env::var("AUDIT_RETENTION_DAYS")
+                    .ok()
+                    .map(|v| v.parse::<u32>().expect("AUDIT_RETENTION_DAYS must be a number"))
+                    .filter(|&days| days > 0),
+                compress_after_days: env::var("AUDIT_COMPRESS_AFTER_DAYS")
+                    .ok()
+                    .map(|v| v.parse::<u32>().expect("AUDIT_COMPRESS_AFTER_DAYS must be a number")),
+            },
+            audit_max_file_bytes: env::var("AUDIT_MAX_FILE_BYTES")
+                .map(|v| v.parse().expect("AUDIT_MAX_FILE_BYTES must be a number"))
+                .unwrap_or(DEFAULT_AUDIT_MAX_FILE_BYTES),
+            audit_hmac_key: env::var("AUDIT_HMAC_KEY").ok().filter(|k| !k.is_empty()),
+            audit_writer: AuditWriterConfig {
+                capacity: env::var("AUDIT_QUEUE_CAPACITY")
+                    .unwrap_or_else(|_| "10000".to_string())
+                    .parse::<usize>()
+                    .expect("AUDIT_QUEUE_CAPACITY must be a number")
+                    .max(1),
+                batch_size: env::var("AUDIT_BATCH_SIZE")
+                    .unwrap_or_else(|_| "256".to_string())
+                    .parse::<usize>()
+                    .expect("AUDIT_BATCH_SIZE must be a number")
+                    .max(1),
+                flush_interval: std::time::Duration::from_millis(
+                    env::var("AUDIT_FLUSH_INTERVAL_MS")
+                        .unwrap_or_else(|_| "200".to_string())
+                        .parse()
+                        .expect("AUDIT_FLUSH_INTERVAL_MS must be a number"),
+                ),
+                overflow: AuditOverflowPolicy::from_env_value(
+                    &env::var("AUDIT_OVERFLOW_POLICY").unwrap_or_else(|_| "block".to_string()),
+                ),
+            },
+            audit_http_sink: env::var("AUDIT_HTTP_ENDPOINT")
+                .ok()
+                .filter(|url| !url.trim().is_empty())
+                .map(|endpoint| HttpAuditSinkConfig {
+                    endpoint,
+                    auth_header: env::var("AUDIT_HTTP_AUTH_HEADER")
+                        .ok()
+                        .filter(|h| !h.trim().is_empty())
+                        .map(|h| {
+                            let (name, value) = h
+                                .split_once(':')
+                                .expect("AUDIT_HTTP_AUTH_HEADER must be 'Name: value'");
+                            (name.trim().to_string(), value.trim().to_string())
+                        }),
+                    batch_size: env::var("AUDIT_HTTP_BATCH_SIZE")
+                        .unwrap_or_else(|_| "100".to_string())
+                        .parse::<usize>()
+                        .expect("AUDIT_HTTP_BATCH_SIZE must be a number")
+                        .max(1),
+                    max_retries: env::var("AUDIT_HTTP_MAX_RETRIES")
+                        .unwrap_or_else(|_| "3".to_string())
+                        .parse()
+                        .expect("AUDIT_HTTP_MAX_RETRIES must be a number"),
+                    retry_backoff: std::time::Duration::from_millis(
+                        env::var("AUDIT_HTTP_RETRY_BACKOFF_MS")
+                            .unwrap_or_else(|_| "500".to_string())
+                            .parse()
+                            .expect("AUDIT_HTTP_RETRY_BACKOFF_MS must be a number"),
+                    ),
+                    spool_path: Some(
+                        env::var("AUDIT_HTTP_SPOOL_PATH").unwrap_or_else(|_| "data/audit_http_spool.jsonl".to_string()),
+                    )
+                    .filter(|p| !p.trim().is_empty())
+                    .map(std::path::PathBuf::from),
+                }),
+            audit_syslog_addr: env::var("AUDIT_SYSLOG_ADDR").ok().filter(|a| !a.trim().is_empty()),
+            admin_audit_log_path: Some(
+                env::var("ADMIN_AUDIT_LOG_PATH").unwrap_or_else(|_| "data/admin_audit.jsonl".to_string()),
+            )
+            .filter(|p| !p.trim().is_empty()),
+            alert_rules_path: env::var("ALERT_RULES_PATH").ok().filter(|p| !p.trim().is_empty()),
+            statsd_host: env::var("STATSD_HOST").ok().filter(|h| !h.trim().is_empty()),
+            statsd_port: env::var("STATSD_PORT")
+                .unwrap_or_else(|_| "8125".to_string())
+                .parse()
+                .expect("STATSD_PORT must be a port number"),
+            error_format: ErrorFormat::from_env_value(
+                &env::var("ERROR_FORMAT").unwrap_or_else(|_| "default".to_string()),
+            ),
+            validate_service_urls_on_startup: env::var("VALIDATE_SERVICE_URLS_ON_STARTUP")
+                .map(|v| v == "true")
+                .unwrap_or(false),
+            ssrf_allowlist: env::var("SSRF_PROTECTION_ALLOWLIST")
+                .map(|v| v.split(',').map(|s| s.trim().to_string()).filter(|s| !s.is_empty()).collect())
+                .unwrap_or_default(),
+            storage_backend: StorageBackend::from_env_value(
+                &env::var("STORAGE_BACKEND").unwrap_or_else(|_| "file".to_string()),
+            ),
+            agents_flush_interval_secs: env::var("AGENTS_FLUSH_INTERVAL_SECS")
+                .unwrap_or_else(|_| "5".to_string())
+                .parse()
+                .expect("AGENTS_FLUSH_INTERVAL_SECS must be a number"),
+            agents_flush_max_pending: env::var("AGENTS_FLUSH_MAX_PENDING")
+                .unwrap_or_else(|_| "100".to_string())
+                .parse()
+                .expect("AGENTS_FLUSH_MAX_PENDING must be a number"),
+            file_io_timeout_secs: env::var("FILE_IO_TIMEOUT_SECS")
+                .unwrap_or_else(|_| "5".to_string())
+                .parse::<u64>()
+                .expect("FILE_IO_TIMEOUT_SECS must be a number")
+                .max(1),
+            agents_file_encryption: env::var("AGENTS_FILE_ENCRYPTION")
+                .map(|v| v == "true")
+                .unwrap_or(false),
+            session_cache_ttl_ms: env::var("SESSION_CACHE_TTL_MS")
+                .unwrap_or_else(|_| "1000".to_string())
+                .parse()
+                .expect("SESSION_CACHE_TTL_MS must be a number"),
+            credentials_backup_count: env::var("CREDENTIALS_BACKUP_COUNT")
+                .unwrap_or_else(|_| "5".to_string())
+                .parse()
+                .expect("CREDENTIALS_BACKUP_COUNT must be a number"),
+            store_backup_count: env::var("STORE_BACKUP_COUNT")
+                .unwrap_or_else(|_| "5".to_string())
+                .parse()
+                .expect("STORE_BACKUP_COUNT must be a number"),
+            database_path: env::var("DATABASE_PATH")
+                .unwrap_or_else(|_| "data/gateway.db".to_string()),
+            database_url: env::var("DATABASE_URL").ok().filter(|u| !u.is_empty()),
+            database_max_connections: env::var("DATABASE_MAX_CONNECTIONS")
+                .unwrap_or_else(|_| "10".to_string())
+                .parse()
+                .expect("DATABASE_MAX_CONNECTIONS must be a number"),
+            session_store: env::var("SESSION_STORE")
+                .ok()
+                .filter(|v| !v.is_empty())
+                .map(|v| SessionStoreKind::from_env_value(&v)),
+            redis_url: env::var("REDIS_URL").ok().filter(|u| !u.is_empty()),
             services_config_path: env::var("SERVICES_CONFIG_PATH")
                 .unwrap_or_else(|_| "config/services.json".to_string()),
+            discovery_prefix: env::var("DISCOVERY_PREFIX")
+                .ok()
+                .filter(|p| !p.trim().is_empty())
+                .unwrap_or_else(|| "GATEWAY".to_string()),
             credentials_path: env::var("CREDENTIALS_PATH")
                 .unwrap_or_else(|_| "data/credentials.json".to_string()),
+            users_path: env::var("USERS_PATH")
+                .unwrap_or_else(|_| "data/users.json".to_string()),
+            agents_path: env::var("AGENTS_PATH")
+                .unwrap_or_else(|_| "data/agents.json".to_string()),
+            sessions_path: env::var("SESSIONS_PATH")
+                .unwrap_or_else(|_| "data/sessions.json".to_string()),
         }
     }
 
@@ -54,3 +474,13 @@ impl Settings {
         format!("{}:{}", self.host, self.port)
     }
 }
+
+/// Load shared rate limit groups; a missing file means no groups
+fn load_rate_limit_groups(path: &str) -> HashMap<String, RateLimitConfig> {
+    match fs::read_to_string(path) {
+        Ok(content) => serde_json::from_str::<RateLimitsFile>(&content)
+            .unwrap_or_else(|e| panic!("Failed to parse {}: {}", path, e))
+            .groups,
+        Err(_) => HashMap::new(),
+    }
+}
@@ -0,0 +1,135 @@
+// === Wire format for error responses (ERROR_FORMAT) ===
+
+use axum::{
+    http::{header, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde_json::{json, Value};
+use std::sync::atomic::{AtomicU8, Ordering};
+
+use crate::audit::current_request_id;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorFormat {
+    /// `{ "error": "type", "message": "..." }`
+    DefaultJson,
+    /// `{ "error": { "message": "...", "type": "...", "code": null } }`
+    OpenAiStyle,
+    /// Bare message as `text/plain`
+    PlainText,
+}
+
+// Process-wide so `IntoResponse` (which has no access to state) can see it
+static ERROR_FORMAT: AtomicU8 = AtomicU8::new(ErrorFormat::DefaultJson as u8);
+
+/// Set the format used by every `GatewayError` response
+pub fn set_error_format(format: ErrorFormat) {
+    ERROR_FORMAT.store(format as u8, Ordering::Relaxed);
+}
+
+pub fn error_format() -> ErrorFormat {
+    match ERROR_FORMAT.load(Ordering::Relaxed) {
+        x if x == ErrorFormat::OpenAiStyle as u8 => ErrorFormat::OpenAiStyle,
+        x if x == ErrorFormat::PlainText as u8 => ErrorFormat::PlainText,
+        _ => ErrorFormat::DefaultJson,
+    }
+}
+
+impl ErrorFormat {
+    pub fn from_env_value(value: &str) -> Self {
+        match value.to_lowercase().as_str() {
+            "default" | "default_json" | "json" => Self::DefaultJson,
+            "openai" | "openai_style" => Self::OpenAiStyle,
+            "plain" | "plain_text" | "text" => Self::PlainText,
+            other => panic!(
+                "ERROR_FORMAT must be 'default', 'openai' or 'plain', got '{}'",
+                other
+            ),
+        }
+    }
+
+    /// JSON formats also carry the ID of the request being handled, when there is one
+    pub fn render(self, status: StatusCode, error_type: &str, message: &str) -> Response {
+        let with_request_id = |mut body: Value| {
+            if let Some(id) = current_request_id() {
+                body["request_id"] = Value::String(id);
+            }
+            Json(body)
+        };
+        match self {
+            ErrorFormat::DefaultJson => (
+                status,
+                with_request_id(json!({
+                    "error": error_type,
+                    "message": message,
+                })),
+            )
+                .into_response(),
+            ErrorFormat::OpenAiStyle => (
+                status,
+                with_request_id(json!({
+                    "error": {
+                        "message": message,
+                        "type": error_type,
+                        "code": null,
+                    }
+                })),
+            )
+                .into_response(),
+            ErrorFormat::PlainText => (
+                status,
+                [(header::CONTENT_TYPE, "text/plain; charset=utf-8")],
+                message.to_string(),
+            )
+                .into_response(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn body(response: Response) -> Vec<u8> {
+        axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap()
+            .to_vec()
+    }
+
+    #[tokio::test]
+    async fn test_openai_style_shape() {
+        let response = ErrorFormat::OpenAiStyle.render(
+            StatusCode::TOO_MANY_REQUESTS,
+            "rate_limit_exceeded",
+            "Rate limit exceeded",
+        );
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+
+        let json: Value = serde_json::from_slice(&body(response).await).unwrap();
+        assert_eq!(
+            json,
+            json!({ "error": { "message": "Rate limit exceeded", "type": "rate_limit_exceeded", "code": null } })
+        );
+    }
+
+    #[tokio::test]
+    async fn test_plain_text_shape() {
+        let response =
+            ErrorFormat::PlainText.render(StatusCode::UNAUTHORIZED, "unauthorized", "Invalid session");
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        assert_eq!(
+            response.headers()[header::CONTENT_TYPE],
+            "text/plain; charset=utf-8"
+        );
+        assert_eq!(body(response).await, b"Invalid session");
+    }
+
+    #[tokio::test]
+    async fn test_default_shape() {
+        let response = ErrorFormat::DefaultJson.render(StatusCode::NOT_FOUND, "not_found", "nope");
+        let json: Value = serde_json::from_slice(&body(response).await).unwrap();
+        assert_eq!(json, json!({ "error": "not_found", "message": "nope" }));
+    }
+}
@@ -1,3 +1,5 @@
+mod format;
 mod types;
 
+pub use format::*;
 pub use types::*;
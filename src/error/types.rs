@@ -1,9 +1,33 @@
 use axum::{
     http::StatusCode,
     response::{IntoResponse, Response},
-    Json,
 };
-use serde_json::json;
+
+use super::format::error_format;
+
+/// The quota that turned a request away
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RateLimitScope {
+    User,    // All agents of one user together
+    Agent,
+    Group,   // Agents sharing a rate_limit_group
+    Service,
+    Ip,      // Pre-auth endpoints, per client address
+    Concurrency,  // Requests one agent has in flight at once
+}
+
+impl RateLimitScope {
+    fn message(self) -> &'static str {
+        match self {
+            RateLimitScope::User => "User rate limit exceeded",
+            RateLimitScope::Agent => "Agent rate limit exceeded",
+            RateLimitScope::Group => "Group rate limit exceeded",
+            RateLimitScope::Service => "Service rate limit exceeded",
+            RateLimitScope::Ip => "IP rate limit exceeded",
+            RateLimitScope::Concurrency => "Agent concurrent request limit exceeded: too many requests in flight",
+        }
+    }
+}
 
 #[derive(Debug)]
 pub enum GatewayError {
@@ -12,21 +36,23 @@ pub enum GatewayError {
     SessionExpired,
     TokenError(String),
 
-    // Access errors (Forbidden for future authorization)
-    #[allow(dead_code)]
+    // Access errors
     Forbidden(String),
     ServiceNotAllowed(String),
-    RateLimitExceeded,
+    RateLimitExceeded(RateLimitScope),
 
     // Request errors
     BadRequest(String),
+    PayloadTooLarge(String),
+    Conflict(String),  // Record changed since it was read (optimistic concurrency)
     #[allow(dead_code)]
     ReplayDetected,
 
     // Proxy errors
     UpstreamError(String),
+    UpstreamTimeout(String),
+    UpstreamUnavailable(String),
     CredentialNotFound(String),
-    #[allow(dead_code)]
     TokenRefreshFailed(String),
 
     // Internal errors
@@ -34,9 +60,10 @@ pub enum GatewayError {
     NotFound(String),
 }
 
-impl IntoResponse for GatewayError {
-    fn into_response(self) -> Response {
-        let (status, error_type, message) = match self {
+impl GatewayError {
+    /// HTTP status, stable machine-readable type, and human-readable message
+    pub fn parts(self) -> (StatusCode, &'static str, String) {
+        match self {
             GatewayError::Unauthorized(msg) => (StatusCode::UNAUTHORIZED, "unauthorized", msg),
             GatewayError::SessionExpired => {
                 (StatusCode::UNAUTHORIZED, "session_expired", "Session has expired".to_string())
@@ -46,16 +73,26 @@ impl IntoResponse for GatewayError {
             GatewayError::ServiceNotAllowed(svc) => {
                 (StatusCode::FORBIDDEN, "service_not_allowed", format!("Access to {} not permitted", svc))
             }
-            GatewayError::RateLimitExceeded => {
-                (StatusCode::TOO_MANY_REQUESTS, "rate_limit_exceeded", "Rate limit exceeded".to_string())
+            GatewayError::RateLimitExceeded(scope) => {
+                (StatusCode::TOO_MANY_REQUESTS, "rate_limit_exceeded", scope.message().to_string())
             }
             GatewayError::BadRequest(msg) => (StatusCode::BAD_REQUEST, "bad_request", msg),
+            GatewayError::PayloadTooLarge(msg) => {
+                (StatusCode::PAYLOAD_TOO_LARGE, "payload_too_large", msg)
+            }
+            GatewayError::Conflict(msg) => (StatusCode::CONFLICT, "conflict", msg),
             GatewayError::ReplayDetected => {
                 (StatusCode::BAD_REQUEST, "replay_detected", "Replay attack detected".to_string())
             }
             GatewayError::UpstreamError(msg) => {
                 (StatusCode::BAD_GATEWAY, "upstream_error", msg)
             }
+            GatewayError::UpstreamTimeout(msg) => {
+                (StatusCode::GATEWAY_TIMEOUT, "upstream_timeout", msg)
+            }
+            GatewayError::UpstreamUnavailable(msg) => {
+                (StatusCode::SERVICE_UNAVAILABLE, "upstream_unavailable", msg)
+            }
             GatewayError::CredentialNotFound(svc) => {
                 (StatusCode::NOT_FOUND, "credential_not_found", format!("No credentials for {}", svc))
             }
@@ -66,13 +103,13 @@ impl IntoResponse for GatewayError {
                 (StatusCode::INTERNAL_SERVER_ERROR, "internal_error", msg)
             }
             GatewayError::NotFound(msg) => (StatusCode::NOT_FOUND, "not_found", msg),
-        };
-
-        let body = Json(json!({
-            "error": error_type,
-            "message": message,
-        }));
+        }
+    }
+}
 
-        (status, body).into_response()
+impl IntoResponse for GatewayError {
+    fn into_response(self) -> Response {
+        let (status, error_type, message) = self.parts();
+        error_format().render(status, error_type, &message)
     }
 }
@@ -0,0 +1,88 @@
+// === Per-agent cap on requests in flight (max_concurrent_requests) ===
+// The rate limiter counts requests per window; an agent opening hundreds of
+// connections at once can still stay under it while swamping the upstream.
+// Each limited agent gets a semaphore, created on its first request; a
+// request that finds every permit taken is turned away rather than queued.
+
+use dashmap::mapref::entry::Entry;
+use dashmap::DashMap;
+use std::sync::Arc;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+use uuid::Uuid;
+
+use crate::error::{GatewayError, RateLimitScope};
+
+#[derive(Clone, Default)]
+pub struct ConcurrencyLimiter {
+    // Agent id -> (limit the semaphore was made for, semaphore)
+    semaphores: Arc<DashMap<Uuid, (u32, Arc<Semaphore>)>>,
+}
+
+impl ConcurrencyLimiter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A permit to hold until the upstream has answered; `None` when the
+    /// agent has no limit. A changed limit takes a fresh semaphore, so
+    /// requests already in flight under the old one aren't counted.
+    pub fn acquire(&self, agent_id: Uuid, limit: Option<u32>) -> Result<Option<OwnedSemaphorePermit>, GatewayError> {
+        let Some(limit) = limit else {
+            // A lifted limit leaves nothing behind; most agents never had one
+            if self.semaphores.contains_key(&agent_id) {
+                self.semaphores.remove(&agent_id);
+            }
+            return Ok(None);
+        };
+        let semaphore = match self.semaphores.entry(agent_id) {
+            Entry::Occupied(entry) if entry.get().0 == limit => entry.get().1.clone(),
+            entry => {
+                let semaphore = Arc::new(Semaphore::new(limit as usize));
+                entry.insert((limit, semaphore.clone()));
+                semaphore
+            }
+        };
+        semaphore
+            .try_acquire_owned()
+            .map(Some)
+            .map_err(|_| GatewayError::RateLimitExceeded(RateLimitScope::Concurrency))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_permits_are_capped_and_returned_on_drop() {
+        let limiter = ConcurrencyLimiter::new();
+        let agent = Uuid::new_v4();
+        let first = limiter.acquire(agent, Some(2)).unwrap();
+        let second = limiter.acquire(agent, Some(2)).unwrap();
+        assert!(first.is_some() && second.is_some());
+        assert!(matches!(
+            limiter.acquire(agent, Some(2)),
+            Err(GatewayError::RateLimitExceeded(RateLimitScope::Concurrency))
+        ));
+        // Other agents have their own permits
+        assert!(limiter.acquire(Uuid::new_v4(), Some(2)).is_ok());
+
+        drop(first);
+        assert!(limiter.acquire(agent, Some(2)).is_ok());
+    }
+
+    #[test]
+    fn test_no_limit_and_changed_limits() {
+        let limiter = ConcurrencyLimiter::new();
+        let agent = Uuid::new_v4();
+        assert!(limiter.acquire(agent, None).unwrap().is_none());
+        assert!(limiter.semaphores.is_empty());
+
+        let _held = limiter.acquire(agent, Some(1)).unwrap();
+        assert!(limiter.acquire(agent, Some(1)).is_err());
+        // Raising the limit starts from a fresh semaphore
+        let _more = limiter.acquire(agent, Some(2)).unwrap();
+        assert!(limiter.acquire(agent, None).unwrap().is_none());
+        assert!(limiter.semaphores.is_empty());
+    }
+}
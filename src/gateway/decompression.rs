@@ -0,0 +1,159 @@
+// === Request and upstream response body decompression (Content-Encoding) ===
+
+use axum::body::Bytes;
+use axum::http::{header, HeaderMap};
+use flate2::read::{GzDecoder, ZlibDecoder};
+use std::io::Read;
+
+use crate::error::GatewayError;
+
+/// `Accept-Encoding` sent upstream by services with `accept_compressed_response`:
+/// exactly what `decode_response_body` can undo
+pub const UPSTREAM_ACCEPT_ENCODING: &str = "gzip, deflate";
+
+/// Decode `body` per its `Content-Encoding`, refusing output larger than `max_bytes`.
+/// Returns the body unchanged when there is no encoding (or `identity`).
+pub fn decode_request_body(
+    headers: &HeaderMap,
+    body: Bytes,
+    max_bytes: usize,
+) -> Result<Bytes, GatewayError> {
+    let encoding = headers
+        .get(header::CONTENT_ENCODING)
+        .and_then(|v| v.to_str().ok())
+        .map(|v| v.trim().to_lowercase());
+
+    let decoded = match encoding.as_deref() {
+        None | Some("") | Some("identity") => Ok(Some(body)),
+        Some("gzip") | Some("x-gzip") => read_limited(GzDecoder::new(&body[..]), max_bytes),
+        Some("deflate") => read_limited(ZlibDecoder::new(&body[..]), max_bytes),
+        Some("br") => read_limited(brotli::Decompressor::new(&body[..], 4096), max_bytes),
+        Some(other) => {
+            return Err(GatewayError::BadRequest(format!(
+                "Unsupported Content-Encoding '{}'",
+                other
+            )))
+        }
+    }
+    .map_err(|_| GatewayError::BadRequest(format!("Invalid {} body", encoding.unwrap_or_default())))?;
+
+    match decoded {
+        Some(decoded) if decoded.len() <= max_bytes => Ok(decoded),
+        _ => Err(too_large(max_bytes)),
+    }
+}
+
+/// Decode an upstream response body with `encoding` (its lowercased `Content-Encoding`),
+/// refusing output larger than `max_bytes` so a small compressed body can't expand
+/// past the response limit.
+pub fn decode_response_body(
+    encoding: Option<&str>,
+    body: Bytes,
+    max_bytes: usize,
+) -> Result<Bytes, GatewayError> {
+    let decoded = match encoding {
+        None | Some("") | Some("identity") => return Ok(body),
+        Some("gzip") | Some("x-gzip") => read_limited(GzDecoder::new(&body[..]), max_bytes),
+        Some("deflate") => read_limited(ZlibDecoder::new(&body[..]), max_bytes),
+        // Not in UPSTREAM_ACCEPT_ENCODING, so the upstream shouldn't have used it
+        Some(other) => {
+            return Err(GatewayError::UpstreamError(format!(
+                "Unsupported response Content-Encoding '{}'",
+                other
+            )))
+        }
+    }
+    .map_err(|_| GatewayError::UpstreamError(format!("Invalid {} response", encoding.unwrap_or_default())))?;
+
+    decoded.ok_or_else(|| GatewayError::UpstreamError("Decompressed response too large".to_string()))
+}
+
+// === Read at most max_bytes + 1 so a zip bomb can't exhaust memory ===
+// `None` when the output would pass `max_bytes`.
+fn read_limited<R: Read>(reader: R, max_bytes: usize) -> std::io::Result<Option<Bytes>> {
+    let mut out = Vec::new();
+    reader.take(max_bytes as u64 + 1).read_to_end(&mut out)?;
+
+    if out.len() > max_bytes {
+        return Ok(None);
+    }
+    Ok(Some(Bytes::from(out)))
+}
+
+fn too_large(max_bytes: usize) -> GatewayError {
+    GatewayError::PayloadTooLarge(format!(
+        "Request body exceeds {} bytes (MAX_REQUEST_BODY_BYTES)",
+        max_bytes
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::HeaderValue;
+    use flate2::write::{GzEncoder, ZlibEncoder};
+    use flate2::Compression;
+    use std::io::Write;
+
+    const PAYLOAD: &[u8] = br#"{"prompt":"hello hello hello hello"}"#;
+
+    fn headers(encoding: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(header::CONTENT_ENCODING, HeaderValue::from_str(encoding).unwrap());
+        headers
+    }
+
+    fn gzip(data: &[u8]) -> Bytes {
+        let mut enc = GzEncoder::new(Vec::new(), Compression::default());
+        enc.write_all(data).unwrap();
+        Bytes::from(enc.finish().unwrap())
+    }
+
+    #[test]
+    fn test_decodes_each_encoding() {
+        assert_eq!(decode_request_body(&headers("gzip"), gzip(PAYLOAD), 1024).unwrap(), PAYLOAD);
+
+        let mut zlib = ZlibEncoder::new(Vec::new(), Compression::default());
+        zlib.write_all(PAYLOAD).unwrap();
+        let deflated = Bytes::from(zlib.finish().unwrap());
+        assert_eq!(decode_request_body(&headers("deflate"), deflated, 1024).unwrap(), PAYLOAD);
+
+        let mut br = Vec::new();
+        brotli::CompressorWriter::new(&mut br, 4096, 5, 22)
+            .write_all(PAYLOAD)
+            .unwrap();
+        assert_eq!(decode_request_body(&headers("br"), Bytes::from(br), 1024).unwrap(), PAYLOAD);
+    }
+
+    #[test]
+    fn test_plain_body_passes_through() {
+        let body = Bytes::from_static(PAYLOAD);
+        assert_eq!(decode_request_body(&HeaderMap::new(), body, 1024).unwrap(), PAYLOAD);
+    }
+
+    #[test]
+    fn test_invalid_gzip_is_bad_request() {
+        let result = decode_request_body(&headers("gzip"), Bytes::from_static(b"not gzip"), 1024);
+        assert!(matches!(result, Err(GatewayError::BadRequest(msg)) if msg == "Invalid gzip body"));
+    }
+
+    #[test]
+    fn test_decompressed_size_is_capped() {
+        let bomb = gzip(&vec![b'a'; 10_000]);
+        let result = decode_request_body(&headers("gzip"), bomb, 1_000);
+        assert!(matches!(result, Err(GatewayError::PayloadTooLarge(_))));
+    }
+
+    #[test]
+    fn test_response_decoding_is_capped() {
+        assert_eq!(decode_response_body(Some("gzip"), gzip(PAYLOAD), 1024).unwrap(), PAYLOAD);
+        assert_eq!(decode_response_body(None, Bytes::from_static(PAYLOAD), 1024).unwrap(), PAYLOAD);
+
+        let bomb = gzip(&vec![b'a'; 10_000]);
+        let result = decode_response_body(Some("gzip"), bomb, 1_000);
+        assert!(matches!(result, Err(GatewayError::UpstreamError(msg)) if msg == "Decompressed response too large"));
+
+        let result = decode_response_body(Some("br"), Bytes::from_static(PAYLOAD), 1024);
+        assert!(matches!(result, Err(GatewayError::UpstreamError(_))));
+    }
+}
@@ -0,0 +1,246 @@
+// === Response envelopes (services with `wrap_response`) ===
+// Agent SDKs that expect one response shape whatever the upstream returns get
+// `{ "success": true, "data": <upstream body>, "meta": { ... } }`, or
+// `{ "success": false, "error": { ... }, "meta": { ... } }` for gateway errors
+// and upstream error statuses. The HTTP status is left as it was.
+//
+// `response_envelope_template` replaces that shape with any JSON in which
+// string values of the form "{{name}}" are replaced by: success, data, error,
+// meta, status, service_id, request_id, latency_ms, cache_hit.
+
+use serde_json::{json, Map, Value};
+use std::collections::HashMap;
+
+use crate::config::{ServiceConfig, ServiceRegistry};
+use crate::error::GatewayError;
+
+const PLACEHOLDERS: [&str; 9] = [
+    "success",
+    "data",
+    "error",
+    "meta",
+    "status",
+    "service_id",
+    "request_id",
+    "latency_ms",
+    "cache_hit",
+];
+
+/// What goes in `meta`
+#[derive(Debug, Clone, Default)]
+pub struct EnvelopeMeta {
+    pub service_id: String,
+    pub request_id: Option<String>,
+    pub latency_ms: u64,
+    pub cache_hit: bool,
+}
+
+impl EnvelopeMeta {
+    fn to_json(&self) -> Value {
+        json!({
+            "service_id": self.service_id,
+            "request_id": self.request_id,
+            "latency_ms": self.latency_ms,
+            "cache_hit": self.cache_hit,
+        })
+    }
+}
+
+pub struct ResponseEnvelope {
+    // `None`: the default shape
+    template: Option<Value>,
+}
+
+impl ResponseEnvelope {
+    /// `None` when `service` doesn't set `wrap_response`
+    pub fn for_service(service: &ServiceConfig) -> Result<Option<Self>, GatewayError> {
+        if !service.wrap_response {
+            return Ok(None);
+        }
+        let template = service
+            .response_envelope_template
+            .as_deref()
+            .map(|template| {
+                let template: Value = serde_json::from_str(template).map_err(|e| {
+                    GatewayError::Internal(format!(
+                        "Service '{}' has an invalid response_envelope_template: {}",
+                        service.id, e
+                    ))
+                })?;
+                check_placeholders(&template).map_err(|name| {
+                    GatewayError::Internal(format!(
+                        "Service '{}' response_envelope_template has an unknown placeholder {{{{{}}}}}",
+                        service.id, name
+                    ))
+                })?;
+                Ok::<_, GatewayError>(template)
+            })
+            .transpose()?;
+        Ok(Some(Self { template }))
+    }
+
+    /// Wrap an upstream answer; 4xx/5xx bodies go in `error`
+    pub fn wrap(&self, status: u16, body: Value, meta: &EnvelopeMeta) -> Value {
+        if (200..400).contains(&status) {
+            self.render(status, Some(body), None, meta)
+        } else {
+            self.render(status, None, Some(body), meta)
+        }
+    }
+
+    /// Wrap an error raised by the gateway itself
+    pub fn wrap_error(&self, status: u16, error_type: &str, message: &str, meta: &EnvelopeMeta) -> Value {
+        let error = json!({ "type": error_type, "message": message });
+        self.render(status, None, Some(error), meta)
+    }
+
+    fn render(&self, status: u16, data: Option<Value>, error: Option<Value>, meta: &EnvelopeMeta) -> Value {
+        let success = error.is_none();
+        let Some(template) = &self.template else {
+            let mut envelope = Map::new();
+            envelope.insert("success".to_string(), Value::Bool(success));
+            match (data, error) {
+                (_, Some(error)) => envelope.insert("error".to_string(), error),
+                (data, None) => envelope.insert("data".to_string(), data.unwrap_or(Value::Null)),
+            };
+            envelope.insert("meta".to_string(), meta.to_json());
+            return Value::Object(envelope);
+        };
+        let values = HashMap::from([
+            ("success", Value::Bool(success)),
+            ("data", data.unwrap_or(Value::Null)),
+            ("error", error.unwrap_or(Value::Null)),
+            ("meta", meta.to_json()),
+            ("status", json!(status)),
+            ("service_id", json!(meta.service_id)),
+            ("request_id", json!(meta.request_id)),
+            ("latency_ms", json!(meta.latency_ms)),
+            ("cache_hit", json!(meta.cache_hit)),
+        ]);
+        fill(template, &values)
+    }
+}
+
+/// "{{name}}" -> `name`
+fn placeholder(value: &str) -> Option<&str> {
+    value.strip_prefix("{{")?.strip_suffix("}}").map(str::trim)
+}
+
+/// The first placeholder that isn't one of `PLACEHOLDERS`
+fn check_placeholders(template: &Value) -> Result<(), String> {
+    match template {
+        Value::String(s) => match placeholder(s) {
+            Some(name) if !PLACEHOLDERS.contains(&name) => Err(name.to_string()),
+            _ => Ok(()),
+        },
+        Value::Array(items) => items.iter().try_for_each(check_placeholders),
+        Value::Object(fields) => fields.values().try_for_each(check_placeholders),
+        _ => Ok(()),
+    }
+}
+
+fn fill(template: &Value, values: &HashMap<&str, Value>) -> Value {
+    match template {
+        Value::String(s) => match placeholder(s).and_then(|name| values.get(name)) {
+            Some(value) => value.clone(),
+            None => template.clone(),
+        },
+        Value::Array(items) => Value::Array(items.iter().map(|item| fill(item, values)).collect()),
+        Value::Object(fields) => Value::Object(
+            fields
+                .iter()
+                .map(|(key, value)| (key.clone(), fill(value, values)))
+                .collect(),
+        ),
+        other => other.clone(),
+    }
+}
+
+/// Envelopes for the services with `wrap_response`; a bad template fails startup
+pub fn build_response_envelopes(
+    services: &ServiceRegistry,
+) -> Result<HashMap<String, ResponseEnvelope>, GatewayError> {
+    let mut envelopes = HashMap::new();
+    for service in services.list() {
+        if let Some(envelope) = ResponseEnvelope::for_service(service)? {
+            envelopes.insert(service.id.clone(), envelope);
+        }
+    }
+    Ok(envelopes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn envelope(template: Option<&str>) -> Result<Option<ResponseEnvelope>, GatewayError> {
+        let mut service: ServiceConfig = serde_json::from_value(json!({
+            "id": "payment",
+            "name": "Payment",
+            "description": "",
+            "base_url": "https://api.example.com",
+            "auth_type": "bearer_token",
+            "endpoints": [],
+            "rate_limit": { "requests": 10, "window_secs": 60 },
+            "wrap_response": true
+        }))
+        .unwrap();
+        service.response_envelope_template = template.map(str::to_string);
+        ResponseEnvelope::for_service(&service)
+    }
+
+    fn meta() -> EnvelopeMeta {
+        EnvelopeMeta {
+            service_id: "payment".to_string(),
+            request_id: Some("req-1".to_string()),
+            latency_ms: 42,
+            cache_hit: false,
+        }
+    }
+
+    #[test]
+    fn test_default_shape() {
+        let envelope = envelope(None).unwrap().unwrap();
+        let wrapped = envelope.wrap(200, json!({"balance": 10}), &meta());
+        assert_eq!(
+            wrapped,
+            json!({
+                "success": true,
+                "data": {"balance": 10},
+                "meta": {"service_id": "payment", "request_id": "req-1", "latency_ms": 42, "cache_hit": false}
+            })
+        );
+
+        let failed = envelope.wrap(404, json!({"detail": "missing"}), &meta());
+        assert_eq!(failed["success"], false);
+        assert_eq!(failed["error"], json!({"detail": "missing"}));
+        assert!(failed.get("data").is_none());
+
+        let denied = envelope.wrap_error(429, "rate_limit_exceeded", "Agent rate limit exceeded", &meta());
+        assert_eq!(denied["error"]["type"], "rate_limit_exceeded");
+    }
+
+    #[test]
+    fn test_template_placeholders_are_filled() {
+        let template = r#"{"ok": "{{success}}", "result": "{{data}}", "problem": "{{error}}",
+            "trace": {"id": "{{request_id}}", "ms": "{{latency_ms}}"}, "note": "{{ not a placeholder"}"#;
+        let envelope = envelope(Some(template)).unwrap().unwrap();
+        let wrapped = envelope.wrap(201, json!([1, 2]), &meta());
+        assert_eq!(
+            wrapped,
+            json!({
+                "ok": true,
+                "result": [1, 2],
+                "problem": null,
+                "trace": {"id": "req-1", "ms": 42},
+                "note": "{{ not a placeholder"
+            })
+        );
+    }
+
+    #[test]
+    fn test_bad_templates_fail_startup() {
+        assert!(envelope(Some("{not json")).is_err());
+        assert!(envelope(Some(r#"{"x": "{{body}}"}"#)).is_err());
+    }
+}
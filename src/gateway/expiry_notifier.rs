@@ -0,0 +1,258 @@
+// === Webhook notifications for agents whose access keys are about to expire ===
+
+use chrono::{DateTime, Utc};
+use reqwest::Client;
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::collections::HashSet;
+use std::sync::Arc;
+use std::time::Duration;
+use uuid::Uuid;
+
+use crate::error::GatewayError;
+use crate::models::Agent;
+use crate::storage::AgentStoreTrait;
+
+const WEBHOOK_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// EXPIRY_WEBHOOK_FORMAT: shape of the webhook body
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExpiryWebhookFormat {
+    /// `ExpiryNotification` plus agent metadata (description, services, owner)
+    Default,
+    /// Only the `ExpiryNotification` fields
+    Minimal,
+    /// `{"text": "..."}` for Slack incoming webhooks
+    Slack,
+}
+
+impl ExpiryWebhookFormat {
+    pub fn from_env_value(value: &str) -> Self {
+        match value.to_lowercase().as_str() {
+            "default" => Self::Default,
+            "minimal" => Self::Minimal,
+            "slack" => Self::Slack,
+            other => panic!(
+                "EXPIRY_WEBHOOK_FORMAT must be 'default', 'minimal' or 'slack', got '{}'",
+                other
+            ),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ExpiryNotification {
+    pub agent_id: Uuid,
+    pub name: String,
+    pub expires_at: DateTime<Utc>,
+    pub days_remaining: i64,
+}
+
+impl From<&Agent> for ExpiryNotification {
+    fn from(agent: &Agent) -> Self {
+        Self {
+            agent_id: agent.id,
+            name: agent.name.clone(),
+            expires_at: agent.expires_at,
+            days_remaining: agent.days_until_expiry(),
+        }
+    }
+}
+
+pub struct ExpiryNotifier {
+    client: Client,
+    webhook_url: String,
+    format: ExpiryWebhookFormat,
+    notify_days: i64,
+}
+
+impl ExpiryNotifier {
+    pub fn new(webhook_url: String, format: ExpiryWebhookFormat, notify_days: i64) -> Self {
+        Self {
+            client: Client::builder()
+                .timeout(WEBHOOK_TIMEOUT)
+                .build()
+                .unwrap_or_default(),
+            webhook_url,
+            format,
+            notify_days,
+        }
+    }
+
+    /// Still valid, but within EXPIRY_NOTIFICATION_DAYS of expiring
+    pub fn is_expiring(&self, agent: &Agent) -> bool {
+        !agent.is_expired() && agent.days_until_expiry() <= self.notify_days
+    }
+
+    fn payload(&self, agent: &Agent, test: bool) -> Value {
+        let notification = ExpiryNotification::from(agent);
+        match self.format {
+            ExpiryWebhookFormat::Slack => json!({
+                "text": format!(
+                    "{}Agent '{}' ({}) access key expires in {} day(s), at {}",
+                    if test { "[test] " } else { "" },
+                    notification.name,
+                    notification.agent_id,
+                    notification.days_remaining,
+                    notification.expires_at.to_rfc3339()
+                )
+            }),
+            ExpiryWebhookFormat::Minimal => {
+                let mut body = json!(notification);
+                body["test"] = json!(test);
+                body
+            }
+            ExpiryWebhookFormat::Default => {
+                let mut body = json!(notification);
+                body["description"] = json!(agent.description);
+                body["allowed_services"] = json!(agent.service_ids());
+                body["owner_id"] = json!(agent.owner_id);
+                body["lifespan_days"] = json!(agent.lifespan_days);
+                body["test"] = json!(test);
+                body
+            }
+        }
+    }
+
+    /// POST one notification; a non-2xx answer is an error
+    pub async fn notify(&self, agent: &Agent, test: bool) -> Result<(), GatewayError> {
+        let response = self
+            .client
+            .post(&self.webhook_url)
+            .json(&self.payload(agent, test))
+            .send()
+            .await
+            .map_err(|e| GatewayError::UpstreamError(format!("Expiry webhook failed: {}", e)))?;
+
+        if !response.status().is_success() {
+            return Err(GatewayError::UpstreamError(format!(
+                "Expiry webhook returned {}",
+                response.status()
+            )));
+        }
+        Ok(())
+    }
+
+    /// Notify every expiring agent not yet in `notified`, returning how many were sent.
+    /// Failed deliveries stay out of `notified` so the next scan retries them.
+    pub async fn notify_expiring(
+        &self,
+        agents: &dyn AgentStoreTrait,
+        notified: &mut HashSet<Uuid>,
+    ) -> Result<usize, GatewayError> {
+        let mut sent = 0;
+        for agent in agents.list_agents().await? {
+            if !self.is_expiring(&agent) || notified.contains(&agent.id) {
+                continue;
+            }
+            match self.notify(&agent, false).await {
+                Ok(()) => {
+                    tracing::info!(agent_id = %agent.id, days_remaining = agent.days_until_expiry(), "Expiry notification sent");
+                    notified.insert(agent.id);
+                    sent += 1;
+                }
+                Err(e) => {
+                    tracing::warn!(agent_id = %agent.id, error = ?e, "Expiry notification failed");
+                }
+            }
+        }
+        Ok(sent)
+    }
+}
+
+// === Scan for expiring agents every `interval`, at most one notification per agent per day ===
+pub fn spawn_expiry_notifier(
+    notifier: Arc<ExpiryNotifier>,
+    agents: Arc<dyn AgentStoreTrait>,
+    interval: Duration,
+) {
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(interval);
+        // Agents notified today; cleared when the UTC date changes
+        let mut notified = HashSet::new();
+        let mut day = Utc::now().date_naive();
+        loop {
+            ticker.tick().await;
+            let today = Utc::now().date_naive();
+            if today != day {
+                notified.clear();
+                day = today;
+            }
+            if let Err(e) = notifier.notify_expiring(agents.as_ref(), &mut notified).await {
+                tracing::error!(error = ?e, "Expiry notification scan failed");
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::InMemoryStore;
+    use wiremock::matchers::{body_partial_json, method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    fn agent(name: &str, lifespan_days: u32) -> Agent {
+        Agent::with_lifespan(name.to_string(), "".to_string(), lifespan_days)
+    }
+
+    #[tokio::test]
+    async fn test_notifies_expiring_agents_once() {
+        let server = MockServer::start().await;
+        let store = InMemoryStore::new();
+        let soon = store.create_agent(agent("soon", 3)).await.unwrap();
+        store.create_agent(agent("later", 30)).await.unwrap();
+        let mut expired = agent("expired", 1);
+        expired.expires_at = Utc::now() - chrono::Duration::days(1);
+        store.create_agent(expired).await.unwrap();
+
+        Mock::given(method("POST"))
+            .and(path("/hook"))
+            .and(body_partial_json(json!({ "agent_id": soon.id, "name": "soon", "test": false })))
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let notifier = ExpiryNotifier::new(format!("{}/hook", server.uri()), ExpiryWebhookFormat::Default, 7);
+        let mut notified = HashSet::new();
+        assert_eq!(notifier.notify_expiring(&store, &mut notified).await.unwrap(), 1);
+        // Same day: already notified
+        assert_eq!(notifier.notify_expiring(&store, &mut notified).await.unwrap(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_failed_delivery_is_retried() {
+        let server = MockServer::start().await;
+        let store = InMemoryStore::new();
+        store.create_agent(agent("soon", 1)).await.unwrap();
+
+        Mock::given(method("POST"))
+            .respond_with(ResponseTemplate::new(500))
+            .up_to_n_times(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .respond_with(ResponseTemplate::new(204))
+            .mount(&server)
+            .await;
+
+        let notifier = ExpiryNotifier::new(server.uri(), ExpiryWebhookFormat::Minimal, 7);
+        let mut notified = HashSet::new();
+        assert_eq!(notifier.notify_expiring(&store, &mut notified).await.unwrap(), 0);
+        assert_eq!(notifier.notify_expiring(&store, &mut notified).await.unwrap(), 1);
+    }
+
+    #[test]
+    fn test_payload_formats() {
+        let agent = agent("bot", 5);
+        let minimal = ExpiryNotifier::new(String::new(), ExpiryWebhookFormat::Minimal, 7).payload(&agent, false);
+        assert_eq!(minimal["days_remaining"], 4);
+        assert!(minimal.get("allowed_services").is_none());
+
+        let slack = ExpiryNotifier::new(String::new(), ExpiryWebhookFormat::Slack, 7).payload(&agent, true);
+        let text = slack["text"].as_str().unwrap();
+        assert!(text.starts_with("[test] Agent 'bot'"));
+        assert!(text.contains("expires in 4 day(s)"));
+    }
+}
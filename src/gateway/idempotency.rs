@@ -0,0 +1,268 @@
+// === Deduplication of proxied requests carrying X-Idempotency-Key ===
+// An agent retrying after a client-side timeout must not reach the upstream
+// twice: the first request with a key goes through, and every later one with
+// the same key (from the same agent) gets its outcome back. A retry that
+// arrives while the first is still in flight waits for it. Once the upstream
+// has the request, even a failed answer is kept: it may have acted on it.
+
+use dashmap::mapref::entry::Entry;
+use dashmap::DashMap;
+use serde_json::Value;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::watch;
+use uuid::Uuid;
+
+use crate::error::GatewayError;
+
+/// Longest key accepted
+const MAX_KEY_LEN: usize = 255;
+
+/// Past this many keys, storing a response first drops the expired ones
+const SWEEP_THRESHOLD: usize = 10_000;
+
+/// What the first request with a key got, replayed to its retries
+#[derive(Debug, Clone, PartialEq)]
+pub enum StoredResponse {
+    /// The upstream's status and body; `partial` when its read timed out midway
+    Upstream { status: u16, body: Value, partial: bool },
+    /// The gateway's error for a request the upstream received but never fully
+    /// answered (`504` on a read timeout, `502` otherwise)
+    Failed { timed_out: bool, message: String },
+}
+
+impl StoredResponse {
+    /// The outcome to keep for a request that failed once sent; `None` for
+    /// failures before the upstream had it (refused connection, invalid
+    /// request), which free the key for a retry
+    pub fn failed(error: &GatewayError) -> Option<Self> {
+        let (timed_out, message) = match error {
+            GatewayError::UpstreamTimeout(message) => (true, message),
+            GatewayError::UpstreamError(message) => (false, message),
+            _ => return None,
+        };
+        Some(Self::Failed { timed_out, message: message.clone() })
+    }
+}
+
+enum Slot {
+    // First request still in flight; completes with its response
+    Pending(watch::Receiver<Option<StoredResponse>>),
+    Done(StoredResponse, Instant),
+}
+
+/// What to do with a request carrying an idempotency key
+pub enum Claim {
+    /// First with this key: forward it, then `complete` the guard
+    First(IdempotencyGuard),
+    /// Seen before: answer with the stored response
+    Replay(StoredResponse),
+}
+
+#[derive(Clone, Default)]
+pub struct IdempotencyCache {
+    // "<agent id>\0<key>" -> slot
+    entries: Arc<DashMap<String, Slot>>,
+}
+
+impl IdempotencyCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Keys are opaque to the gateway but end up in memory, so they are bounded
+    pub fn validate_key(key: &str) -> Result<(), GatewayError> {
+        if key.is_empty() || key.len() > MAX_KEY_LEN {
+            return Err(GatewayError::BadRequest(format!(
+                "X-Idempotency-Key must be 1 to {} characters",
+                MAX_KEY_LEN
+            )));
+        }
+        Ok(())
+    }
+
+    /// Claim `key` for `agent_id`. If an earlier request with it is in flight,
+    /// wait for its response; if that one fails without a response, this
+    /// request takes its place.
+    pub async fn claim(&self, agent_id: Uuid, key: &str) -> Claim {
+        let slot_key = format!("{}\0{}", agent_id, key);
+        loop {
+            let mut pending = match self.entries.entry(slot_key.clone()) {
+                Entry::Occupied(mut entry) => {
+                    let receiver = match entry.get() {
+                        Slot::Done(response, expires_at) if *expires_at > Instant::now() => {
+                            return Claim::Replay(response.clone());
+                        }
+                        Slot::Done(..) => None,
+                        Slot::Pending(receiver) => Some(receiver.clone()),
+                    };
+                    match receiver {
+                        Some(receiver) => receiver,
+                        None => {
+                            let (guard, slot) = self.pending(slot_key);
+                            entry.insert(slot);
+                            return Claim::First(guard);
+                        }
+                    }
+                }
+                Entry::Vacant(entry) => {
+                    let (guard, slot) = self.pending(slot_key);
+                    entry.insert(slot);
+                    return Claim::First(guard);
+                }
+            };
+            // The map entry is released before waiting
+            let response = match pending.wait_for(Option::is_some).await {
+                Ok(response) => response.clone(),
+                // Sender dropped: the first request failed, so claim the key again
+                Err(_) => None,
+            };
+            if let Some(response) = response {
+                return Claim::Replay(response);
+            }
+        }
+    }
+
+    /// Guard for a new first request, and the slot its retries wait on
+    fn pending(&self, slot_key: String) -> (IdempotencyGuard, Slot) {
+        let (sender, receiver) = watch::channel(None);
+        let guard = IdempotencyGuard {
+            cache: self.clone(),
+            slot_key,
+            sender,
+            completed: false,
+        };
+        (guard, Slot::Pending(receiver))
+    }
+}
+
+/// Held by the first request with a key. Dropped without `complete` (the request
+/// failed before it was sent, or the client went away), it frees the key so a
+/// retry is forwarded.
+pub struct IdempotencyGuard {
+    cache: IdempotencyCache,
+    slot_key: String,
+    sender: watch::Sender<Option<StoredResponse>>,
+    completed: bool,
+}
+
+impl IdempotencyGuard {
+    /// Store the request's outcome for `ttl` and hand it to any waiting retries
+    pub fn complete(mut self, response: StoredResponse, ttl: Duration) {
+        let entries = &self.cache.entries;
+        if entries.len() >= SWEEP_THRESHOLD {
+            let now = Instant::now();
+            entries.retain(|_, slot| !matches!(slot, Slot::Done(_, expires_at) if *expires_at <= now));
+        }
+        entries.insert(
+            self.slot_key.clone(),
+            Slot::Done(response.clone(), Instant::now() + ttl),
+        );
+        self.sender.send_replace(Some(response));
+        self.completed = true;
+    }
+}
+
+impl Drop for IdempotencyGuard {
+    fn drop(&mut self) {
+        if !self.completed {
+            self.cache
+                .entries
+                .remove_if(&self.slot_key, |_, slot| matches!(slot, Slot::Pending(_)));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    const TTL: Duration = Duration::from_secs(60);
+
+    fn upstream(status: u16, body: Value) -> StoredResponse {
+        StoredResponse::Upstream { status, body, partial: false }
+    }
+
+    #[tokio::test]
+    async fn test_second_request_replays_the_first_response() {
+        let cache = IdempotencyCache::new();
+        let agent = Uuid::new_v4();
+
+        let Claim::First(guard) = cache.claim(agent, "pay-1").await else {
+            panic!("first request must go through");
+        };
+        guard.complete(upstream(201, json!({"charge": "ch_1"})), TTL);
+
+        let Claim::Replay(replayed) = cache.claim(agent, "pay-1").await else {
+            panic!("retry must be replayed");
+        };
+        assert_eq!(replayed, upstream(201, json!({"charge": "ch_1"})));
+        // Keys belong to one agent
+        assert!(matches!(cache.claim(Uuid::new_v4(), "pay-1").await, Claim::First(_)));
+    }
+
+    #[tokio::test]
+    async fn test_retry_waits_for_the_request_in_flight() {
+        let cache = IdempotencyCache::new();
+        let agent = Uuid::new_v4();
+        let Claim::First(guard) = cache.claim(agent, "book-1").await else {
+            panic!("first request must go through");
+        };
+
+        let waiting = {
+            let cache = cache.clone();
+            tokio::spawn(async move { cache.claim(agent, "book-1").await })
+        };
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        assert!(!waiting.is_finished());
+        guard.complete(upstream(200, json!({"booking": 7})), TTL);
+
+        let Claim::Replay(replayed) = waiting.await.unwrap() else {
+            panic!("waiting retry must get the first response");
+        };
+        assert_eq!(replayed, upstream(200, json!({"booking": 7})));
+    }
+
+    #[tokio::test]
+    async fn test_failed_or_expired_first_request_frees_the_key() {
+        let cache = IdempotencyCache::new();
+        let agent = Uuid::new_v4();
+
+        let Claim::First(guard) = cache.claim(agent, "k").await else {
+            panic!("first request must go through");
+        };
+        let waiting = {
+            let cache = cache.clone();
+            tokio::spawn(async move { cache.claim(agent, "k").await })
+        };
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        drop(guard);
+        // The waiting retry takes over instead of replaying nothing
+        let Claim::First(retry) = waiting.await.unwrap() else {
+            panic!("retry must be forwarded once the first failed");
+        };
+        retry.complete(upstream(200, json!({})), Duration::ZERO);
+        assert!(matches!(cache.claim(agent, "k").await, Claim::First(_)));
+    }
+
+    #[test]
+    fn test_only_failures_after_the_send_are_kept() {
+        let timeout = GatewayError::UpstreamTimeout("Upstream timed out".to_string());
+        assert_eq!(
+            StoredResponse::failed(&timeout),
+            Some(StoredResponse::Failed { timed_out: true, message: "Upstream timed out".to_string() })
+        );
+        let too_large = GatewayError::UpstreamError("Response too large".to_string());
+        assert!(matches!(StoredResponse::failed(&too_large), Some(StoredResponse::Failed { timed_out: false, .. })));
+        let refused = GatewayError::UpstreamUnavailable("Upstream unreachable".to_string());
+        assert_eq!(StoredResponse::failed(&refused), None);
+    }
+
+    #[test]
+    fn test_key_length_is_bounded() {
+        assert!(IdempotencyCache::validate_key("abc").is_ok());
+        assert!(IdempotencyCache::validate_key("").is_err());
+        assert!(IdempotencyCache::validate_key(&"k".repeat(MAX_KEY_LEN + 1)).is_err());
+    }
+}
@@ -0,0 +1,118 @@
+// === Sliding window rate limiter keyed on client IP ===
+// Guards the unauthenticated endpoints (registration, agent creation), where
+// there is no agent id to key on yet.
+
+use axum::http::HeaderMap;
+use std::collections::HashMap;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::RwLock;
+
+use crate::error::{GatewayError, RateLimitScope};
+use super::{RateLimitConfig, RecentCounter};
+
+/// Past this many tracked IPs, a check first drops the ones with no recent requests
+const SWEEP_THRESHOLD: usize = 10_000;
+
+#[derive(Clone)]
+pub struct IpRateLimiter {
+    windows: Arc<RwLock<HashMap<IpAddr, Vec<Instant>>>>,
+    pub ip_limit: RateLimitConfig,
+    // Requests turned away, per minute
+    rejections: RecentCounter,
+}
+
+impl IpRateLimiter {
+    pub fn new(ip_limit: RateLimitConfig) -> Self {
+        Self {
+            windows: Arc::new(RwLock::new(HashMap::new())),
+            ip_limit,
+            rejections: RecentCounter::new(),
+        }
+    }
+
+    // === Check if request is allowed for this client IP ===
+    pub async fn check_ip(&self, ip: IpAddr) -> Result<(), GatewayError> {
+        let now = Instant::now();
+        let window_start = now.checked_sub(self.ip_limit.window).unwrap_or(now);
+
+        let mut windows = self.windows.write().await;
+        if windows.len() >= SWEEP_THRESHOLD {
+            windows.retain(|_, timestamps| timestamps.iter().any(|&t| t > window_start));
+        }
+        let timestamps = windows.entry(ip).or_default();
+        timestamps.retain(|&t| t > window_start);
+        if timestamps.len() >= self.ip_limit.requests as usize {
+            self.rejections.record();
+            return Err(GatewayError::RateLimitExceeded(RateLimitScope::Ip));
+        }
+        timestamps.push(now);
+        Ok(())
+    }
+
+    /// Requests rejected over the last hour
+    pub fn rejections_last_hour(&self) -> u64 {
+        self.rejections.last_hour()
+    }
+}
+
+impl Default for IpRateLimiter {
+    fn default() -> Self {
+        Self::new(RateLimitConfig {
+            requests: 20,
+            window: Duration::from_secs(60),
+        })
+    }
+}
+
+/// The client's address. With `trusted_header` set (TRUSTED_CLIENT_IP_HEADER, e.g.
+/// `X-Forwarded-For` behind a reverse proxy), its last entry wins: that is the one
+/// the proxy appended, while earlier entries are whatever the client sent. Without
+/// it, or when the header is missing or unparseable, the peer address is used.
+pub fn client_ip(headers: &HeaderMap, peer: Option<SocketAddr>, trusted_header: Option<&str>) -> Option<IpAddr> {
+    let forwarded = trusted_header
+        .and_then(|name| headers.get_all(name).iter().next_back())
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.rsplit(',').next())
+        .and_then(|last| last.trim().parse().ok());
+    forwarded.or(peer.map(|addr| addr.ip()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_each_ip_has_its_own_quota() {
+        let limiter = IpRateLimiter::new(RateLimitConfig {
+            requests: 2,
+            window: Duration::from_secs(60),
+        });
+        let (first, second): (IpAddr, IpAddr) = ("203.0.113.1".parse().unwrap(), "203.0.113.2".parse().unwrap());
+
+        assert!(limiter.check_ip(first).await.is_ok());
+        assert!(limiter.check_ip(first).await.is_ok());
+        assert!(matches!(limiter.check_ip(first).await, Err(GatewayError::RateLimitExceeded(RateLimitScope::Ip))));
+        assert!(limiter.check_ip(second).await.is_ok());
+    }
+
+    #[test]
+    fn test_client_ip_trusts_only_the_configured_header() {
+        let peer: SocketAddr = "10.0.0.5:4000".parse().unwrap();
+        let mut headers = HeaderMap::new();
+        headers.insert("x-forwarded-for", "1.1.1.1, 198.51.100.7".parse().unwrap());
+
+        // Spoofable unless the operator says a proxy sets it
+        assert_eq!(client_ip(&headers, Some(peer), None), Some(peer.ip()));
+        assert_eq!(
+            client_ip(&headers, Some(peer), Some("X-Forwarded-For")),
+            Some("198.51.100.7".parse().unwrap())
+        );
+        assert_eq!(client_ip(&headers, Some(peer), Some("X-Real-IP")), Some(peer.ip()));
+
+        headers.insert("x-forwarded-for", "garbage".parse().unwrap());
+        assert_eq!(client_ip(&headers, Some(peer), Some("X-Forwarded-For")), Some(peer.ip()));
+        assert_eq!(client_ip(&HeaderMap::new(), None, Some("X-Forwarded-For")), None);
+    }
+}
@@ -0,0 +1,294 @@
+// === Static API key rotation via provider endpoints ===
+
+use chrono::Utc;
+use reqwest::{Client, Method};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::time::Instant;
+
+use crate::config::{
+    CredentialManager, RotationAuth, RotationConfig, ServiceRegistry, StoredCredential,
+};
+use crate::error::GatewayError;
+
+// === Call the rotation endpoint and build the rotated credential ===
+pub async fn fetch_rotated_key(
+    client: &Client,
+    rotation: &RotationConfig,
+    current: &StoredCredential,
+) -> Result<StoredCredential, GatewayError> {
+    let method = Method::from_bytes(rotation.method.to_uppercase().as_bytes())
+        .map_err(|_| GatewayError::BadRequest(format!("Invalid rotation method '{}'", rotation.method)))?;
+
+    let mut request = client.request(method, &rotation.endpoint);
+    request = match &rotation.auth {
+        RotationAuth::None => request,
+        RotationAuth::CurrentKey => request.bearer_auth(&current.access_token),
+        RotationAuth::Header { name, value } => request.header(name.as_str(), value.as_str()),
+    };
+
+    let response = request
+        .send()
+        .await
+        .map_err(|e| GatewayError::UpstreamError(format!("Rotation request failed: {}", e)))?;
+
+    if !response.status().is_success() {
+        return Err(GatewayError::UpstreamError(format!(
+            "Rotation endpoint returned {}",
+            response.status()
+        )));
+    }
+
+    let body: Value = response
+        .json()
+        .await
+        .map_err(|e| GatewayError::UpstreamError(format!("Rotation response is not JSON: {}", e)))?;
+
+    let new_key = body
+        .pointer(&rotation.key_pointer)
+        .and_then(Value::as_str)
+        .filter(|k| !k.is_empty())
+        .ok_or_else(|| {
+            GatewayError::UpstreamError(format!(
+                "Rotation response has no key at '{}'",
+                rotation.key_pointer
+            ))
+        })?;
+
+    let mut rotated = current.clone();
+    rotated.access_token = new_key.to_string();
+    Ok(rotated)
+}
+
+// === Rotate a service key and persist it; the old key is kept on any failure ===
+pub async fn rotate_service_key(
+    client: &Client,
+    services: &ServiceRegistry,
+    credentials: &CredentialManager,
+    service_id: &str,
+) -> Result<(), GatewayError> {
+    let rotation = services
+        .get(service_id)
+        .ok_or_else(|| GatewayError::NotFound(format!("Service '{}' not found", service_id)))?
+        .rotation
+        .clone()
+        .ok_or_else(|| {
+            GatewayError::BadRequest(format!("Service '{}' has no rotation configured", service_id))
+        })?;
+
+    let current = credentials
+        .get(service_id)
+        .await
+        .ok_or_else(|| GatewayError::CredentialNotFound(service_id.to_string()))?;
+
+    match fetch_rotated_key(client, &rotation, &current).await {
+        Ok(rotated) => {
+            credentials.update(rotated).await?;
+            tracing::info!(service_id = %service_id, rotated_at = %Utc::now(), "API key rotated");
+            Ok(())
+        }
+        Err(e) => {
+            tracing::error!(service_id = %service_id, error = ?e, "API key rotation failed, keeping current key");
+            Err(e)
+        }
+    }
+}
+
+/// How often the rotation task looks for due services
+const ROTATION_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+// === When each service with a rotation block is next due ===
+// Built from whatever services are current at each check, so services added
+// or changed by a reload get a schedule and removed ones lose theirs.
+#[derive(Debug, Default)]
+pub struct RotationSchedule {
+    // Service id -> (interval it was scheduled with, next rotation)
+    due: HashMap<String, (Duration, Instant)>,
+}
+
+impl RotationSchedule {
+    /// Services due at `now`, each moved on by its interval. A service seen for
+    /// the first time (or with a new interval) is first due one interval from
+    /// now, so neither startup nor a reload rotates straight away.
+    pub fn take_due(&mut self, services: &ServiceRegistry, now: Instant) -> Vec<String> {
+        let mut due = Vec::new();
+        let mut next = HashMap::new();
+        for service in services.list() {
+            let Some(rotation) = &service.rotation else {
+                continue;
+            };
+            let interval = Duration::from_secs(rotation.interval_secs.max(1));
+            let at = match self.due.get(&service.id) {
+                Some(&(scheduled, at)) if scheduled == interval && at <= now => {
+                    due.push(service.id.clone());
+                    now + interval
+                }
+                Some(&(scheduled, at)) if scheduled == interval => at,
+                _ => now + interval,
+            };
+            next.insert(service.id.clone(), (interval, at));
+        }
+        self.due = next;
+        due
+    }
+}
+
+// === Background key rotation for the services `current` returns at each check ===
+pub fn spawn_key_rotation<F, S>(current: F, credentials: Arc<CredentialManager>)
+where
+    F: Fn() -> Arc<S> + Send + 'static,
+    S: AsRef<ServiceRegistry> + Send + Sync + 'static,
+{
+    spawn_rotation_loop(ROTATION_CHECK_INTERVAL, current, credentials);
+}
+
+fn spawn_rotation_loop<F, S>(check_interval: Duration, current: F, credentials: Arc<CredentialManager>)
+where
+    F: Fn() -> Arc<S> + Send + 'static,
+    S: AsRef<ServiceRegistry> + Send + Sync + 'static,
+{
+    let client = Client::new();
+    tokio::spawn(async move {
+        let mut schedule = RotationSchedule::default();
+        let mut ticker = tokio::time::interval(check_interval);
+        loop {
+            ticker.tick().await;
+            let services = current();
+            for service_id in schedule.take_due(services.as_ref().as_ref(), Instant::now()) {
+                // A slow rotation endpoint doesn't hold up the other services
+                let (client, services, credentials) = (client.clone(), services.clone(), credentials.clone());
+                tokio::spawn(async move {
+                    let _ = rotate_service_key(&client, services.as_ref().as_ref(), &credentials, &service_id).await;
+                });
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::ServiceConfig;
+    use serde_json::json;
+    use std::io::Write;
+    use tempfile::NamedTempFile;
+    use wiremock::matchers::{header, method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    const KEY: &str = "test-encryption-key-32-chars!!!";
+
+    fn registry(endpoint: &str) -> ServiceRegistry {
+        registry_with("static", endpoint, 3600)
+    }
+
+    fn registry_with(id: &str, endpoint: &str, interval_secs: u64) -> ServiceRegistry {
+        let service: ServiceConfig = serde_json::from_value(json!({
+            "id": id,
+            "name": "Static",
+            "description": "",
+            "base_url": "http://localhost",
+            "auth_type": "api_key",
+            "endpoints": [],
+            "rate_limit": { "requests": 10, "window_secs": 60 },
+            "rotation": {
+                "endpoint": endpoint,
+                "key_pointer": "/data/api_key",
+                "interval_secs": interval_secs
+            }
+        }))
+        .unwrap();
+        ServiceRegistry::from_services(vec![service])
+    }
+
+    fn credentials() -> (NamedTempFile, CredentialManager) {
+        let mut file = NamedTempFile::new().unwrap();
+        file.write_all(
+            br#"{"credentials":[{"service_id":"static","access_token":"old-key","refresh_token":null,"expires_at":null,"scopes":[],"encrypted":false}]}"#,
+        )
+        .unwrap();
+        let manager = CredentialManager::load_from_file(file.path(), KEY).unwrap();
+        (file, manager)
+    }
+
+    #[tokio::test]
+    async fn test_rotation_stores_new_key() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/rotate"))
+            .and(header("authorization", "Bearer old-key"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({"data": {"api_key": "new-key"}})))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let services = registry(&format!("{}/rotate", server.uri()));
+        let (_file, creds) = credentials();
+
+        rotate_service_key(&Client::new(), &services, &creds, "static")
+            .await
+            .unwrap();
+
+        assert_eq!(creds.get("static").await.unwrap().access_token, "new-key");
+    }
+
+    #[tokio::test]
+    async fn test_malformed_rotation_response_keeps_old_key() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/rotate"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({"data": {"unexpected": true}})))
+            .mount(&server)
+            .await;
+
+        let services = registry(&format!("{}/rotate", server.uri()));
+        let (_file, creds) = credentials();
+
+        let result = rotate_service_key(&Client::new(), &services, &creds, "static").await;
+
+        assert!(result.is_err());
+        assert_eq!(creds.get("static").await.unwrap().access_token, "old-key");
+    }
+
+    #[test]
+    fn test_schedule_follows_reloaded_services() {
+        let mut schedule = RotationSchedule::default();
+        let start = Instant::now();
+        let hourly = registry_with("static", "http://localhost/rotate", 3600);
+        assert!(schedule.take_due(&hourly, start).is_empty());
+        assert_eq!(schedule.take_due(&hourly, start + Duration::from_secs(3600)), ["static"]);
+
+        // A reload swaps the service out for another: the new one is scheduled, the old one dropped
+        let reloaded = registry_with("reports", "http://localhost/rotate", 60);
+        let reloaded_at = start + Duration::from_secs(3700);
+        assert!(schedule.take_due(&reloaded, reloaded_at).is_empty());
+        assert_eq!(schedule.take_due(&reloaded, reloaded_at + Duration::from_secs(60)), ["reports"]);
+        assert!(!schedule.due.contains_key("static"));
+    }
+
+    #[tokio::test]
+    async fn test_rotation_task_picks_up_reloaded_services() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/rotate"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({"data": {"api_key": "new-key"}})))
+            .mount(&server)
+            .await;
+        let (_file, creds) = credentials();
+        let creds = Arc::new(creds);
+        // Starts with no rotating service, like a gateway before the service is added
+        let current = Arc::new(std::sync::RwLock::new(Arc::new(ServiceRegistry::from_services(vec![]))));
+        let reader = current.clone();
+        spawn_rotation_loop(Duration::from_millis(50), move || reader.read().unwrap().clone(), creds.clone());
+
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        *current.write().unwrap() = Arc::new(registry_with("static", &format!("{}/rotate", server.uri()), 1));
+
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while creds.get("static").await.unwrap().access_token != "new-key" {
+            assert!(Instant::now() < deadline, "reloaded service was never rotated");
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+    }
+}
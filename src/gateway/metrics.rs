@@ -0,0 +1,442 @@
+// === In-memory counters for gateway internals (token refresh, proxy latency, ...) ===
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::collections::{HashMap, VecDeque};
+use std::sync::atomic::{AtomicI64, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::RwLock;
+
+use crate::models::RefreshTrigger;
+
+// === Histogram bucket upper bounds in milliseconds (last bucket is +Inf) ===
+const DURATION_BUCKETS_MS: [u64; 8] = [10, 50, 100, 250, 500, 1000, 2500, 5000];
+
+// === Fixed-bucket duration histogram ===
+#[derive(Debug, Clone, Serialize)]
+pub struct DurationHistogram {
+    pub buckets_ms: Vec<u64>,
+    pub counts: Vec<u64>,
+    pub count: u64,
+    pub sum_ms: u64,
+}
+
+impl DurationHistogram {
+    fn new() -> Self {
+        Self {
+            buckets_ms: DURATION_BUCKETS_MS.to_vec(),
+            counts: vec![0; DURATION_BUCKETS_MS.len() + 1],
+            count: 0,
+            sum_ms: 0,
+        }
+    }
+
+    fn observe(&mut self, duration: Duration) {
+        let ms = duration.as_millis() as u64;
+        let idx = DURATION_BUCKETS_MS
+            .iter()
+            .position(|&bound| ms <= bound)
+            .unwrap_or(DURATION_BUCKETS_MS.len());
+        self.counts[idx] += 1;
+        self.count += 1;
+        self.sum_ms += ms;
+    }
+}
+
+// === Per-service refresh counters ===
+#[derive(Debug, Clone, Serialize)]
+pub struct RefreshStats {
+    pub attempts: u64,
+    pub successes: u64,
+    pub failures: u64,
+    pub duration: DurationHistogram,
+}
+
+impl RefreshStats {
+    fn new() -> Self {
+        Self {
+            attempts: 0,
+            successes: 0,
+            failures: 0,
+            duration: DurationHistogram::new(),
+        }
+    }
+}
+
+// === Refresh attempts remembered per service (GET /admin/credentials) ===
+const REFRESH_HISTORY_LEN: usize = 10;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct RefreshAttempt {
+    pub at: DateTime<Utc>,
+    pub trigger: RefreshTrigger,
+    pub success: bool,
+    pub duration_ms: u64,
+}
+
+// === Token refresh metrics shared across handlers ===
+#[derive(Clone, Default)]
+pub struct RefreshMetrics {
+    // Key: service_id
+    stats: Arc<RwLock<HashMap<String, RefreshStats>>>,
+    // Key: service_id; the latest REFRESH_HISTORY_LEN attempts, oldest first
+    history: Arc<RwLock<HashMap<String, VecDeque<RefreshAttempt>>>>,
+}
+
+impl RefreshMetrics {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // === Record the outcome of one refresh attempt ===
+    pub async fn record(&self, service_id: &str, trigger: RefreshTrigger, success: bool, duration: Duration) {
+        {
+            let mut stats = self.stats.write().await;
+            let entry = stats
+                .entry(service_id.to_string())
+                .or_insert_with(RefreshStats::new);
+
+            entry.attempts += 1;
+            if success {
+                entry.successes += 1;
+            } else {
+                entry.failures += 1;
+            }
+            entry.duration.observe(duration);
+        }
+
+        let mut history = self.history.write().await;
+        let attempts = history.entry(service_id.to_string()).or_default();
+        if attempts.len() == REFRESH_HISTORY_LEN {
+            attempts.pop_front();
+        }
+        attempts.push_back(RefreshAttempt {
+            at: Utc::now(),
+            trigger,
+            success,
+            duration_ms: duration.as_millis() as u64,
+        });
+    }
+
+    // === Point-in-time copy of all counters ===
+    pub async fn snapshot(&self) -> HashMap<String, RefreshStats> {
+        self.stats.read().await.clone()
+    }
+
+    // === Latest attempts for one service, newest first ===
+    pub async fn history(&self, service_id: &str) -> Vec<RefreshAttempt> {
+        self.history
+            .read()
+            .await
+            .get(service_id)
+            .map(|attempts| attempts.iter().rev().cloned().collect())
+            .unwrap_or_default()
+    }
+}
+
+// === In-flight proxy request counters ===
+#[derive(Clone, Default)]
+pub struct ProxyMetrics {
+    in_flight: Arc<AtomicI64>,
+    completed: Arc<AtomicU64>,
+    cancelled: Arc<AtomicU64>,
+    // Requests sent upstream, per minute
+    recent: RecentCounter,
+}
+
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct ProxyStats {
+    pub in_flight: i64,
+    pub completed: u64,
+    pub cancelled_by_client: u64,
+}
+
+impl ProxyMetrics {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // === Mark a request as in flight until the guard is dropped ===
+    pub fn track(&self) -> InFlightGuard {
+        self.in_flight.fetch_add(1, Ordering::Relaxed);
+        self.recent.record();
+        InFlightGuard {
+            metrics: self.clone(),
+            finished: false,
+        }
+    }
+
+    pub fn snapshot(&self) -> ProxyStats {
+        ProxyStats {
+            in_flight: self.in_flight.load(Ordering::Relaxed),
+            completed: self.completed.load(Ordering::Relaxed),
+            cancelled_by_client: self.cancelled.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Requests sent upstream over the last hour, finished or not
+    pub fn proxied_last_hour(&self) -> u64 {
+        self.recent.last_hour()
+    }
+}
+
+// === RAII guard around an upstream call ===
+// When the client disconnects, hyper drops the handler future, which drops
+// this guard (and the pending reqwest future, closing the upstream connection)
+// without `finish` having been called.
+pub struct InFlightGuard {
+    metrics: ProxyMetrics,
+    finished: bool,
+}
+
+impl InFlightGuard {
+    pub fn finish(mut self) {
+        self.finished = true;
+    }
+}
+
+impl Drop for InFlightGuard {
+    fn drop(&mut self) {
+        self.metrics.in_flight.fetch_sub(1, Ordering::Relaxed);
+        if self.finished {
+            self.metrics.completed.fetch_add(1, Ordering::Relaxed);
+        } else {
+            self.metrics.cancelled.fetch_add(1, Ordering::Relaxed);
+            tracing::info!("Client disconnected, upstream request cancelled");
+        }
+    }
+}
+
+// === Events per minute over the last hour (GET /admin/overview) ===
+
+/// Minutes a `RecentCounter` remembers
+const RECENT_WINDOW_MINUTES: i64 = 60;
+
+#[derive(Clone, Default)]
+pub struct RecentCounter {
+    // (minute since the epoch, events in it), oldest first
+    minutes: Arc<std::sync::Mutex<VecDeque<(i64, u64)>>>,
+}
+
+impl RecentCounter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn record(&self) {
+        self.record_at(current_minute());
+    }
+
+    /// Events in the current minute and the 59 before it
+    pub fn last_hour(&self) -> u64 {
+        self.count_at(current_minute())
+    }
+
+    fn record_at(&self, minute: i64) {
+        let mut minutes = self.minutes.lock().unwrap_or_else(|e| e.into_inner());
+        match minutes.back_mut() {
+            Some((last, count)) if *last == minute => *count += 1,
+            _ => minutes.push_back((minute, 1)),
+        }
+        while minutes.front().is_some_and(|(m, _)| *m <= minute - RECENT_WINDOW_MINUTES) {
+            minutes.pop_front();
+        }
+    }
+
+    fn count_at(&self, minute: i64) -> u64 {
+        let minutes = self.minutes.lock().unwrap_or_else(|e| e.into_inner());
+        minutes
+            .iter()
+            .filter(|(m, _)| *m > minute - RECENT_WINDOW_MINUTES)
+            .map(|(_, count)| count)
+            .sum()
+    }
+}
+
+fn current_minute() -> i64 {
+    chrono::Utc::now().timestamp().div_euclid(60)
+}
+
+// === Recent proxy latencies per service, for percentiles ===
+
+/// Samples kept per service; older ones are dropped first
+const LATENCY_SAMPLES_PER_SERVICE: usize = 1000;
+
+/// Timing of one request that reached the upstream
+#[derive(Debug, Clone, Copy)]
+pub struct LatencySample {
+    pub total_ms: u64,
+    pub upstream_ms: u64,
+    /// Validation, rate limiting, credential refresh: `total_ms - upstream_ms`
+    pub gateway_ms: u64,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct Percentiles {
+    pub p50: Option<u64>,
+    pub p95: Option<u64>,
+    pub p99: Option<u64>,
+}
+
+impl Percentiles {
+    /// Nearest-rank percentiles; all `None` without samples
+    fn of(mut values: Vec<u64>) -> Self {
+        values.sort_unstable();
+        let rank = |p: usize| {
+            let idx = (values.len() * p).div_ceil(100).saturating_sub(1);
+            values.get(idx).copied()
+        };
+        Self { p50: rank(50), p95: rank(95), p99: rank(99) }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct LatencyStats {
+    pub count: usize,
+    pub total_ms: Percentiles,
+    pub upstream_ms: Percentiles,
+    pub gateway_ms: Percentiles,
+}
+
+#[derive(Clone, Default)]
+pub struct LatencyMetrics {
+    // Key: service_id
+    samples: Arc<RwLock<HashMap<String, VecDeque<LatencySample>>>>,
+}
+
+impl LatencyMetrics {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub async fn record(&self, service_id: &str, sample: LatencySample) {
+        let mut samples = self.samples.write().await;
+        let recent = samples.entry(service_id.to_string()).or_default();
+        if recent.len() == LATENCY_SAMPLES_PER_SERVICE {
+            recent.pop_front();
+        }
+        recent.push_back(sample);
+    }
+
+    /// Percentiles over the recent samples of `service_id`, or of every service
+    pub async fn stats(&self, service_id: Option<&str>) -> LatencyStats {
+        let samples = self.samples.read().await;
+        let recent: Vec<LatencySample> = match service_id {
+            Some(id) => samples.get(id).into_iter().flatten().copied().collect(),
+            None => samples.values().flatten().copied().collect(),
+        };
+        let field = |f: fn(&LatencySample) -> u64| Percentiles::of(recent.iter().map(f).collect());
+        LatencyStats {
+            count: recent.len(),
+            total_ms: field(|s| s.total_ms),
+            upstream_ms: field(|s| s.upstream_ms),
+            gateway_ms: field(|s| s.gateway_ms),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_finished_request_is_not_cancelled() {
+        let metrics = ProxyMetrics::new();
+
+        let guard = metrics.track();
+        assert_eq!(metrics.snapshot().in_flight, 1);
+        guard.finish();
+
+        let stats = metrics.snapshot();
+        assert_eq!(stats.in_flight, 0);
+        assert_eq!(stats.completed, 1);
+        assert_eq!(stats.cancelled_by_client, 0);
+    }
+
+    #[tokio::test]
+    async fn test_refresh_history_keeps_the_latest_attempts() {
+        let metrics = RefreshMetrics::new();
+        for i in 0..REFRESH_HISTORY_LEN + 2 {
+            // Only the last attempt succeeds
+            let success = i == REFRESH_HISTORY_LEN + 1;
+            metrics.record("svc", RefreshTrigger::Background, success, Duration::from_millis(i as u64)).await;
+        }
+
+        let history = metrics.history("svc").await;
+        assert_eq!(history.len(), REFRESH_HISTORY_LEN);
+        assert!(history[0].success);
+        assert_eq!(history[0].duration_ms, REFRESH_HISTORY_LEN as u64 + 1);
+        assert_eq!(history[REFRESH_HISTORY_LEN - 1].duration_ms, 2);
+        assert_eq!(metrics.snapshot().await["svc"].attempts, REFRESH_HISTORY_LEN as u64 + 2);
+        assert!(metrics.history("other").await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_dropped_request_counts_as_cancelled() {
+        let metrics = ProxyMetrics::new();
+
+        // Simulate a handler stuck waiting on upstream, then the client goes away
+        let m = metrics.clone();
+        let handle = tokio::spawn(async move {
+            let guard = m.track();
+            std::future::pending::<()>().await;
+            guard.finish();
+        });
+        tokio::task::yield_now().await;
+        assert_eq!(metrics.snapshot().in_flight, 1);
+
+        handle.abort();
+        let _ = handle.await;
+
+        let stats = metrics.snapshot();
+        assert_eq!(stats.in_flight, 0);
+        assert_eq!(stats.completed, 0);
+        assert_eq!(stats.cancelled_by_client, 1);
+    }
+
+    #[test]
+    fn test_recent_counter_forgets_minutes_older_than_an_hour() {
+        let counter = RecentCounter::new();
+        counter.record_at(100);
+        counter.record_at(100);
+        counter.record_at(130);
+        assert_eq!(counter.count_at(130), 3);
+        assert_eq!(counter.count_at(159), 3);
+        assert_eq!(counter.count_at(160), 1);
+
+        // Recording drops the minutes that fell out of the window
+        counter.record_at(200);
+        assert_eq!(counter.minutes.lock().unwrap().len(), 1);
+        assert_eq!(counter.count_at(200), 1);
+    }
+
+    #[test]
+    fn test_percentiles_use_nearest_rank() {
+        let values: Vec<u64> = (1..=200).collect();
+        let p = Percentiles::of(values);
+        assert_eq!((p.p50, p.p95, p.p99), (Some(100), Some(190), Some(198)));
+        assert_eq!(Percentiles::of(vec![7]).p99, Some(7));
+        assert_eq!(Percentiles::of(Vec::new()), Percentiles::default());
+    }
+
+    #[tokio::test]
+    async fn test_latency_keeps_recent_samples_per_service() {
+        let metrics = LatencyMetrics::new();
+        for ms in 0..(LATENCY_SAMPLES_PER_SERVICE as u64 + 10) {
+            let sample = LatencySample { total_ms: ms + 5, upstream_ms: ms, gateway_ms: 5 };
+            metrics.record("slow", sample).await;
+        }
+        metrics
+            .record("fast", LatencySample { total_ms: 1, upstream_ms: 0, gateway_ms: 1 })
+            .await;
+
+        let slow = metrics.stats(Some("slow")).await;
+        assert_eq!(slow.count, LATENCY_SAMPLES_PER_SERVICE);
+        // The ten oldest samples were dropped
+        assert_eq!(slow.upstream_ms.p50, Some(509));
+        assert_eq!(slow.gateway_ms.p99, Some(5));
+        assert_eq!(metrics.stats(None).await.count, LATENCY_SAMPLES_PER_SERVICE + 1);
+        assert_eq!(metrics.stats(Some("unknown")).await.count, 0);
+    }
+}
@@ -1,13 +1,47 @@
+mod concurrency_limiter;
 mod credential_vault;
+mod decompression;
 mod encryption;
+mod envelope;
+mod expiry_notifier;
+mod idempotency;
+mod injection_guard;
+mod ip_rate_limiter;
+mod jwt_assertion;
+mod key_rotation;
+mod metrics;
+mod path_normalization;
 mod proxy;
 mod rate_limiter;
+mod refresh_coordinator;
 mod replay_guard;
+mod request_schema;
+mod response_cache;
+mod response_sanitizer;
 mod scope_checker;
+mod ssrf;
+mod statsd;
 mod token_refresh;
 
+pub use concurrency_limiter::*;
+pub use decompression::*;
+pub use envelope::*;
+pub use expiry_notifier::*;
+pub use idempotency::*;
+pub use injection_guard::*;
+pub use ip_rate_limiter::*;
+pub use jwt_assertion::*;
+pub use key_rotation::*;
+pub use metrics::*;
+pub use path_normalization::*;
 pub use proxy::*;
 pub use rate_limiter::*;
+pub use refresh_coordinator::*;
+pub use request_schema::*;
+pub use response_cache::*;
+pub use response_sanitizer::*;
+pub use ssrf::*;
+pub use statsd::*;
 pub use token_refresh::*;
 
 // Encryption module prepared for credential encryption
@@ -0,0 +1,85 @@
+// === Proxy path normalization (dot segments, empty segments, percent-encoding) ===
+
+use std::path::{Component, Path};
+
+use crate::error::GatewayError;
+
+/// Reject `%` not followed by two hex digits (checked on the raw, still-encoded path)
+pub fn validate_percent_encoding(raw_path: &str) -> Result<(), GatewayError> {
+    let bytes = raw_path.as_bytes();
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' {
+            let valid = bytes.len() > i + 2
+                && bytes[i + 1].is_ascii_hexdigit()
+                && bytes[i + 2].is_ascii_hexdigit();
+            if !valid {
+                return Err(GatewayError::BadRequest(
+                    "Malformed percent-encoding in path".to_string(),
+                ));
+            }
+            i += 3;
+        } else {
+            i += 1;
+        }
+    }
+    Ok(())
+}
+
+/// Collapse `//`, `.` and `..` in a (decoded) upstream path.
+/// Returns the path without a leading slash; a trailing slash is kept.
+/// A `..` that would climb above the root is a traversal attempt.
+pub fn normalize_path(path: &str) -> Result<String, GatewayError> {
+    let mut segments: Vec<&str> = Vec::new();
+
+    for component in Path::new(path).components() {
+        match component {
+            Component::Normal(segment) => {
+                // Built from a &str, so always valid UTF-8
+                segments.push(segment.to_str().unwrap_or_default());
+            }
+            Component::ParentDir => {
+                if segments.pop().is_none() {
+                    return Err(GatewayError::BadRequest("Path traversal detected".to_string()));
+                }
+            }
+            Component::RootDir | Component::CurDir | Component::Prefix(_) => {}
+        }
+    }
+
+    let mut normalized = segments.join("/");
+    if path.ends_with('/') && !normalized.is_empty() {
+        normalized.push('/');
+    }
+    Ok(normalized)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_collapses_dot_and_empty_segments() {
+        assert_eq!(normalize_path("v1/chat/../chat").unwrap(), "v1/chat");
+        assert_eq!(normalize_path("v1//chat").unwrap(), "v1/chat");
+        assert_eq!(normalize_path("./v1/./models/").unwrap(), "v1/models/");
+    }
+
+    #[test]
+    fn test_traversal_is_rejected() {
+        for path in ["../etc/passwd", "/../etc/passwd", "v1/../../secret"] {
+            assert!(matches!(
+                normalize_path(path),
+                Err(GatewayError::BadRequest(msg)) if msg == "Path traversal detected"
+            ));
+        }
+    }
+
+    #[test]
+    fn test_percent_encoding_validation() {
+        assert!(validate_percent_encoding("/api/svc/v1/a%20b%2F").is_ok());
+        assert!(validate_percent_encoding("/api/svc/v1/100%").is_err());
+        assert!(validate_percent_encoding("/api/svc/v1/%zz").is_err());
+        assert!(validate_percent_encoding("/api/svc/v1/%4").is_err());
+    }
+}
@@ -1,35 +1,246 @@
 // === HTTP proxy with credential injection ===
 
-use axum::http::{HeaderMap, Method};
-use reqwest::Client;
+use axum::body::Bytes;
+use axum::http::{HeaderMap, HeaderName, HeaderValue, Method};
+use hmac::{Hmac, Mac};
+use reqwest::{Client, RequestBuilder};
 use serde_json::Value;
+use sha2::Sha256;
+use std::collections::HashMap;
+use std::error::Error as _;
+use std::sync::Arc;
+use std::time::Duration;
 
-use crate::config::StoredCredential;
+use crate::config::{ServiceConfig, ServiceRegistry, StoredCredential};
 use crate::error::GatewayError;
+use crate::models::ServiceAuthType;
+use super::decompression::{decode_response_body, UPSTREAM_ACCEPT_ENCODING};
+use super::ssrf::{SsrfBlocked, SsrfGuardResolver, SsrfPolicy};
+
+const DEFAULT_CONNECT_TIMEOUT_SECS: u64 = 10;
+const DEFAULT_READ_TIMEOUT_SECS: u64 = 60;
+/// Extra attempts after a read timeout when `retry_on_read_timeout` is set
+const READ_TIMEOUT_RETRIES: u32 = 2;
+/// Upstream response body cap when neither the service nor MAX_RESPONSE_BODY_BYTES sets one
+pub const DEFAULT_MAX_RESPONSE_BODY_BYTES: usize = 10 * 1024 * 1024;
+/// Partial response cap for services with `partial_response_on_timeout` and no `max_partial_response_bytes`
+const DEFAULT_MAX_PARTIAL_RESPONSE_BYTES: usize = 1024 * 1024;
+/// Client headers always forwarded upstream; anything else needs `context_headers_passthrough`
+const DEFAULT_FORWARDED_HEADERS: &[&str] = &[
+    "accept",
+    "accept-language",
+    "cache-control",
+    "content-type",
+    "if-match",
+    "if-modified-since",
+    "if-none-match",
+    "if-unmodified-since",
+    "user-agent",
+    // Correlation ID; the gateway sets it on every request before it gets here
+    "x-request-id",
+];
+/// `sha256=<hex>` HMAC of the body as sent upstream, for services with a `signing_key`
+pub const GATEWAY_SIGNATURE_HEADER: &str = "x-gateway-signature";
+
+type HmacSha256 = Hmac<Sha256>;
+
+// === What the upstream answered ===
+#[derive(Debug)]
+pub enum ProxyResult {
+    /// The whole response: status and JSON body
+    Complete(u16, Value),
+    /// The read timed out mid-body on a service with `partial_response_on_timeout`: the
+    /// text received until then (capped), relayed as a 206 with X-Partial-Response
+    PartialResponse(Value),
+}
+
+impl ProxyResult {
+    /// Status relayed to the agent
+    pub fn status(&self) -> u16 {
+        match self {
+            Self::Complete(status, _) => *status,
+            Self::PartialResponse(_) => 206,
+        }
+    }
+}
+
+// === How reading a response ended ===
+enum Received {
+    // Status, lowercased Content-Encoding and the body as received
+    Body(u16, Option<String>, Bytes),
+    // Past the body limit, abandoned mid-read
+    TooLarge,
+    // The read timed out after the headers and some of the body
+    TimedOut { status: u16, partial: Vec<u8>, error: reqwest::Error },
+}
+
+// === How the stored credential goes upstream ===
+// `Authorization: Bearer {token}` unless the service says otherwise: `auth_header_name`
+// (or an `api_key` auth's `header_name`) picks the header, and API keys are sent bare.
+#[derive(Debug, Clone)]
+pub struct CredentialInjection {
+    header: HeaderName,
+    bearer: bool,
+}
+
+impl Default for CredentialInjection {
+    fn default() -> Self {
+        Self {
+            header: reqwest::header::AUTHORIZATION,
+            bearer: true,
+        }
+    }
+}
+
+impl CredentialInjection {
+    pub fn for_service(service: &ServiceConfig) -> Result<Self, GatewayError> {
+        let api_key_header = match &service.auth {
+            Some(ServiceAuthType::ApiKey { header_name }) => Some(header_name),
+            _ => None,
+        };
+        let bearer = api_key_header.is_none() && service.auth_type != "api_key";
+        let Some(raw) = service.auth_header_name.as_ref().or(api_key_header) else {
+            return Ok(Self { bearer, ..Self::default() });
+        };
+        let header = HeaderName::from_bytes(raw.trim().as_bytes())
+            .ok()
+            .filter(|name| !is_hop_by_hop(name.as_str()) && !matches!(name.as_str(), "host" | "content-length"))
+            .ok_or_else(|| {
+                GatewayError::Internal(format!("Service '{}' has an invalid auth header name '{}'", service.id, raw))
+            })?;
+        Ok(Self { header, bearer })
+    }
+
+    pub fn header(&self) -> &HeaderName {
+        &self.header
+    }
+
+    /// The header's value for `token`
+    pub fn value(&self, token: &str) -> String {
+        if self.bearer {
+            format!("Bearer {}", token)
+        } else {
+            token.to_string()
+        }
+    }
+}
 
 // === Proxy client for forwarding requests ===
 #[derive(Clone)]
 pub struct ProxyClient {
     client: Client,
+    retry_on_read_timeout: bool,
+    // A read timeout mid-body returns what arrived (up to the cap) instead of failing
+    partial_response_on_timeout: bool,
+    max_partial_response_bytes: usize,
+    // Sent as `Host` instead of the one derived from the URL
+    override_host: Option<HeaderValue>,
+    // Client headers forwarded in addition to DEFAULT_FORWARDED_HEADERS
+    passthrough_headers: Vec<HeaderName>,
+    // Responses larger than this are abandoned mid-read with a 502
+    max_response_body_bytes: usize,
+    // Ask for gzip/deflate and decode here, capped at max_response_body_bytes;
+    // otherwise ask for identity
+    accept_compressed_response: bool,
+    // Signs every outbound body (X-Gateway-Signature)
+    signing_key: Option<Vec<u8>>,
+    // Header and format the credential is sent in
+    credential_injection: CredentialInjection,
 }
 
 impl ProxyClient {
     pub fn new() -> Self {
         Self {
             client: Client::new(),
+            retry_on_read_timeout: false,
+            partial_response_on_timeout: false,
+            max_partial_response_bytes: DEFAULT_MAX_PARTIAL_RESPONSE_BYTES,
+            override_host: None,
+            passthrough_headers: Vec::new(),
+            max_response_body_bytes: DEFAULT_MAX_RESPONSE_BODY_BYTES,
+            accept_compressed_response: false,
+            signing_key: None,
+            credential_injection: CredentialInjection::default(),
         }
     }
 
+    // === Dedicated client for one upstream, honoring its protocol and timeout settings ===
+    // Hostnames are resolved through the SSRF guard on every connection. The connect
+    // timeout covers TCP + TLS; the read timeout bounds the whole exchange, body included,
+    // so an upstream trickling bytes can't hold the request open forever.
+    pub fn for_service(service: &ServiceConfig, ssrf: &SsrfPolicy) -> Result<Self, GatewayError> {
+        let mut builder = Client::builder()
+            .dns_resolver(Arc::new(SsrfGuardResolver::new(ssrf.clone())))
+            .connect_timeout(Duration::from_secs(
+                service.connect_timeout_secs.unwrap_or(DEFAULT_CONNECT_TIMEOUT_SECS),
+            ))
+            .timeout(Duration::from_secs(
+                service.read_timeout_secs.unwrap_or(DEFAULT_READ_TIMEOUT_SECS),
+            ));
+        if service.use_http2 {
+            builder = builder.http2_prior_knowledge();
+        }
+
+        let override_host = service
+            .override_host
+            .as_deref()
+            .map(|host| {
+                HeaderValue::from_str(host).map_err(|_| {
+                    GatewayError::Internal(format!(
+                        "Service '{}' has an invalid override_host '{}'",
+                        service.id, host
+                    ))
+                })
+            })
+            .transpose()?;
+        let credential_injection = CredentialInjection::for_service(service)?;
+        let passthrough_headers = passthrough_headers(service, credential_injection.header())?;
+
+        let client = builder.build().map_err(|e| {
+            GatewayError::Internal(format!("Failed to build client for '{}': {}", service.id, e))
+        })?;
+        Ok(Self {
+            client,
+            retry_on_read_timeout: service.retry_on_read_timeout,
+            partial_response_on_timeout: service.partial_response_on_timeout,
+            max_partial_response_bytes: service
+                .max_partial_response_bytes
+                .unwrap_or(DEFAULT_MAX_PARTIAL_RESPONSE_BYTES),
+            override_host,
+            passthrough_headers,
+            max_response_body_bytes: service
+                .max_response_body_bytes
+                .unwrap_or(DEFAULT_MAX_RESPONSE_BODY_BYTES),
+            accept_compressed_response: service.accept_compressed_response,
+            signing_key: service.signing_key.as_ref().map(|key| key.as_bytes().to_vec()),
+            credential_injection,
+        })
+    }
+
+    /// Cap on the upstream response body
+    pub fn with_max_response_body_bytes(mut self, max_bytes: usize) -> Self {
+        self.max_response_body_bytes = max_bytes;
+        self
+    }
+
+    fn forwards_header(&self, name: &HeaderName) -> bool {
+        // Only the gateway vouches for a body
+        if name.as_str() == GATEWAY_SIGNATURE_HEADER {
+            return false;
+        }
+        DEFAULT_FORWARDED_HEADERS.contains(&name.as_str()) || self.passthrough_headers.contains(name)
+    }
+
     // === Forward request to external service with injected credentials ===
     pub async fn forward(
         &self,
         base_url: &str,
         path: &str,
         method: Method,
-        headers: HeaderMap,
-        body: Option<Value>,
+        headers: &HeaderMap,
+        body: Option<&Value>,
         credential: &StoredCredential,
-    ) -> Result<(u16, Value), GatewayError> {
+    ) -> Result<ProxyResult, GatewayError> {
         let url = format!("{}/{}", base_url.trim_end_matches('/'), path);
 
         // Build request
@@ -42,39 +253,157 @@ impl ProxyClient {
             _ => return Err(GatewayError::BadRequest("Unsupported method".to_string())),
         };
 
-        // Inject authorization header
-        request = request.header("Authorization", format!("Bearer {}", credential.access_token));
+        // Inject the credential
+        request = request.header(
+            self.credential_injection.header().clone(),
+            self.credential_injection.value(&credential.access_token),
+        );
 
-        // Forward relevant headers (skip hop-by-hop headers)
+        // Forward only whitelisted client headers; gateway auth and anything unknown stay here
         for (name, value) in headers.iter() {
-            let name_str = name.as_str().to_lowercase();
-            if !is_hop_by_hop(&name_str) && name_str != "host" && name_str != "authorization" {
+            if self.forwards_header(name) {
                 if let Ok(v) = value.to_str() {
                     request = request.header(name.as_str(), v);
                 }
             }
         }
 
+        // Decompression is done here rather than by reqwest so the decoded size can be capped
+        let accept_encoding = if self.accept_compressed_response { UPSTREAM_ACCEPT_ENCODING } else { "identity" };
+        request = request.header(reqwest::header::ACCEPT_ENCODING, accept_encoding);
+
+        // Virtual-hosted upstreams (CDN / SNI load balancers) route on Host
+        if let Some(host) = &self.override_host {
+            request = request.header(reqwest::header::HOST, host.clone());
+        }
+
         // Add body if present
         if let Some(json_body) = body {
-            request = request.json(&json_body);
+            request = request.json(json_body);
         }
 
-        // Execute request
-        let response = request
-            .send()
-            .await
-            .map_err(|e| GatewayError::UpstreamError(format!("Request failed: {}", e)))?;
+        // Sign the body exactly as `json` encodes it (the client's bytes are re-encoded,
+        // so their signature wouldn't hold); no body signs the empty string
+        if let Some(key) = &self.signing_key {
+            let bytes = body
+                .map(serde_json::to_vec)
+                .transpose()
+                .map_err(|e| GatewayError::Internal(format!("Failed to encode request body: {}", e)))?
+                .unwrap_or_default();
+            request = request.header(GATEWAY_SIGNATURE_HEADER, sign_body(key, &bytes));
+        }
+
+        // Execute request; only read timeouts are worth another attempt
+        let mut retries = 0;
+        let (status, encoding, bytes) = loop {
+            let attempt = request
+                .try_clone()
+                .ok_or_else(|| GatewayError::Internal("Request cannot be retried".to_string()))?;
 
-        let status = response.status().as_u16();
+            let error = match send(attempt, self.max_response_body_bytes).await {
+                Ok(Received::Body(status, encoding, bytes)) => break (status, encoding, bytes),
+                Ok(Received::TooLarge) => {
+                    tracing::warn!(url = %url, limit = self.max_response_body_bytes, "Upstream response too large");
+                    return Err(GatewayError::UpstreamError(format!(
+                        "Response too large (limit {} bytes)",
+                        self.max_response_body_bytes
+                    )));
+                }
+                // What already arrived is worth more to a streaming client than a retry
+                Ok(Received::TimedOut { status, partial, error }) => {
+                    if self.partial_response_on_timeout && (200..300).contains(&status) {
+                        tracing::warn!(url = %url, received = partial.len(), "Upstream read timed out, returning the partial response");
+                        return Ok(self.partial_response(partial));
+                    }
+                    error
+                }
+                Err(e) => e,
+            };
+            if is_read_timeout(&error) && self.retry_on_read_timeout && retries < READ_TIMEOUT_RETRIES {
+                retries += 1;
+                tracing::warn!(url = %url, attempt = retries, "Upstream read timed out, retrying");
+                continue;
+            }
+            return Err(upstream_error(error));
+        };
+
+        // Only decode what was asked for; anything else is passed on as before
+        let bytes = if self.accept_compressed_response {
+            decode_response_body(encoding.as_deref(), bytes, self.max_response_body_bytes).inspect_err(|e| {
+                tracing::warn!(url = %url, error = ?e, "Failed to decode upstream response");
+            })?
+        } else {
+            bytes
+        };
 
         // Parse response body
-        let body: Value = response
-            .json()
-            .await
+        let body: Value = serde_json::from_slice(&bytes)
             .unwrap_or_else(|_| serde_json::json!({"raw": "non-json response"}));
 
-        Ok((status, body))
+        Ok(ProxyResult::Complete(status, body))
+    }
+
+    // A stream cut short is rarely valid JSON, so it is relayed as text; a character
+    // split by the cap or the timeout becomes U+FFFD
+    fn partial_response(&self, mut partial: Vec<u8>) -> ProxyResult {
+        partial.truncate(self.max_partial_response_bytes);
+        ProxyResult::PartialResponse(Value::String(String::from_utf8_lossy(&partial).into_owned()))
+    }
+}
+
+// === Send and read the full body, so a stall mid-body also surfaces as a timeout ===
+// The body is read chunk by chunk and abandoned (`TooLarge`) as soon as it passes
+// `max_bytes`. A read timeout after some of the body keeps what arrived.
+async fn send(request: RequestBuilder, max_bytes: usize) -> Result<Received, reqwest::Error> {
+    let mut response = request.send().await?;
+    let status = response.status().as_u16();
+    let encoding = response
+        .headers()
+        .get(reqwest::header::CONTENT_ENCODING)
+        .and_then(|v| v.to_str().ok())
+        .map(|v| v.trim().to_lowercase());
+    // A declared length over the limit fails before reading anything
+    if response.content_length().is_some_and(|len| len > max_bytes as u64) {
+        return Ok(Received::TooLarge);
+    }
+
+    let mut body = Vec::new();
+    loop {
+        match response.chunk().await {
+            Ok(Some(chunk)) => {
+                if body.len() + chunk.len() > max_bytes {
+                    return Ok(Received::TooLarge);
+                }
+                body.extend_from_slice(&chunk);
+            }
+            Ok(None) => return Ok(Received::Body(status, encoding, Bytes::from(body))),
+            Err(error) if is_read_timeout(&error) && !body.is_empty() => {
+                return Ok(Received::TimedOut { status, partial: body, error });
+            }
+            Err(error) => return Err(error),
+        }
+    }
+}
+
+// Connect timeouts report both is_connect() and is_timeout(); they are not read timeouts
+fn is_read_timeout(e: &reqwest::Error) -> bool {
+    e.is_timeout() && !e.is_connect()
+}
+
+// === Map transport failures to errors clients can act on ===
+fn upstream_error(e: reqwest::Error) -> GatewayError {
+    if let Some(blocked) = ssrf_blocked(&e) {
+        return GatewayError::UpstreamError(blocked.to_string());
+    }
+    if e.is_connect() {
+        // Unreachable host / refused / connect timeout: infrastructure, not transient
+        GatewayError::UpstreamUnavailable(format!("Upstream unreachable: {}", e))
+    } else if e.is_timeout() {
+        GatewayError::UpstreamTimeout(format!("Upstream timed out: {}", e))
+    } else if e.is_request() {
+        GatewayError::UpstreamError(format!("Request failed: {}", e))
+    } else {
+        GatewayError::UpstreamError(format!("Upstream response failed: {}", e))
     }
 }
 
@@ -84,6 +413,37 @@ impl Default for ProxyClient {
     }
 }
 
+// === Validate a service's context_headers_passthrough ===
+// Headers the gateway sets itself (or that describe the connection) can never be passed through.
+fn passthrough_headers(service: &ServiceConfig, auth_header: &HeaderName) -> Result<Vec<HeaderName>, GatewayError> {
+    service
+        .context_headers_passthrough
+        .iter()
+        .map(|raw| {
+            let name = HeaderName::from_bytes(raw.trim().as_bytes()).map_err(|_| {
+                GatewayError::Internal(format!(
+                    "Service '{}' has an invalid context header '{}'",
+                    service.id, raw
+                ))
+            })?;
+            // The client mustn't be able to send its own credential alongside the gateway's
+            let reserved = is_hop_by_hop(name.as_str())
+                || name == auth_header
+                || matches!(
+                    name.as_str(),
+                    "host" | "authorization" | "accept-encoding" | "content-length" | "content-encoding"
+                );
+            if reserved {
+                return Err(GatewayError::Internal(format!(
+                    "Service '{}' cannot pass through reserved header '{}'",
+                    service.id, name
+                )));
+            }
+            Ok(name)
+        })
+        .collect()
+}
+
 // === Check if header is hop-by-hop (should not be forwarded) ===
 fn is_hop_by_hop(name: &str) -> bool {
     matches!(
@@ -98,3 +458,505 @@ fn is_hop_by_hop(name: &str) -> bool {
             | "upgrade"
     )
 }
+
+// === Find a resolver rejection anywhere in the error's source chain ===
+fn ssrf_blocked(e: &reqwest::Error) -> Option<&SsrfBlocked> {
+    let mut source = e.source();
+    while let Some(err) = source {
+        if let Some(blocked) = err.downcast_ref::<SsrfBlocked>() {
+            return Some(blocked);
+        }
+        source = err.source();
+    }
+    None
+}
+
+// === One client per configured service, reused across requests ===
+// `max_response_body_bytes` applies to services that don't set their own limit.
+pub fn build_proxy_clients(
+    services: &ServiceRegistry,
+    ssrf: &SsrfPolicy,
+    max_response_body_bytes: usize,
+) -> Result<HashMap<String, ProxyClient>, GatewayError> {
+    services
+        .list()
+        .into_iter()
+        .map(|service| {
+            let client = ProxyClient::for_service(service, ssrf)?.with_max_response_body_bytes(
+                service.max_response_body_bytes.unwrap_or(max_response_body_bytes),
+            );
+            Ok((service.id.clone(), client))
+        })
+        .collect()
+}
+
+/// `sha256=<hex>` of HMAC-SHA256(`key`, `body`)
+pub fn sign_body(key: &[u8], body: &[u8]) -> String {
+    let mut mac = HmacSha256::new_from_slice(key).expect("HMAC accepts any key length");
+    mac.update(body);
+    format!("sha256={}", hex::encode(mac.finalize().into_bytes()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::Version;
+    use serde_json::json;
+    use wiremock::matchers::{header, header_exists, headers, method};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    fn service_with(extra: Value) -> ServiceConfig {
+        let mut config = json!({
+            "id": "fast",
+            "name": "Fast",
+            "description": "",
+            "base_url": "http://localhost",
+            "auth_type": "bearer",
+            "endpoints": [],
+            "rate_limit": { "requests": 10, "window_secs": 60 }
+        });
+        config.as_object_mut().unwrap().extend(extra.as_object().unwrap().clone());
+        serde_json::from_value(config).unwrap()
+    }
+
+    fn service(use_http2: bool) -> ServiceConfig {
+        service_with(json!({ "use_http2": use_http2 }))
+    }
+
+    fn credential() -> StoredCredential {
+        StoredCredential {
+            service_id: "fast".to_string(),
+            access_token: "token".to_string(),
+            refresh_token: None,
+            expires_at: None,
+            scopes: vec![],
+        }
+    }
+
+    async fn forward(proxy: &ProxyClient, base_url: &str) -> Result<ProxyResult, GatewayError> {
+        proxy
+            .forward(base_url, "v1", Method::GET, &HeaderMap::new(), None, &credential())
+            .await
+    }
+
+    async fn stalled_upstream(expected_requests: u64) -> MockServer {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .respond_with(ResponseTemplate::new(200).set_delay(Duration::from_secs(3)))
+            .expect(expected_requests)
+            .mount(&server)
+            .await;
+        server
+    }
+
+    async fn version_used(use_http2: bool) -> Version {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .respond_with(ResponseTemplate::new(200))
+            .mount(&server)
+            .await;
+
+        let proxy = ProxyClient::for_service(&service(use_http2), &SsrfPolicy::default()).unwrap();
+        proxy.client.get(server.uri()).send().await.unwrap().version()
+    }
+
+    #[tokio::test]
+    async fn test_http2_service_uses_http2() {
+        assert_eq!(version_used(true).await, Version::HTTP_2);
+    }
+
+    #[tokio::test]
+    async fn test_default_service_uses_http1() {
+        assert_eq!(version_used(false).await, Version::HTTP_11);
+    }
+
+    #[tokio::test]
+    async fn test_read_timeout_is_gateway_timeout_without_retry() {
+        let server = stalled_upstream(1).await;
+        let proxy = ProxyClient::for_service(&service_with(json!({ "read_timeout_secs": 1 })), &SsrfPolicy::default())
+                .unwrap();
+
+        let result = forward(&proxy, &server.uri()).await;
+        assert!(matches!(result, Err(GatewayError::UpstreamTimeout(_))));
+    }
+
+    #[tokio::test]
+    async fn test_read_timeout_is_retried_when_enabled() {
+        // First attempt plus READ_TIMEOUT_RETRIES; verified when the server drops
+        let server = stalled_upstream(1 + READ_TIMEOUT_RETRIES as u64).await;
+        let proxy = ProxyClient::for_service(
+            &service_with(json!({ "read_timeout_secs": 1, "retry_on_read_timeout": true })),
+            &SsrfPolicy::default(),
+        )
+        .unwrap();
+
+        let result = forward(&proxy, &server.uri()).await;
+        assert!(matches!(result, Err(GatewayError::UpstreamTimeout(_))));
+    }
+
+    #[tokio::test]
+    async fn test_trickling_response_hits_read_timeout() {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        // A byte every 300ms: no single read stalls, but the response never finishes in time
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let _ = socket.read(&mut [0u8; 4096]).await;
+            let head = "HTTP/1.1 200 OK\r\ncontent-type: application/json\r\ncontent-length: 100\r\n\r\n";
+            socket.write_all(head.as_bytes()).await.unwrap();
+            for _ in 0..100 {
+                tokio::time::sleep(Duration::from_millis(300)).await;
+                if socket.write_all(b" ").await.is_err() {
+                    break;
+                }
+            }
+        });
+
+        let proxy = ProxyClient::for_service(&service_with(json!({ "read_timeout_secs": 1 })), &SsrfPolicy::default())
+            .unwrap();
+        let result = forward(&proxy, &format!("http://{}", addr)).await;
+        assert!(matches!(result, Err(GatewayError::UpstreamTimeout(_))));
+    }
+
+    // Streams `sent` as one chunk of a chunked body, then stalls without ending it
+    async fn stalling_stream(sent: &'static str) -> String {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            while let Ok((mut socket, _)) = listener.accept().await {
+                let _ = socket.read(&mut [0u8; 4096]).await;
+                let head = "HTTP/1.1 200 OK\r\ncontent-type: text/event-stream\r\ntransfer-encoding: chunked\r\n\r\n";
+                let chunk = format!("{}{:x}\r\n{}\r\n", head, sent.len(), sent);
+                socket.write_all(chunk.as_bytes()).await.unwrap();
+                tokio::spawn(async move {
+                    tokio::time::sleep(Duration::from_secs(5)).await;
+                    drop(socket);
+                });
+            }
+        });
+        format!("http://{}", addr)
+    }
+
+    #[tokio::test]
+    async fn test_read_timeout_returns_partial_response_when_enabled() {
+        let url = stalling_stream("data: {\"token\": \"Hel\"}\n\n").await;
+
+        let proxy = ProxyClient::for_service(
+            &service_with(json!({ "read_timeout_secs": 1, "partial_response_on_timeout": true })),
+            &SsrfPolicy::default(),
+        )
+        .unwrap();
+        let result = forward(&proxy, &url).await.unwrap();
+        assert_eq!(result.status(), 206);
+        assert!(matches!(result, ProxyResult::PartialResponse(Value::String(text)) if text == "data: {\"token\": \"Hel\"}\n\n"));
+
+        let capped = ProxyClient::for_service(
+            &service_with(json!({
+                "read_timeout_secs": 1,
+                "partial_response_on_timeout": true,
+                "max_partial_response_bytes": 5
+            })),
+            &SsrfPolicy::default(),
+        )
+        .unwrap();
+        let result = forward(&capped, &url).await.unwrap();
+        assert!(matches!(result, ProxyResult::PartialResponse(Value::String(text)) if text == "data:"));
+
+        // Without the option the same stall is a plain timeout
+        let strict = ProxyClient::for_service(&service_with(json!({ "read_timeout_secs": 1 })), &SsrfPolicy::default())
+            .unwrap();
+        assert!(matches!(forward(&strict, &url).await, Err(GatewayError::UpstreamTimeout(_))));
+    }
+
+    #[tokio::test]
+    async fn test_connect_failure_is_unavailable_and_not_retried() {
+        // Bind then drop a listener to get a port nothing is listening on
+        let port = std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+        let proxy = ProxyClient::for_service(
+            &service_with(json!({ "retry_on_read_timeout": true })),
+            &SsrfPolicy::default(),
+        )
+        .unwrap();
+
+        let result = forward(&proxy, &format!("http://127.0.0.1:{}", port)).await;
+        assert!(matches!(result, Err(GatewayError::UpstreamUnavailable(_))));
+    }
+
+    #[tokio::test]
+    async fn test_hostname_resolving_to_private_ip_is_blocked() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .respond_with(ResponseTemplate::new(200))
+            .mount(&server)
+            .await;
+        // Same server, reached by name so the guarded resolver runs
+        let by_name = server.uri().replace("127.0.0.1", "localhost");
+
+        let guarded = ProxyClient::for_service(&service(false), &SsrfPolicy::default()).unwrap();
+        let result = forward(&guarded, &by_name).await;
+        assert!(matches!(result, Err(GatewayError::UpstreamError(msg)) if msg.contains("blocked address")));
+
+        let allowlist = SsrfPolicy::from_allowlist(&["127.0.0.1".to_string(), "::1".to_string()]).unwrap();
+        let allowed = ProxyClient::for_service(&service(false), &allowlist).unwrap();
+        assert_eq!(forward(&allowed, &by_name).await.unwrap().status(), 200);
+    }
+
+    #[tokio::test]
+    async fn test_override_host_is_sent_upstream() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(header("host", "api.cdn-fronted.example"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({"ok": true})))
+            .expect(1)
+            .mount(&server)
+            .await;
+        let proxy = ProxyClient::for_service(
+            &service_with(json!({ "override_host": "api.cdn-fronted.example" })),
+            &SsrfPolicy::default(),
+        )
+        .unwrap();
+
+        assert_eq!(forward(&proxy, &server.uri()).await.unwrap().status(), 200);
+    }
+
+    #[tokio::test]
+    async fn test_host_derived_from_url_by_default() {
+        let server = MockServer::start().await;
+        let expected = server.address().to_string();
+        Mock::given(method("GET"))
+            .and(header("host", expected.as_str()))
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&server)
+            .await;
+        let proxy = ProxyClient::for_service(&service(false), &SsrfPolicy::default()).unwrap();
+
+        let mut headers = HeaderMap::new();
+        headers.insert("host", HeaderValue::from_static("gateway.local"));
+        let result = proxy
+            .forward(&server.uri(), "v1", Method::GET, &headers, None, &credential())
+            .await;
+        assert_eq!(result.unwrap().status(), 200);
+    }
+
+    #[tokio::test]
+    async fn test_only_whitelisted_headers_reach_upstream() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(header("x-conversation-id", "conv-42"))
+            .and(header("accept", "application/json"))
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&server)
+            .await;
+        // Leaking the gateway session or an unlisted header turns the answer into a 400
+        for leaked in ["x-session-id", "x-workflow-run"] {
+            Mock::given(header_exists(leaked))
+                .respond_with(ResponseTemplate::new(400))
+                .with_priority(1)
+                .mount(&server)
+                .await;
+        }
+        let proxy = ProxyClient::for_service(
+            &service_with(json!({ "context_headers_passthrough": ["X-Conversation-Id"] })),
+            &SsrfPolicy::default(),
+        )
+        .unwrap();
+
+        let mut headers = HeaderMap::new();
+        headers.insert("accept", HeaderValue::from_static("application/json"));
+        headers.insert("x-conversation-id", HeaderValue::from_static("conv-42"));
+        headers.insert("x-workflow-run", HeaderValue::from_static("run-7"));
+        headers.insert("x-session-id", HeaderValue::from_static("secret-session"));
+        let result = proxy
+            .forward(&server.uri(), "v1", Method::GET, &headers, None, &credential())
+            .await;
+        assert_eq!(result.unwrap().status(), 200);
+    }
+
+    #[tokio::test]
+    async fn test_credential_goes_in_the_configured_header() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(header("x-auth-token", "Bearer token"))
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(header_exists("authorization"))
+            .respond_with(ResponseTemplate::new(400))
+            .with_priority(1)
+            .mount(&server)
+            .await;
+        let proxy = ProxyClient::for_service(
+            &service_with(json!({ "auth_header_name": "X-Auth-Token" })),
+            &SsrfPolicy::default(),
+        )
+        .unwrap();
+        assert_eq!(forward(&proxy, &server.uri()).await.unwrap().status(), 200);
+
+        // API keys are sent bare
+        let api_key = service_with(json!({ "auth_type": "api_key", "auth_header_name": "Api-Key" }));
+        let injection = CredentialInjection::for_service(&api_key).unwrap();
+        assert_eq!((injection.header().as_str(), injection.value("k").as_str()), ("api-key", "k"));
+        let api_key = service_with(json!({ "auth": { "type": "api_key", "header_name": "X-Api-Key" } }));
+        let injection = CredentialInjection::for_service(&api_key).unwrap();
+        assert_eq!((injection.header().as_str(), injection.value("k").as_str()), ("x-api-key", "k"));
+        let default = CredentialInjection::for_service(&service(false)).unwrap();
+        assert_eq!((default.header().as_str(), default.value("k").as_str()), ("authorization", "Bearer k"));
+
+        for name in ["bad header", "Host", "Connection"] {
+            let config = service_with(json!({ "auth_header_name": name }));
+            assert!(ProxyClient::for_service(&config, &SsrfPolicy::default()).is_err(), "{}", name);
+        }
+        // The client can't pass its own value for the credential header
+        let config = service_with(json!({
+            "auth_header_name": "X-Auth-Token",
+            "context_headers_passthrough": ["x-auth-token"]
+        }));
+        assert!(ProxyClient::for_service(&config, &SsrfPolicy::default()).is_err());
+    }
+
+    #[test]
+    fn test_reserved_passthrough_headers_are_rejected() {
+        for name in ["Authorization", "host", "transfer-encoding", "bad header"] {
+            let config = service_with(json!({ "context_headers_passthrough": [name] }));
+            assert!(ProxyClient::for_service(&config, &SsrfPolicy::default()).is_err(), "{}", name);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_response_over_limit_is_bad_gateway() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(" ".repeat(64 * 1024)))
+            .mount(&server)
+            .await;
+
+        let limited = ProxyClient::for_service(
+            &service_with(json!({ "max_response_body_bytes": 1024 })),
+            &SsrfPolicy::default(),
+        )
+        .unwrap();
+        let result = forward(&limited, &server.uri()).await;
+        assert!(matches!(result, Err(GatewayError::UpstreamError(msg)) if msg.starts_with("Response too large")));
+
+        let default = ProxyClient::for_service(&service(false), &SsrfPolicy::default()).unwrap();
+        assert_eq!(forward(&default, &server.uri()).await.unwrap().status(), 200);
+    }
+
+    #[tokio::test]
+    async fn test_compressed_response_is_decoded_within_limit() {
+        use flate2::write::GzEncoder;
+        use flate2::Compression;
+        use std::io::Write;
+
+        fn gzip(data: &[u8]) -> Vec<u8> {
+            let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+            encoder.write_all(data).unwrap();
+            encoder.finish().unwrap()
+        }
+
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(headers("accept-encoding", vec!["gzip", "deflate"]))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .insert_header("content-encoding", "gzip")
+                    .set_body_raw(gzip(br#"{"answer":42}"#), "application/json"),
+            )
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(header("accept-encoding", "identity"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({ "answer": 0 })))
+            .mount(&server)
+            .await;
+
+        let compressed = ProxyClient::for_service(
+            &service_with(json!({ "accept_compressed_response": true })),
+            &SsrfPolicy::default(),
+        )
+        .unwrap();
+        let result = forward(&compressed, &server.uri()).await.unwrap();
+        assert!(matches!(result, ProxyResult::Complete(200, body) if body == json!({ "answer": 42 })));
+
+        let plain = ProxyClient::for_service(&service(false), &SsrfPolicy::default()).unwrap();
+        let result = forward(&plain, &server.uri()).await.unwrap();
+        assert!(matches!(result, ProxyResult::Complete(200, body) if body == json!({ "answer": 0 })));
+
+        // A few hundred compressed bytes that expand past the limit
+        let bomb = MockServer::start().await;
+        Mock::given(method("GET"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .insert_header("content-encoding", "gzip")
+                    .set_body_raw(gzip(&vec![b' '; 64 * 1024]), "application/json"),
+            )
+            .mount(&bomb)
+            .await;
+        let limited = ProxyClient::for_service(
+            &service_with(json!({ "accept_compressed_response": true, "max_response_body_bytes": 1024 })),
+            &SsrfPolicy::default(),
+        )
+        .unwrap();
+        let result = forward(&limited, &bomb.uri()).await;
+        assert!(matches!(result, Err(GatewayError::UpstreamError(msg)) if msg == "Decompressed response too large"));
+    }
+
+    #[tokio::test]
+    async fn test_endless_response_stops_reading_at_limit() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        // Chunked body with no end and no Content-Length: only a reader that gives up
+        // mid-stream ever returns. Counts what the upstream managed to send.
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let sent = Arc::new(AtomicUsize::new(0));
+        let counter = sent.clone();
+        tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let _ = socket.read(&mut [0u8; 4096]).await;
+            let head = "HTTP/1.1 200 OK\r\ncontent-type: application/json\r\ntransfer-encoding: chunked\r\n\r\n";
+            socket.write_all(head.as_bytes()).await.unwrap();
+            let chunk = [b' '; 64 * 1024];
+            loop {
+                let frame = format!("{:x}\r\n", chunk.len());
+                let written = async {
+                    socket.write_all(frame.as_bytes()).await?;
+                    socket.write_all(&chunk).await?;
+                    socket.write_all(b"\r\n").await
+                };
+                if written.await.is_err() {
+                    break;
+                }
+                counter.fetch_add(chunk.len(), Ordering::Relaxed);
+            }
+        });
+
+        let limit = 256 * 1024;
+        let proxy = ProxyClient::for_service(&service(false), &SsrfPolicy::default())
+            .unwrap()
+            .with_max_response_body_bytes(limit);
+        let result = tokio::time::timeout(Duration::from_secs(10), forward(&proxy, &format!("http://{}", addr)))
+            .await
+            .expect("reading should stop at the limit");
+        assert!(matches!(result, Err(GatewayError::UpstreamError(msg)) if msg.starts_with("Response too large")));
+
+        // Beyond the limit, the upstream only got as far as the socket buffers allow
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        let sent = sent.load(Ordering::Relaxed);
+        assert!(sent < limit + 16 * 1024 * 1024, "upstream sent {} bytes", sent);
+    }
+
+    #[test]
+    fn test_invalid_override_host_is_rejected() {
+        let config = service_with(json!({ "override_host": "bad\nhost" }));
+        assert!(ProxyClient::for_service(&config, &SsrfPolicy::default()).is_err());
+    }
+}
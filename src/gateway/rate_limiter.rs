@@ -1,11 +1,13 @@
-// === Sliding window rate limiter for agents and services ===
+// === Sliding window rate limiter for users, agents and services ===
 
 use std::collections::HashMap;
 use std::sync::Arc;
 use std::time::{Duration, Instant};
 use tokio::sync::RwLock;
 
-use crate::error::GatewayError;
+use crate::error::{GatewayError, RateLimitScope};
+use crate::models::RateLimit;
+use super::RecentCounter;
 
 // === Rate limit configuration ===
 #[derive(Clone)]
@@ -14,6 +16,15 @@ pub struct RateLimitConfig {
     pub window: Duration,
 }
 
+impl From<&RateLimit> for RateLimitConfig {
+    fn from(limit: &RateLimit) -> Self {
+        Self {
+            requests: limit.requests,
+            window: Duration::from_secs(limit.window_secs),
+        }
+    }
+}
+
 impl Default for RateLimitConfig {
     fn default() -> Self {
         Self {
@@ -26,11 +37,18 @@ impl Default for RateLimitConfig {
 // === Rate limiter with sliding window ===
 #[derive(Clone)]
 pub struct RateLimiter {
-    // Key: identifier (agent_id or service_id), Value: list of request timestamps
+    // Key: identifier (user_id, agent_id or service_id), Value: list of request timestamps
     windows: Arc<RwLock<HashMap<String, Vec<Instant>>>>,
     // Default limits (public for testing)
+    // Aggregate over all agents of one user, so spreading load across agents doesn't help
+    pub user_limit: RateLimitConfig,
     pub agent_limit: RateLimitConfig,
-    pub service_limits: HashMap<String, RateLimitConfig>,
+    // From the services file; replaced whole when it is reloaded
+    service_limits: Arc<std::sync::RwLock<HashMap<String, RateLimitConfig>>>,
+    // Quotas shared by every agent in a group, keyed by group name
+    pub group_limits: HashMap<String, RateLimitConfig>,
+    // Requests turned away, per minute
+    rejections: RecentCounter,
 }
 
 impl RateLimiter {
@@ -56,34 +74,116 @@ impl RateLimiter {
 
         Self {
             windows: Arc::new(RwLock::new(HashMap::new())),
+            user_limit: RateLimitConfig {
+                requests: 1000,
+                window: Duration::from_secs(60),
+            },
             agent_limit: RateLimitConfig {
                 requests: 200,
                 window: Duration::from_secs(60),
             },
-            service_limits,
+            service_limits: Arc::new(std::sync::RwLock::new(service_limits)),
+            group_limits: HashMap::new(),
+            rejections: RecentCounter::new(),
         }
     }
 
-    // === Check if request is allowed for agent ===
-    pub async fn check_agent(&self, agent_id: &str) -> Result<(), GatewayError> {
-        self.check_limit(&format!("agent:{}", agent_id), &self.agent_limit)
+    // === Create rate limiter with shared group quotas ===
+    pub fn with_group_limits(group_limits: HashMap<String, RateLimitConfig>) -> Self {
+        Self {
+            group_limits,
+            ..Self::new()
+        }
+    }
+
+    // === Replace the per-service limits (services file loaded or reloaded) ===
+    // Services without an entry get the default limit; their windows are kept.
+    pub fn set_service_limits(&self, limits: HashMap<String, RateLimitConfig>) {
+        if let Ok(mut service_limits) = self.service_limits.write() {
+            *service_limits = limits;
+        }
+    }
+
+    // === Set the per-user aggregate limit ===
+    pub fn with_user_limit(mut self, user_limit: RateLimitConfig) -> Self {
+        self.user_limit = user_limit;
+        self
+    }
+
+    // === Check if request is allowed for the agent's owner ===
+    pub async fn check_user(&self, user_id: &str) -> Result<(), GatewayError> {
+        self.check_limit(&format!("user:{}", user_id), &self.user_limit, RateLimitScope::User)
             .await
     }
 
+    // === Check if request is allowed for agent, at the default agent limit ===
+    #[allow(dead_code)]
+    pub async fn check_agent(&self, agent_id: &str) -> Result<(), GatewayError> {
+        self.check_agent_with_group(agent_id, &self.agent_limit, None).await
+    }
+
+    // === Check the agent's own limit and, if the agent is grouped, the shared group limit ===
+    // Both windows are checked before either is recorded, so a request rejected
+    // by the group does not consume the agent's own quota.
+    pub async fn check_agent_with_group(
+        &self,
+        agent_id: &str,
+        agent_limit: &RateLimitConfig,
+        group: Option<&str>,
+    ) -> Result<(), GatewayError> {
+        let group_limit = group.and_then(|g| self.group_limits.get(g).map(|l| (g, l)));
+        let Some((group, group_limit)) = group_limit else {
+            return self
+                .check_limit(&format!("agent:{}", agent_id), agent_limit, RateLimitScope::Agent)
+                .await;
+        };
+
+        let now = Instant::now();
+        let agent_key = format!("agent:{}", agent_id);
+        let group_key = format!("group:{}", group);
+
+        let mut windows = self.windows.write().await;
+        let layers = [
+            (&agent_key, agent_limit, RateLimitScope::Agent),
+            (&group_key, group_limit, RateLimitScope::Group),
+        ];
+        for (key, config, scope) in layers {
+            let window_start = now - config.window;
+            let timestamps = windows.entry(key.clone()).or_insert_with(Vec::new);
+            timestamps.retain(|&t| t > window_start);
+            if timestamps.len() >= config.requests as usize {
+                self.rejections.record();
+                return Err(GatewayError::RateLimitExceeded(scope));
+            }
+        }
+
+        for key in [agent_key, group_key] {
+            windows.entry(key).or_insert_with(Vec::new).push(now);
+        }
+
+        Ok(())
+    }
+
     // === Check if request is allowed for service ===
     pub async fn check_service(&self, service_id: &str) -> Result<(), GatewayError> {
         let limit = self
             .service_limits
-            .get(service_id)
-            .cloned()
+            .read()
+            .ok()
+            .and_then(|limits| limits.get(service_id).cloned())
             .unwrap_or_default();
 
-        self.check_limit(&format!("service:{}", service_id), &limit)
+        self.check_limit(&format!("service:{}", service_id), &limit, RateLimitScope::Service)
             .await
     }
 
     // === Core rate limit check with sliding window ===
-    async fn check_limit(&self, key: &str, config: &RateLimitConfig) -> Result<(), GatewayError> {
+    async fn check_limit(
+        &self,
+        key: &str,
+        config: &RateLimitConfig,
+        scope: RateLimitScope,
+    ) -> Result<(), GatewayError> {
         let now = Instant::now();
         let window_start = now - config.window;
 
@@ -95,7 +195,8 @@ impl RateLimiter {
 
         // Check if limit exceeded
         if timestamps.len() >= config.requests as usize {
-            return Err(GatewayError::RateLimitExceeded);
+            self.rejections.record();
+            return Err(GatewayError::RateLimitExceeded(scope));
         }
 
         // Record this request
@@ -104,6 +205,11 @@ impl RateLimiter {
         Ok(())
     }
 
+    /// Requests rejected by any user, agent, group or service limit over the last hour
+    pub fn rejections_last_hour(&self) -> u64 {
+        self.rejections.last_hour()
+    }
+
     /// Get remaining requests for a key (for future rate limit monitoring)
     #[allow(dead_code)]
     pub async fn remaining(&self, key: &str, config: &RateLimitConfig) -> u32 {
@@ -152,4 +258,23 @@ mod tests {
         assert!(limiter.check_agent("test-agent").await.is_ok());
         assert!(limiter.check_agent("test-agent").await.is_err());
     }
+
+    #[tokio::test]
+    async fn test_user_limit_spans_all_agents_of_the_user() {
+        let limiter = RateLimiter::new().with_user_limit(RateLimitConfig {
+            requests: 3,
+            window: Duration::from_secs(60),
+        });
+
+        // Each agent is far below its own limit; together they exhaust the user's
+        for agent in ["a", "b", "c"] {
+            assert!(limiter.check_user("owner").await.is_ok());
+            assert!(limiter.check_agent(agent).await.is_ok());
+        }
+        assert!(matches!(
+            limiter.check_user("owner").await,
+            Err(GatewayError::RateLimitExceeded(RateLimitScope::User))
+        ));
+        assert!(limiter.check_user("someone-else").await.is_ok());
+    }
 }
@@ -0,0 +1,146 @@
+// === One token refresh per service at a time ===
+// A credential shared by many agents enters its refresh window for all of
+// their requests at once. The first request to notice refreshes it; the
+// others wait for that refresh and use whatever it stored, so the token
+// endpoint sees one call per service per expiry. With a cooldown
+// (TOKEN_REFRESH_COOLDOWN_SECS), a service refreshed within it, successfully
+// or not, isn't refreshed again until it ends.
+
+use dashmap::mapref::entry::Entry;
+use dashmap::DashMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::watch;
+
+/// Longest a request waits on another's refresh before using the stored credential
+const REFRESH_WAIT: Duration = Duration::from_secs(10);
+
+enum Slot {
+    // Refresh in flight; its sender is dropped when it finishes
+    Refreshing(watch::Receiver<()>),
+    // Last refresh finished at, kept only while a cooldown is configured
+    Refreshed(Instant),
+}
+
+/// What a request whose credential is due for refresh should do
+pub enum RefreshTurn {
+    /// Refresh and store the credential, then drop the permit to release the waiters
+    Refresh(RefreshPermit),
+    /// Another request refreshed it (or tried to) just now: use the stored credential
+    UseStored,
+}
+
+#[derive(Clone)]
+pub struct RefreshCoordinator {
+    slots: Arc<DashMap<String, Slot>>,
+    cooldown: Duration,
+}
+
+impl RefreshCoordinator {
+    pub fn new(cooldown: Duration) -> Self {
+        Self {
+            slots: Arc::new(DashMap::new()),
+            cooldown,
+        }
+    }
+
+    /// Take the refresh of `service_id`, or wait for the one in flight to finish
+    pub async fn acquire(&self, service_id: &str) -> RefreshTurn {
+        let mut done = match self.slots.entry(service_id.to_string()) {
+            Entry::Occupied(mut entry) => match entry.get() {
+                Slot::Refreshing(done) => done.clone(),
+                Slot::Refreshed(at) if at.elapsed() < self.cooldown => return RefreshTurn::UseStored,
+                Slot::Refreshed(_) => {
+                    let permit = self.permit(service_id, |slot| {
+                        entry.insert(slot);
+                    });
+                    return RefreshTurn::Refresh(permit);
+                }
+            },
+            Entry::Vacant(entry) => {
+                let permit = self.permit(service_id, |slot| {
+                    entry.insert(slot);
+                });
+                return RefreshTurn::Refresh(permit);
+            }
+        };
+        // The map entry is released before waiting; `changed` fails once the permit is dropped
+        if tokio::time::timeout(REFRESH_WAIT, done.changed()).await.is_err() {
+            tracing::warn!(service = %service_id, "Token refresh still running; using the stored credential");
+        }
+        RefreshTurn::UseStored
+    }
+
+    fn permit(&self, service_id: &str, claim: impl FnOnce(Slot)) -> RefreshPermit {
+        let (sender, receiver) = watch::channel(());
+        claim(Slot::Refreshing(receiver));
+        RefreshPermit {
+            coordinator: self.clone(),
+            service_id: service_id.to_string(),
+            _done: sender,
+        }
+    }
+}
+
+/// Held by the request refreshing a service's credential. Waiters wake when it
+/// is dropped, so it must outlive the store update.
+pub struct RefreshPermit {
+    coordinator: RefreshCoordinator,
+    service_id: String,
+    _done: watch::Sender<()>,
+}
+
+impl Drop for RefreshPermit {
+    fn drop(&mut self) {
+        // The slot is settled before the sender drops and wakes the waiters
+        let RefreshCoordinator { slots, cooldown } = &self.coordinator;
+        if cooldown.is_zero() {
+            slots.remove(&self.service_id);
+        } else {
+            slots.insert(self.service_id.clone(), Slot::Refreshed(Instant::now()));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    #[tokio::test]
+    async fn test_concurrent_requests_refresh_once() {
+        let coordinator = RefreshCoordinator::new(Duration::ZERO);
+        let refreshes = Arc::new(AtomicUsize::new(0));
+
+        let tasks: Vec<_> = (0..20)
+            .map(|_| {
+                let (coordinator, refreshes) = (coordinator.clone(), refreshes.clone());
+                tokio::spawn(async move {
+                    if let RefreshTurn::Refresh(_permit) = coordinator.acquire("payment").await {
+                        refreshes.fetch_add(1, Ordering::SeqCst);
+                        tokio::time::sleep(Duration::from_millis(50)).await;
+                    }
+                })
+            })
+            .collect();
+        for task in tasks {
+            task.await.unwrap();
+        }
+        assert_eq!(refreshes.load(Ordering::SeqCst), 1);
+
+        // Without a cooldown the next expiry gets its own refresh
+        assert!(matches!(coordinator.acquire("payment").await, RefreshTurn::Refresh(_)));
+    }
+
+    #[tokio::test]
+    async fn test_cooldown_skips_refreshes_after_one() {
+        let coordinator = RefreshCoordinator::new(Duration::from_millis(100));
+
+        drop(coordinator.acquire("payment").await);
+        assert!(matches!(coordinator.acquire("payment").await, RefreshTurn::UseStored));
+        assert!(matches!(coordinator.acquire("bank").await, RefreshTurn::Refresh(_)));
+
+        tokio::time::sleep(Duration::from_millis(150)).await;
+        assert!(matches!(coordinator.acquire("payment").await, RefreshTurn::Refresh(_)));
+    }
+}
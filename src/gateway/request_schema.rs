@@ -0,0 +1,232 @@
+// === JSON Schema validation of request bodies, per service endpoint ===
+// Rejects malformed requests at the gateway, before they are billed upstream.
+
+use jsonschema::Validator;
+use serde_json::Value;
+use std::collections::HashMap;
+
+use crate::config::{ServiceConfig, ServiceRegistry};
+use crate::error::GatewayError;
+
+/// Violations listed in one error message; the rest are counted
+const MAX_REPORTED_ERRORS: usize = 10;
+
+struct EndpointSchema {
+    // Path segments; `{name}` matches any one segment
+    segments: Vec<String>,
+    // Upper-case; empty matches every method
+    methods: Vec<String>,
+    validator: Validator,
+}
+
+impl EndpointSchema {
+    fn matches(&self, method: &str, path: &str) -> bool {
+        let method_matches =
+            self.methods.is_empty() || self.methods.iter().any(|m| m.eq_ignore_ascii_case(method));
+        let segments: Vec<&str> = path.split('/').filter(|s| !s.is_empty()).collect();
+        method_matches
+            && segments.len() == self.segments.len()
+            && self
+                .segments
+                .iter()
+                .zip(&segments)
+                .all(|(pattern, segment)| is_placeholder(pattern) || pattern == segment)
+    }
+}
+
+fn is_placeholder(segment: &str) -> bool {
+    segment.starts_with('{') && segment.ends_with('}')
+}
+
+/// Compiled `request_schema`s of one service's endpoints
+pub struct RequestSchemas {
+    endpoints: Vec<EndpointSchema>,
+}
+
+impl RequestSchemas {
+    /// `None` when no endpoint of `service` has a schema
+    pub fn for_service(service: &ServiceConfig) -> Result<Option<Self>, GatewayError> {
+        let mut endpoints = Vec::new();
+        for endpoint in &service.endpoints {
+            let Some(schema) = &endpoint.request_schema else {
+                continue;
+            };
+            let invalid = |reason: String| {
+                GatewayError::Internal(format!(
+                    "Service '{}' endpoint '{}' has an invalid request_schema: {}",
+                    service.id, endpoint.path, reason
+                ))
+            };
+            // A string is a path to the schema file; schemas themselves are objects or booleans
+            let loaded;
+            let schema = match schema {
+                Value::String(path) => {
+                    let content = std::fs::read_to_string(path)
+                        .map_err(|e| invalid(format!("cannot read {}: {}", path, e)))?;
+                    loaded = serde_json::from_str::<Value>(&content)
+                        .map_err(|e| invalid(format!("{} is not JSON: {}", path, e)))?;
+                    &loaded
+                }
+                inline => inline,
+            };
+            endpoints.push(EndpointSchema {
+                segments: endpoint
+                    .path
+                    .split('/')
+                    .filter(|s| !s.is_empty())
+                    .map(str::to_string)
+                    .collect(),
+                methods: endpoint.methods.clone(),
+                validator: jsonschema::validator_for(schema).map_err(|e| invalid(e.to_string()))?,
+            });
+        }
+        Ok((!endpoints.is_empty()).then_some(Self { endpoints }))
+    }
+
+    /// Check `body` against the schema of the first endpoint matching `method` and
+    /// `path`; requests no endpoint schema covers pass. A missing or non-JSON body
+    /// is checked as `null`, which an object schema rejects.
+    pub fn validate(&self, method: &str, path: &str, body: Option<&Value>) -> Result<(), GatewayError> {
+        let Some(endpoint) = self.endpoints.iter().find(|e| e.matches(method, path)) else {
+            return Ok(());
+        };
+        let body = body.unwrap_or(&Value::Null);
+        let errors: Vec<String> = endpoint
+            .validator
+            .iter_errors(body)
+            .map(|error| match error.instance_path.to_string() {
+                root if root.is_empty() => error.to_string(),
+                field => format!("{}: {}", field, error),
+            })
+            .collect();
+        if errors.is_empty() {
+            return Ok(());
+        }
+
+        let mut message = format!(
+            "Request body does not match the schema: {}",
+            errors.iter().take(MAX_REPORTED_ERRORS).cloned().collect::<Vec<_>>().join("; ")
+        );
+        if errors.len() > MAX_REPORTED_ERRORS {
+            message.push_str(&format!(" (and {} more)", errors.len() - MAX_REPORTED_ERRORS));
+        }
+        Err(GatewayError::BadRequest(message))
+    }
+}
+
+/// Schemas for the services whose endpoints have a `request_schema`; a bad
+/// schema (or an unreadable schema file) fails startup
+pub fn build_request_schemas(
+    services: &ServiceRegistry,
+) -> Result<HashMap<String, RequestSchemas>, GatewayError> {
+    let mut schemas = HashMap::new();
+    for service in services.list() {
+        if let Some(service_schemas) = RequestSchemas::for_service(service)? {
+            schemas.insert(service.id.clone(), service_schemas);
+        }
+    }
+    Ok(schemas)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use std::io::Write;
+    use tempfile::NamedTempFile;
+
+    fn service(endpoints: Value) -> ServiceConfig {
+        serde_json::from_value(json!({
+            "id": "llm",
+            "name": "LLM",
+            "description": "",
+            "base_url": "https://llm.example.com",
+            "auth_type": "bearer_token",
+            "endpoints": endpoints,
+            "rate_limit": { "requests": 10, "window_secs": 60 }
+        }))
+        .unwrap()
+    }
+
+    fn chat_schema() -> Value {
+        json!({
+            "type": "object",
+            "required": ["model", "messages"],
+            "properties": {
+                "model": { "type": "string" },
+                "messages": { "type": "array", "minItems": 1 },
+                "temperature": { "type": "number", "maximum": 2 }
+            }
+        })
+    }
+
+    #[test]
+    fn test_errors_name_the_failing_fields() {
+        let schemas = RequestSchemas::for_service(&service(json!([{
+            "path": "/v1/chat/completions",
+            "methods": ["POST"],
+            "required_scopes": [],
+            "request_schema": chat_schema()
+        }])))
+        .unwrap()
+        .unwrap();
+
+        let valid = json!({ "model": "m", "messages": [{ "role": "user" }] });
+        assert!(schemas.validate("POST", "v1/chat/completions", Some(&valid)).is_ok());
+
+        let invalid = json!({ "messages": [], "temperature": 3 });
+        let Err(GatewayError::BadRequest(message)) =
+            schemas.validate("POST", "v1/chat/completions", Some(&invalid))
+        else {
+            panic!("expected a BadRequest");
+        };
+        assert!(message.contains("\"model\" is a required property"), "{}", message);
+        assert!(message.contains("/messages: "), "{}", message);
+        assert!(message.contains("/temperature: "), "{}", message);
+
+        assert!(schemas.validate("POST", "v1/chat/completions", None).is_err());
+        // Other methods and paths aren't covered by the schema
+        assert!(schemas.validate("GET", "v1/chat/completions", None).is_ok());
+        assert!(schemas.validate("POST", "v1/embeddings", Some(&invalid)).is_ok());
+    }
+
+    #[test]
+    fn test_placeholders_and_schema_files() {
+        let mut file = NamedTempFile::new().unwrap();
+        file.write_all(chat_schema().to_string().as_bytes()).unwrap();
+        let schemas = RequestSchemas::for_service(&service(json!([
+            {
+                "path": "/v1/models/{model}/generate",
+                "methods": [],
+                "required_scopes": [],
+                "request_schema": file.path().to_string_lossy()
+            },
+            { "path": "/v1/health", "methods": ["GET"], "required_scopes": [] }
+        ])))
+        .unwrap()
+        .unwrap();
+
+        assert!(schemas.validate("PUT", "v1/models/large/generate", Some(&json!({}))).is_err());
+        assert!(schemas.validate("PUT", "v1/models/large/generate/extra", Some(&json!({}))).is_ok());
+        assert!(RequestSchemas::for_service(&service(json!([]))).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_bad_schemas_fail_startup() {
+        let bad = |schema: Value| {
+            RequestSchemas::for_service(&service(json!([{
+                "path": "/x",
+                "methods": ["POST"],
+                "required_scopes": [],
+                "request_schema": schema
+            }])))
+            .map(|_| ())
+        };
+        let message = |result| match result {
+            Err(GatewayError::Internal(message)) => message,
+            _ => panic!("expected a startup error"),
+        };
+        assert!(message(bad(json!({ "type": "no-such-type" }))).contains("invalid request_schema"));
+        assert!(message(bad(json!("/nonexistent/schema.json"))).contains("cannot read"));
+    }
+}
@@ -0,0 +1,125 @@
+// === Shared cache for idempotent upstream GET responses ===
+
+use axum::http::HeaderMap;
+use dashmap::DashMap;
+use serde_json::Value;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// Request headers that can change an upstream response, and so belong in the key.
+/// Agent identity is deliberately excluded: cached data is shared across agents.
+const VARY_HEADERS: [&str; 2] = ["accept", "accept-language"];
+
+/// Separates the service id from the rest of the key (can't appear in a header value or URL)
+const KEY_SEPARATOR: char = '\0';
+
+#[derive(Clone, Default)]
+pub struct ResponseCache {
+    // key -> (response body, expires at)
+    entries: Arc<DashMap<String, (Value, Instant)>>,
+}
+
+impl ResponseCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Cache key from the service, normalized path, raw query and the vary headers
+    pub fn key(service_id: &str, path: &str, query: Option<&str>, headers: &HeaderMap) -> String {
+        let mut vary: Vec<String> = VARY_HEADERS
+            .iter()
+            .filter_map(|name| {
+                let value = headers.get(*name)?.to_str().ok()?;
+                Some(format!("{}={}", name, value.trim().to_lowercase()))
+            })
+            .collect();
+        vary.sort();
+
+        format!(
+            "{}{sep}{}{sep}{}{sep}{}",
+            service_id,
+            path,
+            query.unwrap_or_default(),
+            vary.join("&"),
+            sep = KEY_SEPARATOR
+        )
+    }
+
+    /// Cached body, if present and unexpired; expired entries are dropped here
+    pub fn get(&self, key: &str) -> Option<Value> {
+        let now = Instant::now();
+        if let Some(entry) = self.entries.get(key) {
+            let (value, expires_at) = entry.value();
+            if *expires_at > now {
+                return Some(value.clone());
+            }
+        }
+        self.entries.remove_if(key, |_, (_, expires_at)| *expires_at <= now);
+        None
+    }
+
+    pub fn insert(&self, key: String, value: Value, ttl: Duration) {
+        self.entries.insert(key, (value, Instant::now() + ttl));
+    }
+
+    /// Drop every entry, or only one service's; returns how many were removed
+    pub fn clear(&self, service_id: Option<&str>) -> usize {
+        let before = self.entries.len();
+        match service_id {
+            Some(service) => {
+                let prefix = format!("{}{}", service, KEY_SEPARATOR);
+                self.entries.retain(|key, _| !key.starts_with(&prefix));
+            }
+            None => self.entries.clear(),
+        }
+        before.saturating_sub(self.entries.len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::HeaderValue;
+    use serde_json::json;
+
+    #[test]
+    fn test_key_ignores_header_order_case_and_unrelated_headers() {
+        let mut a = HeaderMap::new();
+        a.insert("accept", HeaderValue::from_static("Application/JSON"));
+        a.insert("accept-language", HeaderValue::from_static("en"));
+        a.insert("x-session-id", HeaderValue::from_static("agent-one"));
+
+        let mut b = HeaderMap::new();
+        b.insert("accept-language", HeaderValue::from_static("en"));
+        b.insert("accept", HeaderValue::from_static("application/json"));
+        b.insert("x-session-id", HeaderValue::from_static("agent-two"));
+
+        let key = ResponseCache::key("models", "v1/models", Some("limit=10"), &a);
+        assert_eq!(key, ResponseCache::key("models", "v1/models", Some("limit=10"), &b));
+        assert_ne!(key, ResponseCache::key("models", "v1/models", Some("limit=20"), &b));
+    }
+
+    #[test]
+    fn test_entries_expire_lazily() {
+        let cache = ResponseCache::new();
+        cache.insert("fresh".to_string(), json!({"ok": true}), Duration::from_secs(60));
+        cache.insert("stale".to_string(), json!({"ok": false}), Duration::ZERO);
+
+        assert_eq!(cache.get("fresh"), Some(json!({"ok": true})));
+        assert_eq!(cache.get("stale"), None);
+        assert_eq!(cache.entries.len(), 1);
+    }
+
+    #[test]
+    fn test_clear_by_service() {
+        let cache = ResponseCache::new();
+        let headers = HeaderMap::new();
+        let ttl = Duration::from_secs(60);
+        cache.insert(ResponseCache::key("models", "a", None, &headers), json!(1), ttl);
+        cache.insert(ResponseCache::key("models", "b", None, &headers), json!(2), ttl);
+        cache.insert(ResponseCache::key("models-v2", "a", None, &headers), json!(3), ttl);
+
+        assert_eq!(cache.clear(Some("models")), 2);
+        assert_eq!(cache.clear(None), 1);
+    }
+}
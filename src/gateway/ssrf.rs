@@ -0,0 +1,198 @@
+// === SSRF protection: keep upstream traffic off private/internal addresses ===
+
+use ipnet::IpNet;
+use reqwest::dns::{Addrs, Name, Resolve, Resolving};
+use reqwest::Url;
+use std::fmt;
+use std::net::{IpAddr, ToSocketAddrs};
+use std::sync::Arc;
+
+use crate::error::GatewayError;
+
+/// Which private destinations are explicitly allowed (SSRF_PROTECTION_ALLOWLIST).
+/// Entries are IPs (`10.0.0.5`), CIDRs (`10.20.0.0/16`) or exact hostnames (`erp.internal`).
+#[derive(Debug, Clone, Default)]
+pub struct SsrfPolicy {
+    allow_hosts: Vec<String>,
+    allow_nets: Vec<IpNet>,
+}
+
+impl SsrfPolicy {
+    pub fn from_allowlist(entries: &[String]) -> Result<Self, GatewayError> {
+        let mut policy = Self::default();
+
+        for entry in entries.iter().map(|e| e.trim()).filter(|e| !e.is_empty()) {
+            if let Ok(net) = entry.parse::<IpNet>() {
+                policy.allow_nets.push(net);
+            } else if let Ok(ip) = entry.parse::<IpAddr>() {
+                policy.allow_nets.push(IpNet::from(ip));
+            } else if entry.contains('/') {
+                return Err(GatewayError::Internal(format!(
+                    "Invalid CIDR '{}' in SSRF_PROTECTION_ALLOWLIST",
+                    entry
+                )));
+            } else {
+                policy.allow_hosts.push(entry.to_lowercase());
+            }
+        }
+
+        Ok(policy)
+    }
+
+    /// Private/internal and not explicitly allowed
+    pub fn is_blocked(&self, ip: IpAddr) -> bool {
+        is_internal(ip) && !self.allow_nets.iter().any(|net| net.contains(&ip))
+    }
+
+    fn host_allowed(&self, host: &str) -> bool {
+        self.allow_hosts.iter().any(|h| h.eq_ignore_ascii_case(host))
+    }
+
+    /// Load-time check of a service `base_url`. Hostnames are resolved once here;
+    /// names that don't resolve yet are left to the request-time resolver.
+    pub fn check_base_url(&self, service_id: &str, base_url: &str) -> Result<(), GatewayError> {
+        let url = Url::parse(base_url).map_err(|e| {
+            GatewayError::Internal(format!(
+                "Service '{}' has an invalid base_url '{}': {}",
+                service_id, base_url, e
+            ))
+        })?;
+        let host = url.host_str().ok_or_else(|| {
+            GatewayError::Internal(format!("Service '{}' base_url has no host", service_id))
+        })?;
+        if self.host_allowed(host) {
+            return Ok(());
+        }
+
+        let port = url.port_or_known_default().unwrap_or(443);
+        // IPv6 literals come back bracketed
+        let literal = host.trim_start_matches('[').trim_end_matches(']').parse::<IpAddr>();
+        let addrs: Vec<IpAddr> = match literal {
+            Ok(ip) => vec![ip],
+            Err(_) => match (host, port).to_socket_addrs() {
+                Ok(addrs) => addrs.map(|a| a.ip()).collect(),
+                Err(e) => {
+                    tracing::warn!(service = %service_id, host = %host, error = %e, "Could not resolve base_url host at load time");
+                    Vec::new()
+                }
+            },
+        };
+
+        match addrs.into_iter().find(|ip| self.is_blocked(*ip)) {
+            Some(ip) => Err(GatewayError::Internal(format!(
+                "Service '{}' base_url {} points to private address {}; add it to SSRF_PROTECTION_ALLOWLIST if this is intended",
+                service_id, base_url, ip
+            ))),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Loopback, RFC-1918, link-local, unspecified (and the IPv6 equivalents)
+fn is_internal(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(v4) => {
+            v4.is_loopback() || v4.is_private() || v4.is_link_local() || v4.is_unspecified()
+        }
+        IpAddr::V6(v6) => {
+            if let Some(v4) = v6.to_ipv4_mapped() {
+                return is_internal(IpAddr::V4(v4));
+            }
+            let first = v6.segments()[0];
+            v6.is_loopback()
+                || v6.is_unspecified()
+                || (first & 0xfe00) == 0xfc00 // unique local fc00::/7
+                || (first & 0xffc0) == 0xfe80 // link-local fe80::/10
+        }
+    }
+}
+
+/// A hostname resolved to an address the policy forbids
+#[derive(Debug)]
+pub struct SsrfBlocked {
+    pub host: String,
+    pub ip: IpAddr,
+}
+
+impl fmt::Display for SsrfBlocked {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Upstream host '{}' resolves to blocked address {}", self.host, self.ip)
+    }
+}
+
+impl std::error::Error for SsrfBlocked {}
+
+/// reqwest resolver that refuses private addresses at connect time, so a public
+/// name re-pointed at an internal IP (DNS rebinding) is caught on every request.
+pub struct SsrfGuardResolver {
+    policy: Arc<SsrfPolicy>,
+}
+
+impl SsrfGuardResolver {
+    pub fn new(policy: SsrfPolicy) -> Self {
+        Self {
+            policy: Arc::new(policy),
+        }
+    }
+}
+
+impl Resolve for SsrfGuardResolver {
+    fn resolve(&self, name: Name) -> Resolving {
+        let policy = self.policy.clone();
+        Box::pin(async move {
+            let host = name.as_str().to_string();
+            let addrs: Vec<_> = tokio::net::lookup_host((host.as_str(), 0)).await?.collect();
+
+            if !policy.host_allowed(&host) {
+                if let Some(addr) = addrs.iter().find(|a| policy.is_blocked(a.ip())) {
+                    tracing::warn!(host = %host, ip = %addr.ip(), "Blocked upstream resolving to private address");
+                    return Err(Box::new(SsrfBlocked { host, ip: addr.ip() }) as _);
+                }
+            }
+            Ok(Box::new(addrs.into_iter()) as Addrs)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn policy(entries: &[&str]) -> SsrfPolicy {
+        SsrfPolicy::from_allowlist(&entries.iter().map(|e| e.to_string()).collect::<Vec<_>>())
+            .unwrap()
+    }
+
+    #[test]
+    fn test_internal_ranges_are_blocked() {
+        let policy = SsrfPolicy::default();
+        for ip in [
+            "10.0.0.1", "172.16.5.4", "192.168.1.1", "127.0.0.1", "169.254.169.254", "0.0.0.0",
+            "::1", "fe80::1", "fd00::1", "::ffff:10.0.0.1",
+        ] {
+            assert!(policy.is_blocked(ip.parse().unwrap()), "{} should be blocked", ip);
+        }
+        for ip in ["8.8.8.8", "172.32.0.1", "2606:4700::1111"] {
+            assert!(!policy.is_blocked(ip.parse().unwrap()), "{} should be allowed", ip);
+        }
+    }
+
+    #[test]
+    fn test_base_url_check_and_allowlist() {
+        let err = SsrfPolicy::default()
+            .check_base_url("meta", "http://169.254.169.254/latest")
+            .unwrap_err();
+        assert!(matches!(err, GatewayError::Internal(msg) if msg.contains("169.254.169.254")));
+
+        let onprem = policy(&["10.20.0.0/16", "localhost"]);
+        assert!(onprem.check_base_url("erp", "http://10.20.1.5:8080/api").is_ok());
+        assert!(onprem.check_base_url("erp", "http://10.0.0.1/admin").is_err());
+        assert!(onprem.check_base_url("dev", "http://localhost:3000").is_ok());
+        assert!(policy(&[]).check_base_url("dev", "http://localhost:3000").is_err());
+    }
+
+    #[test]
+    fn test_invalid_cidr_is_rejected() {
+        assert!(SsrfPolicy::from_allowlist(&["10.0.0.0/99".to_string()]).is_err());
+    }
+}
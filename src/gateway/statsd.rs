@@ -0,0 +1,128 @@
+// === StatsD telemetry (STATSD_HOST / STATSD_PORT) ===
+// Push-based alternative to scraping /admin/metrics: each proxied request
+// sends a few plain-text datagrams to a StatsD-compatible collector (Datadog
+// Agent, Graphite, ...). Telemetry must never cost the request anything, so
+// sends don't wait and their errors are only logged.
+
+use std::sync::Arc;
+use tokio::net::UdpSocket;
+use tokio::sync::OnceCell;
+
+/// Fire-and-forget StatsD client over UDP. Cheap to clone.
+#[derive(Clone)]
+pub struct StatsDClient {
+    addr: String,
+    // Bound on first use: AppState is built outside the runtime
+    socket: Arc<OnceCell<UdpSocket>>,
+}
+
+impl StatsDClient {
+    /// `addr`: `host:port` of the collector
+    pub fn new(addr: String) -> Self {
+        Self {
+            addr,
+            socket: Arc::new(OnceCell::new()),
+        }
+    }
+
+    /// `{metric}:{value}|c`
+    pub fn count(&self, metric: &str, value: u64) {
+        self.send(format!("{}:{}|c", metric, value));
+    }
+
+    /// `{metric}:1|c`
+    pub fn incr(&self, metric: &str) {
+        self.count(metric, 1);
+    }
+
+    /// `{metric}:{value}|g`
+    pub fn gauge(&self, metric: &str, value: i64) {
+        self.send(format!("{}:{}|g", metric, value));
+    }
+
+    /// `{metric}:{ms}|ms`
+    pub fn timing(&self, metric: &str, ms: u64) {
+        self.send(format!("{}:{}|ms", metric, ms));
+    }
+
+    fn send(&self, line: String) {
+        // The common case: the socket is up and a UDP send never waits
+        if let Some(socket) = self.socket.get() {
+            if let Err(e) = socket.try_send(line.as_bytes()) {
+                tracing::debug!(addr = %self.addr, error = %e, "StatsD send failed");
+            }
+            return;
+        }
+        // Binding (and resolving the host) happens once, off the request path
+        let client = self.clone();
+        tokio::spawn(async move {
+            let socket = client
+                .socket
+                .get_or_try_init(|| async {
+                    let socket = UdpSocket::bind("0.0.0.0:0").await?;
+                    socket.connect(&client.addr).await?;
+                    Ok::<_, std::io::Error>(socket)
+                })
+                .await;
+            let result = match socket {
+                Ok(socket) => socket.send(line.as_bytes()).await.map(|_| ()),
+                Err(e) => Err(e),
+            };
+            if let Err(e) = result {
+                tracing::warn!(addr = %client.addr, error = %e, "StatsD send failed");
+            }
+        });
+    }
+}
+
+/// A metric name segment: StatsD reserves `:`, `|` and `@`, and `.` separates segments
+pub fn statsd_segment(name: &str) -> String {
+    name.chars()
+        .map(|c| if c.is_ascii_alphanumeric() || c == '-' || c == '_' { c } else { '_' })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    async fn recv(collector: &UdpSocket) -> String {
+        let mut datagram = [0u8; 512];
+        let len = tokio::time::timeout(Duration::from_secs(5), collector.recv(&mut datagram))
+            .await
+            .expect("no datagram")
+            .unwrap();
+        String::from_utf8_lossy(&datagram[..len]).into_owned()
+    }
+
+    #[tokio::test]
+    async fn test_metrics_reach_the_collector() {
+        let collector = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let client = StatsDClient::new(collector.local_addr().unwrap().to_string());
+
+        client.incr("gateway.requests.mock.2xx");
+        assert_eq!(recv(&collector).await, "gateway.requests.mock.2xx:1|c");
+        // Once the socket is bound, sends go out without a task
+        client.timing("gateway.latency.mock", 42);
+        assert_eq!(recv(&collector).await, "gateway.latency.mock:42|ms");
+        client.gauge("gateway.in_flight", 3);
+        assert_eq!(recv(&collector).await, "gateway.in_flight:3|g");
+    }
+
+    #[tokio::test]
+    async fn test_unreachable_collector_is_ignored() {
+        // Nothing listens on the discard port; refused datagrams are only logged
+        let client = StatsDClient::new("127.0.0.1:9".to_string());
+        for _ in 0..3 {
+            client.incr("gateway.requests.mock.2xx");
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+    }
+
+    #[test]
+    fn test_segments_are_sanitized() {
+        assert_eq!(statsd_segment("payment-api_v2"), "payment-api_v2");
+        assert_eq!(statsd_segment("a.b:c|d@e"), "a_b_c_d_e");
+    }
+}
@@ -1,17 +1,33 @@
 // === Token refresh logic for service credentials ===
 
-use chrono::{Duration, Utc};
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Deserializer};
+use serde_json::Value;
+use std::time::Instant;
 
+use crate::audit::log_token_refresh;
 use crate::config::StoredCredential;
+use crate::models::{RefreshOutcome, RefreshTrigger, TokenRefreshAudit};
+
+use super::metrics::RefreshMetrics;
 
 // === Refresh buffer: 6 hours before expiry ===
 const REFRESH_BUFFER_HOURS: i64 = 6;
 
-// === Check if credential needs refresh ===
+// === Providers occasionally return nonsense; cap expires_in at one year ===
+const MAX_EXPIRES_IN_SECS: i64 = 365 * 24 * 3600;
+
+// === Check if credential needs refresh (no skew allowance) ===
+#[allow(dead_code)]
 pub fn needs_refresh(credential: &StoredCredential) -> bool {
+    needs_refresh_with_skew(credential, 0)
+}
+
+// === Refresh early by `skew_secs` in case our clock runs behind the provider's ===
+pub fn needs_refresh_with_skew(credential: &StoredCredential, skew_secs: u64) -> bool {
     match credential.expires_at {
         Some(expires_at) => {
-            let buffer = Duration::hours(REFRESH_BUFFER_HOURS);
+            let buffer = Duration::hours(REFRESH_BUFFER_HOURS) + Duration::seconds(skew_secs as i64);
             Utc::now() + buffer > expires_at
         }
         None => false, // No expiry = no refresh needed
@@ -21,12 +37,51 @@ pub fn needs_refresh(credential: &StoredCredential) -> bool {
 /// Check if credential is expired (alternative expiry check)
 #[allow(dead_code)]
 pub fn is_expired(credential: &StoredCredential) -> bool {
+    is_expired_with_skew(credential, 0)
+}
+
+/// Only treat a credential as expired once it is past expiry by more than `skew_secs`,
+/// so a clock running ahead doesn't report still-valid tokens as expired
+pub fn is_expired_with_skew(credential: &StoredCredential, skew_secs: u64) -> bool {
     match credential.expires_at {
-        Some(expires_at) => Utc::now() > expires_at,
+        Some(expires_at) => Utc::now() - Duration::seconds(skew_secs as i64) > expires_at,
         None => false,
     }
 }
 
+// === Parse `expires_in` from a token response: number or numeric string ===
+// Negative, non-numeric or missing values yield None; huge values are clamped.
+pub fn parse_expires_in(value: &Value) -> Option<i64> {
+    let secs = match value {
+        Value::Number(n) => n.as_i64().or_else(|| n.as_f64().map(|f| f as i64))?,
+        Value::String(s) => {
+            let s = s.trim();
+            s.parse::<i64>()
+                .ok()
+                .or_else(|| s.parse::<f64>().ok().filter(|f| f.is_finite()).map(|f| f as i64))?
+        }
+        _ => return None,
+    };
+
+    (secs >= 0).then(|| secs.min(MAX_EXPIRES_IN_SECS))
+}
+
+// === Serde adapter for `expires_in` fields ===
+pub fn deserialize_expires_in<'de, D>(deserializer: D) -> Result<Option<i64>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let value = Option::<Value>::deserialize(deserializer)?;
+    Ok(value.as_ref().and_then(parse_expires_in))
+}
+
+// === Absolute expiry from `expires_in`, falling back to a per-service default TTL ===
+pub fn resolve_expiry(expires_in: Option<i64>, default_ttl_secs: Option<u64>) -> Option<DateTime<Utc>> {
+    expires_in
+        .or(default_ttl_secs.map(|ttl| (ttl as i64).min(MAX_EXPIRES_IN_SECS)))
+        .map(|secs| Utc::now() + Duration::seconds(secs))
+}
+
 // === Simulate token refresh (in production, call OAuth2 token endpoint) ===
 pub async fn refresh_token(credential: &StoredCredential) -> Option<StoredCredential> {
     // In production: use oauth2 crate to call token_url with refresh_token
@@ -45,6 +100,51 @@ pub async fn refresh_token(credential: &StoredCredential) -> Option<StoredCreden
     Some(refreshed)
 }
 
+// === Refresh with metrics and an audit record for every attempt ===
+pub async fn refresh_instrumented(
+    credential: &StoredCredential,
+    trigger: RefreshTrigger,
+    metrics: &RefreshMetrics,
+) -> Option<StoredCredential> {
+    let started = Instant::now();
+    let refreshed = refresh_token(credential).await;
+
+    record_refresh(
+        &credential.service_id,
+        trigger,
+        refreshed.as_ref(),
+        started.elapsed(),
+        metrics,
+    )
+    .await;
+
+    refreshed
+}
+
+// === Count and audit one refresh attempt, however the token was obtained ===
+pub async fn record_refresh(
+    service_id: &str,
+    trigger: RefreshTrigger,
+    refreshed: Option<&StoredCredential>,
+    elapsed: std::time::Duration,
+    metrics: &RefreshMetrics,
+) {
+    metrics.record(service_id, trigger, refreshed.is_some(), elapsed).await;
+
+    log_token_refresh(&TokenRefreshAudit {
+        service_id: service_id.to_string(),
+        trigger,
+        outcome: if refreshed.is_some() {
+            RefreshOutcome::Success
+        } else {
+            RefreshOutcome::Failure
+        },
+        new_expires_at: refreshed.and_then(|c| c.expires_at),
+        duration_ms: elapsed.as_millis() as u64,
+        timestamp: Utc::now(),
+    });
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -88,4 +188,113 @@ mod tests {
 
         assert!(refreshed.expires_at.unwrap() > cred.expires_at.unwrap());
     }
+
+    #[test]
+    fn test_parse_expires_in_shapes() {
+        use serde_json::json;
+
+        assert_eq!(parse_expires_in(&json!(3600)), Some(3600));
+        assert_eq!(parse_expires_in(&json!("3600")), Some(3600));
+        assert_eq!(parse_expires_in(&json!(" 3600 ")), Some(3600));
+        assert_eq!(parse_expires_in(&json!(3599.9)), Some(3599));
+        assert_eq!(parse_expires_in(&json!("3600.0")), Some(3600));
+        assert_eq!(parse_expires_in(&json!("soon")), None);
+        assert_eq!(parse_expires_in(&json!("")), None);
+        assert_eq!(parse_expires_in(&json!(-60)), None);
+        assert_eq!(parse_expires_in(&json!(null)), None);
+        assert_eq!(parse_expires_in(&json!(true)), None);
+        assert_eq!(parse_expires_in(&json!({"secs": 10})), None);
+        assert_eq!(parse_expires_in(&json!(1_000_000_000_000i64)), Some(MAX_EXPIRES_IN_SECS));
+        assert_eq!(parse_expires_in(&json!("1e12")), Some(MAX_EXPIRES_IN_SECS));
+    }
+
+    #[test]
+    fn test_deserialize_expires_in_missing_field() {
+        #[derive(Deserialize)]
+        struct Response {
+            #[serde(default, deserialize_with = "deserialize_expires_in")]
+            expires_in: Option<i64>,
+        }
+
+        let missing: Response = serde_json::from_str("{}").unwrap();
+        assert_eq!(missing.expires_in, None);
+        let string: Response = serde_json::from_str(r#"{"expires_in":"120"}"#).unwrap();
+        assert_eq!(string.expires_in, Some(120));
+        let garbage: Response = serde_json::from_str(r#"{"expires_in":[1]}"#).unwrap();
+        assert_eq!(garbage.expires_in, None);
+    }
+
+    #[test]
+    fn test_resolve_expiry_default_ttl() {
+        assert!(resolve_expiry(None, None).is_none());
+
+        let expiry = resolve_expiry(None, Some(600)).unwrap();
+        let secs = (expiry - Utc::now()).num_seconds();
+        assert!((598..=600).contains(&secs));
+
+        // Provider value wins over the default
+        let expiry = resolve_expiry(Some(60), Some(600)).unwrap();
+        assert!((expiry - Utc::now()).num_seconds() <= 60);
+    }
+
+    #[test]
+    fn test_needs_refresh_skew_boundary() {
+        let mut cred = make_credential(0);
+        let buffer = REFRESH_BUFFER_HOURS * 3600;
+
+        // Just outside buffer + skew: no refresh yet
+        cred.expires_at = Some(Utc::now() + Duration::seconds(buffer + 60 + 5));
+        assert!(!needs_refresh_with_skew(&cred, 60));
+
+        // Inside buffer + skew: refresh, although plain buffer alone would not
+        cred.expires_at = Some(Utc::now() + Duration::seconds(buffer + 60 - 5));
+        assert!(needs_refresh_with_skew(&cred, 60));
+        assert!(!needs_refresh(&cred));
+    }
+
+    #[test]
+    fn test_is_expired_skew_boundary() {
+        let mut cred = make_credential(0);
+
+        // Expired 30s ago, 60s skew allowance: still accepted
+        cred.expires_at = Some(Utc::now() - Duration::seconds(30));
+        assert!(!is_expired_with_skew(&cred, 60));
+        assert!(is_expired(&cred));
+
+        // Expired 90s ago: beyond the allowance
+        cred.expires_at = Some(Utc::now() - Duration::seconds(90));
+        assert!(is_expired_with_skew(&cred, 60));
+    }
+
+    #[tokio::test]
+    async fn test_instrumented_refresh_counts_success() {
+        let metrics = RefreshMetrics::new();
+        let cred = make_credential(1);
+
+        assert!(refresh_instrumented(&cred, RefreshTrigger::Lazy, &metrics).await.is_some());
+
+        let stats = metrics.snapshot().await;
+        let svc = stats.get("test").unwrap();
+        assert_eq!(svc.attempts, 1);
+        assert_eq!(svc.successes, 1);
+        assert_eq!(svc.failures, 0);
+        assert_eq!(svc.duration.count, 1);
+    }
+
+    #[tokio::test]
+    async fn test_instrumented_refresh_counts_failure() {
+        let metrics = RefreshMetrics::new();
+        // No refresh token = refresh fails
+        let mut cred = make_credential(1);
+        cred.refresh_token = None;
+
+        assert!(refresh_instrumented(&cred, RefreshTrigger::Forced, &metrics).await.is_none());
+        assert!(refresh_instrumented(&cred, RefreshTrigger::Forced, &metrics).await.is_none());
+
+        let stats = metrics.snapshot().await;
+        let svc = stats.get("test").unwrap();
+        assert_eq!(svc.attempts, 2);
+        assert_eq!(svc.successes, 0);
+        assert_eq!(svc.failures, 2);
+    }
 }
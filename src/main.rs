@@ -1,4 +1,5 @@
-use axum::{routing::get, Router};
+use axum::{middleware, Router};
+use std::net::SocketAddr;
 use tower_http::trace::TraceLayer;
 use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
 
@@ -12,8 +13,9 @@ mod storage;
 mod error;
 mod state;
 
+use audit::{assign_request_id, SampledMakeSpan};
 use config::Settings;
-use routes::{admin_routes, auth_routes, credential_routes, proxy_routes};
+use routes::{admin_routes, auth_routes, credential_routes, health_routes, proxy_routes};
 use state::AppState;
 
 #[tokio::main]
@@ -29,6 +31,7 @@ async fn main() {
     // Load configuration
     let settings = Settings::from_env();
     let addr = settings.addr();
+    error::set_error_format(settings.error_format);
 
     tracing::info!("Starting Secure AI Agent Gateway on {}", addr);
 
@@ -36,19 +39,62 @@ async fn main() {
     let state = AppState::new(settings).expect("Failed to initialize application state");
 
     tracing::info!(
-        services = state.services.list().len(),
+        services = state.services().registry.list().len(),
         "Loaded services configuration"
     );
 
+    if state.settings.validate_service_urls_on_startup {
+        state.services().registry.validate_urls().await;
+    }
+
+    // Clear out agents left long expired by earlier runs (STARTUP_PRUNE)
+    if state.settings.startup_prune {
+        state
+            .prune_stores()
+            .prune(state.settings.prune_grace_days, false)
+            .await
+            .expect("Startup prune failed");
+    }
+
+    // Periodic removal of expired sessions (SESSION_CLEANUP_INTERVAL_SECS)
+    storage::spawn_session_cleanup(
+        state.sessions.clone(),
+        std::time::Duration::from_secs(state.settings.session_cleanup_interval_secs),
+    );
+
+    // Background rotation for services with a static key rotation hook, following
+    // the services as they are reloaded or edited through the admin API
+    let rotation_state = state.clone();
+    gateway::spawn_key_rotation(move || rotation_state.services(), state.credentials.clone());
+
+    // Webhook for agents about to expire (EXPIRY_WEBHOOK_URL)
+    if let Some(notifier) = &state.expiry_notifier {
+        gateway::spawn_expiry_notifier(
+            notifier.clone(),
+            state.agents.clone(),
+            std::time::Duration::from_secs(state.settings.expiry_check_interval_secs),
+        );
+    }
+
+    // Daily deletion/compression of old audit files (AUDIT_RETENTION_DAYS)
+    if let Some(store) = &state.audit {
+        if state.settings.audit_retention.is_enabled() {
+            audit::spawn_audit_retention(store.clone(), state.settings.audit_retention);
+        }
+    }
+
     // Build router with state
+    let make_span = SampledMakeSpan::new(state.settings.log_sample_rate);
     let app = Router::new()
-        .route("/health", get(health_check))
+        .merge(health_routes())
         .nest("/auth", auth_routes())
         .nest("/credentials", credential_routes())
         .nest("/api", proxy_routes())
         .nest("/admin", admin_routes())
-        .layer(TraceLayer::new_for_http())
-        .with_state(state);
+        .layer(TraceLayer::new_for_http().make_span_with(make_span))
+        // Outermost, so the request's span and every response carry the ID
+        .layer(middleware::from_fn(assign_request_id))
+        .with_state(state.clone());
 
     // Start server
     let listener = tokio::net::TcpListener::bind(&addr)
@@ -62,9 +108,41 @@ async fn main() {
     tracing::info!("  GET  /auth/services     - List available services");
     tracing::info!("  ANY  /api/{{service}}/{{path}} - Proxy to external service");
 
-    axum::serve(listener, app).await.expect("Server failed");
+    // Peer addresses feed the per-IP limit on the pre-auth endpoints
+    axum::serve(listener, app.into_make_service_with_connect_info::<SocketAddr>())
+        .with_graceful_shutdown(shutdown_signal())
+        .await
+        .expect("Server failed");
+
+    // Persist batched store writes and queued audit entries before exiting
+    if let Some(writer) = &state.audit_writer {
+        writer.drain().await;
+    }
+    if let Err(e) = state.agents.flush().await {
+        tracing::error!(error = ?e, "Failed to flush agent store on shutdown");
+    }
 }
 
-async fn health_check() -> &'static str {
-    "OK"
+async fn shutdown_signal() {
+    let ctrl_c = async {
+        tokio::signal::ctrl_c()
+            .await
+            .expect("Failed to install Ctrl+C handler");
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())
+            .expect("Failed to install SIGTERM handler")
+            .recv()
+            .await;
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => {},
+        _ = terminate => {},
+    }
+    tracing::info!("Shutdown signal received");
 }
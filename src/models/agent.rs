@@ -1,5 +1,6 @@
 use chrono::{DateTime, Duration, Utc};
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 use std::net::IpAddr;
 use uuid::Uuid;
 
@@ -12,12 +13,24 @@ const DEFAULT_LIFESPAN_DAYS: i64 = 30;
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Agent {
     pub id: Uuid,
+    #[serde(default)]
+    pub owner_id: Option<Uuid>,              // Creating user; agent names are unique per owner
     pub name: String,
     pub description: String,
-    pub allowed_services: Vec<String>,
+    pub allowed_services: Vec<ServiceAccess>,
     pub scopes: Vec<String>,
     pub rate_limit: RateLimit,
+    #[serde(default)]
+    pub rate_limit_group: Option<String>,  // Quota shared with other agents in the group
     pub ip_allowlist: Option<Vec<IpAddr>>,
+    #[serde(default)]
+    pub max_concurrent_requests: Option<u32>,  // Requests in flight at once; None: no cap
+    #[serde(default)]
+    pub version: u64,                        // Bumped by every update_agent (optimistic concurrency)
+    #[serde(default)]
+    pub usage: AgentUsage,                   // Written by the store (record_usage), never by update_agent
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,                       // False while suspended: every session is refused
     // === Access Key Lifespan ===
     pub expires_at: DateTime<Utc>,           // When this access key expires
     pub lifespan_days: u32,                  // How long the key is valid (for rotation)
@@ -26,6 +39,10 @@ pub struct Agent {
     pub updated_at: DateTime<Utc>,
 }
 
+fn default_enabled() -> bool {
+    true
+}
+
 impl Agent {
     /// Create agent with default lifespan
     #[allow(dead_code)]
@@ -33,12 +50,18 @@ impl Agent {
         let now = Utc::now();
         Self {
             id: Uuid::new_v4(),
+            owner_id: None,
             name,
             description,
             allowed_services: Vec::new(),
             scopes: Vec::new(),
             rate_limit: RateLimit::default(),
+            rate_limit_group: None,
             ip_allowlist: None,
+            max_concurrent_requests: None,
+            version: 0,
+            usage: AgentUsage::default(),
+            enabled: true,
             expires_at: now + Duration::days(DEFAULT_LIFESPAN_DAYS),
             lifespan_days: DEFAULT_LIFESPAN_DAYS as u32,
             created_at: now,
@@ -51,12 +74,18 @@ impl Agent {
         let now = Utc::now();
         Self {
             id: Uuid::new_v4(),
+            owner_id: None,
             name,
             description,
             allowed_services: Vec::new(),
             scopes: Vec::new(),
             rate_limit: RateLimit::default(),
+            rate_limit_group: None,
             ip_allowlist: None,
+            max_concurrent_requests: None,
+            version: 0,
+            usage: AgentUsage::default(),
+            enabled: true,
             expires_at: now + Duration::days(lifespan_days as i64),
             lifespan_days,
             created_at: now,
@@ -65,7 +94,22 @@ impl Agent {
     }
 
     pub fn can_access_service(&self, service_id: &str) -> bool {
-        self.allowed_services.contains(&service_id.to_string())
+        self.service_access(service_id).is_some()
+    }
+
+    pub fn service_access(&self, service_id: &str) -> Option<&ServiceAccess> {
+        self.allowed_services.iter().find(|access| access.service_id == service_id)
+    }
+
+    /// Scopes granted on `service_id`; empty when every scope is granted (or
+    /// the service isn't, see `can_access_service`)
+    pub fn get_service_scopes(&self, service_id: &str) -> &[String] {
+        self.service_access(service_id).map_or(&[], |access| access.granted_scopes.as_slice())
+    }
+
+    /// Ids of the services the agent may call, in grant order
+    pub fn service_ids(&self) -> Vec<String> {
+        self.allowed_services.iter().map(|access| access.service_id.clone()).collect()
     }
 
     /// Check if the access key has expired
@@ -73,10 +117,20 @@ impl Agent {
         Utc::now() > self.expires_at
     }
 
-    /// Add a service to allowed services
+    /// Add a service to allowed services, with every scope
+    #[allow(dead_code)]
     pub fn add_service(&mut self, service_id: String) {
-        if !self.allowed_services.contains(&service_id) {
-            self.allowed_services.push(service_id);
+        self.grant_service(service_id, Vec::new());
+    }
+
+    /// Add a service limited to `scopes` (empty: every scope); a service
+    /// already granted is left as it is
+    pub fn grant_service(&mut self, service_id: String, scopes: Vec<String>) {
+        if !self.can_access_service(&service_id) {
+            self.allowed_services.push(ServiceAccess {
+                service_id,
+                granted_scopes: scopes,
+            });
             self.updated_at = Utc::now();
         }
     }
@@ -84,7 +138,7 @@ impl Agent {
     /// Remove a service from allowed services
     pub fn remove_service(&mut self, service_id: &str) -> bool {
         let initial_len = self.allowed_services.len();
-        self.allowed_services.retain(|s| s != service_id);
+        self.allowed_services.retain(|access| access.service_id != service_id);
         if self.allowed_services.len() != initial_len {
             self.updated_at = Utc::now();
             true
@@ -93,10 +147,51 @@ impl Agent {
         }
     }
 
+    /// Replace the name and/or description; unset fields are kept
+    pub fn update_metadata(&mut self, name: Option<String>, description: Option<String>) {
+        if let Some(name) = name {
+            self.name = name;
+        }
+        if let Some(description) = description {
+            self.description = description;
+        }
+        self.updated_at = Utc::now();
+    }
+
+    /// Suspend (`false`) or resume the agent; returns whether anything changed
+    pub fn set_enabled(&mut self, enabled: bool) -> bool {
+        if self.enabled == enabled {
+            return false;
+        }
+        self.enabled = enabled;
+        self.updated_at = Utc::now();
+        true
+    }
+
+    /// Whether a request from `ip` may use the agent. Without an allowlist any
+    /// address (or none, when it's unknown) may; with one it must be listed.
+    pub fn allows_ip(&self, ip: Option<IpAddr>) -> bool {
+        match &self.ip_allowlist {
+            None => true,
+            Some(allowlist) => ip.is_some_and(|ip| allowlist.contains(&ip)),
+        }
+    }
+
+    /// Change the lifespan and move the expiry to `days` after the key was
+    /// issued, or after now with `from_now`
+    pub fn set_lifespan(&mut self, days: u32, from_now: bool) {
+        let now = Utc::now();
+        let start = if from_now { now } else { self.key_issued_at() };
+        self.expires_at = start + Duration::days(days as i64);
+        self.lifespan_days = days;
+        self.updated_at = now;
+    }
+
     /// Rotate/regenerate the access key (extends expiration)
     pub fn rotate(&mut self) -> Uuid {
         let now = Utc::now();
         self.id = Uuid::new_v4();
+        self.version = 0; // A new record under the new id
         self.expires_at = now + Duration::days(self.lifespan_days as i64);
         self.updated_at = now;
         self.id
@@ -106,6 +201,89 @@ impl Agent {
     pub fn days_until_expiry(&self) -> i64 {
         (self.expires_at - Utc::now()).num_days()
     }
+
+    /// When the current key was issued: creation, or the last rotation
+    pub fn key_issued_at(&self) -> DateTime<Utc> {
+        self.expires_at - Duration::days(self.lifespan_days as i64)
+    }
+
+    /// Whether the key is valid for longer than `max_days` from its issue, or
+    /// from now
+    pub fn exceeds_lifespan(&self, max_days: u32) -> bool {
+        self.lifespan_days > max_days || self.expires_at > Utc::now() + Duration::days(max_days as i64)
+    }
+
+    /// Shorten the key to `max_days` from its issue (and later rotations to
+    /// `max_days`); returns whether anything changed
+    pub fn cap_lifespan(&mut self, max_days: u32) -> bool {
+        if !self.exceeds_lifespan(max_days) {
+            return false;
+        }
+        let now = Utc::now();
+        let max = Duration::days(max_days as i64);
+        self.expires_at = self.expires_at.min(self.key_issued_at() + max).min(now + max);
+        self.lifespan_days = self.lifespan_days.min(max_days);
+        self.updated_at = now;
+        true
+    }
+}
+
+/// One service an agent may call. Stored as an object; the plain service id
+/// that `allowed_services` held before scopes could be granted per service
+/// still reads (as every scope), for rows and journal entries not yet rewritten.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(from = "ServiceAccessRepr")]
+pub struct ServiceAccess {
+    pub service_id: String,
+    /// Scopes the agent holds on this service; empty grants every scope
+    pub granted_scopes: Vec<String>,
+}
+
+impl ServiceAccess {
+    /// Every scope on `service_id`
+    pub fn all_scopes(service_id: impl Into<String>) -> Self {
+        Self {
+            service_id: service_id.into(),
+            granted_scopes: Vec::new(),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum ServiceAccessRepr {
+    ServiceId(String),
+    Access {
+        service_id: String,
+        #[serde(default)]
+        granted_scopes: Vec<String>,
+    },
+}
+
+impl From<ServiceAccessRepr> for ServiceAccess {
+    fn from(repr: ServiceAccessRepr) -> Self {
+        match repr {
+            ServiceAccessRepr::ServiceId(service_id) => Self::all_scopes(service_id),
+            ServiceAccessRepr::Access { service_id, granted_scopes } => Self { service_id, granted_scopes },
+        }
+    }
+}
+
+/// Requests an agent has sent through the proxy
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct AgentUsage {
+    pub total_requests: u64,
+    pub last_active_at: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub requests_by_service: BTreeMap<String, u64>,
+}
+
+impl AgentUsage {
+    pub fn record(&mut self, service_id: &str) {
+        self.total_requests += 1;
+        self.last_active_at = Some(Utc::now());
+        *self.requests_by_service.entry(service_id.to_string()).or_default() += 1;
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
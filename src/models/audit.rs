@@ -1,48 +1,243 @@
-//! Audit log model (prepared for request audit trail)
+//! Audit records: proxied requests (persisted by `AuditStore`), token refreshes
+//! and admin operations (data transfers, audit exports; persisted by `AdminAuditLogger`)
 
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
 use std::net::IpAddr;
 use uuid::Uuid;
 
-#[allow(dead_code)]
+/// Audit record for one proxy attempt, allowed or denied
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AuditLog {
     pub id: Uuid,
-    pub agent_id: Uuid,
-    pub session_id: String,
+    pub request_id: Option<String>, // X-Request-ID of the request, as echoed to the client
+    pub agent_id: Option<Uuid>,     // None when the session didn't validate
+    pub session_id: Option<String>, // As presented in X-Session-ID
     pub service_id: String,
     pub endpoint: String,
     pub method: String,
-    pub status_code: u16,
-    pub request_id: String,
+    pub status_code: u16,           // Upstream status, or the gateway's own error status
+    #[serde(default)]
+    pub decision: Decision,
+    #[serde(default)]
+    pub deny_reason: Option<String>, // Error type of a denial (`rate_limit_exceeded`, ...)
+    #[serde(alias = "denied_reason")]
+    pub deny_message: Option<String>, // The denial's message, as sent to the client
     pub timestamp: DateTime<Utc>,
-    pub response_time_ms: u64,
+    pub response_time_ms: u64,      // Whole handler, upstream included
+    pub upstream_time_ms: Option<u64>, // Inside the upstream call(s); None if it was never reached
+    #[serde(default)]
+    pub gateway_time_ms: u64,       // response_time_ms minus upstream_time_ms
     pub ip_address: Option<IpAddr>,
+    // Payloads of services with `audit_capture`, redacted (see audit::capture)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub request_headers: Option<BTreeMap<String, String>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub request_body: Option<CapturedBody>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub response_body: Option<CapturedBody>,
+    // Tamper evidence with AUDIT_HMAC_KEY (see audit::chain); chain_hash must stay last
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub chain_seq: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub prev_hash: Option<String>,  // chain_hash of entry chain_seq - 1 (the seed for the first)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub chain_hash: Option<String>,
 }
 
-#[allow(dead_code)]
 impl AuditLog {
-    pub fn new(
-        agent_id: Uuid,
-        session_id: String,
-        service_id: String,
-        endpoint: String,
-        method: String,
-        request_id: String,
-    ) -> Self {
+    pub fn new(service_id: String, endpoint: String, method: String) -> Self {
         Self {
             id: Uuid::new_v4(),
-            agent_id,
-            session_id,
+            request_id: None,
+            agent_id: None,
+            session_id: None,
             service_id,
             endpoint,
             method,
             status_code: 0,
-            request_id,
+            decision: Decision::Allowed,
+            deny_reason: None,
+            deny_message: None,
             timestamp: Utc::now(),
             response_time_ms: 0,
+            upstream_time_ms: None,
+            gateway_time_ms: 0,
             ip_address: None,
+            request_headers: None,
+            request_body: None,
+            response_body: None,
+            chain_seq: None,
+            prev_hash: None,
+            chain_hash: None,
+        }
+    }
+}
+
+/// A request or response body as kept in an audit entry
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CapturedBody {
+    pub length: usize,              // Bytes, before redaction
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub body: Option<Value>,        // Redacted JSON, when within the size cap
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sha256: Option<String>,     // Hex digest instead, past the cap or when not JSON
+}
+
+/// Whether a proxy attempt reached the upstream and got its answer back
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Decision {
+    /// The upstream's response, whatever its status, went back to the agent
+    #[default]
+    Allowed,
+    /// The gateway answered with an error of its own: the request was refused
+    /// (session, access, limits, validation) or the upstream couldn't be used
+    Denied,
+}
+
+impl Decision {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Decision::Allowed => "allowed",
+            Decision::Denied => "denied",
         }
     }
 }
+
+/// What caused a credential refresh
+#[allow(dead_code)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RefreshTrigger {
+    Lazy,       // Refreshed on the proxy path right before use
+    Background, // Refreshed by a background task
+    Forced,     // Refreshed on explicit request
+    Rejected,   // Refreshed after the upstream answered 401 to the current token
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RefreshOutcome {
+    Success,
+    Failure,
+}
+
+/// Audit record for a single token refresh attempt
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TokenRefreshAudit {
+    pub service_id: String,
+    pub trigger: RefreshTrigger,
+    pub outcome: RefreshOutcome,
+    pub new_expires_at: Option<DateTime<Utc>>,
+    pub duration_ms: u64,
+    pub timestamp: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DataTransferAction {
+    Export,
+    Import,
+}
+
+/// Audit record for an admin data export or import
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DataTransferAudit {
+    pub action: DataTransferAction,
+    pub mode: Option<String>,   // Import mode (merge / replace)
+    pub users: usize,
+    pub agents: usize,
+    pub sessions: usize,
+    pub credentials: usize,
+    pub timestamp: DateTime<Utc>,
+}
+
+/// Audit record for an agent deleted by its owner or an admin
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AgentDeletionAudit {
+    pub agent_id: Uuid,
+    pub owner_id: Option<Uuid>,
+    pub by_admin: bool,             // Admin key rather than the agent's own session
+    pub sessions_revoked: usize,
+    pub timestamp: DateTime<Utc>,
+}
+
+/// One setting changed by an admin update of an agent (or of a service's entry)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AgentFieldChange {
+    pub field: String,
+    pub before: Value,
+    pub after: Value,
+}
+
+/// Audit record for an admin change to an agent's configuration
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AgentConfigAudit {
+    pub agent_id: Uuid,
+    pub changes: Vec<AgentFieldChange>,
+    pub timestamp: DateTime<Utc>,
+}
+
+/// Audit record for an admin export of the proxy audit trail
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AuditExportAudit {
+    pub format: String,             // csv / jsonl
+    pub service_id: Option<String>,
+    pub from: Option<DateTime<Utc>>,
+    pub to: Option<DateTime<Utc>>,
+    pub timestamp: DateTime<Utc>,
+}
+
+/// Audit record for a security alert fired by an alert rule
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AlertAudit {
+    pub rule: String,
+    pub key: String,                // Agent id, service id or IP the rule counted per
+    pub count: u32,                 // Matches within the window
+    pub window_secs: u64,
+    pub delivered: bool,            // Whether the webhook accepted it
+    pub timestamp: DateTime<Utc>,
+}
+
+/// What an admin did, as recorded in the admin event log
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AdminAction {
+    AgentUpdated,
+    AgentSuspended,
+    AgentResumed,
+    AgentDeleted,
+    AgentKeyRotated,
+    UserDeleted,
+    UserAgentsPruned,
+    ExpiryNotificationSent,
+    AuditExported,
+    AuditVerified,
+    ServicesReloaded,
+    ServiceCreated,
+    ServiceUpdated,
+    ServiceDeleted,
+    CredentialRotated,
+    CacheCleared,
+    DataExported,
+    DataImported,
+    ExpiredAgentsPruned,
+    LifespanEnforced,
+    ExpiredSessionsDeleted,
+    AgentSessionsInvalidated,
+    AllSessionsInvalidated,
+    BackupWritten,
+}
+
+/// One admin operation in the admin event log (`GET /admin/audit/admin`)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AdminEvent {
+    pub timestamp: DateTime<Utc>,
+    pub action: AdminAction,
+    pub admin_key_hash: String,         // First 8 hex chars of SHA-256 of the X-Admin-Key used
+    pub resource_id: Option<String>,    // Agent id, service id, ...; None for gateway-wide actions
+    pub details: Value,
+}
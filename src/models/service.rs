@@ -17,11 +17,23 @@ pub struct ExternalService {
 
 #[allow(dead_code)]
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
 pub enum ServiceAuthType {
     None,
     ApiKey { header_name: String },
     BearerToken,
     OAuth2 { token_url: String, client_id: String },
+    /// Signed JWT exchanged for an access token (RFC 7523, Google service accounts)
+    JwtAssertion {
+        token_url: String,
+        issuer: String,
+        audience: String,
+        key_path: String,  // PEM private key, never stored in credentials.json
+        #[serde(default)]
+        scope: Option<String>,
+        #[serde(default)]
+        subject: Option<String>,
+    },
 }
 
 #[allow(dead_code)]
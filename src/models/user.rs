@@ -1,4 +1,5 @@
-use chrono::{DateTime, Utc};
+use chrono::{DateTime, Duration, Utc};
+use rand::Rng;
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
@@ -31,4 +32,59 @@ impl User {
             self.updated_at = Utc::now();
         }
     }
+
+    /// Drop a deleted agent from the user's list; false if it wasn't there
+    pub fn remove_agent(&mut self, agent_id: Uuid) -> bool {
+        let initial_len = self.agents.len();
+        self.agents.retain(|id| *id != agent_id);
+        if self.agents.len() != initial_len {
+            self.updated_at = Utc::now();
+            return true;
+        }
+        false
+    }
+}
+
+/// Failed `POST /auth/verify-email` attempts before the registration is dropped
+pub const MAX_VERIFICATION_ATTEMPTS: u32 = 5;
+
+/// A registration waiting for its email to be verified (REQUIRE_EMAIL_VERIFICATION).
+/// Kept apart from the users until the code comes back; the id becomes the user's.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PendingUser {
+    pub id: Uuid,
+    pub username: String,
+    pub email: String,
+    pub code: String,          // 6 digits, sent to `email` (returned in the response for now)
+    pub attempts: u32,         // Wrong codes so far
+    pub expires_at: DateTime<Utc>,
+    pub created_at: DateTime<Utc>,
+}
+
+impl PendingUser {
+    /// New registration with a random code valid for `ttl_secs`
+    pub fn new(username: String, email: String, ttl_secs: u64) -> Self {
+        let now = Utc::now();
+        Self {
+            id: Uuid::new_v4(),
+            username,
+            email,
+            code: format!("{:06}", rand::thread_rng().gen_range(0..1_000_000)),
+            attempts: 0,
+            expires_at: now + Duration::seconds(ttl_secs as i64),
+            created_at: now,
+        }
+    }
+
+    pub fn is_expired(&self) -> bool {
+        Utc::now() > self.expires_at
+    }
+
+    /// The verified user, under the id the registration returned
+    pub fn into_user(self) -> User {
+        User {
+            id: self.id,
+            ..User::new(self.username, self.email)
+        }
+    }
 }
@@ -1,49 +1,1787 @@
 use axum::{
-    extract::State,
-    routing::get,
+    body::Body,
+    extract::{Path, Query, State},
+    http::{header, HeaderMap},
+    response::{IntoResponse, Response},
+    routing::{delete, get, post, put},
     Json, Router,
 };
-use serde::Serialize;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Deserializer, Serialize};
+use serde_json::{Map, Value};
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::Arc;
+use uuid::Uuid;
 
-use crate::state::AppState;
+use crate::audit::{
+    admin_key_hash, compute_stats, log_agent_config_change, log_audit_export, log_data_transfer, parse_window, AdminEventFilter, AuditFilter, AuditStats, AuditStoreTrait,
+    AuditWriterStats, ChainReport, ExportFormat, StatsGroupBy, DEFAULT_STATS_WINDOW, DEFAULT_TOP_PATHS, MAX_TOP_PATHS,
+};
+use crate::auth::{is_admin, AgentAccess, ADMIN_KEY_HEADER};
+use crate::config::{CredentialSummary, ServiceConfig};
+use crate::error::GatewayError;
+use crate::gateway::{needs_refresh_with_skew, rotate_service_key, LatencyStats, RefreshAttempt};
+use crate::models::{
+    AdminAction, Agent, AgentConfigAudit, AgentFieldChange, AgentUsage, AuditExportAudit, AuditLog, RateLimit, DataTransferAction, Decision,
+    DataTransferAudit, ServiceAccess, User,
+};
+use crate::state::{AppState, ServiceReload};
+use crate::storage::{
+    AgentCounts, AgentFilter, AgentSort, AgentStatus, ImportMode, ImportSummary, PruneSummary, Snapshot, UserFilter,
+};
+use super::auth::{check_name_available, delete_agent_and_sessions, update_agent_with_retry, DeleteAgentResponse};
 
 pub fn admin_routes() -> Router<AppState> {
     Router::new()
         .route("/agents", get(list_agents))
+        .route("/users", get(list_users))
+        .route(
+            "/agents/:agent_id",
+            get(get_agent).patch(update_agent_config).delete(delete_agent),
+        )
+        .route("/agents/:agent_id/suspend", post(suspend_agent))
+        .route("/agents/:agent_id/resume", post(resume_agent))
+        .route("/agents/:agent_id/sessions/invalidate", post(invalidate_agent_sessions))
+        .route("/agents/:agent_id/test-notification", post(test_expiry_notification))
         .route("/audit", get(query_audit))
-        .route("/services", get(list_services))
+        .route("/audit/admin", get(query_admin_events))
+        .route("/audit/export", get(export_audit))
+        .route("/audit/verify", post(verify_audit))
+        .route("/services", get(list_services).post(create_service))
+        .route("/services/:service_id", put(update_service).delete(delete_service))
+        .route("/services/reload", post(reload_services))
+        .route("/refresh/stats", get(refresh_stats))
+        .route("/proxy/stats", get(proxy_stats))
+        .route("/overview", get(overview))
+        .route("/stats", get(audit_stats))
+        .route("/stats/latency", get(latency_stats))
+        .route("/credentials", get(credential_health))
+        .route("/credentials/:service/rotate-now", post(rotate_credential_now))
+        .route("/cache/clear", post(clear_cache))
+        .route("/export", get(export_data))
+        .route("/import", post(import_data))
+        .route("/maintenance/prune", post(prune_expired))
+        .route("/maintenance/enforce-lifespan", post(enforce_max_lifespan))
+        .route("/sessions/expired", delete(delete_expired_sessions))
+        .route("/sessions/invalidate-all", post(invalidate_all_sessions))
+        .route("/backup", post(backup_now))
+}
+
+// === Paged listings ===
+
+const DEFAULT_PER_PAGE: usize = 50;
+const MAX_PER_PAGE: usize = 500;
+
+/// Offset and page size for 1-based `page`
+fn page_window(page: Option<usize>, per_page: Option<usize>) -> Result<(usize, usize), GatewayError> {
+    let (page, per_page) = (page.unwrap_or(1), per_page.unwrap_or(DEFAULT_PER_PAGE));
+    if page == 0 {
+        return Err(GatewayError::BadRequest("page starts at 1".to_string()));
+    }
+    if per_page == 0 || per_page > MAX_PER_PAGE {
+        return Err(GatewayError::BadRequest(format!(
+            "per_page must be between 1 and {}",
+            MAX_PER_PAGE
+        )));
+    }
+    Ok(((page - 1) * per_page, per_page))
+}
+
+fn non_empty(value: Option<String>) -> Option<String> {
+    value.filter(|v| !v.trim().is_empty())
+}
+
+#[derive(Deserialize)]
+struct AgentListQuery {
+    page: Option<usize>,
+    per_page: Option<usize>,
+    /// Name substring
+    name: Option<String>,
+    /// Agents allowed to call this service
+    service: Option<String>,
+    status: Option<AgentStatus>,
+    /// Only agents whose key outlives MAX_AGENT_LIFESPAN_DAYS
+    #[serde(default)]
+    over_max_lifespan: bool,
+    #[serde(default)]
+    sort: AgentSort,
+}
+
+#[derive(Serialize)]
+struct AgentOwner {
+    user_id: Uuid,
+    username: String,
+    email: String,
+}
+
+impl From<User> for AgentOwner {
+    fn from(user: User) -> Self {
+        Self {
+            user_id: user.id,
+            username: user.username,
+            email: user.email,
+        }
+    }
 }
 
 #[derive(Serialize)]
 struct AgentInfo {
-    id: String,
+    agent_id: Uuid,
     name: String,
     description: String,
+    owner_id: Option<Uuid>,
+    /// `None` for agents created before owners were recorded, or whose user is gone
+    owner: Option<AgentOwner>,
     allowed_services: Vec<String>,
+    rate_limit: RateLimit,
+    expires_at: String,
+    is_expired: bool,
+    days_until_expiry: i64,
+    /// False while suspended
+    enabled: bool,
+    created_at: String,
+    usage: AgentUsage,
+}
+
+impl From<Agent> for AgentInfo {
+    fn from(agent: Agent) -> Self {
+        Self {
+            is_expired: agent.is_expired(),
+            days_until_expiry: agent.days_until_expiry(),
+            agent_id: agent.id,
+            name: agent.name,
+            description: agent.description,
+            owner_id: agent.owner_id,
+            owner: None,
+            allowed_services: agent.allowed_services.into_iter().map(|access| access.service_id).collect(),
+            rate_limit: agent.rate_limit,
+            expires_at: agent.expires_at.to_rfc3339(),
+            enabled: agent.enabled,
+            created_at: agent.created_at.to_rfc3339(),
+            usage: agent.usage,
+        }
+    }
+}
+
+/// GET /admin/agents?page=&per_page=&name=&service=&status=active|expired&over_max_lifespan=&sort=created|last_active
+/// Agents oldest first (or most recently active first), one page at a time
+async fn list_agents(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Query(query): Query<AgentListQuery>,
+) -> Result<Json<serde_json::Value>, GatewayError> {
+    require_admin(&headers, &state)?;
+
+    let (offset, per_page) = page_window(query.page, query.per_page)?;
+    let filter = AgentFilter {
+        name: non_empty(query.name),
+        service_id: non_empty(query.service),
+        status: query.status,
+        over_lifespan_days: query.over_max_lifespan.then_some(state.settings.max_agent_lifespan_days),
+    };
+    let page = state.agents.list_agents_page(offset, per_page, &filter, query.sort).await?;
+
+    // Only the owners of this page's agents are looked up
+    let mut owners: HashMap<Uuid, Option<User>> = HashMap::new();
+    for owner_id in page.items.iter().filter_map(|agent| agent.owner_id) {
+        if let Entry::Vacant(entry) = owners.entry(owner_id) {
+            entry.insert(state.users.get_user(owner_id).await?);
+        }
+    }
+    let agents: Vec<AgentInfo> = page
+        .items
+        .into_iter()
+        .map(|agent| {
+            let owner = agent.owner_id.and_then(|id| owners.get(&id).cloned().flatten());
+            AgentInfo {
+                owner: owner.map(AgentOwner::from),
+                ..AgentInfo::from(agent)
+            }
+        })
+        .collect();
+
+    Ok(Json(serde_json::json!({
+        "agents": agents,
+        "page": query.page.unwrap_or(1),
+        "per_page": per_page,
+        "total": page.total,
+    })))
+}
+
+/// Everything in the listing, plus the settings `PATCH /admin/agents/{id}` changes
+#[derive(Serialize)]
+struct AgentDetail {
+    #[serde(flatten)]
+    info: AgentInfo,
+    /// Granted services with their scopes (empty: every scope)
+    service_access: Vec<ServiceAccess>,
+    rate_limit_group: Option<String>,
+    ip_allowlist: Option<Vec<IpAddr>>,
+    max_concurrent_requests: Option<u32>,
+    lifespan_days: u32,
+    updated_at: String,
+}
+
+impl AgentDetail {
+    async fn load(state: &AppState, agent: Agent) -> Result<Self, GatewayError> {
+        let owner = match agent.owner_id {
+            Some(owner_id) => state.users.get_user(owner_id).await?,
+            None => None,
+        };
+        Ok(Self {
+            service_access: agent.allowed_services.clone(),
+            rate_limit_group: agent.rate_limit_group.clone(),
+            ip_allowlist: agent.ip_allowlist.clone(),
+            max_concurrent_requests: agent.max_concurrent_requests,
+            lifespan_days: agent.lifespan_days,
+            updated_at: agent.updated_at.to_rfc3339(),
+            info: AgentInfo {
+                owner: owner.map(AgentOwner::from),
+                ..AgentInfo::from(agent)
+            },
+        })
+    }
+}
+
+/// GET /admin/agents/{agent_id}
+/// One agent in full, with its owner
+async fn get_agent(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(agent_id): Path<Uuid>,
+) -> Result<Json<AgentDetail>, GatewayError> {
+    require_admin(&headers, &state)?;
+
+    let agent = state
+        .agents
+        .get_agent(agent_id)
+        .await?
+        .ok_or_else(|| GatewayError::NotFound("Agent not found".to_string()))?;
+    AgentDetail::load(&state, agent).await.map(Json)
+}
+
+#[derive(Deserialize)]
+struct AgentConfigUpdate {
+    name: Option<String>,
+    description: Option<String>,
+    rate_limit: Option<RateLimit>,
+    lifespan_days: Option<u32>,
+    /// Count the new lifespan from now rather than from when the key was issued
+    #[serde(default)]
+    lifespan_from_now: bool,
+    /// `null` removes the allowlist
+    #[serde(default, deserialize_with = "present")]
+    ip_allowlist: Option<Option<Vec<IpAddr>>>,
+    /// `null` removes the cap
+    #[serde(default, deserialize_with = "present")]
+    max_concurrent_requests: Option<Option<u32>>,
+}
+
+/// Tells a field sent as `null` (`Some(None)`) from one left out (`None`)
+fn present<'de, D, T>(deserializer: D) -> Result<Option<Option<T>>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Deserialize<'de>,
+{
+    Option::<T>::deserialize(deserializer).map(Some)
+}
+
+impl AgentConfigUpdate {
+    fn validate(&mut self, max_lifespan_days: u32) -> Result<(), GatewayError> {
+        let bad_request = |msg: &str| Err(GatewayError::BadRequest(msg.to_string()));
+        if self.name.is_none()
+            && self.description.is_none()
+            && self.rate_limit.is_none()
+            && self.lifespan_days.is_none()
+            && self.ip_allowlist.is_none()
+            && self.max_concurrent_requests.is_none()
+        {
+            return bad_request(
+                "Provide at least one of name, description, rate_limit, lifespan_days, ip_allowlist, max_concurrent_requests",
+            );
+        }
+        if let Some(name) = &mut self.name {
+            *name = name.trim().to_string();
+            if name.is_empty() {
+                return bad_request("Agent name cannot be empty");
+            }
+        }
+        if let Some(limit) = &self.rate_limit {
+            if limit.requests == 0 || limit.window_secs == 0 {
+                return bad_request("rate_limit needs at least 1 request and a window of at least 1 second");
+            }
+        }
+        match self.lifespan_days {
+            Some(days) if days == 0 || days > max_lifespan_days => {
+                return Err(GatewayError::BadRequest(format!(
+                    "lifespan_days must be between 1 and {} days",
+                    max_lifespan_days
+                )));
+            }
+            None if self.lifespan_from_now => return bad_request("lifespan_from_now needs lifespan_days"),
+            _ => {}
+        }
+        if matches!(&self.ip_allowlist, Some(Some(ips)) if ips.is_empty()) {
+            return bad_request("ip_allowlist cannot be empty; send null to remove it");
+        }
+        if self.max_concurrent_requests == Some(Some(0)) {
+            return bad_request("max_concurrent_requests must be at least 1; send null to remove it");
+        }
+        Ok(())
+    }
+
+    fn apply(&self, agent: &mut Agent) {
+        if self.name.is_some() || self.description.is_some() {
+            agent.update_metadata(self.name.clone(), self.description.clone());
+        }
+        if let Some(limit) = &self.rate_limit {
+            agent.rate_limit = *limit;
+        }
+        if let Some(days) = self.lifespan_days {
+            agent.set_lifespan(days, self.lifespan_from_now);
+        }
+        if let Some(allowlist) = &self.ip_allowlist {
+            agent.ip_allowlist = allowlist.clone();
+        }
+        if let Some(limit) = self.max_concurrent_requests {
+            agent.max_concurrent_requests = limit;
+        }
+        agent.updated_at = Utc::now();
+    }
+}
+
+/// Settings that differ between two versions of an agent, for the audit trail
+fn config_changes(before: &Agent, after: &Agent) -> Vec<AgentFieldChange> {
+    let fields = |agent: &Agent| {
+        [
+            ("name", serde_json::json!(agent.name)),
+            ("description", serde_json::json!(agent.description)),
+            ("rate_limit", serde_json::json!(agent.rate_limit)),
+            ("lifespan_days", serde_json::json!(agent.lifespan_days)),
+            ("expires_at", serde_json::json!(agent.expires_at)),
+            ("ip_allowlist", serde_json::json!(agent.ip_allowlist)),
+            ("max_concurrent_requests", serde_json::json!(agent.max_concurrent_requests)),
+            ("enabled", serde_json::json!(agent.enabled)),
+        ]
+    };
+    fields(before)
+        .into_iter()
+        .zip(fields(after))
+        .filter(|((_, before), (_, after))| before != after)
+        .map(|((field, before), (_, after))| AgentFieldChange {
+            field: field.to_string(),
+            before,
+            after,
+        })
+        .collect()
+}
+
+/// PATCH /admin/agents/{agent_id}
+/// Change an agent's name, description, rate limit, lifespan, IP allowlist or
+/// concurrency cap; the proxy applies them from the next request
+async fn update_agent_config(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(agent_id): Path<Uuid>,
+    Json(mut update): Json<AgentConfigUpdate>,
+) -> Result<Json<AgentDetail>, GatewayError> {
+    let admin = require_admin(&headers, &state)?;
+    update.validate(state.settings.max_agent_lifespan_days)?;
+
+    let before = state
+        .agents
+        .get_agent(agent_id)
+        .await?
+        .ok_or_else(|| GatewayError::NotFound("Agent not found".to_string()))?;
+    if let Some(name) = &update.name {
+        check_name_available(&state, &before, name).await?;
+    }
+
+    let agent = update_agent_with_retry(&state, before.clone(), |agent| {
+        update.apply(agent);
+        Ok(())
+    })
+    .await?;
+
+    let changes = config_changes(&before, &agent);
+    state
+        .admin_audit_logger
+        .record(
+            AdminAction::AgentUpdated,
+            &admin,
+            Some(agent_id.to_string()),
+            serde_json::json!({ "changes": changes }),
+        )
+        .await;
+    log_agent_config_change(&AgentConfigAudit {
+        agent_id,
+        changes,
+        timestamp: Utc::now(),
+    });
+    AgentDetail::load(&state, agent).await.map(Json)
+}
+
+/// POST /admin/agents/{agent_id}/suspend
+/// Refuse the agent's requests, live sessions included, until it is resumed
+async fn suspend_agent(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(agent_id): Path<Uuid>,
+) -> Result<Json<AgentDetail>, GatewayError> {
+    set_agent_enabled(state, headers, agent_id, false).await
+}
+
+/// POST /admin/agents/{agent_id}/resume
+async fn resume_agent(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(agent_id): Path<Uuid>,
+) -> Result<Json<AgentDetail>, GatewayError> {
+    set_agent_enabled(state, headers, agent_id, true).await
+}
+
+// Suspending a suspended agent (or resuming an enabled one) writes and records nothing
+async fn set_agent_enabled(
+    state: AppState,
+    headers: HeaderMap,
+    agent_id: Uuid,
+    enabled: bool,
+) -> Result<Json<AgentDetail>, GatewayError> {
+    let admin = require_admin(&headers, &state)?;
+
+    let before = state
+        .agents
+        .get_agent(agent_id)
+        .await?
+        .ok_or_else(|| GatewayError::NotFound("Agent not found".to_string()))?;
+    if before.enabled == enabled {
+        return AgentDetail::load(&state, before).await.map(Json);
+    }
+
+    let agent = update_agent_with_retry(&state, before.clone(), |agent| {
+        agent.set_enabled(enabled);
+        Ok(())
+    })
+    .await?;
+
+    let changes = config_changes(&before, &agent);
+    let action = if enabled { AdminAction::AgentResumed } else { AdminAction::AgentSuspended };
+    state
+        .admin_audit_logger
+        .record(action, &admin, Some(agent_id.to_string()), serde_json::json!({}))
+        .await;
+    log_agent_config_change(&AgentConfigAudit {
+        agent_id,
+        changes,
+        timestamp: Utc::now(),
+    });
+    AgentDetail::load(&state, agent).await.map(Json)
+}
+
+#[derive(Deserialize)]
+struct UserListQuery {
+    page: Option<usize>,
+    per_page: Option<usize>,
+    /// Username or email substring
+    #[serde(alias = "name")]
+    q: Option<String>,
+    /// Show emails as `j***@example.com`
+    #[serde(default)]
+    mask_pii: bool,
+}
+
+#[derive(Serialize)]
+struct UserInfo {
+    user_id: Uuid,
+    username: String,
+    email: String,
+    agent_count: usize,
+    /// Agents whose key hasn't expired
+    active_agent_count: usize,
+    created_at: String,
+}
+
+impl UserInfo {
+    fn new(user: User, active: &HashMap<Uuid, bool>) -> Self {
+        Self {
+            user_id: user.id,
+            active_agent_count: user.agents.iter().filter(|id| active.get(id).copied().unwrap_or(false)).count(),
+            agent_count: user.agents.len(),
+            username: user.username,
+            email: user.email,
+            created_at: user.created_at.to_rfc3339(),
+        }
+    }
+}
+
+/// First character of the local part and the domain, enough to tell users apart
+/// on a screenshot
+fn mask_email(email: &str) -> String {
+    match email.split_once('@') {
+        Some((local, domain)) => {
+            let shown: String = local.chars().take(1).collect();
+            format!("{}***@{}", shown, domain)
+        }
+        None => "***".to_string(),
+    }
+}
+
+/// GET /admin/users?page=&per_page=&q=&mask_pii=
+/// Users oldest first with how many of their agents are still active
+async fn list_users(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Query(query): Query<UserListQuery>,
+) -> Result<Json<serde_json::Value>, GatewayError> {
+    require_admin(&headers, &state)?;
+
+    let (offset, per_page) = page_window(query.page, query.per_page)?;
+    let filter = UserFilter { name: non_empty(query.q) };
+    let page = state.users.list_users_page(offset, per_page, &filter).await?;
+
+    // One lookup for every agent on the page
+    let agent_ids: Vec<Uuid> = page.items.iter().flat_map(|u| u.agents.iter().copied()).collect();
+    let active: HashMap<Uuid, bool> = state
+        .agents
+        .get_agents(&agent_ids)
+        .await?
+        .into_iter()
+        .map(|agent| (agent.id, !agent.is_expired()))
+        .collect();
+    let users: Vec<UserInfo> = page
+        .items
+        .into_iter()
+        .map(|user| {
+            let mut info = UserInfo::new(user, &active);
+            if query.mask_pii {
+                info.email = mask_email(&info.email);
+            }
+            info
+        })
+        .collect();
+
+    Ok(Json(serde_json::json!({
+        "users": users,
+        "page": query.page.unwrap_or(1),
+        "per_page": per_page,
+        "total": page.total,
+    })))
+}
+
+#[derive(Deserialize)]
+struct AuditQuery {
+    agent_id: Option<Uuid>,
+    service: Option<String>,
+    /// RFC3339, inclusive
+    from: Option<DateTime<Utc>>,
+    /// RFC3339, inclusive
+    to: Option<DateTime<Utc>>,
+    status_min: Option<u16>,
+    status_max: Option<u16>,
+    decision: Option<Decision>,
+    limit: Option<usize>,
+    #[serde(default)]
+    offset: usize,
+}
+
+/// One page of proxy audit entries; no matches is an empty `entries`, not an error
+#[derive(Serialize)]
+struct AuditQueryResponse {
+    entries: Vec<AuditLog>,
+    /// Matches across all pages
+    total: usize,
+    limit: usize,
+    offset: usize,
+}
+
+/// GET /admin/audit?agent_id=&service=&from=&to=&status_min=&status_max=&decision=&limit=&offset=
+/// Proxy audit entries newest first
+async fn query_audit(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Query(query): Query<AuditQuery>,
+) -> Result<Json<AuditQueryResponse>, GatewayError> {
+    require_admin(&headers, &state)?;
+
+    let audit = audit_store(&state).await?;
+    let limit = query.limit.unwrap_or(DEFAULT_PER_PAGE);
+    if limit == 0 || limit > MAX_PER_PAGE {
+        return Err(GatewayError::BadRequest(format!(
+            "limit must be between 1 and {}",
+            MAX_PER_PAGE
+        )));
+    }
+    check_time_range(query.from, query.to)?;
+    if let (Some(min), Some(max)) = (query.status_min, query.status_max) {
+        if min > max {
+            return Err(GatewayError::BadRequest("status_min must not exceed status_max".to_string()));
+        }
+    }
+
+    let filter = AuditFilter {
+        agent_id: query.agent_id,
+        service_id: non_empty(query.service),
+        from: query.from,
+        to: query.to,
+        status_min: query.status_min,
+        status_max: query.status_max,
+        decision: query.decision,
+    };
+    let page = audit.query(&filter, query.offset, limit).await?;
+
+    Ok(Json(AuditQueryResponse {
+        entries: page.items,
+        total: page.total,
+        limit,
+        offset: query.offset,
+    }))
+}
+
+#[derive(Deserialize)]
+struct AdminEventQuery {
+    action: Option<AdminAction>,
+    resource_id: Option<String>,
+    /// `admin_key_hash` of the acting key
+    admin: Option<String>,
+    /// RFC3339, inclusive
+    from: Option<DateTime<Utc>>,
+    /// RFC3339, inclusive
+    to: Option<DateTime<Utc>>,
+    limit: Option<usize>,
+    #[serde(default)]
+    offset: usize,
+}
+
+/// GET /admin/audit/admin?action=&resource_id=&admin=&from=&to=&limit=&offset=
+/// Admin operations newest first (ADMIN_AUDIT_LOG_PATH)
+async fn query_admin_events(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Query(query): Query<AdminEventQuery>,
+) -> Result<Json<serde_json::Value>, GatewayError> {
+    require_admin(&headers, &state)?;
+
+    let limit = query.limit.unwrap_or(DEFAULT_PER_PAGE);
+    if limit == 0 || limit > MAX_PER_PAGE {
+        return Err(GatewayError::BadRequest(format!(
+            "limit must be between 1 and {}",
+            MAX_PER_PAGE
+        )));
+    }
+    check_time_range(query.from, query.to)?;
+
+    let filter = AdminEventFilter {
+        action: query.action,
+        resource_id: non_empty(query.resource_id),
+        admin_key_hash: non_empty(query.admin),
+        from: query.from,
+        to: query.to,
+    };
+    let page = state.admin_audit_logger.query(&filter, query.offset, limit).await?;
+
+    Ok(Json(serde_json::json!({
+        "events": page.items,
+        "total": page.total,
+        "limit": limit,
+        "offset": query.offset,
+    })))
+}
+
+/// The audit store, once every entry queued so far is written
+pub(super) async fn audit_store(state: &AppState) -> Result<&Arc<dyn AuditStoreTrait>, GatewayError> {
+    let store = state.audit.as_ref().ok_or_else(|| {
+        GatewayError::BadRequest(
+            "Audit log is disabled (AUDIT_LOG_PATH is empty or STORAGE_BACKEND=memory)".to_string(),
+        )
+    })?;
+    if let Some(writer) = &state.audit_writer {
+        writer.flush().await;
+    }
+    Ok(store)
+}
+
+pub(super) fn check_time_range(from: Option<DateTime<Utc>>, to: Option<DateTime<Utc>>) -> Result<(), GatewayError> {
+    match (from, to) {
+        (Some(from), Some(to)) if from > to => {
+            Err(GatewayError::BadRequest("from must not be after to".to_string()))
+        }
+        _ => Ok(()),
+    }
 }
 
-async fn list_agents(State(_state): State<AppState>) -> Json<Vec<AgentInfo>> {
-    // TODO: Implement full agent listing from storage
-    Json(vec![])
+#[derive(Deserialize)]
+struct AuditExportQuery {
+    #[serde(default)]
+    format: ExportFormat,
+    service: Option<String>,
+    /// RFC3339, inclusive
+    from: Option<DateTime<Utc>>,
+    /// RFC3339, inclusive
+    to: Option<DateTime<Utc>>,
+    decision: Option<Decision>,
 }
 
-async fn query_audit() -> &'static str {
-    // TODO: Implement audit log query
-    "[]"
+/// GET /admin/audit/export?format=csv|jsonl&service=&from=&to=&decision=
+/// Every matching proxy audit entry, oldest first, streamed as a download
+async fn export_audit(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Query(query): Query<AuditExportQuery>,
+) -> Result<Response, GatewayError> {
+    let admin = require_admin(&headers, &state)?;
+
+    let audit = audit_store(&state).await?.clone();
+    check_time_range(query.from, query.to)?;
+    let filter = AuditFilter {
+        service_id: non_empty(query.service),
+        from: query.from,
+        to: query.to,
+        decision: query.decision,
+        ..Default::default()
+    };
+    state
+        .admin_audit_logger
+        .record(
+            AdminAction::AuditExported,
+            &admin,
+            None,
+            serde_json::json!({
+                "format": query.format.extension(),
+                "service_id": filter.service_id,
+                "from": filter.from,
+                "to": filter.to,
+                "decision": filter.decision,
+            }),
+        )
+        .await;
+    log_audit_export(&AuditExportAudit {
+        format: query.format.extension().to_string(),
+        service_id: filter.service_id.clone(),
+        from: filter.from,
+        to: filter.to,
+        timestamp: Utc::now(),
+    });
+
+    let filename = format!(
+        "audit-{}_to_{}.{}",
+        query.from.map_or("start".to_string(), |from| from.format("%Y-%m-%d").to_string()),
+        query.to.unwrap_or_else(Utc::now).format("%Y-%m-%d"),
+        query.format.extension()
+    );
+    let chunks = audit.export(filter, query.format).await?;
+    Ok((
+        [
+            (header::CONTENT_TYPE, query.format.content_type().to_string()),
+            (header::CONTENT_DISPOSITION, format!("attachment; filename=\"{}\"", filename)),
+        ],
+        Body::from_stream(chunks),
+    )
+        .into_response())
 }
 
-async fn list_services(State(state): State<AppState>) -> Json<serde_json::Value> {
-    let services: Vec<_> = state
-        .services
+#[derive(Deserialize)]
+struct AuditVerifyQuery {
+    /// RFC3339; only the days in range are checked
+    from: Option<DateTime<Utc>>,
+    to: Option<DateTime<Utc>>,
+}
+
+/// POST /admin/audit/verify?from=&to=
+/// Replay the audit HMAC chain (AUDIT_HMAC_KEY) and report any entry edited or removed
+async fn verify_audit(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Query(query): Query<AuditVerifyQuery>,
+) -> Result<Json<ChainReport>, GatewayError> {
+    let admin = require_admin(&headers, &state)?;
+
+    check_time_range(query.from, query.to)?;
+    let report = audit_store(&state).await?.verify_chain(query.from, query.to).await?;
+    if report.valid {
+        tracing::info!(checked = report.checked, anchored = report.anchored, "Audit chain verified");
+    } else {
+        tracing::warn!(
+            invalid = report.invalid,
+            first_invalid = ?report.first_invalid,
+            first_missing = ?report.first_missing,
+            "Audit chain verification failed"
+        );
+    }
+    state
+        .admin_audit_logger
+        .record(
+            AdminAction::AuditVerified,
+            &admin,
+            None,
+            serde_json::json!({ "from": query.from, "to": query.to, "report": report }),
+        )
+        .await;
+    Ok(Json(report))
+}
+
+/// GET /admin/services
+/// Registered services with their upstream URLs and status
+async fn list_services(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> Result<Json<serde_json::Value>, GatewayError> {
+    require_admin(&headers, &state)?;
+
+    let current = state.services();
+    let services: Vec<_> = current
+        .registry
         .list()
         .iter()
         .map(|s| serde_json::json!({
             "id": s.id,
             "name": s.name,
             "description": s.description,
+            "aliases": s.aliases,
             "base_url": s.base_url,
+            "status": current.registry.status(&s.id),
         }))
         .collect();
 
-    Json(serde_json::json!({ "services": services }))
+    Ok(Json(serde_json::json!({ "services": services })))
+}
+
+/// A service as sent to `POST`/`PUT /admin/services`, checked to parse; `path_id`
+/// fills in a missing id and must match a given one
+fn service_entry(body: Value, path_id: Option<&str>) -> Result<Map<String, Value>, GatewayError> {
+    let Value::Object(mut entry) = body else {
+        return Err(GatewayError::BadRequest("Service must be a JSON object".to_string()));
+    };
+    if let Some(path_id) = path_id {
+        match entry.get("id").and_then(Value::as_str) {
+            None => {
+                entry.insert("id".to_string(), Value::String(path_id.to_string()));
+            }
+            Some(id) if id != path_id => {
+                return Err(GatewayError::BadRequest(format!(
+                    "Service id '{}' doesn't match the URL; a service's id can't be changed",
+                    id
+                )));
+            }
+            Some(_) => {}
+        }
+    }
+    serde_json::from_value::<ServiceConfig>(Value::Object(entry.clone()))
+        .map_err(|e| GatewayError::BadRequest(format!("Invalid service: {}", e)))?;
+    Ok(entry)
+}
+
+/// Fields that differ between two versions of a service's entry, for the admin
+/// event log; `None` is no entry (created or deleted). Signing keys are masked.
+fn service_changes(before: Option<&Map<String, Value>>, after: Option<&Map<String, Value>>) -> Vec<AgentFieldChange> {
+    let empty = Map::new();
+    let (before, after) = (before.unwrap_or(&empty), after.unwrap_or(&empty));
+    let mut fields: Vec<&String> = before.keys().chain(after.keys()).collect();
+    fields.sort();
+    fields.dedup();
+    let value = |entry: &Map<String, Value>, field: &str| match entry.get(field) {
+        Some(_) if field == "signing_key" => Value::String("[REDACTED]".to_string()),
+        Some(value) => value.clone(),
+        None => Value::Null,
+    };
+    fields
+        .into_iter()
+        .filter(|field| before.get(*field) != after.get(*field))
+        .map(|field| AgentFieldChange {
+            field: field.clone(),
+            before: value(before, field),
+            after: value(after, field),
+        })
+        .collect()
+}
+
+/// The service as now served
+fn current_service(state: &AppState, service_id: &str) -> Result<Json<ServiceConfig>, GatewayError> {
+    state
+        .services()
+        .registry
+        .get(service_id)
+        .cloned()
+        .map(Json)
+        .ok_or_else(|| GatewayError::NotFound(format!("Service '{}' not found", service_id)))
+}
+
+/// POST /admin/services
+/// Add a service to the services file and serve it from the next request
+async fn create_service(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Json(body): Json<Value>,
+) -> Result<Json<ServiceConfig>, GatewayError> {
+    let admin = require_admin(&headers, &state)?;
+    let entry = service_entry(body, None)?;
+    let service_id = entry.get("id").and_then(Value::as_str).unwrap_or_default().to_string();
+
+    state
+        .edit_services(|document, registry| {
+            if registry.resolve(&service_id).is_some() || document.get(&service_id).is_some() {
+                return Err(GatewayError::Conflict(format!("Service '{}' already exists", service_id)));
+            }
+            document.upsert(entry.clone());
+            Ok(())
+        })
+        .await?;
+
+    tracing::info!(service_id = %service_id, "Service created");
+    state
+        .admin_audit_logger
+        .record(
+            AdminAction::ServiceCreated,
+            &admin,
+            Some(service_id.clone()),
+            serde_json::json!({ "changes": service_changes(None, Some(&entry)) }),
+        )
+        .await;
+    current_service(&state, &service_id)
+}
+
+/// PUT /admin/services/{service_id}
+/// Replace a service's entry in the services file. A service discovered from
+/// the environment gets an entry, which then takes precedence.
+async fn update_service(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(service_id): Path<String>,
+    Json(body): Json<Value>,
+) -> Result<Json<ServiceConfig>, GatewayError> {
+    let admin = require_admin(&headers, &state)?;
+    let entry = service_entry(body, Some(&service_id))?;
+
+    let (before, _) = state
+        .edit_services(|document, registry| {
+            let discovered = registry
+                .get(&service_id)
+                .filter(|service| service.id == service_id)
+                .and_then(|service| serde_json::to_value(service).ok());
+            match document.upsert(entry.clone()) {
+                Some(before) => Ok(Some(before)),
+                None => match discovered {
+                    Some(Value::Object(before)) => Ok(Some(before)),
+                    _ => Err(GatewayError::NotFound(format!("Service '{}' not found", service_id))),
+                },
+            }
+        })
+        .await?;
+
+    let changes = service_changes(before.as_ref(), Some(&entry));
+    tracing::info!(service_id = %service_id, changed = changes.len(), "Service updated");
+    state
+        .admin_audit_logger
+        .record(
+            AdminAction::ServiceUpdated,
+            &admin,
+            Some(service_id.clone()),
+            serde_json::json!({ "changes": changes }),
+        )
+        .await;
+    current_service(&state, &service_id)
+}
+
+#[derive(Deserialize)]
+struct DeleteServiceQuery {
+    /// Also revoke the service from the agents it is granted to
+    #[serde(default)]
+    force: bool,
+}
+
+/// DELETE /admin/services/{service_id}?force=true
+/// Remove a service from the services file. Refused while agents hold a grant
+/// on it (under its id or an alias) unless `force`, which revokes those grants.
+async fn delete_service(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(service_id): Path<String>,
+    Query(query): Query<DeleteServiceQuery>,
+) -> Result<Json<serde_json::Value>, GatewayError> {
+    let admin = require_admin(&headers, &state)?;
+
+    let names: Vec<String> = match state.services().registry.get(&service_id) {
+        Some(service) if service.id == service_id => service.names().map(str::to_string).collect(),
+        _ => vec![service_id.clone()],
+    };
+    let granted: Vec<Agent> = state
+        .agents
+        .list_agents()
+        .await?
+        .into_iter()
+        .filter(|agent| names.iter().any(|name| agent.can_access_service(name)))
+        .collect();
+    if !granted.is_empty() && !query.force {
+        return Err(GatewayError::Conflict(format!(
+            "Service '{}' is still granted to {} agent(s); pass force=true to revoke those grants and delete it",
+            service_id,
+            granted.len()
+        )));
+    }
+
+    let (before, _) = state
+        .edit_services(|document, registry| match document.remove(&service_id) {
+            Some(before) => Ok(before),
+            None if registry.resolve(&service_id) == Some(service_id.as_str()) => Err(GatewayError::BadRequest(format!(
+                "Service '{}' is discovered from the environment; unset its _SERVICE_URL variable to remove it",
+                service_id
+            ))),
+            None => Err(GatewayError::NotFound(format!("Service '{}' not found", service_id))),
+        })
+        .await?;
+
+    // Only once the service is gone, so a rejected delete leaves every grant in place
+    let mut revoked = Vec::new();
+    for agent in granted {
+        let agent = update_agent_with_retry(&state, agent, |agent| {
+            for name in &names {
+                agent.remove_service(name);
+            }
+            Ok(())
+        })
+        .await?;
+        revoked.push(agent.id);
+    }
+
+    tracing::warn!(service_id = %service_id, revoked_grants = revoked.len(), "Service deleted");
+    state
+        .admin_audit_logger
+        .record(
+            AdminAction::ServiceDeleted,
+            &admin,
+            Some(service_id.clone()),
+            serde_json::json!({
+                "changes": service_changes(Some(&before), None),
+                "revoked_grants": revoked,
+            }),
+        )
+        .await;
+    Ok(Json(serde_json::json!({
+        "service_id": service_id,
+        "deleted": true,
+        "revoked_grants": revoked,
+    })))
+}
+
+/// POST /admin/services/reload
+/// Re-read SERVICES_CONFIG_PATH and switch to it without a restart. Requests
+/// already in flight finish on the services they started with; if the file is
+/// invalid, the current services stay and the problems are returned.
+async fn reload_services(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> Result<Json<ServiceReload>, GatewayError> {
+    let admin = require_admin(&headers, &state)?;
+
+    let reload = state.reload_services().await.inspect_err(|e| {
+        tracing::warn!(error = ?e, "Services reload rejected");
+    })?;
+    tracing::info!(
+        services = reload.services,
+        added = ?reload.added,
+        removed = ?reload.removed,
+        "Services reloaded"
+    );
+    state
+        .admin_audit_logger
+        .record(AdminAction::ServicesReloaded, &admin, None, serde_json::json!(reload))
+        .await;
+    Ok(Json(reload))
+}
+
+/// GET /admin/refresh/stats
+/// Token refresh counters and duration histogram per service
+async fn refresh_stats(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> Result<Json<serde_json::Value>, GatewayError> {
+    require_admin(&headers, &state)?;
+
+    let stats = state.refresh_metrics.snapshot().await;
+    Ok(Json(serde_json::json!({ "services": stats })))
+}
+
+/// GET /admin/proxy/stats
+/// In-flight, completed and client-cancelled proxy requests
+#[derive(Debug, Serialize)]
+struct ProxyStatsResponse {
+    #[serde(flatten)]
+    proxy: crate::gateway::ProxyStats,
+    /// Audit queue; absent when the audit log is disabled
+    #[serde(skip_serializing_if = "Option::is_none")]
+    audit: Option<AuditWriterStats>,
+}
+
+async fn proxy_stats(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> Result<Json<ProxyStatsResponse>, GatewayError> {
+    require_admin(&headers, &state)?;
+
+    Ok(Json(ProxyStatsResponse {
+        proxy: state.proxy_metrics.snapshot(),
+        audit: state.audit_writer.as_ref().map(|writer| writer.stats()),
+    }))
+}
+
+#[derive(Debug, Serialize)]
+struct OverviewCredentials {
+    stored: usize,
+    /// Expiring within the refresh buffer (or already expired)
+    needs_refresh: usize,
+}
+
+/// Fixed set of numeric fields, so dashboards can map them once
+#[derive(Debug, Serialize)]
+struct GatewayOverview {
+    users: usize,
+    agents: AgentCounts,
+    live_sessions: usize,
+    services: usize,
+    credentials: OverviewCredentials,
+    /// Requests sent upstream
+    requests_last_hour: u64,
+    /// User, agent, group and service limits on the proxy
+    rate_limit_rejections_last_hour: u64,
+    /// Per-IP limit on registration and key creation
+    ip_rate_limit_rejections_last_hour: u64,
+    uptime_secs: u64,
+    generated_at: DateTime<Utc>,
+}
+
+/// GET /admin/overview
+/// Counts across the stores plus recent traffic, for dashboards to poll
+async fn overview(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> Result<Json<GatewayOverview>, GatewayError> {
+    require_admin(&headers, &state)?;
+
+    let (users, agents, live_sessions) = tokio::try_join!(
+        state.users.count_users(),
+        state.agents.count_agents_by_status(),
+        state.sessions.count_live_sessions(),
+    )?;
+    let credentials = state.credentials.list().await;
+    let skew = state.settings.clock_skew_secs;
+
+    Ok(Json(GatewayOverview {
+        users,
+        agents,
+        live_sessions,
+        services: state.services().registry.list().len(),
+        credentials: OverviewCredentials {
+            stored: credentials.len(),
+            needs_refresh: credentials.iter().filter(|c| needs_refresh_with_skew(c, skew)).count(),
+        },
+        requests_last_hour: state.proxy_metrics.proxied_last_hour(),
+        rate_limit_rejections_last_hour: state.rate_limiter.rejections_last_hour(),
+        ip_rate_limit_rejections_last_hour: state.ip_rate_limiter.rejections_last_hour(),
+        uptime_secs: state.started_at.elapsed().as_secs(),
+        generated_at: Utc::now(),
+    }))
+}
+
+#[derive(Debug, Deserialize)]
+struct LatencyQuery {
+    service: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+struct LatencyResponse {
+    service: Option<String>,
+    #[serde(flatten)]
+    stats: LatencyStats,
+}
+
+/// GET /admin/stats/latency?service={id}
+/// p50/p95/p99 of total, upstream and gateway time over recent proxied requests,
+/// for one service or all of them
+async fn latency_stats(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Query(query): Query<LatencyQuery>,
+) -> Result<Json<LatencyResponse>, GatewayError> {
+    require_admin(&headers, &state)?;
+
+    if let Some(service) = &query.service {
+        state
+            .services()
+            .registry
+            .get(service)
+            .ok_or_else(|| GatewayError::NotFound(format!("Service '{}' not found", service)))?;
+    }
+    let stats = state.latency_metrics.stats(query.service.as_deref()).await;
+    Ok(Json(LatencyResponse { service: query.service, stats }))
+}
+
+#[derive(Debug, Deserialize)]
+struct AuditStatsQuery {
+    window: Option<String>,
+    #[serde(default)]
+    group_by: StatsGroupBy,
+    top: Option<usize>,
+}
+
+/// GET /admin/stats?window=24h&group_by=service|agent&top=5
+/// Request and error counts, latency and top paths per service or agent over
+/// the last `window`, from the audit log; cached briefly
+async fn audit_stats(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Query(query): Query<AuditStatsQuery>,
+) -> Result<Json<AuditStats>, GatewayError> {
+    require_admin(&headers, &state)?;
+
+    let window = parse_window(query.window.as_deref().unwrap_or(DEFAULT_STATS_WINDOW))?;
+    let top = query.top.unwrap_or(DEFAULT_TOP_PATHS);
+    if top == 0 || top > MAX_TOP_PATHS {
+        return Err(GatewayError::BadRequest(format!("top must be between 1 and {}", MAX_TOP_PATHS)));
+    }
+    if let Some(stats) = state.audit_stats_cache.get(window, query.group_by, top) {
+        return Ok(Json(stats));
+    }
+
+    let store = audit_store(&state).await?;
+    let to = Utc::now();
+    let mut stats = compute_stats(store.as_ref(), to - window, to, query.group_by, top).await?;
+    if query.group_by == StatsGroupBy::Agent {
+        let ids: Vec<Uuid> = stats.groups.iter().filter_map(|group| group.key.parse().ok()).collect();
+        let names: HashMap<String, String> = state
+            .agents
+            .get_agents(&ids)
+            .await?
+            .into_iter()
+            .map(|agent| (agent.id.to_string(), agent.name))
+            .collect();
+        for group in &mut stats.groups {
+            group.name = names.get(&group.key).cloned();
+        }
+    }
+    state.audit_stats_cache.insert(window, query.group_by, top, stats.clone());
+    Ok(Json(stats))
+}
+
+/// POST /admin/credentials/{service}/rotate-now
+/// Run the service's static key rotation hook immediately
+async fn rotate_credential_now(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(service): Path<String>,
+) -> Result<Json<serde_json::Value>, GatewayError> {
+    let admin = require_admin(&headers, &state)?;
+
+    rotate_service_key(
+        &reqwest::Client::new(),
+        &state.services().registry,
+        &state.credentials,
+        &service,
+    )
+    .await?;
+
+    state
+        .admin_audit_logger
+        .record(AdminAction::CredentialRotated, &admin, Some(service.clone()), serde_json::json!({}))
+        .await;
+    Ok(Json(serde_json::json!({
+        "service_id": service,
+        "rotated": true,
+        "rotated_at": chrono::Utc::now().to_rfc3339(),
+    })))
+}
+
+#[derive(Debug, Serialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+enum CredentialStatus {
+    Healthy,
+    /// Within the refresh buffer
+    Expiring,
+    Expired,
+    /// Configured service with nothing stored
+    Missing,
+}
+
+/// One service's credential; the same fields whatever its status, so
+/// dashboards can map them once
+#[derive(Debug, Serialize)]
+struct CredentialHealth {
+    service_id: String,
+    status: CredentialStatus,
+    /// False for a stored credential whose service is no longer configured
+    configured: bool,
+    token_suffix: Option<String>,
+    scopes: Vec<String>,
+    expires_at: Option<DateTime<Utc>>,
+    needs_refresh: bool,
+    has_refresh_token: bool,
+    /// Latest refresh attempt since startup
+    last_refresh: Option<RefreshAttempt>,
+    /// Recent refresh attempts, newest first
+    refresh_history: Vec<RefreshAttempt>,
+    /// Last proxied call the upstream accepted, since startup
+    last_used_at: Option<DateTime<Utc>>,
+}
+
+/// GET /admin/credentials
+/// Every configured service's credential state, without the tokens
+async fn credential_health(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> Result<Json<serde_json::Value>, GatewayError> {
+    require_admin(&headers, &state)?;
+
+    let services = state.services();
+    let mut stored: HashMap<String, CredentialSummary> = state
+        .credentials
+        .summaries(state.settings.clock_skew_secs)
+        .await
+        .into_iter()
+        .map(|summary| (summary.service_id.clone(), summary))
+        .collect();
+    let mut service_ids: Vec<String> = services.registry.list().iter().map(|s| s.id.clone()).collect();
+    service_ids.sort();
+    let unconfigured: Vec<String> = stored.keys().filter(|id| !service_ids.contains(id)).cloned().collect();
+
+    let mut credentials = Vec::new();
+    for (service_id, configured) in service_ids
+        .into_iter()
+        .map(|id| (id, true))
+        .chain(unconfigured.into_iter().map(|id| (id, false)))
+    {
+        let refresh_history = state.refresh_metrics.history(&service_id).await;
+        let summary = stored.remove(&service_id);
+        let status = match &summary {
+            None => CredentialStatus::Missing,
+            Some(c) if c.expired => CredentialStatus::Expired,
+            Some(c) if c.needs_refresh => CredentialStatus::Expiring,
+            Some(_) => CredentialStatus::Healthy,
+        };
+        credentials.push(CredentialHealth {
+            status,
+            configured,
+            token_suffix: summary.as_ref().map(|c| c.token_suffix.clone()),
+            scopes: summary.as_ref().map(|c| c.scopes.clone()).unwrap_or_default(),
+            expires_at: summary.as_ref().and_then(|c| c.expires_at),
+            needs_refresh: summary.as_ref().is_some_and(|c| c.needs_refresh),
+            has_refresh_token: summary.as_ref().is_some_and(|c| c.has_refresh_token),
+            last_refresh: refresh_history.first().cloned(),
+            refresh_history,
+            last_used_at: summary.and_then(|c| c.last_used_at),
+            service_id,
+        });
+    }
+
+    Ok(Json(serde_json::json!({ "credentials": credentials })))
+}
+
+#[derive(Deserialize)]
+struct ClearCacheQuery {
+    service: Option<String>,
+}
+
+/// POST /admin/cache/clear?service={service}
+/// Drop cached GET responses for one service, or all of them
+async fn clear_cache(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Query(query): Query<ClearCacheQuery>,
+) -> Result<Json<serde_json::Value>, GatewayError> {
+    let admin = require_admin(&headers, &state)?;
+
+    let cleared = state.response_cache.clear(query.service.as_deref());
+
+    tracing::info!(service = ?query.service, cleared, "Response cache cleared");
+    state
+        .admin_audit_logger
+        .record(
+            AdminAction::CacheCleared,
+            &admin,
+            query.service.clone(),
+            serde_json::json!({ "cleared": cleared }),
+        )
+        .await;
+    Ok(Json(serde_json::json!({
+        "service_id": query.service,
+        "cleared": cleared,
+    })))
+}
+
+/// The admin key's hash (`admin_key_hash`), for the admin event log
+pub(super) fn require_admin(headers: &HeaderMap, state: &AppState) -> Result<String, GatewayError> {
+    if !is_admin(headers, state) {
+        return Err(GatewayError::Unauthorized("Missing or invalid X-Admin-Key header".to_string()));
+    }
+    let key = headers.get(ADMIN_KEY_HEADER).and_then(|v| v.to_str().ok()).unwrap_or_default();
+    Ok(admin_key_hash(key))
+}
+
+/// DELETE /admin/agents/{agent_id}
+/// Delete any agent, revoking its sessions and dropping it from its owner
+async fn delete_agent(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(agent_id): Path<Uuid>,
+) -> Result<Json<DeleteAgentResponse>, GatewayError> {
+    let admin = require_admin(&headers, &state)?;
+
+    let agent = state
+        .agents
+        .get_agent(agent_id)
+        .await?
+        .ok_or_else(|| GatewayError::NotFound("Agent not found".to_string()))?;
+    let deleted = delete_agent_and_sessions(&state, agent, AgentAccess::Admin).await?;
+    state
+        .admin_audit_logger
+        .record(
+            AdminAction::AgentDeleted,
+            &admin,
+            Some(agent_id.to_string()),
+            serde_json::json!({ "sessions_revoked": deleted.sessions_revoked }),
+        )
+        .await;
+    Ok(Json(deleted))
+}
+
+/// POST /admin/agents/{agent_id}/test-notification
+/// Send the expiry webhook for one agent now, whatever its expiry date
+async fn test_expiry_notification(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(agent_id): Path<Uuid>,
+) -> Result<Json<serde_json::Value>, GatewayError> {
+    let admin = require_admin(&headers, &state)?;
+
+    let notifier = state.expiry_notifier.as_ref().ok_or_else(|| {
+        GatewayError::BadRequest("Expiry notifications are disabled (EXPIRY_WEBHOOK_URL is not set)".to_string())
+    })?;
+    let agent = state
+        .agents
+        .get_agent(agent_id)
+        .await?
+        .ok_or_else(|| GatewayError::NotFound("Agent not found".to_string()))?;
+
+    notifier.notify(&agent, true).await?;
+
+    tracing::info!(agent_id = %agent_id, "Test expiry notification sent");
+    state
+        .admin_audit_logger
+        .record(AdminAction::ExpiryNotificationSent, &admin, Some(agent_id.to_string()), serde_json::json!({}))
+        .await;
+    Ok(Json(serde_json::json!({
+        "agent_id": agent_id,
+        "delivered": true,
+        "days_remaining": agent.days_until_expiry(),
+    })))
+}
+
+#[derive(Deserialize)]
+struct ExportQuery {
+    #[serde(default = "default_include_sessions")]
+    include_sessions: bool,
+}
+
+fn default_include_sessions() -> bool {
+    true
+}
+
+/// GET /admin/export?include_sessions={bool}
+/// Users, agents, sessions and (still encrypted) service credentials as one document
+async fn export_data(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Query(query): Query<ExportQuery>,
+) -> Result<Json<Snapshot>, GatewayError> {
+    let admin = require_admin(&headers, &state)?;
+
+    let snapshot = state.snapshot_stores().export(query.include_sessions).await?;
+    state
+        .admin_audit_logger
+        .record(
+            AdminAction::DataExported,
+            &admin,
+            None,
+            serde_json::json!({
+                "users": snapshot.users.len(),
+                "agents": snapshot.agents.len(),
+                "sessions": snapshot.sessions.len(),
+                "credentials": snapshot.credentials.len(),
+            }),
+        )
+        .await;
+    log_data_transfer(&DataTransferAudit {
+        action: DataTransferAction::Export,
+        mode: None,
+        users: snapshot.users.len(),
+        agents: snapshot.agents.len(),
+        sessions: snapshot.sessions.len(),
+        credentials: snapshot.credentials.len(),
+        timestamp: Utc::now(),
+    });
+    Ok(Json(snapshot))
+}
+
+#[derive(Deserialize)]
+struct ImportRequest {
+    snapshot: Snapshot,
+    #[serde(default)]
+    mode: ImportMode,
+    /// ENCRYPTION_KEY of the exporting gateway; defaults to this gateway's key
+    source_encryption_key: Option<String>,
+}
+
+/// POST /admin/import
+/// Load a snapshot from `GET /admin/export`, merging into or replacing current data
+async fn import_data(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Json(request): Json<ImportRequest>,
+) -> Result<Json<ImportSummary>, GatewayError> {
+    let admin = require_admin(&headers, &state)?;
+
+    let source_key = request
+        .source_encryption_key
+        .unwrap_or_else(|| state.settings.encryption_key.clone());
+    let summary = state
+        .snapshot_stores()
+        .import(request.snapshot, request.mode, &source_key)
+        .await?;
+    let mode = format!("{:?}", request.mode).to_lowercase();
+    state
+        .admin_audit_logger
+        .record(
+            AdminAction::DataImported,
+            &admin,
+            None,
+            serde_json::json!({ "mode": mode, "summary": summary }),
+        )
+        .await;
+
+    log_data_transfer(&DataTransferAudit {
+        action: DataTransferAction::Import,
+        mode: Some(mode),
+        users: summary.users,
+        agents: summary.agents,
+        sessions: summary.sessions,
+        credentials: summary.credentials,
+        timestamp: Utc::now(),
+    });
+    Ok(Json(summary))
+}
+
+#[derive(Deserialize)]
+struct PruneQuery {
+    #[serde(default)]
+    dry_run: bool,
+    /// Overrides PRUNE_GRACE_DAYS for this run
+    grace_days: Option<i64>,
+}
+
+/// POST /admin/maintenance/prune?dry_run={bool}&grace_days={days}
+/// Remove long-expired agents, their sessions and dangling agent ids on users
+async fn prune_expired(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Query(query): Query<PruneQuery>,
+) -> Result<Json<PruneSummary>, GatewayError> {
+    let admin = require_admin(&headers, &state)?;
+
+    let grace_days = query.grace_days.unwrap_or(state.settings.prune_grace_days);
+    if grace_days < 0 {
+        return Err(GatewayError::BadRequest("grace_days must not be negative".to_string()));
+    }
+    let summary = state.prune_stores().prune(grace_days, query.dry_run).await?;
+    if !query.dry_run {
+        state
+            .admin_audit_logger
+            .record(
+                AdminAction::ExpiredAgentsPruned,
+                &admin,
+                None,
+                serde_json::json!({ "grace_days": grace_days, "summary": summary }),
+            )
+            .await;
+    }
+    Ok(Json(summary))
+}
+
+/// DELETE /admin/sessions/expired
+/// Remove every expired session now, without waiting for the background sweep
+async fn delete_expired_sessions(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> Result<Json<serde_json::Value>, GatewayError> {
+    let admin = require_admin(&headers, &state)?;
+
+    let removed = state.sessions.delete_expired_sessions().await?;
+    tracing::info!(removed, "Expired sessions cleaned up");
+    state
+        .admin_audit_logger
+        .record(AdminAction::ExpiredSessionsDeleted, &admin, None, serde_json::json!({ "removed": removed }))
+        .await;
+    Ok(Json(serde_json::json!({ "removed": removed })))
+}
+
+/// POST /admin/agents/{agent_id}/sessions/invalidate
+/// Revoke every session of one agent; it keeps its key and must start new sessions
+async fn invalidate_agent_sessions(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(agent_id): Path<Uuid>,
+) -> Result<Json<serde_json::Value>, GatewayError> {
+    let admin = require_admin(&headers, &state)?;
+
+    if state.agents.get_agent(agent_id).await?.is_none() {
+        return Err(GatewayError::NotFound("Agent not found".to_string()));
+    }
+    let invalidated = state.sessions.delete_sessions_for_agents(&[agent_id]).await?;
+    tracing::warn!(agent_id = %agent_id, invalidated, "Agent sessions invalidated by admin");
+    state
+        .admin_audit_logger
+        .record(
+            AdminAction::AgentSessionsInvalidated,
+            &admin,
+            Some(agent_id.to_string()),
+            serde_json::json!({ "invalidated": invalidated }),
+        )
+        .await;
+    Ok(Json(serde_json::json!({ "agent_id": agent_id, "sessions_invalidated": invalidated })))
+}
+
+#[derive(Deserialize)]
+struct InvalidateAllRequest {
+    /// Must be "yes": a second confirmation on top of the admin key
+    confirm: Option<String>,
+}
+
+/// POST /admin/sessions/invalidate-all
+/// Kill switch: revoke every session of every agent
+async fn invalidate_all_sessions(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Json(req): Json<InvalidateAllRequest>,
+) -> Result<Json<serde_json::Value>, GatewayError> {
+    let admin = require_admin(&headers, &state)?;
+    if req.confirm.as_deref() != Some("yes") {
+        return Err(GatewayError::BadRequest(
+            "Invalidating every session requires {\"confirm\": \"yes\"}".to_string(),
+        ));
+    }
+
+    let invalidated = state.sessions.delete_all_sessions().await?;
+    tracing::warn!(invalidated, "All sessions invalidated by admin");
+    state
+        .admin_audit_logger
+        .record(
+            AdminAction::AllSessionsInvalidated,
+            &admin,
+            None,
+            serde_json::json!({ "invalidated": invalidated }),
+        )
+        .await;
+    Ok(Json(serde_json::json!({ "sessions_invalidated": invalidated })))
+}
+
+#[derive(Deserialize)]
+struct EnforceLifespanQuery {
+    #[serde(default)]
+    dry_run: bool,
+}
+
+#[derive(Serialize)]
+struct CappedAgent {
+    agent_id: Uuid,
+    name: String,
+    expires_at: String,
+    new_expires_at: String,
+}
+
+#[derive(Serialize)]
+struct EnforceLifespanSummary {
+    max_lifespan_days: u32,
+    dry_run: bool,
+    agents: Vec<CappedAgent>,
+}
+
+/// POST /admin/maintenance/enforce-lifespan?dry_run={bool}
+/// Shorten keys that outlive MAX_AGENT_LIFESPAN_DAYS (after the cap was lowered)
+/// to that many days from when they were issued
+async fn enforce_max_lifespan(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Query(query): Query<EnforceLifespanQuery>,
+) -> Result<Json<EnforceLifespanSummary>, GatewayError> {
+    let admin = require_admin(&headers, &state)?;
+
+    let max_days = state.settings.max_agent_lifespan_days;
+    let mut capped = Vec::new();
+    for agent in state.agents.list_agents().await? {
+        if !agent.exceeds_lifespan(max_days) {
+            continue;
+        }
+        let expires_at = agent.expires_at;
+        let agent = if query.dry_run {
+            let mut agent = agent;
+            agent.cap_lifespan(max_days);
+            agent
+        } else {
+            update_agent_with_retry(&state, agent, |agent| {
+                agent.cap_lifespan(max_days);
+                Ok(())
+            })
+            .await?
+        };
+        capped.push(CappedAgent {
+            agent_id: agent.id,
+            name: agent.name,
+            expires_at: expires_at.to_rfc3339(),
+            new_expires_at: agent.expires_at.to_rfc3339(),
+        });
+    }
+
+    if !query.dry_run && !capped.is_empty() {
+        tracing::info!(count = capped.len(), max_lifespan_days = max_days, "Agent key lifespans capped");
+        let agent_ids: Vec<Uuid> = capped.iter().map(|agent| agent.agent_id).collect();
+        state
+            .admin_audit_logger
+            .record(
+                AdminAction::LifespanEnforced,
+                &admin,
+                None,
+                serde_json::json!({ "max_lifespan_days": max_days, "agent_ids": agent_ids }),
+            )
+            .await;
+    }
+    Ok(Json(EnforceLifespanSummary {
+        max_lifespan_days: max_days,
+        dry_run: query.dry_run,
+        agents: capped,
+    }))
+}
+
+/// POST /admin/backup
+/// Copy the store and credentials files to fresh timestamped backups now
+async fn backup_now(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> Result<Json<serde_json::Value>, GatewayError> {
+    let admin = require_admin(&headers, &state)?;
+
+    let mut backups = state.users.backup().await?;
+    backups.extend(state.agents.backup().await?);
+    backups.extend(state.credentials.backup().await?);
+
+    tracing::info!(count = backups.len(), "Backups written");
+    state
+        .admin_audit_logger
+        .record(AdminAction::BackupWritten, &admin, None, serde_json::json!({ "backups": backups }))
+        .await;
+    Ok(Json(serde_json::json!({
+        "backups": backups,
+        "message": if backups.is_empty() {
+            "Nothing to back up (in-memory or database storage, or backups disabled)"
+        } else {
+            "Backups written"
+        },
+    })))
 }
@@ -1,23 +1,43 @@
 use axum::{
-    extract::{Path, State},
+    extract::{ConnectInfo, Path, Query, State},
+    http::HeaderMap,
     routing::{delete, get, post},
     Json, Router,
 };
+use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
+use std::net::SocketAddr;
 use uuid::Uuid;
 
+use super::admin::{audit_store, check_time_range, require_admin};
+use crate::audit::{log_agent_deletion, AuditFilter};
+use crate::auth::{AgentAccess, VerifiedAgent};
 use crate::error::GatewayError;
-use crate::models::{Agent, User};
+use crate::gateway::client_ip;
+use crate::models::{
+    AdminAction, Agent, AgentDeletionAudit, AgentUsage, AuditLog, Decision, PendingUser, ServiceAccess, User,
+    MAX_VERIFICATION_ATTEMPTS,
+};
 use crate::state::AppState;
 
+const DEFAULT_ACTIVITY_LIMIT: usize = 50;
+const MAX_ACTIVITY_LIMIT: usize = 500;
+
 pub fn auth_routes() -> Router<AppState> {
     Router::new()
         .route("/register", post(register_user))
+        .route("/verify-email", post(verify_email))
+        .route("/users/:user_id", delete(delete_user))
+        .route("/users/:user_id/agents", get(list_user_agents))
         .route("/agent", post(create_agent_access))
-        .route("/agent/{agent_id}", get(get_agent_info))
-        .route("/agent/{agent_id}/rotate", post(rotate_agent_key))
-        .route("/agent/{agent_id}/services", post(grant_service_access))
-        .route("/agent/{agent_id}/services/{service_id}", delete(revoke_service_access))
+        .route(
+            "/agent/:agent_id",
+            get(get_agent_info).patch(update_agent_metadata).delete(delete_agent),
+        )
+        .route("/agent/:agent_id/rotate", post(rotate_agent_key))
+        .route("/agent/:agent_id/activity", get(agent_activity))
+        .route("/agent/:agent_id/services", post(grant_service_access))
+        .route("/agent/:agent_id/services/:service_id", delete(revoke_service_access))
         .route("/services", get(list_available_services))
 }
 
@@ -35,6 +55,41 @@ pub struct RegisterResponse {
     pub username: String,
     pub email: String,
     pub message: String,
+    // Set while the user waits for POST /auth/verify-email
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub verification_required: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub verification_code: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub verification_expires_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct VerifyEmailRequest {
+    pub user_id: Uuid,
+    pub code: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct DeleteUserQuery {
+    #[serde(default)]
+    pub purge_credentials: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct DeleteUserResponse {
+    pub user_id: Uuid,
+    pub sessions_revoked: usize,
+    pub credentials_removed: usize,
+    pub agents_removed: usize,
+    pub message: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct DeleteAgentResponse {
+    pub agent_id: Uuid,
+    pub sessions_revoked: usize,
+    pub message: String,
 }
 
 #[derive(Debug, Deserialize)]
@@ -45,6 +100,8 @@ pub struct CreateAgentRequest {
     pub services: Vec<String>,
     #[serde(default = "default_lifespan")]
     pub lifespan_days: u32,
+    #[serde(default)]
+    pub rate_limit_group: Option<String>,
 }
 
 fn default_lifespan() -> u32 { 30 }
@@ -66,24 +123,80 @@ pub struct AgentInfoResponse {
     pub name: String,
     pub description: String,
     pub allowed_services: Vec<String>,
+    /// The same services with the scopes granted on each (empty: all)
+    pub service_access: Vec<ServiceAccess>,
     pub rate_limit: crate::models::RateLimit,
     pub expires_at: String,
     pub lifespan_days: u32,
     pub days_until_expiry: i64,
     pub is_expired: bool,
+    /// False while an admin has the agent suspended
+    pub enabled: bool,
     pub created_at: String,
     pub updated_at: String,
+    pub usage: AgentUsage,
+}
+
+impl From<&Agent> for AgentInfoResponse {
+    fn from(agent: &Agent) -> Self {
+        Self {
+            agent_id: agent.id,
+            name: agent.name.clone(),
+            description: agent.description.clone(),
+            allowed_services: agent.service_ids(),
+            service_access: agent.allowed_services.clone(),
+            rate_limit: agent.rate_limit,
+            expires_at: agent.expires_at.to_rfc3339(),
+            lifespan_days: agent.lifespan_days,
+            days_until_expiry: agent.days_until_expiry(),
+            is_expired: agent.is_expired(),
+            enabled: agent.enabled,
+            created_at: agent.created_at.to_rfc3339(),
+            updated_at: agent.updated_at.to_rfc3339(),
+            usage: agent.usage.clone(),
+        }
+    }
+}
+
+/// Fields left out are unchanged
+#[derive(Debug, Deserialize)]
+pub struct UpdateAgentRequest {
+    pub name: Option<String>,
+    pub description: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ListUserAgentsQuery {
+    #[serde(default = "default_true")]
+    pub include_expired: bool,
+    /// Also remove ids of deleted agents from the user record
+    #[serde(default)]
+    pub prune: bool,
+}
+
+fn default_true() -> bool { true }
+
+#[derive(Debug, Serialize)]
+pub struct UserAgentsResponse {
+    pub user_id: Uuid,
+    pub agents: Vec<AgentInfoResponse>,
+    pub missing_agent_ids: Vec<Uuid>,
 }
 
 #[derive(Debug, Deserialize)]
 pub struct GrantServiceRequest {
     pub service_id: String,
+    /// Limit the grant to these scopes; unset or empty grants every scope
+    #[serde(default)]
+    pub scopes: Option<Vec<String>>,
 }
 
 #[derive(Debug, Serialize)]
 pub struct GrantServiceResponse {
     pub agent_id: Uuid,
     pub service_id: String,
+    /// Scopes held on `service_id`; empty when every scope is granted
+    pub granted_scopes: Vec<String>,
     pub allowed_services: Vec<String>,
     pub message: String,
 }
@@ -96,11 +209,71 @@ pub struct RotateKeyResponse {
     pub message: String,
 }
 
+/// No `agent_id`: the activity view is always that of the agent in the path
+#[derive(Debug, Deserialize)]
+pub struct AgentActivityQuery {
+    /// RFC3339, inclusive
+    pub from: Option<DateTime<Utc>>,
+    /// RFC3339, inclusive
+    pub to: Option<DateTime<Utc>>,
+    pub limit: Option<usize>,
+}
+
+/// One proxied request, without what only admins should see (IP, chain fields)
+#[derive(Debug, Serialize)]
+pub struct AgentActivityEntry {
+    pub service_id: String,
+    pub path: String,
+    pub method: String,
+    pub status_code: u16,
+    pub decision: Decision,
+    pub deny_reason: Option<String>,
+    pub timestamp: DateTime<Utc>,
+    pub latency_ms: u64,
+    pub request_id: Option<String>,
+    /// First 8 characters only
+    pub session_id: Option<String>,
+}
+
+impl From<AuditLog> for AgentActivityEntry {
+    fn from(entry: AuditLog) -> Self {
+        Self {
+            service_id: entry.service_id,
+            path: entry.endpoint,
+            method: entry.method,
+            status_code: entry.status_code,
+            decision: entry.decision,
+            deny_reason: entry.deny_reason,
+            timestamp: entry.timestamp,
+            latency_ms: entry.response_time_ms,
+            request_id: entry.request_id,
+            session_id: entry.session_id.as_deref().map(mask_session_id),
+        }
+    }
+}
+
+/// Enough to tell sessions apart, not enough to use one
+fn mask_session_id(session_id: &str) -> String {
+    let shown: String = session_id.chars().take(8).collect();
+    format!("{}...", shown)
+}
+
+#[derive(Debug, Serialize)]
+pub struct AgentActivityResponse {
+    pub agent_id: Uuid,
+    pub entries: Vec<AgentActivityEntry>,
+    pub total: usize,
+    pub limit: usize,
+}
+
 #[derive(Debug, Serialize)]
 pub struct ServiceInfo {
     pub id: String,
     pub name: String,
     pub description: String,
+    /// The service an alias entry names; requests under either reach the same upstream
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub alias_for: Option<String>,
 }
 
 #[derive(Debug, Serialize)]
@@ -110,12 +283,31 @@ pub struct AvailableServicesResponse {
 
 // ============ Handlers ============
 
+/// Count a pre-auth request against the caller's IP quota (IP_RATE_LIMIT_REQUESTS).
+/// In-process routers (tests) have no peer address; without a trusted header
+/// nothing is counted there.
+async fn check_client_ip(
+    state: &AppState,
+    headers: &HeaderMap,
+    peer: Option<ConnectInfo<SocketAddr>>,
+) -> Result<(), GatewayError> {
+    let peer = peer.map(|ConnectInfo(addr)| addr);
+    match client_ip(headers, peer, state.settings.trusted_client_ip_header.as_deref()) {
+        Some(ip) => state.ip_rate_limiter.check_ip(ip).await,
+        None => Ok(()),
+    }
+}
+
 /// POST /auth/register
 /// Register a new user with username and email
 async fn register_user(
     State(state): State<AppState>,
+    peer: Option<ConnectInfo<SocketAddr>>,
+    headers: HeaderMap,
     Json(req): Json<RegisterRequest>,
 ) -> Result<Json<RegisterResponse>, GatewayError> {
+    check_client_ip(&state, &headers, peer).await?;
+
     // Validate input
     if req.username.trim().is_empty() {
         return Err(GatewayError::BadRequest("Username cannot be empty".to_string()));
@@ -124,38 +316,222 @@ async fn register_user(
         return Err(GatewayError::BadRequest("Invalid email".to_string()));
     }
 
+    if state.settings.require_email_verification {
+        return register_pending_user(&state, req).await.map(Json);
+    }
+
     // Create user
     let user = User::new(req.username.clone(), req.email.clone());
     let user = state.users.create_user(user).await?;
 
     tracing::info!(user_id = %user.id, username = %user.username, "User registered");
 
-    Ok(Json(RegisterResponse {
+    Ok(Json(registered(user)))
+}
+
+/// Hold the registration until its code comes back to POST /auth/verify-email.
+/// No email is sent yet: with EMAIL_VERIFICATION_RETURN_CODE the code is in the response.
+async fn register_pending_user(state: &AppState, req: RegisterRequest) -> Result<RegisterResponse, GatewayError> {
+    if state.users.get_user_by_email(&req.email).await?.is_some() {
+        return Err(GatewayError::BadRequest("Email already registered".to_string()));
+    }
+
+    let pending = PendingUser::new(req.username, req.email, state.settings.email_verification_ttl_secs);
+    state.users.save_pending_user(pending.clone()).await?;
+
+    tracing::info!(user_id = %pending.id, username = %pending.username, "Verification code issued");
+
+    let return_code = state.settings.email_verification_return_code;
+    Ok(RegisterResponse {
+        user_id: pending.id,
+        username: pending.username,
+        email: pending.email,
+        message: "Verification required. Send the code to /auth/verify-email to finish registering.".to_string(),
+        verification_required: true,
+        verification_code: return_code.then_some(pending.code),
+        verification_expires_at: Some(pending.expires_at),
+    })
+}
+
+/// POST /auth/verify-email
+/// Turn a pending registration into a user once its code matches
+async fn verify_email(
+    State(state): State<AppState>,
+    peer: Option<ConnectInfo<SocketAddr>>,
+    headers: HeaderMap,
+    Json(req): Json<VerifyEmailRequest>,
+) -> Result<Json<RegisterResponse>, GatewayError> {
+    check_client_ip(&state, &headers, peer).await?;
+
+    let mut pending = state
+        .users
+        .get_pending_user(req.user_id)
+        .await?
+        .ok_or_else(|| GatewayError::NotFound(format!("No pending registration {}", req.user_id)))?;
+
+    if pending.is_expired() {
+        state.users.delete_pending_user(pending.id).await?;
+        return Err(GatewayError::BadRequest(
+            "Verification code expired; register again".to_string(),
+        ));
+    }
+
+    if pending.code != req.code.trim() {
+        pending.attempts += 1;
+        if pending.attempts >= MAX_VERIFICATION_ATTEMPTS {
+            state.users.delete_pending_user(pending.id).await?;
+            tracing::warn!(user_id = %pending.id, "Registration dropped after too many wrong verification codes");
+            return Err(GatewayError::BadRequest(
+                "Too many invalid verification codes; register again".to_string(),
+            ));
+        }
+        state.users.save_pending_user(pending).await?;
+        return Err(GatewayError::BadRequest("Invalid verification code".to_string()));
+    }
+
+    let user = state.users.create_user(pending.into_user()).await?;
+    state.users.delete_pending_user(user.id).await?;
+
+    tracing::info!(user_id = %user.id, username = %user.username, "User registered (email verified)");
+
+    Ok(Json(registered(user)))
+}
+
+fn registered(user: User) -> RegisterResponse {
+    RegisterResponse {
         user_id: user.id,
         username: user.username,
         email: user.email,
         message: "Registration successful. Use your user_id to create agent access.".to_string(),
-    }))
+        verification_required: false,
+        verification_code: None,
+        verification_expires_at: None,
+    }
+}
+
+/// DELETE /auth/users/{user_id}?purge_credentials=true
+/// Offboard a user: revoke sessions, delete agents (and optionally their credentials),
+/// then the user itself. Requires the admin key.
+async fn delete_user(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(user_id): Path<Uuid>,
+    Query(query): Query<DeleteUserQuery>,
+) -> Result<Json<DeleteUserResponse>, GatewayError> {
+    let admin = require_admin(&headers, &state)?;
+
+    let user = state
+        .users
+        .get_user(user_id)
+        .await?
+        .ok_or_else(|| GatewayError::NotFound("User not found".to_string()))?;
+
+    // Refuse up front rather than half-deleting
+    let credential_store = match (&state.agent_credentials, query.purge_credentials) {
+        (Some(store), true) => Some(store),
+        (None, true) => {
+            return Err(GatewayError::BadRequest(
+                "purge_credentials is not supported by this storage backend".to_string(),
+            ))
+        }
+        (_, false) => None,
+    };
+
+    let mut summary = DeleteUserResponse {
+        user_id,
+        sessions_revoked: 0,
+        credentials_removed: 0,
+        agents_removed: 0,
+        message: "User deleted".to_string(),
+    };
+
+    // Sessions first so access stops immediately; the user record goes last,
+    // so a failed run can simply be retried.
+    summary.sessions_revoked = state
+        .sessions
+        .delete_sessions_for_agents(&user.agents)
+        .await
+        .map_err(|e| deletion_incomplete("sessions", &summary, e))?;
+
+    if let Some(store) = credential_store {
+        summary.credentials_removed = store
+            .delete_credentials_for_agents(&user.agents)
+            .await
+            .map_err(|e| deletion_incomplete("credentials", &summary, e))?;
+    }
+
+    summary.agents_removed = state
+        .agents
+        .delete_agents(&user.agents)
+        .await
+        .map_err(|e| deletion_incomplete("agents", &summary, e))?;
+
+    state
+        .users
+        .delete_user(user_id)
+        .await
+        .map_err(|e| deletion_incomplete("user", &summary, e))?;
+
+    tracing::info!(
+        user_id = %user_id,
+        agents = summary.agents_removed,
+        sessions = summary.sessions_revoked,
+        credentials = summary.credentials_removed,
+        "User deleted"
+    );
+    state
+        .admin_audit_logger
+        .record(
+            AdminAction::UserDeleted,
+            &admin,
+            Some(user_id.to_string()),
+            serde_json::json!({
+                "sessions_revoked": summary.sessions_revoked,
+                "credentials_removed": summary.credentials_removed,
+                "agents_removed": summary.agents_removed,
+            }),
+        )
+        .await;
+
+    Ok(Json(summary))
+}
+
+// === Report which steps of a user deletion completed before one failed ===
+fn deletion_incomplete(step: &str, done: &DeleteUserResponse, error: GatewayError) -> GatewayError {
+    let (_, _, message) = error.parts();
+    tracing::error!(user_id = %done.user_id, step, error = %message, "User deletion incomplete");
+
+    GatewayError::Internal(format!(
+        "User deletion failed while removing {}: {}. Already removed: {} sessions, {} credentials, {} agents. Retry to finish.",
+        step, message, done.sessions_revoked, done.credentials_removed, done.agents_removed
+    ))
 }
 
 /// POST /auth/agent
 /// Create an agent with access to specified services, returns session_id
 async fn create_agent_access(
     State(state): State<AppState>,
+    peer: Option<ConnectInfo<SocketAddr>>,
+    headers: HeaderMap,
     Json(req): Json<CreateAgentRequest>,
 ) -> Result<Json<CreateAgentResponse>, GatewayError> {
+    check_client_ip(&state, &headers, peer).await?;
+
     // Verify user exists
     let mut user = state
         .users
         .get_user(req.user_id)
-        .await
+        .await?
         .ok_or_else(|| GatewayError::NotFound("User not found".to_string()))?;
 
-    // Validate requested services exist
+    // Validate requested services exist; aliases are granted as the service they name
+    let services = state.services();
     let mut valid_services = Vec::new();
     for service_id in &req.services {
-        if state.services.exists(service_id) {
-            valid_services.push(service_id.clone());
+        if let Some(id) = services.registry.resolve(service_id) {
+            if !valid_services.iter().any(|s| s == id) {
+                valid_services.push(id.to_string());
+            }
         } else {
             return Err(GatewayError::BadRequest(format!(
                 "Service '{}' does not exist",
@@ -170,25 +546,55 @@ async fn create_agent_access(
         ));
     }
 
+    // Validate rate limit group is configured
+    if let Some(group) = &req.rate_limit_group {
+        if !state.settings.rate_limit_groups.contains_key(group) {
+            return Err(GatewayError::BadRequest(format!(
+                "Rate limit group '{}' does not exist",
+                group
+            )));
+        }
+    }
+
+    let max_lifespan = state.settings.max_agent_lifespan_days;
+    if req.lifespan_days > max_lifespan {
+        return Err(GatewayError::BadRequest(format!(
+            "lifespan_days must not exceed {} days",
+            max_lifespan
+        )));
+    }
+
     // Create agent with lifespan
     let mut agent = Agent::with_lifespan(
         req.agent_name.clone(),
         req.agent_description,
         req.lifespan_days,
     );
-    agent.allowed_services = valid_services.clone();
+    agent.owner_id = Some(user.id);
+    agent.allowed_services = valid_services.iter().map(ServiceAccess::all_scopes).collect();
+    agent.rate_limit_group = req.rate_limit_group;
 
-    let agent = state.agents.create_agent(agent.clone()).await?;
+    let agent = if state.settings.allow_duplicate_names {
+        state.agents.create_agent(agent).await?
+    } else {
+        // A retried request gets the agent it already created (and a fresh session);
+        // the same name with different settings is a conflict
+        let requested = agent.clone();
+        let stored = state.agents.create_or_get_agent(agent).await?;
+        if stored.id != requested.id && !same_agent_request(&stored, &requested) {
+            return Err(GatewayError::BadRequest(
+                "Agent with this name already exists for user".to_string(),
+            ));
+        }
+        stored
+    };
 
-    // Link agent to user
+    // Link agent to user (no-op when the agent already existed)
     user.add_agent(agent.id);
     state.users.update_user(user).await?;
 
     // Create session
-    let session = state
-        .agents
-        .create_session(agent.id, state.settings.session_ttl_secs)
-        .await?;
+    let session = state.start_session(agent.id).await?;
 
     tracing::info!(
         agent_id = %agent.id,
@@ -209,63 +615,262 @@ async fn create_agent_access(
     }))
 }
 
+/// Apply `change` and save it against the version that was read. If another
+/// request updated the agent in between, re-read it and apply `change` once more;
+/// a second conflict is returned to the caller as 409.
+pub(super) async fn update_agent_with_retry<F>(
+    state: &AppState,
+    agent: Agent,
+    change: F,
+) -> Result<Agent, GatewayError>
+where
+    F: Fn(&mut Agent) -> Result<(), GatewayError>,
+{
+    let (id, expected) = (agent.id, agent.version);
+    let mut updated = agent;
+    change(&mut updated)?;
+
+    match state.agents.update_agent(updated, expected).await {
+        Err(GatewayError::Conflict(_)) => {
+            tracing::debug!(agent_id = %id, "Agent changed concurrently; retrying update");
+            let mut fresh = state
+                .agents
+                .get_agent(id)
+                .await?
+                .ok_or_else(|| GatewayError::NotFound("Agent not found".to_string()))?;
+            let expected = fresh.version;
+            change(&mut fresh)?;
+            state.agents.update_agent(fresh, expected).await
+        }
+        result => result,
+    }
+}
+
+/// Whether an existing agent is what a repeated create request would have produced
+fn same_agent_request(existing: &Agent, requested: &Agent) -> bool {
+    let services = |agent: &Agent| {
+        let mut services = agent.service_ids();
+        services.sort();
+        services.dedup();
+        services
+    };
+    !existing.is_expired()
+        && services(existing) == services(requested)
+        && existing.rate_limit_group == requested.rate_limit_group
+}
+
 /// GET /auth/agent/{agent_id}
-/// Get agent information including expiration status
+/// Get agent information including expiration status (admin key or own session)
 async fn get_agent_info(
+    VerifiedAgent { agent, .. }: VerifiedAgent,
+) -> Result<Json<AgentInfoResponse>, GatewayError> {
+    Ok(Json(AgentInfoResponse::from(&agent)))
+}
+
+/// PATCH /auth/agent/{agent_id}
+/// Rename an agent or change its description, keeping its key and sessions
+async fn update_agent_metadata(
     State(state): State<AppState>,
-    Path(agent_id): Path<Uuid>,
+    VerifiedAgent { agent, .. }: VerifiedAgent,
+    Json(req): Json<UpdateAgentRequest>,
 ) -> Result<Json<AgentInfoResponse>, GatewayError> {
-    let agent = state
+    if req.name.is_none() && req.description.is_none() {
+        return Err(GatewayError::BadRequest(
+            "Provide a name and/or description to update".to_string(),
+        ));
+    }
+    let name = req.name.map(|name| name.trim().to_string());
+    if name.as_deref() == Some("") {
+        return Err(GatewayError::BadRequest("Agent name cannot be empty".to_string()));
+    }
+
+    if let Some(name) = &name {
+        check_name_available(&state, &agent, name).await?;
+    }
+
+    let agent = update_agent_with_retry(&state, agent, |agent| {
+        agent.update_metadata(name.clone(), req.description.clone());
+        Ok(())
+    })
+    .await?;
+
+    tracing::info!(agent_id = %agent.id, "Agent metadata updated");
+
+    Ok(Json(AgentInfoResponse::from(&agent)))
+}
+
+/// Names are unique per owner, as at creation: `agent` may not take one its
+/// owner already uses for another agent
+pub(super) async fn check_name_available(state: &AppState, agent: &Agent, name: &str) -> Result<(), GatewayError> {
+    let Some(owner_id) = agent.owner_id.filter(|_| !state.settings.allow_duplicate_names) else {
+        return Ok(());
+    };
+    let taken = state.agents.find_agent_by_name(owner_id, name).await?;
+    if taken.is_some_and(|other| other.id != agent.id) {
+        return Err(GatewayError::BadRequest(
+            "Agent with this name already exists for user".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+/// GET /auth/users/{user_id}/agents?include_expired=false&prune=true
+/// Resolve a user's agent ids to agent info (admin key). Ids whose agent no
+/// longer exists are reported in `missing_agent_ids`, and dropped from the user with `prune`.
+async fn list_user_agents(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(user_id): Path<Uuid>,
+    Query(query): Query<ListUserAgentsQuery>,
+) -> Result<Json<UserAgentsResponse>, GatewayError> {
+    let admin = require_admin(&headers, &state)?;
+
+    let mut user = state
+        .users
+        .get_user(user_id)
+        .await?
+        .ok_or_else(|| GatewayError::NotFound("User not found".to_string()))?;
+
+    let agents = state.agents.get_agents(&user.agents).await?;
+    let missing_agent_ids: Vec<Uuid> = user
         .agents
-        .get_agent(agent_id)
-        .await
-        .ok_or_else(|| GatewayError::NotFound("Agent not found".to_string()))?;
+        .iter()
+        .filter(|id| !agents.iter().any(|a| a.id == **id))
+        .copied()
+        .collect();
 
-    let days_until_expiry = agent.days_until_expiry();
-    let is_expired = agent.is_expired();
+    if query.prune && !missing_agent_ids.is_empty() {
+        user.agents.retain(|id| !missing_agent_ids.contains(id));
+        user.updated_at = chrono::Utc::now();
+        state.users.update_user(user).await?;
+        tracing::info!(user_id = %user_id, pruned = missing_agent_ids.len(), "Pruned dead agent ids");
+        state
+            .admin_audit_logger
+            .record(
+                AdminAction::UserAgentsPruned,
+                &admin,
+                Some(user_id.to_string()),
+                serde_json::json!({ "pruned_agent_ids": missing_agent_ids }),
+            )
+            .await;
+    }
 
-    Ok(Json(AgentInfoResponse {
-        agent_id: agent.id,
-        name: agent.name.clone(),
-        description: agent.description.clone(),
-        allowed_services: agent.allowed_services.clone(),
-        rate_limit: agent.rate_limit,
-        expires_at: agent.expires_at.to_rfc3339(),
-        lifespan_days: agent.lifespan_days,
-        days_until_expiry,
-        is_expired,
-        created_at: agent.created_at.to_rfc3339(),
-        updated_at: agent.updated_at.to_rfc3339(),
+    Ok(Json(UserAgentsResponse {
+        user_id,
+        agents: agents
+            .iter()
+            .filter(|a| query.include_expired || !a.is_expired())
+            .map(AgentInfoResponse::from)
+            .collect(),
+        missing_agent_ids,
     }))
 }
 
+/// DELETE /auth/agent/{agent_id}
+/// Delete an agent with its own session or the admin key
+async fn delete_agent(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    VerifiedAgent { agent, access }: VerifiedAgent,
+) -> Result<Json<DeleteAgentResponse>, GatewayError> {
+    let admin = admin_caller(&headers, &state, access)?;
+    let deleted = delete_agent_and_sessions(&state, agent, access).await?;
+    if let Some(admin) = admin {
+        state
+            .admin_audit_logger
+            .record(
+                AdminAction::AgentDeleted,
+                &admin,
+                Some(deleted.agent_id.to_string()),
+                serde_json::json!({ "sessions_revoked": deleted.sessions_revoked }),
+            )
+            .await;
+    }
+    Ok(Json(deleted))
+}
+
+/// The admin key's hash when the admin key granted `access`, for the admin event log
+fn admin_caller(headers: &HeaderMap, state: &AppState, access: AgentAccess) -> Result<Option<String>, GatewayError> {
+    match access {
+        AgentAccess::Admin => require_admin(headers, state).map(Some),
+        AgentAccess::OwnSession => Ok(None),
+    }
+}
+
+/// Revoke the agent's sessions, delete it and drop it from its owner's list.
+/// Sessions go first so the key stops working even if a later step fails.
+pub(super) async fn delete_agent_and_sessions(
+    state: &AppState,
+    agent: Agent,
+    access: AgentAccess,
+) -> Result<DeleteAgentResponse, GatewayError> {
+    let sessions_revoked = state.sessions.delete_sessions_for_agents(&[agent.id]).await?;
+    if !state.agents.delete_agent(agent.id).await? {
+        return Err(GatewayError::NotFound("Agent not found".to_string()));
+    }
+
+    // A failure here leaves a dangling id, which GET /auth/users/{id}/agents?prune=true clears
+    if let Some(owner_id) = agent.owner_id {
+        if let Some(mut user) = state.users.get_user(owner_id).await? {
+            if user.remove_agent(agent.id) {
+                state.users.update_user(user).await?;
+            }
+        }
+    }
+
+    log_agent_deletion(&AgentDeletionAudit {
+        agent_id: agent.id,
+        owner_id: agent.owner_id,
+        by_admin: access == AgentAccess::Admin,
+        sessions_revoked,
+        timestamp: Utc::now(),
+    });
+
+    Ok(DeleteAgentResponse {
+        agent_id: agent.id,
+        sessions_revoked,
+        message: "Agent deleted".to_string(),
+    })
+}
+
 /// POST /auth/agent/{agent_id}/rotate
 /// Rotate/regenerate the access key (extends expiration)
 async fn rotate_agent_key(
     State(state): State<AppState>,
-    Path(agent_id): Path<Uuid>,
+    headers: HeaderMap,
+    VerifiedAgent { agent, access }: VerifiedAgent,
 ) -> Result<Json<RotateKeyResponse>, GatewayError> {
-    let mut agent = state
-        .agents
-        .get_agent(agent_id)
-        .await
-        .ok_or_else(|| GatewayError::NotFound("Agent not found".to_string()))?;
+    let agent_id = agent.id;
+    let admin = admin_caller(&headers, &state, access)?;
+
+    // Claim the current version first, so a concurrent grant/revoke either lands
+    // before the copy is taken or fails and retries - never silently dropped
+    let mut agent = update_agent_with_retry(&state, agent, |_| Ok(())).await?;
 
-    // Rotate the key
+    // Rotate the key; a lifespan from before MAX_AGENT_LIFESPAN_DAYS was lowered is capped
     let new_id = agent.rotate();
-    state.agents.update_agent(agent.clone()).await?;
+    agent.cap_lifespan(state.settings.max_agent_lifespan_days);
+    state.agents.create_agent(agent.clone()).await?;
 
     // Create new session for the rotated key
-    let session = state
-        .agents
-        .create_session(new_id, state.settings.session_ttl_secs)
-        .await?;
+    let session = state.start_session(new_id).await?;
 
     tracing::info!(
         old_agent_id = %agent_id,
         new_agent_id = %new_id,
         "Agent key rotated"
     );
+    if let Some(admin) = admin {
+        state
+            .admin_audit_logger
+            .record(
+                AdminAction::AgentKeyRotated,
+                &admin,
+                Some(agent_id.to_string()),
+                serde_json::json!({ "new_agent_id": new_id }),
+            )
+            .await;
+    }
 
     Ok(Json(RotateKeyResponse {
         agent_id: new_id,
@@ -275,49 +880,92 @@ async fn rotate_agent_key(
     }))
 }
 
+/// GET /auth/agent/{agent_id}/activity?from=&to=&limit=
+/// The agent's own proxy audit entries, newest first (its session or the admin key)
+async fn agent_activity(
+    State(state): State<AppState>,
+    VerifiedAgent { agent, .. }: VerifiedAgent,
+    Query(query): Query<AgentActivityQuery>,
+) -> Result<Json<AgentActivityResponse>, GatewayError> {
+    let limit = query.limit.unwrap_or(DEFAULT_ACTIVITY_LIMIT);
+    if limit == 0 || limit > MAX_ACTIVITY_LIMIT {
+        return Err(GatewayError::BadRequest(format!(
+            "limit must be between 1 and {}",
+            MAX_ACTIVITY_LIMIT
+        )));
+    }
+    check_time_range(query.from, query.to)?;
+
+    let audit = audit_store(&state).await?;
+    let filter = AuditFilter {
+        agent_id: Some(agent.id),
+        from: query.from,
+        to: query.to,
+        ..Default::default()
+    };
+    let page = audit.query(&filter, 0, limit).await?;
+
+    Ok(Json(AgentActivityResponse {
+        agent_id: agent.id,
+        entries: page.items.into_iter().map(AgentActivityEntry::from).collect(),
+        total: page.total,
+        limit,
+    }))
+}
+
 /// POST /auth/agent/{agent_id}/services
 /// Grant service access to an agent
 async fn grant_service_access(
     State(state): State<AppState>,
-    Path(agent_id): Path<Uuid>,
+    VerifiedAgent { agent, .. }: VerifiedAgent,
     Json(req): Json<GrantServiceRequest>,
 ) -> Result<Json<GrantServiceResponse>, GatewayError> {
-    // Verify service exists
-    if !state.services.exists(&req.service_id) {
-        return Err(GatewayError::BadRequest(format!(
-            "Service '{}' does not exist",
-            req.service_id
-        )));
-    }
+    let agent_id = agent.id;
 
-    let mut agent = state
-        .agents
-        .get_agent(agent_id)
-        .await
-        .ok_or_else(|| GatewayError::NotFound("Agent not found".to_string()))?;
+    // Verify service exists; an alias is granted as the service it names
+    let service_id = state
+        .services()
+        .registry
+        .resolve(&req.service_id)
+        .map(str::to_string)
+        .ok_or_else(|| GatewayError::BadRequest(format!("Service '{}' does not exist", req.service_id)))?;
 
-    // Check if already has access
-    if agent.can_access_service(&req.service_id) {
-        return Err(GatewayError::BadRequest(format!(
-            "Agent already has access to service '{}'",
-            req.service_id
-        )));
+    let mut scopes: Vec<String> = Vec::new();
+    for scope in req.scopes.iter().flatten() {
+        let scope = scope.trim();
+        if scope.is_empty() {
+            return Err(GatewayError::BadRequest("scopes must not be empty strings".to_string()));
+        }
+        if !scopes.iter().any(|s| s == scope) {
+            scopes.push(scope.to_string());
+        }
     }
 
-    // Grant access
-    agent.add_service(req.service_id.clone());
-    state.agents.update_agent(agent.clone()).await?;
+    // Grant access (checked again if a concurrent update forces a re-read)
+    let agent = update_agent_with_retry(&state, agent, |agent| {
+        if agent.can_access_service(&service_id) {
+            return Err(GatewayError::BadRequest(format!(
+                "Agent already has access to service '{}'",
+                service_id
+            )));
+        }
+        agent.grant_service(service_id.clone(), scopes.clone());
+        Ok(())
+    })
+    .await?;
 
     tracing::info!(
         agent_id = %agent_id,
-        service_id = %req.service_id,
+        service_id = %service_id,
+        scopes = ?scopes,
         "Service access granted"
     );
 
     Ok(Json(GrantServiceResponse {
         agent_id,
-        service_id: req.service_id,
-        allowed_services: agent.allowed_services,
+        granted_scopes: agent.get_service_scopes(&service_id).to_vec(),
+        service_id,
+        allowed_services: agent.service_ids(),
         message: "Service access granted successfully".to_string(),
     }))
 }
@@ -326,23 +974,30 @@ async fn grant_service_access(
 /// Revoke service access from an agent
 async fn revoke_service_access(
     State(state): State<AppState>,
+    VerifiedAgent { agent, .. }: VerifiedAgent,
     Path((agent_id, service_id)): Path<(Uuid, String)>,
 ) -> Result<Json<GrantServiceResponse>, GatewayError> {
-    let mut agent = state
-        .agents
-        .get_agent(agent_id)
-        .await
-        .ok_or_else(|| GatewayError::NotFound("Agent not found".to_string()))?;
+    // An alias revokes the service it names; a grant under a name that is no longer
+    // a service (e.g. a removed alias) can still be revoked by that name
+    let service_id = state
+        .services()
+        .registry
+        .resolve(&service_id)
+        .filter(|id| agent.can_access_service(id))
+        .map(str::to_string)
+        .unwrap_or(service_id);
 
     // Remove access
-    if !agent.remove_service(&service_id) {
-        return Err(GatewayError::BadRequest(format!(
-            "Agent does not have access to service '{}'",
-            service_id
-        )));
-    }
-
-    state.agents.update_agent(agent.clone()).await?;
+    let agent = update_agent_with_retry(&state, agent, |agent| {
+        if !agent.remove_service(&service_id) {
+            return Err(GatewayError::BadRequest(format!(
+                "Agent does not have access to service '{}'",
+                service_id
+            )));
+        }
+        Ok(())
+    })
+    .await?;
 
     tracing::info!(
         agent_id = %agent_id,
@@ -353,7 +1008,8 @@ async fn revoke_service_access(
     Ok(Json(GrantServiceResponse {
         agent_id,
         service_id,
-        allowed_services: agent.allowed_services,
+        granted_scopes: Vec::new(),
+        allowed_services: agent.service_ids(),
         message: "Service access revoked successfully".to_string(),
     }))
 }
@@ -363,15 +1019,25 @@ async fn revoke_service_access(
 async fn list_available_services(
     State(state): State<AppState>,
 ) -> Result<Json<AvailableServicesResponse>, GatewayError> {
-    let services: Vec<ServiceInfo> = state
-        .services
+    let services = state.services();
+    let mut aliases = services.registry.aliases();
+    aliases.sort_by_key(|(alias, _)| *alias);
+    let services: Vec<ServiceInfo> = services
+        .registry
         .list()
         .iter()
         .map(|s| ServiceInfo {
             id: s.id.clone(),
             name: s.name.clone(),
             description: s.description.clone(),
+            alias_for: None,
         })
+        .chain(aliases.into_iter().map(|(alias, s)| ServiceInfo {
+            id: alias.to_string(),
+            name: format!("{} (alias for: {})", s.name, s.id),
+            description: s.description.clone(),
+            alias_for: Some(s.id.clone()),
+        }))
         .collect();
 
     Ok(Json(AvailableServicesResponse { services }))
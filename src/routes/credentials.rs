@@ -7,8 +7,8 @@ use crate::state::AppState;
 
 pub fn credential_routes() -> Router<AppState> {
     Router::new()
-        .route("/{service}", post(store_credential))
-        .route("/{service}", delete(remove_credential))
+        .route("/:service", post(store_credential))
+        .route("/:service", delete(remove_credential))
         .route("/", get(list_credentials))
 }
 
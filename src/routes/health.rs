@@ -0,0 +1,166 @@
+use axum::{extract::State, http::StatusCode, routing::get, Json, Router};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::time::Duration;
+
+use crate::config::{ServiceStatus, SessionStoreKind};
+use crate::error::GatewayError;
+use crate::state::AppState;
+
+/// Deadline for each dependency check; a hung backend counts as down
+const HEALTH_CHECK_TIMEOUT: Duration = Duration::from_secs(5);
+
+pub fn health_routes() -> Router<AppState> {
+    Router::new()
+        .route("/health", get(health_check))
+        .route("/health/detailed", get(detailed_health))
+}
+
+/// GET /health
+/// Liveness: the process is up and serving
+async fn health_check() -> &'static str {
+    "OK"
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ComponentStatus {
+    Ok,
+    /// Still serving, but not fully (an upstream service can't be reached)
+    Degraded,
+    Down,
+    NotConfigured,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OverallStatus {
+    Healthy,
+    Degraded,
+    Unhealthy,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Components {
+    pub credential_store: ComponentStatus,
+    pub user_store: ComponentStatus,
+    pub agent_store: ComponentStatus,
+    pub redis: ComponentStatus,
+    pub services: BTreeMap<String, ComponentStatus>,
+}
+
+impl Components {
+    /// Unhealthy when a store the gateway can't work without is down;
+    /// degraded when anything else (Redis, an upstream) is
+    fn overall(&self) -> OverallStatus {
+        let critical = [self.credential_store, self.user_store, self.agent_store];
+        if critical.iter().any(|status| *status != ComponentStatus::Ok) {
+            return OverallStatus::Unhealthy;
+        }
+        let degraded = std::iter::once(&self.redis)
+            .chain(self.services.values())
+            .any(|status| matches!(status, ComponentStatus::Degraded | ComponentStatus::Down));
+        if degraded {
+            OverallStatus::Degraded
+        } else {
+            OverallStatus::Healthy
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct HealthReport {
+    pub status: OverallStatus,
+    pub components: Components,
+    pub uptime_secs: u64,
+}
+
+/// GET /health/detailed
+/// Every dependency checked concurrently: store files (or databases), Redis
+/// sessions and a TCP connect to each upstream. `200` when healthy, `503`
+/// when degraded or unhealthy, so a readiness probe stops routing new traffic.
+async fn detailed_health(State(state): State<AppState>) -> (StatusCode, Json<HealthReport>) {
+    let redis_configured = state.settings.session_store == Some(SessionStoreKind::Redis);
+    let services = state.services();
+    let (credential_store, user_store, agent_store, redis, services) = tokio::join!(
+        check("credential_store", state.credentials.health_check()),
+        check("user_store", state.users.health_check()),
+        check("agent_store", state.agents.health_check()),
+        async {
+            if redis_configured {
+                check("redis", state.sessions.health_check()).await
+            } else {
+                ComponentStatus::NotConfigured
+            }
+        },
+        services.registry.probe(),
+    );
+
+    let components = Components {
+        credential_store,
+        user_store,
+        agent_store,
+        redis,
+        services: services
+            .into_iter()
+            .map(|(id, status)| {
+                let status = match status {
+                    ServiceStatus::Reachable => ComponentStatus::Ok,
+                    ServiceStatus::Unreachable => ComponentStatus::Degraded,
+                };
+                (id, status)
+            })
+            .collect(),
+    };
+    let status = components.overall();
+    let code = match status {
+        OverallStatus::Healthy => StatusCode::OK,
+        OverallStatus::Degraded | OverallStatus::Unhealthy => StatusCode::SERVICE_UNAVAILABLE,
+    };
+    let report = HealthReport {
+        status,
+        components,
+        uptime_secs: state.started_at.elapsed().as_secs(),
+    };
+    (code, Json(report))
+}
+
+async fn check(component: &str, health: impl Future<Output = Result<(), GatewayError>>) -> ComponentStatus {
+    match tokio::time::timeout(HEALTH_CHECK_TIMEOUT, health).await {
+        Ok(Ok(())) => ComponentStatus::Ok,
+        Ok(Err(e)) => {
+            tracing::warn!(component, error = ?e, "Health check failed");
+            ComponentStatus::Down
+        }
+        Err(_) => {
+            tracing::warn!(component, "Health check timed out");
+            ComponentStatus::Down
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn components(store: ComponentStatus, redis: ComponentStatus, service: ComponentStatus) -> Components {
+        Components {
+            credential_store: ComponentStatus::Ok,
+            user_store: ComponentStatus::Ok,
+            agent_store: store,
+            redis,
+            services: BTreeMap::from([("payment".to_string(), ComponentStatus::Ok), ("bank".to_string(), service)]),
+        }
+    }
+
+    #[test]
+    fn test_overall_status() {
+        use ComponentStatus::{Degraded, Down, NotConfigured};
+        let ok = ComponentStatus::Ok;
+        assert_eq!(components(ok, NotConfigured, ok).overall(), OverallStatus::Healthy);
+        assert_eq!(components(ok, ok, Degraded).overall(), OverallStatus::Degraded);
+        assert_eq!(components(ok, Down, ok).overall(), OverallStatus::Degraded);
+        assert_eq!(components(Down, ok, ok).overall(), OverallStatus::Unhealthy);
+    }
+}
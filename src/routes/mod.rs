@@ -2,8 +2,10 @@ mod auth;
 mod credentials;
 mod proxy;
 mod admin;
+mod health;
 
 pub use auth::*;
 pub use credentials::*;
 pub use proxy::*;
 pub use admin::*;
+pub use health::*;
@@ -2,38 +2,165 @@
 
 use axum::{
     body::Bytes,
-    extract::{Path, State},
-    http::{HeaderMap, Method},
+    extract::{ConnectInfo, Path, State},
+    http::{header, HeaderMap, HeaderValue, Method, StatusCode, Uri},
+    response::{IntoResponse, Response},
     Json, Router,
     routing::any,
 };
 use serde_json::Value;
+use std::net::SocketAddr;
+use std::time::{Duration, Instant};
 
-use crate::error::GatewayError;
-use crate::gateway::{needs_refresh, refresh_token, ProxyClient};
-use crate::state::AppState;
+use crate::audit::{capture_exchange, current_request_id, should_log};
+use crate::config::StoredCredential;
+use crate::error::{error_format, GatewayError};
+use crate::gateway::{
+    assertion_credential, decode_request_body, exchange_assertion, needs_refresh_with_skew,
+    client_ip, normalize_path, refresh_instrumented, validate_percent_encoding, Claim, EnvelopeMeta, IdempotencyCache,
+    statsd_segment, LatencySample, ProxyResult, RefreshTurn, ResponseCache, StoredResponse,
+};
+use crate::models::{AuditLog, Decision, RefreshTrigger};
+use crate::state::{check_agent_enabled, AppState, ServiceSet};
 
 const SESSION_HEADER: &str = "x-session-id";
+const CACHE_HEADER: &str = "x-cache";
+const IDEMPOTENCY_HEADER: &str = "x-idempotency-key";
+const REPLAYED_HEADER: &str = "x-idempotent-replayed";
+const PARTIAL_HEADER: &str = "x-partial-response";
 
 pub fn proxy_routes() -> Router<AppState> {
     Router::new().route("/:service/*path", any(proxy_request))
 }
 
-// === Main proxy handler ===
+// === Main proxy handler: every attempt, allowed or not, goes to the audit trail ===
+// A request whose client disconnects mid-proxy is dropped before it is recorded;
+// ProxyMetrics counts those.
 async fn proxy_request(
     State(state): State<AppState>,
+    peer: Option<ConnectInfo<SocketAddr>>,
     method: Method,
+    uri: Uri,
     headers: HeaderMap,
     Path((service, path)): Path<(String, String)>,
     body: Option<Bytes>,
-) -> Result<Json<Value>, GatewayError> {
+) -> Response {
+    let started = Instant::now();
+    let mut audit = AuditLog::new(service.clone(), path.clone(), method.to_string());
+    audit.request_id = current_request_id();
+    audit.ip_address = client_ip(
+        &headers,
+        peer.map(|ConnectInfo(addr)| addr),
+        state.settings.trusted_client_ip_header.as_deref(),
+    );
+    audit.session_id = headers
+        .get(SESSION_HEADER)
+        .and_then(|v| v.to_str().ok())
+        .map(str::to_string);
+
+    // One version of the services for the whole request, even if they are reloaded meanwhile
+    let services = state.services();
+    let result =
+        forward_request(&state, &services, &mut audit, started, method, uri, headers, service, path, body).await;
+    // Services with `wrap_response` answer in their envelope, errors included
+    let envelope = services.response_envelopes.get(&audit.service_id);
+    let meta = |cache_hit: bool| EnvelopeMeta {
+        service_id: audit.service_id.clone(),
+        request_id: audit.request_id.clone(),
+        latency_ms: started.elapsed().as_millis() as u64,
+        cache_hit,
+    };
+    // A replayed failure is answered as the error it was, flagged as a replay
+    let (result, replayed_failure) = match result {
+        Ok(ProxyResponse { failure: Some(e), .. }) => (Err(e), true),
+        result => (result, false),
+    };
+    let mut response = match result {
+        Ok(mut response) => {
+            if let Some(envelope) = envelope {
+                let meta = meta(response.cache == Some("HIT"));
+                response.body = envelope.wrap(response.status, response.body, &meta);
+            }
+            response.into_response()
+        }
+        Err(e) => {
+            let (status, error_type, message) = e.parts();
+            let response = match envelope {
+                Some(envelope) => {
+                    let body = envelope.wrap_error(status.as_u16(), error_type, &message, &meta(false));
+                    (status, Json(body)).into_response()
+                }
+                None => error_format().render(status, error_type, &message),
+            };
+            audit.decision = Decision::Denied;
+            audit.deny_reason = Some(error_type.to_string());
+            audit.deny_message = Some(message);
+            response
+        }
+    };
+    if replayed_failure {
+        response.headers_mut().insert(REPLAYED_HEADER, HeaderValue::from_static("true"));
+    }
+
+    audit.status_code = response.status().as_u16();
+    audit.response_time_ms = started.elapsed().as_millis() as u64;
+    audit.gateway_time_ms = audit.response_time_ms.saturating_sub(audit.upstream_time_ms.unwrap_or(0));
+    if let Some(upstream_ms) = audit.upstream_time_ms {
+        let sample = LatencySample {
+            total_ms: audit.response_time_ms,
+            upstream_ms,
+            gateway_ms: audit.gateway_time_ms,
+        };
+        state.latency_metrics.record(&audit.service_id, sample).await;
+    }
+    if let Some(statsd) = &state.statsd {
+        // Names the client made up would each start a new metric series
+        let service = match services.registry.get(&audit.service_id) {
+            Some(_) => statsd_segment(&audit.service_id),
+            None => "unknown".to_string(),
+        };
+        statsd.incr(&format!("gateway.requests.{}.{}xx", service, audit.status_code / 100));
+        statsd.timing(&format!("gateway.latency.{}", service), audit.response_time_ms);
+        statsd.gauge("gateway.in_flight", state.proxy_metrics.snapshot().in_flight);
+    }
+    // Written by a background task; the response doesn't wait for the disk
+    if let Some(writer) = &state.audit_writer {
+        writer.record(audit).await;
+    }
+    response
+}
+
+#[allow(clippy::too_many_arguments)]
+async fn forward_request(
+    state: &AppState,
+    services: &ServiceSet,
+    audit: &mut AuditLog,
+    started: Instant,
+    method: Method,
+    uri: Uri,
+    mut headers: HeaderMap,
+    service: String,
+    path: String,
+    body: Option<Bytes>,
+) -> Result<ProxyResponse, GatewayError> {
+    // === An alias is the service it names, for everything from here on ===
+    let service = match services.registry.resolve(&service) {
+        Some(id) if id != service => id.to_string(),
+        _ => service,
+    };
+    audit.service_id = service.clone();
+
     // === Extract and validate session ===
     let session_id = headers
         .get(SESSION_HEADER)
         .and_then(|v| v.to_str().ok())
         .ok_or_else(|| GatewayError::Unauthorized("Missing X-Session-ID header".to_string()))?;
 
-    let (session, agent) = state.agents.validate_session(session_id).await?;
+    let (session, agent) = state.resolve_session(session_id).await?;
+    audit.agent_id = Some(agent.id);
+
+    // === Suspended agents are refused, live sessions included ===
+    check_agent_enabled(&agent)?;
 
     // === Check if access key has expired ===
     if agent.is_expired() {
@@ -42,60 +169,403 @@ async fn proxy_request(
         ));
     }
 
-    // === Check agent has access to service ===
-    if !agent.can_access_service(&service) {
+    // === Check the client IP against the agent's allowlist ===
+    if !agent.allows_ip(audit.ip_address) {
+        return Err(GatewayError::Forbidden(
+            "Client IP is not in the agent's allowlist".to_string(),
+        ));
+    }
+
+    // === Check agent has access to service, granted under its id or an alias ===
+    let granted = match services.registry.get(&service) {
+        Some(config) => config.names().any(|name| agent.can_access_service(name)),
+        None => agent.can_access_service(&service),
+    };
+    if !granted {
         return Err(GatewayError::ServiceNotAllowed(service.clone()));
     }
 
-    // === Rate limiting ===
-    state.rate_limiter.check_agent(&agent.id.to_string()).await?;
+    // === Rate limiting: user (all their agents), then agent (its own limit)/group, then service ===
+    if let Some(owner_id) = agent.owner_id {
+        state.rate_limiter.check_user(&owner_id.to_string()).await?;
+    }
+    state
+        .rate_limiter
+        .check_agent_with_group(
+            &agent.id.to_string(),
+            &(&agent.rate_limit).into(),
+            agent.rate_limit_group.as_deref(),
+        )
+        .await?;
     state.rate_limiter.check_service(&service).await?;
 
     // === Get service config ===
-    let service_config = state
-        .services
+    let service_config = services
+        .registry
         .get(&service)
         .ok_or_else(|| GatewayError::NotFound(format!("Service '{}' not found", service)))?;
 
+    // === Usage statistics (best effort: never fails the request) ===
+    if let Err(e) = state.agents.record_usage(agent.id, &service).await {
+        tracing::warn!(agent_id = %agent.id, error = ?e, "Failed to record agent usage");
+    }
+
+    // === Normalize the upstream path ===
+    let path = if service_config.skip_path_normalization {
+        path
+    } else {
+        // `path` is already percent-decoded; malformed escapes only show in the raw URI
+        validate_percent_encoding(uri.path())?;
+        normalize_path(&path)?
+    };
+
+    // === Deduplicate retries carrying X-Idempotency-Key (any method) ===
+    let idempotency_ttl = state.settings.idempotency_cache_ttl_secs;
+    let mut idempotency = None;
+    if let Some(key) = headers.get(IDEMPOTENCY_HEADER).filter(|_| idempotency_ttl > 0) {
+        let key = key
+            .to_str()
+            .map_err(|_| GatewayError::BadRequest("X-Idempotency-Key must be visible ASCII".to_string()))?;
+        IdempotencyCache::validate_key(key)?;
+        match state.idempotency.claim(agent.id, key).await {
+            Claim::Replay(replayed) => {
+                tracing::debug!(agent_id = %agent.id, service = %service, "Idempotent replay");
+                let response = match replayed {
+                    StoredResponse::Upstream { status, body, partial } => {
+                        ProxyResponse::new(status, body).with_partial(partial)
+                    }
+                    StoredResponse::Failed { timed_out: true, message } => {
+                        ProxyResponse::failed(GatewayError::UpstreamTimeout(message))
+                    }
+                    StoredResponse::Failed { timed_out: false, message } => {
+                        ProxyResponse::failed(GatewayError::UpstreamError(message))
+                    }
+                };
+                return Ok(response.replayed());
+            }
+            Claim::First(guard) => idempotency = Some(guard),
+        }
+    }
+
+    // === Serve from the response cache when enabled ===
+    let cache_key = (method == Method::GET && service_config.cache_get_responses)
+        .then(|| ResponseCache::key(&service, &path, uri.query(), &headers));
+    if let Some(cached) = cache_key.as_deref().and_then(|key| state.response_cache.get(key)) {
+        tracing::debug!(agent_id = %agent.id, service = %service, path = %path, "Cache hit");
+        return Ok(ProxyResponse::new(200, cached).with_cache("HIT"));
+    }
+
     // === Get and refresh credentials if needed ===
-    let mut credential = state
-        .credentials
-        .get(&service)
-        .await
-        .ok_or_else(|| GatewayError::CredentialNotFound(service.clone()))?;
-
-    if needs_refresh(&credential) {
-        if let Some(refreshed) = refresh_token(&credential).await {
-            state.credentials.update(refreshed.clone()).await?;
-            credential = refreshed;
-            tracing::info!(service = %service, "Token refreshed before proxy");
+    let credential = match services.assertion_signers.get(&service) {
+        // Service-account APIs: exchange a signed assertion, cached like any token
+        Some(signer) => {
+            assertion_credential(
+                &reqwest::Client::new(),
+                signer,
+                &state.credentials,
+                &state.refresh_metrics,
+                &state.refresh_coordinator,
+            )
+            .await?
+        }
+        None => {
+            let mut credential = state
+                .credentials
+                .get(&service)
+                .await
+                .ok_or_else(|| GatewayError::CredentialNotFound(service.clone()))?;
+
+            if needs_refresh_with_skew(&credential, state.settings.clock_skew_secs) {
+                match state.refresh_coordinator.acquire(&service).await {
+                    RefreshTurn::Refresh(_permit) => {
+                        if let Some(refreshed) =
+                            refresh_instrumented(&credential, RefreshTrigger::Lazy, &state.refresh_metrics).await
+                        {
+                            state.credentials.update(refreshed.clone()).await?;
+                            credential = refreshed;
+                            tracing::info!(service = %service, "Token refreshed before proxy");
+                        }
+                    }
+                    // Refreshed by a concurrent request (or still stale if that failed)
+                    RefreshTurn::UseStored => {
+                        credential = state.credentials.get(&service).await.unwrap_or(credential);
+                    }
+                }
+            }
+            credential
         }
+    };
+
+    // === Decompress and parse body if present ===
+    let body = body
+        .map(|b| decode_request_body(&headers, b, state.settings.max_request_body_bytes))
+        .transpose()?;
+    // The upstream gets the decoded body, so its encoding/length headers no longer apply
+    headers.remove(header::CONTENT_ENCODING);
+    headers.remove(header::CONTENT_LENGTH);
+
+    // === Prompt injection guard (opt-in per service) ===
+    if let (Some(guard), Some(body)) = (services.injection_guards.get(&service), body.as_ref()) {
+        guard.check(&service, body, state.settings.injection_guard_mode)?;
     }
 
-    // === Parse body if present ===
-    let json_body: Option<Value> = body.and_then(|b| serde_json::from_slice(&b).ok());
+    let json_body: Option<Value> = body.as_ref().and_then(|b| serde_json::from_slice(b).ok());
+
+    // === Request body schema (endpoints with `request_schema`) ===
+    if let Some(schemas) = services.request_schemas.get(&service) {
+        schemas.validate(method.as_str(), &path, json_body.as_ref())?;
+    }
 
     // === Forward request ===
-    let proxy = ProxyClient::new();
-    let (status, response_body) = proxy
-        .forward(
-            &service_config.base_url,
-            &path,
-            method,
-            headers,
-            json_body,
-            &credential,
+    // If the client disconnects, this future is dropped mid-await: the guard
+    // records the cancellation and the upstream request is dropped with it.
+    let proxy = services
+        .proxy_clients
+        .get(&service)
+        .ok_or_else(|| GatewayError::NotFound(format!("Service '{}' not found", service)))?;
+    // === Cap the agent's requests in flight until the upstream has answered ===
+    let permit = state
+        .concurrency_limiter
+        .acquire(agent.id, agent.max_concurrent_requests)?;
+    let in_flight = state.proxy_metrics.track();
+    // Each call also reports its time upstream; everything else is gateway overhead
+    let forward = |credential: StoredCredential| {
+        let (method, headers, json_body) = (method.clone(), &headers, json_body.as_ref());
+        let (base_url, path) = (&service_config.base_url, &path);
+        async move {
+            let call_started = Instant::now();
+            let result = proxy.forward(base_url, path, method, headers, json_body, &credential).await;
+            (result, call_started.elapsed())
+        }
+    };
+    let (mut result, mut upstream_time) = forward(credential.clone()).await;
+    // A 401 means the upstream no longer takes our token, not that the agent's
+    // session is bad: get a new token and try once more before passing it on
+    if matches!(result, Ok(ProxyResult::Complete(401, _))) {
+        if let Some(refreshed) = refresh_rejected(state, services, &service, &credential).await? {
+            tracing::info!(service = %service, "Upstream rejected the token; retrying with a refreshed one");
+            let (retried, elapsed) = forward(refreshed).await;
+            result = retried;
+            upstream_time += elapsed;
+        }
+    }
+    in_flight.finish();
+    drop(permit);
+    let upstream_ms = upstream_time.as_millis() as u64;
+    audit.upstream_time_ms = Some(upstream_ms);
+    let result = match result {
+        Ok(result) => result,
+        Err(e) => {
+            // Once sent, the upstream may have acted on it: retries get this error, not a second send
+            if let (Some(guard), Some(failed)) = (idempotency, StoredResponse::failed(&e)) {
+                guard.complete(failed, Duration::from_secs(idempotency_ttl));
+            }
+            return Err(e);
+        }
+    };
+    let status = result.status();
+    if status < 400 {
+        state.credentials.mark_used(&service);
+    }
+    let (mut response_body, partial) = match result {
+        ProxyResult::Complete(_, body) => (body, false),
+        ProxyResult::PartialResponse(body) => (body, true),
+    };
+    // === Response sanitizer (opt-in per service), before anything caches the body ===
+    if let Some(sanitizer) = services.response_sanitizers.get(&service) {
+        sanitizer.sanitize(&service, &mut response_body);
+    }
+    // === Payload capture for the audit entry (opt-in per service); copies only ===
+    if let Some(capture) = &service_config.audit_capture {
+        capture_exchange(audit, capture, &headers, body.as_deref(), &response_body);
+    }
+    // A partial answer is replayed as partial: the upstream did get the request
+    if let Some(guard) = idempotency {
+        guard.complete(
+            StoredResponse::Upstream { status, body: response_body.clone(), partial },
+            Duration::from_secs(idempotency_ttl),
+        );
+    }
+
+    if should_log(state.settings.log_sample_rate) {
+        let total_ms = started.elapsed().as_millis() as u64;
+        tracing::info!(
+            agent_id = %agent.id,
+            session_id = %session.session_id,
+            service = %service,
+            path = %path,
+            status = status,
+            total_ms = total_ms,
+            upstream_ms = upstream_ms,
+            gateway_ms = total_ms.saturating_sub(upstream_ms),
+            "Request proxied"
+        );
+    }
+
+    let response = ProxyResponse::new(status, response_body).with_partial(partial);
+    let Some(key) = cache_key else {
+        return Ok(response);
+    };
+    // Only successful responses are worth replaying
+    if status == 200 {
+        state.response_cache.insert(
+            key,
+            response.body.clone(),
+            std::time::Duration::from_secs(service_config.cache_ttl_secs),
+        );
+    }
+    Ok(response.with_cache("MISS"))
+}
+
+// === New token for a service whose upstream rejected `credential` ===
+// `None` when there is no way to get one (no refresh token, failed exchange):
+// the upstream's 401 is then passed on as is.
+async fn refresh_rejected(
+    state: &AppState,
+    services: &ServiceSet,
+    service: &str,
+    credential: &StoredCredential,
+) -> Result<Option<StoredCredential>, GatewayError> {
+    let _permit = match state.refresh_coordinator.acquire(service).await {
+        RefreshTurn::Refresh(permit) => permit,
+        // Another request just got a new token: retry with it, unless it's the rejected one
+        RefreshTurn::UseStored => {
+            let stored = state.credentials.get(service).await;
+            return Ok(stored.filter(|stored| stored.access_token != credential.access_token));
+        }
+    };
+    if let Some(signer) = services.assertion_signers.get(service) {
+        let exchanged = exchange_assertion(
+            &reqwest::Client::new(),
+            signer,
+            &state.credentials,
+            &state.refresh_metrics,
+            RefreshTrigger::Rejected,
         )
-        .await?;
+        .await;
+        return Ok(exchanged
+            .inspect_err(|e| tracing::warn!(service = %service, error = ?e, "Token exchange after upstream 401 failed"))
+            .ok());
+    }
+    let Some(refreshed) =
+        refresh_instrumented(credential, RefreshTrigger::Rejected, &state.refresh_metrics).await
+    else {
+        return Ok(None);
+    };
+    state.credentials.update(refreshed.clone()).await?;
+    Ok(Some(refreshed))
+}
 
-    tracing::info!(
-        agent_id = %agent.id,
-        session_id = %session.session_id,
-        service = %service,
-        path = %path,
-        status = status,
-        "Request proxied"
-    );
+// === Upstream answer relayed to the agent with the upstream's status ===
+struct ProxyResponse {
+    status: u16,
+    body: Value,
+    /// X-Cache value for services with response caching
+    cache: Option<&'static str>,
+    /// Answered from the idempotency cache instead of the upstream
+    replayed: bool,
+    /// What arrived before the upstream read timed out (X-Partial-Response)
+    partial: bool,
+    /// A replayed gateway error, answered like the first request's
+    failure: Option<GatewayError>,
+}
+
+impl ProxyResponse {
+    fn new(status: u16, body: Value) -> Self {
+        Self { status, body, cache: None, replayed: false, partial: false, failure: None }
+    }
+
+    fn failed(error: GatewayError) -> Self {
+        Self { failure: Some(error), ..Self::new(StatusCode::BAD_GATEWAY.as_u16(), Value::Null) }
+    }
+
+    fn with_partial(mut self, partial: bool) -> Self {
+        self.partial = partial;
+        self
+    }
+
+    fn with_cache(mut self, value: &'static str) -> Self {
+        self.cache = Some(value);
+        self
+    }
 
-    Ok(Json(response_body))
+    fn replayed(mut self) -> Self {
+        self.replayed = true;
+        self
+    }
+}
+
+impl IntoResponse for ProxyResponse {
+    fn into_response(self) -> Response {
+        // Codes outside the valid range mean a broken upstream
+        let status = StatusCode::from_u16(self.status).unwrap_or(StatusCode::BAD_GATEWAY);
+
+        // 204/304 carry no body; don't invent an empty JSON object
+        let mut response = if status == StatusCode::NO_CONTENT || status == StatusCode::NOT_MODIFIED {
+            status.into_response()
+        } else {
+            (status, Json(self.body)).into_response()
+        };
+        if let Some(value) = self.cache {
+            response
+                .headers_mut()
+                .insert(CACHE_HEADER, HeaderValue::from_static(value));
+        }
+        if self.replayed {
+            response
+                .headers_mut()
+                .insert(REPLAYED_HEADER, HeaderValue::from_static("true"));
+        }
+        if self.partial {
+            response
+                .headers_mut()
+                .insert(PARTIAL_HEADER, HeaderValue::from_static("true"));
+        }
+        response
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    async fn body_bytes(response: Response) -> Vec<u8> {
+        axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap()
+            .to_vec()
+    }
+
+    #[tokio::test]
+    async fn test_upstream_error_status_is_propagated() {
+        let response = ProxyResponse::new(404, json!({"detail": "missing"})).into_response();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+        let body: Value = serde_json::from_slice(&body_bytes(response).await).unwrap();
+        assert_eq!(body["detail"], "missing");
+    }
+
+    #[tokio::test]
+    async fn test_no_content_has_empty_body() {
+        let response = ProxyResponse::new(204, json!({"raw": "non-json response"})).into_response();
+        assert_eq!(response.status(), StatusCode::NO_CONTENT);
+        assert!(body_bytes(response).await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_invalid_upstream_status_maps_to_bad_gateway() {
+        let response = ProxyResponse::new(1000, json!({})).into_response();
+        assert_eq!(response.status(), StatusCode::BAD_GATEWAY);
+    }
+
+    #[tokio::test]
+    async fn test_partial_response_is_flagged() {
+        let response = ProxyResponse::new(206, json!("data: {\"token\"")).with_partial(true).into_response();
+        assert_eq!(response.status(), StatusCode::PARTIAL_CONTENT);
+        assert_eq!(response.headers()[PARTIAL_HEADER], "true");
+
+        let body: Value = serde_json::from_slice(&body_bytes(response).await).unwrap();
+        assert_eq!(body, "data: {\"token\"");
+    }
 }
@@ -1,38 +1,630 @@
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+use arc_swap::ArcSwap;
 use std::sync::Arc;
+use std::time::{Duration, Instant};
+use uuid::Uuid;
 
-use crate::config::{CredentialManager, ServiceRegistry, Settings};
+use crate::audit::{
+    load_alert_rules, AdminAuditLogger, AlertEngine, AlertRule, AuditOverflowPolicy, AuditSink, AuditStore, AuditStoreTrait, AuditWriter, AuditWriterConfig, HttpAuditSink, SqliteAuditStore, StatsCache, SyslogAuditSink,
+};
+use crate::auth::create_session;
+use crate::config::{
+    AuditBackend, CredentialManager, ServiceRegistry, ServicesDocument, SessionStoreKind, Settings, StorageBackend,
+};
 use crate::error::GatewayError;
-use crate::gateway::RateLimiter;
-use crate::storage::{AgentStore, UserStore};
+use crate::gateway::{
+    build_injection_guards, build_proxy_clients, build_request_schemas, build_response_envelopes, build_response_sanitizers, load_assertion_signers, AssertionSigner,
+    ConcurrencyLimiter, ExpiryNotifier, IdempotencyCache, IpRateLimiter, LatencyMetrics, PromptInjectionGuard, ProxyClient, ProxyMetrics, RateLimitConfig, RateLimiter, RefreshCoordinator, RefreshMetrics, RequestSchemas, ResponseCache, ResponseEnvelope, ResponseSanitizer, SsrfPolicy, StatsDClient,
+};
+use crate::models::{Agent, AgentSession};
+use crate::storage::{
+    load_with_timeout, AgentStore, AgentStoreTrait, CachedAgentStore, CachedSessionStore,
+    CredentialStoreTrait, InMemoryStore, PruneStores, SessionCache, SessionStoreTrait, SnapshotStores,
+    SqliteStore, UserStore, UserStoreTrait,
+};
 
 #[derive(Clone)]
 pub struct AppState {
     pub settings: Arc<Settings>,
-    pub users: UserStore,
-    pub agents: AgentStore,
-    pub services: Arc<ServiceRegistry>,
+    pub users: Arc<dyn UserStoreTrait>,
+    pub agents: Arc<dyn AgentStoreTrait>,
+    pub sessions: Arc<dyn SessionStoreTrait>,
+    /// Recent `validate_session` results; `agents` and `sessions` invalidate it on writes
+    pub session_cache: SessionCache,
+    /// Per-agent credentials; `None` on the file backend
+    pub agent_credentials: Option<Arc<dyn CredentialStoreTrait>>,
+    /// The services file and what is built from it; read it through `services()`
+    service_set: Arc<ArcSwap<ServiceSet>>,
+    /// Held while the admin API rewrites the services file (`edit_services`)
+    services_edit: Arc<tokio::sync::Mutex<()>>,
     pub credentials: Arc<CredentialManager>,
     pub rate_limiter: RateLimiter,
+    /// Pre-auth endpoints, keyed on client IP
+    pub ip_rate_limiter: IpRateLimiter,
+    pub refresh_metrics: RefreshMetrics,
+    /// One credential refresh per service at a time; concurrent requests wait for it
+    pub refresh_coordinator: RefreshCoordinator,
+    pub proxy_metrics: ProxyMetrics,
+    pub latency_metrics: LatencyMetrics,
+    pub response_cache: ResponseCache,
+    pub idempotency: IdempotencyCache,
+    /// Permits for agents with `max_concurrent_requests`, created on first use
+    pub concurrency_limiter: ConcurrencyLimiter,
+    /// Recent `GET /admin/stats` results, served again while dashboards poll
+    pub audit_stats_cache: StatsCache,
+    /// Set when EXPIRY_WEBHOOK_URL is configured
+    pub expiry_notifier: Option<Arc<ExpiryNotifier>>,
+    /// Proxy audit trail; `None` when AUDIT_LOG_PATH is empty or on the memory backend
+    pub audit: Option<Arc<dyn AuditStoreTrait>>,
+    /// Queues the proxy hands audit entries to: the `audit` files and any
+    /// external sinks (AUDIT_HTTP_ENDPOINT, AUDIT_SYSLOG_ADDR); `None` without either
+    pub audit_writer: Option<AuditWriter>,
+    /// Per-request metrics pushed to STATSD_HOST; `None` when it is unset
+    pub statsd: Option<Arc<StatsDClient>>,
+    /// Admin operations, kept apart from the proxy audit trail (ADMIN_AUDIT_LOG_PATH)
+    pub admin_audit_logger: AdminAuditLogger,
+    /// When the state was built, for `uptime_secs` in the health report
+    pub started_at: Instant,
+}
+
+/// Everything built from the services file. `POST /admin/services/reload`
+/// swaps it as a whole, so a request sees one version from start to finish.
+pub struct ServiceSet {
+    pub registry: ServiceRegistry,
+    pub assertion_signers: HashMap<String, AssertionSigner>,
+    pub proxy_clients: HashMap<String, ProxyClient>,
+    /// Only services with `enable_injection_guard` have an entry
+    pub injection_guards: HashMap<String, PromptInjectionGuard>,
+    /// Only services with an endpoint `request_schema` have an entry
+    pub request_schemas: HashMap<String, RequestSchemas>,
+    /// Only services with `wrap_response` have an entry
+    pub response_envelopes: HashMap<String, ResponseEnvelope>,
+    /// Only services with `sanitize_responses` have an entry
+    pub response_sanitizers: HashMap<String, ResponseSanitizer>,
+}
+
+// The key rotation task reads the current services through this
+impl AsRef<ServiceRegistry> for ServiceSet {
+    fn as_ref(&self) -> &ServiceRegistry {
+        &self.registry
+    }
+}
+
+impl ServiceSet {
+    fn load(settings: &Settings) -> Result<Self, GatewayError> {
+        let ssrf = SsrfPolicy::from_allowlist(&settings.ssrf_allowlist)?;
+        let registry = ServiceRegistry::load(&settings.services_config_path, &settings.discovery_prefix, &ssrf)?;
+        Ok(Self {
+            assertion_signers: load_assertion_signers(&registry)?,
+            proxy_clients: build_proxy_clients(&registry, &ssrf, settings.max_response_body_bytes)?,
+            injection_guards: build_injection_guards(&registry)?,
+            request_schemas: build_request_schemas(&registry)?,
+            response_envelopes: build_response_envelopes(&registry)?,
+            response_sanitizers: build_response_sanitizers(&registry)?,
+            registry,
+        })
+    }
+
+    /// Each service's `rate_limit`, for `RateLimiter::check_service`
+    fn rate_limits(&self) -> HashMap<String, RateLimitConfig> {
+        self.registry
+            .list()
+            .into_iter()
+            .map(|service| {
+                let limit = RateLimitConfig {
+                    requests: service.rate_limit.requests,
+                    window: Duration::from_secs(service.rate_limit.window_secs),
+                };
+                (service.id.clone(), limit)
+            })
+            .collect()
+    }
+}
+
+/// What `POST /admin/services/reload` changed
+#[derive(Debug, Serialize)]
+pub struct ServiceReload {
+    pub services: usize,
+    pub added: Vec<String>,
+    pub removed: Vec<String>,
 }
 
 impl AppState {
     pub fn new(settings: Settings) -> Result<Self, GatewayError> {
-        let services = ServiceRegistry::load_from_file(&settings.services_config_path)?;
-        let credentials = CredentialManager::load_from_file(
-            &settings.credentials_path,
-            &settings.encryption_key,
-        )?;
-        let users = UserStore::load_from_file("data/users.json")?;
-        let agents = AgentStore::load_from_file("data/agents.json")?;
-        let rate_limiter = RateLimiter::new();
+        let stores = open_stores(&settings)?;
+        Self::with_stores(settings, stores)
+    }
+
+    /// State on the memory backend, so tests never write to `data/`.
+    /// Services and the credential seed are still read from the configured paths.
+    #[allow(dead_code)]
+    pub fn for_tests() -> Self {
+        Self::for_tests_with(Settings::from_env())
+    }
+
+    /// `for_tests` with explicit settings (e.g. a different encryption key)
+    #[allow(dead_code)]
+    pub fn for_tests_with(mut settings: Settings) -> Self {
+        settings.storage_backend = StorageBackend::Memory;
+        Self::new(settings).expect("Failed to create test state")
+    }
+
+    fn with_stores(settings: Settings, stores: Stores) -> Result<Self, GatewayError> {
+        let (users, agents, sessions, agent_credentials) = stores;
+        let session_cache = SessionCache::new(Duration::from_millis(settings.session_cache_ttl_ms));
+        let agents: Arc<dyn AgentStoreTrait> =
+            Arc::new(CachedAgentStore::new(agents, session_cache.clone()));
+        let sessions: Arc<dyn SessionStoreTrait> =
+            Arc::new(CachedSessionStore::new(sessions, session_cache.clone()));
+        let services = ServiceSet::load(&settings)?;
+        let credentials = match settings.storage_backend {
+            // Seeded from the file, but never written back
+            StorageBackend::Memory => CredentialManager::in_memory(
+                CredentialManager::read_seed(&settings.credentials_path, &settings.encryption_key)?,
+                &settings.encryption_key,
+            ),
+            _ => {
+                let io_timeout = Duration::from_secs(settings.file_io_timeout_secs);
+                let (path, key) = (settings.credentials_path.clone(), settings.encryption_key.clone());
+                load_with_timeout(io_timeout, Path::new(&settings.credentials_path), move || {
+                    CredentialManager::load_from_file(path, &key)
+                })?
+                .with_io_timeout(io_timeout)
+                .with_backup_count(settings.credentials_backup_count)
+            }
+        };
+        let expiry_notifier = settings.expiry_webhook_url.clone().map(|url| {
+            Arc::new(ExpiryNotifier::new(
+                url,
+                settings.expiry_webhook_format,
+                settings.expiry_notification_days,
+            ))
+        });
+        // The memory backend promises no disk writes
+        let audit = match settings.storage_backend {
+            StorageBackend::Memory => None,
+            _ => open_audit_store(&settings)?,
+        };
+        let alert_rules = match &settings.alert_rules_path {
+            Some(path) => load_alert_rules(path)?,
+            None => Vec::new(),
+        };
+        let audit_writer = build_audit_writer(&settings, audit.clone(), alert_rules);
+        let admin_audit_logger = match (&settings.storage_backend, &settings.admin_audit_log_path) {
+            (StorageBackend::Memory, _) => AdminAuditLogger::in_memory(),
+            (_, Some(path)) => AdminAuditLogger::file(path)
+                .with_io_timeout(Duration::from_secs(settings.file_io_timeout_secs)),
+            (_, None) => AdminAuditLogger::disabled(),
+        };
+        let rate_limiter = RateLimiter::with_group_limits(
+            settings
+                .rate_limit_groups
+                .iter()
+                .map(|(group, limit)| {
+                    (
+                        group.clone(),
+                        RateLimitConfig {
+                            requests: limit.requests,
+                            window: Duration::from_secs(limit.window_secs),
+                        },
+                    )
+                })
+                .collect(),
+        )
+        .with_user_limit(RateLimitConfig {
+            requests: settings.user_rate_limit.requests,
+            window: Duration::from_secs(settings.user_rate_limit.window_secs),
+        });
+        rate_limiter.set_service_limits(services.rate_limits());
+        let ip_rate_limiter = IpRateLimiter::new(RateLimitConfig {
+            requests: settings.ip_rate_limit.requests,
+            window: Duration::from_secs(settings.ip_rate_limit.window_secs),
+        });
+        let refresh_coordinator = RefreshCoordinator::new(Duration::from_secs(settings.token_refresh_cooldown_secs));
+        let statsd = settings
+            .statsd_host
+            .as_ref()
+            .map(|host| Arc::new(StatsDClient::new(format!("{}:{}", host, settings.statsd_port))));
 
         Ok(Self {
             settings: Arc::new(settings),
             users,
             agents,
-            services: Arc::new(services),
+            sessions,
+            session_cache,
+            agent_credentials,
+            service_set: Arc::new(ArcSwap::from_pointee(services)),
+            services_edit: Arc::default(),
             credentials: Arc::new(credentials),
             rate_limiter,
+            ip_rate_limiter,
+            refresh_metrics: RefreshMetrics::new(),
+            refresh_coordinator,
+            proxy_metrics: ProxyMetrics::new(),
+            latency_metrics: LatencyMetrics::new(),
+            response_cache: ResponseCache::new(),
+            idempotency: IdempotencyCache::new(),
+            concurrency_limiter: ConcurrencyLimiter::new(),
+            audit_stats_cache: StatsCache::new(),
+            expiry_notifier,
+            audit,
+            audit_writer,
+            statsd,
+            admin_audit_logger,
+            started_at: Instant::now(),
         })
     }
+
+    /// The current services; a reload doesn't affect a set already taken.
+    /// Takes no lock: a reload swaps the pointer.
+    pub fn services(&self) -> Arc<ServiceSet> {
+        self.service_set.load_full()
+    }
+
+    /// Re-read the services file and swap in what is built from it, with the
+    /// per-service rate limits. Nothing changes unless all of it is valid.
+    pub async fn reload_services(&self) -> Result<ServiceReload, GatewayError> {
+        let settings = self.settings.clone();
+        // File reads and base_url DNS lookups
+        let loaded = tokio::task::spawn_blocking(move || ServiceSet::load(&settings))
+            .await
+            .map_err(|e| GatewayError::Internal(format!("Service reload task failed: {}", e)))?;
+        let loaded = loaded.map_err(|e| {
+            let (_, _, message) = e.parts();
+            GatewayError::BadRequest(format!("Services file rejected, keeping the current services: {}", message))
+        })?;
+        if self.settings.validate_service_urls_on_startup {
+            loaded.registry.validate_urls().await;
+        }
+
+        let old = self.services();
+        let ids = |set: &ServiceSet| -> HashSet<String> {
+            set.registry.list().into_iter().map(|s| s.id.clone()).collect()
+        };
+        let (old_ids, new_ids) = (ids(&old), ids(&loaded));
+        let mut reload = ServiceReload {
+            services: new_ids.len(),
+            added: new_ids.difference(&old_ids).cloned().collect(),
+            removed: old_ids.difference(&new_ids).cloned().collect(),
+        };
+        reload.added.sort();
+        reload.removed.sort();
+
+        let rate_limits = loaded.rate_limits();
+        self.service_set.store(Arc::new(loaded));
+        self.rate_limiter.set_service_limits(rate_limits);
+        Ok(reload)
+    }
+
+    /// Apply `edit` to the services file, write it back and reload from it. The
+    /// edited services are checked like the file is at startup first; if the
+    /// reload still fails, the previous file is put back. One edit at a time.
+    pub async fn edit_services<T>(
+        &self,
+        edit: impl FnOnce(&mut ServicesDocument, &ServiceRegistry) -> Result<T, GatewayError>,
+    ) -> Result<(T, ServiceReload), GatewayError> {
+        let _editing = self.services_edit.lock().await;
+        let path = Path::new(&self.settings.services_config_path);
+        let previous = match tokio::fs::read_to_string(path).await {
+            Ok(content) => Some(content),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => None,
+            Err(e) => {
+                return Err(GatewayError::Internal(format!("Failed to read services config: {}", e)));
+            }
+        };
+        let mut document = ServicesDocument::parse(previous.as_deref())?;
+        let output = edit(&mut document, &self.services().registry)?;
+
+        let ssrf = SsrfPolicy::from_allowlist(&self.settings.ssrf_allowlist)?;
+        let problems = ServiceRegistry::check(&document.services()?, &ssrf);
+        if !problems.is_empty() {
+            return Err(GatewayError::BadRequest(problems.join("; ")));
+        }
+        write_services_file(path, &document.render()).await?;
+
+        match self.reload_services().await {
+            Ok(reload) => Ok((output, reload)),
+            Err(e) => {
+                let restored = match &previous {
+                    Some(content) => write_services_file(path, content).await,
+                    None => tokio::fs::remove_file(path)
+                        .await
+                        .map_err(|e| GatewayError::Internal(format!("Failed to remove services config: {}", e))),
+                };
+                if let Err(restore_error) = restored {
+                    tracing::error!(error = ?restore_error, "Failed to restore the services file after a rejected edit");
+                }
+                Err(e)
+            }
+        }
+    }
+
+    /// The stores covered by data export/import
+    pub fn snapshot_stores(&self) -> SnapshotStores<'_> {
+        SnapshotStores {
+            users: self.users.as_ref(),
+            agents: self.agents.as_ref(),
+            sessions: self.sessions.as_ref(),
+            credentials: &self.credentials,
+        }
+    }
+
+    pub fn prune_stores(&self) -> PruneStores<'_> {
+        PruneStores {
+            users: self.users.as_ref(),
+            agents: self.agents.as_ref(),
+            sessions: self.sessions.as_ref(),
+        }
+    }
+
+    /// Issue a new session for an agent using the configured TTL. At
+    /// MAX_SESSIONS_PER_AGENT live sessions, the agent's oldest one is revoked.
+    pub async fn start_session(&self, agent_id: Uuid) -> Result<AgentSession, GatewayError> {
+        let session = create_session(agent_id, self.settings.session_ttl_secs);
+        let (session, evicted) = self
+            .sessions
+            .create_session_capped(session, self.settings.max_sessions_per_agent)
+            .await?;
+        if evicted > 0 {
+            tracing::info!(agent_id = %agent_id, evicted, "Revoked oldest sessions over MAX_SESSIONS_PER_AGENT");
+        }
+        Ok(session)
+    }
+
+    /// Resolve a session ID to its (unexpired) session and agent, refusing
+    /// suspended agents
+    pub async fn validate_session(
+        &self,
+        session_id: &str,
+    ) -> Result<(AgentSession, Agent), GatewayError> {
+        let (session, agent) = self.resolve_session(session_id).await?;
+        check_agent_enabled(&agent)?;
+        Ok((session, agent))
+    }
+
+    /// Resolve a session ID to its (unexpired) session and agent, whether or not
+    /// the agent is suspended (the proxy audits the attempt before refusing it).
+    /// Results are reused for SESSION_CACHE_TTL_MS, so hot paths skip the store locks.
+    /// Suspending an agent goes through `update_agent`, which drops its cached sessions.
+    pub async fn resolve_session(
+        &self,
+        session_id: &str,
+    ) -> Result<(AgentSession, Agent), GatewayError> {
+        if let Some((session, agent)) = self.session_cache.get(session_id) {
+            if session.is_expired() {
+                return Err(GatewayError::SessionExpired);
+            }
+            return Ok((session, agent));
+        }
+
+        let generation = self.session_cache.generation();
+        let session = self
+            .sessions
+            .get_session(session_id)
+            .await?
+            .ok_or_else(|| GatewayError::Unauthorized("Invalid session".to_string()))?;
+
+        if session.is_expired() {
+            return Err(GatewayError::SessionExpired);
+        }
+
+        let agent = self
+            .agents
+            .get_agent(session.agent_id)
+            .await?
+            .ok_or_else(|| GatewayError::Internal("Agent not found".to_string()))?;
+
+        self.session_cache.insert(generation, &session, &agent);
+        Ok((session, agent))
+    }
+}
+
+/// Refuse a suspended agent, saying so rather than that its key expired
+pub(crate) fn check_agent_enabled(agent: &Agent) -> Result<(), GatewayError> {
+    if agent.enabled {
+        return Ok(());
+    }
+    Err(GatewayError::Forbidden(
+        "Agent is suspended. Contact an administrator to resume it.".to_string(),
+    ))
+}
+
+// === The configured audit backend; `None` when AUDIT_LOG_PATH is empty ===
+fn open_audit_store(settings: &Settings) -> Result<Option<Arc<dyn AuditStoreTrait>>, GatewayError> {
+    let Some(path) = &settings.audit_log_path else {
+        return Ok(None);
+    };
+    match settings.audit_backend {
+        AuditBackend::File => {
+            let store = AuditStore::new(path)
+                .with_io_timeout(Duration::from_secs(settings.file_io_timeout_secs))
+                .with_max_file_bytes(settings.audit_max_file_bytes);
+            Ok(Some(Arc::new(match &settings.audit_hmac_key {
+                Some(key) => store.with_hmac_key(key),
+                None => store,
+            })))
+        }
+        AuditBackend::Sqlite => {
+            if settings.audit_hmac_key.is_some() {
+                return Err(GatewayError::Internal(
+                    "AUDIT_HMAC_KEY requires AUDIT_BACKEND=file".to_string(),
+                ));
+            }
+            let store = SqliteAuditStore::open(&settings.audit_database_path)?;
+            let imported = store.import_jsonl_if_empty(Path::new(path))?;
+            if imported > 0 {
+                tracing::info!(entries = imported, from = %path, "Imported audit log files into SQLite");
+            }
+
+            tracing::info!(path = %settings.audit_database_path, "Using SQLite audit store");
+            Ok(Some(Arc::new(store)))
+        }
+    }
+}
+
+// === Audit queues: the local files plus the external sinks ===
+// External sinks never hold a request up: a full queue drops, whatever
+// AUDIT_OVERFLOW_POLICY says for the local files.
+fn build_audit_writer(
+    settings: &Settings,
+    store: Option<Arc<dyn AuditStoreTrait>>,
+    alert_rules: Vec<AlertRule>,
+) -> Option<AuditWriter> {
+    let mut sinks: Vec<(Arc<dyn AuditSink>, usize)> = Vec::new();
+    if let Some(config) = &settings.audit_http_sink {
+        let mut config = config.clone();
+        // The memory backend promises no disk writes
+        if settings.storage_backend == StorageBackend::Memory {
+            config.spool_path = None;
+        }
+        let batch_size = config.batch_size;
+        sinks.push((Arc::new(HttpAuditSink::new(config)), batch_size));
+    }
+    if let Some(addr) = &settings.audit_syslog_addr {
+        sinks.push((Arc::new(SyslogAuditSink::new(addr.clone())), settings.audit_writer.batch_size));
+    }
+    // Alert rules see entries as they are written, like any other sink
+    if !alert_rules.is_empty() {
+        tracing::info!(rules = alert_rules.len(), "Security alert rules loaded");
+        sinks.push((Arc::new(AlertEngine::new(alert_rules)), settings.audit_writer.batch_size));
+    }
+    if store.is_none() && sinks.is_empty() {
+        return None;
+    }
+
+    let writer = store.map_or_else(AuditWriter::default, |store| AuditWriter::new(store, settings.audit_writer));
+    Some(sinks.into_iter().fold(writer, |writer, (sink, batch_size)| {
+        let config = AuditWriterConfig {
+            batch_size,
+            overflow: AuditOverflowPolicy::Drop,
+            ..settings.audit_writer
+        };
+        writer.with_sink(sink, config)
+    }))
+}
+
+type Stores = (
+    Arc<dyn UserStoreTrait>,
+    Arc<dyn AgentStoreTrait>,
+    Arc<dyn SessionStoreTrait>,
+    Option<Arc<dyn CredentialStoreTrait>>,
+);
+
+// === Construct the configured storage backend, then the session store ===
+fn open_stores(settings: &Settings) -> Result<Stores, GatewayError> {
+    let (users, agents, backend_sessions, agent_credentials) = open_backend(settings)?;
+
+    let sessions: Arc<dyn SessionStoreTrait> = match settings.session_store {
+        None => backend_sessions,
+        Some(SessionStoreKind::File) if settings.storage_backend == StorageBackend::File => {
+            backend_sessions
+        }
+        Some(SessionStoreKind::File) => {
+            return Err(GatewayError::Internal(
+                "SESSION_STORE=file requires STORAGE_BACKEND=file".to_string(),
+            ))
+        }
+        Some(SessionStoreKind::Memory) => {
+            tracing::warn!("Sessions are kept in memory: they are lost on restart and not shared between replicas");
+            Arc::new(InMemoryStore::new())
+        }
+        Some(SessionStoreKind::Redis) => open_redis_sessions(settings)?,
+    };
+
+    Ok((users, agents, sessions, agent_credentials))
+}
+
+#[cfg(feature = "redis")]
+fn open_redis_sessions(settings: &Settings) -> Result<Arc<dyn SessionStoreTrait>, GatewayError> {
+    let url = settings.redis_url.as_deref().ok_or_else(|| {
+        GatewayError::Internal("REDIS_URL must be set for SESSION_STORE=redis".to_string())
+    })?;
+    tracing::info!("Using Redis session store");
+    Ok(Arc::new(crate::storage::RedisSessionStore::open(url)?))
+}
+
+#[cfg(not(feature = "redis"))]
+fn open_redis_sessions(_settings: &Settings) -> Result<Arc<dyn SessionStoreTrait>, GatewayError> {
+    Err(GatewayError::Internal(
+        "SESSION_STORE=redis requires building with --features redis".to_string(),
+    ))
+}
+
+fn open_backend(settings: &Settings) -> Result<Stores, GatewayError> {
+    match settings.storage_backend {
+        StorageBackend::Memory => {
+            tracing::warn!("Using in-memory storage: all data is lost on exit");
+            let store = Arc::new(InMemoryStore::new());
+            Ok((store.clone(), store.clone(), store.clone(), Some(store)))
+        }
+        StorageBackend::File => {
+            let io_timeout = Duration::from_secs(settings.file_io_timeout_secs);
+            let users_path = settings.users_path.clone();
+            let users = load_with_timeout(io_timeout, Path::new(&settings.users_path), move || {
+                UserStore::load_from_file(users_path)
+            })?
+            .with_io_timeout(io_timeout)
+            .with_backup_count(settings.store_backup_count);
+            let (agents_path, sessions_path) =
+                (settings.agents_path.clone(), settings.sessions_path.clone());
+            let agents_key = settings
+                .agents_file_encryption
+                .then(|| settings.encryption_key.clone());
+            let agents = load_with_timeout(io_timeout, Path::new(&settings.agents_path), move || {
+                AgentStore::load_encrypted(agents_path, sessions_path, agents_key)
+            })?
+            .with_max_pending(settings.agents_flush_max_pending)
+            .with_io_timeout(io_timeout)
+            .with_backup_count(settings.store_backup_count);
+            agents.spawn_flusher(Duration::from_secs(settings.agents_flush_interval_secs.max(1)));
+            let agents = Arc::new(agents);
+            // credentials.json is per service, not per agent
+            Ok((Arc::new(users), agents.clone(), agents, None))
+        }
+        StorageBackend::Sqlite => {
+            let store = Arc::new(SqliteStore::open(
+                &settings.database_path,
+                &settings.encryption_key,
+            )?);
+            store.import_json_if_empty(
+                &settings.users_path,
+                &settings.agents_path,
+                &settings.sessions_path,
+            )?;
+
+            tracing::info!(path = %settings.database_path, "Using SQLite storage");
+            Ok((store.clone(), store.clone(), store.clone(), Some(store)))
+        }
+        #[cfg(feature = "postgres")]
+        StorageBackend::Postgres => {
+            let url = settings.database_url.as_deref().ok_or_else(|| {
+                GatewayError::Internal("DATABASE_URL must be set for postgres storage".to_string())
+            })?;
+            let store = Arc::new(crate::storage::PostgresStore::connect_lazy(
+                url,
+                settings.database_max_connections,
+                &settings.encryption_key,
+            )?);
+
+            tracing::info!("Using PostgreSQL storage");
+            Ok((store.clone(), store.clone(), store.clone(), Some(store)))
+        }
+        #[cfg(not(feature = "postgres"))]
+        StorageBackend::Postgres => Err(GatewayError::Internal(
+            "STORAGE_BACKEND=postgres requires building with --features postgres".to_string(),
+        )),
+    }
+}
+
+/// Replace the services file in one step, so `reload_services` never reads half of it
+async fn write_services_file(path: &Path, content: &str) -> Result<(), GatewayError> {
+    let write_error = |e: std::io::Error| GatewayError::Internal(format!("Failed to write services config: {}", e));
+    if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+        tokio::fs::create_dir_all(dir).await.map_err(write_error)?;
+    }
+    let staged = format!("{}.tmp", path.display());
+    tokio::fs::write(&staged, content).await.map_err(write_error)?;
+    tokio::fs::rename(&staged, path).await.map_err(write_error)
 }
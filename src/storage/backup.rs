@@ -0,0 +1,117 @@
+//! Timestamped backups of store files: `<file>.bak.<unix millis>`, taken before every
+//! save (and by `POST /admin/backup`). The newest `keep` are retained
+//! (CREDENTIALS_BACKUP_COUNT, STORE_BACKUP_COUNT); a corrupt store file is recovered
+//! from them, newest first. To roll back by hand, copy one over the file and restart.
+
+use chrono::Utc;
+use serde::de::IgnoredAny;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::error::GatewayError;
+
+pub const DEFAULT_BACKUP_COUNT: usize = 5;
+
+const BACKUP_INFIX: &str = ".bak.";
+
+/// A new backup name for `path`, stamped with the current time, or just past the
+/// newest existing backup when saves come faster than one per millisecond (a stamp
+/// freed by pruning is never reused: it would sort below newer backups)
+pub fn backup_path(path: &Path) -> PathBuf {
+    let now = Utc::now().timestamp_millis().max(0) as u64;
+    let stamp = stamped_backups(path).first().map_or(now, |(newest, _)| now.max(newest + 1));
+    PathBuf::from(format!("{}{}{}", path.display(), BACKUP_INFIX, stamp))
+}
+
+/// Timestamped backups of `path` with their stamps, newest first
+fn stamped_backups(path: &Path) -> Vec<(u64, PathBuf)> {
+    let Some(name) = path.file_name().map(|n| format!("{}{}", n.to_string_lossy(), BACKUP_INFIX)) else {
+        return Vec::new();
+    };
+    let dir = path.parent().filter(|d| !d.as_os_str().is_empty()).unwrap_or(Path::new("."));
+    let mut stamped: Vec<(u64, PathBuf)> = fs::read_dir(dir)
+        .into_iter()
+        .flatten()
+        .flatten()
+        .filter_map(|entry| {
+            let file_name = entry.file_name();
+            let stamp = file_name.to_string_lossy().strip_prefix(&name)?.parse().ok()?;
+            Some((stamp, entry.path()))
+        })
+        .collect();
+    stamped.sort_by_key(|(stamp, _)| std::cmp::Reverse(*stamp));
+    stamped
+}
+
+/// Existing backups of `path`, newest first. Pre-timestamp backups (`<file>.bak`,
+/// `<file>.bak.1`) come last, so they are still tried during recovery.
+pub fn list_backups(path: &Path) -> Vec<PathBuf> {
+    let mut backups: Vec<PathBuf> = stamped_backups(path).into_iter().map(|(_, path)| path).collect();
+    let legacy = PathBuf::from(format!("{}.bak", path.display()));
+    if legacy.exists() {
+        backups.push(legacy);
+    }
+    backups
+}
+
+/// Copy `path` to a new timestamped backup, then delete all but the newest `keep`.
+/// Returns the backup, or `None` when `keep` is 0 or there was nothing worth keeping:
+/// a missing or unparseable file is never copied, so it can't push out a good backup.
+pub async fn backup_file(path: &Path, keep: usize) -> Result<Option<PathBuf>, GatewayError> {
+    if keep == 0 || !is_valid_json(path).await {
+        return Ok(None);
+    }
+    let backup = backup_path(path);
+    tokio::fs::copy(path, &backup).await.map_err(|e| {
+        GatewayError::Internal(format!("Failed to back up {}: {}", path.display(), e))
+    })?;
+
+    let old: Vec<PathBuf> = list_backups(path).into_iter().skip(keep).collect();
+    for stale in old {
+        if let Err(e) = tokio::fs::remove_file(&stale).await {
+            tracing::warn!(path = %stale.display(), error = %e, "Failed to remove old backup");
+        }
+    }
+    Ok(Some(backup))
+}
+
+async fn is_valid_json(path: &Path) -> bool {
+    tokio::fs::read(path)
+        .await
+        .is_ok_and(|content| serde_json::from_slice::<IgnoredAny>(&content).is_ok())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[tokio::test]
+    async fn test_backup_holds_the_previous_content_and_old_ones_are_pruned() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("store.json");
+        // Several saves per millisecond: pruned stamps must not be handed out again
+        for version in 0..4 {
+            fs::write(&path, format!("{{\"version\": {}}}", version)).unwrap();
+            backup_file(&path, 2).await.unwrap().unwrap();
+        }
+
+        let backups = list_backups(&path);
+        assert_eq!(backups.len(), 2);
+        assert_eq!(fs::read_to_string(&backups[0]).unwrap(), "{\"version\": 3}");
+        assert_eq!(fs::read_to_string(&backups[1]).unwrap(), "{\"version\": 2}");
+    }
+
+    #[tokio::test]
+    async fn test_unusable_files_are_not_backed_up() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("store.json");
+        assert!(backup_file(&path, 5).await.unwrap().is_none());
+
+        fs::write(&path, "{ garbage").unwrap();
+        assert!(backup_file(&path, 5).await.unwrap().is_none());
+        fs::write(&path, "{}").unwrap();
+        assert!(backup_file(&path, 0).await.unwrap().is_none());
+        assert!(list_backups(&path).is_empty());
+    }
+}
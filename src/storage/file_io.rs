@@ -0,0 +1,119 @@
+//! Deadlines for store file IO (FILE_IO_TIMEOUT_SECS). On network filesystems
+//! (NFS, EFS) a read or write can hang indefinitely; these turn that into an error.
+
+use std::future::Future;
+use std::path::Path;
+use std::sync::mpsc;
+use std::time::Duration;
+
+use crate::error::GatewayError;
+
+pub const DEFAULT_FILE_IO_TIMEOUT: Duration = Duration::from_secs(5);
+
+fn timeout_error(path: &Path, timeout: Duration) -> GatewayError {
+    GatewayError::Internal(format!(
+        "File store timeout: {} did not respond within {}s",
+        path.display(),
+        timeout.as_secs_f64()
+    ))
+}
+
+/// Run async file IO on `path`, failing once `timeout` has passed
+pub async fn with_io_timeout<T>(
+    timeout: Duration,
+    path: &Path,
+    io: impl Future<Output = Result<T, GatewayError>>,
+) -> Result<T, GatewayError> {
+    tokio::time::timeout(timeout, io)
+        .await
+        .map_err(|_| timeout_error(path, timeout))?
+}
+
+/// Health check of a store file: it is still there, is a file and can be
+/// written, within `timeout`
+pub async fn check_store_file(timeout: Duration, path: &Path) -> Result<(), GatewayError> {
+    with_io_timeout(timeout, path, async {
+        let metadata = tokio::fs::metadata(path)
+            .await
+            .map_err(|e| GatewayError::Internal(format!("{} is unavailable: {}", path.display(), e)))?;
+        if !metadata.is_file() {
+            return Err(GatewayError::Internal(format!("{} is not a file", path.display())));
+        }
+        if metadata.permissions().readonly() {
+            return Err(GatewayError::Internal(format!("{} is read-only", path.display())));
+        }
+        Ok(())
+    })
+    .await
+}
+
+/// Run a blocking startup load on its own thread, failing once `timeout` has passed.
+/// AppState::new is synchronous, so this waits on a thread rather than the runtime's
+/// blocking pool; a load stuck on a dead mount is abandoned and startup fails.
+pub fn load_with_timeout<T: Send + 'static>(
+    timeout: Duration,
+    path: &Path,
+    load: impl FnOnce() -> Result<T, GatewayError> + Send + 'static,
+) -> Result<T, GatewayError> {
+    let (done, result) = mpsc::sync_channel(1);
+    std::thread::spawn(move || {
+        // The receiver is gone if we already timed out
+        let _ = done.send(load());
+    });
+    match result.recv_timeout(timeout) {
+        Ok(loaded) => loaded,
+        Err(mpsc::RecvTimeoutError::Timeout) => Err(timeout_error(path, timeout)),
+        Err(mpsc::RecvTimeoutError::Disconnected) => Err(GatewayError::Internal(format!(
+            "Loading {} panicked",
+            path.display()
+        ))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_store_file_check() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("users.json");
+        assert!(check_store_file(DEFAULT_FILE_IO_TIMEOUT, &path).await.is_err());
+
+        std::fs::write(&path, "{}").unwrap();
+        check_store_file(DEFAULT_FILE_IO_TIMEOUT, &path).await.unwrap();
+
+        let mut permissions = std::fs::metadata(&path).unwrap().permissions();
+        permissions.set_readonly(true);
+        std::fs::set_permissions(&path, permissions).unwrap();
+        let result = check_store_file(DEFAULT_FILE_IO_TIMEOUT, &path).await;
+        assert!(matches!(result, Err(GatewayError::Internal(msg)) if msg.ends_with("is read-only")));
+
+        assert!(check_store_file(DEFAULT_FILE_IO_TIMEOUT, dir.path()).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_slow_io_times_out() {
+        let slow = async {
+            tokio::time::sleep(Duration::from_secs(5)).await;
+            Ok(())
+        };
+        let result = with_io_timeout(Duration::from_millis(50), Path::new("agents.json"), slow).await;
+        assert!(matches!(result, Err(GatewayError::Internal(msg)) if msg.starts_with("File store timeout")));
+
+        let fast = async { Ok(7) };
+        assert_eq!(with_io_timeout(DEFAULT_FILE_IO_TIMEOUT, Path::new("x"), fast).await.unwrap(), 7);
+    }
+
+    #[test]
+    fn test_slow_startup_load_times_out() {
+        let result = load_with_timeout(Duration::from_millis(50), Path::new("users.json"), || {
+            std::thread::sleep(Duration::from_secs(2));
+            Ok(())
+        });
+        assert!(matches!(result, Err(GatewayError::Internal(msg)) if msg.starts_with("File store timeout")));
+
+        let loaded = load_with_timeout(DEFAULT_FILE_IO_TIMEOUT, Path::new("x"), || Ok("ok"));
+        assert_eq!(loaded.unwrap(), "ok");
+    }
+}
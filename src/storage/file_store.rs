@@ -1,101 +1,394 @@
-use chrono::{Duration, Utc};
+use async_trait::async_trait;
+use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
+use serde_json::Value;
 use std::collections::HashMap;
 use std::fs;
-use std::path::Path;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
 use std::sync::Arc;
-use tokio::sync::RwLock;
+use std::time::Duration;
+use tokio::sync::{Mutex, RwLock, RwLockWriteGuard};
 use uuid::Uuid;
 
 use crate::error::GatewayError;
-use crate::models::{Agent, AgentSession, User};
+use crate::gateway::{decrypt, encrypt};
+use crate::models::{Agent, AgentSession, PendingUser, User};
+use super::backup::{backup_file, backup_path, list_backups, DEFAULT_BACKUP_COUNT};
+use super::file_io::{check_store_file, with_io_timeout, DEFAULT_FILE_IO_TIMEOUT};
+use super::journal::{Journal, JournalEntry};
+use super::listing::{paginate, paginate_agents, user_order, AgentCounts, AgentFilter, AgentSort, Page, UserFilter};
+use super::schema::{FileSchema, AGENTS_SCHEMA, USERS_SCHEMA};
+use super::traits::{
+    agent_conflict, agent_not_found, sessions_to_drop, AgentStoreTrait, SessionStoreTrait, UserStoreTrait,
+};
+
+// === Create the parent directory, and the file itself (as `empty`) if missing ===
+fn ensure_file(path: &str, empty: &str) -> Result<(), GatewayError> {
+    if let Some(dir) = Path::new(path).parent().filter(|d| !d.as_os_str().is_empty()) {
+        fs::create_dir_all(dir).map_err(|e| {
+            GatewayError::Internal(format!("Failed to create directory {}: {}", dir.display(), e))
+        })?;
+    }
+    if !Path::new(path).exists() {
+        fs::write(path, empty)
+            .map_err(|e| GatewayError::Internal(format!("Failed to create {}: {}", path, e)))?;
+    }
+    Ok(())
+}
+
+// ============ Backups & Recovery ============
+
+/// Back up the current file (see `backup`), then replace it. Fails after `timeout`.
+async fn write_with_backup(
+    path: &str,
+    content: &str,
+    keep_backups: usize,
+    timeout: Duration,
+) -> Result<(), GatewayError> {
+    let primary = Path::new(path);
+    with_io_timeout(timeout, primary, async {
+        backup_file(primary, keep_backups).await?;
+        tokio::fs::write(primary, content)
+            .await
+            .map_err(|e| GatewayError::Internal(format!("Failed to write {}: {}", path, e)))
+    })
+    .await
+}
+
+// ============ Encryption at rest ============
+
+/// An encrypted store file is `{"ciphertext": "<AES-GCM, base64>"}`. It stays JSON,
+/// so backups and corruption recovery work the same as for plaintext files.
+const CIPHERTEXT_KEY: &str = "ciphertext";
+
+fn sealed_doc(content: &str, key: &str) -> Result<Value, GatewayError> {
+    Ok(serde_json::json!({ CIPHERTEXT_KEY: encrypt(content, key)? }))
+}
+
+/// Encrypt serialized file content when `key` is set
+fn seal(content: String, key: Option<&str>) -> Result<String, GatewayError> {
+    let Some(key) = key else {
+        return Ok(content);
+    };
+    serde_json::to_string_pretty(&sealed_doc(&content, key)?)
+        .map_err(|e| GatewayError::Internal(format!("Failed to serialize encrypted store: {}", e)))
+}
+
+/// Decrypt a sealed document. Fails (fatally, never falling back to a backup)
+/// without a key or with the wrong one.
+fn unseal(doc: &Value, path: &Path, key: Option<&str>) -> Result<Option<Value>, GatewayError> {
+    let Some(ciphertext) = doc.get(CIPHERTEXT_KEY).and_then(Value::as_str) else {
+        return Ok(None);
+    };
+    let Some(key) = key else {
+        return Err(GatewayError::Internal(format!(
+            "{} is encrypted; set AGENTS_FILE_ENCRYPTION=true with the ENCRYPTION_KEY it was written with",
+            path.display()
+        )));
+    };
+    let content = decrypt(ciphertext, key).map_err(|_| {
+        GatewayError::Internal(format!(
+            "Failed to decrypt {}: ENCRYPTION_KEY is not the key it was written with, or the file is damaged",
+            path.display()
+        ))
+    })?;
+    serde_json::from_str(&content)
+        .map(Some)
+        .map_err(|e| GatewayError::Internal(format!("Decrypted {} is not valid JSON: {}", path.display(), e)))
+}
+
+/// Parse a store file; a missing file parses as `empty`. Encrypted files are
+/// decrypted with `key`. Versioned files are upgraded to the current `schema`
+/// first and the upgrade is written back. If the file is corrupt, the newest
+/// parseable backup is restored over it (the bad file is kept as `<file>.corrupt`).
+/// Fails when the file can't be decrypted, is newer than this binary, or when the
+/// file and every backup are unusable. Also returns whether the file was encrypted.
+fn read_store_file<T: DeserializeOwned>(
+    path: &Path,
+    empty: &str,
+    schema: Option<&FileSchema>,
+    key: Option<&str>,
+) -> Result<(T, bool), GatewayError> {
+    // Outer error: undecryptable or unusable schema version (fatal). Inner error: corrupt file.
+    type Parsed<T> = Option<Result<(T, Value, u32, bool), serde_json::Error>>;
+    let parse = |p: &Path| -> Result<Parsed<T>, GatewayError> {
+        let Ok(content) = fs::read_to_string(p) else {
+            return Ok(None);
+        };
+        let mut doc = match serde_json::from_str::<Value>(&content) {
+            Ok(doc) => doc,
+            Err(e) => return Ok(Some(Err(e))),
+        };
+        let sealed = match unseal(&doc, p, key)? {
+            Some(plain) => {
+                doc = plain;
+                true
+            }
+            None => false,
+        };
+        let from_version = match schema {
+            Some(schema) => schema.upgrade(&mut doc, p)?,
+            None => 0,
+        };
+        Ok(Some(T::deserialize(&doc).map(|value| (value, doc, from_version, sealed))))
+    };
+    let write_back = |doc: &Value, from_version: u32| match schema {
+        Some(schema) if from_version < schema.current_version() => match key {
+            Some(key) => schema.save_if_upgraded(path, &sealed_doc(&doc.to_string(), key)?, from_version),
+            None => schema.save_if_upgraded(path, doc, from_version),
+        },
+        _ => Ok(()),
+    };
+
+    let error = match parse(path)? {
+        None => {
+            return serde_json::from_str(empty)
+                .map(|value| (value, false))
+                .map_err(|e| GatewayError::Internal(format!("Invalid empty store: {}", e)))
+        }
+        Some(Ok((value, doc, from_version, sealed))) => {
+            write_back(&doc, from_version)?;
+            return Ok((value, sealed));
+        }
+        Some(Err(e)) => e,
+    };
+
+    for backup in list_backups(path) {
+        let Some(Ok((value, doc, from_version, sealed))) = parse(&backup)? else {
+            continue;
+        };
+
+        tracing::error!(
+            path = %path.display(),
+            backup = %backup.display(),
+            error = %error,
+            "STORE FILE CORRUPT: recovered from backup; changes since that backup are lost"
+        );
+        let corrupt = PathBuf::from(format!("{}.corrupt", path.display()));
+        match fs::rename(path, &corrupt).and_then(|_| fs::copy(&backup, path)) {
+            Ok(_) => write_back(&doc, from_version)?,
+            Err(e) => {
+                tracing::error!(path = %path.display(), error = %e, "Failed to restore backup over corrupt file")
+            }
+        }
+        return Ok((value, sealed));
+    }
+
+    Err(GatewayError::Internal(format!(
+        "Failed to parse {} and no usable backup was found: {}",
+        path.display(),
+        error
+    )))
+}
 
 // ============ Users Storage ============
 
 #[derive(Debug, Serialize, Deserialize)]
 struct UsersFile {
+    #[serde(default)]
+    schema_version: u32,
     users: Vec<User>,
+    // Registrations awaiting email verification
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pending_users: Vec<PendingUser>,
+}
+
+const EMPTY_USERS: &str = r#"{"schema_version":1,"users":[]}"#;
+
+/// Parse users.json; a missing file means no users
+pub(super) fn read_users_file<P: AsRef<Path>>(path: P) -> Result<Vec<User>, GatewayError> {
+    Ok(read_users_and_pending(path)?.users)
+}
+
+fn read_users_and_pending<P: AsRef<Path>>(path: P) -> Result<UsersFile, GatewayError> {
+    let (file, _) = read_store_file(path.as_ref(), EMPTY_USERS, Some(&USERS_SCHEMA), None)?;
+    Ok(file)
 }
 
 #[derive(Clone)]
 pub struct UserStore {
     users: Arc<RwLock<HashMap<Uuid, User>>>,
     users_by_email: Arc<RwLock<HashMap<String, Uuid>>>,
+    // Saved in users.json with the users; always locked after `users`
+    pending_users: Arc<RwLock<HashMap<Uuid, PendingUser>>>,
     file_path: String,
+    // Serializes writes to users.json (see `save_to_file`)
+    write_lock: Arc<Mutex<()>>,
+    io_timeout: Duration,
+    backup_count: usize,
 }
 
 impl UserStore {
     pub fn load_from_file<P: AsRef<Path>>(path: P) -> Result<Self, GatewayError> {
         let path_str = path.as_ref().to_string_lossy().to_string();
-
-        let content = fs::read_to_string(&path).unwrap_or_else(|_| r#"{"users":[]}"#.to_string());
-
-        let file: UsersFile = serde_json::from_str(&content)
-            .map_err(|e| GatewayError::Internal(format!("Failed to parse users: {}", e)))?;
+        ensure_file(&path_str, EMPTY_USERS)?;
 
         let mut users = HashMap::new();
         let mut users_by_email = HashMap::new();
 
+        let file = read_users_and_pending(&path)?;
         for user in file.users {
             users_by_email.insert(user.email.clone(), user.id);
             users.insert(user.id, user);
         }
+        let pending_users = file.pending_users.into_iter().map(|p| (p.id, p)).collect();
 
         Ok(Self {
             users: Arc::new(RwLock::new(users)),
             users_by_email: Arc::new(RwLock::new(users_by_email)),
+            pending_users: Arc::new(RwLock::new(pending_users)),
             file_path: path_str,
+            write_lock: Arc::new(Mutex::new(())),
+            io_timeout: DEFAULT_FILE_IO_TIMEOUT,
+            backup_count: DEFAULT_BACKUP_COUNT,
         })
     }
 
-    pub async fn create_user(&self, user: User) -> Result<User, GatewayError> {
-        // Check if email already exists
-        if self.users_by_email.read().await.contains_key(&user.email) {
-            return Err(GatewayError::BadRequest("Email already registered".to_string()));
-        }
+    /// Deadline for each users.json write (FILE_IO_TIMEOUT_SECS)
+    pub fn with_io_timeout(mut self, timeout: Duration) -> Self {
+        self.io_timeout = timeout;
+        self
+    }
+
+    /// Timestamped backups of users.json to keep (STORE_BACKUP_COUNT, 0 = none)
+    pub fn with_backup_count(mut self, backup_count: usize) -> Self {
+        self.backup_count = backup_count;
+        self
+    }
+
+    /// Snapshot under the map lock, release it, then write without blocking readers.
+    /// The write lock is taken before the map lock is released, so saves reach the
+    /// file in the same order as the mutations they capture.
+    async fn save_to_file(
+        &self,
+        users: RwLockWriteGuard<'_, HashMap<Uuid, User>>,
+    ) -> Result<(), GatewayError> {
+        let file = UsersFile {
+            schema_version: USERS_SCHEMA.current_version(),
+            users: users.values().cloned().collect(),
+            pending_users: self.pending_users.read().await.values().cloned().collect(),
+        };
+        let content = serde_json::to_string_pretty(&file)
+            .map_err(|e| GatewayError::Internal(format!("Failed to serialize users: {}", e)))?;
+
+        let _write = self.write_lock.lock().await;
+        drop(users);
+        write_with_backup(&self.file_path, &content, self.backup_count, self.io_timeout).await
+    }
+}
 
+#[async_trait]
+impl UserStoreTrait for UserStore {
+    async fn create_user(&self, user: User) -> Result<User, GatewayError> {
         let mut users = self.users.write().await;
         let mut by_email = self.users_by_email.write().await;
 
+        // Check under the write lock so two registrations can't race
+        if by_email.contains_key(&user.email) {
+            return Err(GatewayError::BadRequest("Email already registered".to_string()));
+        }
+
         by_email.insert(user.email.clone(), user.id);
         users.insert(user.id, user.clone());
+        drop(by_email);
 
-        self.save_to_file(&users).await?;
+        self.save_to_file(users).await?;
         Ok(user)
     }
 
-    pub async fn get_user(&self, id: Uuid) -> Option<User> {
-        self.users.read().await.get(&id).cloned()
+    async fn get_user(&self, id: Uuid) -> Result<Option<User>, GatewayError> {
+        Ok(self.users.read().await.get(&id).cloned())
     }
 
-    /// Get user by email (alternative lookup method)
-    #[allow(dead_code)]
-    pub async fn get_user_by_email(&self, email: &str) -> Option<User> {
-        let by_email = self.users_by_email.read().await;
-        if let Some(id) = by_email.get(email) {
-            return self.users.read().await.get(id).cloned();
-        }
-        None
+    async fn get_user_by_email(&self, email: &str) -> Result<Option<User>, GatewayError> {
+        let Some(id) = self.users_by_email.read().await.get(email).copied() else {
+            return Ok(None);
+        };
+        Ok(self.users.read().await.get(&id).cloned())
+    }
+
+    async fn list_users(&self) -> Result<Vec<User>, GatewayError> {
+        Ok(self.users.read().await.values().cloned().collect())
+    }
+
+    async fn list_users_page(
+        &self,
+        offset: usize,
+        limit: usize,
+        filter: &UserFilter,
+    ) -> Result<Page<User>, GatewayError> {
+        let users = self.users.read().await;
+        Ok(paginate(users.values().filter(|u| filter.matches(u)), user_order, offset, limit))
     }
 
-    pub async fn update_user(&self, user: User) -> Result<(), GatewayError> {
+    async fn count_users(&self) -> Result<usize, GatewayError> {
+        Ok(self.users.read().await.len())
+    }
+
+    /// Insert-or-replace keyed by `user.id`, keeping the email index in step
+    async fn update_user(&self, user: User) -> Result<(), GatewayError> {
         let mut users = self.users.write().await;
+        let mut by_email = self.users_by_email.write().await;
+
+        if let Some(previous) = users.get(&user.id) {
+            by_email.remove(&previous.email);
+        }
+        by_email.insert(user.email.clone(), user.id);
         users.insert(user.id, user);
-        self.save_to_file(&users).await
+        drop(by_email);
+
+        self.save_to_file(users).await
     }
 
-    async fn save_to_file(&self, users: &HashMap<Uuid, User>) -> Result<(), GatewayError> {
-        let file = UsersFile {
-            users: users.values().cloned().collect(),
+    async fn delete_user(&self, id: Uuid) -> Result<bool, GatewayError> {
+        let mut users = self.users.write().await;
+        let mut by_email = self.users_by_email.write().await;
+
+        let Some(user) = users.remove(&id) else {
+            return Ok(false);
         };
+        by_email.remove(&user.email);
+        drop(by_email);
 
-        let content = serde_json::to_string_pretty(&file)
-            .map_err(|e| GatewayError::Internal(format!("Failed to serialize users: {}", e)))?;
+        self.save_to_file(users).await?;
+        Ok(true)
+    }
 
-        fs::write(&self.file_path, content)
-            .map_err(|e| GatewayError::Internal(format!("Failed to write users: {}", e)))?;
+    async fn save_pending_user(&self, pending: PendingUser) -> Result<(), GatewayError> {
+        let users = self.users.write().await;
+        let mut pending_users = self.pending_users.write().await;
+        pending_users.retain(|_, p| !p.is_expired());
+        pending_users.insert(pending.id, pending);
+        drop(pending_users);
 
-        Ok(())
+        self.save_to_file(users).await
+    }
+
+    async fn get_pending_user(&self, id: Uuid) -> Result<Option<PendingUser>, GatewayError> {
+        Ok(self.pending_users.read().await.get(&id).cloned())
+    }
+
+    async fn delete_pending_user(&self, id: Uuid) -> Result<bool, GatewayError> {
+        let users = self.users.write().await;
+        if self.pending_users.write().await.remove(&id).is_none() {
+            return Ok(false);
+        }
+
+        self.save_to_file(users).await?;
+        Ok(true)
+    }
+
+    async fn backup(&self) -> Result<Vec<PathBuf>, GatewayError> {
+        // Not while a save is writing the file
+        let _write = self.write_lock.lock().await;
+        let path = Path::new(&self.file_path);
+        let backup = with_io_timeout(self.io_timeout, path, backup_file(path, self.backup_count)).await?;
+        Ok(backup.into_iter().collect())
+    }
+
+    async fn health_check(&self) -> Result<(), GatewayError> {
+        check_store_file(self.io_timeout, Path::new(&self.file_path)).await
     }
 }
 
@@ -103,128 +396,1236 @@ impl UserStore {
 
 #[derive(Debug, Serialize, Deserialize)]
 struct AgentsFile {
+    #[serde(default)]
+    schema_version: u32,
     agents: Vec<Agent>,
+    // Legacy: sessions used to live here; now only read for migration
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
     sessions: Vec<AgentSession>,
 }
 
+#[derive(Debug, Serialize, Deserialize)]
+struct SessionsFile {
+    sessions: Vec<AgentSession>,
+}
+
+const EMPTY_AGENTS: &str = r#"{"schema_version":2,"agents":[]}"#;
+const EMPTY_SESSIONS: &str = r#"{"sessions":[]}"#;
+
+/// Parse agents.json, decrypting it with `key` if it is encrypted; a missing file
+/// means no agents. Also returns any sessions left over from the old combined format.
+pub(super) fn read_agents_file<P: AsRef<Path>>(
+    path: P,
+    key: Option<&str>,
+) -> Result<(Vec<Agent>, Vec<AgentSession>), GatewayError> {
+    let (file, _): (AgentsFile, _) =
+        read_store_file(path.as_ref(), EMPTY_AGENTS, Some(&AGENTS_SCHEMA), key)?;
+    Ok((file.agents, file.sessions))
+}
+
+/// Parse sessions.json, decrypting it with `key` if it is encrypted; a missing file
+/// means no sessions
+pub(super) fn read_sessions_file<P: AsRef<Path>>(
+    path: P,
+    key: Option<&str>,
+) -> Result<Vec<AgentSession>, GatewayError> {
+    let (file, _): (SessionsFile, _) = read_store_file(path.as_ref(), EMPTY_SESSIONS, None, key)?;
+    Ok(file.sessions)
+}
+
+/// Persist after this many unflushed mutations even if the timer hasn't fired
+const DEFAULT_FLUSH_MAX_PENDING: u64 = 100;
+
 #[derive(Clone)]
 pub struct AgentStore {
     agents: Arc<RwLock<HashMap<Uuid, Agent>>>,
     sessions: Arc<RwLock<HashMap<String, AgentSession>>>,
-    file_path: String,
+    // Secondary index: agent_id -> session_ids, kept in sync with `sessions`
+    sessions_by_agent: Arc<RwLock<HashMap<Uuid, Vec<String>>>>,
+    // Secondary index: (owner_id, agent name) -> agent_id, kept in sync with `agents`
+    // and always locked after it
+    name_index: Arc<RwLock<HashMap<(Uuid, String), Uuid>>>,
+    agents_path: String,
+    sessions_path: String,
+    // === Write batching: mutations mark a file dirty, `flush` persists ===
+    agents_dirty: Arc<AtomicBool>,
+    sessions_dirty: Arc<AtomicBool>,
+    pending: Arc<AtomicU64>,
+    max_pending: u64,
+    flush_lock: Arc<Mutex<()>>,
+    writes: Arc<AtomicU64>,
+    io_timeout: Duration,
+    backup_count: usize,
+    // AGENTS_FILE_ENCRYPTION: both files are written encrypted with this key
+    encryption_key: Option<String>,
+    // Mutations not yet in a flushed snapshot, replayed on load
+    journal: Arc<Journal>,
+    // Stalls every file write, to exercise reads during a slow save
+    #[cfg(test)]
+    write_delay: Duration,
 }
 
 impl AgentStore {
-    pub fn load_from_file<P: AsRef<Path>>(path: P) -> Result<Self, GatewayError> {
-        let path_str = path.as_ref().to_string_lossy().to_string();
+    /// Load agents and sessions from their files, migrating the old combined agents.json
+    #[allow(dead_code)]
+    pub fn load_from_files<P: AsRef<Path>>(agents_path: P, sessions_path: P) -> Result<Self, GatewayError> {
+        Self::load_encrypted(agents_path, sessions_path, None)
+    }
 
-        let content = fs::read_to_string(&path)
-            .unwrap_or_else(|_| r#"{"agents":[],"sessions":[]}"#.to_string());
+    /// `load_from_files`, keeping both files encrypted with `encryption_key` when set.
+    /// Plaintext files are still read (and encrypted on the next save); encrypted
+    /// files fail to load without the key they were written with.
+    pub fn load_encrypted<P: AsRef<Path>>(
+        agents_path: P,
+        sessions_path: P,
+        encryption_key: Option<String>,
+    ) -> Result<Self, GatewayError> {
+        let agents_path = agents_path.as_ref().to_string_lossy().to_string();
+        let sessions_path = sessions_path.as_ref().to_string_lossy().to_string();
+        ensure_file(&agents_path, EMPTY_AGENTS)?;
+        ensure_file(&sessions_path, EMPTY_SESSIONS)?;
 
-        let file: AgentsFile = serde_json::from_str(&content)
-            .map_err(|e| GatewayError::Internal(format!("Failed to parse agents: {}", e)))?;
+        let key = encryption_key.as_deref();
+        let (AgentsFile { agents, sessions: legacy_sessions, .. }, agents_sealed) =
+            read_store_file(Path::new(&agents_path), EMPTY_AGENTS, Some(&AGENTS_SCHEMA), key)?;
+        let (SessionsFile { sessions: current_sessions }, sessions_sealed) =
+            read_store_file(Path::new(&sessions_path), EMPTY_SESSIONS, None, key)?;
+        let migrate = !legacy_sessions.is_empty();
 
-        let agents = file.agents.into_iter().map(|a| (a.id, a)).collect();
-        let sessions = file
-            .sessions
+        // Entries already in sessions.json win over leftovers in agents.json
+        let mut sessions: HashMap<String, AgentSession> = legacy_sessions
             .into_iter()
             .map(|s| (s.session_id.clone(), s))
             .collect();
+        for session in current_sessions {
+            sessions.insert(session.session_id.clone(), session);
+        }
 
-        Ok(Self {
+        // Mutations after the last flush; they stay journaled until the next one
+        let mut agents: HashMap<Uuid, Agent> = agents.into_iter().map(|a| (a.id, a)).collect();
+        let (journal, replay) = Journal::open(&agents_path, encryption_key.clone())?;
+        let (mut replayed_agents, mut replayed_sessions) = (false, false);
+        if !replay.is_empty() {
+            tracing::info!(entries = replay.len(), "Replaying agent store journal");
+        }
+        for entry in replay {
+            if entry.touches_agents() {
+                replayed_agents = true;
+            } else {
+                replayed_sessions = true;
+            }
+            entry.apply(&mut agents, &mut sessions);
+        }
+
+        let store = Self {
+            name_index: Arc::new(RwLock::new(index_by_name(agents.values()))),
             agents: Arc::new(RwLock::new(agents)),
+            sessions_by_agent: Arc::new(RwLock::new(index_by_agent(sessions.values()))),
             sessions: Arc::new(RwLock::new(sessions)),
-            file_path: path_str,
-        })
+            agents_path,
+            sessions_path,
+            // Plaintext files are rewritten encrypted by the first flush
+            agents_dirty: Arc::new(AtomicBool::new(
+                migrate || replayed_agents || (key.is_some() && !agents_sealed),
+            )),
+            sessions_dirty: Arc::new(AtomicBool::new(
+                migrate || replayed_sessions || (key.is_some() && !sessions_sealed),
+            )),
+            pending: Arc::new(AtomicU64::new(0)),
+            max_pending: DEFAULT_FLUSH_MAX_PENDING,
+            flush_lock: Arc::new(Mutex::new(())),
+            writes: Arc::new(AtomicU64::new(0)),
+            io_timeout: DEFAULT_FILE_IO_TIMEOUT,
+            backup_count: DEFAULT_BACKUP_COUNT,
+            encryption_key: encryption_key.clone(),
+            journal: Arc::new(journal),
+            #[cfg(test)]
+            write_delay: Duration::ZERO,
+        };
+
+        if migrate {
+            // Runs once at startup, so plain blocking writes are fine here.
+            // Sessions first, so a crash mid-migration never loses them;
+            // the combined file is kept as the agents backup.
+            let sessions = seal(
+                sessions_json(&store.sessions.try_read().expect("fresh store is unlocked"))?,
+                key,
+            )?;
+            let agents = seal(
+                agents_json(&store.agents.try_read().expect("fresh store is unlocked"))?,
+                key,
+            )?;
+            let agents_bak = backup_path(Path::new(&store.agents_path));
+            let migrate_files = || -> std::io::Result<()> {
+                fs::write(&store.sessions_path, sessions)?;
+                fs::copy(&store.agents_path, agents_bak)?;
+                fs::write(&store.agents_path, agents)
+            };
+            migrate_files().map_err(|e| {
+                GatewayError::Internal(format!("Failed to migrate {}: {}", store.agents_path, e))
+            })?;
+            store.agents_dirty.store(false, Ordering::Release);
+            store.sessions_dirty.store(false, Ordering::Release);
+            tracing::info!(path = %store.sessions_path, "Migrated sessions out of agents file");
+        }
+
+        Ok(store)
+    }
+
+    /// Flush inline once this many mutations are pending (minimum 1 = write-through)
+    pub fn with_max_pending(mut self, max_pending: u64) -> Self {
+        self.max_pending = max_pending.max(1);
+        self
+    }
+
+    /// Deadline for each agents.json / sessions.json write (FILE_IO_TIMEOUT_SECS)
+    pub fn with_io_timeout(mut self, timeout: Duration) -> Self {
+        self.io_timeout = timeout;
+        self
+    }
+
+    /// Timestamped backups of each file to keep (STORE_BACKUP_COUNT, 0 = none)
+    pub fn with_backup_count(mut self, backup_count: usize) -> Self {
+        self.backup_count = backup_count;
+        self
     }
 
-    pub async fn create_agent(&self, agent: Agent) -> Result<Agent, GatewayError> {
+    /// Insert or replace under the agents lock, keeping the name index in sync.
+    /// With `expected_version` this is update_agent's compare-and-swap.
+    async fn put_agent(&self, mut agent: Agent, expected_version: Option<u64>) -> Result<Agent, GatewayError> {
         let mut agents = self.agents.write().await;
-        agents.insert(agent.id, agent.clone());
-        self.save_to_file(&agents, &*self.sessions.read().await).await?;
+        if let Some(expected) = expected_version {
+            let stored = agents.get(&agent.id).ok_or_else(agent_not_found)?;
+            if stored.version != expected {
+                return Err(agent_conflict(agent.id));
+            }
+            agent.version = expected + 1;
+            agent.usage = stored.usage.clone();
+        }
+        self.write_ahead(&self.agents_dirty, JournalEntry::PutAgent(Box::new(agent.clone()))).await?;
+
+        let mut names = self.name_index.write().await;
+        let renamed_from = agents
+            .insert(agent.id, agent.clone())
+            .and_then(|previous| name_key(&previous))
+            .filter(|key| Some(key) != name_key(&agent).as_ref() && names.get(key) == Some(&agent.id));
+        if let Some(key) = renamed_from {
+            reindex_name(&mut names, &agents, key);
+        }
+        if let Some(key) = name_key(&agent) {
+            names.insert(key, agent.id);
+        }
         Ok(agent)
     }
 
-    pub async fn get_agent(&self, id: Uuid) -> Option<Agent> {
-        self.agents.read().await.get(&id).cloned()
+    /// Periodically persist pending mutations in the background
+    pub fn spawn_flusher(&self, interval: Duration) {
+        let store = self.clone();
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            loop {
+                ticker.tick().await;
+                if let Err(e) = store.flush().await {
+                    tracing::error!(error = ?e, "Failed to flush agent store");
+                }
+            }
+        });
     }
 
-    pub async fn update_agent(&self, agent: Agent) -> Result<(), GatewayError> {
-        let mut agents = self.agents.write().await;
-        agents.insert(agent.id, agent);
-        self.save_to_file(&agents, &*self.sessions.read().await).await
+    /// Write each file whose in-memory state changed since the last flush
+    pub async fn flush(&self) -> Result<(), GatewayError> {
+        let _guard = self.flush_lock.lock().await;
+        self.pending.store(0, Ordering::Release);
+        // Everything journaled so far is in memory, so in the snapshots written below
+        let checkpoint = self.journal.checkpoint().await;
+
+        // Each map is cloned under its read lock, then serialized and written after
+        // releasing it, so a slow save never holds back writers (or the readers queued
+        // behind them). `flush_lock` keeps the writes themselves in order.
+        if self.agents_dirty.swap(false, Ordering::AcqRel) {
+            let snapshot = self.agents.read().await.clone();
+            let content = agents_json(&snapshot);
+            let result = self.write_file(&self.agents_path, content).await;
+            if result.is_err() {
+                // Keep the changes queued for the next attempt
+                self.agents_dirty.store(true, Ordering::Release);
+            }
+            result?;
+        }
+
+        if self.sessions_dirty.swap(false, Ordering::AcqRel) {
+            let snapshot = self.sessions.read().await.clone();
+            let content = sessions_json(&snapshot);
+            let result = self.write_file(&self.sessions_path, content).await;
+            if result.is_err() {
+                self.sessions_dirty.store(true, Ordering::Release);
+            }
+            result?;
+        }
+
+        self.journal.compact(checkpoint, self.io_timeout).await
+    }
+
+    /// Flush, then back up agents.json and sessions.json (POST /admin/backup)
+    pub async fn backup(&self) -> Result<Vec<PathBuf>, GatewayError> {
+        self.flush().await?;
+        let _guard = self.flush_lock.lock().await;
+        let mut backups = Vec::new();
+        for path in [&self.agents_path, &self.sessions_path] {
+            let path = Path::new(path);
+            backups.extend(with_io_timeout(self.io_timeout, path, backup_file(path, self.backup_count)).await?);
+        }
+        Ok(backups)
     }
 
-    /// Delete an agent (for future agent management)
+    /// Number of file writes so far (for tests/metrics)
     #[allow(dead_code)]
-    pub async fn delete_agent(&self, id: Uuid) -> Result<bool, GatewayError> {
-        let mut agents = self.agents.write().await;
-        let removed = agents.remove(&id).is_some();
-        if removed {
-            self.save_to_file(&agents, &*self.sessions.read().await).await?;
+    pub fn write_count(&self) -> u64 {
+        self.writes.load(Ordering::Relaxed)
+    }
+
+    // === Journal a mutation before applying it ===
+    // Called under the map write locks, so the journal keeps their order. The file is
+    // marked dirty first: a flush that checkpoints past this entry always writes it.
+    async fn write_ahead(&self, dirty: &AtomicBool, entry: JournalEntry) -> Result<(), GatewayError> {
+        dirty.store(true, Ordering::Release);
+        self.journal.append(&entry, self.io_timeout).await
+    }
+
+    // === Count a journaled mutation; must be called after releasing the map write locks ===
+    async fn mark_dirty(&self) -> Result<(), GatewayError> {
+        if self.pending.fetch_add(1, Ordering::AcqRel) + 1 >= self.max_pending {
+            self.flush().await?;
         }
-        Ok(removed)
+        Ok(())
     }
 
-    pub async fn create_session(
+    async fn write_file(
         &self,
-        agent_id: Uuid,
-        ttl_secs: u64,
-    ) -> Result<AgentSession, GatewayError> {
-        let now = Utc::now();
-        let session = AgentSession {
-            session_id: Uuid::new_v4().to_string(),
-            agent_id,
-            created_at: now,
-            expires_at: now + Duration::seconds(ttl_secs as i64),
-            last_used_at: now,
-        };
-
-        let mut sessions = self.sessions.write().await;
-        sessions.insert(session.session_id.clone(), session.clone());
-
-        self.save_to_file(&*self.agents.read().await, &sessions).await?;
+        path: &str,
+        content: Result<String, GatewayError>,
+    ) -> Result<(), GatewayError> {
+        let content = seal(content?, self.encryption_key.as_deref())?;
+        #[cfg(test)]
+        tokio::time::sleep(self.write_delay).await;
+        write_with_backup(path, &content, self.backup_count, self.io_timeout).await?;
+        self.writes.fetch_add(1, Ordering::Relaxed);
+        Ok(())
+    }
+}
+
+fn agents_json(agents: &HashMap<Uuid, Agent>) -> Result<String, GatewayError> {
+    let file = AgentsFile {
+        schema_version: AGENTS_SCHEMA.current_version(),
+        agents: agents.values().cloned().collect(),
+        sessions: Vec::new(),
+    };
+    serde_json::to_string_pretty(&file)
+        .map_err(|e| GatewayError::Internal(format!("Failed to serialize agents: {}", e)))
+}
+
+fn sessions_json(sessions: &HashMap<String, AgentSession>) -> Result<String, GatewayError> {
+    let file = SessionsFile {
+        sessions: sessions.values().cloned().collect(),
+    };
+    serde_json::to_string_pretty(&file)
+        .map_err(|e| GatewayError::Internal(format!("Failed to serialize sessions: {}", e)))
+}
+
+fn index_by_agent<'a>(sessions: impl Iterator<Item = &'a AgentSession>) -> HashMap<Uuid, Vec<String>> {
+    let mut index: HashMap<Uuid, Vec<String>> = HashMap::new();
+    for session in sessions {
+        index
+            .entry(session.agent_id)
+            .or_default()
+            .push(session.session_id.clone());
+    }
+    index
+}
+
+fn name_key(agent: &Agent) -> Option<(Uuid, String)> {
+    agent.owner_id.map(|owner| (owner, agent.name.clone()))
+}
+
+/// When a rotation left several same-named agents, the most recently updated one wins
+fn index_by_name<'a>(agents: impl Iterator<Item = &'a Agent>) -> HashMap<(Uuid, String), Uuid> {
+    let mut agents: Vec<&Agent> = agents.collect();
+    agents.sort_by_key(|a| a.updated_at);
+    agents
+        .into_iter()
+        .filter_map(|a| name_key(a).map(|key| (key, a.id)))
+        .collect()
+}
+
+/// Point `key` at the newest remaining agent with that owner and name, if any
+fn reindex_name(
+    index: &mut HashMap<(Uuid, String), Uuid>,
+    agents: &HashMap<Uuid, Agent>,
+    key: (Uuid, String),
+) {
+    let newest = agents
+        .values()
+        .filter(|a| name_key(a).as_ref() == Some(&key))
+        .max_by_key(|a| a.updated_at)
+        .map(|a| a.id);
+    match newest {
+        Some(id) => index.insert(key, id),
+        None => index.remove(&key),
+    };
+}
+
+#[async_trait]
+impl AgentStoreTrait for AgentStore {
+    async fn get_agent(&self, id: Uuid) -> Result<Option<Agent>, GatewayError> {
+        Ok(self.agents.read().await.get(&id).cloned())
+    }
+
+    async fn get_agents(&self, ids: &[Uuid]) -> Result<Vec<Agent>, GatewayError> {
+        let agents = self.agents.read().await;
+        Ok(ids.iter().filter_map(|id| agents.get(id).cloned()).collect())
+    }
+
+    async fn list_agents(&self) -> Result<Vec<Agent>, GatewayError> {
+        Ok(self.agents.read().await.values().cloned().collect())
+    }
+
+    async fn count_agents_by_status(&self) -> Result<AgentCounts, GatewayError> {
+        Ok(AgentCounts::tally(self.agents.read().await.values()))
+    }
+
+    async fn list_agents_page(
+        &self,
+        offset: usize,
+        limit: usize,
+        filter: &AgentFilter,
+        sort: AgentSort,
+    ) -> Result<Page<Agent>, GatewayError> {
+        let agents = self.agents.read().await;
+        Ok(paginate_agents(agents.values().filter(|a| filter.matches(a)), sort, offset, limit))
+    }
+
+    async fn create_agent(&self, agent: Agent) -> Result<Agent, GatewayError> {
+        let agent = self.put_agent(agent, None).await?;
+        self.mark_dirty().await?;
+        Ok(agent)
+    }
+
+    async fn find_agent_by_name(
+        &self,
+        owner_id: Uuid,
+        name: &str,
+    ) -> Result<Option<Agent>, GatewayError> {
+        let agents = self.agents.read().await;
+        let names = self.name_index.read().await;
+        Ok(names
+            .get(&(owner_id, name.to_string()))
+            .and_then(|id| agents.get(id))
+            .cloned())
+    }
+
+    async fn create_or_get_agent(&self, agent: Agent) -> Result<Agent, GatewayError> {
+        {
+            let mut agents = self.agents.write().await;
+            let mut names = self.name_index.write().await;
+            let key = name_key(&agent);
+            if let Some(existing) = key.as_ref().and_then(|key| names.get(key)).and_then(|id| agents.get(id)) {
+                return Ok(existing.clone());
+            }
+            self.write_ahead(&self.agents_dirty, JournalEntry::PutAgent(Box::new(agent.clone()))).await?;
+            if let Some(key) = key {
+                names.insert(key, agent.id);
+            }
+            agents.insert(agent.id, agent.clone());
+        }
+        self.mark_dirty().await?;
+        Ok(agent)
+    }
+
+    async fn update_agent(&self, agent: Agent, expected_version: u64) -> Result<Agent, GatewayError> {
+        let agent = self.put_agent(agent, Some(expected_version)).await?;
+        self.mark_dirty().await?;
+        Ok(agent)
+    }
+
+    async fn delete_agent(&self, id: Uuid) -> Result<bool, GatewayError> {
+        self.delete_agents(&[id]).await.map(|removed| removed > 0)
+    }
+
+    async fn delete_agents(&self, ids: &[Uuid]) -> Result<usize, GatewayError> {
+        let removed = {
+            let mut agents = self.agents.write().await;
+            let mut names = self.name_index.write().await;
+            let present: Vec<Uuid> = ids.iter().copied().filter(|id| agents.contains_key(id)).collect();
+            if !present.is_empty() {
+                self.write_ahead(&self.agents_dirty, JournalEntry::DeleteAgents(present.clone())).await?;
+            }
+            let removed: Vec<Agent> = present.iter().filter_map(|id| agents.remove(id)).collect();
+            for agent in &removed {
+                if let Some(key) = name_key(agent).filter(|key| names.get(key) == Some(&agent.id)) {
+                    reindex_name(&mut names, &agents, key);
+                }
+            }
+            removed.len()
+        };
+        if removed > 0 {
+            self.mark_dirty().await?;
+        }
+        Ok(removed)
+    }
+
+    // Counts are saved with the next flush but not journaled: a crash loses at
+    // most one flush interval of them, and proxied requests stay off the journal
+    async fn record_usage(&self, agent_id: Uuid, service_id: &str) -> Result<(), GatewayError> {
+        if let Some(agent) = self.agents.write().await.get_mut(&agent_id) {
+            agent.usage.record(service_id);
+            self.agents_dirty.store(true, Ordering::Release);
+        }
+        Ok(())
+    }
+
+    async fn flush(&self) -> Result<(), GatewayError> {
+        AgentStore::flush(self).await
+    }
+
+    async fn backup(&self) -> Result<Vec<PathBuf>, GatewayError> {
+        AgentStore::backup(self).await
+    }
+
+    async fn health_check(&self) -> Result<(), GatewayError> {
+        check_store_file(self.io_timeout, Path::new(&self.agents_path)).await?;
+        check_store_file(self.io_timeout, Path::new(&self.sessions_path)).await
+    }
+}
+
+#[async_trait]
+impl SessionStoreTrait for AgentStore {
+    async fn get_session(&self, session_id: &str) -> Result<Option<AgentSession>, GatewayError> {
+        Ok(self.sessions.read().await.get(session_id).cloned())
+    }
+
+    async fn list_sessions(&self) -> Result<Vec<AgentSession>, GatewayError> {
+        Ok(self.sessions.read().await.values().cloned().collect())
+    }
+
+    async fn count_live_sessions(&self) -> Result<usize, GatewayError> {
+        Ok(self.sessions.read().await.values().filter(|s| !s.is_expired()).count())
+    }
+
+    async fn create_session(&self, session: AgentSession) -> Result<AgentSession, GatewayError> {
+        {
+            let mut sessions = self.sessions.write().await;
+            let mut index = self.sessions_by_agent.write().await;
+            self.write_ahead(&self.sessions_dirty, JournalEntry::PutSession(session.clone())).await?;
+            if sessions
+                .insert(session.session_id.clone(), session.clone())
+                .is_none()
+            {
+                index
+                    .entry(session.agent_id)
+                    .or_default()
+                    .push(session.session_id.clone());
+            }
+        }
+        self.mark_dirty().await?;
         Ok(session)
     }
 
-    pub async fn get_session(&self, session_id: &str) -> Option<AgentSession> {
-        self.sessions.read().await.get(session_id).cloned()
+    async fn delete_session(&self, session_id: &str) -> Result<(), GatewayError> {
+        let removed = {
+            let mut sessions = self.sessions.write().await;
+            let mut index = self.sessions_by_agent.write().await;
+            if sessions.contains_key(session_id) {
+                let entry = JournalEntry::DeleteSessions(vec![session_id.to_string()]);
+                self.write_ahead(&self.sessions_dirty, entry).await?;
+            }
+            let removed = sessions.remove(session_id);
+            if let Some(session) = &removed {
+                if let Some(ids) = index.get_mut(&session.agent_id) {
+                    ids.retain(|id| id != session_id);
+                    if ids.is_empty() {
+                        index.remove(&session.agent_id);
+                    }
+                }
+            }
+            removed.is_some()
+        };
+        if removed {
+            self.mark_dirty().await?;
+        }
+        Ok(())
     }
 
-    pub async fn validate_session(&self, session_id: &str) -> Result<(AgentSession, Agent), GatewayError> {
-        let session = self
-            .get_session(session_id)
-            .await
-            .ok_or_else(|| GatewayError::Unauthorized("Invalid session".to_string()))?;
+    // Counted, evicted and inserted under the write locks, so concurrent logins
+    // of one agent can't both see room under the cap
+    async fn create_session_capped(
+        &self,
+        session: AgentSession,
+        max_sessions: u32,
+    ) -> Result<(AgentSession, usize), GatewayError> {
+        let evicted = {
+            let mut sessions = self.sessions.write().await;
+            let mut index = self.sessions_by_agent.write().await;
+            let current = index
+                .get(&session.agent_id)
+                .map(|ids| ids.iter().filter_map(|id| sessions.get(id).cloned()).collect())
+                .unwrap_or_default();
+            let (expired, evicted) = sessions_to_drop(current, max_sessions);
+            let dropped: Vec<String> = expired.into_iter().chain(evicted.iter().cloned()).collect();
+            if !dropped.is_empty() {
+                let entry = JournalEntry::DeleteSessions(dropped.clone());
+                self.write_ahead(&self.sessions_dirty, entry).await?;
+            }
+            self.write_ahead(&self.sessions_dirty, JournalEntry::PutSession(session.clone())).await?;
+            for session_id in &dropped {
+                sessions.remove(session_id);
+            }
+            let ids = index.entry(session.agent_id).or_default();
+            ids.retain(|id| !dropped.contains(id));
+            if sessions.insert(session.session_id.clone(), session.clone()).is_none() {
+                ids.push(session.session_id.clone());
+            }
+            evicted.len()
+        };
+        self.mark_dirty().await?;
+        Ok((session, evicted))
+    }
+
+    async fn sessions_for_agent(&self, agent_id: Uuid) -> Result<Vec<AgentSession>, GatewayError> {
+        let sessions = self.sessions.read().await;
+        let index = self.sessions_by_agent.read().await;
+        Ok(index
+            .get(&agent_id)
+            .map(|ids| ids.iter().filter_map(|id| sessions.get(id).cloned()).collect())
+            .unwrap_or_default())
+    }
+
+    async fn delete_sessions_for_agents(&self, agent_ids: &[Uuid]) -> Result<usize, GatewayError> {
+        let removed = {
+            let mut sessions = self.sessions.write().await;
+            let mut index = self.sessions_by_agent.write().await;
+            let session_ids: Vec<String> = agent_ids
+                .iter()
+                .filter_map(|agent_id| index.get(agent_id))
+                .flatten()
+                .filter(|session_id| sessions.contains_key(*session_id))
+                .cloned()
+                .collect();
+            if !session_ids.is_empty() {
+                let entry = JournalEntry::DeleteSessions(session_ids.clone());
+                self.write_ahead(&self.sessions_dirty, entry).await?;
+            }
+            for agent_id in agent_ids {
+                index.remove(agent_id);
+            }
+            session_ids.iter().filter(|id| sessions.remove(*id).is_some()).count()
+        };
+        if removed > 0 {
+            self.mark_dirty().await?;
+        }
+        Ok(removed)
+    }
+
+    // The maps are held only to remove the sessions; sessions.json is then
+    // written by `flush`, from a snapshot, after the locks are released
+    async fn delete_expired_sessions(&self) -> Result<usize, GatewayError> {
+        let removed = {
+            let mut sessions = self.sessions.write().await;
+            let mut index = self.sessions_by_agent.write().await;
+            let expired: Vec<(String, Uuid)> = sessions
+                .values()
+                .filter(|session| session.is_expired())
+                .map(|session| (session.session_id.clone(), session.agent_id))
+                .collect();
+            if !expired.is_empty() {
+                let ids = expired.iter().map(|(session_id, _)| session_id.clone()).collect();
+                self.write_ahead(&self.sessions_dirty, JournalEntry::DeleteSessions(ids)).await?;
+            }
+            for (session_id, agent_id) in &expired {
+                sessions.remove(session_id);
+                if let Some(ids) = index.get_mut(agent_id) {
+                    ids.retain(|id| id != session_id);
+                    if ids.is_empty() {
+                        index.remove(agent_id);
+                    }
+                }
+            }
+            expired.len()
+        };
+        if removed > 0 {
+            self.flush().await?;
+        }
+        Ok(removed)
+    }
+
+    // Journaled like any delete, then flushed, so a restart can't bring them back
+    async fn delete_all_sessions(&self) -> Result<usize, GatewayError> {
+        let removed = {
+            let mut sessions = self.sessions.write().await;
+            let mut index = self.sessions_by_agent.write().await;
+            if !sessions.is_empty() {
+                let ids = sessions.keys().cloned().collect();
+                self.write_ahead(&self.sessions_dirty, JournalEntry::DeleteSessions(ids)).await?;
+            }
+            let removed = sessions.len();
+            sessions.clear();
+            index.clear();
+            removed
+        };
+        if removed > 0 {
+            self.flush().await?;
+        }
+        Ok(removed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::auth::create_session;
+    use crate::storage::journal::append_raw;
+    use tempfile::TempDir;
+
+    fn store(dir: &TempDir, max_pending: u64) -> AgentStore {
+        AgentStore::load_from_files(dir.path().join("agents.json"), dir.path().join("sessions.json"))
+            .unwrap()
+            .with_max_pending(max_pending)
+    }
+
+    fn agent() -> Agent {
+        Agent::with_lifespan("a".to_string(), "".to_string(), 30)
+    }
+
+    #[tokio::test]
+    async fn test_rapid_sessions_are_batched() {
+        let dir = TempDir::new().unwrap();
+        let store = store(&dir, 10);
+        let agent = store.create_agent(agent()).await.unwrap();
+
+        for _ in 0..99 {
+            store.create_session(create_session(agent.id, 60)).await.unwrap();
+        }
+
+        // 100 mutations with a threshold of 10 -> one flush per 10; only the
+        // first flush includes the agent, the rest only rewrite sessions.json
+        assert_eq!(store.write_count(), 11);
+    }
+
+    #[tokio::test]
+    async fn test_reads_see_unflushed_state_and_flush_persists() {
+        let dir = TempDir::new().unwrap();
+        let store = store(&dir, 1_000);
+        let agent = store.create_agent(agent()).await.unwrap();
+        let session = store.create_session(create_session(agent.id, 60)).await.unwrap();
+
+        assert_eq!(store.write_count(), 0);
+        assert!(store.get_session(&session.session_id).await.unwrap().is_some());
+
+        store.flush().await.unwrap();
+        store.flush().await.unwrap(); // clean: no further writes
+        assert_eq!(store.write_count(), 2);
+
+        let reloaded = self::store(&dir, 1_000);
+        assert!(reloaded.get_agent(agent.id).await.unwrap().is_some());
+        assert!(reloaded.get_session(&session.session_id).await.unwrap().is_some());
+    }
+
+    #[tokio::test]
+    async fn test_reads_and_writes_proceed_during_slow_save() {
+        let dir = TempDir::new().unwrap();
+        let mut store = store(&dir, 1000);
+        store.write_delay = Duration::from_millis(500);
+        let agent = store.create_agent(agent()).await.unwrap();
+        let session = store.create_session(create_session(agent.id, 3600)).await.unwrap();
+
+        let saving = tokio::spawn({
+            let store = store.clone();
+            async move { store.flush().await }
+        });
+        tokio::time::sleep(Duration::from_millis(50)).await;
+
+        let started = std::time::Instant::now();
+        for _ in 0..100 {
+            assert!(store.get_session(&session.session_id).await.unwrap().is_some());
+            assert!(store.get_agent(agent.id).await.unwrap().is_some());
+        }
+        let created = store.create_session(create_session(agent.id, 3600)).await.unwrap();
+        store.delete_session(&created.session_id).await.unwrap();
+        let elapsed = started.elapsed();
+
+        assert!(!saving.is_finished(), "save should still be in progress");
+        assert!(elapsed < Duration::from_millis(250), "lookups waited on the save: {:?}", elapsed);
+        saving.await.unwrap().unwrap();
+        assert_eq!(store.write_count(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_usage_survives_updates_and_reload() {
+        let dir = TempDir::new().unwrap();
+        let store = store(&dir, 1_000);
+        let agent = store.create_agent(agent()).await.unwrap();
+        store.record_usage(agent.id, "billing").await.unwrap();
+        store.record_usage(agent.id, "search").await.unwrap();
+
+        // A grant/revoke from a copy read before the requests keeps their counts
+        let updated = store.update_agent(agent.clone(), agent.version).await.unwrap();
+        assert_eq!(updated.usage.total_requests, 2);
+        store.flush().await.unwrap();
+
+        let reloaded = self::store(&dir, 1_000).get_agent(agent.id).await.unwrap().unwrap();
+        assert_eq!(reloaded.usage, updated.usage);
+        assert_eq!(reloaded.usage.requests_by_service["billing"], 1);
+    }
+
+    #[tokio::test]
+    async fn test_migrates_combined_agents_file() {
+        let dir = TempDir::new().unwrap();
+        let agent = agent();
+        let session = create_session(agent.id, 60);
+        fs::write(
+            dir.path().join("agents.json"),
+            serde_json::json!({ "agents": [agent], "sessions": [session] }).to_string(),
+        )
+        .unwrap();
+
+        let store = store(&dir, 1_000);
+        assert_eq!(store.sessions_for_agent(agent.id).await.unwrap().len(), 1);
+
+        let agents_json = fs::read_to_string(dir.path().join("agents.json")).unwrap();
+        assert!(!agents_json.contains("sessions"));
+        let sessions = read_sessions_file(dir.path().join("sessions.json"), None).unwrap();
+        assert_eq!(sessions[0].session_id, session.session_id);
+    }
+
+    #[tokio::test]
+    async fn test_journal_replays_unflushed_mutations_after_crash() {
+        let dir = TempDir::new().unwrap();
+        let store = store(&dir, 1_000);
+        let agent = store.create_agent(agent()).await.unwrap();
+        let mut renamed = agent.clone();
+        renamed.name = "renamed".to_string();
+        store.update_agent(renamed, agent.version).await.unwrap();
+        let kept = store.create_session(create_session(agent.id, 60)).await.unwrap();
+        let dropped = store.create_session(create_session(agent.id, 60)).await.unwrap();
+        store.delete_session(&dropped.session_id).await.unwrap();
+        let deleted = store.create_agent(self::agent()).await.unwrap();
+        store.delete_agent(deleted.id).await.unwrap();
+
+        // Crash: no snapshot was ever written
+        assert_eq!(store.write_count(), 0);
+        drop(store);
+
+        let reloaded = self::store(&dir, 1_000);
+        assert_eq!(reloaded.get_agent(agent.id).await.unwrap().unwrap().name, "renamed");
+        assert!(reloaded.get_agent(deleted.id).await.unwrap().is_none());
+        assert!(reloaded.get_session(&kept.session_id).await.unwrap().is_some());
+        assert!(reloaded.get_session(&dropped.session_id).await.unwrap().is_none());
+        assert_eq!(reloaded.sessions_for_agent(agent.id).await.unwrap().len(), 1);
+
+        // The next flush saves the replayed state and empties the journal
+        reloaded.flush().await.unwrap();
+        assert_eq!(reloaded.write_count(), 2);
+        let journal = dir.path().join("agents.json.journal");
+        assert_eq!(fs::read_to_string(&journal).unwrap(), "");
+        let agents = read_agents_file(dir.path().join("agents.json"), None).unwrap().0;
+        assert_eq!(agents[0].name, "renamed");
+    }
+
+    #[tokio::test]
+    async fn test_torn_journal_line_is_dropped() {
+        let dir = TempDir::new().unwrap();
+        let store = store(&dir, 1_000);
+        let agent = store.create_agent(agent()).await.unwrap();
+        drop(store);
+        // Crash halfway through appending the next entry
+        let agents_path = dir.path().join("agents.json");
+        append_raw(&agents_path.to_string_lossy(), br#"{"op":"put_se"#);
+
+        let reloaded = self::store(&dir, 1_000);
+        assert!(reloaded.get_agent(agent.id).await.unwrap().is_some());
+        let session = reloaded.create_session(create_session(agent.id, 60)).await.unwrap();
+        drop(reloaded);
+        let reloaded = self::store(&dir, 1_000);
+        assert!(reloaded.get_session(&session.session_id).await.unwrap().is_some());
+    }
+
+    fn encrypted_store(dir: &TempDir, key: &str) -> Result<AgentStore, GatewayError> {
+        AgentStore::load_encrypted(
+            dir.path().join("agents.json"),
+            dir.path().join("sessions.json"),
+            Some(key.to_string()),
+        )
+    }
+
+    #[tokio::test]
+    async fn test_encrypted_files_hide_sessions_and_reload() {
+        let dir = TempDir::new().unwrap();
+        let key = "agents-file-key-32-chars-long!!!";
+        let store = encrypted_store(&dir, key).unwrap().with_max_pending(1);
+        let mut secret = agent();
+        secret.name = "payroll-bot".to_string();
+        let secret = store.create_agent(secret).await.unwrap();
+        let session = store.create_session(create_session(secret.id, 60)).await.unwrap();
+
+        for file in ["agents.json", "sessions.json"] {
+            let raw = fs::read_to_string(dir.path().join(file)).unwrap();
+            assert!(!raw.contains("payroll-bot") && !raw.contains(&session.session_id), "{}", file);
+            assert!(serde_json::from_str::<SessionsFile>(&raw).is_err());
+            assert!(serde_json::from_str::<AgentsFile>(&raw).is_err());
+        }
+
+        let reloaded = encrypted_store(&dir, key).unwrap();
+        assert_eq!(reloaded.get_agent(secret.id).await.unwrap().unwrap().name, "payroll-bot");
+        let found = reloaded.get_session(&session.session_id).await.unwrap().unwrap();
+        assert_eq!(found.agent_id, secret.id);
+        assert!(!found.is_expired());
+    }
+
+    #[tokio::test]
+    async fn test_plaintext_files_are_encrypted_on_first_flush() {
+        let dir = TempDir::new().unwrap();
+        let saved = store(&dir, 1).create_agent(agent()).await.unwrap();
+
+        let store = encrypted_store(&dir, "agents-file-key").unwrap();
+        assert!(store.get_agent(saved.id).await.unwrap().is_some());
+        store.flush().await.unwrap();
+        let raw = fs::read_to_string(dir.path().join("agents.json")).unwrap();
+        assert!(raw.contains(CIPHERTEXT_KEY) && !raw.contains(&saved.id.to_string()));
+    }
+
+    #[tokio::test]
+    async fn test_encrypted_files_need_the_right_key() {
+        let dir = TempDir::new().unwrap();
+        let store = encrypted_store(&dir, "right-key").unwrap().with_max_pending(1);
+        store.create_agent(agent()).await.unwrap();
+
+        let wrong = encrypted_store(&dir, "wrong-key");
+        assert!(matches!(wrong, Err(GatewayError::Internal(msg)) if msg.contains("Failed to decrypt")));
+        let missing = AgentStore::load_from_files(dir.path().join("agents.json"), dir.path().join("sessions.json"));
+        assert!(matches!(missing, Err(GatewayError::Internal(msg)) if msg.contains("AGENTS_FILE_ENCRYPTION")));
+        // The failed loads left the encrypted files alone
+        assert!(!dir.path().join("agents.json.corrupt").exists());
+    }
+
+    #[tokio::test]
+    async fn test_session_index_tracks_creates_and_deletes() {
+        let dir = TempDir::new().unwrap();
+        let store = store(&dir, 1_000);
+        let (a, b) = (agent(), agent());
+
+        let a1 = store.create_session(create_session(a.id, 60)).await.unwrap();
+        let a2 = store.create_session(create_session(a.id, 60)).await.unwrap();
+        store.create_session(create_session(b.id, 60)).await.unwrap();
+
+        assert_eq!(store.sessions_for_agent(a.id).await.unwrap().len(), 2);
+        assert_eq!(store.sessions_for_agent(b.id).await.unwrap().len(), 1);
+
+        store.delete_session(&a1.session_id).await.unwrap();
+        let remaining = store.sessions_for_agent(a.id).await.unwrap();
+        assert_eq!(remaining.len(), 1);
+        assert_eq!(remaining[0].session_id, a2.session_id);
+
+        store.delete_session(&a2.session_id).await.unwrap();
+        assert!(store.sessions_for_agent(a.id).await.unwrap().is_empty());
+        assert!(store.sessions_by_agent.read().await.get(&a.id).is_none());
+    }
+
+    #[tokio::test]
+    async fn test_capped_sessions_evict_the_oldest_and_drop_expired() {
+        let dir = TempDir::new().unwrap();
+        let store = store(&dir, 1_000);
+        let (a, b) = (agent(), agent());
+        let mut expired = create_session(a.id, 60);
+        expired.expires_at = chrono::Utc::now() - chrono::Duration::seconds(1);
+        store.create_session(expired.clone()).await.unwrap();
+        store.create_session(create_session(b.id, 60)).await.unwrap();
 
-        if session.is_expired() {
-            return Err(GatewayError::SessionExpired);
+        let mut created = Vec::new();
+        for age in [30, 10, 20] {
+            let mut session = create_session(a.id, 60);
+            session.created_at -= chrono::Duration::seconds(age);
+            let (session, evicted) = store.create_session_capped(session, 2).await.unwrap();
+            created.push((session, evicted));
         }
 
-        let agent = self
-            .get_agent(session.agent_id)
+        // The expired session went first, then the oldest live one (30s) made room
+        assert_eq!(created.iter().map(|(_, evicted)| *evicted).collect::<Vec<_>>(), [0, 0, 1]);
+        let mut remaining: Vec<_> = store
+            .sessions_for_agent(a.id)
             .await
-            .ok_or_else(|| GatewayError::Internal("Agent not found".to_string()))?;
+            .unwrap()
+            .into_iter()
+            .map(|session| session.session_id)
+            .collect();
+        remaining.sort();
+        let mut expected = vec![created[1].0.session_id.clone(), created[2].0.session_id.clone()];
+        expected.sort();
+        assert_eq!(remaining, expected);
+        assert_eq!(store.sessions_for_agent(b.id).await.unwrap().len(), 1);
 
-        Ok((session, agent))
+        // 0: no limit
+        store.create_session_capped(create_session(a.id, 60), 0).await.unwrap();
+        assert_eq!(store.sessions_for_agent(a.id).await.unwrap().len(), 3);
+        assert!(store.sessions_for_agent(a.id).await.unwrap().iter().all(|s| !s.is_expired()));
     }
 
-    async fn save_to_file(
-        &self,
-        agents: &HashMap<Uuid, Agent>,
-        sessions: &HashMap<String, AgentSession>,
-    ) -> Result<(), GatewayError> {
-        let file = AgentsFile {
-            agents: agents.values().cloned().collect(),
-            sessions: sessions.values().cloned().collect(),
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_concurrent_capped_sessions_hold_the_cap() {
+        let dir = TempDir::new().unwrap();
+        let store = Arc::new(store(&dir, 1_000));
+        let a = agent();
+        let logins: Vec<_> = (0..20)
+            .map(|_| {
+                let store = store.clone();
+                tokio::spawn(async move { store.create_session_capped(create_session(a.id, 60), 3).await.unwrap() })
+            })
+            .collect();
+        let mut evicted = 0;
+        for login in logins {
+            evicted += login.await.unwrap().1;
+        }
+
+        // Every login but the three kept evicted exactly one other
+        assert_eq!(evicted, 17);
+        assert_eq!(store.sessions_for_agent(a.id).await.unwrap().len(), 3);
+        assert_eq!(store.sessions_by_agent.read().await[&a.id].len(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_expired_sessions_survive_reload_until_cleanup() {
+        let dir = TempDir::new().unwrap();
+        let store = store(&dir, 1_000);
+        let a = store.create_agent(agent()).await.unwrap();
+        let live = store.create_session(create_session(a.id, 3600)).await.unwrap();
+        let mut expired = create_session(a.id, 60);
+        expired.expires_at = chrono::Utc::now() - chrono::Duration::seconds(1);
+        store.create_session(expired.clone()).await.unwrap();
+        store.flush().await.unwrap();
+
+        // Loading keeps what the file holds; only the cleanup removes it
+        let reloaded = self::store(&dir, 1_000);
+        assert!(reloaded.get_session(&expired.session_id).await.unwrap().is_some());
+        assert_eq!(reloaded.delete_expired_sessions().await.unwrap(), 1);
+        assert_eq!(reloaded.delete_expired_sessions().await.unwrap(), 0);
+        assert_eq!(reloaded.sessions_for_agent(a.id).await.unwrap().len(), 1);
+
+        // Written straight away, not left for the flusher
+        let reloaded = self::store(&dir, 1_000);
+        assert!(reloaded.get_session(&expired.session_id).await.unwrap().is_none());
+        assert!(reloaded.get_session(&live.session_id).await.unwrap().is_some());
+    }
+
+    #[tokio::test]
+    async fn test_delete_all_sessions_is_not_resurrected_by_reload() {
+        let dir = TempDir::new().unwrap();
+        let store = store(&dir, 1_000);
+        let a = store.create_agent(agent()).await.unwrap();
+        let b = store.create_agent(agent()).await.unwrap();
+        store.create_session(create_session(a.id, 3600)).await.unwrap();
+        store.create_session(create_session(b.id, 3600)).await.unwrap();
+        store.flush().await.unwrap();
+
+        assert_eq!(store.delete_all_sessions().await.unwrap(), 2);
+        assert!(store.sessions_for_agent(a.id).await.unwrap().is_empty());
+        assert_eq!(store.delete_all_sessions().await.unwrap(), 0);
+
+        let reloaded = self::store(&dir, 1_000);
+        assert!(reloaded.list_sessions().await.unwrap().is_empty());
+        assert!(reloaded.get_agent(a.id).await.unwrap().is_some());
+    }
+
+    #[tokio::test]
+    async fn test_name_index_dedups_and_survives_rotation() {
+        let dir = TempDir::new().unwrap();
+        let store = store(&dir, 1_000);
+        let owner = Uuid::new_v4();
+        let mut first = agent();
+        first.owner_id = Some(owner);
+
+        let created = store.create_or_get_agent(first.clone()).await.unwrap();
+        let mut retry = agent();
+        retry.owner_id = Some(owner);
+        assert_eq!(store.create_or_get_agent(retry).await.unwrap().id, created.id);
+        assert_eq!(store.list_agents().await.unwrap().len(), 1);
+
+        // Rotation stores the agent under a new id and leaves the old entry behind
+        let old_id = first.id;
+        first.rotate();
+        store.create_agent(first.clone()).await.unwrap();
+        let found = store.find_agent_by_name(owner, "a").await.unwrap().unwrap();
+        assert_eq!(found.id, first.id);
+
+        // Deleting the indexed agent falls back to the remaining same-named one
+        store.delete_agent(first.id).await.unwrap();
+        let found = store.find_agent_by_name(owner, "a").await.unwrap().unwrap();
+        assert_eq!(found.id, old_id);
+
+        // Renaming frees the old name; the index is rebuilt on reload
+        let mut renamed = found.clone();
+        renamed.name = "b".to_string();
+        store.update_agent(renamed, found.version).await.unwrap();
+        store.flush().await.unwrap();
+        let reloaded = self::store(&dir, 1_000);
+        assert!(reloaded.find_agent_by_name(owner, "a").await.unwrap().is_none());
+        assert_eq!(reloaded.find_agent_by_name(owner, "b").await.unwrap().unwrap().id, old_id);
+    }
+
+    #[tokio::test]
+    async fn test_stale_update_conflicts_instead_of_overwriting() {
+        let dir = TempDir::new().unwrap();
+        let store = store(&dir, 1_000);
+        let agent = store.create_agent(agent()).await.unwrap();
+
+        // Both read version 0; the first write wins and bumps the version
+        let (mut grant, mut other) = (agent.clone(), agent.clone());
+        grant.add_service("bank".to_string());
+        other.add_service("payment".to_string());
+        assert_eq!(store.update_agent(grant, 0).await.unwrap().version, 1);
+        assert!(matches!(store.update_agent(other, 0).await, Err(GatewayError::Conflict(_))));
+
+        // Re-read and reapply: both changes survive
+        let mut fresh = store.get_agent(agent.id).await.unwrap().unwrap();
+        fresh.add_service("payment".to_string());
+        let saved = store.update_agent(fresh, 1).await.unwrap();
+        assert_eq!(saved.service_ids(), vec!["bank", "payment"]);
+
+        store.delete_agent(agent.id).await.unwrap();
+        assert!(matches!(store.update_agent(saved, 2).await, Err(GatewayError::NotFound(_))));
+    }
+
+    #[tokio::test]
+    async fn test_saves_keep_the_newest_backups() {
+        let dir = TempDir::new().unwrap();
+        let store = store(&dir, 1).with_backup_count(2);
+        let path = dir.path().join("agents.json");
+
+        // The first save backs up the empty file written on load
+        let first = store.create_agent(agent()).await.unwrap();
+        assert_eq!(list_backups(&path).len(), 1);
+
+        store.create_agent(agent()).await.unwrap();
+        store.create_agent(agent()).await.unwrap();
+        // Newest holds the two-agent save, the next the one-agent save
+        let backups = list_backups(&path);
+        assert_eq!(backups.len(), 2);
+        let (agents, _) = read_agents_file(&backups[0], None).unwrap();
+        assert_eq!(agents.len(), 2);
+        let (agents, _) = read_agents_file(&backups[1], None).unwrap();
+        assert_eq!(agents.len(), 1);
+        assert_eq!(agents[0].id, first.id);
+
+        // A manual backup copies the current state of both files
+        let manual = store.backup().await.unwrap();
+        assert_eq!(manual.len(), 2);
+        let (agents, _) = read_agents_file(&manual[0], None).unwrap();
+        assert_eq!(agents.len(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_corrupt_primary_recovers_from_backup() {
+        let dir = TempDir::new().unwrap();
+        let saved = {
+            let store = store(&dir, 1);
+            let saved = store.create_agent(agent()).await.unwrap();
+            store.create_agent(agent()).await.unwrap(); // backs up the first save
+            saved
         };
+        let path = dir.path().join("agents.json");
+        fs::write(&path, "{ garbage").unwrap();
 
-        let content = serde_json::to_string_pretty(&file)
-            .map_err(|e| GatewayError::Internal(format!("Failed to serialize agents: {}", e)))?;
+        let reloaded = store(&dir, 1);
+        assert!(reloaded.get_agent(saved.id).await.unwrap().is_some());
+        // Primary restored from the backup; the bad file is kept for inspection
+        assert!(read_agents_file(&path, None).is_ok());
+        assert!(dir.path().join("agents.json.corrupt").exists());
+    }
 
-        fs::write(&self.file_path, content)
-            .map_err(|e| GatewayError::Internal(format!("Failed to write agents: {}", e)))?;
+    #[tokio::test]
+    async fn test_corrupt_primary_and_backups_is_fatal() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("agents.json");
+        fs::write(&path, "not json").unwrap();
+        fs::write(backup_path(&path), "not json").unwrap();
+        // Backups from before timestamping are tried too
+        fs::write(dir.path().join("agents.json.bak"), "not json").unwrap();
 
-        Ok(())
+        let result =
+            AgentStore::load_from_files(&path, &dir.path().join("sessions.json"));
+        assert!(matches!(
+            result,
+            Err(GatewayError::Internal(msg)) if msg.contains("no usable backup")
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_newer_schema_is_fatal_without_touching_backups() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("agents.json");
+        fs::write(&path, r#"{"schema_version": 99, "agents": []}"#).unwrap();
+        let backup = backup_path(&path);
+        fs::write(&backup, EMPTY_AGENTS).unwrap();
+
+        let result =
+            AgentStore::load_from_files(&path, &dir.path().join("sessions.json"));
+        assert!(matches!(
+            result,
+            Err(GatewayError::Internal(msg)) if msg.contains("upgrade the gateway")
+        ));
+        assert!(!dir.path().join("agents.json.corrupt").exists());
+        assert_eq!(fs::read_to_string(&backup).unwrap(), EMPTY_AGENTS);
+    }
+
+    #[test]
+    fn test_new_files_are_written_at_current_schema() {
+        let users: serde_json::Value = serde_json::from_str(EMPTY_USERS).unwrap();
+        let agents: serde_json::Value = serde_json::from_str(EMPTY_AGENTS).unwrap();
+        assert_eq!(users["schema_version"], USERS_SCHEMA.current_version());
+        assert_eq!(agents["schema_version"], AGENTS_SCHEMA.current_version());
+        assert!(agents_json(&HashMap::new()).unwrap().contains("\"schema_version\": 2"));
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_concurrent_user_writes_lose_nothing() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("users.json");
+        let users = UserStore::load_from_file(&path).unwrap();
+
+        let tasks: Vec<_> = (0..50)
+            .map(|i| {
+                let users = users.clone();
+                tokio::spawn(async move {
+                    let user = User::new(format!("u{}", i), format!("u{}@example.com", i));
+                    users.create_user(user).await.unwrap()
+                })
+            })
+            .collect();
+        for task in tasks {
+            task.await.unwrap();
+        }
+
+        // The last write to land must be the last mutation: every user is on disk
+        assert_eq!(read_users_file(&path).unwrap().len(), 50);
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_concurrent_agent_writes_leave_valid_files() {
+        let dir = TempDir::new().unwrap();
+        let store = store(&dir, 1);
+
+        let tasks: Vec<_> = (0..50)
+            .map(|_| {
+                let store = store.clone();
+                tokio::spawn(async move {
+                    let agent = store.create_agent(agent()).await.unwrap();
+                    store.create_session(create_session(agent.id, 60)).await.unwrap();
+                })
+            })
+            .collect();
+        for task in tasks {
+            task.await.unwrap();
+        }
+
+        let (agents, _) = read_agents_file(dir.path().join("agents.json"), None).unwrap();
+        let sessions = read_sessions_file(dir.path().join("sessions.json"), None).unwrap();
+        assert_eq!((agents.len(), sessions.len()), (50, 50));
     }
 }
@@ -0,0 +1,233 @@
+//! Write-ahead journal for the file backend's agent store.
+//!
+//! agents.json and sessions.json are saved in batches, so a crash between flushes
+//! would lose the latest mutations. Each mutation is therefore appended to
+//! `<agents file>.journal` as one compact JSON line before it is applied. On load
+//! the journal is replayed over the snapshot; after a successful flush the lines
+//! the snapshot now covers are dropped. Lines are not fsynced: the journal
+//! survives a crash of the gateway, not of the machine.
+
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tokio::io::AsyncWriteExt;
+use tokio::sync::Mutex;
+use uuid::Uuid;
+
+use crate::error::GatewayError;
+use crate::gateway::{decrypt, encrypt};
+use crate::models::{Agent, AgentSession};
+use super::file_io::with_io_timeout;
+
+/// One mutation of the agent store. Replaying is idempotent: puts replace whole
+/// records and deletes of missing records do nothing.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "op", content = "data", rename_all = "snake_case")]
+pub(super) enum JournalEntry {
+    PutAgent(Box<Agent>),
+    DeleteAgents(Vec<Uuid>),
+    PutSession(AgentSession),
+    DeleteSessions(Vec<String>),
+}
+
+impl JournalEntry {
+    pub(super) fn touches_agents(&self) -> bool {
+        matches!(self, Self::PutAgent(_) | Self::DeleteAgents(_))
+    }
+
+    /// Apply to the in-memory maps (secondary indexes are rebuilt after replay)
+    pub(super) fn apply(
+        self,
+        agents: &mut HashMap<Uuid, Agent>,
+        sessions: &mut HashMap<String, AgentSession>,
+    ) {
+        match self {
+            Self::PutAgent(mut agent) => {
+                // Usage isn't journaled, so the snapshot's counts may be newer
+                if let Some(stored) = agents.get(&agent.id) {
+                    if stored.usage.total_requests > agent.usage.total_requests {
+                        agent.usage = stored.usage.clone();
+                    }
+                }
+                agents.insert(agent.id, *agent);
+            }
+            Self::DeleteAgents(ids) => ids.iter().for_each(|id| {
+                agents.remove(id);
+            }),
+            Self::PutSession(session) => {
+                sessions.insert(session.session_id.clone(), session);
+            }
+            Self::DeleteSessions(ids) => ids.iter().for_each(|id| {
+                sessions.remove(id);
+            }),
+        }
+    }
+}
+
+struct JournalFile {
+    file: tokio::fs::File,
+    /// Bytes written so far; everything before a checkpoint offset is applied in memory
+    len: u64,
+}
+
+pub(super) struct Journal {
+    path: PathBuf,
+    file: Mutex<JournalFile>,
+    // AGENTS_FILE_ENCRYPTION: each line is encrypted on its own
+    encryption_key: Option<String>,
+}
+
+impl Journal {
+    /// Open (or create) the journal next to `store_path` and read back its entries.
+    /// A torn last line from a crash mid-append is cut off; other unreadable lines
+    /// are skipped with an error log. Runs once at startup, so IO is blocking.
+    pub(super) fn open(
+        store_path: &str,
+        encryption_key: Option<String>,
+    ) -> Result<(Self, Vec<JournalEntry>), GatewayError> {
+        let path = PathBuf::from(format!("{}.journal", store_path));
+        let io_error =
+            |e: std::io::Error| GatewayError::Internal(format!("Failed to open {}: {}", path.display(), e));
+
+        let mut content = match fs::read_to_string(&path) {
+            Ok(content) => content,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => String::new(),
+            Err(e) => return Err(io_error(e)),
+        };
+        if !content.is_empty() && !content.ends_with('\n') {
+            let complete = content.rfind('\n').map_or(0, |i| i + 1);
+            tracing::warn!(path = %path.display(), "Dropping torn last line of agent store journal");
+            content.truncate(complete);
+            fs::write(&path, &content).map_err(io_error)?;
+        }
+
+        let key = encryption_key.as_deref();
+        let mut entries = Vec::new();
+        for (number, line) in content.lines().enumerate() {
+            match parse_line(line, &path, key)? {
+                Ok(entry) => entries.push(entry),
+                Err(e) => tracing::error!(
+                    path = %path.display(),
+                    line = number + 1,
+                    error = %e,
+                    "Skipping unreadable agent store journal entry"
+                ),
+            }
+        }
+
+        let file = fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .map_err(io_error)?;
+        let journal = Self {
+            file: Mutex::new(JournalFile {
+                file: tokio::fs::File::from_std(file),
+                len: content.len() as u64,
+            }),
+            path,
+            encryption_key,
+        };
+        Ok((journal, entries))
+    }
+
+    /// Append one entry; fails after `timeout`
+    pub(super) async fn append(&self, entry: &JournalEntry, timeout: Duration) -> Result<(), GatewayError> {
+        let mut line = serde_json::to_string(entry)
+            .map_err(|e| GatewayError::Internal(format!("Failed to serialize journal entry: {}", e)))?;
+        if let Some(key) = &self.encryption_key {
+            line = encrypt(&line, key)?;
+        }
+        line.push('\n');
+
+        let mut journal = self.file.lock().await;
+        let write = async {
+            journal.file.write_all(line.as_bytes()).await?;
+            journal.file.flush().await
+        };
+        with_io_timeout(timeout, &self.path, async {
+            write.await.map_err(|e| {
+                GatewayError::Internal(format!("Failed to append to {}: {}", self.path.display(), e))
+            })
+        })
+        .await?;
+        journal.len += line.len() as u64;
+        Ok(())
+    }
+
+    /// Offset up to which entries are applied in memory. Take it before writing a
+    /// snapshot, then `compact` to it once the snapshot is saved.
+    pub(super) async fn checkpoint(&self) -> u64 {
+        self.file.lock().await.len
+    }
+
+    /// Drop the entries before `checkpoint`, keeping any appended since
+    pub(super) async fn compact(&self, checkpoint: u64, timeout: Duration) -> Result<(), GatewayError> {
+        if checkpoint == 0 {
+            return Ok(());
+        }
+        let mut journal = self.file.lock().await;
+        let compact = async {
+            if journal.len == checkpoint {
+                journal.file.set_len(0).await?;
+                journal.len = 0;
+                return Ok(());
+            }
+            // Rare: mutations landed while the snapshot was written
+            let content = tokio::fs::read(&self.path).await?;
+            let rest = content.get(checkpoint as usize..).unwrap_or_default().to_vec();
+            let staged = PathBuf::from(format!("{}.tmp", self.path.display()));
+            tokio::fs::write(&staged, &rest).await?;
+            tokio::fs::rename(&staged, &self.path).await?;
+            journal.file = tokio::fs::OpenOptions::new().append(true).open(&self.path).await?;
+            journal.len = rest.len() as u64;
+            Ok(())
+        };
+        with_io_timeout(timeout, &self.path, async {
+            compact.await.map_err(|e: std::io::Error| {
+                GatewayError::Internal(format!("Failed to compact {}: {}", self.path.display(), e))
+            })
+        })
+        .await
+    }
+}
+
+/// Outer error: an encrypted line without the key or with the wrong one (fatal,
+/// like an undecryptable store file). Inner error: a damaged line.
+fn parse_line(
+    line: &str,
+    path: &Path,
+    key: Option<&str>,
+) -> Result<Result<JournalEntry, serde_json::Error>, GatewayError> {
+    // Lines written before AGENTS_FILE_ENCRYPTION was turned on are still plain JSON
+    if line.starts_with('{') {
+        return Ok(serde_json::from_str(line));
+    }
+    let Some(key) = key else {
+        return Err(GatewayError::Internal(format!(
+            "{} is encrypted; set AGENTS_FILE_ENCRYPTION=true with the ENCRYPTION_KEY it was written with",
+            path.display()
+        )));
+    };
+    let line = decrypt(line, key).map_err(|_| {
+        GatewayError::Internal(format!(
+            "Failed to decrypt {}: ENCRYPTION_KEY is not the key it was written with",
+            path.display()
+        ))
+    })?;
+    Ok(serde_json::from_str(&line))
+}
+
+/// Append raw bytes, for tests simulating a crash mid-write
+#[cfg(test)]
+pub(super) fn append_raw(store_path: &str, bytes: &[u8]) {
+    use std::io::Write;
+    let mut file = fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(format!("{}.journal", store_path))
+        .unwrap();
+    file.write_all(bytes).unwrap();
+}
@@ -0,0 +1,250 @@
+//! Paged, filtered listings (`GET /admin/agents`, `GET /admin/users`).
+//! Results are ordered by `created_at` (agents optionally by last activity), then
+//! id, so pages are stable.
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::cmp::Reverse;
+use uuid::Uuid;
+
+use crate::models::{Agent, User};
+
+type SortKey = (DateTime<Utc>, Uuid);
+
+/// Access key state, as of the listing
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AgentStatus {
+    Active,
+    Expired,
+}
+
+/// Agents per status (`GET /admin/overview`)
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct AgentCounts {
+    pub active: usize,
+    pub expired: usize,
+}
+
+impl AgentCounts {
+    pub fn tally<'a>(agents: impl Iterator<Item = &'a Agent>) -> Self {
+        agents.fold(Self::default(), |mut counts, agent| {
+            if agent.is_expired() {
+                counts.expired += 1;
+            } else {
+                counts.active += 1;
+            }
+            counts
+        })
+    }
+}
+
+/// Agent listing order
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AgentSort {
+    /// Oldest first
+    #[default]
+    Created,
+    /// Most recently active first; agents that never proxied a request last
+    LastActive,
+}
+
+/// Agents a listing returns; unset fields match everything
+#[derive(Debug, Clone, Default)]
+pub struct AgentFilter {
+    /// Case-insensitive substring of the agent name
+    pub name: Option<String>,
+    /// Agents allowed to call this service
+    pub service_id: Option<String>,
+    pub status: Option<AgentStatus>,
+    /// Agents whose key outlives this many days (`Agent::exceeds_lifespan`)
+    pub over_lifespan_days: Option<u32>,
+}
+
+impl AgentFilter {
+    pub fn matches(&self, agent: &Agent) -> bool {
+        self.name.as_deref().is_none_or(|name| contains_ignore_case(&agent.name, name))
+            && self.service_id.as_deref().is_none_or(|id| agent.can_access_service(id))
+            && self.status.is_none_or(|status| (status == AgentStatus::Expired) == agent.is_expired())
+            && self.over_lifespan_days.is_none_or(|days| agent.exceeds_lifespan(days))
+    }
+}
+
+/// Users a listing returns; unset fields match everything
+#[derive(Debug, Clone, Default)]
+pub struct UserFilter {
+    /// Case-insensitive substring of the username or email
+    pub name: Option<String>,
+}
+
+impl UserFilter {
+    pub fn matches(&self, user: &User) -> bool {
+        self.name.as_deref().is_none_or(|name| {
+            contains_ignore_case(&user.username, name) || contains_ignore_case(&user.email, name)
+        })
+    }
+}
+
+/// One page of a listing, with the number of matches across all pages
+#[derive(Debug, Serialize)]
+pub struct Page<T> {
+    pub items: Vec<T>,
+    pub total: usize,
+}
+
+/// Sort matching items by creation and cut out `limit` of them from `offset`.
+/// Sorting references first means only the returned page is cloned.
+pub(crate) fn paginate<'a, T: Clone + 'a, K: Ord>(
+    matching: impl Iterator<Item = &'a T>,
+    sort_key: impl Fn(&T) -> K,
+    offset: usize,
+    limit: usize,
+) -> Page<T> {
+    let mut matching: Vec<&T> = matching.collect();
+    matching.sort_by_key(|item| sort_key(item));
+    Page {
+        total: matching.len(),
+        items: matching.into_iter().skip(offset).take(limit).cloned().collect(),
+    }
+}
+
+pub(crate) fn paginate_agents<'a>(
+    matching: impl Iterator<Item = &'a Agent>,
+    sort: AgentSort,
+    offset: usize,
+    limit: usize,
+) -> Page<Agent> {
+    match sort {
+        AgentSort::Created => paginate(matching, agent_order, offset, limit),
+        AgentSort::LastActive => paginate(
+            matching,
+            |agent| (Reverse(agent.usage.last_active_at), agent_order(agent)),
+            offset,
+            limit,
+        ),
+    }
+}
+
+fn agent_order(agent: &Agent) -> SortKey {
+    (agent.created_at, agent.id)
+}
+
+pub(crate) fn user_order(user: &User) -> SortKey {
+    (user.created_at, user.id)
+}
+
+fn contains_ignore_case(haystack: &str, needle: &str) -> bool {
+    haystack.to_lowercase().contains(&needle.to_lowercase())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Duration;
+    use tempfile::TempDir;
+
+    use crate::storage::{AgentStore, AgentStoreTrait, InMemoryStore, UserStore, UserStoreTrait};
+
+    /// 50 agents created a minute apart: every 5th expired, even ones on "billing",
+    /// names "agent-00".."agent-49"
+    async fn seed(store: &dyn AgentStoreTrait) -> Vec<Uuid> {
+        let start = Utc::now() - Duration::days(1);
+        let mut ids = Vec::new();
+        for i in 0..50 {
+            let mut agent = Agent::new(format!("agent-{:02}", i), "".to_string());
+            agent.created_at = start + Duration::minutes(i);
+            agent.add_service(if i % 2 == 0 { "billing" } else { "search" }.to_string());
+            if i % 5 == 0 {
+                agent.expires_at = Utc::now() - Duration::hours(1);
+            }
+            ids.push(store.create_agent(agent).await.unwrap().id);
+        }
+        ids
+    }
+
+    async fn check_agent_pages(store: &dyn AgentStoreTrait) {
+        let ids = seed(store).await;
+        let all = AgentFilter::default();
+
+        let first = store.list_agents_page(0, 20, &all, AgentSort::Created).await.unwrap();
+        assert_eq!(first.total, 50);
+        assert_eq!(first.items.iter().map(|a| a.id).collect::<Vec<_>>(), ids[..20]);
+        let last = store.list_agents_page(40, 20, &all, AgentSort::Created).await.unwrap();
+        assert_eq!(last.items.iter().map(|a| a.id).collect::<Vec<_>>(), ids[40..]);
+        let past_end = store.list_agents_page(50, 20, &all, AgentSort::Created).await.unwrap();
+        assert!(past_end.items.is_empty() && past_end.total == 50);
+
+        let expired = AgentFilter { status: Some(AgentStatus::Expired), ..Default::default() };
+        let page = store.list_agents_page(0, 100, &expired, AgentSort::Created).await.unwrap();
+        assert_eq!(page.total, 10);
+        assert!(page.items.iter().all(Agent::is_expired));
+        let active = AgentFilter { status: Some(AgentStatus::Active), ..Default::default() };
+        assert_eq!(store.list_agents_page(0, 1, &active, AgentSort::Created).await.unwrap().total, 40);
+
+        // Even indexes on billing, and of those every 10th is expired
+        let billing_active = AgentFilter {
+            service_id: Some("billing".to_string()),
+            status: Some(AgentStatus::Active),
+            ..Default::default()
+        };
+        assert_eq!(store.list_agents_page(0, 100, &billing_active, AgentSort::Created).await.unwrap().total, 20);
+
+        let named = AgentFilter { name: Some("AGENT-1".to_string()), ..Default::default() };
+        let page = store.list_agents_page(5, 3, &named, AgentSort::Created).await.unwrap();
+        assert_eq!(page.total, 10);
+        let names: Vec<_> = page.items.iter().map(|a| a.name.as_str()).collect();
+        assert_eq!(names, ["agent-15", "agent-16", "agent-17"]);
+    }
+
+    #[tokio::test]
+    async fn test_agent_pages_on_file_store() {
+        let dir = TempDir::new().unwrap();
+        let store = AgentStore::load_from_files(dir.path().join("agents.json"), dir.path().join("sessions.json"))
+            .unwrap();
+        check_agent_pages(&store).await;
+    }
+
+    #[tokio::test]
+    async fn test_agent_pages_on_default_implementation() {
+        check_agent_pages(&InMemoryStore::new()).await;
+    }
+
+    #[tokio::test]
+    async fn test_agents_sort_by_last_activity() {
+        let store = InMemoryStore::new();
+        let ids = seed(&store).await;
+        for id in [ids[7], ids[3], ids[9]] {
+            store.record_usage(id, "billing").await.unwrap();
+            tokio::time::sleep(std::time::Duration::from_millis(2)).await;
+        }
+
+        let all = AgentFilter::default();
+        let page = store.list_agents_page(0, 5, &all, AgentSort::LastActive).await.unwrap();
+        let order: Vec<_> = page.items.iter().map(|a| a.id).collect();
+        // Most recent first, then the inactive ones oldest first
+        assert_eq!(order, [ids[9], ids[3], ids[7], ids[0], ids[1]]);
+    }
+
+    #[tokio::test]
+    async fn test_user_pages_filter_by_name_or_email() {
+        let dir = TempDir::new().unwrap();
+        let store = UserStore::load_from_file(dir.path().join("users.json")).unwrap();
+        let start = Utc::now() - Duration::days(1);
+        for i in 0..12 {
+            let domain = if i < 4 { "acme.com" } else { "example.com" };
+            let mut user = User::new(format!("user{}", i), format!("u{}@{}", i, domain));
+            user.created_at = start + Duration::minutes(i);
+            store.create_user(user).await.unwrap();
+        }
+
+        let acme = UserFilter { name: Some("ACME".to_string()) };
+        let page = store.list_users_page(2, 10, &acme).await.unwrap();
+        assert_eq!(page.total, 4);
+        let names: Vec<_> = page.items.iter().map(|u| u.username.as_str()).collect();
+        assert_eq!(names, ["user2", "user3"]);
+
+        let by_name = UserFilter { name: Some("user1".to_string()) };
+        assert_eq!(store.list_users_page(0, 10, &by_name).await.unwrap().total, 3);
+    }
+}
@@ -5,13 +5,19 @@ use tokio::sync::RwLock;
 use uuid::Uuid;
 
 use crate::error::GatewayError;
-use crate::models::{Agent, AgentSession, ServiceCredential};
-use super::traits::{AgentStoreTrait, CredentialStoreTrait, SessionStoreTrait};
+use crate::models::{Agent, AgentSession, PendingUser, ServiceCredential, User};
+use super::listing::AgentCounts;
+use super::traits::{
+    agent_conflict, agent_not_found, sessions_to_drop, AgentStoreTrait, CredentialStoreTrait, SessionStoreTrait,
+    UserStoreTrait,
+};
 
 /// In-memory storage for development/testing
 #[allow(dead_code)]
 #[derive(Default)]
 pub struct InMemoryStore {
+    users: Arc<RwLock<HashMap<Uuid, User>>>,
+    pending_users: Arc<RwLock<HashMap<Uuid, PendingUser>>>,
     agents: Arc<RwLock<HashMap<Uuid, Agent>>>,
     sessions: Arc<RwLock<HashMap<String, AgentSession>>>,
     credentials: Arc<RwLock<HashMap<(Uuid, String), ServiceCredential>>>,
@@ -24,21 +30,117 @@ impl InMemoryStore {
     }
 }
 
+#[async_trait]
+impl UserStoreTrait for InMemoryStore {
+    async fn create_user(&self, user: User) -> Result<User, GatewayError> {
+        let mut users = self.users.write().await;
+        if users.values().any(|u| u.email == user.email) {
+            return Err(GatewayError::BadRequest("Email already registered".to_string()));
+        }
+        users.insert(user.id, user.clone());
+        Ok(user)
+    }
+
+    async fn get_user(&self, id: Uuid) -> Result<Option<User>, GatewayError> {
+        Ok(self.users.read().await.get(&id).cloned())
+    }
+
+    async fn get_user_by_email(&self, email: &str) -> Result<Option<User>, GatewayError> {
+        Ok(self.users.read().await.values().find(|u| u.email == email).cloned())
+    }
+
+    async fn list_users(&self) -> Result<Vec<User>, GatewayError> {
+        Ok(self.users.read().await.values().cloned().collect())
+    }
+
+    async fn count_users(&self) -> Result<usize, GatewayError> {
+        Ok(self.users.read().await.len())
+    }
+
+    async fn update_user(&self, user: User) -> Result<(), GatewayError> {
+        self.users.write().await.insert(user.id, user);
+        Ok(())
+    }
+
+    async fn delete_user(&self, id: Uuid) -> Result<bool, GatewayError> {
+        Ok(self.users.write().await.remove(&id).is_some())
+    }
+
+    async fn save_pending_user(&self, pending: PendingUser) -> Result<(), GatewayError> {
+        let mut pending_users = self.pending_users.write().await;
+        pending_users.retain(|_, p| !p.is_expired());
+        pending_users.insert(pending.id, pending);
+        Ok(())
+    }
+
+    async fn get_pending_user(&self, id: Uuid) -> Result<Option<PendingUser>, GatewayError> {
+        Ok(self.pending_users.read().await.get(&id).cloned())
+    }
+
+    async fn delete_pending_user(&self, id: Uuid) -> Result<bool, GatewayError> {
+        Ok(self.pending_users.write().await.remove(&id).is_some())
+    }
+}
+
 #[async_trait]
 impl AgentStoreTrait for InMemoryStore {
     async fn get_agent(&self, id: Uuid) -> Result<Option<Agent>, GatewayError> {
         Ok(self.agents.read().await.get(&id).cloned())
     }
 
+    async fn get_agents(&self, ids: &[Uuid]) -> Result<Vec<Agent>, GatewayError> {
+        let agents = self.agents.read().await;
+        Ok(ids.iter().filter_map(|id| agents.get(id).cloned()).collect())
+    }
+
+    async fn list_agents(&self) -> Result<Vec<Agent>, GatewayError> {
+        Ok(self.agents.read().await.values().cloned().collect())
+    }
+
+    async fn count_agents_by_status(&self) -> Result<AgentCounts, GatewayError> {
+        Ok(AgentCounts::tally(self.agents.read().await.values()))
+    }
+
     async fn create_agent(&self, agent: Agent) -> Result<Agent, GatewayError> {
         self.agents.write().await.insert(agent.id, agent.clone());
         Ok(agent)
     }
 
-    async fn delete_agent(&self, id: Uuid) -> Result<(), GatewayError> {
-        self.agents.write().await.remove(&id);
+    async fn create_or_get_agent(&self, agent: Agent) -> Result<Agent, GatewayError> {
+        let mut agents = self.agents.write().await;
+        let existing = agents
+            .values()
+            .filter(|a| a.owner_id.is_some() && a.owner_id == agent.owner_id && a.name == agent.name)
+            .max_by_key(|a| a.updated_at);
+        if let Some(existing) = existing {
+            return Ok(existing.clone());
+        }
+        agents.insert(agent.id, agent.clone());
+        Ok(agent)
+    }
+
+    async fn update_agent(&self, mut agent: Agent, expected_version: u64) -> Result<Agent, GatewayError> {
+        let mut agents = self.agents.write().await;
+        let stored = agents.get(&agent.id).ok_or_else(agent_not_found)?;
+        if stored.version != expected_version {
+            return Err(agent_conflict(agent.id));
+        }
+        agent.version = expected_version + 1;
+        agent.usage = stored.usage.clone();
+        agents.insert(agent.id, agent.clone());
+        Ok(agent)
+    }
+
+    async fn record_usage(&self, agent_id: Uuid, service_id: &str) -> Result<(), GatewayError> {
+        if let Some(agent) = self.agents.write().await.get_mut(&agent_id) {
+            agent.usage.record(service_id);
+        }
         Ok(())
     }
+
+    async fn delete_agent(&self, id: Uuid) -> Result<bool, GatewayError> {
+        Ok(self.agents.write().await.remove(&id).is_some())
+    }
 }
 
 #[async_trait]
@@ -47,6 +149,14 @@ impl SessionStoreTrait for InMemoryStore {
         Ok(self.sessions.read().await.get(session_id).cloned())
     }
 
+    async fn list_sessions(&self) -> Result<Vec<AgentSession>, GatewayError> {
+        Ok(self.sessions.read().await.values().cloned().collect())
+    }
+
+    async fn count_live_sessions(&self) -> Result<usize, GatewayError> {
+        Ok(self.sessions.read().await.values().filter(|s| !s.is_expired()).count())
+    }
+
     async fn create_session(&self, session: AgentSession) -> Result<AgentSession, GatewayError> {
         self.sessions.write().await.insert(session.session_id.clone(), session.clone());
         Ok(session)
@@ -56,6 +166,35 @@ impl SessionStoreTrait for InMemoryStore {
         self.sessions.write().await.remove(session_id);
         Ok(())
     }
+
+    async fn create_session_capped(
+        &self,
+        session: AgentSession,
+        max_sessions: u32,
+    ) -> Result<(AgentSession, usize), GatewayError> {
+        let mut sessions = self.sessions.write().await;
+        let current = sessions.values().filter(|s| s.agent_id == session.agent_id).cloned().collect();
+        let (expired, evicted) = sessions_to_drop(current, max_sessions);
+        for session_id in expired.iter().chain(&evicted) {
+            sessions.remove(session_id);
+        }
+        sessions.insert(session.session_id.clone(), session.clone());
+        Ok((session, evicted.len()))
+    }
+
+    async fn delete_sessions_for_agents(&self, agent_ids: &[Uuid]) -> Result<usize, GatewayError> {
+        let mut sessions = self.sessions.write().await;
+        let before = sessions.len();
+        sessions.retain(|_, s| !agent_ids.contains(&s.agent_id));
+        Ok(before - sessions.len())
+    }
+
+    async fn delete_all_sessions(&self) -> Result<usize, GatewayError> {
+        let mut sessions = self.sessions.write().await;
+        let removed = sessions.len();
+        sessions.clear();
+        Ok(removed)
+    }
 }
 
 #[async_trait]
@@ -80,4 +219,11 @@ impl CredentialStoreTrait for InMemoryStore {
         self.credentials.write().await.remove(&key);
         Ok(())
     }
+
+    async fn delete_credentials_for_agents(&self, agent_ids: &[Uuid]) -> Result<usize, GatewayError> {
+        let mut credentials = self.credentials.write().await;
+        let before = credentials.len();
+        credentials.retain(|(agent_id, _), _| !agent_ids.contains(agent_id));
+        Ok(before - credentials.len())
+    }
 }
@@ -1,9 +1,42 @@
+mod backup;
+mod file_io;
 mod file_store;
+mod journal;
+mod listing;
 mod memory;
+#[cfg(feature = "postgres")]
+mod postgres_store;
+mod prune;
+#[cfg(feature = "redis")]
+mod redis_store;
+mod schema;
+mod session_cache;
+mod snapshot;
+mod sqlite_store;
 mod traits;
 
+pub use backup::{backup_file, DEFAULT_BACKUP_COUNT};
+#[allow(unused_imports)]
+pub use backup::list_backups;
+pub use file_io::*;
 pub use file_store::{AgentStore, UserStore};
+pub use listing::{AgentCounts, AgentFilter, AgentSort, AgentStatus, UserFilter};
+pub use prune::{spawn_session_cleanup, PruneStores, PruneSummary};
+pub use schema::CREDENTIALS_SCHEMA;
+pub use session_cache::{CachedAgentStore, CachedSessionStore, SessionCache};
+pub use snapshot::*;
+pub use sqlite_store::SqliteStore;
+pub(crate) use sqlite_store::run_migrations;
+#[cfg(feature = "postgres")]
+pub use postgres_store::PostgresStore;
+#[cfg(feature = "redis")]
+pub use redis_store::RedisSessionStore;
+pub use traits::*;
 
-// Traits and memory store prepared for future abstraction
+// Returned by the paged listings; only named outside the binary
 #[allow(unused_imports)]
-pub use traits::*;
+pub use listing::Page;
+
+// Memory store prepared for tests
+#[allow(unused_imports)]
+pub use memory::*;
@@ -0,0 +1,596 @@
+//! PostgreSQL storage backend (STORAGE_BACKEND=postgres, built with `--features postgres`)
+//!
+//! Shared by every gateway replica. Uniqueness (user email, session id) is
+//! enforced by the database, so concurrent registrations can't race.
+
+use async_trait::async_trait;
+use chrono::Utc;
+use serde::de::DeserializeOwned;
+use sqlx::migrate::Migrator;
+use sqlx::postgres::{PgConnection, PgPool, PgPoolOptions};
+use sqlx::types::Json;
+use sqlx::Connection;
+use std::collections::HashMap;
+use uuid::Uuid;
+
+use crate::error::GatewayError;
+use crate::gateway::{decrypt, encrypt};
+use crate::models::{Agent, AgentSession, PendingUser, ServiceCredential, User};
+use super::listing::AgentCounts;
+use super::traits::{
+    agent_conflict, agent_not_found, sessions_to_drop, AgentStoreTrait, CredentialStoreTrait, SessionStoreTrait,
+    UserStoreTrait,
+};
+
+static MIGRATOR: Migrator = sqlx::migrate!("./migrations/postgres");
+
+/// The owner's newest agent with a given name (agents keep their model as JSONB in `data`)
+const AGENT_BY_NAME: &str = "SELECT data FROM agents
+     WHERE data->>'owner_id' = $1 AND data->>'name' = $2
+     ORDER BY data->>'updated_at' DESC LIMIT 1";
+
+#[derive(Clone)]
+pub struct PostgresStore {
+    pool: PgPool,
+    encryption_key: String,
+}
+
+impl PostgresStore {
+    /// Run pending migrations, then build a pool that connects on first use.
+    /// Must be called from inside a Tokio runtime (the pool's tasks run there).
+    pub fn connect_lazy(
+        database_url: &str,
+        max_connections: u32,
+        encryption_key: &str,
+    ) -> Result<Self, GatewayError> {
+        run_migrations_blocking(database_url)?;
+
+        let pool = PgPoolOptions::new()
+            .max_connections(max_connections)
+            .connect_lazy(database_url)
+            .map_err(db_error)?;
+
+        Ok(Self {
+            pool,
+            encryption_key: encryption_key.to_string(),
+        })
+    }
+
+    /// Connect and migrate from async code (tests, tooling)
+    #[allow(dead_code)]
+    pub async fn connect(
+        database_url: &str,
+        max_connections: u32,
+        encryption_key: &str,
+    ) -> Result<Self, GatewayError> {
+        let pool = PgPoolOptions::new()
+            .max_connections(max_connections)
+            .connect(database_url)
+            .await
+            .map_err(db_error)?;
+        MIGRATOR.run(&pool).await.map_err(|e| {
+            GatewayError::Internal(format!("Failed to run Postgres migrations: {}", e))
+        })?;
+
+        Ok(Self {
+            pool,
+            encryption_key: encryption_key.to_string(),
+        })
+    }
+}
+
+impl PostgresStore {
+    // === Fetch the JSON `data` column of every row ===
+    async fn list_json<T: DeserializeOwned + Send + Unpin + 'static>(
+        &self,
+        sql: &'static str,
+    ) -> Result<Vec<T>, GatewayError> {
+        let rows: Vec<(Json<T>,)> = sqlx::query_as(sql)
+            .fetch_all(&self.pool)
+            .await
+            .map_err(db_error)?;
+        Ok(rows.into_iter().map(|(Json(value),)| value).collect())
+    }
+
+    // === One trivial query: the pool can reach the database ===
+    async fn ping(&self) -> Result<(), GatewayError> {
+        sqlx::query("SELECT 1").execute(&self.pool).await.map_err(db_error)?;
+        Ok(())
+    }
+
+    // === One statement over the whole id set, so the batch is atomic ===
+    async fn delete_by_agent(&self, sql: &'static str, ids: &[Uuid]) -> Result<usize, GatewayError> {
+        let result = sqlx::query(sql)
+            .bind(ids)
+            .execute(&self.pool)
+            .await
+            .map_err(db_error)?;
+        Ok(result.rows_affected() as usize)
+    }
+}
+
+// === Migrate over a throwaway connection on its own thread/runtime ===
+// AppState::new is synchronous; this keeps startup sync without blocking
+// (or panicking inside) the caller's runtime.
+fn run_migrations_blocking(database_url: &str) -> Result<(), GatewayError> {
+    let url = database_url.to_string();
+    std::thread::spawn(move || {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .map_err(|e| GatewayError::Internal(format!("Failed to start migration runtime: {}", e)))?;
+
+        runtime.block_on(async move {
+            let mut conn = PgConnection::connect(&url).await.map_err(db_error)?;
+            MIGRATOR.run(&mut conn).await.map_err(|e| {
+                GatewayError::Internal(format!("Failed to run Postgres migrations: {}", e))
+            })?;
+            conn.close().await.map_err(db_error)
+        })
+    })
+    .join()
+    .map_err(|_| GatewayError::Internal("Postgres migration thread panicked".to_string()))?
+}
+
+fn db_error(e: sqlx::Error) -> GatewayError {
+    GatewayError::Internal(format!("Database error: {}", e))
+}
+
+fn from_row<T: DeserializeOwned>(row: Option<(Json<T>,)>) -> Option<T> {
+    row.map(|(Json(value),)| value)
+}
+
+#[async_trait]
+impl UserStoreTrait for PostgresStore {
+    async fn create_user(&self, user: User) -> Result<User, GatewayError> {
+        let result = sqlx::query("INSERT INTO users (id, email, data) VALUES ($1, $2, $3)")
+            .bind(user.id)
+            .bind(&user.email)
+            .bind(Json(&user))
+            .execute(&self.pool)
+            .await;
+
+        match result {
+            Ok(_) => Ok(user),
+            Err(sqlx::Error::Database(e)) if e.is_unique_violation() => {
+                Err(GatewayError::BadRequest("Email already registered".to_string()))
+            }
+            Err(e) => Err(db_error(e)),
+        }
+    }
+
+    async fn get_user(&self, id: Uuid) -> Result<Option<User>, GatewayError> {
+        let row = sqlx::query_as("SELECT data FROM users WHERE id = $1")
+            .bind(id)
+            .fetch_optional(&self.pool)
+            .await
+            .map_err(db_error)?;
+        Ok(from_row(row))
+    }
+
+    async fn get_user_by_email(&self, email: &str) -> Result<Option<User>, GatewayError> {
+        let row = sqlx::query_as("SELECT data FROM users WHERE email = $1")
+            .bind(email)
+            .fetch_optional(&self.pool)
+            .await
+            .map_err(db_error)?;
+        Ok(from_row(row))
+    }
+
+    async fn list_users(&self) -> Result<Vec<User>, GatewayError> {
+        self.list_json("SELECT data FROM users").await
+    }
+
+    async fn count_users(&self) -> Result<usize, GatewayError> {
+        let (count,): (i64,) = sqlx::query_as("SELECT COUNT(*) FROM users")
+            .fetch_one(&self.pool)
+            .await
+            .map_err(db_error)?;
+        Ok(count as usize)
+    }
+
+    async fn update_user(&self, user: User) -> Result<(), GatewayError> {
+        sqlx::query(
+            "INSERT INTO users (id, email, data) VALUES ($1, $2, $3)
+             ON CONFLICT (id) DO UPDATE SET email = EXCLUDED.email, data = EXCLUDED.data",
+        )
+        .bind(user.id)
+        .bind(&user.email)
+        .bind(Json(&user))
+        .execute(&self.pool)
+        .await
+        .map_err(db_error)?;
+        Ok(())
+    }
+
+    async fn delete_user(&self, id: Uuid) -> Result<bool, GatewayError> {
+        let result = sqlx::query("DELETE FROM users WHERE id = $1")
+            .bind(id)
+            .execute(&self.pool)
+            .await
+            .map_err(db_error)?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn save_pending_user(&self, pending: PendingUser) -> Result<(), GatewayError> {
+        let mut tx = self.pool.begin().await.map_err(db_error)?;
+        sqlx::query("DELETE FROM pending_users WHERE expires_at < $1")
+            .bind(Utc::now())
+            .execute(&mut *tx)
+            .await
+            .map_err(db_error)?;
+        sqlx::query(
+            "INSERT INTO pending_users (id, expires_at, data) VALUES ($1, $2, $3)
+             ON CONFLICT (id) DO UPDATE SET expires_at = EXCLUDED.expires_at, data = EXCLUDED.data",
+        )
+        .bind(pending.id)
+        .bind(pending.expires_at)
+        .bind(Json(&pending))
+        .execute(&mut *tx)
+        .await
+        .map_err(db_error)?;
+        tx.commit().await.map_err(db_error)
+    }
+
+    async fn get_pending_user(&self, id: Uuid) -> Result<Option<PendingUser>, GatewayError> {
+        let row = sqlx::query_as("SELECT data FROM pending_users WHERE id = $1")
+            .bind(id)
+            .fetch_optional(&self.pool)
+            .await
+            .map_err(db_error)?;
+        Ok(from_row(row))
+    }
+
+    async fn delete_pending_user(&self, id: Uuid) -> Result<bool, GatewayError> {
+        let result = sqlx::query("DELETE FROM pending_users WHERE id = $1")
+            .bind(id)
+            .execute(&self.pool)
+            .await
+            .map_err(db_error)?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn health_check(&self) -> Result<(), GatewayError> {
+        self.ping().await
+    }
+}
+
+#[async_trait]
+impl AgentStoreTrait for PostgresStore {
+    async fn get_agent(&self, id: Uuid) -> Result<Option<Agent>, GatewayError> {
+        let row = sqlx::query_as("SELECT data FROM agents WHERE id = $1")
+            .bind(id)
+            .fetch_optional(&self.pool)
+            .await
+            .map_err(db_error)?;
+        Ok(from_row(row))
+    }
+
+    async fn get_agents(&self, ids: &[Uuid]) -> Result<Vec<Agent>, GatewayError> {
+        let rows: Vec<(Uuid, Json<Agent>)> =
+            sqlx::query_as("SELECT id, data FROM agents WHERE id = ANY($1)")
+                .bind(ids)
+                .fetch_all(&self.pool)
+                .await
+                .map_err(db_error)?;
+
+        let mut found: HashMap<Uuid, Agent> =
+            rows.into_iter().map(|(id, Json(agent))| (id, agent)).collect();
+        Ok(ids.iter().filter_map(|id| found.remove(id)).collect())
+    }
+
+    async fn list_agents(&self) -> Result<Vec<Agent>, GatewayError> {
+        self.list_json("SELECT data FROM agents").await
+    }
+
+    async fn count_agents_by_status(&self) -> Result<AgentCounts, GatewayError> {
+        let (active, expired): (i64, i64) = sqlx::query_as(
+            "SELECT COUNT(*) FILTER (WHERE (data->>'expires_at')::timestamptz >= now()),
+                    COUNT(*) FILTER (WHERE (data->>'expires_at')::timestamptz < now())
+             FROM agents",
+        )
+        .fetch_one(&self.pool)
+        .await
+        .map_err(db_error)?;
+        Ok(AgentCounts { active: active as usize, expired: expired as usize })
+    }
+
+    async fn create_agent(&self, agent: Agent) -> Result<Agent, GatewayError> {
+        sqlx::query(
+            "INSERT INTO agents (id, data) VALUES ($1, $2)
+             ON CONFLICT (id) DO UPDATE SET data = EXCLUDED.data",
+        )
+        .bind(agent.id)
+        .bind(Json(&agent))
+        .execute(&self.pool)
+        .await
+        .map_err(db_error)?;
+        Ok(agent)
+    }
+
+    async fn find_agent_by_name(
+        &self,
+        owner_id: Uuid,
+        name: &str,
+    ) -> Result<Option<Agent>, GatewayError> {
+        let row = sqlx::query_as(AGENT_BY_NAME)
+            .bind(owner_id.to_string())
+            .bind(name)
+            .fetch_optional(&self.pool)
+            .await
+            .map_err(db_error)?;
+        Ok(from_row(row))
+    }
+
+    async fn create_or_get_agent(&self, agent: Agent) -> Result<Agent, GatewayError> {
+        let Some(owner_id) = agent.owner_id else {
+            return self.create_agent(agent).await;
+        };
+
+        let mut tx = self.pool.begin().await.map_err(db_error)?;
+        // Serializes creators of the same (owner, name) across gateway instances;
+        // released when the transaction ends
+        sqlx::query("SELECT pg_advisory_xact_lock(hashtext($1))")
+            .bind(format!("{}/{}", owner_id, agent.name))
+            .execute(&mut *tx)
+            .await
+            .map_err(db_error)?;
+
+        let row: Option<(Json<Agent>,)> = sqlx::query_as(AGENT_BY_NAME)
+            .bind(owner_id.to_string())
+            .bind(&agent.name)
+            .fetch_optional(&mut *tx)
+            .await
+            .map_err(db_error)?;
+        if let Some(existing) = from_row(row) {
+            return Ok(existing);
+        }
+
+        sqlx::query("INSERT INTO agents (id, data) VALUES ($1, $2)")
+            .bind(agent.id)
+            .bind(Json(&agent))
+            .execute(&mut *tx)
+            .await
+            .map_err(db_error)?;
+        tx.commit().await.map_err(db_error)?;
+        Ok(agent)
+    }
+
+    async fn update_agent(&self, mut agent: Agent, expected_version: u64) -> Result<Agent, GatewayError> {
+        agent.version = expected_version + 1;
+        // Keeps the stored usage; record_usage is its only writer
+        let result = sqlx::query(
+            "UPDATE agents SET data = jsonb_set($2, '{usage}', COALESCE(data->'usage', $2->'usage'))
+             WHERE id = $1 AND COALESCE((data->>'version')::BIGINT, 0) = $3",
+        )
+        .bind(agent.id)
+        .bind(Json(&agent))
+        .bind(expected_version as i64)
+        .execute(&self.pool)
+        .await
+        .map_err(db_error)?;
+        if result.rows_affected() > 0 {
+            return Ok(agent);
+        }
+
+        // Tell a concurrent update apart from a deleted agent
+        match self.get_agent(agent.id).await? {
+            Some(_) => Err(agent_conflict(agent.id)),
+            None => Err(agent_not_found()),
+        }
+    }
+
+    async fn record_usage(&self, agent_id: Uuid, service_id: &str) -> Result<(), GatewayError> {
+        // One statement, so concurrent requests from several replicas never lose a count
+        sqlx::query(
+            "UPDATE agents SET data = jsonb_set(data, '{usage}', jsonb_build_object(
+                 'total_requests', COALESCE((data#>>'{usage,total_requests}')::BIGINT, 0) + 1,
+                 'last_active_at', $2::JSONB,
+                 'requests_by_service', COALESCE(data#>'{usage,requests_by_service}', '{}'::JSONB)
+                     || jsonb_build_object($3::TEXT,
+                         COALESCE((data#>>ARRAY['usage', 'requests_by_service', $3])::BIGINT, 0) + 1)))
+             WHERE id = $1",
+        )
+        .bind(agent_id)
+        .bind(Json(Utc::now()))
+        .bind(service_id)
+        .execute(&self.pool)
+        .await
+        .map_err(db_error)?;
+        Ok(())
+    }
+
+    async fn delete_agent(&self, id: Uuid) -> Result<bool, GatewayError> {
+        self.delete_agents(&[id]).await.map(|removed| removed > 0)
+    }
+
+    async fn delete_agents(&self, ids: &[Uuid]) -> Result<usize, GatewayError> {
+        self.delete_by_agent("DELETE FROM agents WHERE id = ANY($1)", ids)
+            .await
+    }
+
+    async fn health_check(&self) -> Result<(), GatewayError> {
+        self.ping().await
+    }
+}
+
+#[async_trait]
+impl SessionStoreTrait for PostgresStore {
+    async fn get_session(&self, session_id: &str) -> Result<Option<AgentSession>, GatewayError> {
+        let row = sqlx::query_as("SELECT data FROM sessions WHERE session_id = $1")
+            .bind(session_id)
+            .fetch_optional(&self.pool)
+            .await
+            .map_err(db_error)?;
+        Ok(from_row(row))
+    }
+
+    async fn list_sessions(&self) -> Result<Vec<AgentSession>, GatewayError> {
+        self.list_json("SELECT data FROM sessions").await
+    }
+
+    async fn count_live_sessions(&self) -> Result<usize, GatewayError> {
+        let (count,): (i64,) = sqlx::query_as("SELECT COUNT(*) FROM sessions WHERE expires_at >= now()")
+            .fetch_one(&self.pool)
+            .await
+            .map_err(db_error)?;
+        Ok(count as usize)
+    }
+
+    async fn create_session(&self, session: AgentSession) -> Result<AgentSession, GatewayError> {
+        sqlx::query(
+            "INSERT INTO sessions (session_id, agent_id, expires_at, data) VALUES ($1, $2, $3, $4)",
+        )
+        .bind(&session.session_id)
+        .bind(session.agent_id)
+        .bind(session.expires_at)
+        .bind(Json(&session))
+        .execute(&self.pool)
+        .await
+        .map_err(db_error)?;
+        Ok(session)
+    }
+
+    async fn delete_session(&self, session_id: &str) -> Result<(), GatewayError> {
+        sqlx::query("DELETE FROM sessions WHERE session_id = $1")
+            .bind(session_id)
+            .execute(&self.pool)
+            .await
+            .map_err(db_error)?;
+        Ok(())
+    }
+
+    async fn create_session_capped(
+        &self,
+        session: AgentSession,
+        max_sessions: u32,
+    ) -> Result<(AgentSession, usize), GatewayError> {
+        let mut tx = self.pool.begin().await.map_err(db_error)?;
+        // Serializes logins of the same agent across gateway instances, so two
+        // can't both see room under the cap; released when the transaction ends
+        sqlx::query("SELECT pg_advisory_xact_lock(hashtext($1))")
+            .bind(format!("sessions/{}", session.agent_id))
+            .execute(&mut *tx)
+            .await
+            .map_err(db_error)?;
+
+        let rows: Vec<(Json<AgentSession>,)> = sqlx::query_as("SELECT data FROM sessions WHERE agent_id = $1")
+            .bind(session.agent_id)
+            .fetch_all(&mut *tx)
+            .await
+            .map_err(db_error)?;
+        let current = rows.into_iter().map(|(Json(session),)| session).collect();
+        let (expired, evicted) = sessions_to_drop(current, max_sessions);
+        let dropped: Vec<&String> = expired.iter().chain(&evicted).collect();
+        if !dropped.is_empty() {
+            sqlx::query("DELETE FROM sessions WHERE session_id = ANY($1)")
+                .bind(dropped)
+                .execute(&mut *tx)
+                .await
+                .map_err(db_error)?;
+        }
+        sqlx::query(
+            "INSERT INTO sessions (session_id, agent_id, expires_at, data) VALUES ($1, $2, $3, $4)",
+        )
+        .bind(&session.session_id)
+        .bind(session.agent_id)
+        .bind(session.expires_at)
+        .bind(Json(&session))
+        .execute(&mut *tx)
+        .await
+        .map_err(db_error)?;
+        tx.commit().await.map_err(db_error)?;
+        Ok((session, evicted.len()))
+    }
+
+    async fn sessions_for_agent(&self, agent_id: Uuid) -> Result<Vec<AgentSession>, GatewayError> {
+        let rows: Vec<(Json<AgentSession>,)> = sqlx::query_as("SELECT data FROM sessions WHERE agent_id = $1")
+            .bind(agent_id)
+            .fetch_all(&self.pool)
+            .await
+            .map_err(db_error)?;
+        Ok(rows.into_iter().map(|(Json(session),)| session).collect())
+    }
+
+    async fn delete_sessions_for_agents(&self, agent_ids: &[Uuid]) -> Result<usize, GatewayError> {
+        self.delete_by_agent("DELETE FROM sessions WHERE agent_id = ANY($1)", agent_ids)
+            .await
+    }
+
+    async fn delete_all_sessions(&self) -> Result<usize, GatewayError> {
+        let result = sqlx::query("DELETE FROM sessions")
+            .execute(&self.pool)
+            .await
+            .map_err(db_error)?;
+        Ok(result.rows_affected() as usize)
+    }
+
+    async fn health_check(&self) -> Result<(), GatewayError> {
+        self.ping().await
+    }
+}
+
+#[async_trait]
+impl CredentialStoreTrait for PostgresStore {
+    async fn get_credential(
+        &self,
+        agent_id: Uuid,
+        service_id: &str,
+    ) -> Result<Option<ServiceCredential>, GatewayError> {
+        let row = sqlx::query_as(
+            "SELECT data FROM agent_credentials WHERE agent_id = $1 AND service_id = $2",
+        )
+        .bind(agent_id)
+        .bind(service_id)
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(db_error)?;
+
+        // Tokens are encrypted at rest, same as credentials.json
+        from_row::<ServiceCredential>(row)
+            .map(|mut c| {
+                c.access_token = decrypt(&c.access_token, &self.encryption_key)?;
+                c.refresh_token = c
+                    .refresh_token
+                    .map(|rt| decrypt(&rt, &self.encryption_key))
+                    .transpose()?;
+                Ok(c)
+            })
+            .transpose()
+    }
+
+    async fn store_credential(&self, mut credential: ServiceCredential) -> Result<(), GatewayError> {
+        credential.access_token = encrypt(&credential.access_token, &self.encryption_key)?;
+        credential.refresh_token = credential
+            .refresh_token
+            .map(|rt| encrypt(&rt, &self.encryption_key))
+            .transpose()?;
+
+        sqlx::query(
+            "INSERT INTO agent_credentials (agent_id, service_id, data) VALUES ($1, $2, $3)
+             ON CONFLICT (agent_id, service_id) DO UPDATE SET data = EXCLUDED.data",
+        )
+        .bind(credential.agent_id)
+        .bind(&credential.service_id)
+        .bind(Json(&credential))
+        .execute(&self.pool)
+        .await
+        .map_err(db_error)?;
+        Ok(())
+    }
+
+    async fn delete_credential(&self, agent_id: Uuid, service_id: &str) -> Result<(), GatewayError> {
+        sqlx::query("DELETE FROM agent_credentials WHERE agent_id = $1 AND service_id = $2")
+            .bind(agent_id)
+            .bind(service_id)
+            .execute(&self.pool)
+            .await
+            .map_err(db_error)?;
+        Ok(())
+    }
+
+    async fn delete_credentials_for_agents(&self, agent_ids: &[Uuid]) -> Result<usize, GatewayError> {
+        self.delete_by_agent("DELETE FROM agent_credentials WHERE agent_id = ANY($1)", agent_ids)
+            .await
+    }
+}
@@ -0,0 +1,184 @@
+//! Removal of long-expired agents and the references left pointing at them
+//! (`STARTUP_PRUNE=true`, `POST /admin/maintenance/prune`), and the periodic
+//! sweep of expired sessions (`SESSION_CLEANUP_INTERVAL_SECS`)
+
+use chrono::{Duration, Utc};
+use serde::Serialize;
+use std::collections::HashSet;
+use std::sync::Arc;
+use uuid::Uuid;
+
+use crate::error::GatewayError;
+use super::traits::{AgentStoreTrait, SessionStoreTrait, UserStoreTrait};
+
+#[derive(Debug, Default, Serialize)]
+pub struct PruneSummary {
+    pub dry_run: bool,
+    /// Agents expired for longer than the grace period
+    pub agents: Vec<Uuid>,
+    /// Sessions of pruned or missing agents
+    pub sessions: usize,
+    /// Users whose agent list referenced a pruned or missing agent
+    pub users: usize,
+    /// Agent ids stripped from those users' agent lists
+    pub agent_refs: usize,
+}
+
+/// Delete expired sessions every `interval`, the first time one interval after startup
+pub fn spawn_session_cleanup(sessions: Arc<dyn SessionStoreTrait>, interval: std::time::Duration) {
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval_at(tokio::time::Instant::now() + interval, interval);
+        loop {
+            ticker.tick().await;
+            match sessions.delete_expired_sessions().await {
+                Ok(removed) => tracing::info!(removed, "Expired sessions cleaned up"),
+                Err(e) => tracing::error!(error = ?e, "Expired session cleanup failed"),
+            }
+        }
+    });
+}
+
+/// The stores a prune pass touches
+pub struct PruneStores<'a> {
+    pub users: &'a dyn UserStoreTrait,
+    pub agents: &'a dyn AgentStoreTrait,
+    pub sessions: &'a dyn SessionStoreTrait,
+}
+
+impl PruneStores<'_> {
+    /// Remove agents that expired more than `grace_days` ago, every session whose
+    /// agent is gone, and dangling ids in `User.agents`. With `dry_run` nothing is
+    /// changed; the summary reports what would be removed.
+    pub async fn prune(&self, grace_days: i64, dry_run: bool) -> Result<PruneSummary, GatewayError> {
+        let cutoff = Utc::now() - Duration::days(grace_days);
+        let (expired, live): (Vec<_>, Vec<_>) = self
+            .agents
+            .list_agents()
+            .await?
+            .into_iter()
+            .partition(|agent| agent.expires_at < cutoff);
+        let live: HashSet<Uuid> = live.into_iter().map(|agent| agent.id).collect();
+
+        let orphaned_sessions: Vec<_> = self
+            .sessions
+            .list_sessions()
+            .await?
+            .into_iter()
+            .filter(|session| !live.contains(&session.agent_id))
+            .collect();
+
+        let mut summary = PruneSummary {
+            dry_run,
+            agents: expired.iter().map(|agent| agent.id).collect(),
+            sessions: orphaned_sessions.len(),
+            ..Default::default()
+        };
+
+        if !dry_run {
+            // Sessions first so access stops before the agent disappears
+            let agent_ids: Vec<Uuid> = orphaned_sessions
+                .iter()
+                .map(|session| session.agent_id)
+                .collect::<HashSet<_>>()
+                .into_iter()
+                .collect();
+            summary.sessions = self.sessions.delete_sessions_for_agents(&agent_ids).await?;
+            self.agents.delete_agents(&summary.agents).await?;
+        }
+
+        for mut user in self.users.list_users().await? {
+            let before = user.agents.len();
+            user.agents.retain(|agent_id| live.contains(agent_id));
+            let stripped = before - user.agents.len();
+            if stripped == 0 {
+                continue;
+            }
+            summary.users += 1;
+            summary.agent_refs += stripped;
+            if !dry_run {
+                user.updated_at = Utc::now();
+                self.users.update_user(user).await?;
+            }
+        }
+
+        tracing::info!(
+            dry_run,
+            grace_days,
+            agents = summary.agents.len(),
+            sessions = summary.sessions,
+            users = summary.users,
+            agent_refs = summary.agent_refs,
+            "Pruned expired agents"
+        );
+        Ok(summary)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::auth::create_session;
+    use crate::models::{Agent, User};
+    use crate::storage::InMemoryStore;
+
+    fn agent(expired_days_ago: i64) -> Agent {
+        let mut agent = Agent::new("bot".to_string(), "".to_string());
+        agent.expires_at = Utc::now() - Duration::days(expired_days_ago);
+        agent
+    }
+
+    /// One live agent, one recently expired, one long expired, and a user that
+    /// also references an agent that never existed
+    async fn seeded() -> (InMemoryStore, [Uuid; 3], Uuid) {
+        let store = InMemoryStore::new();
+        let live = store.create_agent(agent(-10)).await.unwrap().id;
+        let recent = store.create_agent(agent(2)).await.unwrap().id;
+        let old = store.create_agent(agent(90)).await.unwrap().id;
+        for id in [live, recent, old, old] {
+            store.create_session(create_session(id, 60)).await.unwrap();
+        }
+
+        let mut user = User::new("owner".to_string(), "owner@example.com".to_string());
+        user.agents = vec![live, recent, old, Uuid::new_v4()];
+        let user_id = store.create_user(user).await.unwrap().id;
+        (store, [live, recent, old], user_id)
+    }
+
+    fn stores(store: &InMemoryStore) -> PruneStores<'_> {
+        PruneStores { users: store, agents: store, sessions: store }
+    }
+
+    #[tokio::test]
+    async fn test_prune_removes_only_agents_past_grace() {
+        let (store, [live, recent, old], user_id) = seeded().await;
+
+        let summary = stores(&store).prune(7, false).await.unwrap();
+        assert_eq!(summary.agents, vec![old]);
+        assert_eq!(summary.sessions, 2);
+        assert_eq!((summary.users, summary.agent_refs), (1, 2));
+
+        assert!(store.get_agent(old).await.unwrap().is_none());
+        assert!(store.get_agent(live).await.unwrap().is_some());
+        assert!(store.get_agent(recent).await.unwrap().is_some());
+        assert_eq!(store.list_sessions().await.unwrap().len(), 2);
+        let user = store.get_user(user_id).await.unwrap().unwrap();
+        assert_eq!(user.agents, vec![live, recent]);
+
+        // Nothing left to do
+        let again = stores(&store).prune(7, false).await.unwrap();
+        assert!(again.agents.is_empty() && again.sessions == 0 && again.users == 0);
+    }
+
+    #[tokio::test]
+    async fn test_dry_run_reports_without_removing() {
+        let (store, _, user_id) = seeded().await;
+
+        let summary = stores(&store).prune(7, true).await.unwrap();
+        assert!(summary.dry_run);
+        assert_eq!((summary.agents.len(), summary.sessions, summary.agent_refs), (1, 2, 2));
+
+        assert_eq!(store.list_agents().await.unwrap().len(), 3);
+        assert_eq!(store.list_sessions().await.unwrap().len(), 4);
+        assert_eq!(store.get_user(user_id).await.unwrap().unwrap().agents.len(), 4);
+    }
+}
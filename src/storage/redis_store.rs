@@ -0,0 +1,204 @@
+//! Redis session store (SESSION_STORE=redis, built with `--features redis`)
+//!
+//! Sessions live at `session:{id}` with a TTL matching `expires_at`, so every
+//! replica sees the same sessions and Redis drops them on expiry. Each agent
+//! has a set `agent_sessions:{agent_id}` so its sessions can be revoked together.
+
+use async_trait::async_trait;
+use chrono::Utc;
+use redis::aio::ConnectionManager;
+use redis::{AsyncCommands, Client};
+use tokio::sync::OnceCell;
+use uuid::Uuid;
+
+use crate::error::GatewayError;
+use crate::models::AgentSession;
+use super::traits::SessionStoreTrait;
+
+pub struct RedisSessionStore {
+    client: Client,
+    // Multiplexed, auto-reconnecting; opened on first use so construction needs no runtime
+    conn: OnceCell<ConnectionManager>,
+}
+
+impl RedisSessionStore {
+    /// Validate the URL; the connection is established lazily
+    pub fn open(redis_url: &str) -> Result<Self, GatewayError> {
+        let client = Client::open(redis_url).map_err(redis_error)?;
+        Ok(Self {
+            client,
+            conn: OnceCell::new(),
+        })
+    }
+
+    async fn conn(&self) -> Result<ConnectionManager, GatewayError> {
+        self.conn
+            .get_or_try_init(|| ConnectionManager::new(self.client.clone()))
+            .await
+            .cloned()
+            .map_err(redis_error)
+    }
+}
+
+fn session_key(session_id: &str) -> String {
+    format!("session:{}", session_id)
+}
+
+fn agent_key(agent_id: Uuid) -> String {
+    format!("agent_sessions:{}", agent_id)
+}
+
+fn redis_error(e: redis::RedisError) -> GatewayError {
+    GatewayError::Internal(format!("Redis error: {}", e))
+}
+
+#[async_trait]
+impl SessionStoreTrait for RedisSessionStore {
+    /// Redis TTL removes expired sessions; `validate_session` still checks
+    /// `expires_at` in case the key outlives it by a few milliseconds
+    async fn get_session(&self, session_id: &str) -> Result<Option<AgentSession>, GatewayError> {
+        let data: Option<String> = self
+            .conn()
+            .await?
+            .get(session_key(session_id))
+            .await
+            .map_err(redis_error)?;
+
+        data.map(|json| {
+            serde_json::from_str(&json).map_err(|e| {
+                GatewayError::Internal(format!("Corrupt session {}: {}", session_id, e))
+            })
+        })
+        .transpose()
+    }
+
+    async fn list_sessions(&self) -> Result<Vec<AgentSession>, GatewayError> {
+        let mut conn = self.conn().await?;
+        let mut keys: Vec<String> = Vec::new();
+        {
+            let mut iter = conn
+                .scan_match::<_, String>("session:*")
+                .await
+                .map_err(redis_error)?;
+            while let Some(key) = iter.next_item().await {
+                keys.push(key);
+            }
+        }
+        if keys.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        // Keys can expire between SCAN and MGET
+        let values: Vec<Option<String>> = conn.mget(&keys).await.map_err(redis_error)?;
+        values
+            .into_iter()
+            .flatten()
+            .map(|json| {
+                serde_json::from_str(&json)
+                    .map_err(|e| GatewayError::Internal(format!("Corrupt session: {}", e)))
+            })
+            .collect()
+    }
+
+    async fn create_session(&self, session: AgentSession) -> Result<AgentSession, GatewayError> {
+        let ttl = (session.expires_at - Utc::now()).num_seconds();
+        if ttl <= 0 {
+            // Already expired: nothing worth storing
+            return Ok(session);
+        }
+        let ttl = ttl as u64;
+        let json = serde_json::to_string(&session)
+            .map_err(|e| GatewayError::Internal(format!("Failed to serialize session: {}", e)))?;
+
+        let mut conn = self.conn().await?;
+        let created: bool = redis::cmd("SET")
+            .arg(session_key(&session.session_id))
+            .arg(json)
+            .arg("NX")
+            .arg("EX")
+            .arg(ttl)
+            .query_async::<Option<String>>(&mut conn)
+            .await
+            .map_err(redis_error)?
+            .is_some();
+        if !created {
+            return Err(GatewayError::Internal("Session ID collision".to_string()));
+        }
+
+        // Sessions share one configured TTL, so the newest decides when the set can go
+        let agent_key = agent_key(session.agent_id);
+        redis::pipe()
+            .sadd(&agent_key, &session.session_id)
+            .ignore()
+            .expire(&agent_key, ttl as i64)
+            .ignore()
+            .query_async::<()>(&mut conn)
+            .await
+            .map_err(redis_error)?;
+
+        Ok(session)
+    }
+
+    async fn delete_session(&self, session_id: &str) -> Result<(), GatewayError> {
+        let Some(session) = self.get_session(session_id).await? else {
+            return Ok(());
+        };
+
+        redis::pipe()
+            .atomic()
+            .del(session_key(session_id))
+            .ignore()
+            .srem(agent_key(session.agent_id), session_id)
+            .ignore()
+            .query_async::<()>(&mut self.conn().await?)
+            .await
+            .map_err(redis_error)
+    }
+
+    async fn sessions_for_agent(&self, agent_id: Uuid) -> Result<Vec<AgentSession>, GatewayError> {
+        let mut conn = self.conn().await?;
+        let session_ids: Vec<String> = conn.smembers(agent_key(agent_id)).await.map_err(redis_error)?;
+        if session_ids.is_empty() {
+            return Ok(Vec::new());
+        }
+        // Expired members are gone from the keyspace already
+        let keys: Vec<String> = session_ids.iter().map(|id| session_key(id)).collect();
+        let values: Vec<Option<String>> = conn.mget(&keys).await.map_err(redis_error)?;
+        values
+            .into_iter()
+            .flatten()
+            .map(|json| {
+                serde_json::from_str(&json)
+                    .map_err(|e| GatewayError::Internal(format!("Corrupt session: {}", e)))
+            })
+            .collect()
+    }
+
+    async fn delete_sessions_for_agents(&self, agent_ids: &[Uuid]) -> Result<usize, GatewayError> {
+        let mut conn = self.conn().await?;
+        let mut removed = 0;
+
+        for agent_id in agent_ids {
+            let agent_key = agent_key(*agent_id);
+            let session_ids: Vec<String> =
+                conn.smembers(&agent_key).await.map_err(redis_error)?;
+
+            if !session_ids.is_empty() {
+                let keys: Vec<String> = session_ids.iter().map(|id| session_key(id)).collect();
+                // DEL counts only keys that still exist, so expired members don't count
+                let existing: usize = conn.del(&keys).await.map_err(redis_error)?;
+                removed += existing;
+            }
+            conn.del::<_, ()>(&agent_key).await.map_err(redis_error)?;
+        }
+
+        Ok(removed)
+    }
+
+    async fn health_check(&self) -> Result<(), GatewayError> {
+        redis::cmd("PING")
+            .query_async::<()>(&mut self.conn().await?)
+            .await
+            .map_err(redis_error)
+    }
+}
@@ -0,0 +1,300 @@
+//! Schema versions for the JSON store files (users.json, agents.json, credentials.json).
+//!
+//! Each file carries a top-level `schema_version`; files written before versioning
+//! count as version 0. On load, older files are upgraded one migration at a time
+//! and written back, with the original kept as `<file>.v<N>`. A file newer than
+//! this binary understands is a hard error rather than a silent misread.
+//! sessions.json is not versioned: losing it only logs agents out.
+
+use chrono::{DateTime, Duration, Utc};
+use serde_json::{json, Value};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::error::GatewayError;
+
+pub const SCHEMA_VERSION_KEY: &str = "schema_version";
+
+/// Upgrades a document from version `to - 1` to `to`
+pub struct Migration {
+    pub to: u32,
+    pub description: &'static str,
+    pub apply: fn(&mut Value),
+}
+
+/// Migrations for one file; `migrations[n]` upgrades version n to n + 1
+pub struct FileSchema {
+    pub name: &'static str,
+    pub migrations: &'static [Migration],
+}
+
+pub const USERS_SCHEMA: FileSchema = FileSchema {
+    name: "users",
+    migrations: &[Migration {
+        to: 1,
+        description: "Default missing agent lists on users",
+        apply: default_user_agents,
+    }],
+};
+
+pub const AGENTS_SCHEMA: FileSchema = FileSchema {
+    name: "agents",
+    migrations: &[
+        Migration {
+            to: 1,
+            description: "Default lifespan_days and expires_at on agents from before key expiry",
+            apply: default_agent_expiry,
+        },
+        Migration {
+            to: 2,
+            description: "Turn allowed_services ids into grants with per-service scopes",
+            apply: service_access_grants,
+        },
+    ],
+};
+
+pub const CREDENTIALS_SCHEMA: FileSchema = FileSchema {
+    name: "credentials",
+    migrations: &[Migration {
+        to: 1,
+        description: "Default missing scopes and encrypted flag on credentials",
+        apply: default_credential_fields,
+    }],
+};
+
+impl FileSchema {
+    pub fn current_version(&self) -> u32 {
+        self.migrations.len() as u32
+    }
+
+    /// Bring `doc` up to the current version in place, returning the version it had
+    pub fn upgrade(&self, doc: &mut Value, path: &Path) -> Result<u32, GatewayError> {
+        let Some(object) = doc.as_object_mut() else {
+            return Err(GatewayError::Internal(format!(
+                "{} is not a JSON object",
+                path.display()
+            )));
+        };
+        let found = match object.get(SCHEMA_VERSION_KEY) {
+            None => 0,
+            Some(version) => version.as_u64().ok_or_else(|| {
+                GatewayError::Internal(format!(
+                    "{}: {} must be a non-negative integer",
+                    path.display(),
+                    SCHEMA_VERSION_KEY
+                ))
+            })? as u32,
+        };
+
+        let current = self.current_version();
+        if found > current {
+            return Err(GatewayError::Internal(format!(
+                "{} has {} {}, but this gateway only understands {} files up to version {}. \
+                 It was written by a newer release: upgrade the gateway, or restore {}.v{} \
+                 (kept when the file was last upgraded) if it exists.",
+                path.display(),
+                SCHEMA_VERSION_KEY,
+                found,
+                self.name,
+                current,
+                path.display(),
+                current
+            )));
+        }
+
+        for migration in &self.migrations[found as usize..] {
+            (migration.apply)(doc);
+            tracing::info!(
+                file = %path.display(),
+                to_version = migration.to,
+                "Schema migration: {}",
+                migration.description
+            );
+        }
+        doc[SCHEMA_VERSION_KEY] = json!(current);
+        Ok(found)
+    }
+
+    /// `upgrade`, then write the result back if anything changed
+    pub fn upgrade_file(&self, doc: &mut Value, path: &Path) -> Result<(), GatewayError> {
+        let found = self.upgrade(doc, path)?;
+        self.save_if_upgraded(path, doc, found)
+    }
+
+    /// Write an upgraded document back over `path` unless it was already current
+    pub fn save_if_upgraded(&self, path: &Path, doc: &Value, from_version: u32) -> Result<(), GatewayError> {
+        if from_version < self.current_version() {
+            save_upgraded(path, doc, from_version)?;
+        }
+        Ok(())
+    }
+}
+
+/// Replace the file with the upgraded document, keeping the original as `<file>.v<N>`.
+/// Runs once at startup, so plain blocking writes are fine.
+fn save_upgraded(path: &Path, doc: &Value, from_version: u32) -> Result<(), GatewayError> {
+    let original = PathBuf::from(format!("{}.v{}", path.display(), from_version));
+    let content = serde_json::to_string_pretty(doc)
+        .map_err(|e| GatewayError::Internal(format!("Failed to serialize {}: {}", path.display(), e)))?;
+
+    fs::copy(path, &original)
+        .and_then(|_| fs::write(path, content))
+        .map_err(|e| {
+            GatewayError::Internal(format!("Failed to write upgraded {}: {}", path.display(), e))
+        })?;
+
+    tracing::info!(file = %path.display(), original = %original.display(), "Upgraded store file schema");
+    Ok(())
+}
+
+// ============ Migrations ============
+
+fn entries<'a>(doc: &'a mut Value, key: &str) -> impl Iterator<Item = &'a mut serde_json::Map<String, Value>> {
+    doc.get_mut(key)
+        .and_then(Value::as_array_mut)
+        .into_iter()
+        .flatten()
+        .filter_map(Value::as_object_mut)
+}
+
+fn default_user_agents(doc: &mut Value) {
+    for user in entries(doc, "users") {
+        user.entry("agents").or_insert_with(|| json!([]));
+    }
+}
+
+/// Agents created before access keys expired had neither field; give them the
+/// default 30-day lifespan counted from creation
+fn default_agent_expiry(doc: &mut Value) {
+    for agent in entries(doc, "agents") {
+        let lifespan_days = agent
+            .entry("lifespan_days")
+            .or_insert_with(|| json!(30))
+            .as_i64()
+            .unwrap_or(30);
+        if !agent.contains_key("expires_at") {
+            let created_at = agent
+                .get("created_at")
+                .and_then(Value::as_str)
+                .and_then(|c| c.parse::<DateTime<Utc>>().ok())
+                .unwrap_or_else(Utc::now);
+            agent.insert(
+                "expires_at".to_string(),
+                json!(created_at + Duration::days(lifespan_days)),
+            );
+        }
+    }
+}
+
+/// `"payment"` becomes `{"service_id": "payment", "granted_scopes": []}`: every scope, as before
+fn service_access_grants(doc: &mut Value) {
+    for agent in entries(doc, "agents") {
+        let Some(Value::Array(services)) = agent.get_mut("allowed_services") else {
+            continue;
+        };
+        for service in services {
+            if let Value::String(service_id) = service {
+                *service = json!({ "service_id": service_id, "granted_scopes": [] });
+            }
+        }
+    }
+}
+
+fn default_credential_fields(doc: &mut Value) {
+    for credential in entries(doc, "credentials") {
+        credential.entry("scopes").or_insert_with(|| json!([]));
+        credential.entry("encrypted").or_insert(json!(false));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{Agent, User};
+    use tempfile::TempDir;
+
+    /// Fixture files under tests/fixtures/schema, one per historical version
+    fn fixture(dir: &TempDir, name: &str) -> PathBuf {
+        let source = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/schema").join(name);
+        let path = dir.path().join(name);
+        fs::copy(source, &path).unwrap();
+        path
+    }
+
+    fn load(schema: &FileSchema, path: &Path) -> Result<Value, GatewayError> {
+        let mut doc: Value = serde_json::from_str(&fs::read_to_string(path).unwrap()).unwrap();
+        schema.upgrade_file(&mut doc, path)?;
+        Ok(doc)
+    }
+
+    #[test]
+    fn test_agents_v0_gets_expiry_and_is_written_back() {
+        let dir = TempDir::new().unwrap();
+        let path = fixture(&dir, "agents_v0.json");
+        let doc = load(&AGENTS_SCHEMA, &path).unwrap();
+
+        let agents: Vec<Agent> = serde_json::from_value(doc["agents"].clone()).unwrap();
+        assert_eq!(agents[0].lifespan_days, 30);
+        assert_eq!(agents[0].expires_at, agents[0].created_at + Duration::days(30));
+        // An explicit lifespan is kept and used for the expiry
+        assert_eq!(agents[1].lifespan_days, 7);
+        assert_eq!(agents[1].expires_at, agents[1].created_at + Duration::days(7));
+
+        let saved: Value = serde_json::from_str(&fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(saved[SCHEMA_VERSION_KEY], 2);
+        assert!(dir.path().join("agents_v0.json.v0").exists());
+    }
+
+    #[test]
+    fn test_agents_v1_service_ids_become_grants() {
+        let dir = TempDir::new().unwrap();
+        let path = fixture(&dir, "agents_v1.json");
+        let doc = load(&AGENTS_SCHEMA, &path).unwrap();
+
+        assert_eq!(
+            doc["agents"][0]["allowed_services"],
+            json!([{ "service_id": "payment", "granted_scopes": [] }])
+        );
+        let agents: Vec<Agent> = serde_json::from_value(doc["agents"].clone()).unwrap();
+        assert!(agents[0].can_access_service("payment"));
+        assert!(agents[0].get_service_scopes("payment").is_empty());
+        assert!(dir.path().join("agents_v1.json.v1").exists());
+    }
+
+    #[test]
+    fn test_current_versions_load_unchanged() {
+        let dir = TempDir::new().unwrap();
+        for (schema, name) in [
+            (&USERS_SCHEMA, "users_v1.json"),
+            (&AGENTS_SCHEMA, "agents_v2.json"),
+            (&CREDENTIALS_SCHEMA, "credentials_v1.json"),
+        ] {
+            let path = fixture(&dir, name);
+            let before = fs::read_to_string(&path).unwrap();
+            load(schema, &path).unwrap();
+            assert_eq!(fs::read_to_string(&path).unwrap(), before, "{} was rewritten", name);
+        }
+    }
+
+    #[test]
+    fn test_users_and_credentials_v0() {
+        let dir = TempDir::new().unwrap();
+        let users = load(&USERS_SCHEMA, &fixture(&dir, "users_v0.json")).unwrap();
+        let users: Vec<User> = serde_json::from_value(users["users"].clone()).unwrap();
+        assert!(users[0].agents.is_empty());
+
+        let credentials = load(&CREDENTIALS_SCHEMA, &fixture(&dir, "credentials_v0.json")).unwrap();
+        assert_eq!(credentials["credentials"][0]["scopes"], json!([]));
+        assert_eq!(credentials["credentials"][0]["encrypted"], false);
+    }
+
+    #[test]
+    fn test_newer_file_is_rejected() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("agents.json");
+        fs::write(&path, r#"{"schema_version": 99, "agents": []}"#).unwrap();
+
+        let err = load(&AGENTS_SCHEMA, &path).unwrap_err();
+        assert!(matches!(err, GatewayError::Internal(msg) if msg.contains("schema_version 99") && msg.contains("upgrade the gateway")));
+    }
+}
@@ -0,0 +1,362 @@
+//! Short-lived cache of `validate_session` results (SESSION_CACHE_TTL_MS), so repeat
+//! requests from the same agent skip the session and agent lookups.
+//!
+//! `AppState` wraps its stores in `CachedAgentStore` / `CachedSessionStore`, which
+//! drop the affected entries after every mutation: a revoked session or an updated,
+//! rotated or deleted agent is never served from the cache.
+
+use async_trait::async_trait;
+use dashmap::DashMap;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use uuid::Uuid;
+
+use crate::error::GatewayError;
+use crate::models::{Agent, AgentSession};
+use super::listing::{AgentCounts, AgentFilter, AgentSort, Page};
+use super::traits::{AgentStoreTrait, SessionStoreTrait};
+
+/// Past this many entries, an insert first sweeps out the expired ones
+const SWEEP_THRESHOLD: usize = 10_000;
+
+struct CachedSession {
+    session: AgentSession,
+    agent: Agent,
+    expires_at: Instant,
+}
+
+#[derive(Clone)]
+pub struct SessionCache {
+    entries: Arc<DashMap<String, CachedSession>>,
+    // Bumped by every invalidation; a lookup that raced one isn't cached
+    generation: Arc<AtomicU64>,
+    ttl: Duration,
+}
+
+impl SessionCache {
+    /// A zero `ttl` disables the cache
+    pub fn new(ttl: Duration) -> Self {
+        Self {
+            entries: Arc::new(DashMap::new()),
+            generation: Arc::new(AtomicU64::new(0)),
+            ttl,
+        }
+    }
+
+    /// Take before reading the stores; pass to `insert` with what was read
+    pub fn generation(&self) -> u64 {
+        self.generation.load(Ordering::SeqCst)
+    }
+
+    /// Cached session and agent, if unexpired; expired entries are dropped here
+    pub fn get(&self, session_id: &str) -> Option<(AgentSession, Agent)> {
+        let now = Instant::now();
+        if let Some(entry) = self.entries.get(session_id) {
+            if entry.expires_at > now {
+                return Some((entry.session.clone(), entry.agent.clone()));
+            }
+        }
+        self.entries.remove_if(session_id, |_, entry| entry.expires_at <= now);
+        None
+    }
+
+    /// Cache a lookup, unless an invalidation happened since `generation` was taken
+    /// (the lookup may have read the state from before it)
+    pub fn insert(&self, generation: u64, session: &AgentSession, agent: &Agent) {
+        if self.ttl.is_zero() || self.generation() != generation {
+            return;
+        }
+        let now = Instant::now();
+        if self.entries.len() >= SWEEP_THRESHOLD {
+            self.entries.retain(|_, entry| entry.expires_at > now);
+        }
+        let entry = CachedSession {
+            session: session.clone(),
+            agent: agent.clone(),
+            expires_at: now + self.ttl,
+        };
+        self.entries.insert(session.session_id.clone(), entry);
+        // An invalidation that bumped the generation before this check may have
+        // swept before the insert; one that bumps after it sweeps this entry too
+        if self.generation() != generation {
+            self.entries.remove(&session.session_id);
+        }
+    }
+
+    pub fn invalidate_session(&self, session_id: &str) {
+        self.generation.fetch_add(1, Ordering::SeqCst);
+        self.entries.remove(session_id);
+    }
+
+    /// Drop every session of these agents
+    pub fn invalidate_agents(&self, agent_ids: &[Uuid]) {
+        self.generation.fetch_add(1, Ordering::SeqCst);
+        self.entries.retain(|_, entry| !agent_ids.contains(&entry.agent.id));
+    }
+
+    /// Drop every session
+    pub fn invalidate_all(&self) {
+        self.generation.fetch_add(1, Ordering::SeqCst);
+        self.entries.clear();
+    }
+}
+
+// ============ Store wrappers that keep the cache coherent ============
+
+/// Agent store that invalidates the cached sessions of every agent it writes
+pub struct CachedAgentStore {
+    inner: Arc<dyn AgentStoreTrait>,
+    cache: SessionCache,
+}
+
+impl CachedAgentStore {
+    pub fn new(inner: Arc<dyn AgentStoreTrait>, cache: SessionCache) -> Self {
+        Self { inner, cache }
+    }
+}
+
+#[async_trait]
+impl AgentStoreTrait for CachedAgentStore {
+    async fn get_agent(&self, id: Uuid) -> Result<Option<Agent>, GatewayError> {
+        self.inner.get_agent(id).await
+    }
+
+    async fn get_agents(&self, ids: &[Uuid]) -> Result<Vec<Agent>, GatewayError> {
+        self.inner.get_agents(ids).await
+    }
+
+    async fn list_agents(&self) -> Result<Vec<Agent>, GatewayError> {
+        self.inner.list_agents().await
+    }
+
+    async fn list_agents_page(
+        &self,
+        offset: usize,
+        limit: usize,
+        filter: &AgentFilter,
+        sort: AgentSort,
+    ) -> Result<Page<Agent>, GatewayError> {
+        self.inner.list_agents_page(offset, limit, filter, sort).await
+    }
+
+    async fn count_agents_by_status(&self) -> Result<AgentCounts, GatewayError> {
+        self.inner.count_agents_by_status().await
+    }
+
+    async fn create_agent(&self, agent: Agent) -> Result<Agent, GatewayError> {
+        // Creating under an existing id replaces that agent
+        let id = agent.id;
+        let result = self.inner.create_agent(agent).await;
+        self.cache.invalidate_agents(&[id]);
+        result
+    }
+
+    async fn find_agent_by_name(
+        &self,
+        owner_id: Uuid,
+        name: &str,
+    ) -> Result<Option<Agent>, GatewayError> {
+        self.inner.find_agent_by_name(owner_id, name).await
+    }
+
+    async fn create_or_get_agent(&self, agent: Agent) -> Result<Agent, GatewayError> {
+        // Never replaces an agent, so nothing cached can go stale
+        self.inner.create_or_get_agent(agent).await
+    }
+
+    async fn update_agent(&self, agent: Agent, expected_version: u64) -> Result<Agent, GatewayError> {
+        let id = agent.id;
+        let result = self.inner.update_agent(agent, expected_version).await;
+        self.cache.invalidate_agents(&[id]);
+        result
+    }
+
+    async fn delete_agent(&self, id: Uuid) -> Result<bool, GatewayError> {
+        let result = self.inner.delete_agent(id).await;
+        self.cache.invalidate_agents(&[id]);
+        result
+    }
+
+    async fn delete_agents(&self, ids: &[Uuid]) -> Result<usize, GatewayError> {
+        let result = self.inner.delete_agents(ids).await;
+        self.cache.invalidate_agents(ids);
+        result
+    }
+
+    async fn record_usage(&self, agent_id: Uuid, service_id: &str) -> Result<(), GatewayError> {
+        // Counts in a cached entry may lag by the TTL; invalidating on every request
+        // would defeat the cache
+        self.inner.record_usage(agent_id, service_id).await
+    }
+
+    async fn flush(&self) -> Result<(), GatewayError> {
+        self.inner.flush().await
+    }
+
+    async fn backup(&self) -> Result<Vec<PathBuf>, GatewayError> {
+        self.inner.backup().await
+    }
+
+    async fn health_check(&self) -> Result<(), GatewayError> {
+        self.inner.health_check().await
+    }
+}
+
+/// Session store that invalidates the cache entries of every session it writes
+pub struct CachedSessionStore {
+    inner: Arc<dyn SessionStoreTrait>,
+    cache: SessionCache,
+}
+
+impl CachedSessionStore {
+    pub fn new(inner: Arc<dyn SessionStoreTrait>, cache: SessionCache) -> Self {
+        Self { inner, cache }
+    }
+}
+
+#[async_trait]
+impl SessionStoreTrait for CachedSessionStore {
+    async fn get_session(&self, session_id: &str) -> Result<Option<AgentSession>, GatewayError> {
+        self.inner.get_session(session_id).await
+    }
+
+    async fn list_sessions(&self) -> Result<Vec<AgentSession>, GatewayError> {
+        self.inner.list_sessions().await
+    }
+
+    async fn count_live_sessions(&self) -> Result<usize, GatewayError> {
+        self.inner.count_live_sessions().await
+    }
+
+    async fn create_session(&self, session: AgentSession) -> Result<AgentSession, GatewayError> {
+        let session_id = session.session_id.clone();
+        let result = self.inner.create_session(session).await;
+        self.cache.invalidate_session(&session_id);
+        result
+    }
+
+    async fn delete_session(&self, session_id: &str) -> Result<(), GatewayError> {
+        let result = self.inner.delete_session(session_id).await;
+        self.cache.invalidate_session(session_id);
+        result
+    }
+
+    async fn sessions_for_agent(&self, agent_id: Uuid) -> Result<Vec<AgentSession>, GatewayError> {
+        self.inner.sessions_for_agent(agent_id).await
+    }
+
+    // The backend's atomic version; the agent's cached sessions may include evicted ones
+    async fn create_session_capped(
+        &self,
+        session: AgentSession,
+        max_sessions: u32,
+    ) -> Result<(AgentSession, usize), GatewayError> {
+        let (agent_id, session_id) = (session.agent_id, session.session_id.clone());
+        let result = self.inner.create_session_capped(session, max_sessions).await;
+        self.cache.invalidate_session(&session_id);
+        if matches!(&result, Ok((_, evicted)) if *evicted > 0) {
+            self.cache.invalidate_agents(&[agent_id]);
+        }
+        result
+    }
+
+    async fn delete_sessions_for_agents(&self, agent_ids: &[Uuid]) -> Result<usize, GatewayError> {
+        let result = self.inner.delete_sessions_for_agents(agent_ids).await;
+        self.cache.invalidate_agents(agent_ids);
+        result
+    }
+
+    async fn delete_all_sessions(&self) -> Result<usize, GatewayError> {
+        let result = self.inner.delete_all_sessions().await;
+        self.cache.invalidate_all();
+        result
+    }
+
+    // Cached entries are checked for expiry on every hit, so none need dropping
+    async fn delete_expired_sessions(&self) -> Result<usize, GatewayError> {
+        self.inner.delete_expired_sessions().await
+    }
+
+    async fn health_check(&self) -> Result<(), GatewayError> {
+        self.inner.health_check().await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::auth::create_session;
+    use crate::storage::InMemoryStore;
+
+    fn cached_stores(ttl: Duration) -> (SessionCache, CachedAgentStore, CachedSessionStore) {
+        let cache = SessionCache::new(ttl);
+        let store = Arc::new(InMemoryStore::new());
+        (
+            cache.clone(),
+            CachedAgentStore::new(store.clone(), cache.clone()),
+            CachedSessionStore::new(store, cache),
+        )
+    }
+
+    async fn seed(agents: &CachedAgentStore, sessions: &CachedSessionStore) -> (AgentSession, Agent) {
+        let agent = agents.create_agent(Agent::new("cached".to_string(), "".to_string())).await.unwrap();
+        let session = sessions.create_session(create_session(agent.id, 3600)).await.unwrap();
+        (session, agent)
+    }
+
+    #[tokio::test]
+    async fn test_entries_expire_after_ttl() {
+        let (cache, agents, sessions) = cached_stores(Duration::from_millis(50));
+        let (session, agent) = seed(&agents, &sessions).await;
+
+        cache.insert(cache.generation(), &session, &agent);
+        assert_eq!(cache.get(&session.session_id).unwrap().1.id, agent.id);
+        tokio::time::sleep(Duration::from_millis(60)).await;
+        assert!(cache.get(&session.session_id).is_none());
+
+        let disabled = SessionCache::new(Duration::ZERO);
+        disabled.insert(disabled.generation(), &session, &agent);
+        assert!(disabled.get(&session.session_id).is_none());
+    }
+
+    #[tokio::test]
+    async fn test_store_writes_invalidate_entries() {
+        let (cache, agents, sessions) = cached_stores(Duration::from_secs(60));
+        let (session, agent) = seed(&agents, &sessions).await;
+        let (other_session, other_agent) = seed(&agents, &sessions).await;
+        let cache_both = || {
+            cache.insert(cache.generation(), &session, &agent);
+            cache.insert(cache.generation(), &other_session, &other_agent);
+        };
+
+        // Grant/revoke and the old key of a rotation go through update_agent
+        cache_both();
+        agents.update_agent(agent.clone(), agent.version).await.unwrap();
+        assert!(cache.get(&session.session_id).is_none());
+        assert!(cache.get(&other_session.session_id).is_some());
+
+        cache_both();
+        sessions.delete_session(&other_session.session_id).await.unwrap();
+        assert!(cache.get(&other_session.session_id).is_none());
+        assert!(cache.get(&session.session_id).is_some());
+
+        cache_both();
+        sessions.delete_sessions_for_agents(&[agent.id]).await.unwrap();
+        agents.delete_agents(&[agent.id]).await.unwrap();
+        assert!(cache.get(&session.session_id).is_none());
+    }
+
+    #[tokio::test]
+    async fn test_lookup_racing_an_invalidation_is_not_cached() {
+        let (cache, agents, sessions) = cached_stores(Duration::from_secs(60));
+        let (session, agent) = seed(&agents, &sessions).await;
+
+        // Read before the revocation, inserted after it
+        let generation = cache.generation();
+        sessions.delete_session(&session.session_id).await.unwrap();
+        cache.insert(generation, &session, &agent);
+        assert!(cache.get(&session.session_id).is_none());
+    }
+}
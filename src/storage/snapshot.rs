@@ -0,0 +1,155 @@
+//! Full data snapshot for moving a gateway between environments
+//! (`GET /admin/export`, `POST /admin/import`)
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::config::{CredentialManager, EncryptedCredential};
+use crate::error::GatewayError;
+use crate::models::{Agent, AgentSession, User};
+use super::traits::{AgentStoreTrait, SessionStoreTrait, UserStoreTrait};
+
+/// Bumped whenever the snapshot layout changes incompatibly
+pub const SNAPSHOT_VERSION: u32 = 1;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Snapshot {
+    pub version: u32,
+    pub exported_at: DateTime<Utc>,
+    pub users: Vec<User>,
+    pub agents: Vec<Agent>,
+    #[serde(default)]
+    pub sessions: Vec<AgentSession>,
+    /// Service credentials, still encrypted with the exporting gateway's key
+    pub credentials: Vec<EncryptedCredential>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ImportMode {
+    /// Upsert snapshot records by id, keeping everything else
+    #[default]
+    Merge,
+    /// Drop existing users, agents, sessions and credentials first
+    Replace,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct ImportSummary {
+    pub users: usize,
+    pub agents: usize,
+    pub sessions: usize,
+    pub credentials: usize,
+    /// Users not imported because their email belongs to a different user
+    pub skipped_users: Vec<Uuid>,
+}
+
+/// The stores a snapshot covers
+pub struct SnapshotStores<'a> {
+    pub users: &'a dyn UserStoreTrait,
+    pub agents: &'a dyn AgentStoreTrait,
+    pub sessions: &'a dyn SessionStoreTrait,
+    pub credentials: &'a CredentialManager,
+}
+
+impl SnapshotStores<'_> {
+    pub async fn export(&self, include_sessions: bool) -> Result<Snapshot, GatewayError> {
+        Ok(Snapshot {
+            version: SNAPSHOT_VERSION,
+            exported_at: Utc::now(),
+            users: self.users.list_users().await?,
+            agents: self.agents.list_agents().await?,
+            sessions: if include_sessions {
+                self.sessions.list_sessions().await?
+            } else {
+                Vec::new()
+            },
+            credentials: self.credentials.export_encrypted().await?,
+        })
+    }
+
+    /// Apply a snapshot. `source_key` decrypts its credentials (the exporting
+    /// gateway's ENCRYPTION_KEY); they are re-encrypted with this gateway's key.
+    pub async fn import(
+        &self,
+        snapshot: Snapshot,
+        mode: ImportMode,
+        source_key: &str,
+    ) -> Result<ImportSummary, GatewayError> {
+        if snapshot.version != SNAPSHOT_VERSION {
+            return Err(GatewayError::BadRequest(format!(
+                "Unsupported snapshot version {} (expected {})",
+                snapshot.version, SNAPSHOT_VERSION
+            )));
+        }
+
+        // First, so a wrong source key fails before anything else is touched
+        let credentials = self
+            .credentials
+            .import(snapshot.credentials, source_key, mode == ImportMode::Replace)
+            .await?;
+        if mode == ImportMode::Replace {
+            self.clear().await?;
+        }
+
+        let mut summary = ImportSummary {
+            credentials,
+            ..Default::default()
+        };
+
+        for user in snapshot.users {
+            let taken = self
+                .users
+                .get_user_by_email(&user.email)
+                .await?
+                .is_some_and(|existing| existing.id != user.id);
+            if taken {
+                summary.skipped_users.push(user.id);
+                continue;
+            }
+            self.users.update_user(user).await?;
+            summary.users += 1;
+        }
+
+        for agent in snapshot.agents {
+            match self.agents.get_agent(agent.id).await? {
+                Some(existing) => self.agents.update_agent(agent, existing.version).await?,
+                None => self.agents.create_agent(agent).await?,
+            };
+            summary.agents += 1;
+        }
+
+        for session in snapshot.sessions {
+            if session.is_expired() || self.sessions.get_session(&session.session_id).await?.is_some() {
+                continue;
+            }
+            self.sessions.create_session(session).await?;
+            summary.sessions += 1;
+        }
+
+        self.agents.flush().await?;
+        Ok(summary)
+    }
+
+    // === Replace mode: remove every user, agent and session ===
+    async fn clear(&self) -> Result<(), GatewayError> {
+        let agent_ids: Vec<Uuid> = self.agents.list_agents().await?.iter().map(|a| a.id).collect();
+        let mut session_agents: Vec<Uuid> = self
+            .sessions
+            .list_sessions()
+            .await?
+            .iter()
+            .map(|s| s.agent_id)
+            .collect();
+        session_agents.sort();
+        session_agents.dedup();
+
+        self.sessions.delete_sessions_for_agents(&session_agents).await?;
+        self.agents.delete_agents(&agent_ids).await?;
+        for user in self.users.list_users().await? {
+            self.users.delete_user(user.id).await?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,1065 @@
+//! SQLite storage backend (STORAGE_BACKEND=sqlite)
+//!
+//! Rows keep their lookup keys in real columns and the full model as JSON in
+//! `data`, so adding a field to `Agent` or `User` doesn't need a migration.
+
+use async_trait::async_trait;
+use rusqlite::{params, Connection, ErrorCode, OptionalExtension};
+use serde::de::DeserializeOwned;
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use uuid::Uuid;
+
+use crate::error::GatewayError;
+use crate::gateway::{decrypt, encrypt};
+use crate::models::{Agent, AgentSession, PendingUser, ServiceCredential, User};
+use super::file_store::{read_agents_file, read_sessions_file, read_users_file};
+use super::listing::AgentCounts;
+use super::traits::{
+    agent_conflict, agent_not_found, sessions_to_drop, AgentStoreTrait, CredentialStoreTrait, SessionStoreTrait,
+    UserStoreTrait,
+};
+
+/// The owner's newest agent with a given name (agents keep their model as JSON in `data`)
+const AGENT_BY_NAME: &str = "SELECT data FROM agents
+     WHERE json_extract(data, '$.owner_id') = ?1 AND json_extract(data, '$.name') = ?2
+     ORDER BY json_extract(data, '$.updated_at') DESC LIMIT 1";
+
+// === Schema migrations, applied in order; never edit a released entry ===
+const MIGRATIONS: &[&str] = &[
+    // 1: initial schema
+    "CREATE TABLE users (
+        id TEXT PRIMARY KEY,
+        email TEXT NOT NULL UNIQUE,
+        data TEXT NOT NULL
+    );
+    CREATE TABLE agents (
+        id TEXT PRIMARY KEY,
+        data TEXT NOT NULL
+    );
+    CREATE TABLE sessions (
+        session_id TEXT PRIMARY KEY,
+        agent_id TEXT NOT NULL,
+        expires_at TEXT NOT NULL,
+        data TEXT NOT NULL
+    );
+    CREATE INDEX idx_sessions_agent_id ON sessions (agent_id);
+    CREATE TABLE agent_credentials (
+        agent_id TEXT NOT NULL,
+        service_id TEXT NOT NULL,
+        data TEXT NOT NULL,
+        PRIMARY KEY (agent_id, service_id)
+    );",
+    // 2: allowed_services ids become grants with per-service scopes
+    "UPDATE agents SET data = json_set(data, '$.allowed_services', json((
+        SELECT json_group_array(CASE WHEN type = 'text'
+            THEN json_object('service_id', value, 'granted_scopes', json('[]'))
+            ELSE json(value) END)
+        FROM (SELECT type, value FROM json_each(agents.data, '$.allowed_services') ORDER BY key)
+    )))
+    WHERE json_type(data, '$.allowed_services') = 'array';",
+    // 3: registrations awaiting email verification; expires_at in unix seconds
+    "CREATE TABLE pending_users (
+        id TEXT PRIMARY KEY,
+        expires_at INTEGER NOT NULL,
+        data TEXT NOT NULL
+    );",
+];
+
+#[derive(Clone)]
+pub struct SqliteStore {
+    conn: Arc<Mutex<Connection>>,
+    encryption_key: String,
+}
+
+impl SqliteStore {
+    /// Open (or create) the database and bring its schema up to date
+    pub fn open<P: AsRef<Path>>(path: P, encryption_key: &str) -> Result<Self, GatewayError> {
+        let path = path.as_ref();
+        if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+            std::fs::create_dir_all(parent).map_err(|e| {
+                GatewayError::Internal(format!("Failed to create {}: {}", parent.display(), e))
+            })?;
+        }
+
+        let mut conn = Connection::open(path).map_err(|e| {
+            GatewayError::Internal(format!("Failed to open database {}: {}", path.display(), e))
+        })?;
+        run_migrations(&mut conn, MIGRATIONS)?;
+
+        Ok(Self {
+            conn: Arc::new(Mutex::new(conn)),
+            encryption_key: encryption_key.to_string(),
+        })
+    }
+
+    /// Copy users.json / agents.json / sessions.json into an empty database
+    /// (first boot after switching backends)
+    pub fn import_json_if_empty<P: AsRef<Path>>(
+        &self,
+        users_path: P,
+        agents_path: P,
+        sessions_path: P,
+    ) -> Result<(), GatewayError> {
+        let mut conn = self
+            .conn
+            .lock()
+            .map_err(|_| GatewayError::Internal("SQLite connection poisoned".to_string()))?;
+
+        let is_empty: bool = conn
+            .query_row(
+                "SELECT NOT EXISTS (SELECT 1 FROM users) AND NOT EXISTS (SELECT 1 FROM agents)",
+                [],
+                |row| row.get(0),
+            )
+            .map_err(db_error)?;
+        if !is_empty {
+            return Ok(());
+        }
+
+        let users = read_users_file(users_path)?;
+        // Files written with AGENTS_FILE_ENCRYPTION share the same ENCRYPTION_KEY
+        let key = Some(self.encryption_key.as_str());
+        let (agents, mut sessions) = read_agents_file(agents_path, key)?;
+        sessions.extend(read_sessions_file(sessions_path, key)?);
+        if users.is_empty() && agents.is_empty() {
+            return Ok(());
+        }
+
+        // One transaction so a failed import leaves the database empty for the next boot
+        let tx = conn.transaction().map_err(db_error)?;
+        for user in &users {
+            tx.execute(
+                "INSERT INTO users (id, email, data) VALUES (?1, ?2, ?3)",
+                params![user.id.to_string(), user.email, to_json(user)?],
+            )
+            .map_err(db_error)?;
+        }
+        for agent in &agents {
+            tx.execute(
+                "INSERT INTO agents (id, data) VALUES (?1, ?2)",
+                params![agent.id.to_string(), to_json(agent)?],
+            )
+            .map_err(db_error)?;
+        }
+        for session in &sessions {
+            tx.execute(
+                "INSERT OR REPLACE INTO sessions (session_id, agent_id, expires_at, data) VALUES (?1, ?2, ?3, ?4)",
+                params![
+                    session.session_id,
+                    session.agent_id.to_string(),
+                    session.expires_at.to_rfc3339(),
+                    to_json(session)?
+                ],
+            )
+            .map_err(db_error)?;
+        }
+        tx.commit().map_err(db_error)?;
+
+        tracing::info!(
+            users = users.len(),
+            agents = agents.len(),
+            sessions = sessions.len(),
+            "Imported JSON store files into SQLite"
+        );
+        Ok(())
+    }
+
+    // === Run a blocking closure against the connection off the async runtime ===
+    async fn with_conn<T, F>(&self, f: F) -> Result<T, GatewayError>
+    where
+        T: Send + 'static,
+        F: FnOnce(&Connection) -> rusqlite::Result<T> + Send + 'static,
+    {
+        let conn = self.conn.clone();
+        tokio::task::spawn_blocking(move || {
+            let conn = conn
+                .lock()
+                .map_err(|_| GatewayError::Internal("SQLite connection poisoned".to_string()))?;
+            f(&conn).map_err(db_error)
+        })
+        .await
+        .map_err(|e| GatewayError::Internal(format!("SQLite task failed: {}", e)))?
+    }
+
+    // === Fetch one JSON `data` column and deserialize it ===
+    async fn get_json<T: DeserializeOwned + Send + 'static>(
+        &self,
+        sql: &'static str,
+        key: Vec<String>,
+    ) -> Result<Option<T>, GatewayError> {
+        let data: Option<String> = self
+            .with_conn(move |conn| {
+                conn.query_row(sql, rusqlite::params_from_iter(key), |row| row.get(0))
+                    .optional()
+            })
+            .await?;
+
+        data.map(|d| from_json(&d)).transpose()
+    }
+
+    // === Fetch and deserialize the JSON `data` column of every row ===
+    async fn list_json<T: DeserializeOwned + Send + 'static>(
+        &self,
+        sql: &'static str,
+    ) -> Result<Vec<T>, GatewayError> {
+        let rows: Vec<String> = self
+            .with_conn(move |conn| {
+                let mut stmt = conn.prepare(sql)?;
+                let rows = stmt.query_map([], |row| row.get(0))?;
+                rows.collect()
+            })
+            .await?;
+
+        rows.iter().map(|d| from_json(d)).collect()
+    }
+
+    // === Run a single-id DELETE for each agent in one transaction; returns rows removed ===
+    async fn delete_by_agent(&self, sql: &'static str, ids: &[Uuid]) -> Result<usize, GatewayError> {
+        let ids: Vec<String> = ids.iter().map(Uuid::to_string).collect();
+        self.with_conn(move |conn| {
+            let tx = conn.unchecked_transaction()?;
+            let mut removed = 0;
+            for id in &ids {
+                removed += tx.execute(sql, params![id])?;
+            }
+            tx.commit()?;
+            Ok(removed)
+        })
+        .await
+    }
+
+    // === One trivial query: the database file is open and answering ===
+    async fn ping(&self) -> Result<(), GatewayError> {
+        self.with_conn(|conn| conn.query_row("SELECT 1", [], |_| Ok(()))).await
+    }
+}
+
+/// Apply the `migrations` past the database's `user_version`, each in its own transaction
+pub(crate) fn run_migrations(conn: &mut Connection, migrations: &[&str]) -> Result<(), GatewayError> {
+    let current: usize = conn
+        .query_row("PRAGMA user_version", [], |row| row.get(0))
+        .map_err(db_error)?;
+
+    if current > migrations.len() {
+        return Err(GatewayError::Internal(format!(
+            "Database schema version {} is newer than this build supports ({})",
+            current,
+            migrations.len()
+        )));
+    }
+
+    for (idx, sql) in migrations.iter().enumerate().skip(current) {
+        let version = idx + 1;
+        let tx = conn.transaction().map_err(db_error)?;
+        tx.execute_batch(sql).map_err(db_error)?;
+        tx.pragma_update(None, "user_version", version).map_err(db_error)?;
+        tx.commit().map_err(db_error)?;
+        tracing::info!(version, "Applied SQLite migration");
+    }
+
+    Ok(())
+}
+
+fn db_error(e: rusqlite::Error) -> GatewayError {
+    GatewayError::Internal(format!("Database error: {}", e))
+}
+
+fn to_json<T: serde::Serialize>(value: &T) -> Result<String, GatewayError> {
+    serde_json::to_string(value)
+        .map_err(|e| GatewayError::Internal(format!("Failed to serialize row: {}", e)))
+}
+
+fn from_json<T: DeserializeOwned>(data: &str) -> Result<T, GatewayError> {
+    serde_json::from_str(data)
+        .map_err(|e| GatewayError::Internal(format!("Failed to parse row: {}", e)))
+}
+
+#[async_trait]
+impl UserStoreTrait for SqliteStore {
+    async fn create_user(&self, user: User) -> Result<User, GatewayError> {
+        let (id, email, data) = (user.id.to_string(), user.email.clone(), to_json(&user)?);
+
+        // The UNIQUE index on email makes the duplicate check atomic
+        let inserted = self
+            .with_conn(move |conn| {
+                match conn.execute(
+                    "INSERT INTO users (id, email, data) VALUES (?1, ?2, ?3)",
+                    params![id, email, data],
+                ) {
+                    Ok(_) => Ok(true),
+                    Err(rusqlite::Error::SqliteFailure(e, _))
+                        if e.code == ErrorCode::ConstraintViolation =>
+                    {
+                        Ok(false)
+                    }
+                    Err(e) => Err(e),
+                }
+            })
+            .await?;
+
+        if !inserted {
+            return Err(GatewayError::BadRequest("Email already registered".to_string()));
+        }
+        Ok(user)
+    }
+
+    async fn get_user(&self, id: Uuid) -> Result<Option<User>, GatewayError> {
+        self.get_json("SELECT data FROM users WHERE id = ?1", vec![id.to_string()])
+            .await
+    }
+
+    async fn get_user_by_email(&self, email: &str) -> Result<Option<User>, GatewayError> {
+        self.get_json("SELECT data FROM users WHERE email = ?1", vec![email.to_string()])
+            .await
+    }
+
+    async fn list_users(&self) -> Result<Vec<User>, GatewayError> {
+        self.list_json("SELECT data FROM users").await
+    }
+
+    async fn count_users(&self) -> Result<usize, GatewayError> {
+        self.with_conn(|conn| conn.query_row("SELECT COUNT(*) FROM users", [], |row| row.get(0)))
+            .await
+    }
+
+    async fn update_user(&self, user: User) -> Result<(), GatewayError> {
+        let (id, email, data) = (user.id.to_string(), user.email.clone(), to_json(&user)?);
+        self.with_conn(move |conn| {
+            conn.execute(
+                "INSERT INTO users (id, email, data) VALUES (?1, ?2, ?3)
+                 ON CONFLICT (id) DO UPDATE SET email = excluded.email, data = excluded.data",
+                params![id, email, data],
+            )
+        })
+        .await?;
+        Ok(())
+    }
+
+    async fn delete_user(&self, id: Uuid) -> Result<bool, GatewayError> {
+        let id = id.to_string();
+        let removed = self
+            .with_conn(move |conn| conn.execute("DELETE FROM users WHERE id = ?1", params![id]))
+            .await?;
+        Ok(removed > 0)
+    }
+
+    async fn save_pending_user(&self, pending: PendingUser) -> Result<(), GatewayError> {
+        let (id, expires_at, data) = (pending.id.to_string(), pending.expires_at.timestamp(), to_json(&pending)?);
+        let now = chrono::Utc::now().timestamp();
+        self.with_conn(move |conn| {
+            let tx = conn.unchecked_transaction()?;
+            tx.execute("DELETE FROM pending_users WHERE expires_at < ?1", params![now])?;
+            tx.execute(
+                "INSERT OR REPLACE INTO pending_users (id, expires_at, data) VALUES (?1, ?2, ?3)",
+                params![id, expires_at, data],
+            )?;
+            tx.commit()
+        })
+        .await
+    }
+
+    async fn get_pending_user(&self, id: Uuid) -> Result<Option<PendingUser>, GatewayError> {
+        self.get_json("SELECT data FROM pending_users WHERE id = ?1", vec![id.to_string()])
+            .await
+    }
+
+    async fn delete_pending_user(&self, id: Uuid) -> Result<bool, GatewayError> {
+        let id = id.to_string();
+        let removed = self
+            .with_conn(move |conn| conn.execute("DELETE FROM pending_users WHERE id = ?1", params![id]))
+            .await?;
+        Ok(removed > 0)
+    }
+
+    async fn health_check(&self) -> Result<(), GatewayError> {
+        self.ping().await
+    }
+}
+
+#[async_trait]
+impl AgentStoreTrait for SqliteStore {
+    async fn get_agent(&self, id: Uuid) -> Result<Option<Agent>, GatewayError> {
+        self.get_json("SELECT data FROM agents WHERE id = ?1", vec![id.to_string()])
+            .await
+    }
+
+    async fn get_agents(&self, ids: &[Uuid]) -> Result<Vec<Agent>, GatewayError> {
+        if ids.is_empty() {
+            return Ok(Vec::new());
+        }
+        let keys: Vec<String> = ids.iter().map(Uuid::to_string).collect();
+        let sql = format!(
+            "SELECT id, data FROM agents WHERE id IN ({})",
+            vec!["?"; keys.len()].join(", ")
+        );
+
+        let rows: Vec<(String, String)> = self
+            .with_conn(move |conn| {
+                let mut stmt = conn.prepare(&sql)?;
+                let rows = stmt.query_map(rusqlite::params_from_iter(&keys), |row| {
+                    Ok((row.get(0)?, row.get(1)?))
+                })?;
+                rows.collect()
+            })
+            .await?;
+
+        let mut found: HashMap<String, String> = rows.into_iter().collect();
+        ids.iter()
+            .filter_map(|id| found.remove(&id.to_string()))
+            .map(|data| from_json(&data))
+            .collect()
+    }
+
+    async fn list_agents(&self) -> Result<Vec<Agent>, GatewayError> {
+        self.list_json("SELECT data FROM agents").await
+    }
+
+    async fn count_agents_by_status(&self) -> Result<AgentCounts, GatewayError> {
+        let (active, expired) = self
+            .with_conn(|conn| {
+                conn.query_row(
+                    "SELECT COUNT(*) - COUNT(expired), COUNT(expired) FROM (
+                         SELECT NULLIF(julianday(json_extract(data, '$.expires_at')) < julianday('now'), 0) AS expired
+                         FROM agents
+                     )",
+                    [],
+                    |row| Ok((row.get(0)?, row.get(1)?)),
+                )
+            })
+            .await?;
+        Ok(AgentCounts { active, expired })
+    }
+
+    async fn create_agent(&self, agent: Agent) -> Result<Agent, GatewayError> {
+        let (id, data) = (agent.id.to_string(), to_json(&agent)?);
+        self.with_conn(move |conn| {
+            conn.execute(
+                "INSERT OR REPLACE INTO agents (id, data) VALUES (?1, ?2)",
+                params![id, data],
+            )
+        })
+        .await?;
+        Ok(agent)
+    }
+
+    async fn find_agent_by_name(
+        &self,
+        owner_id: Uuid,
+        name: &str,
+    ) -> Result<Option<Agent>, GatewayError> {
+        self.get_json(AGENT_BY_NAME, vec![owner_id.to_string(), name.to_string()])
+            .await
+    }
+
+    async fn create_or_get_agent(&self, agent: Agent) -> Result<Agent, GatewayError> {
+        let Some(owner_id) = agent.owner_id else {
+            return self.create_agent(agent).await;
+        };
+        let (id, owner, name, data) =
+            (agent.id.to_string(), owner_id.to_string(), agent.name.clone(), to_json(&agent)?);
+
+        // One connection behind a mutex, so the lookup and insert can't interleave
+        let existing: Option<String> = self
+            .with_conn(move |conn| {
+                let tx = conn.unchecked_transaction()?;
+                let existing = tx
+                    .query_row(AGENT_BY_NAME, params![owner, name], |row| row.get(0))
+                    .optional()?;
+                if existing.is_none() {
+                    tx.execute("INSERT INTO agents (id, data) VALUES (?1, ?2)", params![id, data])?;
+                }
+                tx.commit()?;
+                Ok(existing)
+            })
+            .await?;
+
+        match existing {
+            Some(data) => from_json(&data),
+            None => Ok(agent),
+        }
+    }
+
+    async fn update_agent(&self, mut agent: Agent, expected_version: u64) -> Result<Agent, GatewayError> {
+        agent.version = expected_version + 1;
+        let (id, data) = (agent.id.to_string(), to_json(&agent)?);
+        let expected = expected_version as i64;
+
+        // The version check and the write are one statement (keeping the stored usage);
+        // on a miss, tell a concurrent update apart from a deleted agent
+        let (updated, exists) = self
+            .with_conn(move |conn| {
+                let updated = conn.execute(
+                    "UPDATE agents
+                     SET data = json_set(?2, '$.usage',
+                         json(COALESCE(json_extract(data, '$.usage'), json_extract(?2, '$.usage'))))
+                     WHERE id = ?1 AND COALESCE(json_extract(data, '$.version'), 0) = ?3",
+                    params![id, data, expected],
+                )?;
+                let exists = updated > 0
+                    || conn
+                        .query_row("SELECT 1 FROM agents WHERE id = ?1", params![id], |_| Ok(()))
+                        .optional()?
+                        .is_some();
+                Ok((updated, exists))
+            })
+            .await?;
+
+        match (updated, exists) {
+            (0, true) => Err(agent_conflict(agent.id)),
+            (0, false) => Err(agent_not_found()),
+            _ => Ok(agent),
+        }
+    }
+
+    async fn record_usage(&self, agent_id: Uuid, service_id: &str) -> Result<(), GatewayError> {
+        let (id, service_id) = (agent_id.to_string(), service_id.to_string());
+        // Read-modify-write is atomic: the connection is held throughout
+        self.with_conn(move |conn| {
+            let data: Option<String> = conn
+                .query_row("SELECT data FROM agents WHERE id = ?1", params![id], |row| row.get(0))
+                .optional()?;
+            let Some(data) = data else {
+                return Ok(());
+            };
+            let json_error = |e: serde_json::Error| rusqlite::Error::ToSqlConversionFailure(Box::new(e));
+            let mut agent: Agent = serde_json::from_str(&data).map_err(json_error)?;
+            agent.usage.record(&service_id);
+            let data = serde_json::to_string(&agent).map_err(json_error)?;
+            conn.execute("UPDATE agents SET data = ?2 WHERE id = ?1", params![id, data])?;
+            Ok(())
+        })
+        .await
+    }
+
+    async fn delete_agent(&self, id: Uuid) -> Result<bool, GatewayError> {
+        self.delete_agents(&[id]).await.map(|removed| removed > 0)
+    }
+
+    async fn delete_agents(&self, ids: &[Uuid]) -> Result<usize, GatewayError> {
+        self.delete_by_agent("DELETE FROM agents WHERE id = ?1", ids).await
+    }
+
+    async fn health_check(&self) -> Result<(), GatewayError> {
+        self.ping().await
+    }
+}
+
+#[async_trait]
+impl SessionStoreTrait for SqliteStore {
+    async fn get_session(&self, session_id: &str) -> Result<Option<AgentSession>, GatewayError> {
+        self.get_json(
+            "SELECT data FROM sessions WHERE session_id = ?1",
+            vec![session_id.to_string()],
+        )
+        .await
+    }
+
+    async fn list_sessions(&self) -> Result<Vec<AgentSession>, GatewayError> {
+        self.list_json("SELECT data FROM sessions").await
+    }
+
+    async fn count_live_sessions(&self) -> Result<usize, GatewayError> {
+        self.with_conn(|conn| {
+            conn.query_row(
+                "SELECT COUNT(*) FROM sessions WHERE julianday(expires_at) >= julianday('now')",
+                [],
+                |row| row.get(0),
+            )
+        })
+        .await
+    }
+
+    async fn create_session(&self, session: AgentSession) -> Result<AgentSession, GatewayError> {
+        let (session_id, agent_id, expires_at, data) = (
+            session.session_id.clone(),
+            session.agent_id.to_string(),
+            session.expires_at.to_rfc3339(),
+            to_json(&session)?,
+        );
+        self.with_conn(move |conn| {
+            conn.execute(
+                "INSERT INTO sessions (session_id, agent_id, expires_at, data) VALUES (?1, ?2, ?3, ?4)",
+                params![session_id, agent_id, expires_at, data],
+            )
+        })
+        .await?;
+        Ok(session)
+    }
+
+    async fn delete_session(&self, session_id: &str) -> Result<(), GatewayError> {
+        let session_id = session_id.to_string();
+        self.with_conn(move |conn| {
+            conn.execute("DELETE FROM sessions WHERE session_id = ?1", params![session_id])
+        })
+        .await?;
+        Ok(())
+    }
+
+    // One transaction on the one connection: nothing else runs between the count and the insert
+    async fn create_session_capped(
+        &self,
+        session: AgentSession,
+        max_sessions: u32,
+    ) -> Result<(AgentSession, usize), GatewayError> {
+        let (session_id, agent_id, expires_at, data) = (
+            session.session_id.clone(),
+            session.agent_id.to_string(),
+            session.expires_at.to_rfc3339(),
+            to_json(&session)?,
+        );
+        let evicted = self
+            .with_conn(move |conn| {
+                let tx = conn.unchecked_transaction()?;
+                let current = {
+                    let mut stmt = tx.prepare("SELECT data FROM sessions WHERE agent_id = ?1")?;
+                    let rows = stmt.query_map(params![agent_id], |row| row.get::<_, String>(0))?;
+                    rows.map(|data| {
+                        serde_json::from_str::<AgentSession>(&data?)
+                            .map_err(|e| rusqlite::Error::ToSqlConversionFailure(Box::new(e)))
+                    })
+                    .collect::<rusqlite::Result<Vec<_>>>()?
+                };
+                let (expired, evicted) = sessions_to_drop(current, max_sessions);
+                for dropped in expired.iter().chain(&evicted) {
+                    tx.execute("DELETE FROM sessions WHERE session_id = ?1", params![dropped])?;
+                }
+                tx.execute(
+                    "INSERT INTO sessions (session_id, agent_id, expires_at, data) VALUES (?1, ?2, ?3, ?4)",
+                    params![session_id, agent_id, expires_at, data],
+                )?;
+                tx.commit()?;
+                Ok(evicted.len())
+            })
+            .await?;
+        Ok((session, evicted))
+    }
+
+    async fn sessions_for_agent(&self, agent_id: Uuid) -> Result<Vec<AgentSession>, GatewayError> {
+        let agent_id = agent_id.to_string();
+        let rows: Vec<String> = self
+            .with_conn(move |conn| {
+                let mut stmt = conn.prepare("SELECT data FROM sessions WHERE agent_id = ?1")?;
+                let rows = stmt.query_map(params![agent_id], |row| row.get(0))?;
+                rows.collect()
+            })
+            .await?;
+        rows.iter().map(|d| from_json(d)).collect()
+    }
+
+    async fn delete_sessions_for_agents(&self, agent_ids: &[Uuid]) -> Result<usize, GatewayError> {
+        self.delete_by_agent("DELETE FROM sessions WHERE agent_id = ?1", agent_ids)
+            .await
+    }
+
+    async fn delete_all_sessions(&self) -> Result<usize, GatewayError> {
+        self.with_conn(|conn| conn.execute("DELETE FROM sessions", [])).await
+    }
+
+    async fn health_check(&self) -> Result<(), GatewayError> {
+        self.ping().await
+    }
+}
+
+#[async_trait]
+impl CredentialStoreTrait for SqliteStore {
+    async fn get_credential(
+        &self,
+        agent_id: Uuid,
+        service_id: &str,
+    ) -> Result<Option<ServiceCredential>, GatewayError> {
+        let credential: Option<ServiceCredential> = self
+            .get_json(
+                "SELECT data FROM agent_credentials WHERE agent_id = ?1 AND service_id = ?2",
+                vec![agent_id.to_string(), service_id.to_string()],
+            )
+            .await?;
+
+        // Tokens are encrypted at rest, same as credentials.json
+        credential
+            .map(|mut c| {
+                c.access_token = decrypt(&c.access_token, &self.encryption_key)?;
+                c.refresh_token = c
+                    .refresh_token
+                    .map(|rt| decrypt(&rt, &self.encryption_key))
+                    .transpose()?;
+                Ok(c)
+            })
+            .transpose()
+    }
+
+    async fn store_credential(&self, mut credential: ServiceCredential) -> Result<(), GatewayError> {
+        credential.access_token = encrypt(&credential.access_token, &self.encryption_key)?;
+        credential.refresh_token = credential
+            .refresh_token
+            .map(|rt| encrypt(&rt, &self.encryption_key))
+            .transpose()?;
+
+        let (agent_id, service_id, data) = (
+            credential.agent_id.to_string(),
+            credential.service_id.clone(),
+            to_json(&credential)?,
+        );
+        self.with_conn(move |conn| {
+            conn.execute(
+                "INSERT OR REPLACE INTO agent_credentials (agent_id, service_id, data) VALUES (?1, ?2, ?3)",
+                params![agent_id, service_id, data],
+            )
+        })
+        .await?;
+        Ok(())
+    }
+
+    async fn delete_credential(&self, agent_id: Uuid, service_id: &str) -> Result<(), GatewayError> {
+        let (agent_id, service_id) = (agent_id.to_string(), service_id.to_string());
+        self.with_conn(move |conn| {
+            conn.execute(
+                "DELETE FROM agent_credentials WHERE agent_id = ?1 AND service_id = ?2",
+                params![agent_id, service_id],
+            )
+        })
+        .await?;
+        Ok(())
+    }
+
+    async fn delete_credentials_for_agents(&self, agent_ids: &[Uuid]) -> Result<usize, GatewayError> {
+        self.delete_by_agent("DELETE FROM agent_credentials WHERE agent_id = ?1", agent_ids)
+            .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+    use tempfile::TempDir;
+
+    const KEY: &str = "test-encryption-key-32-chars!!!";
+
+    fn open_temp() -> (TempDir, SqliteStore) {
+        let dir = TempDir::new().unwrap();
+        let store = SqliteStore::open(dir.path().join("gateway.db"), KEY).unwrap();
+        (dir, store)
+    }
+
+    #[tokio::test]
+    async fn test_user_roundtrip_and_duplicate_email() {
+        let (_dir, store) = open_temp();
+
+        let mut user = store
+            .create_user(User::new("alice".to_string(), "alice@example.com".to_string()))
+            .await
+            .unwrap();
+
+        let dup = store
+            .create_user(User::new("alice2".to_string(), "alice@example.com".to_string()))
+            .await;
+        assert!(matches!(dup, Err(GatewayError::BadRequest(_))));
+
+        let agent_id = Uuid::new_v4();
+        user.add_agent(agent_id);
+        store.update_user(user.clone()).await.unwrap();
+
+        let loaded = store.get_user_by_email("alice@example.com").await.unwrap().unwrap();
+        assert_eq!(loaded.id, user.id);
+        assert_eq!(loaded.agents, vec![agent_id]);
+    }
+
+    #[tokio::test]
+    async fn test_pending_users_are_kept_until_deleted_or_expired() {
+        let (_dir, store) = open_temp();
+
+        let mut pending = PendingUser::new("carol".to_string(), "carol@example.com".to_string(), 600);
+        store.save_pending_user(pending.clone()).await.unwrap();
+        pending.attempts = 2;
+        store.save_pending_user(pending.clone()).await.unwrap();
+        assert_eq!(store.get_pending_user(pending.id).await.unwrap().unwrap().attempts, 2);
+        assert!(store.get_user(pending.id).await.unwrap().is_none());
+
+        // Saving another registration sweeps out the expired ones
+        let mut expired = PendingUser::new("dave".to_string(), "dave@example.com".to_string(), 0);
+        expired.expires_at -= chrono::Duration::seconds(5);
+        store.save_pending_user(expired.clone()).await.unwrap();
+        store
+            .save_pending_user(PendingUser::new("erin".to_string(), "erin@example.com".to_string(), 600))
+            .await
+            .unwrap();
+        assert!(store.get_pending_user(expired.id).await.unwrap().is_none());
+
+        assert!(store.delete_pending_user(pending.id).await.unwrap());
+        assert!(!store.delete_pending_user(pending.id).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_agents_and_sessions() {
+        let (_dir, store) = open_temp();
+
+        let mut agent = Agent::with_lifespan("bot".to_string(), "".to_string(), 7);
+        agent.add_service("payment".to_string());
+        store.create_agent(agent.clone()).await.unwrap();
+
+        let session = crate::auth::create_session(agent.id, 60);
+        store.create_session(session.clone()).await.unwrap();
+
+        let loaded = store.get_session(&session.session_id).await.unwrap().unwrap();
+        assert_eq!(loaded.agent_id, agent.id);
+        assert_eq!(
+            store.get_agent(agent.id).await.unwrap().unwrap().service_ids(),
+            vec!["payment"]
+        );
+
+        store.delete_session(&session.session_id).await.unwrap();
+        store.delete_agent(agent.id).await.unwrap();
+        assert!(store.get_session(&session.session_id).await.unwrap().is_none());
+        assert!(store.get_agent(agent.id).await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_counts_match_stored_rows() {
+        let (_dir, store) = open_temp();
+
+        store
+            .create_user(User::new("alice".to_string(), "alice@example.com".to_string()))
+            .await
+            .unwrap();
+        let live = Agent::with_lifespan("live".to_string(), "".to_string(), 7);
+        let mut lapsed = Agent::with_lifespan("lapsed".to_string(), "".to_string(), 7);
+        lapsed.expires_at = chrono::Utc::now() - chrono::Duration::hours(1);
+        store.create_agent(live.clone()).await.unwrap();
+        store.create_agent(lapsed).await.unwrap();
+
+        store.create_session(crate::auth::create_session(live.id, 60)).await.unwrap();
+        let mut stale = crate::auth::create_session(live.id, 60);
+        stale.expires_at = chrono::Utc::now() - chrono::Duration::seconds(1);
+        store.create_session(stale).await.unwrap();
+
+        assert_eq!(store.count_users().await.unwrap(), 1);
+        assert_eq!(store.count_agents_by_status().await.unwrap(), AgentCounts { active: 1, expired: 1 });
+        assert_eq!(store.count_live_sessions().await.unwrap(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_create_or_get_agent_by_owner_and_name() {
+        let (_dir, store) = open_temp();
+        let owner = Uuid::new_v4();
+        let named = |name: &str, owner_id| {
+            let mut agent = Agent::with_lifespan(name.to_string(), "".to_string(), 7);
+            agent.owner_id = owner_id;
+            agent
+        };
+
+        let first = store.create_or_get_agent(named("bot", Some(owner))).await.unwrap();
+        let again = store.create_or_get_agent(named("bot", Some(owner))).await.unwrap();
+        assert_eq!(again.id, first.id);
+
+        let other_owner = store.create_or_get_agent(named("bot", Some(Uuid::new_v4()))).await.unwrap();
+        let unowned = store.create_or_get_agent(named("bot", None)).await.unwrap();
+        assert_ne!(other_owner.id, first.id);
+        assert_ne!(unowned.id, first.id);
+        assert_eq!(store.list_agents().await.unwrap().len(), 3);
+        assert_eq!(store.find_agent_by_name(owner, "bot").await.unwrap().unwrap().id, first.id);
+    }
+
+    #[tokio::test]
+    async fn test_update_agent_checks_version() {
+        let (_dir, store) = open_temp();
+        let agent = store
+            .create_agent(Agent::with_lifespan("bot".to_string(), "".to_string(), 7))
+            .await
+            .unwrap();
+
+        let saved = store.update_agent(agent.clone(), 0).await.unwrap();
+        assert_eq!(saved.version, 1);
+        assert_eq!(store.get_agent(agent.id).await.unwrap().unwrap().version, 1);
+        assert!(matches!(store.update_agent(agent.clone(), 0).await, Err(GatewayError::Conflict(_))));
+
+        store.delete_agent(agent.id).await.unwrap();
+        assert!(matches!(store.update_agent(saved, 1).await, Err(GatewayError::NotFound(_))));
+    }
+
+    #[tokio::test]
+    async fn test_usage_is_kept_by_updates() {
+        let (_dir, store) = open_temp();
+        let agent = store
+            .create_agent(Agent::with_lifespan("bot".to_string(), "".to_string(), 7))
+            .await
+            .unwrap();
+        store.record_usage(agent.id, "payment").await.unwrap();
+        store.record_usage(agent.id, "payment").await.unwrap();
+        store.record_usage(Uuid::new_v4(), "payment").await.unwrap();
+
+        let mut stale = agent.clone();
+        stale.description = "updated".to_string();
+        store.update_agent(stale, 0).await.unwrap();
+
+        let stored = store.get_agent(agent.id).await.unwrap().unwrap();
+        assert_eq!(stored.description, "updated");
+        assert_eq!(stored.usage.total_requests, 2);
+        assert_eq!(stored.usage.requests_by_service["payment"], 2);
+    }
+
+    #[tokio::test]
+    async fn test_batch_deletes_by_agent() {
+        let (_dir, store) = open_temp();
+        let keep = Agent::with_lifespan("keep".to_string(), "".to_string(), 7);
+        let gone: Vec<Agent> = (0..2)
+            .map(|_| Agent::with_lifespan("gone".to_string(), "".to_string(), 7))
+            .collect();
+
+        for agent in gone.iter().chain([&keep]) {
+            store.create_agent(agent.clone()).await.unwrap();
+            store.create_session(crate::auth::create_session(agent.id, 60)).await.unwrap();
+        }
+        let kept_session = store.create_session(crate::auth::create_session(keep.id, 60)).await.unwrap();
+
+        let ids: Vec<Uuid> = gone.iter().map(|a| a.id).collect();
+        let found = store.get_agents(&[ids[1], Uuid::new_v4(), keep.id]).await.unwrap();
+        assert_eq!(found.iter().map(|a| a.id).collect::<Vec<_>>(), [ids[1], keep.id]);
+
+        assert_eq!(store.delete_sessions_for_agents(&ids).await.unwrap(), 2);
+        assert_eq!(store.delete_agents(&ids).await.unwrap(), 2);
+        assert_eq!(store.delete_agents(&ids).await.unwrap(), 0);
+
+        assert!(store.get_agent(keep.id).await.unwrap().is_some());
+        assert!(store.get_session(&kept_session.session_id).await.unwrap().is_some());
+    }
+
+    #[tokio::test]
+    async fn test_credentials_encrypted_at_rest() {
+        let (_dir, store) = open_temp();
+        let now = Utc::now();
+        let agent_id = Uuid::new_v4();
+
+        store
+            .store_credential(ServiceCredential {
+                id: Uuid::new_v4(),
+                agent_id,
+                service_id: "bank".to_string(),
+                access_token: "secret-token".to_string(),
+                refresh_token: Some("secret-refresh".to_string()),
+                expires_at: None,
+                scopes: vec![],
+                created_at: now,
+                updated_at: now,
+            })
+            .await
+            .unwrap();
+
+        let raw: String = store
+            .with_conn(|conn| conn.query_row("SELECT data FROM agent_credentials", [], |r| r.get(0)))
+            .await
+            .unwrap();
+        assert!(!raw.contains("secret-token"));
+
+        let loaded = store.get_credential(agent_id, "bank").await.unwrap().unwrap();
+        assert_eq!(loaded.access_token, "secret-token");
+        assert_eq!(loaded.refresh_token.as_deref(), Some("secret-refresh"));
+    }
+
+    #[tokio::test]
+    async fn test_reopen_keeps_data_and_schema_version() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("gateway.db");
+
+        let user = User::new("bob".to_string(), "bob@example.com".to_string());
+        SqliteStore::open(&path, KEY)
+            .unwrap()
+            .create_user(user.clone())
+            .await
+            .unwrap();
+
+        let store = SqliteStore::open(&path, KEY).unwrap();
+        assert!(store.get_user(user.id).await.unwrap().is_some());
+
+        let version: usize = store
+            .with_conn(|conn| conn.query_row("PRAGMA user_version", [], |r| r.get(0)))
+            .await
+            .unwrap();
+        assert_eq!(version, MIGRATIONS.len());
+    }
+
+    #[tokio::test]
+    async fn test_service_ids_are_migrated_to_grants() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("gateway.db");
+        let mut conn = Connection::open(&path).unwrap();
+        run_migrations(&mut conn, &MIGRATIONS[..1]).unwrap();
+        let mut old = serde_json::to_value(Agent::with_lifespan("old".to_string(), "".to_string(), 7)).unwrap();
+        old["allowed_services"] = serde_json::json!(["payment", "bank"]);
+        conn.execute(
+            "INSERT INTO agents (id, data) VALUES (?1, ?2)",
+            params![old["id"].as_str().unwrap(), old.to_string()],
+        )
+        .unwrap();
+        drop(conn);
+
+        let store = SqliteStore::open(&path, KEY).unwrap();
+        let data: String = store
+            .with_conn(|conn| conn.query_row("SELECT data FROM agents", [], |r| r.get(0)))
+            .await
+            .unwrap();
+        let data: serde_json::Value = serde_json::from_str(&data).unwrap();
+        assert_eq!(
+            data["allowed_services"],
+            serde_json::json!([
+                { "service_id": "payment", "granted_scopes": [] },
+                { "service_id": "bank", "granted_scopes": [] }
+            ])
+        );
+    }
+
+    #[tokio::test]
+    async fn test_imports_json_files_on_first_boot() {
+        let dir = TempDir::new().unwrap();
+        let users_path = dir.path().join("users.json");
+        let agents_path = dir.path().join("agents.json");
+        let sessions_path = dir.path().join("sessions.json");
+
+        let user = User::new("carol".to_string(), "carol@example.com".to_string());
+        let agent = Agent::with_lifespan("legacy".to_string(), "".to_string(), 30);
+        let session = crate::auth::create_session(agent.id, 3600);
+        std::fs::write(&users_path, serde_json::json!({ "users": [user] }).to_string()).unwrap();
+        std::fs::write(
+            &agents_path,
+            serde_json::json!({ "agents": [agent], "sessions": [session] }).to_string(),
+        )
+        .unwrap();
+
+        let store = SqliteStore::open(dir.path().join("gateway.db"), KEY).unwrap();
+        store.import_json_if_empty(&users_path, &agents_path, &sessions_path).unwrap();
+        // Second boot must not duplicate or fail on existing rows
+        store.import_json_if_empty(&users_path, &agents_path, &sessions_path).unwrap();
+
+        assert!(store.get_user(user.id).await.unwrap().is_some());
+        assert!(store.get_agent(agent.id).await.unwrap().is_some());
+        assert!(store.get_session(&session.session_id).await.unwrap().is_some());
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_concurrent_capped_sessions_hold_the_cap() {
+        let (_dir, store) = open_temp();
+        let agent_id = Uuid::new_v4();
+        let mut expired = crate::auth::create_session(agent_id, 60);
+        expired.expires_at = Utc::now() - chrono::Duration::seconds(1);
+        store.create_session(expired).await.unwrap();
+
+        let logins: Vec<_> = (0..20)
+            .map(|_| {
+                let store = store.clone();
+                tokio::spawn(async move {
+                    store.create_session_capped(crate::auth::create_session(agent_id, 60), 3).await.unwrap()
+                })
+            })
+            .collect();
+        let mut evicted = 0;
+        for login in logins {
+            evicted += login.await.unwrap().1;
+        }
+
+        assert_eq!(evicted, 17);
+        let sessions = store.sessions_for_agent(agent_id).await.unwrap();
+        assert_eq!(sessions.len(), 3);
+        assert!(sessions.iter().all(|session| !session.is_expired()));
+    }
+}
@@ -1,25 +1,230 @@
-//! Storage traits for future database abstraction
+//! Storage traits implemented by every persistence backend (file, SQLite, ...)
 
 use async_trait::async_trait;
+use std::path::PathBuf;
 use uuid::Uuid;
 
 use crate::error::GatewayError;
-use crate::models::{Agent, AgentSession, ServiceCredential};
+use crate::models::{Agent, AgentSession, PendingUser, ServiceCredential, User};
+use super::listing::{paginate, paginate_agents, user_order, AgentCounts, AgentFilter, AgentSort, Page, UserFilter};
+
+/// `update_agent` lost the race: the stored agent changed since it was read
+pub(crate) fn agent_conflict(id: Uuid) -> GatewayError {
+    GatewayError::Conflict(format!("Agent {} was modified by another request", id))
+}
+
+pub(crate) fn agent_not_found() -> GatewayError {
+    GatewayError::NotFound("Agent not found".to_string())
+}
+
+/// What `create_session_capped` drops from one agent's `sessions`: the ids of
+/// the expired ones, then of the oldest live ones that leave room for one more
+/// under `max_sessions` (`0`: none). Backends call it with the agent's sessions
+/// locked, so the count can't change before the new session is stored.
+pub(crate) fn sessions_to_drop(sessions: Vec<AgentSession>, max_sessions: u32) -> (Vec<String>, Vec<String>) {
+    let (expired, mut live): (Vec<_>, Vec<_>) = sessions.into_iter().partition(AgentSession::is_expired);
+    let expired = expired.into_iter().map(|session| session.session_id).collect();
+    if max_sessions == 0 {
+        return (expired, Vec::new());
+    }
+    live.sort_by_key(|session| session.created_at);
+    let excess = (live.len() + 1).saturating_sub(max_sessions as usize);
+    (expired, live.into_iter().take(excess).map(|session| session.session_id).collect())
+}
+
+#[async_trait]
+pub trait UserStoreTrait: Send + Sync {
+    /// Fails with `BadRequest` when the email is already registered
+    async fn create_user(&self, user: User) -> Result<User, GatewayError>;
+    async fn get_user(&self, id: Uuid) -> Result<Option<User>, GatewayError>;
+    async fn get_user_by_email(&self, email: &str) -> Result<Option<User>, GatewayError>;
+    /// Every user (data export)
+    async fn list_users(&self) -> Result<Vec<User>, GatewayError>;
+    /// `limit` users matching `filter` from `offset`, oldest first, with the total
+    /// number of matches. Backends override the full copy with a scan over references.
+    async fn list_users_page(
+        &self,
+        offset: usize,
+        limit: usize,
+        filter: &UserFilter,
+    ) -> Result<Page<User>, GatewayError> {
+        let users = self.list_users().await?;
+        Ok(paginate(users.iter().filter(|u| filter.matches(u)), user_order, offset, limit))
+    }
+    /// Number of users. Backends override the full copy with a count.
+    async fn count_users(&self) -> Result<usize, GatewayError> {
+        Ok(self.list_users().await?.len())
+    }
+    async fn update_user(&self, user: User) -> Result<(), GatewayError>;
+    /// Returns whether the user existed
+    async fn delete_user(&self, id: Uuid) -> Result<bool, GatewayError>;
+    /// Insert or replace a registration awaiting email verification, dropping
+    /// expired ones on the way
+    async fn save_pending_user(&self, pending: PendingUser) -> Result<(), GatewayError>;
+    async fn get_pending_user(&self, id: Uuid) -> Result<Option<PendingUser>, GatewayError>;
+    /// Returns whether the registration was pending
+    async fn delete_pending_user(&self, id: Uuid) -> Result<bool, GatewayError>;
+    /// Copy the backing files to timestamped backups, returning them.
+    /// Backends without files have nothing to copy.
+    async fn backup(&self) -> Result<Vec<PathBuf>, GatewayError> {
+        Ok(Vec::new())
+    }
+    /// Whether the backend can serve requests right now (`GET /health/detailed`).
+    /// Backends with nothing that can go away always can.
+    async fn health_check(&self) -> Result<(), GatewayError> {
+        Ok(())
+    }
+}
 
-#[allow(dead_code)]
 #[async_trait]
 pub trait AgentStoreTrait: Send + Sync {
     async fn get_agent(&self, id: Uuid) -> Result<Option<Agent>, GatewayError>;
+    /// Fetch several agents in one lookup; missing ids are skipped, order follows `ids`
+    async fn get_agents(&self, ids: &[Uuid]) -> Result<Vec<Agent>, GatewayError>;
+    /// Every agent (data export)
+    async fn list_agents(&self) -> Result<Vec<Agent>, GatewayError>;
+    /// `limit` agents matching `filter` from `offset` in `sort` order, with the total
+    /// number of matches. Backends override the full copy with a scan over references.
+    async fn list_agents_page(
+        &self,
+        offset: usize,
+        limit: usize,
+        filter: &AgentFilter,
+        sort: AgentSort,
+    ) -> Result<Page<Agent>, GatewayError> {
+        let agents = self.list_agents().await?;
+        Ok(paginate_agents(agents.iter().filter(|a| filter.matches(a)), sort, offset, limit))
+    }
+    /// Active and expired agents. Backends override the full copy with a count.
+    async fn count_agents_by_status(&self) -> Result<AgentCounts, GatewayError> {
+        Ok(AgentCounts::tally(self.list_agents().await?.iter()))
+    }
     async fn create_agent(&self, agent: Agent) -> Result<Agent, GatewayError>;
-    async fn delete_agent(&self, id: Uuid) -> Result<(), GatewayError>;
+    /// The owner's agent with this name (the most recently updated one if a
+    /// rotation left several). Backends override the full scan with an index.
+    async fn find_agent_by_name(
+        &self,
+        owner_id: Uuid,
+        name: &str,
+    ) -> Result<Option<Agent>, GatewayError> {
+        Ok(self
+            .list_agents()
+            .await?
+            .into_iter()
+            .filter(|a| a.owner_id == Some(owner_id) && a.name == name)
+            .max_by_key(|a| a.updated_at))
+    }
+    /// Create the agent unless its owner already has one with the same name, in
+    /// which case the existing agent is returned and nothing is written.
+    /// Backends override this to make the check and the insert atomic.
+    async fn create_or_get_agent(&self, agent: Agent) -> Result<Agent, GatewayError> {
+        if let Some(owner_id) = agent.owner_id {
+            if let Some(existing) = self.find_agent_by_name(owner_id, &agent.name).await? {
+                return Ok(existing);
+            }
+        }
+        self.create_agent(agent).await
+    }
+    /// Compare-and-swap keyed by `agent.id`: stores the agent only if the stored
+    /// version is still `expected_version`, returning it with the version bumped.
+    /// `Conflict` when another update got there first, `NotFound` when it's gone.
+    /// `agent.usage` is ignored: the stored counts are kept.
+    async fn update_agent(&self, agent: Agent, expected_version: u64) -> Result<Agent, GatewayError>;
+    /// Count one proxied request to `service_id`. Doesn't bump the version, so it never
+    /// conflicts with `update_agent`; a missing agent is ignored.
+    async fn record_usage(&self, agent_id: Uuid, service_id: &str) -> Result<(), GatewayError>;
+    /// Returns whether the agent existed
+    async fn delete_agent(&self, id: Uuid) -> Result<bool, GatewayError>;
+    /// Delete several agents, returning how many existed.
+    /// Backends override this to apply the whole batch atomically.
+    async fn delete_agents(&self, ids: &[Uuid]) -> Result<usize, GatewayError> {
+        let mut removed = 0;
+        for id in ids {
+            if self.delete_agent(*id).await? {
+                removed += 1;
+            }
+        }
+        Ok(removed)
+    }
+    /// Persist any batched writes (graceful shutdown); no-op for write-through backends
+    async fn flush(&self) -> Result<(), GatewayError> {
+        Ok(())
+    }
+    /// Flush, then copy the backing files (sessions included when they share them)
+    /// to timestamped backups. Backends without files have nothing to copy.
+    async fn backup(&self) -> Result<Vec<PathBuf>, GatewayError> {
+        Ok(Vec::new())
+    }
+    /// Whether the backend can serve requests right now (`GET /health/detailed`).
+    /// Backends with nothing that can go away always can.
+    async fn health_check(&self) -> Result<(), GatewayError> {
+        Ok(())
+    }
 }
 
-#[allow(dead_code)]
 #[async_trait]
 pub trait SessionStoreTrait: Send + Sync {
     async fn get_session(&self, session_id: &str) -> Result<Option<AgentSession>, GatewayError>;
+    /// Every stored session, expired ones included where the backend still has them
+    async fn list_sessions(&self) -> Result<Vec<AgentSession>, GatewayError>;
+    /// Unexpired sessions of all agents. Backends override the full copy with a count.
+    async fn count_live_sessions(&self) -> Result<usize, GatewayError> {
+        Ok(self.list_sessions().await?.iter().filter(|s| !s.is_expired()).count())
+    }
     async fn create_session(&self, session: AgentSession) -> Result<AgentSession, GatewayError>;
     async fn delete_session(&self, session_id: &str) -> Result<(), GatewayError>;
+    /// Revoke every session belonging to these agents, returning how many were removed
+    async fn delete_sessions_for_agents(&self, agent_ids: &[Uuid]) -> Result<usize, GatewayError>;
+    /// Revoke every session of every agent (`POST /admin/sessions/invalidate-all`),
+    /// returning how many were removed. Backends override the one-by-one delete.
+    async fn delete_all_sessions(&self) -> Result<usize, GatewayError> {
+        let sessions = self.list_sessions().await?;
+        for session in &sessions {
+            self.delete_session(&session.session_id).await?;
+        }
+        Ok(sessions.len())
+    }
+    /// One agent's sessions, expired ones included where the backend still has them.
+    /// Backends that index sessions by agent override this scan.
+    async fn sessions_for_agent(&self, agent_id: Uuid) -> Result<Vec<AgentSession>, GatewayError> {
+        let mut sessions = self.list_sessions().await?;
+        sessions.retain(|session| session.agent_id == agent_id);
+        Ok(sessions)
+    }
+    /// Delete every expired session, of all agents, returning how many were
+    /// removed (`SESSION_CLEANUP_INTERVAL_SECS`, `DELETE /admin/sessions/expired`)
+    async fn delete_expired_sessions(&self) -> Result<usize, GatewayError> {
+        let expired: Vec<_> = self
+            .list_sessions()
+            .await?
+            .into_iter()
+            .filter(AgentSession::is_expired)
+            .collect();
+        for session in &expired {
+            self.delete_session(&session.session_id).await?;
+        }
+        Ok(expired.len())
+    }
+    /// Store `session` after dropping the agent's expired sessions and, when it
+    /// already has `max_sessions` live ones, its oldest (by `created_at`) to make
+    /// room. `0` means no limit. Returns the session and how many were evicted.
+    /// This default takes no lock, so concurrent calls for one agent can overshoot
+    /// the cap; backends that can check and insert atomically override it.
+    async fn create_session_capped(
+        &self,
+        session: AgentSession,
+        max_sessions: u32,
+    ) -> Result<(AgentSession, usize), GatewayError> {
+        let (expired, evicted) = sessions_to_drop(self.sessions_for_agent(session.agent_id).await?, max_sessions);
+        for session_id in expired.iter().chain(&evicted) {
+            self.delete_session(session_id).await?;
+        }
+        Ok((self.create_session(session).await?, evicted.len()))
+    }
+    /// Whether the backend can serve requests right now (`GET /health/detailed`)
+    async fn health_check(&self) -> Result<(), GatewayError> {
+        Ok(())
+    }
 }
 
 #[allow(dead_code)]
@@ -32,4 +237,6 @@ pub trait CredentialStoreTrait: Send + Sync {
     ) -> Result<Option<ServiceCredential>, GatewayError>;
     async fn store_credential(&self, credential: ServiceCredential) -> Result<(), GatewayError>;
     async fn delete_credential(&self, agent_id: Uuid, service_id: &str) -> Result<(), GatewayError>;
+    /// Remove all credentials held by these agents, returning how many were removed
+    async fn delete_credentials_for_agents(&self, agent_ids: &[Uuid]) -> Result<usize, GatewayError>;
 }
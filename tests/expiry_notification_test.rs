@@ -0,0 +1,83 @@
+// ===================================================================
+// Agent expiry webhook tests (POST /admin/agents/{id}/test-notification)
+// ===================================================================
+
+use axum::{
+    body::Body,
+    http::{Request, StatusCode},
+    Router,
+};
+use serde_json::{json, Value};
+use tower::ServiceExt;
+use uuid::Uuid;
+use wiremock::matchers::{body_partial_json, method, path};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+
+use sec_ai_agent_gw::config::Settings;
+use sec_ai_agent_gw::models::Agent;
+use sec_ai_agent_gw::routes::admin_routes;
+use sec_ai_agent_gw::state::AppState;
+
+const ADMIN_KEY: &str = "test-admin-key";
+
+fn test_state(webhook_url: Option<String>) -> AppState {
+    std::env::set_var("ENCRYPTION_KEY", "test-encryption-key-32chars!!");
+    std::env::set_var("SESSION_SECRET", "test-session-secret");
+    std::env::set_var("ADMIN_API_KEY", ADMIN_KEY);
+    let mut settings = Settings::from_env();
+    settings.expiry_webhook_url = webhook_url;
+    AppState::for_tests_with(settings)
+}
+
+async fn post(app: Router, uri: &str, admin_key: Option<&str>) -> (StatusCode, Value) {
+    let mut request = Request::builder().method("POST").uri(uri);
+    if let Some(key) = admin_key {
+        request = request.header("X-Admin-Key", key);
+    }
+    let response = app.oneshot(request.body(Body::empty()).unwrap()).await.unwrap();
+    let status = response.status();
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+        .await
+        .unwrap();
+    (status, serde_json::from_slice(&body).unwrap_or(json!({})))
+}
+
+// ===================================================================
+// TEST: Test Notification
+// Sends a webhook flagged as a test for any agent, even one far from expiry.
+// Expects: 200 and one delivery; 401 without the admin key; 404 for an
+// unknown agent; 400 when no webhook is configured.
+// ===================================================================
+#[tokio::test]
+async fn test_notification_endpoint() {
+    let hook = MockServer::start().await;
+    let state = test_state(Some(format!("{}/hook", hook.uri())));
+    let agent = Agent::with_lifespan("nightly-batch".to_string(), "".to_string(), 90);
+    state.agents.create_agent(agent.clone()).await.unwrap();
+
+    Mock::given(method("POST"))
+        .and(path("/hook"))
+        .and(body_partial_json(json!({ "agent_id": agent.id, "name": "nightly-batch", "test": true })))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&hook)
+        .await;
+
+    let app = Router::new().nest("/admin", admin_routes()).with_state(state);
+    let uri = format!("/admin/agents/{}/test-notification", agent.id);
+
+    let (status, body) = post(app.clone(), &uri, Some(ADMIN_KEY)).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["delivered"], true);
+
+    let (status, _) = post(app.clone(), &uri, None).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+    let unknown = format!("/admin/agents/{}/test-notification", Uuid::new_v4());
+    let (status, _) = post(app, &unknown, Some(ADMIN_KEY)).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+
+    let disabled = Router::new().nest("/admin", admin_routes()).with_state(test_state(None));
+    let (status, _) = post(disabled, &uri, Some(ADMIN_KEY)).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+}
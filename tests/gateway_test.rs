@@ -105,6 +105,40 @@ async fn test_rate_limiter_separate_agent_limits() {
     assert!(limiter.check_agent("agent-b").await.is_ok());
 }
 
+// ===================================================================
+// TEST: Rate Limiter - Agents in the same group share one quota
+// ===================================================================
+#[tokio::test]
+async fn test_rate_limiter_group_shared_quota() {
+    use std::collections::HashMap;
+    use sec_ai_agent_gw::gateway::{RateLimitConfig, RateLimiter};
+
+    let mut groups = HashMap::new();
+    groups.insert(
+        "workers".to_string(),
+        RateLimitConfig {
+            requests: 5,
+            window: Duration::from_secs(60),
+        },
+    );
+    let limiter = RateLimiter::with_group_limits(groups);
+
+    // Two agents in the same group split the 5 request budget
+    for _ in 0..3 {
+        assert!(limiter.check_agent_with_group("worker-1", &limiter.agent_limit, Some("workers")).await.is_ok());
+    }
+    for _ in 0..2 {
+        assert!(limiter.check_agent_with_group("worker-2", &limiter.agent_limit, Some("workers")).await.is_ok());
+    }
+
+    // 6th combined request is rejected for either agent
+    assert!(limiter.check_agent_with_group("worker-1", &limiter.agent_limit, Some("workers")).await.is_err());
+    assert!(limiter.check_agent_with_group("worker-2", &limiter.agent_limit, Some("workers")).await.is_err());
+
+    // An ungrouped agent is unaffected
+    assert!(limiter.check_agent_with_group("solo", &limiter.agent_limit, None).await.is_ok());
+}
+
 // ===================================================================
 // TEST: Encryption - Round trip encrypt/decrypt
 // ===================================================================
@@ -0,0 +1,7 @@
+// ===================================================================
+// End-to-end proxy tests: a real gateway router in front of a wiremock
+// upstream. Run with `cargo test --test integration`.
+// ===================================================================
+
+mod mock_server;
+mod proxy_flow;
@@ -0,0 +1,191 @@
+// ===================================================================
+// Mock upstream + gateway setup shared by the integration tests
+// ===================================================================
+
+use axum::{
+    body::Body,
+    http::{Request, StatusCode},
+    Router,
+};
+use serde_json::{json, Value};
+use std::path::PathBuf;
+use std::sync::Arc;
+use tempfile::TempDir;
+use tower::ServiceExt;
+use wiremock::MockServer;
+
+use sec_ai_agent_gw::audit::{assign_request_id, AuditStore, AuditWriter, AuditWriterConfig};
+use sec_ai_agent_gw::config::{RateLimitConfig, Settings, StorageBackend};
+use sec_ai_agent_gw::gateway::{encrypt, StatsDClient};
+use sec_ai_agent_gw::routes::{admin_routes, auth_routes, health_routes, proxy_routes};
+use sec_ai_agent_gw::state::AppState;
+
+pub const SERVICE_ID: &str = "mock";
+/// Token stored for the mock service; the upstream should only ever see this one
+pub const UPSTREAM_TOKEN: &str = "upstream-token";
+/// X-Admin-Key for the /admin routes
+pub const ADMIN_KEY: &str = "integration-admin-key";
+const ENCRYPTION_KEY: &str = "integration-encryption-key-32ch!";
+
+/// Changes to the default single-service gateway
+#[derive(Default)]
+pub struct GatewayOptions {
+    /// Fields merged over the mock service's config (e.g. `auth`)
+    pub service: Value,
+    /// Shared rate limit groups agents can be created in
+    pub rate_limit_groups: Vec<(&'static str, RateLimitConfig)>,
+    /// Keep users, agents and sessions in files here instead of in memory
+    pub data_dir: Option<PathBuf>,
+    /// Write the proxy audit trail here (off by default, like the memory backend)
+    pub audit_dir: Option<PathBuf>,
+    /// Aggregate limit over all agents of one user
+    pub user_rate_limit: Option<RateLimitConfig>,
+    /// Write services.json and credentials.json here, to edit and reload them
+    pub config_dir: Option<PathBuf>,
+    /// Push per-request metrics to a StatsD collector at this `host:port`
+    pub statsd_addr: Option<String>,
+}
+
+/// Gateway whose only service proxies to a fresh mock upstream
+pub async fn setup_gateway_with_mock_upstream() -> (Router, MockServer) {
+    setup_gateway_with(|_| GatewayOptions::default()).await
+}
+
+/// `setup_gateway_with_mock_upstream`, with options built from the upstream
+/// (for config that points back at it, like a token URL)
+pub async fn setup_gateway_with(
+    options: impl FnOnce(&MockServer) -> GatewayOptions,
+) -> (Router, MockServer) {
+    let (app, _, upstream) = setup_gateway_and_state(options).await;
+    (app, upstream)
+}
+
+/// `setup_gateway_with`, also returning the state behind the router (to flush stores)
+pub async fn setup_gateway_and_state(
+    options: impl FnOnce(&MockServer) -> GatewayOptions,
+) -> (Router, AppState, MockServer) {
+    let upstream = MockServer::start().await;
+    let options = options(&upstream);
+    // Services and credentials are read once at startup; the memory backend
+    // never writes back, so the directory can go once the state exists
+    let dir = TempDir::new().unwrap();
+
+    let mut service = json!({
+        "id": SERVICE_ID,
+        "name": "Mock",
+        "description": "",
+        "base_url": upstream.uri(),
+        "auth_type": "bearer_token",
+        "endpoints": [],
+        "rate_limit": { "requests": 100, "window_secs": 60 }
+    });
+    if let Value::Object(overrides) = options.service {
+        service.as_object_mut().unwrap().extend(overrides);
+    }
+    let credentials = json!({ "credentials": [{
+        "service_id": SERVICE_ID,
+        "access_token": encrypt(UPSTREAM_TOKEN, ENCRYPTION_KEY).unwrap(),
+        "refresh_token": null,
+        "expires_at": null,
+        "scopes": [],
+        "encrypted": true
+    }]});
+    let config_dir = options.config_dir.as_deref().unwrap_or(dir.path());
+    let services_path = config_dir.join("services.json");
+    let credentials_path = config_dir.join("credentials.json");
+    std::fs::write(&services_path, json!({ "services": [service] }).to_string()).unwrap();
+    std::fs::write(&credentials_path, credentials.to_string()).unwrap();
+
+    std::env::set_var("ENCRYPTION_KEY", ENCRYPTION_KEY);
+    std::env::set_var("SESSION_SECRET", "integration-session-secret");
+    let mut settings = Settings::from_env();
+    settings.encryption_key = ENCRYPTION_KEY.to_string();
+    settings.services_config_path = services_path.to_string_lossy().to_string();
+    settings.credentials_path = credentials_path.to_string_lossy().to_string();
+    settings.ssrf_allowlist = vec!["127.0.0.1".to_string()];
+    settings.expiry_webhook_url = None;
+    settings.admin_api_key = Some(ADMIN_KEY.to_string());
+    settings.rate_limit_groups = options
+        .rate_limit_groups
+        .into_iter()
+        .map(|(group, limit)| (group.to_string(), limit))
+        .collect();
+    if let Some(limit) = options.user_rate_limit {
+        settings.user_rate_limit = limit;
+    }
+
+    let mut state = match options.data_dir {
+        Some(data_dir) => {
+            settings.storage_backend = StorageBackend::File;
+            settings.users_path = data_dir.join("users.json").to_string_lossy().to_string();
+            settings.agents_path = data_dir.join("agents.json").to_string_lossy().to_string();
+            settings.sessions_path = data_dir.join("sessions.json").to_string_lossy().to_string();
+            AppState::new(settings).unwrap()
+        }
+        None => AppState::for_tests_with(settings),
+    };
+    if let Some(audit_dir) = options.audit_dir {
+        let store = Arc::new(AuditStore::new(audit_dir));
+        state.audit_writer = Some(AuditWriter::new(store.clone(), AuditWriterConfig::default()));
+        state.audit = Some(store);
+    }
+    state.statsd = options.statsd_addr.map(|addr| Arc::new(StatsDClient::new(addr)));
+
+    let app = Router::new()
+        .merge(health_routes())
+        .nest("/auth", auth_routes())
+        .nest("/api", proxy_routes())
+        .nest("/admin", admin_routes())
+        .layer(axum::middleware::from_fn(assign_request_id))
+        .with_state(state.clone());
+    (app, state, upstream)
+}
+
+/// Register a user and give them an agent on the mock service; returns its session id
+pub async fn create_agent_session(app: &Router, rate_limit_group: Option<&str>) -> String {
+    let (status, user) = send(
+        app,
+        post_json("/auth/register", json!({ "username": "tester", "email": "tester@example.com" })),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK, "register failed: {}", user);
+
+    let agent = json!({
+        "user_id": user["user_id"],
+        "agent_name": "integration-agent",
+        "agent_description": "",
+        "services": [SERVICE_ID],
+        "rate_limit_group": rate_limit_group,
+    });
+    let (status, agent) = send(app, post_json("/auth/agent", agent)).await;
+    assert_eq!(status, StatusCode::OK, "agent creation failed: {}", agent);
+    agent["session_id"].as_str().unwrap().to_string()
+}
+
+/// GET through the proxy to `path` on the mock service
+pub fn proxy_get(session_id: Option<&str>, path: &str) -> Request<Body> {
+    let mut request = Request::builder().uri(format!("/api/{}{}", SERVICE_ID, path));
+    if let Some(session_id) = session_id {
+        request = request.header("X-Session-ID", session_id);
+    }
+    request.body(Body::empty()).unwrap()
+}
+
+pub fn post_json(uri: &str, body: Value) -> Request<Body> {
+    Request::builder()
+        .method("POST")
+        .uri(uri)
+        .header("Content-Type", "application/json")
+        .body(Body::from(body.to_string()))
+        .unwrap()
+}
+
+/// Status and JSON body (`{}` when the body isn't JSON)
+pub async fn send(app: &Router, request: Request<Body>) -> (StatusCode, Value) {
+    let response = app.clone().oneshot(request).await.unwrap();
+    let status = response.status();
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+        .await
+        .unwrap();
+    (status, serde_json::from_slice(&body).unwrap_or(json!({})))
+}
@@ -0,0 +1,70 @@
+// ===================================================================
+// Redis session store tests
+// Need a live Redis, so they are ignored by default. Run with:
+//   docker run -d -p 6379:6379 redis:7
+//   REDIS_URL=redis://localhost:6379 \
+//     cargo test --features redis --test redis_test -- --ignored
+// ===================================================================
+#![cfg(feature = "redis")]
+
+use chrono::{Duration, Utc};
+use uuid::Uuid;
+
+use sec_ai_agent_gw::auth::create_session;
+use sec_ai_agent_gw::storage::{RedisSessionStore, SessionStoreTrait};
+
+fn open() -> RedisSessionStore {
+    let url = std::env::var("REDIS_URL").expect("REDIS_URL must be set");
+    RedisSessionStore::open(&url).expect("Invalid REDIS_URL")
+}
+
+// ===================================================================
+// TEST: A session created through one store instance is visible to
+// another (i.e. to another gateway replica).
+// ===================================================================
+#[tokio::test]
+#[ignore]
+async fn test_redis_sessions_are_shared() {
+    let (a, b) = (open(), open());
+    let session = a.create_session(create_session(Uuid::new_v4(), 3600)).await.unwrap();
+
+    let loaded = b.get_session(&session.session_id).await.unwrap().unwrap();
+    assert_eq!(loaded.agent_id, session.agent_id);
+
+    b.delete_session(&session.session_id).await.unwrap();
+    assert!(a.get_session(&session.session_id).await.unwrap().is_none());
+}
+
+// ===================================================================
+// TEST: Redis TTL removes the session once expires_at passes.
+// ===================================================================
+#[tokio::test]
+#[ignore]
+async fn test_redis_session_expires_with_ttl() {
+    let store = open();
+    let mut session = create_session(Uuid::new_v4(), 1);
+    session.expires_at = Utc::now() + Duration::seconds(1);
+    let session = store.create_session(session).await.unwrap();
+
+    tokio::time::sleep(std::time::Duration::from_millis(2100)).await;
+    assert!(store.get_session(&session.session_id).await.unwrap().is_none());
+}
+
+// ===================================================================
+// TEST: All sessions of an agent are revoked via the per-agent set.
+// ===================================================================
+#[tokio::test]
+#[ignore]
+async fn test_redis_delete_sessions_for_agents() {
+    let store = open();
+    let (agent, other) = (Uuid::new_v4(), Uuid::new_v4());
+    let first = store.create_session(create_session(agent, 3600)).await.unwrap();
+    store.create_session(create_session(agent, 3600)).await.unwrap();
+    let kept = store.create_session(create_session(other, 3600)).await.unwrap();
+
+    assert_eq!(store.delete_sessions_for_agents(&[agent]).await.unwrap(), 2);
+    assert!(store.get_session(&first.session_id).await.unwrap().is_none());
+    assert!(store.get_session(&kept.session_id).await.unwrap().is_some());
+
+    store.delete_sessions_for_agents(&[other]).await.unwrap();
+}
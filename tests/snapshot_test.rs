@@ -0,0 +1,218 @@
+// ===================================================================
+// Data export / import tests
+// Run in their own process: they point SERVICES_CONFIG_PATH at a mock upstream.
+// ===================================================================
+
+use axum::{
+    body::Body,
+    http::{Request, StatusCode},
+    Router,
+};
+use serde_json::{json, Value};
+use tempfile::TempDir;
+use tower::ServiceExt;
+use wiremock::matchers::{header, method, path};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+
+use sec_ai_agent_gw::config::{Settings, StorageBackend};
+use sec_ai_agent_gw::gateway::encrypt;
+use sec_ai_agent_gw::models::{Agent, User};
+use sec_ai_agent_gw::routes::{admin_routes, proxy_routes};
+use sec_ai_agent_gw::state::AppState;
+
+const ADMIN_KEY: &str = "test-admin-key";
+const SOURCE_KEY: &str = "source-encryption-key-32-chars!!";
+const TARGET_KEY: &str = "target-encryption-key-32-chars!!";
+
+/// Settings for a gateway whose only service is the mock upstream
+fn settings(dir: &TempDir, upstream: &MockServer, key: &str, credentials: Value) -> Settings {
+    let services = json!({
+        "services": [{
+            "id": "mock",
+            "name": "Mock",
+            "description": "",
+            "base_url": upstream.uri(),
+            "auth_type": "bearer_token",
+            "endpoints": [],
+            "rate_limit": { "requests": 100, "window_secs": 60 }
+        }]
+    });
+    let services_path = dir.path().join("services.json");
+    let credentials_path = dir.path().join(format!("credentials-{}.json", &key[..6]));
+    std::fs::write(&services_path, services.to_string()).unwrap();
+    std::fs::write(&credentials_path, credentials.to_string()).unwrap();
+
+    std::env::set_var("ENCRYPTION_KEY", key);
+    std::env::set_var("SESSION_SECRET", "test-session-secret");
+    std::env::set_var("ADMIN_API_KEY", ADMIN_KEY);
+    std::env::set_var("SSRF_PROTECTION_ALLOWLIST", "127.0.0.1");
+    let mut settings = Settings::from_env();
+    settings.encryption_key = key.to_string();
+    settings.services_config_path = services_path.to_string_lossy().to_string();
+    settings.credentials_path = credentials_path.to_string_lossy().to_string();
+    settings
+}
+
+fn app(state: AppState) -> Router {
+    Router::new()
+        .nest("/admin", admin_routes())
+        .nest("/api", proxy_routes())
+        .with_state(state)
+}
+
+async fn send(app: Router, request: Request<Body>) -> (StatusCode, Value) {
+    let response = app.oneshot(request).await.unwrap();
+    let status = response.status();
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+        .await
+        .unwrap();
+    (status, serde_json::from_slice(&body).unwrap_or(json!({})))
+}
+
+fn admin(method: &str, uri: &str, body: Option<Value>) -> Request<Body> {
+    Request::builder()
+        .method(method)
+        .uri(uri)
+        .header("X-Admin-Key", ADMIN_KEY)
+        .header("Content-Type", "application/json")
+        .body(body.map(|b| Body::from(b.to_string())).unwrap_or_default())
+        .unwrap()
+}
+
+// ===================================================================
+// TEST: A snapshot exported from one gateway and imported into a fresh
+// one (with a different encryption key) lets the agent keep proxying.
+// ===================================================================
+#[tokio::test]
+async fn test_export_import_round_trip() {
+    let upstream = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/ping"))
+        .and(header("Authorization", "Bearer upstream-token"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({"pong": true})))
+        .mount(&upstream)
+        .await;
+    let dir = TempDir::new().unwrap();
+
+    // === Source gateway with a user, an agent and a live session ===
+    let credentials = json!({ "credentials": [{
+        "service_id": "mock",
+        "access_token": encrypt("upstream-token", SOURCE_KEY).unwrap(),
+        "refresh_token": null,
+        "expires_at": null,
+        "scopes": [],
+        "encrypted": true
+    }]});
+    let source = AppState::for_tests_with(settings(&dir, &upstream, SOURCE_KEY, credentials));
+    let mut user = User::new("owner".to_string(), "owner@example.com".to_string());
+    let mut agent = Agent::with_lifespan("mover".to_string(), "".to_string(), 30);
+    agent.add_service("mock".to_string());
+    user.agents.push(agent.id);
+    source.users.create_user(user).await.unwrap();
+    source.agents.create_agent(agent.clone()).await.unwrap();
+    let session = source.start_session(agent.id).await.unwrap();
+
+    let (status, snapshot) = send(app(source), admin("GET", "/admin/export", None)).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(snapshot["version"], 1);
+    assert!(!snapshot.to_string().contains("upstream-token"), "credentials stay encrypted");
+
+    // === Fresh target gateway with its own key ===
+    let target = AppState::for_tests_with(settings(
+        &dir,
+        &upstream,
+        TARGET_KEY,
+        json!({ "credentials": [] }),
+    ));
+    let import = json!({
+        "snapshot": snapshot,
+        "mode": "replace",
+        "source_encryption_key": SOURCE_KEY,
+    });
+    let (status, summary) = send(app(target.clone()), admin("POST", "/admin/import", Some(import))).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(
+        (summary["users"].as_u64(), summary["agents"].as_u64(), summary["sessions"].as_u64()),
+        (Some(1), Some(1), Some(1))
+    );
+
+    // === The imported session proxies with the re-encrypted credential ===
+    let request = Request::builder()
+        .uri("/api/mock/ping")
+        .header("X-Session-ID", &session.session_id)
+        .body(Body::empty())
+        .unwrap();
+    let (status, body) = send(app(target), request).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["pong"], true);
+}
+
+// ===================================================================
+// TEST: Import rejects a wrong source key and unknown versions; both
+// endpoints require the admin key.
+// ===================================================================
+#[tokio::test]
+async fn test_import_validation_and_auth() {
+    let upstream = MockServer::start().await;
+    let dir = TempDir::new().unwrap();
+    let credentials = json!({ "credentials": [{
+        "service_id": "mock",
+        "access_token": encrypt("upstream-token", SOURCE_KEY).unwrap(),
+        "refresh_token": null,
+        "expires_at": null,
+        "scopes": [],
+        "encrypted": true
+    }]});
+    let state = AppState::for_tests_with(settings(&dir, &upstream, SOURCE_KEY, credentials));
+
+    let (_, snapshot) = send(app(state.clone()), admin("GET", "/admin/export", None)).await;
+
+    let wrong_key = json!({ "snapshot": snapshot, "source_encryption_key": TARGET_KEY });
+    let (status, _) = send(app(state.clone()), admin("POST", "/admin/import", Some(wrong_key))).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+
+    let mut future = snapshot.clone();
+    future["version"] = json!(99);
+    let (status, _) = send(app(state.clone()), admin("POST", "/admin/import", Some(json!({ "snapshot": future })))).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+
+    let unauthenticated = Request::builder().uri("/admin/export").body(Body::empty()).unwrap();
+    let (status, _) = send(app(state), unauthenticated).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+}
+
+// ===================================================================
+// TEST: POST /admin/backup copies every store file and the credentials
+// file to a timestamped backup holding the current data.
+// ===================================================================
+#[tokio::test]
+async fn test_backup_endpoint_copies_store_files() {
+    let upstream = MockServer::start().await;
+    let dir = TempDir::new().unwrap();
+    let mut settings = settings(&dir, &upstream, SOURCE_KEY, json!({ "credentials": [] }));
+    settings.storage_backend = StorageBackend::File;
+    settings.users_path = dir.path().join("users.json").to_string_lossy().to_string();
+    settings.agents_path = dir.path().join("agents.json").to_string_lossy().to_string();
+    settings.sessions_path = dir.path().join("sessions.json").to_string_lossy().to_string();
+    settings.admin_audit_log_path = Some(dir.path().join("admin_audit.jsonl").to_string_lossy().to_string());
+    let state = AppState::new(settings).unwrap();
+    let agent = state.agents.create_agent(Agent::new("backed-up".to_string(), "".to_string())).await.unwrap();
+
+    let unauthenticated = Request::builder().method("POST").uri("/admin/backup").body(Body::empty()).unwrap();
+    let (status, _) = send(app(state.clone()), unauthenticated).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+    let (status, body) = send(app(state), admin("POST", "/admin/backup", None)).await;
+    assert_eq!(status, StatusCode::OK);
+    let backups: Vec<String> = serde_json::from_value(body["backups"].clone()).unwrap();
+    for file in ["users.json", "agents.json", "sessions.json", "credentials-source.json"] {
+        assert!(
+            backups.iter().any(|b| b.contains(&format!("{}.bak.", file))),
+            "no backup of {} in {:?}",
+            file,
+            backups
+        );
+    }
+    let agents_backup = backups.iter().find(|b| b.contains("agents.json.bak.")).unwrap();
+    assert!(std::fs::read_to_string(agents_backup).unwrap().contains(&agent.id.to_string()));
+}
@@ -1,25 +1,32 @@
 use axum::{
     body::Body,
+    extract::ConnectInfo,
     http::{Request, StatusCode},
 };
 use serde_json::{json, Value};
+use std::net::SocketAddr;
 use tower::ServiceExt;
 use uuid::Uuid;
 
-use sec_ai_agent_gw::config::Settings;
-use sec_ai_agent_gw::routes::auth_routes;
+use sec_ai_agent_gw::config::{RateLimitConfig, Settings, StorageBackend};
+use sec_ai_agent_gw::models::ServiceCredential;
+use sec_ai_agent_gw::routes::{admin_routes, auth_routes, proxy_routes};
 use sec_ai_agent_gw::state::AppState;
 
-fn setup_test_app() -> axum::Router {
+const TEST_ADMIN_KEY: &str = "test-admin-key";
+
+fn set_test_env() {
     std::env::set_var("ENCRYPTION_KEY", "test-encryption-key-32chars!!");
     std::env::set_var("SESSION_SECRET", "test-session-secret");
     std::env::set_var("SERVICES_CONFIG_PATH", "config/services.json");
     std::env::set_var("CREDENTIALS_PATH", "data/credentials.json");
+    std::env::set_var("ADMIN_API_KEY", TEST_ADMIN_KEY);
+    std::env::set_var("STORAGE_BACKEND", "memory");
+}
 
-    let settings = Settings::from_env();
-    let state = AppState::new(settings).expect("Failed to create test state");
-
-    auth_routes().with_state(state)
+fn setup_test_app() -> axum::Router {
+    set_test_env();
+    auth_routes().with_state(AppState::for_tests())
 }
 
 // === Generate unique email for each test run ===
@@ -49,6 +56,86 @@ async fn post_json(app: axum::Router, uri: &str, body: Value) -> (StatusCode, Va
     (status, json)
 }
 
+async fn get_with_headers(
+    app: axum::Router,
+    uri: &str,
+    headers: &[(&str, &str)],
+) -> StatusCode {
+    let mut builder = Request::builder().method("GET").uri(uri);
+    for (name, value) in headers {
+        builder = builder.header(*name, *value);
+    }
+
+    app.oneshot(builder.body(Body::empty()).unwrap())
+        .await
+        .unwrap()
+        .status()
+}
+
+async fn get_json_with_headers(
+    app: axum::Router,
+    uri: &str,
+    headers: &[(&str, &str)],
+) -> (StatusCode, Value) {
+    let mut builder = Request::builder().method("GET").uri(uri);
+    for (name, value) in headers {
+        builder = builder.header(*name, *value);
+    }
+
+    let response = app.oneshot(builder.body(Body::empty()).unwrap()).await.unwrap();
+    let status = response.status();
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+        .await
+        .unwrap();
+    (status, serde_json::from_slice(&body).unwrap_or(json!({})))
+}
+
+async fn delete_with_headers(
+    app: axum::Router,
+    uri: &str,
+    headers: &[(&str, &str)],
+) -> (StatusCode, Value) {
+    let mut builder = Request::builder().method("DELETE").uri(uri);
+    for (name, value) in headers {
+        builder = builder.header(*name, *value);
+    }
+
+    let response = app.oneshot(builder.body(Body::empty()).unwrap()).await.unwrap();
+    let status = response.status();
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+        .await
+        .unwrap();
+    (status, serde_json::from_slice(&body).unwrap_or(json!({})))
+}
+
+// === Register a user and create one agent, returning (agent_id, session_id) ===
+async fn create_agent(app: axum::Router) -> (String, String) {
+    let (_, user) = post_json(
+        app.clone(),
+        "/register",
+        json!({ "username": "owner", "email": unique_email() }),
+    )
+    .await;
+
+    let (status, agent) = post_json(
+        app,
+        "/agent",
+        json!({
+            "user_id": user["user_id"],
+            "agent_name": "Owned Agent",
+            "agent_description": "Ownership test",
+            "services": ["payment"]
+        }),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+
+    (
+        agent["agent_id"].as_str().unwrap().to_string(),
+        agent["session_id"].as_str().unwrap().to_string(),
+    )
+}
+
 // ===================================================================
 // TEST: User Registration
 // Creates a new user with username and email.
@@ -74,6 +161,120 @@ async fn test_user_registration() {
     assert_eq!(body["username"], "testuser");
 }
 
+// ===================================================================
+// TEST: Email Verification
+// With REQUIRE_EMAIL_VERIFICATION, registration returns a code and the
+// user only exists once POST /verify-email sends it back.
+// Expects: wrong code 400, right code 200 then agents can be created,
+// reuse 404, expired 400, dropped after too many wrong codes.
+// ===================================================================
+#[tokio::test]
+async fn test_registration_requires_email_verification() {
+    set_test_env();
+    let mut settings = Settings::from_env();
+    settings.require_email_verification = true;
+    let app = auth_routes().with_state(AppState::for_tests_with(settings.clone()));
+    let email = unique_email();
+
+    let (status, pending) = post_json(
+        app.clone(),
+        "/register",
+        json!({ "username": "verified", "email": email }),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(pending["verification_required"], true);
+    let code = pending["verification_code"].as_str().unwrap().to_string();
+    assert_eq!(code.len(), 6);
+
+    // Not a user yet
+    let agent = json!({
+        "user_id": pending["user_id"],
+        "agent_name": "Verified Agent",
+        "agent_description": "Email verification test",
+        "services": ["payment"]
+    });
+    let (status, _) = post_json(app.clone(), "/agent", agent.clone()).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+
+    let wrong = if code == "000000" { "111111" } else { "000000" };
+    let (status, body) = post_json(
+        app.clone(),
+        "/verify-email",
+        json!({ "user_id": pending["user_id"], "code": wrong }),
+    )
+    .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(body["message"], "Invalid verification code");
+
+    let verify = json!({ "user_id": pending["user_id"], "code": code });
+    let (status, user) = post_json(app.clone(), "/verify-email", verify.clone()).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(user["user_id"], pending["user_id"]);
+    assert!(user.get("verification_code").is_none());
+
+    let (status, _) = post_json(app.clone(), "/agent", agent).await;
+    assert_eq!(status, StatusCode::OK);
+    let (status, _) = post_json(app.clone(), "/verify-email", verify).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    let (status, body) = post_json(
+        app.clone(),
+        "/register",
+        json!({ "username": "again", "email": email }),
+    )
+    .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(body["message"], "Email already registered");
+
+    // Too many wrong codes drop the registration
+    let (_, pending) = post_json(
+        app.clone(),
+        "/register",
+        json!({ "username": "guesser", "email": unique_email() }),
+    )
+    .await;
+    let code = pending["verification_code"].as_str().unwrap();
+    let wrong = if code == "000000" { "111111" } else { "000000" };
+    let guess = json!({ "user_id": pending["user_id"], "code": wrong });
+    for _ in 0..4 {
+        let (status, _) = post_json(app.clone(), "/verify-email", guess.clone()).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+    }
+    let (_, body) = post_json(app.clone(), "/verify-email", guess).await;
+    assert_eq!(body["message"], "Too many invalid verification codes; register again");
+    let (status, _) = post_json(
+        app,
+        "/verify-email",
+        json!({ "user_id": pending["user_id"], "code": code }),
+    )
+    .await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+
+    // Expired codes are refused, and without the stub no code is returned
+    settings.email_verification_ttl_secs = 0;
+    settings.email_verification_return_code = false;
+    let state = AppState::for_tests_with(settings);
+    let app = auth_routes().with_state(state.clone());
+    let (_, pending) = post_json(
+        app.clone(),
+        "/register",
+        json!({ "username": "late", "email": unique_email() }),
+    )
+    .await;
+    assert!(pending.get("verification_code").is_none());
+    let user_id: Uuid = pending["user_id"].as_str().unwrap().parse().unwrap();
+    let code = state.users.get_pending_user(user_id).await.unwrap().unwrap().code;
+    tokio::time::sleep(std::time::Duration::from_millis(5)).await;
+    let (status, body) = post_json(
+        app,
+        "/verify-email",
+        json!({ "user_id": user_id, "code": code }),
+    )
+    .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(body["message"], "Verification code expired; register again");
+}
+
 // ===================================================================
 // TEST: Agent Access Creation
 // Creates an agent with access to specific services for a user.
@@ -142,3 +343,1221 @@ async fn test_agent_creation_invalid_user() {
     assert_eq!(status, StatusCode::NOT_FOUND);
     assert!(body.get("error").is_some());
 }
+
+// ===================================================================
+// TEST: Agent Creation Deduplication
+// Repeating a create request returns the same agent; reusing the name
+// with other services is rejected; ALLOW_DUPLICATE_AGENT_NAMES opts out.
+// Expects: same agent_id with a new session, then 400, then a new agent.
+// ===================================================================
+#[tokio::test]
+async fn test_agent_creation_deduplicates_names() {
+    set_test_env();
+    let mut settings = Settings::from_env();
+    settings.allow_duplicate_names = false;
+    let app = auth_routes().with_state(AppState::for_tests_with(settings));
+
+    let (_, user) = post_json(
+        app.clone(),
+        "/register",
+        json!({ "username": "retry", "email": unique_email() }),
+    )
+    .await;
+    let create = |services: Value| {
+        json!({
+            "user_id": user["user_id"],
+            "agent_name": "Retried Agent",
+            "agent_description": "Dedup test",
+            "services": services
+        })
+    };
+
+    let (_, first) = post_json(app.clone(), "/agent", create(json!(["payment", "bank"]))).await;
+    let (status, retry) = post_json(app.clone(), "/agent", create(json!(["bank", "payment"]))).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(retry["agent_id"], first["agent_id"]);
+    assert_ne!(retry["session_id"], first["session_id"]);
+
+    let (status, body) = post_json(app.clone(), "/agent", create(json!(["payment"]))).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(body["message"], "Agent with this name already exists for user");
+
+    let mut settings = Settings::from_env();
+    settings.allow_duplicate_names = true;
+    let app = auth_routes().with_state(AppState::for_tests_with(settings));
+    let (_, user) = post_json(
+        app.clone(),
+        "/register",
+        json!({ "username": "twins", "email": unique_email() }),
+    )
+    .await;
+    let twin = json!({
+        "user_id": user["user_id"],
+        "agent_name": "Twin",
+        "agent_description": "Dedup opt-out",
+        "services": ["payment"]
+    });
+    let (_, first) = post_json(app.clone(), "/agent", twin.clone()).await;
+    let (status, second) = post_json(app, "/agent", twin).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_ne!(second["agent_id"], first["agent_id"]);
+}
+
+// ===================================================================
+// TEST: Agent Ownership Verification
+// Agent routes require the agent's own session or the admin key.
+// Expects: 401 without credentials, 403 for another agent's session,
+// 200 for own session and for the admin key.
+// ===================================================================
+#[tokio::test]
+async fn test_agent_route_ownership() {
+    let app = setup_test_app();
+    let (agent_id, session_id) = create_agent(app.clone()).await;
+    let (_, other_session) = create_agent(app.clone()).await;
+    let uri = format!("/agent/{}", agent_id);
+
+    let status = get_with_headers(app.clone(), &uri, &[]).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+    let status = get_with_headers(app.clone(), &uri, &[("X-Session-ID", &other_session)]).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+
+    let status = get_with_headers(app.clone(), &uri, &[("X-Session-ID", &session_id)]).await;
+    assert_eq!(status, StatusCode::OK);
+
+    let status = get_with_headers(app.clone(), &uri, &[("X-Admin-Key", TEST_ADMIN_KEY)]).await;
+    assert_eq!(status, StatusCode::OK);
+
+    let status = get_with_headers(app, &uri, &[("X-Admin-Key", "wrong-key")]).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+}
+
+// ===================================================================
+// TEST: Update Agent Metadata
+// PATCH changes only the given fields, for the owning session or admin.
+// Expects: the session keeps working; other agents and empty bodies are rejected.
+// ===================================================================
+#[tokio::test]
+async fn test_update_agent_metadata() {
+    let app = setup_test_app();
+    let (agent_id, session_id) = create_agent(app.clone()).await;
+    let (_, other_session) = create_agent(app.clone()).await;
+    let uri = format!("/agent/{}", agent_id);
+    let patch = |headers: Vec<(&'static str, String)>, body: Value| {
+        let mut builder = Request::builder()
+            .method("PATCH")
+            .uri(&uri)
+            .header("Content-Type", "application/json");
+        for (name, value) in headers {
+            builder = builder.header(name, value);
+        }
+        let request = builder.body(Body::from(body.to_string())).unwrap();
+        let app = app.clone();
+        async move {
+            let response = app.oneshot(request).await.unwrap();
+            let status = response.status();
+            let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+            (status, serde_json::from_slice::<Value>(&body).unwrap_or(json!({})))
+        }
+    };
+    let own = || vec![("X-Session-ID", session_id.clone())];
+
+    let (status, agent) = patch(own(), json!({ "description": "Reconciles invoices" })).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(agent["description"], "Reconciles invoices");
+    assert_eq!(agent["name"], "Owned Agent");
+
+    let admin = vec![("X-Admin-Key", TEST_ADMIN_KEY.to_string())];
+    let (status, agent) = patch(admin, json!({ "name": "Invoice Agent" })).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(agent["name"], "Invoice Agent");
+    assert_eq!(agent["description"], "Reconciles invoices");
+
+    let (status, _) = patch(vec![("X-Session-ID", other_session)], json!({ "name": "x" })).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    let (status, _) = patch(own(), json!({})).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    let (status, _) = patch(own(), json!({ "name": "  " })).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+
+    // Same key, same session
+    let (status, agent) =
+        get_json_with_headers(app.clone(), &uri, &[("X-Session-ID", &session_id)]).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(agent["name"], "Invoice Agent");
+}
+
+// ===================================================================
+// TEST: Scoped Service Grants
+// A grant may narrow an agent to some of a service's scopes; without
+// `scopes` it covers all of them, as before.
+// Expects: granted_scopes echoed by the grant and listed in service_access.
+// ===================================================================
+#[tokio::test]
+async fn test_grant_service_with_scopes() {
+    let app = setup_test_app();
+    let (agent_id, session_id) = create_agent(app.clone()).await;
+    let services = format!("/agent/{}/services", agent_id);
+    let grant = |body: Value| {
+        let request = Request::builder()
+            .method("POST")
+            .uri(&services)
+            .header("Content-Type", "application/json")
+            .header("X-Session-ID", &session_id)
+            .body(Body::from(body.to_string()))
+            .unwrap();
+        let app = app.clone();
+        async move {
+            let response = app.oneshot(request).await.unwrap();
+            let status = response.status();
+            let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+            (status, serde_json::from_slice::<Value>(&body).unwrap_or(json!({})))
+        }
+    };
+
+    let (status, granted) = grant(json!({ "service_id": "bank", "scopes": ["accounts:read", " accounts:read "] })).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(granted["granted_scopes"], json!(["accounts:read"]));
+    assert_eq!(granted["allowed_services"], json!(["payment", "bank"]));
+
+    let (status, _) = grant(json!({ "service_id": "bank", "scopes": [""] })).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+
+    let (status, agent) = get_json_with_headers(
+        app.clone(),
+        &format!("/agent/{}", agent_id),
+        &[("X-Session-ID", &session_id)],
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(
+        agent["service_access"],
+        json!([
+            { "service_id": "payment", "granted_scopes": [] },
+            { "service_id": "bank", "granted_scopes": ["accounts:read"] }
+        ])
+    );
+}
+
+// ===================================================================
+// TEST: Concurrent Agent Updates
+// Grant, grant and revoke race on one agent. Each handler saves against
+// the version it read and retries once on conflict.
+// Expects: every 200 is reflected in the final agent (no lost update);
+// a request that loses twice gets 409 instead of overwriting.
+// ===================================================================
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn test_concurrent_agent_updates_lose_nothing() {
+    set_test_env();
+    let state = AppState::for_tests();
+    let app = auth_routes().with_state(state.clone());
+
+    for _ in 0..20 {
+        let (agent_id, session_id) = create_agent(app.clone()).await;
+        let send = |method: &'static str, uri: String, body: Value| {
+            let (app, session_id) = (app.clone(), session_id.clone());
+            tokio::spawn(async move {
+                let request = Request::builder()
+                    .method(method)
+                    .uri(uri)
+                    .header("Content-Type", "application/json")
+                    .header("X-Session-ID", session_id)
+                    .body(Body::from(body.to_string()))
+                    .unwrap();
+                app.oneshot(request).await.unwrap().status()
+            })
+        };
+
+        let services = format!("/agent/{}/services", agent_id);
+        let grant_bank = send("POST", services.clone(), json!({ "service_id": "bank" }));
+        let grant_httpbin = send("POST", services.clone(), json!({ "service_id": "httpbin" }));
+        let revoke_payment = send("DELETE", format!("{}/payment", services), json!({}));
+
+        let (bank, httpbin, payment) = (
+            grant_bank.await.unwrap(),
+            grant_httpbin.await.unwrap(),
+            revoke_payment.await.unwrap(),
+        );
+        let agent_id: Uuid = agent_id.parse().unwrap();
+        let agent = state.agents.get_agent(agent_id).await.unwrap().unwrap();
+
+        for status in [bank, httpbin, payment] {
+            assert!(status == StatusCode::OK || status == StatusCode::CONFLICT, "{}", status);
+        }
+        let has = |service: &str| agent.can_access_service(service);
+        assert_eq!(has("bank"), bank == StatusCode::OK);
+        assert_eq!(has("httpbin"), httpbin == StatusCode::OK);
+        assert_eq!(has("payment"), payment != StatusCode::OK);
+        let applied = [bank, httpbin, payment].iter().filter(|s| **s == StatusCode::OK).count();
+        assert_eq!(agent.version, applied as u64);
+    }
+}
+
+// ===================================================================
+// TEST: Test State Isolation
+// AppState::for_tests() keeps users in memory.
+// Expects: the registered email never reaches data/users.json.
+// ===================================================================
+#[tokio::test]
+async fn test_for_tests_state_does_not_write_data_files() {
+    let app = setup_test_app();
+    let email = unique_email();
+
+    let (status, _) = post_json(
+        app,
+        "/register",
+        json!({ "username": "isolated", "email": email }),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+
+    let on_disk = std::fs::read_to_string("data/users.json").unwrap_or_default();
+    assert!(!on_disk.contains(&email));
+}
+
+// ===================================================================
+// TEST: STORAGE_BACKEND=memory
+// AppState::new on the memory backend creates no store files at all,
+// even for paths that don't exist yet.
+// ===================================================================
+#[tokio::test]
+async fn test_memory_backend_writes_nothing() {
+    set_test_env();
+    let dir = tempfile::TempDir::new().unwrap();
+    let mut settings = Settings::from_env();
+    settings.storage_backend = StorageBackend::Memory;
+    settings.users_path = dir.path().join("users.json").to_string_lossy().to_string();
+    settings.agents_path = dir.path().join("agents.json").to_string_lossy().to_string();
+    settings.sessions_path = dir.path().join("sessions.json").to_string_lossy().to_string();
+
+    let app = auth_routes().with_state(AppState::new(settings).unwrap());
+    let (_, user) = post_json(
+        app.clone(),
+        "/register",
+        json!({ "username": "ephemeral", "email": unique_email() }),
+    )
+    .await;
+    let (status, _) = post_json(
+        app,
+        "/agent",
+        json!({
+            "user_id": user["user_id"],
+            "agent_name": "Ephemeral Agent",
+            "agent_description": "",
+            "services": ["payment"]
+        }),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+
+    assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 0);
+}
+
+// ===================================================================
+// TEST: USERS_PATH / AGENTS_PATH are honored and missing directories
+// are created on startup.
+// ===================================================================
+#[tokio::test]
+async fn test_storage_paths_are_configurable() {
+    set_test_env();
+    let dir = tempfile::TempDir::new().unwrap();
+    let users_path = dir.path().join("volume/users/users.json");
+    let agents_path = dir.path().join("volume/agents/agents.json");
+
+    let mut settings = Settings::from_env();
+    settings.storage_backend = StorageBackend::File;
+    settings.users_path = users_path.to_string_lossy().to_string();
+    settings.agents_path = agents_path.to_string_lossy().to_string();
+    settings.sessions_path = dir.path().join("volume/sessions.json").to_string_lossy().to_string();
+
+    let state = AppState::new(settings).unwrap();
+    // Both files exist (and parse) before anything is written
+    let empty: Value = serde_json::from_str(&std::fs::read_to_string(&agents_path).unwrap()).unwrap();
+    assert_eq!(empty, json!({ "schema_version": 2, "agents": [] }));
+
+    let app = auth_routes().with_state(state.clone());
+    let email = unique_email();
+    let (_, user) = post_json(
+        app.clone(),
+        "/register",
+        json!({ "username": "mounted", "email": email }),
+    )
+    .await;
+    let (status, agent) = post_json(
+        app,
+        "/agent",
+        json!({
+            "user_id": user["user_id"],
+            "agent_name": "Mounted Agent",
+            "agent_description": "Custom paths",
+            "services": ["payment"]
+        }),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    state.agents.flush().await.unwrap();
+
+    assert!(std::fs::read_to_string(&users_path).unwrap().contains(&email));
+    let agent_id = agent["agent_id"].as_str().unwrap();
+    assert!(std::fs::read_to_string(&agents_path).unwrap().contains(agent_id));
+}
+
+// ===================================================================
+// TEST: Deleting a user cascades to their agents, sessions and
+// (with purge_credentials) per-agent credentials.
+// Expects: summary counts, agents gone, old sessions rejected by the proxy.
+// ===================================================================
+#[tokio::test]
+async fn test_delete_user_cascades() {
+    set_test_env();
+    let state = AppState::for_tests();
+    let app = auth_routes()
+        .nest("/api", proxy_routes())
+        .with_state(state.clone());
+
+    let (_, user) = post_json(
+        app.clone(),
+        "/register",
+        json!({ "username": "leaving", "email": unique_email() }),
+    )
+    .await;
+    let user_id = user["user_id"].as_str().unwrap().to_string();
+
+    let mut agents = Vec::new();
+    for name in ["First", "Second"] {
+        let (status, agent) = post_json(
+            app.clone(),
+            "/agent",
+            json!({
+                "user_id": user_id,
+                "agent_name": name,
+                "agent_description": "Offboarding test",
+                "services": ["payment"]
+            }),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        agents.push((
+            agent["agent_id"].as_str().unwrap().to_string(),
+            agent["session_id"].as_str().unwrap().to_string(),
+        ));
+    }
+
+    let now = chrono::Utc::now();
+    state
+        .agent_credentials
+        .as_ref()
+        .unwrap()
+        .store_credential(ServiceCredential {
+            id: Uuid::new_v4(),
+            agent_id: agents[0].0.parse().unwrap(),
+            service_id: "payment".to_string(),
+            access_token: "agent-token".to_string(),
+            refresh_token: None,
+            expires_at: None,
+            scopes: vec![],
+            created_at: now,
+            updated_at: now,
+        })
+        .await
+        .unwrap();
+
+    // Admin key is required
+    let uri = format!("/users/{}?purge_credentials=true", user_id);
+    let (status, _) = delete_with_headers(app.clone(), &uri, &[]).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+    let (status, summary) =
+        delete_with_headers(app.clone(), &uri, &[("X-Admin-Key", TEST_ADMIN_KEY)]).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(summary["agents_removed"], 2);
+    assert_eq!(summary["sessions_revoked"], 2);
+    assert_eq!(summary["credentials_removed"], 1);
+
+    for (agent_id, session_id) in &agents {
+        let uri = format!("/agent/{}", agent_id);
+        let status = get_with_headers(app.clone(), &uri, &[("X-Admin-Key", TEST_ADMIN_KEY)]).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+
+        let status =
+            get_with_headers(app.clone(), "/api/payment/v1/charges", &[("X-Session-ID", session_id)]).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+    }
+
+    // The user itself is gone
+    let (status, _) =
+        delete_with_headers(app, &format!("/users/{}", user_id), &[("X-Admin-Key", TEST_ADMIN_KEY)]).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+// ===================================================================
+// TEST: User deletion, and agent deletion/rotation through the admin key on
+// the /auth routes, land in the admin event log; the agent's own session doesn't
+// Expects: user_deleted with the summary counts, agent_key_rotated, agent_deleted.
+// ===================================================================
+#[tokio::test]
+async fn test_admin_key_deletions_are_recorded() {
+    use sec_ai_agent_gw::audit::admin_key_hash;
+
+    set_test_env();
+    let state = AppState::for_tests();
+    let app = auth_routes()
+        .nest("/admin", admin_routes())
+        .with_state(state.clone());
+    let admin = [("X-Admin-Key", TEST_ADMIN_KEY)];
+
+    // Deleted by its own session: not an admin operation
+    let (own_id, own_session) = create_agent(app.clone()).await;
+    let uri = format!("/agent/{}", own_id);
+    let (status, _) = delete_with_headers(app.clone(), &uri, &[("X-Session-ID", &own_session)]).await;
+    assert_eq!(status, StatusCode::OK);
+
+    let (agent_id, _) = create_agent(app.clone()).await;
+    let request = Request::builder()
+        .method("POST")
+        .uri(format!("/agent/{}/rotate", agent_id))
+        .header("X-Admin-Key", TEST_ADMIN_KEY)
+        .body(Body::empty())
+        .unwrap();
+    let response = app.clone().oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    let rotated: Value = serde_json::from_slice(&body).unwrap();
+    let rotated_id = rotated["agent_id"].as_str().unwrap().to_string();
+
+    let (status, _) = delete_with_headers(app.clone(), &format!("/agent/{}", rotated_id), &admin).await;
+    assert_eq!(status, StatusCode::OK);
+
+    let (_, user) = post_json(
+        app.clone(),
+        "/register",
+        json!({ "username": "leaving", "email": unique_email() }),
+    )
+    .await;
+    let user_id = user["user_id"].as_str().unwrap().to_string();
+    let (status, _) = post_json(
+        app.clone(),
+        "/agent",
+        json!({
+            "user_id": user_id,
+            "agent_name": "Last",
+            "agent_description": "Offboarding test",
+            "services": ["payment"]
+        }),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    let (status, _) = delete_with_headers(app.clone(), &format!("/users/{}", user_id), &admin).await;
+    assert_eq!(status, StatusCode::OK);
+
+    let (status, body) = get_json_with_headers(app.clone(), "/admin/audit/admin", &admin).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["total"], 3);
+    let events = body["events"].as_array().unwrap();
+    assert_eq!(events[0]["action"], "user_deleted");
+    assert_eq!(events[0]["resource_id"], user_id.as_str());
+    assert_eq!(events[0]["admin_key_hash"], admin_key_hash(TEST_ADMIN_KEY));
+    assert_eq!(
+        events[0]["details"],
+        json!({ "agents_removed": 1, "sessions_revoked": 1, "credentials_removed": 0 })
+    );
+    assert_eq!(events[1]["action"], "agent_deleted");
+    assert_eq!(events[1]["resource_id"], rotated_id.as_str());
+    assert_eq!(events[2]["action"], "agent_key_rotated");
+    assert_eq!(events[2]["resource_id"], agent_id.as_str());
+    assert_eq!(events[2]["details"]["new_agent_id"], rotated_id.as_str());
+}
+
+// ===================================================================
+// TEST: Deleting an agent, by its own session or through the admin route.
+// Expects: its sessions stop validating, the agent is 404 and gone from
+// its owner's list; unknown ids are 404.
+// ===================================================================
+#[tokio::test]
+async fn test_delete_agent_revokes_sessions() {
+    set_test_env();
+    let state = AppState::for_tests();
+    let app = auth_routes()
+        .nest("/admin", admin_routes())
+        .with_state(state.clone());
+    let admin = [("X-Admin-Key", TEST_ADMIN_KEY)];
+
+    let (own_id, own_session) = create_agent(app.clone()).await;
+    let (admin_id, admin_session) = create_agent(app.clone()).await;
+
+    let uri = format!("/agent/{}", own_id);
+    let (status, summary) = delete_with_headers(app.clone(), &uri, &[("X-Session-ID", &own_session)]).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(summary["agent_id"], own_id.as_str());
+    assert_eq!(summary["sessions_revoked"], 1);
+
+    let uri = format!("/admin/agents/{}", admin_id);
+    let (status, _) = delete_with_headers(app.clone(), &uri, &[]).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    let (status, summary) = delete_with_headers(app.clone(), &uri, &admin).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(summary["sessions_revoked"], 1);
+    let (status, _) = delete_with_headers(app.clone(), &uri, &admin).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+
+    for (agent_id, session_id) in [(&own_id, &own_session), (&admin_id, &admin_session)] {
+        assert!(matches!(
+            state.validate_session(session_id).await,
+            Err(sec_ai_agent_gw::error::GatewayError::Unauthorized(_))
+        ));
+        let status = get_with_headers(app.clone(), &format!("/agent/{}", agent_id), &admin).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+
+        let agent_id: Uuid = agent_id.parse().unwrap();
+        let owners = state.users.list_users().await.unwrap();
+        assert!(owners.iter().all(|user| !user.agents.contains(&agent_id)));
+    }
+}
+
+// ===================================================================
+// TEST: Admins view an agent in full and change its configuration
+// Expects: each field updated and echoed; nonsensical values rejected.
+// ===================================================================
+#[tokio::test]
+async fn test_admin_updates_agent_config() {
+    use chrono::{DateTime, Duration, Utc};
+
+    set_test_env();
+    let state = AppState::for_tests();
+    let app = auth_routes()
+        .nest("/admin", admin_routes())
+        .with_state(state.clone());
+    let (agent_id, _) = create_agent(app.clone()).await;
+    let uri = format!("/admin/agents/{}", agent_id);
+    let patch = |headers: Vec<(&'static str, &'static str)>, body: Value| {
+        let mut builder = Request::builder()
+            .method("PATCH")
+            .uri(&uri)
+            .header("Content-Type", "application/json");
+        for (name, value) in headers {
+            builder = builder.header(name, value);
+        }
+        let request = builder.body(Body::from(body.to_string())).unwrap();
+        let app = app.clone();
+        async move {
+            let response = app.oneshot(request).await.unwrap();
+            let status = response.status();
+            let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+            (status, serde_json::from_slice::<Value>(&body).unwrap_or(json!({})))
+        }
+    };
+    let admin = || vec![("X-Admin-Key", TEST_ADMIN_KEY)];
+    let expires_at = |agent: &Value| agent["expires_at"].as_str().unwrap().parse::<DateTime<Utc>>().unwrap();
+
+    let (status, agent) = get_json_with_headers(app.clone(), &uri, &[("X-Admin-Key", TEST_ADMIN_KEY)]).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(agent["name"], "Owned Agent");
+    assert_eq!(agent["owner"]["username"], "owner");
+    assert_eq!(agent["rate_limit"], json!({ "requests": 100, "window_secs": 60 }));
+    assert!(agent["ip_allowlist"].is_null());
+    assert_eq!(agent["service_access"][0]["service_id"], "payment");
+    let created_at: DateTime<Utc> = agent["created_at"].as_str().unwrap().parse().unwrap();
+
+    let (status, agent) = patch(admin(), json!({ "name": "Tuned Agent", "description": "Nightly batch" })).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!((agent["name"].as_str(), agent["description"].as_str()), (Some("Tuned Agent"), Some("Nightly batch")));
+
+    let (status, agent) = patch(admin(), json!({ "rate_limit": { "requests": 5, "window_secs": 10 } })).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(agent["rate_limit"], json!({ "requests": 5, "window_secs": 10 }));
+    assert_eq!(agent["name"], "Tuned Agent");
+
+    // From when the key was issued, or from now
+    let (status, agent) = patch(admin(), json!({ "lifespan_days": 7 })).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(agent["lifespan_days"], 7);
+    assert_eq!(expires_at(&agent), created_at + Duration::days(7));
+    let (_, agent) = patch(admin(), json!({ "lifespan_days": 7, "lifespan_from_now": true })).await;
+    assert!(expires_at(&agent) > created_at + Duration::days(7));
+    assert!(expires_at(&agent) <= Utc::now() + Duration::days(7));
+
+    let (status, agent) = patch(admin(), json!({ "ip_allowlist": ["10.0.0.1", "::1"] })).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(agent["ip_allowlist"], json!(["10.0.0.1", "::1"]));
+    let (_, agent) = patch(admin(), json!({ "description": "still allowlisted" })).await;
+    assert_eq!(agent["ip_allowlist"], json!(["10.0.0.1", "::1"]));
+    let (_, agent) = patch(admin(), json!({ "ip_allowlist": null })).await;
+    assert!(agent["ip_allowlist"].is_null());
+
+    // Persisted, not just echoed
+    let stored = state.agents.get_agent(agent_id.parse().unwrap()).await.unwrap().unwrap();
+    assert_eq!((stored.name.as_str(), stored.rate_limit.requests, stored.lifespan_days), ("Tuned Agent", 5, 7));
+
+    for invalid in [
+        json!({}),
+        json!({ "name": "  " }),
+        json!({ "rate_limit": { "requests": 0, "window_secs": 60 } }),
+        json!({ "rate_limit": { "requests": 10, "window_secs": 0 } }),
+        json!({ "lifespan_days": 0 }),
+        json!({ "lifespan_days": 100_000 }),
+        json!({ "lifespan_from_now": true }),
+        json!({ "ip_allowlist": [] }),
+    ] {
+        let (status, _) = patch(admin(), invalid.clone()).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST, "{}", invalid);
+    }
+    let (status, _) = patch(vec![], json!({ "name": "x" })).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    let missing = format!("/admin/agents/{}", Uuid::new_v4());
+    let status = get_with_headers(app.clone(), &missing, &[("X-Admin-Key", TEST_ADMIN_KEY)]).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+// ===================================================================
+// TEST: Admin operations land in the admin event log with the acting
+// key's hash, and GET /admin/audit/admin filters and pages them
+// ===================================================================
+#[tokio::test]
+async fn test_admin_operations_are_recorded() {
+    use sec_ai_agent_gw::audit::admin_key_hash;
+
+    set_test_env();
+    let state = AppState::for_tests();
+    let app = auth_routes()
+        .nest("/admin", admin_routes())
+        .with_state(state.clone());
+    let (agent_id, _) = create_agent(app.clone()).await;
+    let admin = [("X-Admin-Key", TEST_ADMIN_KEY)];
+    let send = |method: &'static str, uri: String, headers: &'static [(&'static str, &'static str)], body: Value| {
+        let mut builder = Request::builder()
+            .method(method)
+            .uri(uri)
+            .header("Content-Type", "application/json");
+        for (name, value) in headers {
+            builder = builder.header(*name, *value);
+        }
+        let request = builder.body(Body::from(body.to_string())).unwrap();
+        app.clone().oneshot(request)
+    };
+    const ADMIN: &[(&str, &str)] = &[("X-Admin-Key", TEST_ADMIN_KEY)];
+
+    let response = send("PATCH", format!("/admin/agents/{}", agent_id), ADMIN, json!({ "name": "Audited" }))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let response = send("POST", "/admin/cache/clear?service=payment".to_string(), ADMIN, json!({})).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let response = send("DELETE", "/admin/sessions/expired".to_string(), ADMIN, json!({})).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    // Needs the admin key now that it's recorded against one
+    let response = send("POST", "/admin/cache/clear".to_string(), &[], json!({})).await.unwrap();
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+    let (status, body) = get_json_with_headers(app.clone(), "/admin/audit/admin", &admin).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["total"], 3);
+    let events = body["events"].as_array().unwrap();
+    assert_eq!(events[0]["action"], "expired_sessions_deleted");
+    assert_eq!(events[2]["action"], "agent_updated");
+    assert_eq!(events[2]["resource_id"], agent_id.as_str());
+    assert_eq!(events[2]["details"]["changes"][0]["field"], "name");
+    assert_eq!(events[2]["admin_key_hash"], admin_key_hash(TEST_ADMIN_KEY));
+    assert_eq!(events[2]["admin_key_hash"].as_str().unwrap().len(), 8);
+    assert!(!body.to_string().contains(TEST_ADMIN_KEY));
+
+    let (_, body) =
+        get_json_with_headers(app.clone(), "/admin/audit/admin?action=cache_cleared&resource_id=payment", &admin).await;
+    assert_eq!(body["total"], 1);
+    assert_eq!(body["events"][0]["details"]["cleared"], 0);
+    let (_, body) = get_json_with_headers(app.clone(), "/admin/audit/admin?limit=1&offset=1", &admin).await;
+    assert_eq!((body["total"].as_u64(), body["events"][0]["action"].as_str()), (Some(3), Some("cache_cleared")));
+    let (_, body) = get_json_with_headers(app.clone(), "/admin/audit/admin?admin=00000000", &admin).await;
+    assert_eq!(body["total"], 0);
+
+    let (status, _) = get_json_with_headers(app.clone(), "/admin/audit/admin?limit=0", &admin).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    let (status, _) = get_json_with_headers(app, "/admin/audit/admin", &[]).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+}
+
+// ===================================================================
+// TEST: Listing a user's agents resolves live and expired agents,
+// reports deleted ones, and can filter expired / prune dead ids.
+// ===================================================================
+#[tokio::test]
+async fn test_list_user_agents() {
+    set_test_env();
+    let state = AppState::for_tests();
+    let app = auth_routes().with_state(state.clone());
+    let admin = [("X-Admin-Key", TEST_ADMIN_KEY)];
+
+    let (_, user) = post_json(
+        app.clone(),
+        "/register",
+        json!({ "username": "dashboard", "email": unique_email() }),
+    )
+    .await;
+    let user_id = user["user_id"].as_str().unwrap().to_string();
+
+    let mut ids = Vec::new();
+    for name in ["Live", "Expired", "Deleted"] {
+        let (_, agent) = post_json(
+            app.clone(),
+            "/agent",
+            json!({
+                "user_id": user_id,
+                "agent_name": name,
+                "agent_description": "Listing test",
+                "services": ["payment"]
+            }),
+        )
+        .await;
+        ids.push(agent["agent_id"].as_str().unwrap().parse::<Uuid>().unwrap());
+    }
+
+    let mut expired = state.agents.get_agent(ids[1]).await.unwrap().unwrap();
+    expired.expires_at = chrono::Utc::now() - chrono::Duration::days(1);
+    let version = expired.version;
+    state.agents.update_agent(expired, version).await.unwrap();
+    state.agents.delete_agent(ids[2]).await.unwrap();
+
+    let uri = format!("/users/{}/agents", user_id);
+    let (status, body) = get_json_with_headers(app.clone(), &uri, &admin).await;
+    assert_eq!(status, StatusCode::OK);
+    let names: Vec<&str> = body["agents"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|a| a["name"].as_str().unwrap())
+        .collect();
+    assert_eq!(names, ["Live", "Expired"]);
+    assert_eq!(body["agents"][1]["is_expired"], true);
+    assert_eq!(body["missing_agent_ids"], json!([ids[2]]));
+
+    let (_, body) = get_json_with_headers(
+        app.clone(),
+        &format!("{}?include_expired=false&prune=true", uri),
+        &admin,
+    )
+    .await;
+    assert_eq!(body["agents"].as_array().unwrap().len(), 1);
+    assert_eq!(body["agents"][0]["agent_id"], json!(ids[0]));
+
+    // Pruned: the dead id is gone from the user record
+    let user = state.users.get_user(user_id.parse().unwrap()).await.unwrap().unwrap();
+    assert_eq!(user.agents, ids[..2]);
+
+    let (status, _) = get_json_with_headers(app, &uri, &[]).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+}
+
+// ===================================================================
+// TEST: Admin listings page through agents and users and apply the
+// service / status / name filters.
+// ===================================================================
+#[tokio::test]
+async fn test_admin_listings_are_paged_and_filtered() {
+    set_test_env();
+    let state = AppState::for_tests();
+    let app = auth_routes().with_state(state.clone());
+    let admin_app = admin_routes().with_state(state.clone());
+    let admin = [("X-Admin-Key", TEST_ADMIN_KEY)];
+
+    let email = unique_email();
+    let (_, user) = post_json(app.clone(), "/register", json!({ "username": "pager", "email": email })).await;
+    for i in 0..7 {
+        let services = if i < 3 { json!(["payment"]) } else { json!(["bank"]) };
+        let (status, _) = post_json(
+            app.clone(),
+            "/agent",
+            json!({
+                "user_id": user["user_id"],
+                "agent_name": format!("pager-{}", i),
+                "agent_description": "",
+                "services": services
+            }),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+    }
+
+    let (status, body) =
+        get_json_with_headers(admin_app.clone(), "/agents?name=pager-&per_page=3&page=3", &admin).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!((body["total"].as_u64(), body["page"].as_u64()), (Some(7), Some(3)));
+    assert_eq!(body["agents"].as_array().unwrap().len(), 1);
+    assert_eq!(body["agents"][0]["name"], "pager-6");
+
+    let (_, body) =
+        get_json_with_headers(admin_app.clone(), "/agents?name=pager-&service=payment&status=active", &admin).await;
+    assert_eq!(body["total"], 3);
+    let (_, body) =
+        get_json_with_headers(admin_app.clone(), "/agents?name=pager-&status=expired", &admin).await;
+    assert_eq!(body["total"], 0);
+
+    let (_, body) = get_json_with_headers(admin_app.clone(), &format!("/users?name={}", email), &admin).await;
+    assert_eq!(body["total"], 1);
+    assert_eq!(body["users"][0]["agent_count"], 7);
+
+    let (status, _) = get_json_with_headers(admin_app.clone(), "/agents?page=0", &admin).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    let (status, _) = get_json_with_headers(admin_app.clone(), "/agents?status=retired", &admin).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    let (status, _) = get_json_with_headers(admin_app, "/users", &[]).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+}
+
+// ===================================================================
+// TEST: GET /admin/users searches username and email case-insensitively,
+// pages, counts active agents and masks emails on request
+// ===================================================================
+#[tokio::test]
+async fn test_admin_user_search_and_masking() {
+    set_test_env();
+    let state = AppState::for_tests();
+    let app = auth_routes().with_state(state.clone());
+    let admin_app = admin_routes().with_state(state.clone());
+    let admin = [("X-Admin-Key", TEST_ADMIN_KEY)];
+
+    // A tag unique to this test, in the username of some users and the email of others
+    let tag = Uuid::new_v4().simple().to_string()[..10].to_string();
+    let mut user_ids = Vec::new();
+    for i in 0..3 {
+        let (_, user) = post_json(
+            app.clone(),
+            "/register",
+            json!({ "username": format!("Searcher-{}-{}", tag.to_uppercase(), i), "email": unique_email() }),
+        )
+        .await;
+        user_ids.push(user["user_id"].clone());
+    }
+    let (_, by_email) = post_json(
+        app.clone(),
+        "/register",
+        json!({ "username": "mailonly", "email": format!("{}@example.org", tag) }),
+    )
+    .await;
+
+    let mut agent_ids = Vec::new();
+    for name in ["active", "expired"] {
+        let (_, agent) = post_json(
+            app.clone(),
+            "/agent",
+            json!({
+                "user_id": by_email["user_id"],
+                "agent_name": name,
+                "agent_description": "",
+                "services": ["payment"]
+            }),
+        )
+        .await;
+        agent_ids.push(agent["agent_id"].as_str().unwrap().parse::<Uuid>().unwrap());
+    }
+    let mut expired = state.agents.get_agent(agent_ids[1]).await.unwrap().unwrap();
+    expired.expires_at = chrono::Utc::now() - chrono::Duration::days(1);
+    let version = expired.version;
+    state.agents.update_agent(expired, version).await.unwrap();
+
+    // Lowercase query matches the uppercase usernames and the email
+    let (status, body) = get_json_with_headers(admin_app.clone(), &format!("/users?q={}&per_page=3", tag), &admin).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["total"], 4);
+    let first_page: Vec<Value> = body["users"].as_array().unwrap().iter().map(|u| u["user_id"].clone()).collect();
+    assert_eq!(first_page, user_ids);
+
+    let (_, body) =
+        get_json_with_headers(admin_app.clone(), &format!("/users?q={}&per_page=3&page=2", tag), &admin).await;
+    assert_eq!((body["total"].as_u64(), body["page"].as_u64()), (Some(4), Some(2)));
+    let user = &body["users"][0];
+    assert_eq!(user["user_id"], by_email["user_id"]);
+    assert_eq!(user["email"], format!("{}@example.org", tag));
+    assert_eq!((user["agent_count"].as_u64(), user["active_agent_count"].as_u64()), (Some(2), Some(1)));
+
+    let (_, body) =
+        get_json_with_headers(admin_app.clone(), &format!("/users?q={}@&mask_pii=true", tag), &admin).await;
+    assert_eq!(body["total"], 1);
+    assert_eq!(body["users"][0]["email"], format!("{}***@example.org", &tag[..1]));
+    assert_eq!(body["users"][0]["username"], "mailonly");
+
+    let (_, body) = get_json_with_headers(admin_app, "/users?q=no-such-user-anywhere", &admin).await;
+    assert_eq!(body["total"], 0);
+}
+
+// ===================================================================
+// TEST: GET /admin/overview counts what was seeded into the stores,
+// plus requests and rate-limit rejections recorded this hour
+// ===================================================================
+#[tokio::test]
+async fn test_admin_overview_counts() {
+    use sec_ai_agent_gw::auth::create_session;
+    use sec_ai_agent_gw::models::{Agent, User};
+
+    set_test_env();
+    let mut settings = Settings::from_env();
+    settings.ip_rate_limit = RateLimitConfig { requests: 1, window_secs: 60 };
+    let state = AppState::for_tests_with(settings);
+    let admin_app = admin_routes().with_state(state.clone());
+    let admin = [("X-Admin-Key", TEST_ADMIN_KEY)];
+
+    for name in ["ann", "ben"] {
+        let user = User::new(name.to_string(), unique_email());
+        state.users.create_user(user).await.unwrap();
+    }
+    let mut agents = Vec::new();
+    for name in ["live-1", "live-2", "lapsed"] {
+        let mut agent = Agent::new(name.to_string(), "".to_string());
+        if name == "lapsed" {
+            agent.expires_at = chrono::Utc::now() - chrono::Duration::days(1);
+        }
+        agents.push(state.agents.create_agent(agent).await.unwrap());
+    }
+    state.sessions.create_session(create_session(agents[0].id, 3600)).await.unwrap();
+    state.sessions.create_session(create_session(agents[1].id, 3600)).await.unwrap();
+    let mut stale = create_session(agents[1].id, 3600);
+    stale.expires_at = chrono::Utc::now() - chrono::Duration::seconds(1);
+    state.sessions.create_session(stale).await.unwrap();
+
+    state.proxy_metrics.track().finish();
+    state.proxy_metrics.track().finish();
+    let ip = "198.51.100.7".parse().unwrap();
+    state.ip_rate_limiter.check_ip(ip).await.unwrap();
+    assert!(state.ip_rate_limiter.check_ip(ip).await.is_err());
+
+    let (status, body) = get_json_with_headers(admin_app.clone(), "/overview", &admin).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["users"], 2);
+    assert_eq!(body["agents"], json!({ "active": 2, "expired": 1 }));
+    assert_eq!(body["live_sessions"], 2);
+    assert_eq!(body["services"], state.services().registry.list().len());
+    assert_eq!(body["credentials"]["stored"], state.credentials.list().await.len());
+    assert_eq!(body["requests_last_hour"], 2);
+    assert_eq!(body["rate_limit_rejections_last_hour"], 0);
+    assert_eq!(body["ip_rate_limit_rejections_last_hour"], 1);
+    assert!(body["uptime_secs"].is_u64());
+
+    let (status, _) = get_json_with_headers(admin_app, "/overview", &[]).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+}
+
+#[tokio::test]
+async fn test_admin_credential_health() {
+    use sec_ai_agent_gw::models::RefreshTrigger;
+
+    set_test_env();
+    let dir = tempfile::TempDir::new().unwrap();
+    let credentials_path = dir.path().join("credentials.json");
+    let in_days = |days: i64| (chrono::Utc::now() + chrono::Duration::days(days)).to_rfc3339();
+    let credentials = json!({ "credentials": [
+        {
+            "service_id": "payment",
+            "access_token": "sk-payment-0123456789wxyz",
+            "refresh_token": "refresh-payment",
+            "expires_at": in_days(30),
+            "scopes": ["payments:read"],
+            "encrypted": false
+        },
+        {
+            "service_id": "bank",
+            "access_token": "sk-bank-0123456789abcd",
+            "refresh_token": null,
+            "expires_at": (chrono::Utc::now() + chrono::Duration::hours(1)).to_rfc3339(),
+            "scopes": [],
+            "encrypted": false
+        }
+    ]});
+    std::fs::write(&credentials_path, credentials.to_string()).unwrap();
+    let mut settings = Settings::from_env();
+    settings.credentials_path = credentials_path.to_string_lossy().to_string();
+    let state = AppState::for_tests_with(settings);
+    let admin_app = admin_routes().with_state(state.clone());
+
+    state.credentials.mark_used("payment");
+    state
+        .refresh_metrics
+        .record("bank", RefreshTrigger::Lazy, false, std::time::Duration::from_millis(12))
+        .await;
+
+    let (status, body) =
+        get_json_with_headers(admin_app.clone(), "/credentials", &[("X-Admin-Key", TEST_ADMIN_KEY)]).await;
+    assert_eq!(status, StatusCode::OK);
+    let entries = body["credentials"].as_array().unwrap();
+    let entry = |id: &str| entries.iter().find(|e| e["service_id"] == id).unwrap().clone();
+
+    let payment = entry("payment");
+    assert_eq!(payment["status"], "healthy");
+    assert_eq!(payment["token_suffix"], "****wxyz");
+    assert_eq!(payment["scopes"], json!(["payments:read"]));
+    assert_eq!(payment["needs_refresh"], false);
+    assert_eq!(payment["has_refresh_token"], true);
+    assert!(payment["last_used_at"].is_string());
+    assert!(payment["last_refresh"].is_null());
+
+    let bank = entry("bank");
+    assert_eq!(bank["status"], "expiring");
+    assert_eq!(bank["needs_refresh"], true);
+    assert_eq!(bank["has_refresh_token"], false);
+    assert_eq!(bank["last_refresh"]["success"], false);
+    assert_eq!(bank["last_refresh"]["trigger"], "lazy");
+    assert_eq!(bank["refresh_history"].as_array().unwrap().len(), 1);
+    assert!(bank["last_used_at"].is_null());
+
+    let httpbin = entry("httpbin");
+    assert_eq!(httpbin["status"], "missing");
+    assert_eq!(httpbin["configured"], true);
+    assert!(httpbin["token_suffix"].is_null());
+
+    // Tokens never leave the gateway
+    let text = body.to_string();
+    assert!(!text.contains("sk-payment") && !text.contains("refresh-payment"));
+
+    let (status, _) = get_json_with_headers(admin_app, "/credentials", &[]).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+}
+
+// === Registration and agent creation share a per-IP quota ===
+async fn post_from(
+    app: axum::Router,
+    uri: &str,
+    peer: &str,
+    headers: &[(&str, &str)],
+    body: Value,
+) -> (StatusCode, Value) {
+    let mut builder = Request::builder()
+        .method("POST")
+        .uri(uri)
+        .header("Content-Type", "application/json")
+        .extension(ConnectInfo(peer.parse::<SocketAddr>().unwrap()));
+    for (name, value) in headers {
+        builder = builder.header(*name, *value);
+    }
+    let response = app.oneshot(builder.body(Body::from(body.to_string())).unwrap()).await.unwrap();
+    let status = response.status();
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    (status, serde_json::from_slice(&body).unwrap_or(json!({})))
+}
+
+#[tokio::test]
+async fn test_pre_auth_endpoints_are_rate_limited_per_ip() {
+    set_test_env();
+    let mut settings = Settings::from_env();
+    settings.ip_rate_limit = RateLimitConfig { requests: 3, window_secs: 60 };
+    let app = auth_routes().with_state(AppState::for_tests_with(settings.clone()));
+    let register = || json!({ "username": "spammer", "email": unique_email() });
+
+    let (status, user) = post_from(app.clone(), "/register", "203.0.113.9:5000", &[], register()).await;
+    assert_eq!(status, StatusCode::OK);
+    let agent = json!({
+        "user_id": user["user_id"],
+        "agent_name": "spam-agent",
+        "agent_description": "",
+        "services": ["payment"]
+    });
+    let (status, _) = post_from(app.clone(), "/agent", "203.0.113.9:5001", &[], agent).await;
+    assert_eq!(status, StatusCode::OK);
+    let (status, _) = post_from(app.clone(), "/register", "203.0.113.9:5002", &[], register()).await;
+    assert_eq!(status, StatusCode::OK);
+
+    // Fourth request from the same address, whatever the port
+    let (status, _) = post_from(app.clone(), "/register", "203.0.113.9:5003", &[], register()).await;
+    assert_eq!(status, StatusCode::TOO_MANY_REQUESTS);
+    let (status, _) = post_from(app.clone(), "/register", "203.0.113.10:5000", &[], register()).await;
+    assert_eq!(status, StatusCode::OK);
+
+    // X-Forwarded-For is only believed when configured; then its last entry is the client
+    let spoofed = [("X-Forwarded-For", "198.51.100.1")];
+    let (status, _) = post_from(app, "/register", "203.0.113.9:5004", &spoofed, register()).await;
+    assert_eq!(status, StatusCode::TOO_MANY_REQUESTS);
+
+    settings.trusted_client_ip_header = Some("X-Forwarded-For".to_string());
+    let proxied = auth_routes().with_state(AppState::for_tests_with(settings));
+    for i in 0..3 {
+        let forwarded = [("X-Forwarded-For", "1.2.3.4, 198.51.100.1")];
+        let peer = format!("10.0.0.{}:443", i + 1);
+        let (status, _) = post_from(proxied.clone(), "/register", &peer, &forwarded, register()).await;
+        assert_eq!(status, StatusCode::OK);
+    }
+    let forwarded = [("X-Forwarded-For", "9.9.9.9, 198.51.100.1")];
+    let (status, _) = post_from(proxied, "/register", "10.0.0.9:443", &forwarded, register()).await;
+    assert_eq!(status, StatusCode::TOO_MANY_REQUESTS);
+}
+
+// ===================================================================
+// TEST: MAX_AGENT_LIFESPAN_DAYS caps new keys and rotations, and keys
+// from before it was lowered can be listed and shortened
+// ===================================================================
+#[tokio::test]
+async fn test_agent_lifespan_is_capped() {
+    use chrono::{DateTime, Duration, Utc};
+    use sec_ai_agent_gw::models::Agent;
+
+    set_test_env();
+    let mut settings = Settings::from_env();
+    settings.max_agent_lifespan_days = 90;
+    let state = AppState::for_tests_with(settings);
+    let app = auth_routes().with_state(state.clone());
+    let admin_app = admin_routes().with_state(state.clone());
+    let admin = [("X-Admin-Key", TEST_ADMIN_KEY)];
+    let peer = "127.0.0.1:4000";
+
+    let (_, user) = post_json(app.clone(), "/register", json!({ "username": "capped", "email": unique_email() })).await;
+    let create = |lifespan_days: u32| {
+        json!({
+            "user_id": user["user_id"],
+            "agent_name": format!("capped-{}", lifespan_days),
+            "agent_description": "",
+            "services": ["payment"],
+            "lifespan_days": lifespan_days
+        })
+    };
+    let (status, body) = post_json(app.clone(), "/agent", create(u32::MAX)).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert!(body["message"].as_str().unwrap().contains("90"));
+    let (status, _) = post_json(app.clone(), "/agent", create(90)).await;
+    assert_eq!(status, StatusCode::OK);
+
+    // Keys issued while the cap was higher
+    let old = Agent::with_lifespan("legacy".to_string(), "".to_string(), 400);
+    let rotated = Agent::with_lifespan("legacy-rotated".to_string(), "".to_string(), 400);
+    state.agents.create_agent(old.clone()).await.unwrap();
+    state.agents.create_agent(rotated.clone()).await.unwrap();
+
+    let (_, body) = get_json_with_headers(admin_app.clone(), "/agents?over_max_lifespan=true", &admin).await;
+    assert_eq!(body["total"], 2);
+
+    // Rotation issues the new key for at most 90 days
+    let (status, body) =
+        post_from(app.clone(), &format!("/agent/{}/rotate", rotated.id), peer, &admin, json!({})).await;
+    assert_eq!(status, StatusCode::OK);
+    let expires_at: DateTime<Utc> = body["expires_at"].as_str().unwrap().parse().unwrap();
+    assert!(expires_at <= Utc::now() + Duration::days(90));
+    let new_id = body["agent_id"].clone();
+
+    let (_, dry_run) =
+        post_from(admin_app.clone(), "/maintenance/enforce-lifespan?dry_run=true", peer, &admin, json!({})).await;
+    let listed: Vec<_> = dry_run["agents"].as_array().unwrap().iter().map(|a| a["agent_id"].clone()).collect();
+    assert!(listed.contains(&json!(old.id)));
+    assert!(!listed.contains(&new_id));
+    assert_eq!(state.agents.get_agent(old.id).await.unwrap().unwrap().expires_at, old.expires_at);
+
+    let (status, summary) =
+        post_from(admin_app.clone(), "/maintenance/enforce-lifespan", peer, &admin, json!({})).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(summary["agents"].as_array().unwrap().len(), listed.len());
+    let stored = state.agents.get_agent(old.id).await.unwrap().unwrap();
+    assert_eq!(stored.expires_at, old.created_at + Duration::days(90));
+    assert_eq!(stored.lifespan_days, 90);
+
+    let (_, body) = get_json_with_headers(admin_app, "/agents?over_max_lifespan=true", &admin).await;
+    assert_eq!(body["total"], 0);
+}
+
+#[tokio::test]
+async fn test_admin_read_endpoints_require_the_admin_key() {
+    set_test_env();
+    let app = admin_routes().with_state(AppState::for_tests());
+
+    for uri in ["/services", "/refresh/stats", "/proxy/stats"] {
+        let status = get_with_headers(app.clone(), uri, &[]).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED, "{}", uri);
+        let status = get_with_headers(app.clone(), uri, &[("X-Admin-Key", "wrong-key")]).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED, "{}", uri);
+        let status = get_with_headers(app.clone(), uri, &[("X-Admin-Key", TEST_ADMIN_KEY)]).await;
+        assert_eq!(status, StatusCode::OK, "{}", uri);
+    }
+}